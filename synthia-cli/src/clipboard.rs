@@ -0,0 +1,71 @@
+use std::fmt::Display;
+
+/// Puts `text` on the system clipboard.
+pub(crate) fn copy(text: &str) -> Result<(), String> {
+    let mut clipboard = arboard::Clipboard::new().map_err(describe)?;
+    clipboard.set_text(text).map_err(describe)
+}
+
+/// The text on the system clipboard.
+pub(crate) fn paste() -> Result<String, String> {
+    let mut clipboard = arboard::Clipboard::new().map_err(describe)?;
+    clipboard.get_text().map_err(describe)
+}
+
+fn describe(error: impl Display) -> String {
+    format!("Could not use the clipboard: {}", error)
+}
+
+/// The contents of the last fenced code block in `text`, without its
+/// fences.
+pub(crate) fn last_code_block(text: &str) -> Option<String> {
+    let mut block: Option<Vec<&str>> = None;
+    let mut last = None;
+    for line in text.lines() {
+        if line.trim_start().starts_with("```") {
+            match block.take() {
+                Some(lines) => last = Some(lines.join("\n")),
+                None => block = Some(Vec::new()),
+            }
+        } else if let Some(lines) = &mut block {
+            lines.push(line);
+        }
+    }
+    last
+}
+
+/// `task` followed by the clipboard's text as a fenced block.
+pub(crate) fn with_pasted(task: &str) -> Result<String, String> {
+    let pasted = paste()?;
+    if pasted.trim().is_empty() {
+        return Err("The clipboard has no text to paste.".to_string());
+    }
+    let pasted = format!("From the clipboard:\n```\n{}\n```", pasted.trim_end());
+    if task.trim().is_empty() {
+        return Ok(pasted);
+    }
+    Ok(format!("{}\n\n{}", task.trim_end(), pasted))
+}
+
+/// Copies the answer, or its last code block with `code`, and says which.
+pub(crate) fn copy_answer(answer: Option<&str>, code: bool) {
+    let Some(answer) = answer else {
+        println!("No answer to copy yet.");
+        return;
+    };
+    let (text, what) = if code {
+        match last_code_block(answer) {
+            Some(block) => (block, "code block"),
+            None => {
+                println!("The answer has no code block.");
+                return;
+            }
+        }
+    } else {
+        (answer.to_string(), "answer")
+    };
+    match copy(&text) {
+        Ok(()) => println!("Copied the {} to the clipboard.", what),
+        Err(e) => crate::theme::warn(e),
+    }
+}
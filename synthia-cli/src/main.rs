@@ -0,0 +1,2128 @@
+use anyhow::Result;
+use async_trait::async_trait;
+use clap::{Parser, Subcommand};
+use std::collections::BTreeMap;
+use std::path::PathBuf;
+use std::sync::{Arc, Mutex, RwLock};
+use std::time::{Duration, Instant, SystemTime, UNIX_EPOCH};
+use tokio::io::AsyncBufReadExt;
+use synthia_core::clarify;
+use synthia_core::clients::{
+    Embedder, FallbackClient, LLMClient, OpenAIClient, OpenAIEmbedder, Provider, Reasoning, ReasoningEffort,
+    ResponsesApi, Usage, find_provider,
+};
+use synthia_core::config::{CONFIG_FILE, Config, Profile, RoleModels, parse_checked};
+use synthia_core::paths::{self, MCP_CONFIG_FILE, UserDirs};
+use synthia_core::context::{self, ContextConfig, ContextSelector};
+use synthia_core::core::{
+    assessment, final_answer, AgentResult, Assessment, CancellationToken, Detail, GateDecision, ReactAgent, Step,
+    StepGate, StopReason, render_usage,
+};
+use synthia_core::eval::{Comparison, EvalRunner, Pricing, Suite, SuiteReport};
+use synthia_core::github::{self, GitHubClient, NewPullRequest, RepoRef};
+use synthia_core::mcp::{MCPConfig, MCPManager, load_mcp_config};
+use synthia_core::migrate;
+use synthia_core::ledger::{self, ChangeLedger};
+use synthia_core::lsp::LspServer;
+use synthia_core::project;
+use synthia_core::proto::{AgentFactory, ProtoServer, SessionOptions};
+use synthia_core::redact::{RedactingMakeWriter, Redactor};
+use synthia_core::remote::{RemoteConfig, SshHost, remote_tools};
+use synthia_core::review;
+use synthia_core::search::SemanticSearchTool;
+use synthia_core::secrets::KeySource;
+use synthia_core::session::{
+    self, RunJournal, SESSION_VERSION, ScratchDir, Session, SessionExport, SessionStatus, SessionStore, UsageReport,
+};
+use synthia_core::telemetry::{JsonlSink, TelemetrySink, TracingSink};
+use synthia_core::tools::{
+    ApprovalRequest, Approver, NetworkMode, RunCommandTool, ToolManager, default_tools_with_policy, read_only_tools,
+};
+#[cfg(feature = "voice")]
+use synthia_core::voice::Voice;
+use tokio::io::{self, AsyncWriteExt};
+
+mod clipboard;
+mod theme;
+
+use theme::{Role, ThemeName};
+
+#[derive(Parser, Debug)]
+#[command(name = "synthia-agent")]
+#[command(author = "Synthia")]
+#[command(version = "0.1.0")]
+#[command(about = "AI-powered coding assistant", long_about = None)]
+struct Args {
+    #[command(subcommand)]
+    command: Commands,
+
+    #[arg(short, long, global = true)]
+    api_key: Option<String>,
+
+    #[arg(short, long, global = true, default_value = "gpt-4o")]
+    model: String,
+
+    #[arg(short, long, global = true, help = "LLM provider: openai, deepseek, xai, mistral, anthropic or ollama (default: openai)")]
+    provider: Option<String>,
+
+    #[arg(short, long, global = true, help = "Base URL for the LLM API")]
+    base_url: Option<String>,
+
+    #[arg(short, long, global = true, default_value = ".")]
+    workdir: PathBuf,
+
+    #[arg(long = "redact", global = true, help = "Extra regex whose matches are redacted from observations and logs")]
+    redact_patterns: Vec<String>,
+
+    #[arg(long, global = true, help = "Record provider exchanges as redacted cassettes in this directory")]
+    record: Option<PathBuf>,
+
+    #[arg(long, global = true, help = "Append run telemetry to this file as JSON lines")]
+    telemetry: Option<PathBuf>,
+
+    #[arg(long, global = true, help = "Reasoning effort for reasoning models: low, medium or high")]
+    reasoning_effort: Option<ReasoningEffort>,
+
+    #[arg(long, global = true, conflicts_with = "reasoning_effort", help = "Thinking token budget for models that take one (Anthropic)")]
+    thinking_budget: Option<u32>,
+
+    #[arg(long, global = true, help = "Long-context model to retry a turn on when the context is too long for --model")]
+    fallback_model: Option<String>,
+
+    #[arg(long, global = true, help = "Config file (default: .synthia/config.json in the working directory, else config.json in the user config directory)")]
+    config: Option<PathBuf>,
+
+    #[arg(long, global = true, help = "Only read and search the code; nothing is written or run")]
+    read_only: bool,
+
+    #[arg(long, global = true, help = "Network access for commands: off, allowlist (hosts from the config file) or on")]
+    network: Option<NetworkMode>,
+
+    #[arg(long, global = true, help = "Pause before every tool call to continue, skip, edit its arguments or abort")]
+    step: bool,
+
+    #[arg(long, global = true, help = "Show a desktop notification when a long task finishes or --step is waiting for an answer")]
+    notify: bool,
+
+    #[arg(long, global = true, help = "Leave the repository map out of the system prompt")]
+    no_repo_map: bool,
+
+    #[arg(long, global = true, help = "Work on a host from the config file's remotes over SSH instead of locally")]
+    remote: Option<String>,
+
+    #[arg(long, global = true, help = "Use a model profile from the config file's profiles")]
+    profile: Option<String>,
+
+    #[arg(long, global = true, default_value = "dark", help = "Output colors: dark, light or plain; off when piped or NO_COLOR is set")]
+    theme: ThemeName,
+}
+
+#[derive(Subcommand, Debug)]
+enum Commands {
+    #[command(about = "Run a task with the agent")]
+    Run {
+        #[arg(short, long, required = !cfg!(feature = "voice"), help = "Task description")]
+        task: Option<String>,
+
+        #[arg(short = 's', long, help = "Maximum steps")]
+        max_steps: Option<usize>,
+
+        #[arg(long, help = "No streaming output")]
+        no_stream: bool,
+
+        #[arg(long, help = "Print the full diff of changed files at the end")]
+        diff: bool,
+
+        #[arg(long, help = "Ask clarifying questions first if the task is unclear (default: from the config file)")]
+        clarify: bool,
+
+        #[arg(long, help = "Attach a file to the task, relative to the working directory; files the task names are attached too")]
+        attach: Vec<PathBuf>,
+
+        #[arg(long, help = "Add the clipboard's text to the task")]
+        paste: bool,
+
+        #[arg(
+            long,
+            value_name = "WHAT",
+            num_args = 0..=1,
+            default_missing_value = "answer",
+            value_parser = ["answer", "code"],
+            help = "Copy the final answer, or with 'code' its last code block, to the clipboard"
+        )]
+        copy: Option<String>,
+
+        #[cfg(feature = "voice")]
+        #[arg(long, help = "Speak the task instead of typing it, and hear the answer if the config's voice.speak is set")]
+        voice: bool,
+    },
+
+    #[command(about = "Continue a finished or aborted session from a handoff summary")]
+    Continue {
+        #[arg(default_value = "latest", help = "Session id, unique id prefix, or 'latest'")]
+        session: String,
+
+        #[arg(short = 's', long, help = "Maximum steps")]
+        max_steps: Option<usize>,
+
+        #[arg(long, help = "No streaming output")]
+        no_stream: bool,
+
+        #[arg(long, help = "Print the full diff of changed files at the end")]
+        diff: bool,
+    },
+
+    #[command(about = "Interactive mode")]
+    Interactive {
+        #[arg(long, help = "Maximum steps")]
+        max_steps: Option<usize>,
+
+        #[arg(long, help = "No streaming output")]
+        no_stream: bool,
+    },
+
+    #[command(about = "Work with past sessions", visible_alias = "sessions")]
+    History {
+        #[command(subcommand)]
+        command: HistoryCommand,
+    },
+
+    #[command(about = "Sum tokens and cost across sessions by day, model and project")]
+    Report {
+        #[arg(long, default_value = "7d", help = "How far back to look, e.g. 24h, 7d or 4w")]
+        since: String,
+
+        #[arg(long = "project", help = "Another working directory whose sessions to include (repeatable)")]
+        projects: Vec<PathBuf>,
+
+        #[arg(long, default_value = "table", value_parser = ["table", "json"], help = "Output format")]
+        format: String,
+    },
+
+    #[command(about = "Check that the programs the project needs are installed")]
+    Doctor,
+
+    #[command(about = "Check MCP configuration")]
+    CheckMcp {
+        #[arg(short, long, help = "MCP config (default: .synthia/mcp.json, mcp_config.json, then mcp.json in the user config directory)")]
+        config: Option<PathBuf>,
+    },
+
+    #[command(about = "Check or show the configuration")]
+    Config {
+        #[command(subcommand)]
+        command: ConfigCommand,
+    },
+
+    #[command(about = "Rewrite sessions and files saved by older versions in the current format")]
+    Migrate {
+        #[arg(long, help = "Only list what would change")]
+        dry_run: bool,
+    },
+
+    #[command(about = "Fix a GitHub issue and open a pull request")]
+    Github {
+        #[arg(long, help = "Repository as owner/name")]
+        repo: String,
+
+        #[arg(long, help = "Issue number to fix")]
+        issue: u64,
+
+        #[arg(long, default_value = "main", help = "Base branch for the pull request")]
+        base: String,
+
+        #[arg(long, help = "GitHub token (defaults to GITHUB_TOKEN)")]
+        token: Option<String>,
+
+        #[arg(long, help = "Maximum steps")]
+        max_steps: Option<usize>,
+    },
+
+    #[command(about = "Speak the JSON-RPC embedding protocol over stdin/stdout")]
+    Proto,
+
+    #[command(about = "Serve synthia/* editor requests over LSP framing on stdin/stdout")]
+    Lsp,
+
+    #[command(about = "Review a diff and report structured comments")]
+    Review {
+        #[arg(long, conflicts_with = "pr", help = "Git ref to diff the working tree against")]
+        diff: Option<String>,
+
+        #[arg(long, help = "GitHub pull request URL to review")]
+        pr: Option<String>,
+
+        #[arg(long, default_value = "markdown", value_parser = ["markdown", "json"], help = "Output format")]
+        format: String,
+
+        #[arg(long, help = "Maximum steps")]
+        max_steps: Option<usize>,
+    },
+
+    #[command(about = "Run a suite of benchmark tasks and report how the agent did")]
+    Eval {
+        #[arg(long, help = "Suite YAML file, or a directory of task YAML files")]
+        suite: PathBuf,
+
+        #[arg(long, default_value = "table", value_parser = ["table", "json"], help = "Output format")]
+        format: String,
+
+        #[arg(long, requires = "output_cost", help = "Input price in dollars per million tokens")]
+        input_cost: Option<f64>,
+
+        #[arg(long, requires = "input_cost", help = "Output price in dollars per million tokens")]
+        output_cost: Option<f64>,
+
+        #[arg(long, help = "Maximum steps for tasks that don't set their own")]
+        max_steps: Option<usize>,
+
+        #[arg(short, long, default_value_t = 1, help = "Number of tasks to run at once")]
+        jobs: usize,
+
+        #[arg(long, default_value_t = 1, help = "Run every task this many times")]
+        repeat: usize,
+
+        #[arg(long, help = "Maximum LLM requests per minute across all tasks")]
+        requests_per_minute: Option<u32>,
+
+        #[arg(long, help = "Seconds each setup and verify command may run [default: 600]")]
+        command_timeout: Option<u64>,
+    },
+
+    #[command(about = "Compare two eval reports saved with 'eval --format json'")]
+    EvalCompare {
+        #[arg(help = "Report to compare against")]
+        baseline: PathBuf,
+
+        #[arg(help = "Report of the new run")]
+        current: PathBuf,
+
+        #[arg(long, default_value = "markdown", value_parser = ["markdown", "json"], help = "Output format")]
+        format: String,
+    },
+}
+
+impl Commands {
+    /// Whether the command talks to a model, and so needs the provider's
+    /// key.
+    fn needs_api_key(&self) -> bool {
+        matches!(
+            self,
+            Commands::Run { .. }
+                | Commands::Continue { .. }
+                | Commands::Interactive { .. }
+                | Commands::Github { .. }
+                | Commands::Proto
+                | Commands::Lsp
+                | Commands::Review { .. }
+                | Commands::Eval { .. }
+        )
+    }
+
+    /// The name on the command line, as used for `max_steps.commands` in
+    /// the config.
+    fn name(&self) -> &'static str {
+        match self {
+            Commands::Run { .. } => "run",
+            Commands::Continue { .. } => "continue",
+            Commands::Interactive { .. } => "interactive",
+            Commands::History { .. } => "history",
+            Commands::Report { .. } => "report",
+            Commands::Doctor => "doctor",
+            Commands::CheckMcp { .. } => "check-mcp",
+            Commands::Config { .. } => "config",
+            Commands::Migrate { .. } => "migrate",
+            Commands::Github { .. } => "github",
+            Commands::Proto => "proto",
+            Commands::Lsp => "lsp",
+            Commands::Review { .. } => "review",
+            Commands::Eval { .. } => "eval",
+            Commands::EvalCompare { .. } => "eval-compare",
+        }
+    }
+}
+
+#[derive(Subcommand, Debug)]
+enum ConfigCommand {
+    #[command(about = "Report unknown keys, wrong types and deprecated options in the config and MCP config")]
+    Check,
+
+    #[command(about = "Print the config as loaded, with defaults filled in")]
+    Show {
+        #[arg(long, help = "Apply the profile and command-line overrides too")]
+        resolved: bool,
+    },
+}
+
+#[derive(Subcommand, Debug)]
+enum HistoryCommand {
+    #[command(about = "List past sessions, newest first")]
+    List {
+        #[arg(long, default_value_t = 20, help = "Maximum number of sessions")]
+        limit: usize,
+    },
+
+    #[command(about = "Show a session step by step, with what each step changed")]
+    Show {
+        #[arg(default_value = "latest", help = "Session id, unique id prefix, or 'latest'")]
+        session: String,
+    },
+
+    #[command(about = "Write a session as portable JSON, to move it to another machine or attach it to a report")]
+    Export {
+        #[arg(default_value = "latest", help = "Session id, unique id prefix, or 'latest'")]
+        session: String,
+
+        #[arg(short, long, help = "File to write (default: stdout)")]
+        output: Option<PathBuf>,
+    },
+
+    #[command(about = "Add a session from a file written by 'sessions export'")]
+    Import {
+        #[arg(help = "Exported session file")]
+        file: PathBuf,
+    },
+
+    #[command(about = "Search the transcripts of past sessions")]
+    Search {
+        #[arg(help = "Words that must all appear, case-insensitive")]
+        query: String,
+
+        #[arg(long, default_value_t = 20, help = "Maximum number of matches")]
+        limit: usize,
+    },
+}
+
+/// The provider's key from its variable, or else from the config's
+/// `api_keys` source for it.
+fn get_api_key(provider: &Provider, sources: &BTreeMap<String, KeySource>) -> Result<String, String> {
+    if let Ok(key) = std::env::var(provider.api_key_env)
+        && !key.is_empty()
+    {
+        return Ok(key);
+    }
+    if let Some(source) = sources.get(provider.name) {
+        return source.resolve(provider.name).map_err(|e| e.to_string());
+    }
+    if !provider.requires_api_key {
+        return Ok(String::new());
+    }
+    Err(format!(
+        "API key not found. Please set {} environment variable, use --api-key flag, \
+         or add a command or keychain source under api_keys.{} in the config.",
+        provider.api_key_env, provider.name
+    ))
+}
+
+/// Redacts `secret` from observations and logs from now on.
+fn add_secret(redactor: &mut Redactor, log_redactor: &RwLock<Redactor>, secret: &str) {
+    redactor.add_literal(secret);
+    log_redactor.write().unwrap_or_else(|e| e.into_inner()).add_literal(secret);
+}
+
+/// Everything needed to build the LLM client except the API key, which is
+/// resolved once the command is known.
+#[derive(Clone)]
+struct ClientConfig {
+    provider: &'static Provider,
+    model: String,
+    base_url: Option<String>,
+    record: Option<PathBuf>,
+    reasoning: Option<Reasoning>,
+    fallback_model: Option<String>,
+    roles: RoleModels,
+    stall: Option<Duration>,
+    responses: Option<ResponsesApi>,
+    api_keys: BTreeMap<String, KeySource>,
+}
+
+impl ClientConfig {
+    /// This config with the settings `profile` sets.
+    fn with_profile(&self, profile: &Profile) -> Result<Self> {
+        let mut config = self.clone();
+        if let Some(name) = &profile.provider {
+            config.provider = find_provider(name).ok_or_else(|| anyhow::anyhow!("Unknown provider: {}", name))?;
+        }
+        if let Some(model) = &profile.model {
+            config.model = model.clone();
+        }
+        if profile.base_url.is_some() {
+            config.base_url = profile.base_url.clone();
+        }
+        if profile.responses.is_some() {
+            config.responses = profile.responses.clone();
+        }
+        config.roles = self.roles.merge(&profile.roles);
+        Ok(config)
+    }
+
+    fn build(&self, api_key: String) -> Box<dyn LLMClient> {
+        let client = |model: &str| {
+            let mut client =
+                OpenAIClient::for_provider(self.provider, api_key.clone(), model.to_string(), self.base_url.clone());
+            if let Some(reasoning) = self.reasoning {
+                client = client.with_reasoning(reasoning);
+            }
+            if let Some(stall) = self.stall {
+                client = client.with_stall_timeout(stall);
+            }
+            if let Some(responses) = &self.responses {
+                client = client.with_responses_api(responses.clone());
+            }
+            match &self.record {
+                Some(dir) => client.with_recording(dir.clone()),
+                None => client,
+            }
+        };
+
+        let primary = client(&self.model);
+        match &self.fallback_model {
+            Some(fallback) => Box::new(FallbackClient::new(Box::new(primary), Box::new(client(fallback)))),
+            None => Box::new(primary),
+        }
+    }
+
+    /// The client for summaries, if a smaller model is set for them.
+    fn summarizer(&self, api_key: &str) -> Option<Box<dyn LLMClient>> {
+        let model = self.roles.summary.as_ref()?;
+        let config = ClientConfig {
+            model: model.clone(),
+            ..self.clone()
+        };
+        Some(config.build(api_key.to_string()))
+    }
+
+    /// The client for session titles: the title model, or the main one.
+    fn titler(&self, api_key: &str) -> Box<dyn LLMClient> {
+        let config = ClientConfig {
+            model: self.roles.title.clone().unwrap_or_else(|| self.model.clone()),
+            ..self.clone()
+        };
+        config.build(api_key.to_string())
+    }
+
+    /// The embedding model the config names, if any.
+    fn embedder(&self, api_key: &str, config: &ContextConfig) -> Option<Arc<dyn Embedder>> {
+        let model = config.embedding_model.as_ref()?;
+        let url = config
+            .embedding_url
+            .clone()
+            .unwrap_or_else(|| OpenAIEmbedder::url_for(self.base_url.as_deref().unwrap_or(self.provider.base_url)));
+        Some(Arc::new(OpenAIEmbedder::new(api_key.to_string(), model.clone(), url)))
+    }
+
+    /// Ranks attached code by embedding similarity when the config names
+    /// an embedding model, and by shared words otherwise.
+    fn context_selector(&self, api_key: &str, config: &ContextConfig) -> ContextSelector {
+        let selector = ContextSelector::new(config.max_tokens);
+        match self.embedder(api_key, config) {
+            Some(embedder) => selector.with_embedder(embedder),
+            None => selector,
+        }
+    }
+}
+
+/// `task` with the most relevant code from the files in `attach` and the
+/// files it names, as much as the selector's budget allows.
+async fn attach_files(selector: &ContextSelector, task: &str, workdir: &std::path::Path, attach: &[PathBuf]) -> String {
+    let mut files = context::mentioned_files(task, workdir);
+    for path in attach {
+        let path = path.to_string_lossy().into_owned();
+        if !files.contains(&path) {
+            files.push(path);
+        }
+    }
+    if files.is_empty() {
+        return task.to_string();
+    }
+    let selected = selector.select(task, context::load_snippets(workdir, &files)).await;
+    println!("Attached {} snippets from {}", selected.len(), files.join(", "));
+    context::attach(task, &selected)
+}
+
+fn print_step(step_idx: usize, step: Step) {
+    println!("\n{}", theme::step(step_idx, &step, Detail::Verbose));
+}
+
+fn handle_streaming_output(steps: &[Step]) {
+    println!("\n{}\n", theme::heading("Execution Complete"));
+    println!("Total steps: {}", steps.len());
+
+    for (i, step) in steps.iter().enumerate() {
+        println!("{}", theme::step(i + 1, step, Detail::Compact));
+    }
+
+    println!();
+}
+
+type StdinLines = tokio::io::Lines<tokio::io::BufReader<tokio::io::Stdin>>;
+
+/// Runs `task`, passing lines the user types meanwhile to the agent as
+/// guidance for its next turn.
+/// Ctrl+C stops the run in progress and keeps what it did. Pressed again,
+/// or with no run in progress, it quits as usual.
+#[derive(Clone)]
+struct Interrupts {
+    current: Arc<Mutex<Option<CancellationToken>>>,
+}
+
+impl Interrupts {
+    fn listen() -> Self {
+        let interrupts = Self {
+            current: Arc::new(Mutex::new(None)),
+        };
+        let current = Arc::clone(&interrupts.current);
+        tokio::spawn(async move {
+            while tokio::signal::ctrl_c().await.is_ok() {
+                let running = current.lock().unwrap_or_else(|e| e.into_inner()).take();
+                match running {
+                    Some(token) => {
+                        theme::warn("Interrupting the run; press Ctrl+C again to quit.");
+                        token.cancel();
+                    }
+                    None => std::process::exit(130),
+                }
+            }
+        });
+        interrupts
+    }
+
+    /// A token for the next run, cancelled by the next Ctrl+C.
+    fn start_run(&self) -> CancellationToken {
+        let token = CancellationToken::new();
+        *self.current.lock().unwrap_or_else(|e| e.into_inner()) = Some(token.clone());
+        token
+    }
+
+    fn finish_run(&self) {
+        self.current.lock().unwrap_or_else(|e| e.into_inner()).take();
+    }
+}
+
+async fn run_steered(agent: &mut ReactAgent, task: &str, lines: &mut StdinLines) -> Result<AgentResult> {
+    let steering = agent.steering();
+    let run = agent.run(task);
+    tokio::pin!(run);
+    loop {
+        tokio::select! {
+            result = &mut run => return Ok(result?),
+            line = lines.next_line() => match line? {
+                Some(line) if !line.trim().is_empty() => {
+                    steering.push(line.trim());
+                    println!("(Guidance noted, the agent will see it before its next step.)");
+                }
+                Some(_) => {}
+                None => return Ok(run.await?),
+            },
+        }
+    }
+}
+
+/// Previews a compaction of the conversation carried between interactive
+/// tasks and applies it once the user accepts, optionally with their own
+/// summary.
+/// Handles `/model <name>` and `/profile <name>`, which point the agent at
+/// another model without losing the conversation. Returns whether `input`
+/// was one of them.
+fn switch_model(
+    input: &str,
+    agent: &mut ReactAgent,
+    client_config: &mut ClientConfig,
+    api_key: &mut String,
+    log_redactor: &RwLock<Redactor>,
+    profiles: &BTreeMap<String, Profile>,
+    pricing: &BTreeMap<String, Pricing>,
+) -> bool {
+    let (command, name) = match input.split_once(char::is_whitespace) {
+        Some((command, name)) => (command, name.trim()),
+        None => (input, ""),
+    };
+    if command != "/model" && command != "/profile" {
+        return false;
+    }
+    if name.is_empty() {
+        println!("Model: {} ({})", client_config.model, client_config.provider.name);
+        if command == "/profile" {
+            let names: Vec<&str> = profiles.keys().map(String::as_str).collect();
+            if names.is_empty() {
+                println!("No profiles in the config.");
+            } else {
+                println!("Profiles: {}", names.join(", "));
+            }
+        }
+        return true;
+    }
+
+    let profile = if command == "/model" {
+        Profile {
+            model: Some(name.to_string()),
+            ..Profile::default()
+        }
+    } else {
+        match profiles.get(name) {
+            Some(profile) => profile.clone(),
+            None => {
+                println!("No profile named {} in the config.", name);
+                return true;
+            }
+        }
+    };
+    let switched = match client_config.with_profile(&profile) {
+        Ok(switched) => switched,
+        Err(e) => {
+            println!("{}", e);
+            return true;
+        }
+    };
+    if switched.provider.name != client_config.provider.name {
+        match get_api_key(switched.provider, &switched.api_keys) {
+            Ok(key) => {
+                agent.add_secret(key.as_str());
+                log_redactor.write().unwrap_or_else(|e| e.into_inner()).add_literal(key.as_str());
+                *api_key = key;
+            }
+            Err(e) => {
+                println!("{}", e);
+                return true;
+            }
+        }
+    }
+
+    agent.set_client(switched.build(api_key.clone()));
+    agent.set_summary_client(switched.summarizer(api_key));
+    agent.set_pricing(pricing.get(&switched.model).copied());
+    if profile.minify_schemas.is_some() {
+        agent.set_minify_schemas(profile.minify_schemas);
+    }
+    println!("Switched to {} ({}); the conversation is kept.", switched.model, switched.provider.name);
+    *client_config = switched;
+    true
+}
+
+async fn compact_interactively(
+    agent: &mut ReactAgent,
+    lines: &mut StdinLines,
+) -> Result<()> {
+    let Some(mut compaction) = agent.preview_compaction() else {
+        println!("Nothing to compact yet.");
+        return Ok(());
+    };
+
+    println!("Summary: {}", compaction.summary);
+    println!(
+        "Tokens: {} -> {} ({} reclaimed)",
+        compaction.tokens_before,
+        compaction.tokens_after(),
+        compaction.tokens_reclaimed()
+    );
+    print!("Press Enter to apply, type a replacement summary, or 'cancel': ");
+    io::stdout().flush().await?;
+
+    let line = lines.next_line().await?.unwrap_or_default();
+    match line.trim() {
+        "cancel" => {
+            println!("Compaction cancelled.");
+            return Ok(());
+        }
+        "" => {}
+        edited => compaction.summary = edited.to_string(),
+    }
+
+    agent.apply_compaction(&compaction);
+    println!("History compacted, {} tokens reclaimed.", compaction.tokens_reclaimed());
+    Ok(())
+}
+
+/// Tasks that finish sooner than this don't get a notification with
+/// `--notify`; the user is likely still watching.
+const NOTIFY_AFTER: Duration = Duration::from_secs(30);
+
+/// Shows a desktop notification with the platform's own tool:
+/// `notify-send` on Linux and `osascript` on macOS. Elsewhere, or if the
+/// tool is missing, nothing is shown.
+fn notify(title: &str, body: &str) {
+    let mut command = if cfg!(target_os = "macos") {
+        let mut command = tokio::process::Command::new("osascript");
+        command.arg("-e").arg(format!(
+            "display notification {} with title {}",
+            applescript_string(body),
+            applescript_string(title)
+        ));
+        command
+    } else if cfg!(unix) {
+        let mut command = tokio::process::Command::new("notify-send");
+        command.arg("--app-name=synthia").arg(title).arg(body);
+        command
+    } else {
+        return;
+    };
+    // A missing notifier is not worth interrupting the run for. Tokio
+    // reaps the process once it exits.
+    let _ = command
+        .stdin(std::process::Stdio::null())
+        .stdout(std::process::Stdio::null())
+        .stderr(std::process::Stdio::null())
+        .spawn();
+}
+
+fn applescript_string(text: &str) -> String {
+    format!("\"{}\"", text.replace('\\', "\\\\").replace('"', "\\\""))
+}
+
+/// Notifies that `task` finished, if `--notify` was given and the task ran
+/// long enough for the user to have looked away.
+fn notify_finished(enabled: bool, started: Instant, task: &str, succeeded: bool) {
+    if !enabled || started.elapsed() < NOTIFY_AFTER {
+        return;
+    }
+    let title = if succeeded { "Task finished" } else { "Task failed" };
+    let first_line = task.lines().next().unwrap_or_default();
+    let body: String = first_line.chars().take(100).collect();
+    notify(title, &body);
+}
+
+/// Shows every tool call on the terminal and asks what to do with it, for
+/// `--step`. Also asks about the calls the permission policy holds.
+struct TerminalGate {
+    reader: tokio::sync::Mutex<tokio::io::BufReader<tokio::io::Stdin>>,
+    /// Also notify on the desktop that an answer is needed.
+    notify: bool,
+}
+
+impl TerminalGate {
+    fn new(notify: bool) -> Self {
+        Self {
+            reader: tokio::sync::Mutex::new(tokio::io::BufReader::new(tokio::io::stdin())),
+            notify,
+        }
+    }
+
+    /// Prints `question` and reads the answer, or `None` once stdin is closed.
+    async fn ask(&self, question: &str) -> Option<String> {
+        print!("{}", question);
+        io::stdout().flush().await.ok()?;
+        let mut line = String::new();
+        match self.reader.lock().await.read_line(&mut line).await {
+            Ok(0) | Err(_) => None,
+            Ok(_) => Some(line.trim().to_string()),
+        }
+    }
+}
+
+#[async_trait]
+impl StepGate for TerminalGate {
+    async fn before_tool(&self, step: usize, thought: &str, tool: &str, arguments: &serde_json::Value) -> GateDecision {
+        println!("\n{}", theme::paint(Role::Heading, format!("--- Step {} ---", step)));
+        if !thought.is_empty() {
+            println!("{} {}", theme::paint(Role::Thought, "Thought:"), thought);
+        }
+        println!("{} {}", theme::paint(Role::Action, "Tool:"), theme::paint(Role::Action, tool));
+        println!("Arguments: {}", serde_json::to_string_pretty(arguments).unwrap_or_default());
+        if self.notify {
+            notify("Waiting for approval", &format!("Step {}: {}", step, tool));
+        }
+
+        loop {
+            let Some(answer) = self.ask("[c]ontinue, [s]kip, [e]dit arguments or [a]bort? ").await else {
+                return GateDecision::Abort;
+            };
+            match answer.to_ascii_lowercase().as_str() {
+                "" | "c" | "continue" => return GateDecision::Continue,
+                "s" | "skip" => return GateDecision::Skip,
+                "a" | "abort" => return GateDecision::Abort,
+                "e" | "edit" => {
+                    let Some(edited) = self.ask("New arguments as JSON on one line: ").await else {
+                        return GateDecision::Abort;
+                    };
+                    match serde_json::from_str(&edited) {
+                        Ok(arguments) => return GateDecision::Edit(arguments),
+                        Err(e) => println!("Invalid JSON, arguments unchanged: {}", e),
+                    }
+                }
+                _ => println!("Answer c, s, e or a."),
+            }
+        }
+    }
+}
+
+#[async_trait]
+impl Approver for TerminalGate {
+    async fn approve(&self, request: &ApprovalRequest) -> bool {
+        if self.notify {
+            notify("Waiting for approval", &request.action);
+        }
+        let question = format!("{} [y/N] ", theme::paint(Role::Action, format!("Allow {}?", request.action)));
+        let answer = self.ask(&question).await.unwrap_or_default();
+        matches!(answer.to_ascii_lowercase().as_str(), "y" | "yes")
+    }
+}
+
+/// Who approves the calls the permission policy holds. Without a terminal
+/// to ask on there is no one: calls a rule asks about are refused, and
+/// commands and writes are no longer asked about by default.
+fn approver(notify: bool) -> Option<Arc<dyn Approver>> {
+    use std::io::IsTerminal;
+
+    if std::io::stdin().is_terminal() {
+        Some(Arc::new(TerminalGate::new(notify)))
+    } else {
+        None
+    }
+}
+
+/// The gate for `--step`, if it was given.
+fn step_gate(step: bool, notify: bool) -> Option<Arc<dyn StepGate>> {
+    if step {
+        Some(Arc::new(TerminalGate::new(notify)))
+    } else {
+        None
+    }
+}
+
+/// Asks the user the model's clarifying questions about `task`, if it has
+/// any, and returns the task with their answers.
+async fn clarify_task(client: &dyn LLMClient, task: &str, workdir: &std::path::Path, max: usize) -> Result<String> {
+    println!("Checking whether the task needs clarifying...");
+    let questions = clarify::clarifying_questions(client, task, workdir, max).await?;
+    if questions.is_empty() {
+        return Ok(task.to_string());
+    }
+
+    println!("Press Enter to skip a question.");
+    let mut lines = tokio::io::BufReader::new(tokio::io::stdin()).lines();
+    let mut answers = Vec::new();
+    for question in questions {
+        println!("\n{}", question);
+        print!("> ");
+        io::stdout().flush().await?;
+        let answer = lines.next_line().await?.unwrap_or_default();
+        answers.push((question, answer));
+    }
+    println!();
+    Ok(clarify::build_clarified_task(task, &answers))
+}
+
+/// Runs `task` while recording it as `session`, saving and journaling
+/// every step so an aborted or crashed run can still be continued, and has
+/// `titler` title and sum up the session afterwards. With `remote` the files are on another host, so
+/// only `write_file` changes are reported.
+async fn run_session(
+    mut agent: ReactAgent,
+    task: &str,
+    session: Session,
+    titler: Box<dyn LLMClient>,
+    no_stream: bool,
+    show_diff: bool,
+    remote: bool,
+) -> Result<AgentResult> {
+    let workdir = agent.working_dir().to_path_buf();
+    // Outside a git repository only `write_file` changes are reported.
+    let status_before = if remote { None } else { ledger::git_status(&workdir).await.ok() };
+
+    let session = Arc::new(Mutex::new(session));
+    let store = Arc::new(SessionStore::for_workdir(&workdir));
+    let save = {
+        let store = Arc::clone(&store);
+        move |session: &Session| {
+            if let Err(e) = store.save(session) {
+                theme::warn(e);
+            }
+        }
+    };
+    save(&session.lock().unwrap_or_else(|e| e.into_inner()));
+    let journal = match RunJournal::start(&store, &session.lock().unwrap_or_else(|e| e.into_inner())) {
+        Ok(journal) => Some(journal),
+        Err(e) => {
+            theme::warn(e);
+            None
+        }
+    };
+    let journal = Arc::new(Mutex::new(journal));
+
+    let recorder = Arc::clone(&session);
+    let journaled = Arc::clone(&journal);
+    let save_step = save.clone();
+    agent.set_step_callback(Some(Arc::new(move |step_idx, step: Step| {
+        if !no_stream {
+            print_step(step_idx, step.clone());
+        }
+        if let Some(journal) = journaled.lock().unwrap_or_else(|e| e.into_inner()).as_mut()
+            && let Err(e) = journal.record(&step)
+        {
+            theme::warn(e);
+        }
+        let mut session = recorder.lock().unwrap_or_else(|e| e.into_inner());
+        session.push_step(step);
+        save_step(&session);
+    })));
+
+    let interrupts = Interrupts::listen();
+    agent.set_cancellation(interrupts.start_run());
+    let outcome = agent.run(task).await;
+    interrupts.finish_run();
+
+    let (id, mut changes) = {
+        let mut session = session.lock().unwrap_or_else(|e| e.into_inner());
+        session.finish(match &outcome {
+            Ok(result) if result.stop_reason == StopReason::MaxSteps => Some("Max steps exceeded".to_string()),
+            Ok(result) if result.stop_reason == StopReason::TokenBudget => Some("Token budget exceeded".to_string()),
+            Ok(result) if result.stop_reason == StopReason::Interrupted => Some("Interrupted by the user".to_string()),
+            Ok(_) => None,
+            Err(e) => Some(e.to_string()),
+        });
+        save(&session);
+        if let Some(journal) = journal.lock().unwrap_or_else(|e| e.into_inner()).take()
+            && let Err(e) = journal.finish()
+        {
+            theme::warn(e);
+        }
+        (session.id.clone(), ChangeLedger::from_steps(&session.steps))
+    };
+    let finished = session.lock().unwrap_or_else(|e| e.into_inner()).clone();
+    if !finished.steps.is_empty() {
+        match session::title_and_summary(titler.as_ref(), &finished).await {
+            Ok((title, summary)) => {
+                let mut session = session.lock().unwrap_or_else(|e| e.into_inner());
+                session.title = Some(title);
+                session.summary = Some(summary);
+                save(&session);
+            }
+            Err(e) => theme::warn(format!("Could not title the session: {}", e)),
+        }
+    }
+    if let Some(before) = &status_before
+        && let Ok(after) = ledger::git_status(&workdir).await
+    {
+        changes.add_git_changes(before, &after);
+    }
+    if !remote {
+        changes.settle(&workdir);
+    }
+    print_changes(&changes, &workdir, status_before.is_some(), show_diff).await;
+    let result = outcome?;
+
+    println!("\n{}\n", theme::heading("Execution Complete"));
+    println!("Total steps: {}", result.steps.len());
+    if result.usage != Usage::default() {
+        println!("{}", render_usage(&result.usage, result.cost_usd));
+    }
+    if !no_stream {
+        for (i, step) in result.steps.iter().enumerate() {
+            println!("{}", theme::step(i + 1, step, Detail::Compact));
+        }
+    }
+    print_max_steps_summary(&result);
+    print_assessment(assessment(&result.steps).as_ref());
+    println!("Session: {} (continue with `synthia-agent continue {}`)", id, id);
+
+    Ok(result)
+}
+
+/// Records the task from the microphone and shows what was heard.
+#[cfg(feature = "voice")]
+async fn listen_for_task(voice: &Voice) -> Result<String> {
+    println!("Listening... speak the task; recording stops after a pause.");
+    let task = voice.listen(&std::env::temp_dir()).await?;
+    if task.is_empty() {
+        anyhow::bail!("Heard nothing; try again closer to the microphone, or pass --task");
+    }
+    println!("Heard: {}\n", task);
+    Ok(task)
+}
+
+/// Shows that the agent stopped early, and what it did and what remains
+/// if it ran out of steps.
+fn print_max_steps_summary(result: &AgentResult) {
+    if result.stop_reason == StopReason::Finished {
+        return;
+    }
+    let stopped = format!("The agent {} before finishing.", result.stop_reason);
+    println!("\n{}", theme::paint(Role::Warning, stopped));
+    if let Some(summary) = &result.summary {
+        println!("{}\n", summary);
+    }
+}
+
+/// Shows the agent's self-assessment and whether the result needs a
+/// person to review it.
+fn print_assessment(assessment: Option<&Assessment>) {
+    let Some(assessment) = assessment else {
+        println!("{}", theme::paint(Role::Warning, "Assessment: none given, review the changes before using them"));
+        return;
+    };
+    let tests = match assessment.tests_passed {
+        Some(true) => theme::paint(Role::Success, "passed"),
+        Some(false) => theme::paint(Role::Error, "failed"),
+        None => "not run".to_string(),
+    };
+    println!("Tests: {}", tests);
+    if let Some(confidence) = assessment.confidence {
+        println!("Confidence: {:?}", confidence);
+    }
+    for unverified in &assessment.unverified {
+        println!("Not verified: {}", unverified);
+    }
+    for risk in &assessment.risks {
+        println!("Risk: {}", risk);
+    }
+    if assessment.needs_review() {
+        println!("{}", theme::paint(Role::Warning, "Review needed before using these changes."));
+    }
+}
+
+/// The tools for an agent working in `workdir`, or on `remote`:
+/// everything, or only the non-mutating ones with `--read-only`, plus
+/// `semantic_search` when there is an embedding model.
+fn agent_tools(
+    workdir: PathBuf,
+    read_only: bool,
+    config: &Config,
+    embedder: Option<Arc<dyn Embedder>>,
+    remote: Option<&RemoteConfig>,
+    scratch: Option<&ScratchDir>,
+    mcp: Option<&MCPManager>,
+) -> ToolManager {
+    if let Some(remote) = remote {
+        return remote_tools(SshHost::new(remote.clone()), workdir, read_only, config.limits.clone());
+    }
+    let mut tools = if read_only {
+        read_only_tools(workdir.clone())
+    } else {
+        default_tools_with_policy(workdir.clone(), config.network.clone(), config.limits.clone())
+    };
+    if let Some(scratch) = scratch
+        && !read_only
+    {
+        let run_command = RunCommandTool::new(workdir.clone())
+            .with_network(config.network.clone())
+            .with_limits(config.limits.clone())
+            .with_scratch_dir(Some(scratch.path().to_path_buf()));
+        tools.register(Box::new(run_command));
+    }
+    if let Some(embedder) = embedder
+        && let Some(model) = &config.context.embedding_model
+    {
+        tools.register(Box::new(SemanticSearchTool::new(workdir, embedder, model.clone())));
+    }
+    if let Some(mcp) = mcp {
+        mcp.register_tools(&mut tools, read_only);
+    }
+    tools
+}
+
+/// What every agent the CLI builds shares.
+struct AgentSetup<'a> {
+    config: &'a Config,
+    redactor: &'a Redactor,
+    telemetry: &'a Arc<dyn TelemetrySink>,
+    /// Whether the workspace is on this machine rather than a remote.
+    local: bool,
+}
+
+/// An agent with `tools` in `workdir`, talking to `client_config`'s model
+/// and set up as the config says. Commands add their own step gate,
+/// approver, step callback and scratch directory.
+fn build_agent(
+    setup: &AgentSetup<'_>,
+    client_config: &ClientConfig,
+    api_key: &str,
+    tools: ToolManager,
+    workdir: PathBuf,
+    max_steps: Option<usize>,
+    read_only: bool,
+) -> ReactAgent {
+    let config = setup.config;
+    ReactAgent::new(client_config.build(api_key.to_string()), tools, workdir, max_steps, Some(true), None)
+        .with_redactor(setup.redactor.clone())
+        .with_retention(config.history)
+        .with_timeouts(config.timeouts.clone())
+        .with_quotas(config.quotas.clone())
+        .with_scopes(config.scopes.clone())
+        .with_permissions(config.permissions.clone())
+        .with_pricing(config.pricing.get(&client_config.model).copied())
+        .with_model_pricing(config.pricing.clone())
+        .with_hooks(config.hooks.clone())
+        .with_telemetry(Arc::clone(setup.telemetry))
+        .with_tool_selection(config.tool_selection.clone())
+        .with_minify_schemas(config.minify_schemas.clone())
+        .with_prefetch(config.prefetch.clone().filter(|_| setup.local))
+        .with_summary_client(client_config.summarizer(api_key))
+        .with_read_only(read_only)
+        .with_repo_map(config.repo_map.budget())
+        .with_project_detection(setup.local)
+        .with_checkpoints(setup.local)
+}
+
+/// Reports everything wrong with the config and MCP config files that
+/// exist, and fails if anything is.
+fn check_config(config_path: &std::path::Path, mcp_path: &std::path::Path) -> Result<()> {
+    let mut problems = 0;
+    for path in [config_path, mcp_path] {
+        let text = match std::fs::read_to_string(path) {
+            Ok(text) => text,
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound => {
+                println!("{}: not found, defaults apply", path.display());
+                continue;
+            }
+            Err(e) => anyhow::bail!("{}: {}", path.display(), e),
+        };
+        let warnings = if path == mcp_path {
+            parse_checked::<MCPConfig>(&text, &[]).map(|(_, warnings)| warnings).map_err(|e| e.to_string())
+        } else {
+            Config::check(path).map(|(_, warnings)| warnings).map_err(|e| e.to_string())
+        };
+        match warnings {
+            Ok(warnings) if warnings.is_empty() => println!("{}: ok", path.display()),
+            Ok(warnings) => {
+                println!("{}:", path.display());
+                for warning in &warnings {
+                    println!("  {}", warning);
+                }
+                problems += warnings.len();
+            }
+            Err(e) => {
+                println!("{}: {}", path.display(), e);
+                problems += 1;
+            }
+        }
+    }
+    if problems > 0 {
+        anyhow::bail!("{} problem(s) found", problems);
+    }
+    Ok(())
+}
+
+/// Starts the servers in the workspace's MCP config, if it has any. Their
+/// tools run locally, so there are none for a remote workspace.
+async fn connect_mcp(workdir: &std::path::Path, remote: bool) -> Option<MCPManager> {
+    if remote {
+        return None;
+    }
+    let path = paths::mcp_config_file(workdir)?;
+    let config = match load_mcp_config(&path).await {
+        Ok(config) if !config.servers.is_empty() => config,
+        Ok(_) => return None,
+        Err(e) => {
+            theme::warn(format!("Ignoring {}: {}", path.display(), e));
+            return None;
+        }
+    };
+    let mut manager = MCPManager::new(config);
+    for (name, e) in manager.connect_all().await {
+        theme::warn(format!("MCP server {} is unavailable: {}", name, e));
+    }
+    Some(manager)
+}
+
+/// A scratch directory for `session`, when the agent works locally and
+/// may write.
+fn scratch_dir(workdir: &std::path::Path, session: &Session, config: &Config, writable: bool) -> Option<ScratchDir> {
+    if !writable {
+        return None;
+    }
+    match ScratchDir::create(workdir, &session.id, config.scratch.clone()) {
+        Ok(scratch) => Some(scratch),
+        Err(e) => {
+            theme::warn(format!("no scratch directory: {}", e));
+            None
+        }
+    }
+}
+
+fn finish_scratch(scratch: Option<ScratchDir>, succeeded: bool) {
+    let Some(scratch) = scratch else {
+        return;
+    };
+    let relative = scratch.relative().to_string();
+    if scratch.finish(succeeded) {
+        println!("Scratch files kept in {}", relative);
+    }
+}
+
+async fn print_changes(changes: &ChangeLedger, workdir: &std::path::Path, in_git: bool, show_diff: bool) {
+    if changes.is_empty() {
+        return;
+    }
+    println!("\n{}\n", theme::heading("Changed Files"));
+    for change in changes.changes() {
+        println!("  {:<9} {}", change.kind.as_str(), change.path);
+    }
+    if !in_git {
+        return;
+    }
+    match changes.diff(workdir, false).await {
+        Ok(stat) => print!("\n{}", stat),
+        Err(e) => theme::warn(e),
+    }
+    if show_diff {
+        match changes.diff(workdir, true).await {
+            Ok(diff) => println!("\n{}", theme::diff_lines(diff.trim_end())),
+            Err(e) => theme::warn(e),
+        }
+    }
+}
+
+/// Finishes the sessions of runs cut short by a crash or power loss, and
+/// with `ask` offers to continue the latest of them or show what it
+/// changed. Returns the session to continue instead of the command given.
+async fn recover_sessions(workdir: &std::path::Path, ask: bool) -> Result<Option<String>> {
+    use std::io::IsTerminal;
+
+    let recovered = match SessionStore::for_workdir(workdir).recover_interrupted() {
+        Ok(recovered) => recovered,
+        Err(e) => {
+            theme::warn(format!("Could not check for interrupted runs: {}", e));
+            return Ok(None);
+        }
+    };
+    let Some(latest) = recovered.last() else {
+        return Ok(None);
+    };
+    for session in &recovered {
+        theme::warn(format!(
+            "Session {} ({}) stopped after {} step(s) without finishing, likely in a crash.",
+            session.id,
+            session.label(),
+            session.steps.len()
+        ));
+    }
+    if !ask || !std::io::stdin().is_terminal() {
+        println!("Continue it with `synthia-agent continue {}`.", latest.id);
+        return Ok(None);
+    }
+
+    let mut lines = tokio::io::BufReader::new(tokio::io::stdin()).lines();
+    loop {
+        print!("[c]ontinue session {} instead, [s]how what it changed, or Enter to go on: ", latest.id);
+        io::stdout().flush().await?;
+        let answer = lines.next_line().await?.unwrap_or_default();
+        match answer.trim().to_ascii_lowercase().as_str() {
+            "" => return Ok(None),
+            "c" | "continue" => return Ok(Some(latest.id.clone())),
+            "s" | "show" => {
+                let mut changes = ChangeLedger::from_steps(&latest.steps);
+                changes.settle(workdir);
+                print_changes(&changes, workdir, false, false).await;
+                // What checkpoints saw each step change, commands included.
+                for (i, step) in latest.steps.iter().enumerate() {
+                    if let Some(diff) = &step.diff {
+                        println!("\nStep {}:\n{}", i + 1, theme::diff_lines(diff.trim_end()));
+                    }
+                }
+                println!();
+            }
+            _ => println!("Answer c or s, or press Enter."),
+        }
+    }
+}
+
+#[tokio::main]
+async fn main() -> Result<()> {
+    let mut args = Args::parse();
+    theme::init(args.theme);
+
+    let mut redactor = Redactor::default();
+    for pattern in &args.redact_patterns {
+        redactor.add_pattern(pattern)?;
+    }
+
+    let log_redactor = Arc::new(RwLock::new(redactor.clone()));
+    let subscriber = tracing_subscriber::fmt()
+        .with_env_filter(tracing_subscriber::EnvFilter::from_default_env());
+    if matches!(args.command, Commands::Proto | Commands::Lsp) {
+        // stdout carries protocol messages, so logs must not end up there.
+        subscriber
+            .with_writer(RedactingMakeWriter::new(std::io::stderr, Arc::clone(&log_redactor)))
+            .init();
+    } else {
+        subscriber
+            .with_writer(RedactingMakeWriter::new(std::io::stdout, Arc::clone(&log_redactor)))
+            .init();
+    }
+
+    let provider_name = args.provider.as_deref().unwrap_or("openai");
+    let provider = find_provider(provider_name)
+        .ok_or_else(|| anyhow::anyhow!("Unknown provider: {}", provider_name))?;
+    let mut client_config = ClientConfig {
+        provider,
+        model: args.model.clone(),
+        base_url: args.base_url.clone(),
+        record: args.record.clone(),
+        reasoning: args
+            .reasoning_effort
+            .map(Reasoning::Effort)
+            .or(args.thinking_budget.map(Reasoning::Budget)),
+        fallback_model: args.fallback_model.clone(),
+        roles: RoleModels::default(),
+        stall: None,
+        responses: None,
+        api_keys: BTreeMap::new(),
+    };
+
+    let workdir = args.workdir.clone();
+    // The project's config, else the user's. Neither has to exist.
+    let config_path = args
+        .config
+        .clone()
+        .or_else(|| paths::config_file(&workdir))
+        .unwrap_or_else(|| workdir.join(CONFIG_FILE));
+    if let Commands::Config { command: ConfigCommand::Check } = &args.command {
+        let mcp_path = paths::mcp_config_file(&workdir).unwrap_or_else(|| workdir.join(MCP_CONFIG_FILE));
+        return check_config(&config_path, &mcp_path);
+    }
+    let mut config = if args.config.is_some() || config_path.exists() {
+        let (config, warnings) = Config::check(&config_path)?;
+        for warning in warnings {
+            theme::warn(format!("{}: {}", config_path.display(), warning));
+        }
+        config
+    } else {
+        Config::default()
+    };
+    if let Some(mode) = args.network {
+        config.network.mode = mode;
+    }
+    if args.no_repo_map {
+        config.repo_map.enabled = false;
+    }
+    let remote = match &args.remote {
+        Some(name) => {
+            let remote = config
+                .remotes
+                .get(name)
+                .cloned()
+                .ok_or_else(|| anyhow::anyhow!("No remote named {} in the config", name))?;
+            // The map would describe the local checkout, not the remote one.
+            config.repo_map.enabled = false;
+            Some(remote)
+        }
+        None => None,
+    };
+    client_config.roles = config.roles.clone();
+    client_config.api_keys = config.api_keys.clone();
+    client_config.stall = config.timeouts.stall();
+    if let Some(name) = &args.profile {
+        let profile = config
+            .profiles
+            .get(name)
+            .ok_or_else(|| anyhow::anyhow!("No profile named {} in the config", name))?;
+        client_config = client_config.with_profile(profile)?;
+        if profile.minify_schemas.is_some() {
+            config.minify_schemas = profile.minify_schemas.clone();
+        }
+    }
+    let provider = client_config.provider;
+    if matches!(args.command, Commands::Run { .. } | Commands::Continue { .. } | Commands::Interactive { .. }) {
+        let ask = !matches!(args.command, Commands::Continue { .. });
+        if let Some(session) = recover_sessions(&workdir, ask).await? {
+            args.command = Commands::Continue {
+                session,
+                max_steps: None,
+                no_stream: false,
+                diff: false,
+            };
+        }
+    }
+    let max_steps = match &args.command {
+        Commands::Run { max_steps, .. } => *max_steps,
+        Commands::Continue { max_steps, .. } => *max_steps,
+        Commands::Interactive { max_steps, .. } => *max_steps,
+        Commands::Github { max_steps, .. } => *max_steps,
+        Commands::Review { max_steps, .. } => *max_steps,
+        Commands::Eval { max_steps, .. } => *max_steps,
+        _ => None,
+    };
+    // The flag, then the remote's own limit, then the profile's, then the
+    // config's.
+    let max_steps = Some(
+        max_steps
+            .or(remote.as_ref().and_then(|remote| remote.max_steps))
+            .unwrap_or_else(|| config.max_steps_for(args.command.name(), args.profile.as_deref())),
+    );
+
+    let telemetry: Arc<dyn TelemetrySink> = match &args.telemetry {
+        Some(path) => Arc::new(
+            JsonlSink::open(path).map_err(|e| anyhow::anyhow!("Could not open {}: {}", path.display(), e))?,
+        ),
+        None => Arc::new(TracingSink),
+    };
+    // A key from a key command, the keychain or --api-key is no secret to
+    // the redactors yet, so they learn it before any agent or log line can
+    // show it.
+    let api_key = if args.command.needs_api_key() {
+        let api_key = match args.api_key.take() {
+            Some(key) => key,
+            None => get_api_key(provider, &client_config.api_keys).map_err(|e| anyhow::anyhow!(e))?,
+        };
+        add_secret(&mut redactor, &log_redactor, &api_key);
+        api_key
+    } else {
+        String::new()
+    };
+    let setup = AgentSetup {
+        config: &config,
+        redactor: &redactor,
+        telemetry: &telemetry,
+        local: remote.is_none(),
+    };
+
+    match &args.command {
+        Commands::Run {
+            task,
+            no_stream,
+            diff,
+            clarify,
+            attach,
+            paste,
+            copy,
+            #[cfg(feature = "voice")]
+            voice,
+            ..
+        } => {
+            let client = client_config.build(api_key.clone());
+
+            let task = match task {
+                Some(task) => task.clone(),
+                #[cfg(feature = "voice")]
+                None if *voice => listen_for_task(&Voice::new(config.voice.clone())).await?,
+                None => anyhow::bail!("No task given; pass one with --task"),
+            };
+            let task = if *paste { clipboard::with_pasted(&task).map_err(|e| anyhow::anyhow!(e))? } else { task };
+
+            let task = if *clarify || config.clarify.enabled {
+                clarify_task(client.as_ref(), &task, &workdir, config.clarify.max_questions).await?
+            } else {
+                task.clone()
+            };
+
+            let session = Session::new(task.clone(), workdir.clone(), client_config.model.clone());
+            let scratch = scratch_dir(&workdir, &session, &config, !args.read_only && remote.is_none());
+            let embedder = client_config.embedder(&api_key, &config.context);
+            let mcp = connect_mcp(&workdir, remote.is_some()).await;
+            let tools = agent_tools(
+                workdir.clone(),
+                args.read_only,
+                &config,
+                embedder,
+                remote.as_ref(),
+                scratch.as_ref(),
+                mcp.as_ref(),
+            );
+
+            let agent = build_agent(
+                &setup,
+                &client_config,
+                &api_key,
+                tools,
+                workdir.clone(),
+                max_steps,
+                args.read_only,
+            )
+            .with_scratch_dir(scratch.as_ref().map(|scratch| scratch.relative().to_string()))
+            .with_step_gate(step_gate(args.step, args.notify))
+            .with_approver(approver(args.notify));
+
+            println!("Starting agent with task: {}", task);
+            println!("Working directory: {:?}", workdir);
+            println!("Press Ctrl+C to interrupt...\n");
+
+            let selector = client_config.context_selector(&api_key, &config.context);
+            let prompt = attach_files(&selector, &task, &workdir, attach).await;
+
+            let titler = client_config.titler(&api_key);
+            let started = Instant::now();
+            let outcome = run_session(agent, &prompt, session, titler, *no_stream, *diff, remote.is_some()).await;
+            notify_finished(args.notify, started, &task, outcome.is_ok());
+            finish_scratch(scratch, outcome.is_ok());
+            #[cfg(feature = "voice")]
+            if *voice
+                && let Ok(result) = &outcome
+                && let Some(answer) = final_answer(&result.steps)
+                && let Err(e) = Voice::new(config.voice.clone()).speak(&answer, &std::env::temp_dir()).await
+            {
+                theme::warn(e);
+            }
+            if let (Some(what), Ok(result)) = (copy, &outcome) {
+                clipboard::copy_answer(final_answer(&result.steps).as_deref(), what == "code");
+            }
+            outcome?;
+        }
+
+        Commands::Continue { session: spec, no_stream, diff, .. } => {
+            let store = SessionStore::for_workdir(&workdir);
+            let previous = store.resolve(spec)?;
+            let client = client_config.build(api_key.clone());
+
+            println!("Summarizing session {} ({} steps)...", previous.id, previous.steps.len());
+            let summarizer = client_config.summarizer(&api_key);
+            let summary = session::handoff_summary(summarizer.as_deref().unwrap_or(client.as_ref()), &previous).await?;
+            println!("\n{}\n", summary);
+
+            let task = session::build_continue_task(&previous, &summary);
+            let session = Session::new(previous.task.clone(), workdir.clone(), client_config.model.clone())
+                .with_parent(previous.id.clone());
+            let scratch = scratch_dir(&workdir, &session, &config, !args.read_only && remote.is_none());
+            let mcp = connect_mcp(&workdir, remote.is_some()).await;
+            let tools = agent_tools(
+                workdir.clone(),
+                args.read_only,
+                &config,
+                client_config.embedder(&api_key, &config.context),
+                remote.as_ref(),
+                scratch.as_ref(),
+                mcp.as_ref(),
+            );
+            let agent = build_agent(
+                &setup,
+                &client_config,
+                &api_key,
+                tools,
+                workdir.clone(),
+                max_steps,
+                args.read_only,
+            )
+            .with_scratch_dir(scratch.as_ref().map(|scratch| scratch.relative().to_string()))
+            .with_step_gate(step_gate(args.step, args.notify))
+            .with_approver(approver(args.notify));
+
+            let titler = client_config.titler(&api_key);
+            let started = Instant::now();
+            let outcome = run_session(agent, &task, session, titler, *no_stream, *diff, remote.is_some()).await;
+            notify_finished(args.notify, started, &previous.task, outcome.is_ok());
+            finish_scratch(scratch, outcome.is_ok());
+            outcome?;
+        }
+
+        Commands::Interactive { no_stream, .. } => {
+            let mut api_key = api_key;
+            let mut client_config = client_config.clone();
+
+            let selector = client_config.context_selector(&api_key, &config.context);
+
+            let embedder = client_config.embedder(&api_key, &config.context);
+            let mcp = connect_mcp(&workdir, remote.is_some()).await;
+            let tools =
+                agent_tools(workdir.clone(), args.read_only, &config, embedder, remote.as_ref(), None, mcp.as_ref());
+
+            let step_callback: Option<Arc<dyn Fn(usize, Step) + Send + Sync>> =
+                if *no_stream { None } else { Some(Arc::new(print_step)) };
+
+            let mut agent = build_agent(
+                &setup,
+                &client_config,
+                &api_key,
+                tools,
+                workdir.clone(),
+                max_steps,
+                args.read_only,
+            )
+            .with_step_gate(step_gate(args.step, args.notify))
+            .with_approver(approver(args.notify));
+            agent.set_step_callback(step_callback);
+
+            println!("Interactive mode started. Type 'exit' or 'quit' to end, '/compact' to summarize the conversation so far.");
+            println!("'/model <name>' or '/profile <name>' switches models and keeps the conversation.");
+            println!("'/copy' copies the last answer ('/copy code' its last code block); '/paste <text>' sends text with the clipboard.");
+            if !args.step {
+                println!("While the agent works, type a line to steer it.");
+            }
+            println!("Ctrl+C stops a task in progress and keeps what it did.");
+            println!("Working directory: {:?}", workdir);
+            println!();
+
+            let mut lines = tokio::io::BufReader::new(tokio::io::stdin()).lines();
+            let mut last_answer: Option<String> = None;
+            let interrupts = Interrupts::listen();
+
+            loop {
+                print!("> ");
+                io::stdout().flush().await?;
+
+                let Some(line) = lines.next_line().await? else {
+                    break;
+                };
+
+                let input = line.trim();
+
+                if input.is_empty() {
+                    continue;
+                }
+
+                if input.eq_ignore_ascii_case("exit") || input.eq_ignore_ascii_case("quit") {
+                    println!("Goodbye!");
+                    break;
+                }
+
+                if input.eq_ignore_ascii_case("/compact") {
+                    compact_interactively(&mut agent, &mut lines).await?;
+                    continue;
+                }
+
+                if switch_model(
+                    input,
+                    &mut agent,
+                    &mut client_config,
+                    &mut api_key,
+                    &log_redactor,
+                    &config.profiles,
+                    &config.pricing,
+                ) {
+                    continue;
+                }
+
+                let (command, rest) = input.split_once(char::is_whitespace).unwrap_or((input, ""));
+                if command == "/copy" {
+                    clipboard::copy_answer(last_answer.as_deref(), rest.trim() == "code");
+                    continue;
+                }
+                let input = if command == "/paste" {
+                    match clipboard::with_pasted(rest.trim()) {
+                        Ok(input) => input,
+                        Err(e) => {
+                            println!("{}", e);
+                            continue;
+                        }
+                    }
+                } else {
+                    input.to_string()
+                };
+
+                let input = attach_files(&selector, &input, &workdir, &[]).await;
+                // With --step the gate reads stdin itself.
+                let started = Instant::now();
+                agent.set_cancellation(interrupts.start_run());
+                let outcome = if args.step {
+                    agent.run(&input).await.map_err(anyhow::Error::from)
+                } else {
+                    run_steered(&mut agent, &input, &mut lines).await
+                };
+                interrupts.finish_run();
+                notify_finished(args.notify, started, &input, outcome.is_ok());
+                let result = outcome?;
+                last_answer = final_answer(&result.steps).or(last_answer);
+                if *no_stream {
+                    println!("\n{}", theme::heading("Execution Complete"));
+                    println!("Total steps: {}", result.steps.len());
+                } else {
+                    handle_streaming_output(&result.steps);
+                }
+                if result.usage != Usage::default() {
+                    println!("{}", render_usage(&result.usage, result.cost_usd));
+                }
+                print_max_steps_summary(&result);
+
+                println!();
+            }
+        }
+
+        Commands::History { command: HistoryCommand::List { limit } } => {
+            let sessions = SessionStore::for_workdir(&workdir).list()?;
+            if sessions.is_empty() {
+                println!("No sessions yet.");
+            }
+            for session in sessions.iter().rev().take(*limit) {
+                let status = match session.status {
+                    SessionStatus::Running => "running",
+                    SessionStatus::Finished => "finished",
+                    SessionStatus::Aborted => "aborted",
+                };
+                println!("{}  {:<8}  {}", session.id, status, session.label());
+                if let Some(summary) = &session.summary {
+                    println!("    {}", summary);
+                }
+            }
+        }
+
+        Commands::History { command: HistoryCommand::Show { session } } => {
+            let session = SessionStore::for_workdir(&workdir).resolve(session)?;
+            println!("Session {}: {}", session.id, session.task);
+            for (i, step) in session.steps.into_iter().enumerate() {
+                print_step(i + 1, step);
+            }
+            if let Some(error) = session.error {
+                println!("\nStopped with an error: {}", error);
+            }
+        }
+
+        Commands::History { command: HistoryCommand::Export { session, output } } => {
+            let session = SessionStore::for_workdir(&workdir).resolve(session)?;
+            let json = SessionExport::new(session).to_json();
+            match output {
+                Some(path) => std::fs::write(path, json)?,
+                None => println!("{}", json),
+            }
+        }
+
+        Commands::History { command: HistoryCommand::Import { file } } => {
+            let export = SessionExport::load(file)?;
+            SessionStore::for_workdir(&workdir).import(&export.session)?;
+            println!("Imported session {}: {}", export.session.id, export.session.label());
+        }
+
+        Commands::History { command: HistoryCommand::Search { query, limit } } => {
+            let hits = SessionStore::for_workdir(&workdir).search(query, *limit)?;
+            if hits.is_empty() {
+                println!("No sessions match '{}'.", query);
+            }
+            for hit in hits {
+                match hit.step {
+                    Some(step) => println!("{} step {}: {}", hit.session_id, step, hit.task),
+                    None => println!("{}: {}", hit.session_id, hit.task),
+                }
+                println!("    {}", hit.snippet);
+            }
+        }
+
+        Commands::Report { since, projects, format } => {
+            let age = session::parse_age(since)
+                .ok_or_else(|| anyhow::anyhow!("Invalid --since '{}': expected e.g. 24h, 7d or 4w", since))?;
+            let now = SystemTime::now().duration_since(UNIX_EPOCH).unwrap_or_default().as_secs();
+
+            let mut sessions = Vec::new();
+            for dir in std::iter::once(&workdir).chain(projects) {
+                // Named after the directory, which `.` doesn't say.
+                let name = std::fs::canonicalize(dir)
+                    .ok()
+                    .and_then(|path| path.file_name().map(|name| name.to_string_lossy().into_owned()))
+                    .unwrap_or_else(|| dir.display().to_string());
+                for session in SessionStore::for_workdir(dir).list()? {
+                    sessions.push((name.clone(), session));
+                }
+            }
+            let report = UsageReport::new(
+                sessions.iter().map(|(project, session)| (project.as_str(), session)),
+                now.saturating_sub(age),
+                &config.pricing,
+            );
+
+            if format == "json" {
+                println!("{}", serde_json::to_string_pretty(&report)?);
+            } else if report.rows.is_empty() {
+                println!("No sessions in the last {}.", since);
+            } else {
+                print!("{}", report.render_table());
+            }
+        }
+
+        Commands::Doctor => {
+            // Where the key comes from, never the key itself.
+            let key_source = match (std::env::var(provider.api_key_env), client_config.api_keys.get(provider.name)) {
+                (Ok(key), _) if !key.is_empty() => Ok(provider.api_key_env.to_string()),
+                (_, Some(source)) => source.resolve(provider.name).map(|_| source.describe()).map_err(|e| e.to_string()),
+                _ if !provider.requires_api_key => Ok("nowhere, as none is needed".to_string()),
+                _ => Err(format!("set {} or add api_keys.{} to the config", provider.api_key_env, provider.name)),
+            };
+            match key_source {
+                Ok(from) => println!("  ok       {:<14} {}, from {}", "api key", provider.name, from),
+                Err(e) => println!("  missing  {:<14} {}", "api key", e),
+            }
+            if config_path.exists() {
+                println!("  ok       {:<14} {}", "config", config_path.display());
+            } else {
+                println!("  ok       {:<14} none, defaults apply", "config");
+            }
+            if let Some(dirs) = UserDirs::current() {
+                println!("  ok       {:<14} {}", "user config", dirs.config.display());
+                println!("  ok       {:<14} {}", "cache", dirs.cache.display());
+                println!("  ok       {:<14} {}", "state", dirs.state.display());
+                println!("  ok       {:<14} {}", "logs", dirs.logs.display());
+            }
+
+            let projects = project::detect(&workdir);
+            if projects.is_empty() {
+                println!("No Cargo.toml, package.json, pyproject.toml or go.mod in {:?}.", workdir);
+                return Ok(());
+            }
+            println!("{}", project::describe(&projects));
+            let tools = project::check_tools(&workdir, &projects).await;
+            for tool in &tools {
+                match &tool.version {
+                    Some(version) => println!("  ok       {:<14} {}", tool.program, version),
+                    None => {
+                        let hint = project::install_hint(&tool.program).unwrap_or("not on PATH");
+                        println!("  missing  {:<14} {}", tool.program, hint);
+                    }
+                }
+            }
+            let missing = tools.iter().filter(|tool| tool.is_missing()).count();
+            if missing > 0 {
+                anyhow::bail!("{} required program(s) missing", missing);
+            }
+        }
+
+        Commands::CheckMcp { config } => {
+            let config_path = config
+                .clone()
+                .or_else(|| paths::mcp_config_file(&workdir))
+                .unwrap_or_else(|| workdir.join(MCP_CONFIG_FILE));
+
+            println!("Checking MCP configuration at: {:?}", config_path);
+
+            match load_mcp_config(&config_path).await {
+                Ok(config) => {
+                    println!("MCP Configuration loaded successfully.");
+                    println!("Number of configured servers: {}", config.servers.len());
+
+                    let mut names: Vec<String> = config.servers.keys().cloned().collect();
+                    names.sort();
+                    let mut manager = MCPManager::new(config.clone());
+                    let mut failed = 0;
+                    for name in names {
+                        let server_config = &config.servers[&name];
+                        println!("  - {}: {} {:?}", name, server_config.command, server_config.args);
+                        match manager.connect_server(&name).await {
+                            Ok(()) => {
+                                for tool in manager.server_tools(&name) {
+                                    println!("      {:<24} {}", tool.name, tool.description);
+                                }
+                            }
+                            Err(e) => {
+                                failed += 1;
+                                println!("      failed: {}", e);
+                            }
+                        }
+                    }
+                    manager.disconnect_all().await;
+                    if failed > 0 {
+                        anyhow::bail!("{} MCP server(s) could not be reached", failed);
+                    }
+                }
+                Err(e) => {
+                    println!("Failed to load MCP configuration: {}", e);
+                    println!("Using default (empty) MCP configuration.");
+                }
+            }
+        }
+
+        Commands::Config { command: ConfigCommand::Show { resolved } } => {
+            let shown = if *resolved || !config_path.exists() {
+                config.clone()
+            } else {
+                Config::check(&config_path)?.0
+            };
+            println!("{}", serde_json::to_string_pretty(&shown)?);
+        }
+
+        // Run before the config is loaded, so a broken one can be reported.
+        Commands::Config { command: ConfigCommand::Check } => {}
+
+        Commands::Migrate { dry_run } => {
+            let sessions = SessionStore::for_workdir(&workdir).migrate(*dry_run)?;
+            for (id, version) in &sessions {
+                println!("session {}: format {} -> {}", id, version, SESSION_VERSION);
+            }
+            let moved = migrate::moved_files(&workdir);
+            for (from, to) in &moved {
+                println!("{} -> {}", from.display(), to.display());
+                if !dry_run {
+                    if let Some(dir) = to.parent() {
+                        std::fs::create_dir_all(dir)?;
+                    }
+                    std::fs::rename(from, to)?;
+                }
+            }
+            if sessions.is_empty() && moved.is_empty() {
+                println!("Everything is in the current format.");
+            } else if *dry_run {
+                println!("Nothing was changed (--dry-run).");
+            }
+        }
+
+        Commands::Github { repo, issue, base, token, .. } => {
+            if args.read_only {
+                anyhow::bail!("--read-only cannot be used with github, which has to change the code.");
+            }
+            let token = match token.clone().or_else(|| std::env::var("GITHUB_TOKEN").ok()) {
+                Some(token) => token,
+                None => anyhow::bail!("GitHub token not found. Please set GITHUB_TOKEN or use --token."),
+            };
+
+            let repo = RepoRef::parse(repo)?;
+            let github = GitHubClient::new(token, None);
+
+            let issue = github.fetch_issue(&repo, *issue).await?;
+            println!("Fixing issue #{}: {}", issue.number, issue.title);
+
+            let branch = github::branch_name(&issue);
+            github::git(&workdir, &["checkout", "-b", &branch]).await?;
+
+            let tools = default_tools_with_policy(workdir.clone(), config.network.clone(), config.limits.clone());
+            let mut agent = build_agent(&setup, &client_config, &api_key, tools, workdir.clone(), max_steps, false)
+                .with_approver(approver(args.notify));
+            agent.set_step_callback(Some(Arc::new(print_step)));
+
+            let result = agent.run(&github::build_issue_task(&issue)).await?;
+            if result.stop_reason != StopReason::Finished {
+                print_max_steps_summary(&result);
+                anyhow::bail!("The agent {}; not opening a pull request.", result.stop_reason);
+            }
+
+            // Sessions, indexes and scratch files under .synthia are not
+            // part of the fix.
+            let exclude_project_dir = format!(":!{}", paths::PROJECT_DIR);
+            let changes = ["--", ".", exclude_project_dir.as_str()];
+            if github::git(&workdir, &[&["status", "--porcelain"][..], &changes].concat()).await?.is_empty() {
+                anyhow::bail!("The agent made no changes; not opening a pull request.");
+            }
+
+            let title = format!("Fix #{}: {}", issue.number, issue.title);
+            github::git(&workdir, &[&["add", "-A"][..], &changes].concat()).await?;
+            github::git(&workdir, &["commit", "-m", &title]).await?;
+            github::git(&workdir, &["push", "-u", "origin", &branch]).await?;
+
+            let pull_request = github
+                .create_pull_request(
+                    &repo,
+                    &NewPullRequest {
+                        title,
+                        head: branch,
+                        base: base.clone(),
+                        body: github::build_pull_request_body(&issue, &result.steps, &redactor),
+                    },
+                )
+                .await?;
+
+            println!("Opened pull request #{}: {}", pull_request.number, pull_request.html_url);
+        }
+
+        Commands::Proto | Commands::Lsp => {
+            let client_config = client_config.clone();
+            let redactor = redactor.clone();
+
+            // Proto sessions ask their client through `session/approve`.
+            let factory: AgentFactory = Box::new(move |options: &SessionOptions, approver| {
+                let read_only = options.read_only || args.read_only;
+                let embedder = client_config.embedder(&api_key, &config.context);
+                let tools =
+                    agent_tools(options.workdir.clone(), read_only, &config, embedder, remote.as_ref(), None, None);
+                let setup = AgentSetup {
+                    config: &config,
+                    redactor: &redactor,
+                    telemetry: &telemetry,
+                    local: remote.is_none(),
+                };
+                let max_steps = options.max_steps.or(max_steps);
+                build_agent(&setup, &client_config, &api_key, tools, options.workdir.clone(), max_steps, read_only)
+                    .with_approver(approver)
+            });
+
+            let reader = tokio::io::BufReader::new(tokio::io::stdin());
+            if matches!(args.command, Commands::Lsp) {
+                LspServer::new(factory, workdir.clone())
+                    .serve(reader, io::stdout())
+                    .await?;
+            } else {
+                ProtoServer::new(factory).serve(reader, io::stdout()).await?;
+            }
+        }
+
+        Commands::Review { diff, pr, format, .. } => {
+            let diff = match pr {
+                Some(url) => {
+                    let token = std::env::var("GITHUB_TOKEN")
+                        .map_err(|_| anyhow::anyhow!("GITHUB_TOKEN is required to review a pull request."))?;
+                    let (repo, number) = github::parse_pull_request_url(url)?;
+                    GitHubClient::new(token, None)
+                        .fetch_pull_request_diff(&repo, number)
+                        .await?
+                }
+                None => {
+                    let base = diff.as_deref().unwrap_or("HEAD");
+                    github::git(&workdir, &["diff", base]).await?
+                }
+            };
+
+            if diff.trim().is_empty() {
+                println!("Nothing to review.");
+                return Ok(());
+            }
+
+            let tools = read_only_tools(workdir.clone());
+            let mut agent = build_agent(&setup, &client_config, &api_key, tools, workdir.clone(), max_steps, true)
+                .with_checkpoints(false);
+
+            let result = agent.run(&review::build_review_task(&diff)).await?;
+            let answer = final_answer(&result.steps)
+                .ok_or_else(|| anyhow::anyhow!("The agent finished without a review."))?;
+            let comments = review::parse_review_comments(&answer)?;
+
+            if format == "json" {
+                println!("{}", serde_json::to_string_pretty(&comments)?);
+            } else {
+                print!("{}", review::render_markdown(&comments));
+            }
+        }
+
+        Commands::Eval {
+            suite,
+            format,
+            input_cost,
+            output_cost,
+            jobs,
+            repeat,
+            requests_per_minute,
+            command_timeout,
+            ..
+        } => {
+            let suite = Suite::load(suite)?;
+            let client = client_config.build(api_key.clone());
+            // Each run's agent is set up from the config like any other;
+            // the runner gives it the shared client.
+            let factory = {
+                let config = config.clone();
+                let redactor = redactor.clone();
+                let telemetry = Arc::clone(&telemetry);
+                let client_config = client_config.clone();
+                Box::new(move |client, tools, workdir, max_steps| {
+                    let setup = AgentSetup {
+                        config: &config,
+                        redactor: &redactor,
+                        telemetry: &telemetry,
+                        local: true,
+                    };
+                    let mut agent = build_agent(&setup, &client_config, &api_key, tools, workdir, max_steps, false);
+                    agent.set_client(client);
+                    agent
+                })
+            };
+            let mut runner = EvalRunner::new(Arc::from(client))
+                .with_agent_factory(factory)
+                .with_max_steps(max_steps)
+                .with_network(config.network.clone())
+                .with_limits(config.limits.clone())
+                .with_concurrency(*jobs)
+                .with_repeat(*repeat);
+            if let Some(rate) = requests_per_minute {
+                runner = runner.with_rate_limit(*rate);
+            }
+            if let Some(seconds) = command_timeout {
+                runner = runner.with_command_timeout(std::time::Duration::from_secs(*seconds));
+            }
+            if let (Some(input), Some(output)) = (input_cost, output_cost) {
+                runner = runner.with_pricing(Pricing {
+                    input_per_mtok: *input,
+                    output_per_mtok: *output,
+                });
+            }
+
+            let report = runner.run(&suite).await;
+
+            if format == "json" {
+                println!("{}", serde_json::to_string_pretty(&report)?);
+            } else {
+                print!("{}", report.render_table());
+            }
+        }
+
+        Commands::EvalCompare { baseline, current, format } => {
+            let comparison = Comparison::new(&SuiteReport::load(baseline)?, &SuiteReport::load(current)?);
+            if format == "json" {
+                println!("{}", serde_json::to_string_pretty(&comparison)?);
+            } else {
+                print!("{}", comparison.render_markdown());
+            }
+        }
+    }
+
+    Ok(())
+}
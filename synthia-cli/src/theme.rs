@@ -0,0 +1,161 @@
+use std::fmt::Display;
+use std::io::IsTerminal;
+use std::sync::OnceLock;
+use synthia_core::core::{Detail, Step, render_step};
+
+/// The colors of terminal output, chosen with `--theme`.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub(crate) enum ThemeName {
+    /// For dark terminal backgrounds.
+    #[default]
+    Dark,
+    /// For light terminal backgrounds.
+    Light,
+    /// No colors at all.
+    Plain,
+}
+
+impl std::str::FromStr for ThemeName {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.to_ascii_lowercase().as_str() {
+            "dark" => Ok(ThemeName::Dark),
+            "light" => Ok(ThemeName::Light),
+            "plain" | "none" => Ok(ThemeName::Plain),
+            _ => Err(format!("Unknown theme '{}', expected dark, light or plain", s)),
+        }
+    }
+}
+
+/// What a piece of output is, which decides its color.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub(crate) enum Role {
+    Heading,
+    Thought,
+    Action,
+    Observation,
+    Added,
+    Removed,
+    Success,
+    Warning,
+    Error,
+}
+
+impl ThemeName {
+    /// The SGR parameters for `role`, or `None` to leave it uncolored.
+    fn code(self, role: Role) -> Option<&'static str> {
+        match (self, role) {
+            (ThemeName::Plain, _) => None,
+            (ThemeName::Dark, Role::Heading) => Some("1;36"),
+            (ThemeName::Dark, Role::Thought) => Some("36"),
+            (ThemeName::Dark, Role::Action) => Some("1;33"),
+            (ThemeName::Light, Role::Heading) => Some("1;34"),
+            (ThemeName::Light, Role::Thought) => Some("34"),
+            (ThemeName::Light, Role::Action) => Some("1;35"),
+            (_, Role::Observation) => Some("1;32"),
+            (_, Role::Added | Role::Success) => Some("32"),
+            (_, Role::Removed) => Some("31"),
+            (ThemeName::Dark, Role::Warning) => Some("33"),
+            (ThemeName::Light, Role::Warning) => Some("35"),
+            (_, Role::Error) => Some("1;31"),
+        }
+    }
+}
+
+/// The theme and where it may color: stdout and stderr are checked apart,
+/// since either may be piped.
+struct Theme {
+    name: ThemeName,
+    stdout: bool,
+    stderr: bool,
+}
+
+static THEME: OnceLock<Theme> = OnceLock::new();
+
+/// Sets the theme for the rest of the process. Colors are dropped where
+/// output is not a terminal, and everywhere when `NO_COLOR` is set.
+pub(crate) fn init(name: ThemeName) {
+    let allowed = std::env::var_os("NO_COLOR").is_none_or(|value| value.is_empty()) && name != ThemeName::Plain;
+    let _ = THEME.set(Theme {
+        name,
+        stdout: allowed && std::io::stdout().is_terminal(),
+        stderr: allowed && std::io::stderr().is_terminal(),
+    });
+}
+
+fn apply(name: ThemeName, enabled: bool, role: Role, text: &str) -> String {
+    match name.code(role) {
+        Some(code) if enabled && !text.is_empty() => format!("\x1b[{}m{}\x1b[0m", code, text),
+        _ => text.to_string(),
+    }
+}
+
+/// `text` colored for `role`, for stdout.
+pub(crate) fn paint(role: Role, text: impl Display) -> String {
+    let text = text.to_string();
+    match THEME.get() {
+        Some(theme) => apply(theme.name, theme.stdout, role, &text),
+        None => text,
+    }
+}
+
+/// Prints `message` as a warning on stderr.
+pub(crate) fn warn(message: impl Display) {
+    let text = format!("Warning: {}", message);
+    match THEME.get() {
+        Some(theme) => eprintln!("{}", apply(theme.name, theme.stderr, Role::Warning, &text)),
+        None => eprintln!("{}", text),
+    }
+}
+
+/// A section heading such as `=== Changed Files ===`.
+pub(crate) fn heading(title: &str) -> String {
+    paint(Role::Heading, format!("=== {} ===", title))
+}
+
+/// [`render_step`] with each part in its color.
+pub(crate) fn step(index: usize, step: &Step, detail: Detail) -> String {
+    if !THEME.get().is_some_and(|theme| theme.stdout) {
+        return render_step(index, step, detail);
+    }
+    if detail == Detail::Compact {
+        return format!("{}. {}: {}", index, paint(Role::Action, &step.action), step.observation);
+    }
+
+    let mut out = format!(
+        "{}\n{} {}",
+        paint(Role::Heading, format!("--- Step {} ---", index)),
+        paint(Role::Thought, "Thought:"),
+        step.thought
+    );
+    if !step.action.is_empty() {
+        out.push_str(&format!(
+            "\n{} {}\n{} {}",
+            paint(Role::Action, "Action:"),
+            paint(Role::Action, &step.action),
+            paint(Role::Action, "Action Input:"),
+            step.action_input
+        ));
+    }
+    if !step.observation.is_empty() {
+        out.push_str(&format!("\n{} {}", paint(Role::Observation, "Observation:"), step.observation));
+    }
+    if let Some(diff) = &step.diff {
+        out.push_str(&format!("\n{}\n{}", paint(Role::Heading, "Changes:"), diff_lines(diff.trim_end())));
+    }
+    out
+}
+
+/// `diff` with added lines in green and removed ones in red.
+pub(crate) fn diff_lines(diff: &str) -> String {
+    let lines: Vec<String> = diff
+        .lines()
+        .map(|line| match line.as_bytes().first() {
+            Some(b'+') if !line.starts_with("+++") => paint(Role::Added, line),
+            Some(b'-') if !line.starts_with("---") => paint(Role::Removed, line),
+            _ => line.to_string(),
+        })
+        .collect();
+    lines.join("\n")
+}
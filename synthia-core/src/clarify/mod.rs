@@ -0,0 +1,160 @@
+use crate::clients::{ChunkType, LLMClient, LLMError, Message, MessageRole};
+use crate::prompts::build_clarify_prompt;
+use futures::StreamExt;
+use serde::{Deserialize, Serialize};
+use std::path::Path;
+
+/// Top-level entries of the working directory shown to the model.
+const MAX_ENTRIES: usize = 100;
+
+/// Characters of the README shown to the model.
+const README_CHARS: usize = 2000;
+
+/// Whether to ask clarifying questions before a run, when `--clarify` is
+/// not given.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct ClarifyPolicy {
+    pub enabled: bool,
+    pub max_questions: usize,
+}
+
+impl Default for ClarifyPolicy {
+    fn default() -> Self {
+        Self {
+            enabled: false,
+            max_questions: 3,
+        }
+    }
+}
+
+/// What the model sees of `workdir` before the run: its top-level entries
+/// and the start of its README.
+pub fn repo_overview(workdir: &Path) -> String {
+    let mut entries: Vec<String> = std::fs::read_dir(workdir)
+        .map(|dir| {
+            dir.flatten()
+                .filter_map(|entry| {
+                    let name = entry.file_name().to_string_lossy().into_owned();
+                    if name.starts_with('.') {
+                        return None;
+                    }
+                    let is_dir = entry.file_type().is_ok_and(|kind| kind.is_dir());
+                    Some(if is_dir { format!("{}/", name) } else { name })
+                })
+                .collect()
+        })
+        .unwrap_or_default();
+    entries.sort();
+    entries.truncate(MAX_ENTRIES);
+
+    let mut overview = format!("Files:\n{}", entries.join("\n"));
+    let readme = ["README.md", "README", "README.txt"]
+        .iter()
+        .find_map(|name| std::fs::read_to_string(workdir.join(name)).ok());
+    if let Some(readme) = readme {
+        let end = readme.floor_char_boundary(README_CHARS);
+        overview.push_str("\n\nREADME:\n");
+        overview.push_str(&readme[..end]);
+    }
+    overview
+}
+
+/// Asks the model whether `task` is clear enough to start on, given an
+/// overview of `workdir`. Returns at most `max` questions, none if it is.
+pub async fn clarifying_questions(
+    client: &dyn LLMClient,
+    task: &str,
+    workdir: &Path,
+    max: usize,
+) -> Result<Vec<String>, LLMError> {
+    if max == 0 {
+        return Ok(Vec::new());
+    }
+    let messages = [Message {
+        role: MessageRole::User,
+        content: build_clarify_prompt(task, &repo_overview(workdir), max),
+        tool_calls: None,
+    }];
+
+    let mut stream = client.stream_complete(&messages, &[]).await?;
+    let mut reply = String::new();
+    while let Some(chunk) = stream.next().await {
+        let chunk = chunk?;
+        match chunk.chunk_type {
+            ChunkType::Content => reply.push_str(&chunk.content),
+            ChunkType::Done => break,
+            ChunkType::Error => return Err(LLMError::ApiError(chunk.content)),
+            _ => {}
+        }
+    }
+    Ok(parse_questions(&reply, max))
+}
+
+/// The questions in a reply to [`build_clarify_prompt`]: one per line,
+/// each starting with `Q:`.
+fn parse_questions(reply: &str, max: usize) -> Vec<String> {
+    reply
+        .lines()
+        .filter_map(|line| line.trim().strip_prefix("Q:"))
+        .map(|question| question.trim().to_string())
+        .filter(|question| !question.is_empty())
+        .take(max)
+        .collect()
+}
+
+/// `task` with the user's answers to the clarifying questions. Questions
+/// left unanswered are left to the agent's judgment.
+pub fn build_clarified_task(task: &str, answers: &[(String, String)]) -> String {
+    if answers.is_empty() {
+        return task.to_string();
+    }
+    let mut clarified = format!("{}\n\nClarifications from the user:", task.trim());
+    for (question, answer) in answers {
+        let answer = if answer.trim().is_empty() {
+            "(no answer, use your judgment)"
+        } else {
+            answer.trim()
+        };
+        clarified.push_str(&format!("\n- {}\n  {}", question, answer));
+    }
+    clarified
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::clients::ScriptedClient;
+
+    #[tokio::test]
+    async fn test_clarifying_questions() {
+        let dir = tempfile::tempdir().unwrap();
+        std::fs::create_dir(dir.path().join("src")).unwrap();
+        std::fs::write(dir.path().join("README.md"), "# Parser\nA toy parser.").unwrap();
+        let client = ScriptedClient::from_responses([
+            "Q: Which parser, the lexer or the AST builder?\nSome thinking\nQ: Keep the public API?\nQ: A third?",
+            "READY",
+        ]);
+
+        let questions = clarifying_questions(&client, "Fix the parser", dir.path(), 2).await.unwrap();
+        assert_eq!(questions, ["Which parser, the lexer or the AST builder?", "Keep the public API?"]);
+        let prompt = &client.requests()[0][0].content;
+        assert!(prompt.contains("src/\n") && prompt.contains("A toy parser."));
+
+        let questions = clarifying_questions(&client, "Fix the lexer", dir.path(), 2).await.unwrap();
+        assert!(questions.is_empty());
+    }
+
+    #[test]
+    fn test_build_clarified_task() {
+        let answers = [
+            ("Which parser?".to_string(), "The lexer".to_string()),
+            ("Keep the API?".to_string(), " ".to_string()),
+        ];
+        let task = build_clarified_task("Fix the parser", &answers);
+        assert!(task.starts_with("Fix the parser\n\nClarifications from the user:"));
+        assert!(task.contains("- Which parser?\n  The lexer"));
+        assert!(task.contains("(no answer, use your judgment)"));
+        assert_eq!(build_clarified_task("Fix it", &[]), "Fix it");
+    }
+}
@@ -0,0 +1,238 @@
+use super::{
+    LLMClient, LLMError, Message, ModelInfo, PROVIDERS, StreamChunk, ToolDefinition, api_error, find_provider,
+    parse_stream,
+};
+use crate::redact::Redactor;
+use async_trait::async_trait;
+use futures::{Stream, StreamExt};
+use serde::{Deserialize, Serialize};
+use std::collections::VecDeque;
+use std::convert::Infallible;
+use std::path::{Path, PathBuf};
+use std::pin::Pin;
+use std::sync::{Arc, Mutex};
+use std::time::{SystemTime, UNIX_EPOCH};
+
+pub const CASSETTE_VERSION: u32 = 1;
+
+/// One recorded provider exchange: the request body and the response body
+/// exactly as it arrived, split at the original network read boundaries.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct Cassette {
+    pub version: u32,
+    pub provider: String,
+    pub request: serde_json::Value,
+    pub status: u16,
+    pub chunks: Vec<String>,
+}
+
+impl Cassette {
+    pub fn load(path: &Path) -> Result<Self, LLMError> {
+        let content = std::fs::read_to_string(path)
+            .map_err(|e| LLMError::ConfigError(format!("{}: {}", path.display(), e)))?;
+        serde_json::from_str(&content)
+            .map_err(|e| LLMError::ParseError(format!("{}: {}", path.display(), e)))
+    }
+
+    pub fn save(&self, path: &Path) -> Result<(), LLMError> {
+        let content = serde_json::to_string_pretty(self)
+            .map_err(|e| LLMError::ParseError(e.to_string()))?;
+        std::fs::write(path, content)
+            .map_err(|e| LLMError::ConfigError(format!("{}: {}", path.display(), e)))
+    }
+
+    pub fn redacted(&self, redactor: &Redactor) -> Self {
+        Self {
+            version: self.version,
+            provider: self.provider.clone(),
+            request: redactor.redact_value(&self.request),
+            status: self.status,
+            chunks: self
+                .chunks
+                .iter()
+                .map(|chunk| redactor.redact(chunk).into_owned())
+                .collect(),
+        }
+    }
+
+    pub fn replay(&self) -> impl Stream<Item = Result<StreamChunk, LLMError>> + Send + use<> {
+        // Cassettes from providers we don't know, such as local servers,
+        // are assumed to speak the OpenAI dialect.
+        let provider = find_provider(&self.provider).unwrap_or(&PROVIDERS[0]);
+        let chunks: Vec<Result<String, Infallible>> = self.chunks.iter().cloned().map(Ok).collect();
+        parse_stream(provider.translator(), futures::stream::iter(chunks), None)
+    }
+}
+
+/// Serves recorded cassettes in order through the [`LLMClient`] interface.
+pub struct ReplayClient {
+    cassettes: Mutex<VecDeque<Cassette>>,
+}
+
+impl ReplayClient {
+    pub fn new(cassettes: Vec<Cassette>) -> Self {
+        Self {
+            cassettes: Mutex::new(cassettes.into()),
+        }
+    }
+
+    pub fn from_dir(dir: &Path) -> Result<Self, LLMError> {
+        let mut paths: Vec<PathBuf> = std::fs::read_dir(dir)
+            .map_err(|e| LLMError::ConfigError(format!("{}: {}", dir.display(), e)))?
+            .flatten()
+            .map(|entry| entry.path())
+            .filter(|path| path.extension().is_some_and(|ext| ext == "json"))
+            .collect();
+        paths.sort();
+
+        let cassettes = paths
+            .iter()
+            .map(|path| Cassette::load(path))
+            .collect::<Result<Vec<_>, _>>()?;
+        Ok(Self::new(cassettes))
+    }
+}
+
+#[async_trait]
+impl LLMClient for ReplayClient {
+    async fn stream_complete(
+        &self,
+        _messages: &[Message],
+        _tools: &[ToolDefinition],
+    ) -> Result<Pin<Box<dyn Stream<Item = Result<StreamChunk, LLMError>> + Send>>, LLMError> {
+        let cassette = self
+            .cassettes
+            .lock()
+            .unwrap_or_else(|e| e.into_inner())
+            .pop_front()
+            .ok_or_else(|| LLMError::ApiError("No recorded cassettes left".to_string()))?;
+
+        if !(200..300).contains(&cassette.status) {
+            return Err(api_error(cassette.status, &cassette.chunks.concat()));
+        }
+
+        Ok(Box::pin(cassette.replay()))
+    }
+
+    fn model_info(&self) -> ModelInfo {
+        ModelInfo {
+            name: "replay".to_string(),
+            max_tokens: None,
+            supports_streaming: true,
+        }
+    }
+}
+
+/// Writes every exchange of a live client into `dir` as a redacted cassette.
+#[derive(Debug, Clone)]
+pub struct Recorder {
+    dir: PathBuf,
+    provider: String,
+    redactor: Arc<Redactor>,
+}
+
+impl Recorder {
+    pub fn new(dir: PathBuf, provider: impl Into<String>, redactor: Redactor) -> Self {
+        Self {
+            dir,
+            provider: provider.into(),
+            redactor: Arc::new(redactor),
+        }
+    }
+
+    pub(crate) fn record<S, B, E>(
+        &self,
+        request: serde_json::Value,
+        status: u16,
+        stream: S,
+    ) -> impl Stream<Item = Result<B, E>> + Send + use<S, B, E>
+    where
+        S: Stream<Item = Result<B, E>> + Send,
+        B: AsRef<[u8]> + Send,
+        E: Send,
+    {
+        let recorder = self.clone();
+
+        async_stream::stream! {
+            let mut stream = std::pin::pin!(stream);
+            let mut chunks = Vec::new();
+
+            while let Some(item) = stream.next().await {
+                if let Ok(bytes) = &item {
+                    chunks.push(String::from_utf8_lossy(bytes.as_ref()).into_owned());
+                }
+                yield item;
+            }
+
+            let cassette = Cassette {
+                version: CASSETTE_VERSION,
+                provider: recorder.provider.clone(),
+                request,
+                status,
+                chunks,
+            }
+            .redacted(&recorder.redactor);
+
+            let nanos = SystemTime::now()
+                .duration_since(UNIX_EPOCH)
+                .map(|d| d.as_nanos())
+                .unwrap_or_default();
+            let path = recorder.dir.join(format!("{}-{}.json", recorder.provider, nanos));
+            if let Err(e) = std::fs::create_dir_all(&recorder.dir).map_err(|e| LLMError::ConfigError(e.to_string())).and_then(|_| cassette.save(&path)) {
+                tracing::warn!("Failed to save cassette {}: {}", path.display(), e);
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::clients::ChunkType;
+
+    #[tokio::test]
+    async fn test_record_then_replay() {
+        let dir = tempfile::tempdir().unwrap();
+        let mut redactor = Redactor::empty();
+        redactor.add_literal("sk-secret-key-value");
+        let recorder = Recorder::new(dir.path().to_path_buf(), "openai", redactor);
+
+        let body: Vec<Result<&[u8], Infallible>> = vec![
+            Ok(b"data: {\"choices\":[{\"delta\":{\"content\":\"Hel"),
+            Ok(b"lo sk-secret-key-value\"}}]}\n\ndata: [DONE]\n\n"),
+        ];
+        let recorded: Vec<_> = recorder
+            .record(serde_json::json!({"model": "gpt-4o"}), 200, futures::stream::iter(body))
+            .collect()
+            .await;
+        assert_eq!(recorded.len(), 2);
+
+        let client = ReplayClient::from_dir(dir.path()).unwrap();
+        let chunks: Vec<_> = client
+            .stream_complete(&[], &[])
+            .await
+            .unwrap()
+            .map(|chunk| chunk.unwrap())
+            .collect()
+            .await;
+
+        assert_eq!(chunks[0].content, "Hello [REDACTED]");
+        assert_eq!(chunks[1].chunk_type, ChunkType::Done);
+        assert!(client.stream_complete(&[], &[]).await.is_err());
+    }
+
+    #[tokio::test]
+    async fn test_replay_error_status() {
+        let client = ReplayClient::new(vec![Cassette {
+            version: CASSETTE_VERSION,
+            provider: "openai".to_string(),
+            request: serde_json::json!({}),
+            status: 429,
+            chunks: vec!["rate limited".to_string()],
+        }]);
+
+        let error = client.stream_complete(&[], &[]).await.err().unwrap();
+
+        assert!(error.to_string().contains("429"));
+    }
+}
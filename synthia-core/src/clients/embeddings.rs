@@ -0,0 +1,112 @@
+use super::{LLMError, api_error};
+use async_trait::async_trait;
+use serde::Deserialize;
+use std::time::Duration;
+
+/// Turns text into vectors whose cosine similarity reflects how related the
+/// texts are.
+#[async_trait]
+pub trait Embedder: Send + Sync {
+    /// One vector per text, in order.
+    async fn embed(&self, texts: &[String]) -> Result<Vec<Vec<f32>>, LLMError>;
+}
+
+#[derive(Deserialize)]
+struct EmbeddingResponse {
+    data: Vec<EmbeddingData>,
+}
+
+#[derive(Deserialize)]
+struct EmbeddingData {
+    index: usize,
+    embedding: Vec<f32>,
+}
+
+/// An OpenAI-compatible `/embeddings` endpoint.
+pub struct OpenAIEmbedder {
+    api_key: String,
+    model: String,
+    url: String,
+    client: reqwest::Client,
+    timeout: Duration,
+}
+
+impl OpenAIEmbedder {
+    pub fn new(api_key: String, model: String, url: String) -> Self {
+        Self {
+            api_key,
+            model,
+            url,
+            client: crate::http::shared_client(),
+            timeout: Duration::from_secs(120),
+        }
+    }
+
+    /// The embeddings endpoint next to a chat completions endpoint, e.g.
+    /// `https://api.openai.com/v1/embeddings` for OpenAI's.
+    pub fn url_for(chat_url: &str) -> String {
+        match chat_url.strip_suffix("/chat/completions") {
+            Some(base) => format!("{}/embeddings", base),
+            None => chat_url.to_string(),
+        }
+    }
+}
+
+#[async_trait]
+impl Embedder for OpenAIEmbedder {
+    async fn embed(&self, texts: &[String]) -> Result<Vec<Vec<f32>>, LLMError> {
+        if texts.is_empty() {
+            return Ok(Vec::new());
+        }
+        let response = self
+            .client
+            .post(&self.url)
+            .header("Authorization", format!("Bearer {}", self.api_key))
+            .timeout(self.timeout)
+            .json(&serde_json::json!({ "model": self.model, "input": texts }))
+            .send()
+            .await
+            .map_err(|e| LLMError::RequestFailed(e.to_string()))?;
+
+        let status = response.status();
+        let body = response.text().await.map_err(|e| LLMError::RequestFailed(e.to_string()))?;
+        if !status.is_success() {
+            return Err(api_error(status.as_u16(), &body));
+        }
+        let mut response: EmbeddingResponse =
+            serde_json::from_str(&body).map_err(|e| LLMError::ParseError(e.to_string()))?;
+        if response.data.len() != texts.len() {
+            return Err(LLMError::ParseError(format!(
+                "Expected {} embeddings, got {}",
+                texts.len(),
+                response.data.len()
+            )));
+        }
+        response.data.sort_by_key(|data| data.index);
+        Ok(response.data.into_iter().map(|data| data.embedding).collect())
+    }
+}
+
+/// Cosine similarity of two vectors, 0 if either is all zeros.
+pub fn cosine_similarity(a: &[f32], b: &[f32]) -> f32 {
+    let dot: f32 = a.iter().zip(b).map(|(x, y)| x * y).sum();
+    let norm = |v: &[f32]| v.iter().map(|x| x * x).sum::<f32>().sqrt();
+    let norms = norm(a) * norm(b);
+    if norms == 0.0 { 0.0 } else { dot / norms }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_url_for() {
+        assert_eq!(
+            OpenAIEmbedder::url_for("https://api.openai.com/v1/chat/completions"),
+            "https://api.openai.com/v1/embeddings"
+        );
+        assert_eq!(OpenAIEmbedder::url_for("http://localhost:8080/embed"), "http://localhost:8080/embed");
+        assert!((cosine_similarity(&[1.0, 0.0], &[2.0, 0.0]) - 1.0).abs() < 1e-6);
+        assert_eq!(cosine_similarity(&[0.0, 0.0], &[1.0, 0.0]), 0.0);
+    }
+}
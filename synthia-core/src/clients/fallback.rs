@@ -0,0 +1,116 @@
+use super::{ChunkType, LLMClient, LLMError, Message, ModelInfo, StreamChunk, ToolDefinition, Usage};
+use async_trait::async_trait;
+use futures::{Stream, StreamExt};
+use std::pin::Pin;
+
+/// Sends each request to `primary`, and retries it on a long-context
+/// `fallback` model only when the primary rejects it as too long. The
+/// switch lasts for that one request; the next goes to the primary again.
+///
+/// The agent compresses its context before every request, so this only
+/// comes into play when compression could not bring it under the primary
+/// model's window. The fallback's usage names its model, so it is priced
+/// at that model's rates.
+pub struct FallbackClient {
+    primary: Box<dyn LLMClient>,
+    fallback: Box<dyn LLMClient>,
+}
+
+impl FallbackClient {
+    pub fn new(primary: Box<dyn LLMClient>, fallback: Box<dyn LLMClient>) -> Self {
+        Self { primary, fallback }
+    }
+}
+
+#[async_trait]
+impl LLMClient for FallbackClient {
+    async fn stream_complete(
+        &self,
+        messages: &[Message],
+        tools: &[ToolDefinition],
+    ) -> Result<Pin<Box<dyn Stream<Item = Result<StreamChunk, LLMError>> + Send>>, LLMError> {
+        match self.primary.stream_complete(messages, tools).await {
+            Err(LLMError::ContextLengthExceeded(reason)) => {
+                tracing::warn!(
+                    "{} rejected the context ({}), retrying this turn on {}",
+                    self.primary.model_info().name,
+                    reason,
+                    self.fallback.model_info().name
+                );
+                let model = self.fallback.model_info().name;
+                let stream = self.fallback.stream_complete(messages, tools).await?;
+                Ok(Box::pin(stream.map(move |chunk| {
+                    let chunk = chunk?;
+                    if chunk.chunk_type != ChunkType::Usage {
+                        return Ok(chunk);
+                    }
+                    Ok(match serde_json::from_str::<Usage>(&chunk.content) {
+                        Ok(usage) => StreamChunk::usage_by(&usage, &model),
+                        Err(_) => chunk,
+                    })
+                })))
+            }
+            result => result,
+        }
+    }
+
+    fn model_info(&self) -> ModelInfo {
+        self.primary.model_info()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::clients::{CASSETTE_VERSION, Cassette, ReplayClient, ScriptedClient};
+    use futures::StreamExt;
+
+    fn cassette(status: u16, body: &str) -> Cassette {
+        Cassette {
+            version: CASSETTE_VERSION,
+            provider: "openai".to_string(),
+            request: serde_json::json!({}),
+            status,
+            chunks: vec![body.to_string()],
+        }
+    }
+
+    async fn answer(client: &FallbackClient) -> String {
+        let stream = client.stream_complete(&[], &[]).await.unwrap();
+        let chunks: Vec<_> = stream.collect().await;
+        chunks[0].as_ref().unwrap().content.clone()
+    }
+
+    #[tokio::test]
+    async fn test_falls_back_for_one_turn() {
+        let primary = ReplayClient::new(vec![
+            cassette(400, r#"{"error":{"code":"context_length_exceeded"}}"#),
+            cassette(200, "data: {\"choices\":[{\"delta\":{\"content\":\"short\"}}]}\n\ndata: [DONE]\n\n"),
+            cassette(429, "rate limited"),
+        ]);
+        let usage = Usage {
+            input_tokens: 90000,
+            output_tokens: 10,
+            reasoning_tokens: 0,
+        };
+        let fallback = ScriptedClient::new(vec![
+            vec![StreamChunk::content("long"), StreamChunk::usage(&usage), StreamChunk::done()],
+            vec![StreamChunk::content("unused"), StreamChunk::done()],
+        ])
+        .with_model("long-context");
+        let client = FallbackClient::new(Box::new(primary), Box::new(fallback));
+
+        let chunks: Vec<_> = client.stream_complete(&[], &[]).await.unwrap().collect().await;
+        assert_eq!(chunks[0].as_ref().unwrap().content, "long");
+        // Billed by the fallback's model, not the primary's.
+        let billed: serde_json::Value = serde_json::from_str(&chunks[1].as_ref().unwrap().content).unwrap();
+        assert_eq!(billed["model"], "long-context");
+        assert_eq!(billed["input_tokens"], 90000);
+        assert_eq!(answer(&client).await, "short");
+        // Other errors are not retried.
+        assert!(matches!(
+            client.stream_complete(&[], &[]).await.err(),
+            Some(LLMError::ApiError(_))
+        ));
+    }
+}
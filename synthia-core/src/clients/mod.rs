@@ -0,0 +1,843 @@
+use crate::redact::Redactor;
+use async_trait::async_trait;
+use futures::Stream;
+use futures::StreamExt;
+use futures::future::Either;
+use serde::{Deserialize, Serialize};
+use std::path::PathBuf;
+use std::pin::Pin;
+use std::sync::Arc;
+use std::time::Duration;
+use thiserror::Error;
+
+mod cassette;
+mod embeddings;
+mod fallback;
+mod ollama;
+mod responses;
+mod scripted;
+mod translate;
+mod transport;
+
+pub use cassette::{CASSETTE_VERSION, Cassette, Recorder, ReplayClient};
+pub use embeddings::{Embedder, OpenAIEmbedder, cosine_similarity};
+pub use fallback::FallbackClient;
+pub use responses::ResponsesApi;
+pub use scripted::ScriptedClient;
+use translate::{ChatCompletions, ChatRequest, PendingToolCall, Translator, flush_tool_calls};
+use transport::{Event, ndjson_events, sse_events};
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum MessageRole {
+    System,
+    User,
+    Assistant,
+    Tool,
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct Message {
+    pub role: MessageRole,
+    pub content: String,
+    #[serde(default)]
+    pub tool_calls: Option<Vec<ToolCall>>,
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct ToolCall {
+    pub id: String,
+    pub function: ToolFunction,
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct ToolFunction {
+    pub name: String,
+    pub arguments: String,
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct ToolDefinition {
+    pub name: String,
+    pub description: String,
+    pub parameters: serde_json::Value,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum ChunkType {
+    Content,
+    /// Reasoning the provider streams separately from the answer. It is
+    /// shown to the user but never sent back in later requests.
+    Reasoning,
+    ToolCall,
+    ToolArgs,
+    /// Token counts for the whole request, as a JSON [`Usage`].
+    Usage,
+    Done,
+    Error,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct StreamChunk {
+    pub content: String,
+    pub chunk_type: ChunkType,
+    pub delta: bool,
+}
+
+impl StreamChunk {
+    pub fn content(content: impl Into<String>) -> Self {
+        Self {
+            content: content.into(),
+            chunk_type: ChunkType::Content,
+            delta: true,
+        }
+    }
+
+    pub fn usage(usage: &Usage) -> Self {
+        Self {
+            content: serde_json::to_string(usage).unwrap_or_default(),
+            chunk_type: ChunkType::Usage,
+            delta: false,
+        }
+    }
+
+    /// [`Self::usage`] billed by `model`, for a client that answered with
+    /// another model than the one it names, as [`FallbackClient`] can.
+    pub fn usage_by(usage: &Usage, model: &str) -> Self {
+        let mut content = serde_json::to_value(usage).unwrap_or_default();
+        content["model"] = model.into();
+        Self {
+            content: content.to_string(),
+            chunk_type: ChunkType::Usage,
+            delta: false,
+        }
+    }
+
+    pub fn done() -> Self {
+        Self {
+            content: String::new(),
+            chunk_type: ChunkType::Done,
+            delta: false,
+        }
+    }
+}
+
+/// Tokens billed for one request. Reasoning tokens are counted apart from
+/// `output_tokens`, even where the provider reports them as part of it.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub struct Usage {
+    pub input_tokens: u64,
+    pub output_tokens: u64,
+    #[serde(default)]
+    pub reasoning_tokens: u64,
+}
+
+impl Usage {
+    /// Every token billed, reasoning included.
+    pub fn total(&self) -> u64 {
+        self.input_tokens + self.output_tokens + self.reasoning_tokens
+    }
+
+    /// What the tokens cost at `pricing`, with reasoning billed as output.
+    pub fn cost(&self, pricing: &Pricing) -> f64 {
+        pricing.cost(self.input_tokens as usize, (self.output_tokens + self.reasoning_tokens) as usize)
+    }
+}
+
+impl std::ops::AddAssign for Usage {
+    fn add_assign(&mut self, other: Self) {
+        self.input_tokens += other.input_tokens;
+        self.output_tokens += other.output_tokens;
+        self.reasoning_tokens += other.reasoning_tokens;
+    }
+}
+
+/// Price in dollars per million tokens.
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+pub struct Pricing {
+    pub input_per_mtok: f64,
+    pub output_per_mtok: f64,
+}
+
+impl Pricing {
+    pub fn cost(&self, input_tokens: usize, output_tokens: usize) -> f64 {
+        (input_tokens as f64 * self.input_per_mtok + output_tokens as f64 * self.output_per_mtok) / 1_000_000.0
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum ReasoningEffort {
+    Low,
+    Medium,
+    High,
+}
+
+impl ReasoningEffort {
+    pub fn as_str(self) -> &'static str {
+        match self {
+            ReasoningEffort::Low => "low",
+            ReasoningEffort::Medium => "medium",
+            ReasoningEffort::High => "high",
+        }
+    }
+}
+
+impl std::str::FromStr for ReasoningEffort {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.to_ascii_lowercase().as_str() {
+            "low" => Ok(ReasoningEffort::Low),
+            "medium" => Ok(ReasoningEffort::Medium),
+            "high" => Ok(ReasoningEffort::High),
+            _ => Err(format!("Unknown reasoning effort '{}', expected low, medium or high", s)),
+        }
+    }
+}
+
+/// How long a reasoning model may think before answering: a coarse effort
+/// level (OpenAI style) or a token budget (Anthropic style).
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Reasoning {
+    Effort(ReasoningEffort),
+    Budget(u32),
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct ModelInfo {
+    pub name: String,
+    #[serde(default)]
+    pub max_tokens: Option<u32>,
+    #[serde(default)]
+    pub supports_streaming: bool,
+}
+
+#[derive(Debug, Error)]
+pub enum LLMError {
+    #[error("Request failed: {0}")]
+    RequestFailed(String),
+    #[error("API error: {0}")]
+    ApiError(String),
+    #[error("Parse error: {0}")]
+    ParseError(String),
+    #[error("Configuration error: {0}")]
+    ConfigError(String),
+    /// The request didn't fit the model's context window.
+    #[error("Context length exceeded: {0}")]
+    ContextLengthExceeded(String),
+    /// The response stream sent nothing but keep-alives for this many
+    /// seconds.
+    #[error("Response stalled: no data for {0}s")]
+    Stalled(u64),
+}
+
+impl LLMError {
+    /// Whether the same request might succeed if sent again: the
+    /// connection failed or stalled, or the provider was rate limiting or
+    /// failing (HTTP 408, 429 or 5xx).
+    pub fn is_retryable(&self) -> bool {
+        match self {
+            LLMError::RequestFailed(_) | LLMError::Stalled(_) => true,
+            LLMError::ApiError(message) => {
+                let status = message
+                    .strip_prefix("HTTP ")
+                    .and_then(|rest| rest.split(':').next())
+                    .and_then(|code| code.parse::<u16>().ok());
+                matches!(status, Some(408 | 429 | 500..=599))
+            }
+            LLMError::ParseError(_) | LLMError::ConfigError(_) | LLMError::ContextLengthExceeded(_) => false,
+        }
+    }
+}
+
+/// Phrases providers use when a prompt is too long for the model, matched
+/// case-insensitively against the error body.
+const CONTEXT_LENGTH_MARKERS: &[&str] = &[
+    "context_length_exceeded",
+    "maximum context length",
+    "prompt is too long",
+    "context window",
+];
+
+/// Classifies an unsuccessful HTTP response.
+pub(crate) fn api_error(status: u16, body: &str) -> LLMError {
+    let message = format!("HTTP {}: {}", status, body);
+    let lower = body.to_ascii_lowercase();
+    if CONTEXT_LENGTH_MARKERS.iter().any(|marker| lower.contains(marker)) {
+        LLMError::ContextLengthExceeded(message)
+    } else {
+        LLMError::ApiError(message)
+    }
+}
+
+#[async_trait]
+pub trait LLMClient: Send + Sync {
+    async fn stream_complete(
+        &self,
+        messages: &[Message],
+        tools: &[ToolDefinition],
+    ) -> Result<Pin<Box<dyn Stream<Item = Result<StreamChunk, LLMError>> + Send>>, LLMError>;
+
+    fn model_info(&self) -> ModelInfo;
+}
+
+#[async_trait]
+impl<T: LLMClient + ?Sized> LLMClient for Arc<T> {
+    async fn stream_complete(
+        &self,
+        messages: &[Message],
+        tools: &[ToolDefinition],
+    ) -> Result<Pin<Box<dyn Stream<Item = Result<StreamChunk, LLMError>> + Send>>, LLMError> {
+        (**self).stream_complete(messages, tools).await
+    }
+
+    fn model_info(&self) -> ModelInfo {
+        (**self).model_info()
+    }
+}
+
+/// How a provider constrains the ids of tool calls sent back to it.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ToolCallIdFormat {
+    Any,
+    /// Exactly nine ASCII letters or digits (Mistral).
+    Alphanumeric9,
+}
+
+/// The wire format a provider speaks.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Dialect {
+    /// OpenAI's chat completions, streamed as SSE.
+    ChatCompletions,
+    /// Ollama's native `/api/chat`, streamed as one JSON object per line.
+    Ollama,
+}
+
+/// A chat endpoint and the quirks of the provider behind it.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Provider {
+    pub name: &'static str,
+    pub base_url: &'static str,
+    pub api_key_env: &'static str,
+    /// Refuses requests without a key. Local servers take one only when
+    /// put behind an authenticating proxy.
+    pub requires_api_key: bool,
+    pub dialect: Dialect,
+    pub tool_call_ids: ToolCallIdFormat,
+    /// Accepts `stream_options.include_usage`. Others either report usage
+    /// unasked or reject the option.
+    pub stream_usage: bool,
+}
+
+pub const PROVIDERS: &[Provider] = &[
+    Provider {
+        name: "openai",
+        base_url: "https://api.openai.com/v1/chat/completions",
+        api_key_env: "OPENAI_API_KEY",
+        requires_api_key: true,
+        dialect: Dialect::ChatCompletions,
+        tool_call_ids: ToolCallIdFormat::Any,
+        stream_usage: true,
+    },
+    Provider {
+        name: "deepseek",
+        base_url: "https://api.deepseek.com/chat/completions",
+        api_key_env: "DEEPSEEK_API_KEY",
+        requires_api_key: true,
+        dialect: Dialect::ChatCompletions,
+        tool_call_ids: ToolCallIdFormat::Any,
+        stream_usage: true,
+    },
+    Provider {
+        name: "xai",
+        base_url: "https://api.x.ai/v1/chat/completions",
+        api_key_env: "XAI_API_KEY",
+        requires_api_key: true,
+        dialect: Dialect::ChatCompletions,
+        tool_call_ids: ToolCallIdFormat::Any,
+        stream_usage: true,
+    },
+    Provider {
+        name: "mistral",
+        base_url: "https://api.mistral.ai/v1/chat/completions",
+        api_key_env: "MISTRAL_API_KEY",
+        requires_api_key: true,
+        dialect: Dialect::ChatCompletions,
+        tool_call_ids: ToolCallIdFormat::Alphanumeric9,
+        stream_usage: false,
+    },
+    Provider {
+        name: "anthropic",
+        base_url: "https://api.anthropic.com/v1/chat/completions",
+        api_key_env: "ANTHROPIC_API_KEY",
+        requires_api_key: true,
+        dialect: Dialect::ChatCompletions,
+        tool_call_ids: ToolCallIdFormat::Any,
+        stream_usage: true,
+    },
+    Provider {
+        name: "ollama",
+        base_url: "http://localhost:11434/api/chat",
+        api_key_env: "OLLAMA_API_KEY",
+        requires_api_key: false,
+        dialect: Dialect::Ollama,
+        tool_call_ids: ToolCallIdFormat::Any,
+        stream_usage: false,
+    },
+];
+
+impl ToolCallIdFormat {
+    /// Rewrites `id` into a form the provider accepts. The mapping is
+    /// deterministic so a call and its result keep matching ids.
+    pub fn normalize(self, id: &str) -> String {
+        match self {
+            ToolCallIdFormat::Any => id.to_string(),
+            ToolCallIdFormat::Alphanumeric9 => {
+                let alnum: Vec<char> = id.chars().filter(|c| c.is_ascii_alphanumeric()).collect();
+                let tail = &alnum[alnum.len().saturating_sub(9)..];
+                format!("{:0>9}", tail.iter().collect::<String>())
+            }
+        }
+    }
+}
+
+impl Provider {
+    pub(crate) fn translator(&self) -> Arc<dyn Translator> {
+        match self.dialect {
+            Dialect::ChatCompletions => Arc::new(ChatCompletions {
+                tool_call_ids: self.tool_call_ids,
+                stream_usage: self.stream_usage,
+            }),
+            Dialect::Ollama => Arc::new(ollama::Ollama),
+        }
+    }
+
+    /// The URL to post to: `base_url` if given, else the public endpoint.
+    /// Ollama is usually given as just its server, such as
+    /// `http://localhost:11434`, so its chat path is added when missing.
+    fn endpoint(&self, base_url: Option<String>) -> String {
+        match (base_url, self.dialect) {
+            (Some(url), Dialect::Ollama) if !transport::is_websocket(&url) && !url.ends_with("/api/chat") => {
+                format!("{}/api/chat", url.trim_end_matches('/'))
+            }
+            (Some(url), _) => url,
+            (None, _) => self.base_url.to_string(),
+        }
+    }
+}
+
+pub fn find_provider(name: &str) -> Option<&'static Provider> {
+    PROVIDERS
+        .iter()
+        .find(|provider| provider.name.eq_ignore_ascii_case(name))
+}
+
+pub struct OpenAIClient {
+    provider: &'static Provider,
+    translator: Arc<dyn Translator>,
+    api_key: String,
+    model: String,
+    client: reqwest::Client,
+    timeout: Duration,
+    stall_timeout: Option<Duration>,
+    base_url: String,
+    reasoning: Option<Reasoning>,
+    recorder: Option<Recorder>,
+}
+
+impl OpenAIClient {
+    pub fn new(api_key: String, model: String, base_url: Option<String>) -> Self {
+        Self::for_provider(&PROVIDERS[0], api_key, model, base_url)
+    }
+
+    /// A client for `provider`, talking to `base_url` if given instead of
+    /// the provider's public endpoint. A `ws://` or `wss://` URL streams
+    /// the response over a WebSocket.
+    pub fn for_provider(
+        provider: &'static Provider,
+        api_key: String,
+        model: String,
+        base_url: Option<String>,
+    ) -> Self {
+        Self {
+            provider,
+            translator: provider.translator(),
+            api_key,
+            model,
+            client: crate::http::shared_client(),
+            timeout: Duration::from_secs(600),
+            stall_timeout: None,
+            base_url: provider.endpoint(base_url),
+            reasoning: None,
+            recorder: None,
+        }
+    }
+
+    /// Uses `client` instead of the process-wide shared one.
+    pub fn with_http_client(mut self, client: reqwest::Client) -> Self {
+        self.client = client;
+        self
+    }
+
+    /// Gives up on a response stream, with [`LLMError::Stalled`], once it
+    /// has sent no data for `timeout`. Keep-alives don't count as data.
+    pub fn with_stall_timeout(mut self, timeout: Duration) -> Self {
+        self.stall_timeout = Some(timeout);
+        self
+    }
+
+    /// Asks the model to reason with the given effort or token budget.
+    pub fn with_reasoning(mut self, reasoning: Reasoning) -> Self {
+        self.reasoning = Some(reasoning);
+        self
+    }
+
+    /// Talks to the provider's Responses API instead of chat completions.
+    /// The endpoint follows unless a base URL was given.
+    pub fn with_responses_api(mut self, api: ResponsesApi) -> Self {
+        if self.base_url == self.provider.base_url
+            && let Some(root) = self.base_url.strip_suffix("/chat/completions")
+        {
+            self.base_url = format!("{}/responses", root);
+        }
+        self.translator = Arc::new(responses::Responses::new(api));
+        self
+    }
+
+    /// Saves every exchange as a cassette under `dir`, with the API key and
+    /// anything else [`Redactor::default`] recognizes scrubbed out. Only
+    /// exchanges over HTTP are recorded.
+    pub fn with_recording(mut self, dir: PathBuf) -> Self {
+        let mut redactor = Redactor::default();
+        redactor.add_literal(&self.api_key);
+        self.recorder = Some(Recorder::new(dir, self.provider.name, redactor));
+        self
+    }
+}
+
+/// Assembles a transport's events into chunks. With `stall`, the stream
+/// fails once that long passes without data; keep-alives don't reset the
+/// clock.
+pub(crate) fn assemble<S>(
+    translator: Arc<dyn Translator>,
+    events: S,
+    stall: Option<Duration>,
+) -> impl Stream<Item = Result<StreamChunk, LLMError>> + Send
+where
+    S: Stream<Item = Result<Event, LLMError>> + Send,
+{
+    async_stream::stream! {
+        let mut events = std::pin::pin!(events);
+        let mut tool_calls: Vec<PendingToolCall> = Vec::new();
+        let mut last_event = tokio::time::Instant::now();
+
+        loop {
+            let next = match stall {
+                Some(stall) => match tokio::time::timeout_at(last_event + stall, events.next()).await {
+                    Ok(next) => next,
+                    Err(_) => {
+                        yield Err(LLMError::Stalled(stall.as_secs()));
+                        return;
+                    }
+                },
+                None => events.next().await,
+            };
+            let Some(event) = next else {
+                break;
+            };
+            match event {
+                Ok(Event::KeepAlive) => {}
+                Ok(Event::Data(data)) => {
+                    last_event = tokio::time::Instant::now();
+                    if data == "[DONE]" {
+                        break;
+                    }
+                    for chunk in translator.decode_event(&data, &mut tool_calls) {
+                        yield Ok(chunk);
+                    }
+                }
+                Ok(Event::Body(body)) => {
+                    match serde_json::from_str::<serde_json::Value>(&body) {
+                        Ok(json) => {
+                            for chunk in translator.decode_body(&json) {
+                                yield Ok(chunk);
+                            }
+                        }
+                        Err(_) => {
+                            yield Err(LLMError::ParseError(format!("Failed to parse response: {}", body)));
+                        }
+                    }
+                    break;
+                }
+                Err(e) => {
+                    yield Err(e);
+                    return;
+                }
+            }
+        }
+
+        // Reached on [DONE], or when the stream ended without one.
+        for chunk in flush_tool_calls(&mut tool_calls) {
+            yield Ok(chunk);
+        }
+        yield Ok(StreamChunk::done());
+    }
+}
+
+/// Turns a raw HTTP response body into chunks: SSE or plain JSON, or one
+/// JSON object per line for translators that stream that way.
+pub(crate) fn parse_stream<S, B, E>(
+    translator: Arc<dyn Translator>,
+    stream: S,
+    stall: Option<Duration>,
+) -> impl Stream<Item = Result<StreamChunk, LLMError>> + Send
+where
+    S: Stream<Item = Result<B, E>> + Send,
+    B: AsRef<[u8]> + Send,
+    E: std::fmt::Display + Send,
+{
+    let events = if translator.line_delimited() {
+        Either::Left(ndjson_events(stream))
+    } else {
+        Either::Right(sse_events(stream))
+    };
+    assemble(translator, events, stall)
+}
+
+#[async_trait]
+impl LLMClient for OpenAIClient {
+    async fn stream_complete(
+        &self,
+        messages: &[Message],
+        tools: &[ToolDefinition],
+    ) -> Result<Pin<Box<dyn Stream<Item = Result<StreamChunk, LLMError>> + Send>>, LLMError> {
+        let mut request = ChatRequest::new(&self.model, messages, tools);
+        request.reasoning = self.reasoning;
+        let request = self.translator.encode(&request);
+
+        if transport::is_websocket(&self.base_url) {
+            let events = transport::websocket_events(&self.base_url, &self.api_key, &request).await?;
+            return Ok(Box::pin(assemble(Arc::clone(&self.translator), events, self.stall_timeout)));
+        }
+
+        let response = self
+            .client
+            .post(&self.base_url)
+            .header("Authorization", format!("Bearer {}", self.api_key))
+            .header("Content-Type", "application/json")
+            .header("Accept", "text/event-stream")
+            .timeout(self.timeout)
+            .json(&request)
+            .send()
+            .await
+            .map_err(|e| LLMError::RequestFailed(e.to_string()))?;
+
+        let status = response.status();
+        if !status.is_success() {
+            let body = response.text().await.unwrap_or_default();
+            if let Some(recorder) = &self.recorder {
+                let chunks = futures::stream::iter([Ok::<_, std::convert::Infallible>(body.clone())]);
+                recorder.record(request, status.as_u16(), chunks).collect::<Vec<_>>().await;
+            }
+            return Err(api_error(status.as_u16(), &body));
+        }
+
+        match &self.recorder {
+            Some(recorder) => {
+                let status = status.as_u16();
+                let body = recorder.record(request, status, response.bytes_stream());
+                Ok(Box::pin(parse_stream(Arc::clone(&self.translator), body, self.stall_timeout)))
+            }
+            None => Ok(Box::pin(parse_stream(
+                Arc::clone(&self.translator),
+                response.bytes_stream(),
+                self.stall_timeout,
+            ))),
+        }
+    }
+
+    fn model_info(&self) -> ModelInfo {
+        ModelInfo {
+            name: self.model.clone(),
+            max_tokens: Some(16384),
+            supports_streaming: true,
+        }
+    }
+}
+
+pub fn create_llm_client(provider: &str, api_key: String, model: String, base_url: Option<String>) -> Result<Box<dyn LLMClient>, LLMError> {
+    match find_provider(provider) {
+        Some(provider) => Ok(Box::new(OpenAIClient::for_provider(provider, api_key, model, base_url))),
+        None => Err(LLMError::ConfigError(format!("Unknown provider: {}", provider))),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::convert::Infallible;
+
+    async fn parse(chunks: Vec<&'static [u8]>) -> Vec<Result<StreamChunk, LLMError>> {
+        let chunks: Vec<Result<&[u8], Infallible>> = chunks.into_iter().map(Ok).collect();
+        parse_stream(PROVIDERS[0].translator(), futures::stream::iter(chunks), None)
+            .collect()
+            .await
+    }
+
+    #[tokio::test]
+    async fn test_multibyte_character_split_across_reads() {
+        let body = "data: {\"choices\":[{\"delta\":{\"content\":\"caf\u{e9}\"}}]}\n\ndata: [DONE]\n\n".as_bytes();
+        let split = body.iter().position(|b| *b == 0xC3).unwrap() + 1;
+
+        let chunks = parse(vec![&body[..split], &body[split..]]).await;
+
+        assert_eq!(chunks[0].as_ref().unwrap().content, "caf\u{e9}");
+        assert_eq!(chunks[1].as_ref().unwrap().chunk_type, ChunkType::Done);
+    }
+
+    #[tokio::test]
+    async fn test_plain_json_response() {
+        let chunks = parse(vec![
+            b"{\"choices\":[{\"message\":",
+            b"{\"content\":\"hi\"}}]}",
+        ])
+        .await;
+
+        assert_eq!(chunks[0].as_ref().unwrap().content, "hi");
+        assert_eq!(chunks[1].as_ref().unwrap().chunk_type, ChunkType::Done);
+
+        let chunks = parse(vec![b"not json"]).await;
+        assert!(matches!(chunks[0], Err(LLMError::ParseError(_))));
+    }
+
+    #[test]
+    fn test_usage() {
+        let mut usage = Usage {
+            input_tokens: 1000,
+            output_tokens: 200,
+            reasoning_tokens: 0,
+        };
+        usage += Usage {
+            input_tokens: 500,
+            output_tokens: 100,
+            reasoning_tokens: 300,
+        };
+        assert_eq!((usage.input_tokens, usage.output_tokens, usage.reasoning_tokens), (1500, 300, 300));
+        assert_eq!(usage.total(), 2100);
+
+        let pricing = Pricing {
+            input_per_mtok: 2.0,
+            output_per_mtok: 10.0,
+        };
+        // Reasoning is billed as output: 1500 * 2 + 600 * 10, per million.
+        assert!((usage.cost(&pricing) - 0.009).abs() < 1e-12);
+        assert_eq!(Usage::default().cost(&pricing), 0.0);
+
+        let chunk = StreamChunk::usage_by(&usage, "gpt-4.1");
+        assert_eq!(serde_json::from_str::<Usage>(&chunk.content).unwrap(), usage);
+    }
+
+    #[tokio::test]
+    async fn test_keep_alives_and_missing_done() {
+        let chunks = parse(vec![
+            b": keep-alive\n\n",
+            b"data: {\"choices\":[{\"delta\":{\"content\":\"hi\"}}]}\n\n: ping\n\n",
+        ])
+        .await;
+
+        assert_eq!(chunks.len(), 2);
+        assert_eq!(chunks[0].as_ref().unwrap().content, "hi");
+        assert_eq!(chunks[1].as_ref().unwrap().chunk_type, ChunkType::Done);
+    }
+
+    #[tokio::test]
+    async fn test_stall_despite_keep_alives() {
+        let data: &[u8] = b"data: {\"choices\":[{\"delta\":{\"content\":\"hi\"}}]}\n\n";
+        let body = futures::stream::once(async move { Ok::<_, Infallible>(data) }).chain(futures::stream::unfold((), |()| async {
+            tokio::time::sleep(Duration::from_millis(20)).await;
+            Some((Ok(&b": keep-alive\n\n"[..]), ()))
+        }));
+
+        let chunks: Vec<_> =
+            parse_stream(PROVIDERS[0].translator(), body, Some(Duration::from_millis(100))).collect().await;
+
+        assert_eq!(chunks[0].as_ref().unwrap().content, "hi");
+        assert!(matches!(chunks[1], Err(LLMError::Stalled(0))));
+        assert!(LLMError::Stalled(0).is_retryable());
+    }
+
+    #[cfg(feature = "websocket")]
+    #[tokio::test]
+    async fn test_websocket_stream() {
+        use futures::SinkExt;
+        use tokio_tungstenite::tungstenite::Message as Frame;
+
+        let listener = tokio::net::TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let url = format!("ws://{}", listener.local_addr().unwrap());
+        let server = tokio::spawn(async move {
+            let (tcp, _) = listener.accept().await.unwrap();
+            let mut socket = tokio_tungstenite::accept_async(tcp).await.unwrap();
+            let request = socket.next().await.unwrap().unwrap();
+            let request: serde_json::Value = serde_json::from_str(request.to_text().unwrap()).unwrap();
+            assert_eq!(request["model"], "local");
+            socket.send(Frame::Ping(Vec::new().into())).await.unwrap();
+            for content in ["he", "llo"] {
+                let event = serde_json::json!({"choices": [{"delta": {"content": content}}]});
+                socket.send(Frame::text(event.to_string())).await.unwrap();
+            }
+            socket.close(None).await.unwrap();
+        });
+
+        let client = OpenAIClient::new("key".to_string(), "local".to_string(), Some(url));
+        let messages = [Message {
+            role: MessageRole::User,
+            content: "Hi".to_string(),
+            tool_calls: None,
+        }];
+        let chunks: Vec<StreamChunk> =
+            client.stream_complete(&messages, &[]).await.unwrap().map(Result::unwrap).collect().await;
+        server.await.unwrap();
+
+        let content: String = chunks.iter().map(|chunk| chunk.content.as_str()).collect();
+        assert_eq!(content, "hello");
+        assert_eq!(chunks.last().unwrap().chunk_type, ChunkType::Done);
+    }
+
+    #[test]
+    fn test_tool_call_id_format() {
+        assert_eq!(ToolCallIdFormat::Alphanumeric9.normalize("call_12"), "000call12");
+        assert_eq!(ToolCallIdFormat::Alphanumeric9.normalize("D681PevKs"), "D681PevKs");
+        assert_eq!(ToolCallIdFormat::Any.normalize("call_12"), "call_12");
+    }
+
+    #[test]
+    fn test_ollama_endpoint() {
+        let ollama = find_provider("ollama").unwrap();
+        let client = |url: &str| OpenAIClient::for_provider(ollama, String::new(), "llama3".to_string(), Some(url.to_string()));
+        assert_eq!(client("http://localhost:11434/").base_url, "http://localhost:11434/api/chat");
+        assert_eq!(client("http://gpu:11434/api/chat").base_url, "http://gpu:11434/api/chat");
+        assert!(ollama.translator().line_delimited());
+    }
+
+    #[test]
+    fn test_responses_endpoint() {
+        let client = OpenAIClient::new("key".to_string(), "gpt-5".to_string(), None)
+            .with_responses_api(ResponsesApi::default());
+        assert_eq!(client.base_url, "https://api.openai.com/v1/responses");
+
+        let proxied = OpenAIClient::new("key".to_string(), "gpt-5".to_string(), Some("http://proxy/v1".to_string()))
+            .with_responses_api(ResponsesApi::default());
+        assert_eq!(proxied.base_url, "http://proxy/v1");
+    }
+
+    #[test]
+    fn test_retryable() {
+        assert!(api_error(429, "slow down").is_retryable());
+        assert!(api_error(503, "overloaded").is_retryable());
+        assert!(!api_error(401, "bad key").is_retryable());
+        assert!(!api_error(400, "maximum context length is 8192 tokens").is_retryable());
+        assert!(LLMError::RequestFailed("connection reset".to_string()).is_retryable());
+    }
+}
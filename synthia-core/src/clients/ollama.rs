@@ -0,0 +1,223 @@
+use super::translate::{ChatRequest, PendingToolCall, Translator, Turn, flush_tool_calls};
+use super::{ChunkType, StreamChunk, Usage};
+use serde_json::{Value, json};
+use std::collections::HashMap;
+
+/// Ollama's native `/api/chat` dialect. Tool call arguments travel as JSON
+/// objects rather than strings, calls carry no ids, and the response
+/// streams as one JSON object per line.
+#[derive(Debug, Clone, Copy)]
+pub(crate) struct Ollama;
+
+/// Arguments are stored as the JSON text the model wrote; Ollama wants the
+/// object itself. Text that doesn't parse is sent as it is.
+fn arguments_value(arguments: &str) -> Value {
+    serde_json::from_str(arguments).unwrap_or_else(|_| Value::String(arguments.to_string()))
+}
+
+impl Translator for Ollama {
+    fn encode(&self, request: &ChatRequest<'_>) -> Value {
+        // Results name the tool they answer rather than the call.
+        let mut tool_names: HashMap<&str, &str> = HashMap::new();
+        let messages: Vec<Value> = request
+            .turns
+            .iter()
+            .map(|turn| match turn {
+                Turn::System(content) => json!({"role": "system", "content": content}),
+                Turn::User(content) => json!({"role": "user", "content": content}),
+                Turn::Assistant { content, tool_calls } => {
+                    let mut message = json!({"role": "assistant", "content": content});
+                    if !tool_calls.is_empty() {
+                        let tool_calls: Vec<Value> = tool_calls
+                            .iter()
+                            .map(|tc| {
+                                tool_names.insert(tc.id.as_str(), tc.function.name.as_str());
+                                json!({
+                                    "function": {
+                                        "name": tc.function.name,
+                                        "arguments": arguments_value(&tc.function.arguments)
+                                    }
+                                })
+                            })
+                            .collect();
+                        message["tool_calls"] = Value::Array(tool_calls);
+                    }
+                    message
+                }
+                Turn::ToolResult { call_id, content } => {
+                    let mut message = json!({"role": "tool", "content": content});
+                    if let Some(name) = call_id.and_then(|id| tool_names.get(id)) {
+                        message["tool_name"] = Value::String(name.to_string());
+                    }
+                    message
+                }
+            })
+            .collect();
+
+        let mut body = json!({
+            "model": request.model,
+            "messages": messages,
+            "stream": true,
+        });
+
+        if !request.tools.is_empty() {
+            let tools: Vec<Value> = request
+                .tools
+                .iter()
+                .map(|t| {
+                    json!({
+                        "type": "function",
+                        "function": {
+                            "name": t.name,
+                            "description": t.description,
+                            "parameters": t.parameters
+                        }
+                    })
+                })
+                .collect();
+            body["tools"] = Value::Array(tools);
+        }
+
+        // Thinking is on or off; most local models take no effort or budget.
+        if request.reasoning.is_some() {
+            body["think"] = Value::Bool(true);
+        }
+
+        body
+    }
+
+    fn decode_event(&self, data: &str, tool_calls: &mut Vec<PendingToolCall>) -> Vec<StreamChunk> {
+        let mut chunks = Vec::new();
+
+        let Ok(json) = serde_json::from_str::<Value>(data) else {
+            return chunks;
+        };
+
+        // A model that fails mid-response sends the reason in place of a
+        // message.
+        if let Some(error) = json.get("error").and_then(|e| e.as_str()) {
+            chunks.push(StreamChunk {
+                content: error.to_string(),
+                chunk_type: ChunkType::Error,
+                delta: false,
+            });
+            return chunks;
+        }
+
+        let message = &json["message"];
+        if let Some(s) = message.get("thinking").and_then(|t| t.as_str())
+            && !s.is_empty()
+        {
+            chunks.push(StreamChunk {
+                content: s.to_string(),
+                chunk_type: ChunkType::Reasoning,
+                delta: true,
+            });
+        }
+        if let Some(s) = message.get("content").and_then(|c| c.as_str())
+            && !s.is_empty()
+        {
+            chunks.push(StreamChunk::content(s));
+        }
+
+        // Each call arrives whole, without an id, so one is made up from
+        // its position in the response.
+        for tc in message.get("tool_calls").and_then(|t| t.as_array()).into_iter().flatten() {
+            let function = &tc["function"];
+            let Some(name) = function.get("name").and_then(|n| n.as_str()) else {
+                continue;
+            };
+            let arguments = match function.get("arguments") {
+                Some(Value::String(arguments)) => arguments.clone(),
+                Some(arguments) => arguments.to_string(),
+                None => "{}".to_string(),
+            };
+            let id = format!("call_{}", tool_calls.len());
+            tool_calls.push(PendingToolCall::complete(id, name.to_string(), arguments));
+        }
+
+        if json.get("done").and_then(|d| d.as_bool()) == Some(true) {
+            chunks.extend(flush_tool_calls(tool_calls));
+            if let Some(input_tokens) = json.get("prompt_eval_count").and_then(|c| c.as_u64()) {
+                let usage = Usage {
+                    input_tokens,
+                    output_tokens: json.get("eval_count").and_then(|c| c.as_u64()).unwrap_or(0),
+                    reasoning_tokens: 0,
+                };
+                chunks.push(StreamChunk::usage(&usage));
+            }
+        }
+
+        chunks
+    }
+
+    fn decode_body(&self, body: &Value) -> Vec<StreamChunk> {
+        let mut tool_calls = Vec::new();
+        let mut chunks = self.decode_event(&body.to_string(), &mut tool_calls);
+        chunks.extend(flush_tool_calls(&mut tool_calls));
+        for chunk in &mut chunks {
+            chunk.delta = false;
+        }
+        chunks
+    }
+
+    fn line_delimited(&self) -> bool {
+        true
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::clients::{Message, MessageRole, Reasoning, ReasoningEffort, ToolCall, ToolFunction};
+
+    #[test]
+    fn test_ollama_round_trip() {
+        let messages = vec![
+            Message {
+                role: MessageRole::User,
+                content: "Read main.rs".to_string(),
+                tool_calls: None,
+            },
+            Message {
+                role: MessageRole::Assistant,
+                content: String::new(),
+                tool_calls: Some(vec![ToolCall {
+                    id: "call_0".to_string(),
+                    function: ToolFunction {
+                        name: "read_file".to_string(),
+                        arguments: r#"{"path":"main.rs"}"#.to_string(),
+                    },
+                }]),
+            },
+            Message {
+                role: MessageRole::Tool,
+                content: "fn main() {}".to_string(),
+                tool_calls: None,
+            },
+        ];
+        let mut request = ChatRequest::new("qwen3", &messages, &[]);
+        request.reasoning = Some(Reasoning::Effort(ReasoningEffort::Low));
+        let body = Ollama.encode(&request);
+        assert_eq!(body["messages"][1]["tool_calls"][0]["function"]["arguments"], json!({"path": "main.rs"}));
+        assert_eq!(body["messages"][2], json!({"role": "tool", "content": "fn main() {}", "tool_name": "read_file"}));
+        assert_eq!(body["think"], true);
+
+        let mut pending = Vec::new();
+        let thinking = r#"{"message":{"role":"assistant","content":"","thinking":"Hmm"},"done":false}"#;
+        let thinking = Ollama.decode_event(thinking, &mut pending);
+        assert_eq!(thinking[0].chunk_type, ChunkType::Reasoning);
+        let call = r#"{"message":{"role":"assistant","content":"",
+            "tool_calls":[{"function":{"name":"read_file","arguments":{"path":"lib.rs"}}}]},"done":false}"#;
+        assert!(Ollama.decode_event(call, &mut pending).is_empty());
+        let done = r#"{"message":{"role":"assistant","content":""},"done":true,"prompt_eval_count":20,"eval_count":5}"#;
+        let done = Ollama.decode_event(done, &mut pending);
+        assert_eq!(done[0].chunk_type, ChunkType::ToolCall);
+        let call: Value = serde_json::from_str(&done[0].content).unwrap();
+        assert_eq!((call["id"].as_str(), call["arguments"].as_str()), (Some("call_0"), Some(r#"{"path":"lib.rs"}"#)));
+        assert_eq!(done[1], StreamChunk::usage(&Usage { input_tokens: 20, output_tokens: 5, reasoning_tokens: 0 }));
+
+        let failed = Ollama.decode_event(r#"{"error":"model runner has unexpectedly stopped"}"#, &mut pending);
+        assert_eq!(failed[0].chunk_type, ChunkType::Error);
+    }
+}
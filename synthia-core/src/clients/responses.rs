@@ -0,0 +1,373 @@
+use super::translate::{ChatRequest, PendingToolCall, Translator, Turn, flush_tool_calls};
+use super::{ChunkType, Reasoning, StreamChunk, Usage};
+use serde::{Deserialize, Serialize};
+use serde_json::{Value, json};
+use std::collections::hash_map::DefaultHasher;
+use std::hash::{Hash, Hasher};
+use std::sync::Mutex;
+
+/// Settings for OpenAI's Responses API, which some newer models are only
+/// fully featured on. A profile with this section talks to it instead of
+/// chat completions.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct ResponsesApi {
+    /// Lets the provider keep each response, so the next request only
+    /// sends what is new since it, with `previous_response_id`.
+    pub stateful: bool,
+    /// Provider-run tools sent as given, such as
+    /// `{"type": "web_search_preview"}`.
+    pub builtin_tools: Vec<Value>,
+}
+
+impl Default for ResponsesApi {
+    fn default() -> Self {
+        Self {
+            stateful: true,
+            builtin_tools: Vec::new(),
+        }
+    }
+}
+
+/// What the last request sent and the provider stored of its answer.
+#[derive(Debug, Default)]
+struct Chain {
+    /// One hash per turn of the last request.
+    sent: Vec<u64>,
+    response_id: Option<String>,
+    /// The provider's ids of the calls in its answer, in order.
+    call_ids: Vec<String>,
+}
+
+/// How a request continues the stored response.
+struct Continuation {
+    response_id: String,
+    /// The first turn the provider hasn't seen.
+    start: usize,
+    /// Our ids of the calls in the stored response, with the provider's.
+    call_ids: Vec<(String, String)>,
+}
+
+fn hash_turn(turn: &Turn<'_>) -> u64 {
+    let mut hasher = DefaultHasher::new();
+    format!("{:?}", turn).hash(&mut hasher);
+    hasher.finish()
+}
+
+/// The Responses dialect. With [`ResponsesApi::stateful`] it remembers
+/// the last response, and when a request only adds turns to the one before
+/// it, sends just those. Any other change to the history, such as a
+/// compaction, sends it whole again.
+#[derive(Debug)]
+pub(crate) struct Responses {
+    api: ResponsesApi,
+    chain: Mutex<Chain>,
+}
+
+impl Responses {
+    pub(crate) fn new(api: ResponsesApi) -> Self {
+        Self {
+            api,
+            chain: Mutex::new(Chain::default()),
+        }
+    }
+
+    /// How `turns` continue the stored response, if they do: all of the
+    /// previous request's turns, then the assistant turn it answered with.
+    /// The last turn of the previous request may differ, as it can be one
+    /// sent with that request only, like the step counter.
+    fn continuation(&self, turns: &[Turn<'_>], hashes: &[u64]) -> Option<Continuation> {
+        let chain = self.chain.lock().ok()?;
+        let response_id = chain.response_id.clone()?;
+        let matched = hashes.iter().zip(&chain.sent).take_while(|(a, b)| a == b).count();
+        if matched == 0 || matched + 1 < chain.sent.len() {
+            return None;
+        }
+        let Some(Turn::Assistant { tool_calls, .. }) = turns.get(matched) else {
+            return None;
+        };
+        Some(Continuation {
+            response_id,
+            start: matched + 1,
+            call_ids: tool_calls
+                .iter()
+                .zip(&chain.call_ids)
+                .map(|(call, id)| (call.id.clone(), id.clone()))
+                .collect(),
+        })
+    }
+}
+
+/// Reads a Responses `usage` object. `output_tokens` includes any
+/// reasoning tokens, so those are subtracted out.
+fn decode_usage(usage: &Value) -> Option<Usage> {
+    let count = |value: Option<&Value>| value.and_then(|v| v.as_u64()).unwrap_or(0);
+    let reasoning_tokens = count(usage.pointer("/output_tokens_details/reasoning_tokens"));
+    Some(Usage {
+        input_tokens: usage.get("input_tokens")?.as_u64()?,
+        output_tokens: count(usage.get("output_tokens")).saturating_sub(reasoning_tokens),
+        reasoning_tokens,
+    })
+}
+
+impl Translator for Responses {
+    fn encode(&self, request: &ChatRequest<'_>) -> Value {
+        let hashes: Vec<u64> = request.turns.iter().map(hash_turn).collect();
+        let continuation = if self.api.stateful { self.continuation(&request.turns, &hashes) } else { None };
+        let (previous, start, call_ids) = match continuation {
+            Some(continuation) => (Some(continuation.response_id), continuation.start, continuation.call_ids),
+            None => (None, 0, Vec::new()),
+        };
+
+        // Instructions are not carried over from the previous response.
+        let instructions: Vec<&str> = request
+            .turns
+            .iter()
+            .filter_map(|turn| match turn {
+                Turn::System(content) => Some(*content),
+                _ => None,
+            })
+            .collect();
+
+        let mut input = Vec::new();
+        for turn in &request.turns[start..] {
+            match turn {
+                Turn::System(_) => {}
+                Turn::User(content) => input.push(json!({"role": "user", "content": content})),
+                Turn::Assistant { content, tool_calls } => {
+                    if !content.is_empty() {
+                        input.push(json!({"role": "assistant", "content": content}));
+                    }
+                    for call in tool_calls.iter() {
+                        input.push(json!({
+                            "type": "function_call",
+                            "call_id": call.id,
+                            "name": call.function.name,
+                            "arguments": call.function.arguments
+                        }));
+                    }
+                }
+                Turn::ToolResult { call_id, content } => {
+                    // Calls in the stored response go by the provider's ids.
+                    let call_id = match call_id {
+                        Some(id) if previous.is_some() => {
+                            call_ids.iter().find(|(local, _)| local == id).map(|(_, remote)| remote.as_str())
+                        }
+                        Some(id) => Some(*id),
+                        None => None,
+                    };
+                    input.push(match call_id {
+                        Some(call_id) => json!({"type": "function_call_output", "call_id": call_id, "output": content}),
+                        None => json!({"role": "user", "content": content}),
+                    });
+                }
+            }
+        }
+
+        let mut body = json!({
+            "model": request.model,
+            "input": input,
+            "stream": true,
+            "store": self.api.stateful,
+        });
+        if !instructions.is_empty() {
+            body["instructions"] = Value::String(instructions.join("\n\n"));
+        }
+        if let Some(previous) = previous {
+            body["previous_response_id"] = Value::String(previous);
+        }
+
+        let mut tools: Vec<Value> = request
+            .tools
+            .iter()
+            .map(|t| {
+                json!({
+                    "type": "function",
+                    "name": t.name,
+                    "description": t.description,
+                    "parameters": t.parameters
+                })
+            })
+            .collect();
+        tools.extend(self.api.builtin_tools.iter().cloned());
+        if !tools.is_empty() {
+            body["tools"] = Value::Array(tools);
+        }
+
+        match request.reasoning {
+            Some(Reasoning::Effort(effort)) => {
+                body["reasoning"] = json!({"effort": effort.as_str(), "summary": "auto"});
+            }
+            // Effort is the only knob here; a budget still asks for summaries.
+            Some(Reasoning::Budget(_)) => body["reasoning"] = json!({"summary": "auto"}),
+            None => {}
+        }
+
+        if let Ok(mut chain) = self.chain.lock() {
+            *chain = Chain {
+                sent: hashes,
+                ..Chain::default()
+            };
+        }
+        body
+    }
+
+    fn decode_event(&self, data: &str, tool_calls: &mut Vec<PendingToolCall>) -> Vec<StreamChunk> {
+        let Ok(json) = serde_json::from_str::<Value>(data) else {
+            return Vec::new();
+        };
+        let delta = || json.get("delta").and_then(|d| d.as_str()).filter(|d| !d.is_empty());
+
+        match json.get("type").and_then(|t| t.as_str()) {
+            Some("response.output_text.delta") => delta().map(StreamChunk::content).into_iter().collect(),
+            Some("response.reasoning_summary_text.delta") => delta()
+                .map(|s| StreamChunk {
+                    content: s.to_string(),
+                    chunk_type: ChunkType::Reasoning,
+                    delta: true,
+                })
+                .into_iter()
+                .collect(),
+            Some("response.output_item.done") => {
+                let Some(item) = json.get("item").filter(|item| item["type"] == "function_call") else {
+                    return Vec::new();
+                };
+                let field = |key: &str| item.get(key).and_then(|v| v.as_str()).unwrap_or_default().to_string();
+                if let Ok(mut chain) = self.chain.lock() {
+                    chain.call_ids.push(field("call_id"));
+                }
+                tool_calls.push(PendingToolCall::complete(field("call_id"), field("name"), field("arguments")));
+                Vec::new()
+            }
+            Some("response.completed") => {
+                let response = &json["response"];
+                if let Some(id) = response.get("id").and_then(|id| id.as_str())
+                    && let Ok(mut chain) = self.chain.lock()
+                {
+                    chain.response_id = Some(id.to_string());
+                }
+                let mut chunks = flush_tool_calls(tool_calls);
+                if let Some(usage) = response.get("usage").and_then(decode_usage) {
+                    chunks.push(StreamChunk::usage(&usage));
+                }
+                chunks
+            }
+            _ => Vec::new(),
+        }
+    }
+
+    fn decode_body(&self, body: &Value) -> Vec<StreamChunk> {
+        let mut chunks = Vec::new();
+        let mut tool_calls = Vec::new();
+        for item in body.get("output").and_then(|o| o.as_array()).into_iter().flatten() {
+            let texts = |key: &str| -> Vec<String> {
+                item.get(key)
+                    .and_then(|parts| parts.as_array())
+                    .into_iter()
+                    .flatten()
+                    .filter_map(|part| part.get("text").and_then(|t| t.as_str()))
+                    .filter(|text| !text.is_empty())
+                    .map(str::to_string)
+                    .collect()
+            };
+            match item.get("type").and_then(|t| t.as_str()) {
+                Some("reasoning") => chunks.extend(texts("summary").into_iter().map(|text| StreamChunk {
+                    content: text,
+                    chunk_type: ChunkType::Reasoning,
+                    delta: false,
+                })),
+                Some("message") => chunks.extend(texts("content").into_iter().map(|text| StreamChunk {
+                    content: text,
+                    chunk_type: ChunkType::Content,
+                    delta: false,
+                })),
+                Some("function_call") => {
+                    let event = json!({"type": "response.output_item.done", "item": item}).to_string();
+                    chunks.extend(self.decode_event(&event, &mut tool_calls));
+                }
+                _ => {}
+            }
+        }
+        let completed = json!({"type": "response.completed", "response": body}).to_string();
+        chunks.extend(self.decode_event(&completed, &mut tool_calls));
+        chunks
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::clients::{Message, MessageRole, ReasoningEffort, ToolCall, ToolFunction};
+
+    fn message(role: MessageRole, content: &str, call: Option<&str>) -> Message {
+        Message {
+            role,
+            content: content.to_string(),
+            tool_calls: call.map(|id| {
+                vec![ToolCall {
+                    id: id.to_string(),
+                    function: ToolFunction {
+                        name: "read_file".to_string(),
+                        arguments: "{}".to_string(),
+                    },
+                }]
+            }),
+        }
+    }
+
+    #[test]
+    fn test_responses_chain() {
+        let translator = Responses::new(ResponsesApi {
+            stateful: true,
+            builtin_tools: vec![json!({"type": "web_search_preview"})],
+        });
+        let mut history = vec![
+            message(MessageRole::System, "be brief", None),
+            message(MessageRole::User, "read it", None),
+        ];
+        let mut first = history.clone();
+        first.push(message(MessageRole::User, "Step 1/5", None));
+        let mut request = ChatRequest::new("gpt-5", &first, &[]);
+        request.reasoning = Some(Reasoning::Effort(ReasoningEffort::High));
+        let body = translator.encode(&request);
+        assert_eq!(body["instructions"], "be brief");
+        assert_eq!(body["input"].as_array().unwrap().len(), 2);
+        assert_eq!(body["tools"][0]["type"], "web_search_preview");
+        assert_eq!(body["reasoning"]["summary"], "auto");
+        assert!(body.get("previous_response_id").is_none());
+
+        let mut calls = Vec::new();
+        let events = [
+            r#"{"type":"response.reasoning_summary_text.delta","delta":"Reading first."}"#,
+            r#"{"type":"response.output_item.done","item":{"type":"function_call","call_id":"fc_9","name":"read_file","arguments":"{\"path\":\"a\"}"}}"#,
+            r#"{"type":"response.completed","response":{"id":"resp_1","usage":{"input_tokens":10,"output_tokens":50,"output_tokens_details":{"reasoning_tokens":30}}}}"#,
+        ];
+        let chunks: Vec<StreamChunk> = events.iter().flat_map(|event| translator.decode_event(event, &mut calls)).collect();
+        assert_eq!(chunks[0].chunk_type, ChunkType::Reasoning);
+        assert_eq!(chunks[1].chunk_type, ChunkType::ToolCall);
+        assert!(chunks[1].content.contains("fc_9"));
+        let usage: Usage = serde_json::from_str(&chunks[2].content).unwrap();
+        assert_eq!((usage.output_tokens, usage.reasoning_tokens), (20, 30));
+
+        history.push(message(MessageRole::Assistant, "", Some("call_1")));
+        history.push(message(MessageRole::Tool, "contents", None));
+        history.push(message(MessageRole::User, "Step 2/5", None));
+        let body = translator.encode(&ChatRequest::new("gpt-5", &history, &[]));
+        assert_eq!(body["previous_response_id"], "resp_1");
+        assert_eq!(
+            body["input"],
+            json!([
+                {"type": "function_call_output", "call_id": "fc_9", "output": "contents"},
+                {"role": "user", "content": "Step 2/5"}
+            ])
+        );
+
+        // A compacted history no longer continues the stored response.
+        translator.decode_event(r#"{"type":"response.completed","response":{"id":"resp_2"}}"#, &mut calls);
+        history.remove(1);
+        let body = translator.encode(&ChatRequest::new("gpt-5", &history, &[]));
+        assert!(body.get("previous_response_id").is_none());
+        assert_eq!(body["input"][0]["type"], "function_call");
+        assert_eq!(body["input"][1]["call_id"], "call_1");
+    }
+}
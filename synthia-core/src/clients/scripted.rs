@@ -0,0 +1,168 @@
+use super::{LLMClient, LLMError, Message, ModelInfo, StreamChunk, ToolDefinition};
+use async_trait::async_trait;
+use futures::Stream;
+use std::collections::VecDeque;
+use std::pin::Pin;
+use std::sync::Mutex;
+
+/// An [`LLMClient`] that replays a fixed script of turns instead of calling
+/// a provider, so agent-loop behavior can be tested without a network.
+///
+/// Each call to `stream_complete` consumes the next turn and records the
+/// messages it was sent. Once the script runs out every call fails.
+pub struct ScriptedClient {
+    turns: Mutex<VecDeque<Vec<Result<StreamChunk, LLMError>>>>,
+    requests: Mutex<Vec<Vec<Message>>>,
+    model: String,
+}
+
+impl ScriptedClient {
+    pub fn new(turns: Vec<Vec<StreamChunk>>) -> Self {
+        Self {
+            turns: Mutex::new(
+                turns
+                    .into_iter()
+                    .map(|turn| turn.into_iter().map(Ok).collect())
+                    .collect(),
+            ),
+            requests: Mutex::new(Vec::new()),
+            model: "scripted".to_string(),
+        }
+    }
+
+    /// One turn per response, each streamed as a single content chunk.
+    pub fn from_responses<S: Into<String>>(responses: impl IntoIterator<Item = S>) -> Self {
+        Self::new(
+            responses
+                .into_iter()
+                .map(|response| vec![StreamChunk::content(response), StreamChunk::done()])
+                .collect(),
+        )
+    }
+
+    /// Formats a tool call the way the agent's text protocol expects it.
+    pub fn tool_call(name: &str, arguments: serde_json::Value) -> String {
+        crate::protocol::format_tool_call(name, &arguments.to_string())
+    }
+
+    pub fn push_turn(&self, chunks: Vec<StreamChunk>) {
+        self.lock_turns().push_back(chunks.into_iter().map(Ok).collect());
+    }
+
+    /// Queues a turn whose stream yields `error` after any `chunks`.
+    pub fn push_error(&self, chunks: Vec<StreamChunk>, error: LLMError) {
+        let mut turn: Vec<_> = chunks.into_iter().map(Ok).collect();
+        turn.push(Err(error));
+        self.lock_turns().push_back(turn);
+    }
+
+    pub fn requests(&self) -> Vec<Vec<Message>> {
+        self.requests
+            .lock()
+            .unwrap_or_else(|e| e.into_inner())
+            .clone()
+    }
+
+    pub fn remaining(&self) -> usize {
+        self.lock_turns().len()
+    }
+
+    pub fn with_model(mut self, model: impl Into<String>) -> Self {
+        self.model = model.into();
+        self
+    }
+
+    fn lock_turns(&self) -> std::sync::MutexGuard<'_, VecDeque<Vec<Result<StreamChunk, LLMError>>>> {
+        self.turns.lock().unwrap_or_else(|e| e.into_inner())
+    }
+}
+
+#[async_trait]
+impl LLMClient for ScriptedClient {
+    async fn stream_complete(
+        &self,
+        messages: &[Message],
+        _tools: &[ToolDefinition],
+    ) -> Result<Pin<Box<dyn Stream<Item = Result<StreamChunk, LLMError>> + Send>>, LLMError> {
+        self.requests
+            .lock()
+            .unwrap_or_else(|e| e.into_inner())
+            .push(messages.to_vec());
+
+        let turn = self
+            .lock_turns()
+            .pop_front()
+            .ok_or_else(|| LLMError::ApiError("Scripted client has no turns left".to_string()))?;
+
+        Ok(Box::pin(futures::stream::iter(turn)))
+    }
+
+    fn model_info(&self) -> ModelInfo {
+        ModelInfo {
+            name: self.model.clone(),
+            max_tokens: None,
+            supports_streaming: true,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::clients::{ChunkType, MessageRole};
+    use futures::StreamExt;
+
+    fn user(content: &str) -> Message {
+        Message {
+            role: MessageRole::User,
+            content: content.to_string(),
+            tool_calls: None,
+        }
+    }
+
+    #[tokio::test]
+    async fn test_replays_turns_in_order() {
+        let client = ScriptedClient::from_responses(["first", "second"]);
+
+        let chunks: Vec<_> = client
+            .stream_complete(&[user("a")], &[])
+            .await
+            .unwrap()
+            .collect()
+            .await;
+        assert_eq!(chunks[0].as_ref().unwrap().content, "first");
+        assert_eq!(chunks[1].as_ref().unwrap().chunk_type, ChunkType::Done);
+
+        let chunks: Vec<_> = client
+            .stream_complete(&[user("b")], &[])
+            .await
+            .unwrap()
+            .collect()
+            .await;
+        assert_eq!(chunks[0].as_ref().unwrap().content, "second");
+
+        assert_eq!(client.remaining(), 0);
+        assert!(client.stream_complete(&[], &[]).await.is_err());
+        assert_eq!(client.requests().len(), 3);
+        assert_eq!(client.requests()[1][0].content, "b");
+    }
+
+    #[tokio::test]
+    async fn test_push_error() {
+        let client = ScriptedClient::new(vec![]);
+        client.push_error(
+            vec![StreamChunk::content("partial")],
+            LLMError::RequestFailed("connection reset".to_string()),
+        );
+
+        let chunks: Vec<_> = client
+            .stream_complete(&[], &[])
+            .await
+            .unwrap()
+            .collect()
+            .await;
+
+        assert!(chunks[0].is_ok());
+        assert!(matches!(chunks[1], Err(LLMError::RequestFailed(_))));
+    }
+}
@@ -0,0 +1,414 @@
+use super::{
+    ChunkType, Message, MessageRole, Reasoning, StreamChunk, ToolCall, ToolCallIdFormat, ToolDefinition, Usage,
+};
+use std::collections::VecDeque;
+
+/// A chat request in provider-neutral form. Clients lower their messages
+/// into this once and hand it to the provider's [`Translator`], so wire
+/// quirks live in one place per dialect rather than in every client.
+#[derive(Debug)]
+pub(crate) struct ChatRequest<'a> {
+    pub(crate) model: &'a str,
+    pub(crate) turns: Vec<Turn<'a>>,
+    pub(crate) tools: &'a [ToolDefinition],
+    pub(crate) reasoning: Option<Reasoning>,
+}
+
+#[derive(Debug, PartialEq)]
+pub(crate) enum Turn<'a> {
+    System(&'a str),
+    User(&'a str),
+    Assistant {
+        content: &'a str,
+        tool_calls: &'a [ToolCall],
+    },
+    ToolResult {
+        /// The call this answers, or `None` if no earlier call is waiting.
+        call_id: Option<&'a str>,
+        content: &'a str,
+    },
+}
+
+impl<'a> ChatRequest<'a> {
+    /// Tool results are stored without the id of the call they answer, but
+    /// they always follow their calls in order, so each one is paired with
+    /// the oldest call not yet answered.
+    pub(crate) fn new(model: &'a str, messages: &'a [Message], tools: &'a [ToolDefinition]) -> Self {
+        let mut unanswered: VecDeque<&str> = VecDeque::new();
+        let mut turns = Vec::with_capacity(messages.len());
+
+        for msg in messages {
+            let content = msg.content.as_str();
+            turns.push(match msg.role {
+                MessageRole::System => Turn::System(content),
+                MessageRole::User => Turn::User(content),
+                MessageRole::Assistant => {
+                    let tool_calls = msg.tool_calls.as_deref().unwrap_or_default();
+                    unanswered.clear();
+                    unanswered.extend(tool_calls.iter().map(|tc| tc.id.as_str()));
+                    Turn::Assistant { content, tool_calls }
+                }
+                MessageRole::Tool => Turn::ToolResult {
+                    call_id: unanswered.pop_front(),
+                    content,
+                },
+            });
+        }
+
+        Self {
+            model,
+            turns,
+            tools,
+            reasoning: None,
+        }
+    }
+}
+
+/// A tool call whose name and arguments are still arriving in pieces.
+#[derive(Debug, Default)]
+pub(crate) struct PendingToolCall {
+    id: String,
+    name: String,
+    arguments: String,
+}
+
+impl PendingToolCall {
+    /// A call that arrived whole.
+    pub(crate) fn complete(id: String, name: String, arguments: String) -> Self {
+        Self { id, name, arguments }
+    }
+}
+
+/// Emits every assembled tool call as a [`ChunkType::ToolCall`] chunk.
+pub(crate) fn flush_tool_calls(tool_calls: &mut Vec<PendingToolCall>) -> Vec<StreamChunk> {
+    tool_calls
+        .drain(..)
+        .filter(|call| !call.name.is_empty())
+        .map(|call| StreamChunk {
+            content: serde_json::json!({
+                "id": call.id,
+                "name": call.name,
+                "arguments": call.arguments,
+            })
+            .to_string(),
+            chunk_type: ChunkType::ToolCall,
+            delta: false,
+        })
+        .collect()
+}
+
+/// Converts between [`ChatRequest`]/[`StreamChunk`] and one provider wire
+/// format.
+pub(crate) trait Translator: Send + Sync {
+    /// Builds the JSON request body.
+    fn encode(&self, request: &ChatRequest<'_>) -> serde_json::Value;
+
+    /// Decodes the payload of one SSE `data:` line, collecting partial tool
+    /// calls in `tool_calls` until the provider says they are complete.
+    fn decode_event(&self, data: &str, tool_calls: &mut Vec<PendingToolCall>) -> Vec<StreamChunk>;
+
+    /// Decodes a complete, non-streaming response body.
+    fn decode_body(&self, body: &serde_json::Value) -> Vec<StreamChunk>;
+
+    /// Whether the response streams as one JSON object per line rather
+    /// than as SSE. Each line is then passed to [`Translator::decode_event`].
+    fn line_delimited(&self) -> bool {
+        false
+    }
+}
+
+/// The OpenAI chat completions dialect, spoken with small variations by
+/// most hosted providers.
+#[derive(Debug, Clone, Copy)]
+pub(crate) struct ChatCompletions {
+    pub(crate) tool_call_ids: ToolCallIdFormat,
+    pub(crate) stream_usage: bool,
+}
+
+/// Reads an OpenAI-style `usage` object. `completion_tokens` includes any
+/// reasoning tokens, so those are subtracted out.
+fn decode_usage(usage: &serde_json::Value) -> Option<Usage> {
+    let count = |value: Option<&serde_json::Value>| value.and_then(|v| v.as_u64()).unwrap_or(0);
+    let reasoning_tokens = count(usage.pointer("/completion_tokens_details/reasoning_tokens"));
+    Some(Usage {
+        input_tokens: usage.get("prompt_tokens")?.as_u64()?,
+        output_tokens: count(usage.get("completion_tokens")).saturating_sub(reasoning_tokens),
+        reasoning_tokens,
+    })
+}
+
+impl Translator for ChatCompletions {
+    fn encode(&self, request: &ChatRequest<'_>) -> serde_json::Value {
+        let messages: Vec<serde_json::Value> = request
+            .turns
+            .iter()
+            .map(|turn| match turn {
+                Turn::System(content) => serde_json::json!({"role": "system", "content": content}),
+                Turn::User(content) => serde_json::json!({"role": "user", "content": content}),
+                Turn::Assistant { content, tool_calls } => {
+                    let mut message = serde_json::json!({"role": "assistant", "content": content});
+                    if !tool_calls.is_empty() {
+                        let tool_calls: Vec<serde_json::Value> = tool_calls
+                            .iter()
+                            .map(|tc| {
+                                serde_json::json!({
+                                    "id": self.tool_call_ids.normalize(&tc.id),
+                                    "type": "function",
+                                    "function": {
+                                        "name": tc.function.name,
+                                        "arguments": tc.function.arguments
+                                    }
+                                })
+                            })
+                            .collect();
+                        message["tool_calls"] = serde_json::Value::Array(tool_calls);
+                    }
+                    message
+                }
+                Turn::ToolResult { call_id, content } => {
+                    let mut message = serde_json::json!({"role": "tool", "content": content});
+                    if let Some(id) = call_id {
+                        message["tool_call_id"] = serde_json::Value::String(self.tool_call_ids.normalize(id));
+                    }
+                    message
+                }
+            })
+            .collect();
+
+        let mut body = serde_json::json!({
+            "model": request.model,
+            "messages": messages,
+            "stream": true,
+        });
+
+        if !request.tools.is_empty() {
+            let tools: Vec<serde_json::Value> = request
+                .tools
+                .iter()
+                .map(|t| {
+                    serde_json::json!({
+                        "type": "function",
+                        "function": {
+                            "name": t.name,
+                            "description": t.description,
+                            "parameters": t.parameters
+                        }
+                    })
+                })
+                .collect();
+            body["tools"] = serde_json::Value::Array(tools);
+        }
+
+        if self.stream_usage {
+            body["stream_options"] = serde_json::json!({"include_usage": true});
+        }
+
+        match request.reasoning {
+            Some(Reasoning::Effort(effort)) => {
+                body["reasoning_effort"] = serde_json::Value::String(effort.as_str().to_string());
+            }
+            // Anthropic's compatible endpoint takes its native thinking block.
+            Some(Reasoning::Budget(tokens)) => {
+                body["thinking"] = serde_json::json!({"type": "enabled", "budget_tokens": tokens});
+            }
+            None => {}
+        }
+
+        body
+    }
+
+    fn decode_event(&self, data: &str, tool_calls: &mut Vec<PendingToolCall>) -> Vec<StreamChunk> {
+        let mut chunks = Vec::new();
+
+        let Ok(json) = serde_json::from_str::<serde_json::Value>(data) else {
+            return chunks;
+        };
+
+        for choice in json.get("choices").and_then(|c| c.as_array()).into_iter().flatten() {
+            if let Some(delta) = choice.get("delta").and_then(|d| d.as_object()) {
+                // DeepSeek's reasoner streams its chain of thought separately.
+                if let Some(s) = delta.get("reasoning_content").and_then(|c| c.as_str())
+                    && !s.is_empty()
+                {
+                    chunks.push(StreamChunk {
+                        content: s.to_string(),
+                        chunk_type: ChunkType::Reasoning,
+                        delta: true,
+                    });
+                }
+
+                if let Some(s) = delta.get("content").and_then(|c| c.as_str())
+                    && !s.is_empty()
+                {
+                    chunks.push(StreamChunk::content(s));
+                }
+
+                for tc in delta
+                    .get("tool_calls")
+                    .and_then(|t| t.as_array())
+                    .into_iter()
+                    .flatten()
+                {
+                    // Mistral omits the index and sends each call whole, so a
+                    // new id without an index starts a new call.
+                    let id = tc.get("id").and_then(|i| i.as_str()).filter(|id| !id.is_empty());
+                    let index = match tc.get("index").and_then(|i| i.as_u64()) {
+                        Some(index) => index as usize,
+                        None => match (tool_calls.last(), id) {
+                            (Some(last), Some(id)) if last.id != id => tool_calls.len(),
+                            _ => tool_calls.len().saturating_sub(1),
+                        },
+                    };
+                    if tool_calls.len() <= index {
+                        tool_calls.resize_with(index + 1, PendingToolCall::default);
+                    }
+                    let call = &mut tool_calls[index];
+
+                    if let Some(id) = id {
+                        call.id = id.to_string();
+                    }
+                    if let Some(function) = tc.get("function") {
+                        if let Some(name) = function.get("name").and_then(|n| n.as_str()) {
+                            call.name.push_str(name);
+                        }
+                        if let Some(args) = function.get("arguments").and_then(|a| a.as_str()) {
+                            call.arguments.push_str(args);
+                        }
+                    }
+                }
+            }
+
+            if choice.get("finish_reason").and_then(|f| f.as_str()) == Some("tool_calls") {
+                chunks.extend(flush_tool_calls(tool_calls));
+            }
+        }
+
+        // Usually sent on a final chunk with no choices.
+        if let Some(usage) = json.get("usage").and_then(decode_usage) {
+            chunks.push(StreamChunk::usage(&usage));
+        }
+
+        chunks
+    }
+
+    fn decode_body(&self, body: &serde_json::Value) -> Vec<StreamChunk> {
+        let mut chunks: Vec<StreamChunk> = body
+            .get("choices")
+            .and_then(|c| c.as_array())
+            .into_iter()
+            .flatten()
+            .filter_map(|choice| {
+                choice
+                    .get("message")
+                    .and_then(|m| m.get("content"))
+                    .and_then(|c| c.as_str())
+            })
+            .filter(|content| !content.is_empty())
+            .map(|content| StreamChunk {
+                content: content.to_string(),
+                chunk_type: ChunkType::Content,
+                delta: false,
+            })
+            .collect();
+        if let Some(usage) = body.get("usage").and_then(decode_usage) {
+            chunks.push(StreamChunk::usage(&usage));
+        }
+        chunks
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::clients::ToolFunction;
+
+    fn message(role: MessageRole, content: &str, call_ids: &[&str]) -> Message {
+        let tool_calls: Vec<ToolCall> = call_ids
+            .iter()
+            .map(|id| ToolCall {
+                id: id.to_string(),
+                function: ToolFunction {
+                    name: "read_file".to_string(),
+                    arguments: "{}".to_string(),
+                },
+            })
+            .collect();
+        Message {
+            role,
+            content: content.to_string(),
+            tool_calls: (!tool_calls.is_empty()).then_some(tool_calls),
+        }
+    }
+
+    #[test]
+    fn test_tool_results_paired_with_calls() {
+        let messages = vec![
+            message(MessageRole::User, "read both", &[]),
+            message(MessageRole::Assistant, "", &["call_a", "call_b"]),
+            message(MessageRole::Tool, "a", &[]),
+            message(MessageRole::Tool, "b", &[]),
+            message(MessageRole::Tool, "stray", &[]),
+        ];
+
+        let request = ChatRequest::new("gpt-4", &messages, &[]);
+
+        assert_eq!(request.turns[2], Turn::ToolResult { call_id: Some("call_a"), content: "a" });
+        assert_eq!(request.turns[3], Turn::ToolResult { call_id: Some("call_b"), content: "b" });
+        assert_eq!(request.turns[4], Turn::ToolResult { call_id: None, content: "stray" });
+    }
+
+    #[test]
+    fn test_chat_completions_encode() {
+        let messages = vec![
+            message(MessageRole::System, "be brief", &[]),
+            message(MessageRole::Assistant, "", &["call_12"]),
+            message(MessageRole::Tool, "{}", &[]),
+        ];
+        let translator = ChatCompletions {
+            tool_call_ids: ToolCallIdFormat::Alphanumeric9,
+            stream_usage: false,
+        };
+
+        let body = translator.encode(&ChatRequest::new("mistral-large-latest", &messages, &[]));
+
+        assert_eq!(body["model"], "mistral-large-latest");
+        assert_eq!(body["messages"][0]["role"], "system");
+        assert_eq!(body["messages"][1]["tool_calls"][0]["id"], "000call12");
+        assert_eq!(body["messages"][2]["tool_call_id"], "000call12");
+        assert!(body.get("tools").is_none());
+        assert!(body.get("stream_options").is_none());
+        assert!(body.get("reasoning_effort").is_none());
+    }
+
+    #[test]
+    fn test_reasoning_and_usage() {
+        let translator = ChatCompletions {
+            tool_call_ids: ToolCallIdFormat::Any,
+            stream_usage: true,
+        };
+        let mut request = ChatRequest::new("o3", &[], &[]);
+        request.reasoning = Some(Reasoning::Effort(crate::clients::ReasoningEffort::High));
+        let body = translator.encode(&request);
+        assert_eq!(body["reasoning_effort"], "high");
+        assert_eq!(body["stream_options"]["include_usage"], true);
+
+        request.reasoning = Some(Reasoning::Budget(2048));
+        let body = translator.encode(&request);
+        assert_eq!(body["thinking"]["budget_tokens"], 2048);
+
+        let chunks = translator.decode_event(
+            r#"{"choices":[],"usage":{"prompt_tokens":10,"completion_tokens":50,"completion_tokens_details":{"reasoning_tokens":30}}}"#,
+            &mut Vec::new(),
+        );
+        let usage: Usage = serde_json::from_str(&chunks[0].content).unwrap();
+        assert_eq!(chunks[0].chunk_type, ChunkType::Usage);
+        assert_eq!(
+            usage,
+            Usage {
+                input_tokens: 10,
+                output_tokens: 20,
+                reasoning_tokens: 30,
+            }
+        );
+    }
+}
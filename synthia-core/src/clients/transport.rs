@@ -0,0 +1,243 @@
+use super::LLMError;
+use futures::{Stream, StreamExt};
+use std::pin::Pin;
+
+/// Upper bound on a single unterminated SSE line, or on a non-SSE body,
+/// before the stream is abandoned as malformed.
+const MAX_PENDING_BYTES: usize = 8 * 1024 * 1024;
+
+/// One unit of a streamed response, whatever carried it. Chunks are
+/// assembled from these, so a new transport only has to produce them.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub(crate) enum Event {
+    /// The payload of one event: an SSE `data:` line or a WebSocket
+    /// message, `[DONE]` included.
+    Data(String),
+    /// Sent only to show the connection is alive: an SSE comment or a
+    /// WebSocket ping.
+    KeepAlive,
+    /// A whole non-streaming response body.
+    Body(String),
+}
+
+pub(crate) type Events = Pin<Box<dyn Stream<Item = Result<Event, LLMError>> + Send>>;
+
+/// Parses every complete line in `buf` into `out`. Returns how many bytes
+/// were consumed.
+fn parse_sse_lines(buf: &[u8], saw_sse: &mut bool, out: &mut Vec<Event>) -> usize {
+    let mut start = 0;
+
+    while let Some(len) = buf[start..].iter().position(|b| *b == b'\n') {
+        let line = String::from_utf8_lossy(&buf[start..start + len]);
+        start += len + 1;
+
+        // A comment, which gateways send as a keep-alive. Only SSE has
+        // them, so the body is not plain JSON.
+        if line.starts_with(':') {
+            *saw_sse = true;
+            tracing::trace!("SSE comment: {}", line.trim_end());
+            out.push(Event::KeepAlive);
+            continue;
+        }
+        let Some(data) = line.trim_end().strip_prefix("data:") else {
+            continue;
+        };
+        *saw_sse = true;
+        let data = data.trim_start();
+        tracing::trace!("SSE data: {}", data);
+        out.push(Event::Data(data.to_string()));
+    }
+
+    start
+}
+
+/// Splits a raw HTTP response body, SSE or plain JSON, into events. Lines
+/// may be split across network reads, so bytes are buffered until their
+/// newline arrives; only the unterminated tail is kept between reads. The
+/// whole body is retained only until it's clear the response is not SSE,
+/// since a plain JSON reply has to be parsed in one piece.
+pub(crate) fn sse_events<S, B, E>(stream: S) -> impl Stream<Item = Result<Event, LLMError>> + Send
+where
+    S: Stream<Item = Result<B, E>> + Send,
+    B: AsRef<[u8]> + Send,
+    E: std::fmt::Display + Send,
+{
+    async_stream::stream! {
+        let mut stream = std::pin::pin!(stream);
+        let mut full_response: Vec<u8> = Vec::new();
+        let mut pending: Vec<u8> = Vec::new();
+        let mut saw_sse = false;
+
+        while let Some(chunk) = stream.next().await {
+            let bytes = match chunk {
+                Ok(bytes) => bytes,
+                Err(e) => {
+                    yield Err(LLMError::RequestFailed(e.to_string()));
+                    return;
+                }
+            };
+            let bytes = bytes.as_ref();
+            if !saw_sse {
+                full_response.extend_from_slice(bytes);
+            }
+            pending.extend_from_slice(bytes);
+
+            let mut events = Vec::new();
+            let consumed = parse_sse_lines(&pending, &mut saw_sse, &mut events);
+            pending.drain(..consumed);
+            if saw_sse && !full_response.is_empty() {
+                full_response = Vec::new();
+            }
+
+            for event in events {
+                yield Ok(event);
+            }
+
+            if pending.len() > MAX_PENDING_BYTES || full_response.len() > MAX_PENDING_BYTES {
+                yield Err(LLMError::ParseError(format!(
+                    "Response line exceeds {} bytes",
+                    MAX_PENDING_BYTES
+                )));
+                return;
+            }
+        }
+
+        if saw_sse {
+            // The last line may have no newline.
+            let pending = String::from_utf8_lossy(&pending);
+            if let Some(data) = pending.trim().strip_prefix("data:") {
+                yield Ok(Event::Data(data.trim_start().to_string()));
+            }
+        } else {
+            yield Ok(Event::Body(String::from_utf8_lossy(&full_response).into_owned()));
+        }
+    }
+}
+
+/// Splits a body of one JSON object per line into events, one per
+/// non-blank line. As with SSE, a line may span several network reads.
+pub(crate) fn ndjson_events<S, B, E>(stream: S) -> impl Stream<Item = Result<Event, LLMError>> + Send
+where
+    S: Stream<Item = Result<B, E>> + Send,
+    B: AsRef<[u8]> + Send,
+    E: std::fmt::Display + Send,
+{
+    async_stream::stream! {
+        let mut stream = std::pin::pin!(stream);
+        let mut pending: Vec<u8> = Vec::new();
+
+        while let Some(chunk) = stream.next().await {
+            match chunk {
+                Ok(bytes) => pending.extend_from_slice(bytes.as_ref()),
+                Err(e) => {
+                    yield Err(LLMError::RequestFailed(e.to_string()));
+                    return;
+                }
+            }
+
+            while let Some(len) = pending.iter().position(|b| *b == b'\n') {
+                let line: Vec<u8> = pending.drain(..=len).collect();
+                let line = String::from_utf8_lossy(&line);
+                if !line.trim().is_empty() {
+                    tracing::trace!("NDJSON line: {}", line.trim_end());
+                    yield Ok(Event::Data(line.trim().to_string()));
+                }
+            }
+
+            if pending.len() > MAX_PENDING_BYTES {
+                yield Err(LLMError::ParseError(format!(
+                    "Response line exceeds {} bytes",
+                    MAX_PENDING_BYTES
+                )));
+                return;
+            }
+        }
+
+        // The last line may have no newline.
+        let pending = String::from_utf8_lossy(&pending);
+        if !pending.trim().is_empty() {
+            yield Ok(Event::Data(pending.trim().to_string()));
+        }
+    }
+}
+
+/// Whether `url` is served over WebSocket rather than HTTP.
+pub(crate) fn is_websocket(url: &str) -> bool {
+    url.starts_with("ws://") || url.starts_with("wss://")
+}
+
+/// Opens a WebSocket to `url`, sends `request` as one text message, and
+/// gives each message received as an event. The stream ends when the
+/// server closes the socket, with or without a `[DONE]` message first.
+#[cfg(feature = "websocket")]
+pub(crate) async fn websocket_events(url: &str, api_key: &str, request: &serde_json::Value) -> Result<Events, LLMError> {
+    use futures::SinkExt;
+    use tokio_tungstenite::tungstenite::client::IntoClientRequest;
+    use tokio_tungstenite::tungstenite::{Error, Message};
+
+    let mut handshake = url
+        .into_client_request()
+        .map_err(|e| LLMError::ConfigError(format!("{}: {}", url, e)))?;
+    let authorization = format!("Bearer {}", api_key)
+        .parse()
+        .map_err(|_| LLMError::ConfigError("API key is not a valid header value".to_string()))?;
+    handshake.headers_mut().insert("Authorization", authorization);
+
+    let (mut socket, _) = tokio_tungstenite::connect_async(handshake).await.map_err(|e| match e {
+        Error::Http(response) => {
+            let body = response.body().as_deref().map(String::from_utf8_lossy).unwrap_or_default();
+            super::api_error(response.status().as_u16(), &body)
+        }
+        e => LLMError::RequestFailed(e.to_string()),
+    })?;
+    socket
+        .send(Message::text(request.to_string()))
+        .await
+        .map_err(|e| LLMError::RequestFailed(e.to_string()))?;
+
+    // Reading on after a close frame would only answer it, and fail if the
+    // server has already hung up.
+    let messages = socket.take_while(|message| futures::future::ready(!matches!(message, Ok(Message::Close(_)))));
+    Ok(Box::pin(messages.filter_map(|message| async move {
+        match message {
+            Ok(Message::Text(text)) => Some(Ok(Event::Data(text.as_str().to_string()))),
+            Ok(Message::Binary(bytes)) => Some(Ok(Event::Data(String::from_utf8_lossy(&bytes).into_owned()))),
+            Ok(Message::Ping(_) | Message::Pong(_)) => Some(Ok(Event::KeepAlive)),
+            Ok(Message::Close(_) | Message::Frame(_)) => None,
+            Err(e) => Some(Err(LLMError::RequestFailed(e.to_string()))),
+        }
+    })))
+}
+
+#[cfg(not(feature = "websocket"))]
+pub(crate) async fn websocket_events(url: &str, _api_key: &str, _request: &serde_json::Value) -> Result<Events, LLMError> {
+    Err(LLMError::ConfigError(format!(
+        "{} is a WebSocket endpoint, which needs the websocket feature",
+        url
+    )))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::convert::Infallible;
+
+    #[tokio::test]
+    async fn test_sse_events() {
+        let body: Vec<Result<&[u8], Infallible>> =
+            vec![Ok(b": ping\n\ndata: {\"a\":1}\n\nevent: x\n"), Ok(b"data:{\"b\":"), Ok(b"2}")];
+        let events: Vec<_> = sse_events(futures::stream::iter(body)).map(Result::unwrap).collect().await;
+        assert_eq!(
+            events,
+            [
+                Event::KeepAlive,
+                Event::Data("{\"a\":1}".to_string()),
+                Event::Data("{\"b\":2}".to_string()),
+            ]
+        );
+
+        let body: Vec<Result<&[u8], Infallible>> = vec![Ok(b"{\"a\":"), Ok(b"1}")];
+        let events: Vec<_> = sse_events(futures::stream::iter(body)).map(Result::unwrap).collect().await;
+        assert_eq!(events, [Event::Body("{\"a\":1}".to_string())]);
+    }
+}
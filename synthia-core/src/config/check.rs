@@ -0,0 +1,227 @@
+//! Finding what serde would silently skip in a config file: keys it
+//! doesn't know, which are usually typos, and keys that have been
+//! replaced. Type mismatches already fail the load with serde's own
+//! line and column.
+
+use serde::Serialize;
+use serde::de::DeserializeOwned;
+use serde_json::Value;
+use std::collections::HashMap;
+use std::fmt;
+
+/// A key in a config file that was ignored or is deprecated. The file
+/// still loads.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ConfigWarning {
+    /// Where the key is, like `timeouts.tools` or `scopes[0].allow`.
+    pub path: String,
+    pub line: Option<usize>,
+    pub message: String,
+}
+
+impl fmt::Display for ConfigWarning {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self.line {
+            Some(line) => write!(f, "line {}: {}", line, self.message),
+            None => write!(f, "{}", self.message),
+        }
+    }
+}
+
+/// Parses `text` as `T` and lists its unknown keys, with the closest
+/// known one as a suggestion, and the keys in `deprecated`, each given as
+/// its path and what to use instead.
+///
+/// A key is unknown when loading and saving again loses it, so this only
+/// needs `T` to round-trip through serde.
+pub fn parse_checked<T: Serialize + DeserializeOwned>(
+    text: &str,
+    deprecated: &[(&str, &str)],
+) -> Result<(T, Vec<ConfigWarning>), serde_json::Error> {
+    let parsed: T = serde_json::from_str(text)?;
+    let input: Value = serde_json::from_str(text)?;
+    let loaded = serde_json::to_value(&parsed)?;
+    let lines = key_lines(text);
+
+    let mut warnings = Vec::new();
+    let mut unknown = Vec::new();
+    find_unknown(&input, &loaded, String::new(), &mut unknown);
+    for (path, key, known) in unknown {
+        if let Some((_, instead)) = deprecated.iter().find(|(old, _)| *old == path) {
+            warnings.push(ConfigWarning {
+                line: lines.get(&path).copied(),
+                message: format!("`{}` is no longer read; {}", path, instead),
+                path,
+            });
+            continue;
+        }
+        let message = match closest(&key, &known) {
+            Some(suggestion) => format!("unknown key `{}` is ignored; did you mean `{}`?", path, suggestion),
+            None => format!("unknown key `{}` is ignored", path),
+        };
+        warnings.push(ConfigWarning {
+            line: lines.get(&path).copied(),
+            path,
+            message,
+        });
+    }
+    // Deprecated keys that are still read, e.g. through an alias.
+    for (old, instead) in deprecated {
+        if let Some(&line) = lines.get(*old)
+            && !warnings.iter().any(|warning| warning.path == *old)
+        {
+            warnings.push(ConfigWarning {
+                path: old.to_string(),
+                line: Some(line),
+                message: format!("`{}` is deprecated; {}", old, instead),
+            });
+        }
+    }
+    warnings.sort_by_key(|warning| warning.line);
+    Ok((parsed, warnings))
+}
+
+fn child_path(parent: &str, key: &str) -> String {
+    if parent.is_empty() { key.to_string() } else { format!("{}.{}", parent, key) }
+}
+
+/// Collects each key of `input` that `loaded` lacks, with its path and
+/// the keys `loaded` has beside it.
+fn find_unknown(input: &Value, loaded: &Value, path: String, unknown: &mut Vec<(String, String, Vec<String>)>) {
+    match (input, loaded) {
+        (Value::Object(input), Value::Object(loaded)) => {
+            for (key, value) in input {
+                let child = child_path(&path, key);
+                match loaded.get(key) {
+                    Some(loaded) => find_unknown(value, loaded, child, unknown),
+                    None => unknown.push((child, key.clone(), loaded.keys().cloned().collect())),
+                }
+            }
+        }
+        (Value::Array(input), Value::Array(loaded)) => {
+            for (i, (input, loaded)) in input.iter().zip(loaded).enumerate() {
+                find_unknown(input, loaded, format!("{}[{}]", path, i), unknown);
+            }
+        }
+        // A value saved in another shape than it was written in can't be
+        // compared key by key.
+        _ => {}
+    }
+}
+
+/// The known key a typo most likely meant.
+fn closest<'a>(key: &str, known: &'a [String]) -> Option<&'a str> {
+    known
+        .iter()
+        .map(|candidate| (edit_distance(key, candidate), candidate))
+        .filter(|(distance, candidate)| *distance <= 2.max(candidate.len() / 3))
+        .min_by_key(|(distance, _)| *distance)
+        .map(|(_, candidate)| candidate.as_str())
+}
+
+fn edit_distance(a: &str, b: &str) -> usize {
+    let b: Vec<char> = b.chars().collect();
+    let mut previous: Vec<usize> = (0..=b.len()).collect();
+    for (i, a) in a.chars().enumerate() {
+        let mut current = vec![i + 1];
+        for (j, b) in b.iter().enumerate() {
+            let substitution = previous[j] + usize::from(a != *b);
+            current.push(substitution.min(previous[j + 1] + 1).min(current[j] + 1));
+        }
+        previous = current;
+    }
+    previous[b.len()]
+}
+
+/// The line each key in the JSON `text` starts on, by the same paths as
+/// [`ConfigWarning::path`].
+fn key_lines(text: &str) -> HashMap<String, usize> {
+    enum Frame {
+        Object { key: Option<String>, expecting_key: bool },
+        Array(usize),
+    }
+
+    fn path(stack: &[Frame]) -> String {
+        let mut path = String::new();
+        for frame in stack {
+            match frame {
+                Frame::Object { key: Some(key), .. } => path = child_path(&path, key),
+                Frame::Object { key: None, .. } => {}
+                Frame::Array(i) => path.push_str(&format!("[{}]", i)),
+            }
+        }
+        path
+    }
+
+    let mut lines = HashMap::new();
+    let mut stack = Vec::new();
+    let mut line = 1;
+    let mut chars = text.chars();
+    while let Some(c) = chars.next() {
+        match c {
+            '\n' => line += 1,
+            '{' => stack.push(Frame::Object { key: None, expecting_key: true }),
+            '[' => stack.push(Frame::Array(0)),
+            '}' | ']' => {
+                stack.pop();
+            }
+            ',' => match stack.last_mut() {
+                Some(Frame::Array(i)) => *i += 1,
+                Some(Frame::Object { expecting_key, .. }) => *expecting_key = true,
+                None => {}
+            },
+            ':' => {
+                if let Some(Frame::Object { expecting_key, .. }) = stack.last_mut() {
+                    *expecting_key = false;
+                }
+            }
+            '"' => {
+                let mut string = String::new();
+                while let Some(c) = chars.next() {
+                    match c {
+                        '\\' => string.extend(chars.next()),
+                        '"' => break,
+                        c => string.push(c),
+                    }
+                }
+                if let Some(Frame::Object { key, expecting_key: true }) = stack.last_mut() {
+                    *key = Some(string);
+                    lines.insert(path(&stack), line);
+                }
+            }
+            _ => {}
+        }
+    }
+    lines
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::config::Config;
+
+    #[test]
+    fn test_parse_checked() {
+        let text = r#"{
+  "timeouts": { "tool_secs": 30 },
+  "scopes": [{ "tools": ["run_command"], "deny": ["infra/"], "alow": ["src/"] }],
+  "history": {},
+  "colour": "auto"
+}"#;
+        let (config, warnings) = parse_checked::<Config>(text, &[("history", "use `retention` instead")]).unwrap();
+        assert_eq!(config.scopes.rules[0].deny, ["infra/"]);
+        let shown: Vec<String> = warnings.iter().map(ToString::to_string).collect();
+        assert_eq!(
+            shown,
+            [
+                "line 2: unknown key `timeouts.tool_secs` is ignored; did you mean `tool_seconds`?",
+                "line 3: unknown key `scopes[0].alow` is ignored; did you mean `allow`?",
+                "line 4: `history` is deprecated; use `retention` instead",
+                "line 5: unknown key `colour` is ignored",
+            ]
+        );
+
+        let error = parse_checked::<Config>("{\n  \"quotas\": { \"tokens\": \"lots\" }\n}", &[]).unwrap_err();
+        assert_eq!(error.line(), 2);
+    }
+}
@@ -0,0 +1,281 @@
+//! Picks the code that goes into the context window when there is more of
+//! it than fits, such as files the user attached or mentioned in the task.
+
+use crate::clients::{Embedder, cosine_similarity};
+use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+use std::path::Path;
+use std::sync::Arc;
+
+mod prefetch;
+
+pub use prefetch::{FileFacts, Prefetch, mentioned_paths, render_facts};
+
+/// Lines per snippet when a file is split up for ranking.
+const CHUNK_LINES: usize = 60;
+
+/// Files larger than this are not attached.
+const MAX_FILE_BYTES: u64 = 1024 * 1024;
+
+/// How much attached code goes into the task, and how it is ranked.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct ContextConfig {
+    pub max_tokens: usize,
+    /// Ranks by embedding similarity with this model instead of by shared
+    /// words, and enables the `semantic_search` tool.
+    pub embedding_model: Option<String>,
+    /// The embeddings endpoint, by default the one next to the provider's
+    /// chat endpoint.
+    pub embedding_url: Option<String>,
+}
+
+impl Default for ContextConfig {
+    fn default() -> Self {
+        Self {
+            max_tokens: 4000,
+            embedding_model: None,
+            embedding_url: None,
+        }
+    }
+}
+
+/// A piece of a file.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct Snippet {
+    pub source: String,
+    /// 1-based.
+    pub start_line: usize,
+    pub text: String,
+}
+
+impl Snippet {
+    pub fn tokens(&self) -> usize {
+        self.text.len() / 4
+    }
+}
+
+/// Splits `content` into snippets of about [`CHUNK_LINES`] lines, ending
+/// each at a blank line where one is near.
+pub fn chunk(source: &str, content: &str) -> Vec<Snippet> {
+    let lines: Vec<&str> = content.lines().collect();
+    let mut snippets = Vec::new();
+    let mut start = 0;
+    while start < lines.len() {
+        let mut end = (start + CHUNK_LINES).min(lines.len());
+        if end < lines.len()
+            && let Some(blank) = (start + CHUNK_LINES * 2 / 3..end).rev().find(|&i| lines[i].trim().is_empty())
+        {
+            end = blank + 1;
+        }
+        snippets.push(Snippet {
+            source: source.to_string(),
+            start_line: start + 1,
+            text: lines[start..end].join("\n"),
+        });
+        start = end;
+    }
+    snippets
+}
+
+/// The files under `workdir` that `task` names, e.g. `src/main.rs` or
+/// `` `Cargo.toml` ``, relative to `workdir`.
+pub fn mentioned_files(task: &str, workdir: &Path) -> Vec<String> {
+    let mut files: Vec<String> = task
+        .split_whitespace()
+        .map(|word| word.trim_matches(|c: char| "`'\"()[]{}<>,;:!?".contains(c)).trim_end_matches('.'))
+        .filter(|word| word.contains('.') || word.contains('/'))
+        .map(|word| word.trim_start_matches("./").to_string())
+        .filter(|word| !word.contains("..") && workdir.join(word).is_file())
+        .collect();
+    files.dedup();
+    files
+}
+
+/// Reads `paths` under `workdir` and splits them into snippets. Files that
+/// are too large or not text are skipped.
+pub fn load_snippets(workdir: &Path, paths: &[String]) -> Vec<Snippet> {
+    let mut snippets = Vec::new();
+    for path in paths {
+        let full = workdir.join(path);
+        if std::fs::metadata(&full).map(|meta| meta.len()).unwrap_or(u64::MAX) > MAX_FILE_BYTES {
+            tracing::warn!("Not attaching {}: missing or larger than {} bytes", path, MAX_FILE_BYTES);
+            continue;
+        }
+        match std::fs::read_to_string(&full) {
+            Ok(content) => snippets.extend(chunk(path, &content)),
+            Err(e) => tracing::warn!("Not attaching {}: {}", path, e),
+        }
+    }
+    snippets
+}
+
+/// Ranks snippets by relevance to a query and keeps the best that fit in a
+/// token budget. With an [`Embedder`] relevance is embedding similarity;
+/// without one, or if embedding fails, it is the words they share.
+pub struct ContextSelector {
+    embedder: Option<Arc<dyn Embedder>>,
+    max_tokens: usize,
+}
+
+impl ContextSelector {
+    pub fn new(max_tokens: usize) -> Self {
+        Self {
+            embedder: None,
+            max_tokens,
+        }
+    }
+
+    pub fn with_embedder(mut self, embedder: Arc<dyn Embedder>) -> Self {
+        self.embedder = Some(embedder);
+        self
+    }
+
+    /// `snippets` with their relevance to `query`, most relevant first.
+    pub async fn rank(&self, query: &str, snippets: Vec<Snippet>) -> Vec<(f32, Snippet)> {
+        let scores = match self.embedding_scores(query, &snippets).await {
+            Some(scores) => scores,
+            None => lexical_scores(query, &snippets),
+        };
+        let mut ranked: Vec<(f32, Snippet)> = scores.into_iter().zip(snippets).collect();
+        ranked.sort_by(|a, b| b.0.total_cmp(&a.0));
+        ranked
+    }
+
+    /// The most relevant of `snippets` that fit in the budget together,
+    /// most relevant first.
+    pub async fn select(&self, query: &str, snippets: Vec<Snippet>) -> Vec<Snippet> {
+        let mut used = 0;
+        let mut selected = Vec::new();
+        for (_, snippet) in self.rank(query, snippets).await {
+            if used + snippet.tokens() > self.max_tokens {
+                continue;
+            }
+            used += snippet.tokens();
+            selected.push(snippet);
+        }
+        selected
+    }
+
+    async fn embedding_scores(&self, query: &str, snippets: &[Snippet]) -> Option<Vec<f32>> {
+        let embedder = self.embedder.as_ref()?;
+        let mut texts = vec![query.to_string()];
+        texts.extend(snippets.iter().map(|snippet| snippet.text.clone()));
+        match embedder.embed(&texts).await {
+            Ok(vectors) => {
+                let (query, snippets) = vectors.split_first()?;
+                Some(snippets.iter().map(|vector| cosine_similarity(query, vector)).collect())
+            }
+            Err(e) => {
+                tracing::warn!("Ranking context by shared words, embedding failed: {}", e);
+                None
+            }
+        }
+    }
+}
+
+fn term_counts(text: &str) -> HashMap<String, f32> {
+    let mut counts = HashMap::new();
+    for word in text
+        .split(|c: char| !c.is_alphanumeric())
+        .filter(|word| word.len() >= 2)
+    {
+        *counts.entry(word.to_lowercase()).or_insert(0.0) += 1.0;
+    }
+    counts
+}
+
+/// Cosine similarity of word counts, a stand-in for embeddings.
+fn lexical_scores(query: &str, snippets: &[Snippet]) -> Vec<f32> {
+    let query = term_counts(query);
+    let norm = |counts: &HashMap<String, f32>| counts.values().map(|n| n * n).sum::<f32>().sqrt();
+    let query_norm = norm(&query);
+    snippets
+        .iter()
+        .map(|snippet| {
+            let counts = term_counts(&snippet.text);
+            let dot: f32 = query.iter().filter_map(|(word, n)| counts.get(word).map(|m| n * m)).sum();
+            let norms = query_norm * norm(&counts);
+            if norms == 0.0 { 0.0 } else { dot / norms }
+        })
+        .collect()
+}
+
+/// `task` followed by `snippets` as attached code.
+pub fn attach(task: &str, snippets: &[Snippet]) -> String {
+    if snippets.is_empty() {
+        return task.to_string();
+    }
+    let mut attached = format!("{}\n\nRelevant code from the files mentioned, most relevant first:\n", task.trim_end());
+    for snippet in snippets {
+        attached.push_str(&format!(
+            "\n{}:{}\n```\n{}\n```\n",
+            snippet.source, snippet.start_line, snippet.text
+        ));
+    }
+    attached
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::clients::LLMError;
+    use async_trait::async_trait;
+
+    fn snippet(source: &str, text: &str) -> Snippet {
+        Snippet {
+            source: source.to_string(),
+            start_line: 1,
+            text: text.to_string(),
+        }
+    }
+
+    #[test]
+    fn test_chunk_and_mentions() {
+        let content: String = (1..=100).map(|i| if i == 50 { "\n".to_string() } else { format!("line {}\n", i) }).collect();
+        let snippets = chunk("a.rs", &content);
+        assert_eq!(snippets.len(), 2);
+        assert_eq!(snippets[1].start_line, 51);
+
+        let dir = tempfile::tempdir().unwrap();
+        std::fs::create_dir(dir.path().join("src")).unwrap();
+        std::fs::write(dir.path().join("src/main.rs"), "").unwrap();
+        std::fs::write(dir.path().join("Cargo.toml"), "").unwrap();
+        let files = mentioned_files("Fix `src/main.rs` and ./Cargo.toml. Ignore e.g. foo.rs or ../x", dir.path());
+        assert_eq!(files, ["src/main.rs", "Cargo.toml"]);
+    }
+
+    #[tokio::test]
+    async fn test_select_lexical_within_budget() {
+        let snippets = vec![
+            snippet("a.rs", "fn render_page() { draw the page header }"),
+            snippet("b.rs", "fn validate_token(token: &str) -> bool { check the auth token signature }"),
+            snippet("c.rs", &"unrelated ".repeat(40)),
+        ];
+
+        let selected = ContextSelector::new(30).select("Where is the auth token checked?", snippets).await;
+
+        let sources: Vec<&str> = selected.iter().map(|s| s.source.as_str()).collect();
+        assert_eq!(sources, ["b.rs", "a.rs"]);
+    }
+
+    struct Axis;
+
+    #[async_trait]
+    impl Embedder for Axis {
+        async fn embed(&self, texts: &[String]) -> Result<Vec<Vec<f32>>, LLMError> {
+            Ok(texts.iter().map(|text| vec![text.matches("auth").count() as f32, 1.0]).collect())
+        }
+    }
+
+    #[tokio::test]
+    async fn test_select_by_embedding() {
+        let snippets = vec![snippet("a.rs", "nothing here"), snippet("b.rs", "auth auth")];
+        let selector = ContextSelector::new(100).with_embedder(Arc::new(Axis));
+
+        let ranked = selector.rank("auth", snippets).await;
+
+        assert_eq!(ranked[0].1.source, "b.rs");
+        assert!(ranked[0].0 > ranked[1].0);
+    }
+}
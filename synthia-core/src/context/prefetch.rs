@@ -0,0 +1,130 @@
+//! Looks up the files a task or tool result names before the model asks:
+//! whether each exists, its size and an outline of its definitions. With
+//! that in front of it the model can read the one file it needs instead
+//! of reading five in turn to find out.
+
+use crate::repomap;
+use futures::StreamExt;
+use serde::{Deserialize, Serialize};
+use std::collections::HashSet;
+use std::path::{Path, PathBuf};
+
+/// How mentioned files are looked up; off unless set.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct Prefetch {
+    /// Files looked up per turn at most.
+    pub max_files: usize,
+    /// Files looked up at once.
+    pub concurrency: usize,
+    /// Definitions listed per file at most.
+    pub max_symbols: usize,
+}
+
+impl Default for Prefetch {
+    fn default() -> Self {
+        Self {
+            max_files: 8,
+            concurrency: 4,
+            max_symbols: 12,
+        }
+    }
+}
+
+/// What was found at a mentioned path.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct FileFacts {
+    /// Relative to the working directory.
+    pub path: String,
+    /// `None` when nothing is there.
+    pub size: Option<u64>,
+    pub outline: Vec<String>,
+}
+
+/// Paths in `text` that name a file under `workdir`, or look like they
+/// should, such as `src/missing.rs`. Absolute paths inside `workdir` are
+/// made relative, so tool results that print them are covered too.
+pub fn mentioned_paths(text: &str, workdir: &Path) -> Vec<String> {
+    let root = workdir.to_string_lossy();
+    let mut seen = HashSet::new();
+    text.split(|c: char| !(c.is_alphanumeric() || "./_-".contains(c)))
+        .map(|word| word.strip_prefix(root.as_ref()).unwrap_or(word))
+        .map(|word| word.trim_start_matches("./").trim_start_matches('/').trim_end_matches('.'))
+        .filter(|word| !word.is_empty() && !word.contains("..") && !word.starts_with('-'))
+        .filter(|word| {
+            let looks_like_file = word.contains('/') && word.rsplit('/').next().is_some_and(|name| name.contains('.'));
+            looks_like_file || (word.contains('.') && workdir.join(word).is_file())
+        })
+        .filter(|word| seen.insert(word.to_string()))
+        .map(str::to_string)
+        .collect()
+}
+
+impl Prefetch {
+    /// Looks up the first [`Prefetch::max_files`] of `paths` under
+    /// `workdir`, [`Prefetch::concurrency`] at a time, in the order given.
+    pub async fn fetch(&self, workdir: &Path, paths: Vec<String>) -> Vec<FileFacts> {
+        let max_symbols = self.max_symbols;
+        futures::stream::iter(paths.into_iter().take(self.max_files))
+            .map(|path| {
+                let full: PathBuf = workdir.join(&path);
+                tokio::task::spawn_blocking(move || {
+                    let size = std::fs::metadata(&full).ok().filter(|meta| meta.is_file()).map(|meta| meta.len());
+                    let mut outline = if size.is_some() { repomap::outline_file(&full) } else { Vec::new() };
+                    outline.truncate(max_symbols);
+                    FileFacts { path, size, outline }
+                })
+            })
+            .buffered(self.concurrency.max(1))
+            .filter_map(|facts| async move { facts.ok() })
+            .collect()
+            .await
+    }
+}
+
+/// `facts` as a context block for the model.
+pub fn render_facts(facts: &[FileFacts]) -> String {
+    let mut text = "Files mentioned so far, looked up for you:\n".to_string();
+    for file in facts {
+        match file.size {
+            Some(size) => text.push_str(&format!("- {} ({} bytes)\n", file.path, size)),
+            None => text.push_str(&format!("- {}: does not exist\n", file.path)),
+        }
+        for symbol in &file.outline {
+            text.push_str(&format!("    {}\n", symbol));
+        }
+    }
+    text
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[tokio::test]
+    async fn test_prefetch() {
+        let dir = tempfile::tempdir().unwrap();
+        std::fs::create_dir(dir.path().join("src")).unwrap();
+        std::fs::write(dir.path().join("src/lib.rs"), "pub fn add() {}\nstruct Point;\n").unwrap();
+        std::fs::write(dir.path().join("notes.txt"), "hi").unwrap();
+
+        let observation = format!(
+            "{{\"file\":\"{}/src/lib.rs\",\"note\":\"see notes.txt, src/gone.rs and e.g. v1.2\"}}",
+            dir.path().display()
+        );
+        let paths = mentioned_paths(&observation, dir.path());
+        assert_eq!(paths, ["src/lib.rs", "notes.txt", "src/gone.rs"]);
+
+        let facts = Prefetch::default().fetch(dir.path(), paths).await;
+        assert_eq!(facts[0].outline, ["pub fn add()", "struct Point;"]);
+        assert_eq!(facts[1].size, Some(2));
+        assert_eq!(facts[2].size, None);
+        assert_eq!(
+            render_facts(&facts),
+            "Files mentioned so far, looked up for you:\n- src/lib.rs (30 bytes)\n    pub fn add()\n    struct Point;\n- notes.txt (2 bytes)\n- src/gone.rs: does not exist\n"
+        );
+
+        let limited = Prefetch { max_files: 1, ..Prefetch::default() };
+        assert_eq!(limited.fetch(dir.path(), vec!["notes.txt".to_string(), "src/lib.rs".to_string()]).await.len(), 1);
+    }
+}
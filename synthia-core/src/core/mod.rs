@@ -0,0 +1,2827 @@
+use async_trait::async_trait;
+use crate::clients::{ChunkType, LLMClient, LLMError, Message, MessageRole, Pricing, ToolDefinition, Usage};
+use crate::context::{FileFacts, Prefetch, mentioned_paths, render_facts};
+use crate::guardrail::{self, Guardrail, Verdict};
+use crate::hooks::{EDIT_FILE_TOOL, HookEvent, Hooks, WRITE_FILE_TOOL};
+use crate::ledger::Checkpoints;
+use crate::memory::{Compaction, ContextCompressor, ConversationHistory, RetentionPolicy};
+use crate::prompts::{
+    build_code_agent_prompt, build_empty_turn_prompt, build_max_steps_prompt, build_read_only_note, build_repeated_observation_prompt, build_repo_map_section,
+    build_project_section, build_scratch_section, build_steering_prompt, build_step_prompt,
+};
+use crate::project;
+use crate::protocol::{self, Delta, DeltaSplitter, Response};
+use crate::redact::Redactor;
+use crate::repomap;
+use crate::telemetry::{NoopSink, TelemetryEvent, TelemetrySink};
+use crate::tools::{
+    ApprovalRequest, Approver, LIST_ALL_TOOLS, MinifySchemas, Permission, PermissionPolicy, ToolError, ToolManager,
+    ToolOutput, ToolScopes, ToolSelection, describe_call, edited_content, list_all_tools_result,
+};
+use serde::{Deserialize, Serialize};
+use std::borrow::Cow;
+use std::collections::{BTreeMap, HashMap, HashSet};
+use futures::Stream;
+use futures::channel::mpsc::{self, UnboundedSender};
+use std::future::Future;
+use std::path::{Path, PathBuf};
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant, SystemTime, UNIX_EPOCH};
+use thiserror::Error;
+
+mod render;
+
+pub use render::{Detail, render_result, render_step, render_usage};
+pub use tokio_util::sync::CancellationToken;
+
+/// How a step ended.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum StepStatus {
+    #[default]
+    Success,
+    /// The tool was unknown or failed. The run stops after this step.
+    ToolError,
+    /// The response was neither a tool call nor a final answer.
+    Parsing,
+    /// The tool or the model response ran out of time. The model is told
+    /// and the run goes on.
+    Timeout,
+    /// The model's response was empty or only whitespace. It is asked
+    /// again.
+    Empty,
+    /// The tool call's arguments were rejected. The model is told why and
+    /// may try again, up to [`ReactAgent::with_max_argument_repairs`] times
+    /// in a row.
+    InvalidArguments,
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct Step {
+    pub thought: String,
+    pub action: String,
+    pub action_input: serde_json::Value,
+    pub observation: String,
+    pub raw: String,
+    /// Unix milliseconds when the step's request was sent.
+    #[serde(default)]
+    pub started_at: u64,
+    /// From the request being sent until the tool, if any, returned.
+    #[serde(default)]
+    pub duration_ms: u64,
+    /// Reported by the provider; `None` when it doesn't report usage.
+    #[serde(default)]
+    pub prompt_tokens: Option<u64>,
+    /// Includes reasoning tokens.
+    #[serde(default)]
+    pub completion_tokens: Option<u64>,
+    #[serde(default)]
+    pub status: StepStatus,
+    /// What the step changed in the working tree, as a unified diff, if
+    /// checkpoints were on and it changed anything.
+    #[serde(default)]
+    pub diff: Option<String>,
+}
+
+impl Step {
+    pub fn new(
+        thought: String,
+        action: String,
+        action_input: serde_json::Value,
+        observation: String,
+        raw: String,
+    ) -> Self {
+        Self {
+            thought,
+            action,
+            action_input,
+            observation,
+            raw,
+            started_at: 0,
+            duration_ms: 0,
+            prompt_tokens: None,
+            completion_tokens: None,
+            status: StepStatus::Success,
+            diff: None,
+        }
+    }
+}
+
+/// Time limits enforced by the agent loop, in seconds. Limits that are not
+/// set are not enforced.
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct Timeouts {
+    /// For every tool without its own entry in `tools`.
+    pub tool_seconds: Option<u64>,
+    /// Per-tool limits, by tool name.
+    pub tools: HashMap<String, u64>,
+    /// For one model response, from sending the request to the last chunk.
+    pub llm_turn_seconds: Option<u64>,
+    /// For a gap in a streamed response. Keep-alives some providers send
+    /// while the model is slow don't count; a stall is retried like a
+    /// dropped connection.
+    pub stall_seconds: Option<u64>,
+}
+
+impl Timeouts {
+    pub fn tool(&self, name: &str) -> Option<Duration> {
+        self.tools
+            .get(name)
+            .copied()
+            .or(self.tool_seconds)
+            .map(Duration::from_secs)
+    }
+
+    pub fn llm_turn(&self) -> Option<Duration> {
+        self.llm_turn_seconds.map(Duration::from_secs)
+    }
+
+    pub fn stall(&self) -> Option<Duration> {
+        self.stall_seconds.map(Duration::from_secs)
+    }
+}
+
+/// How many times each tool may be called in one run, by tool name. Tools
+/// without an entry may be called any number of times. `tokens` caps the
+/// tokens a run may use, as the provider reports them, input and output
+/// together; a run past it stops before its next step.
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct Quotas {
+    pub tools: HashMap<String, usize>,
+    pub tokens: Option<u64>,
+}
+
+impl Quotas {
+    pub fn tool(&self, name: &str) -> Option<usize> {
+        self.tools.get(name).copied()
+    }
+}
+
+/// Awaits `future`, or gives up with `None` once `deadline` has passed.
+async fn within<F: Future + Send>(deadline: Option<tokio::time::Instant>, future: F) -> Option<F::Output> {
+    match deadline {
+        Some(deadline) => tokio::time::timeout_at(deadline, future).await.ok(),
+        None => Some(future.await),
+    }
+}
+
+/// What to do with a tool call paused by a [`StepGate`].
+#[derive(Debug, Clone, PartialEq)]
+pub enum GateDecision {
+    Continue,
+    /// Don't run the tool; the model is told it was skipped.
+    Skip,
+    /// Run the tool with these arguments instead.
+    Edit(serde_json::Value),
+    /// Stop the run.
+    Abort,
+}
+
+/// Decides on every tool call before it runs, e.g. by asking the user.
+/// Time spent deciding does not count against tool timeouts.
+#[async_trait]
+pub trait StepGate: Send + Sync {
+    async fn before_tool(&self, step: usize, thought: &str, tool: &str, arguments: &serde_json::Value) -> GateDecision;
+}
+
+/// Guidance from the user for a run in progress, shared with whoever reads
+/// it from them. Each message pushed is added to the conversation before
+/// the model's next turn; messages pushed after a run ends go to the next.
+#[derive(Debug, Clone, Default)]
+pub struct Steering {
+    pending: Arc<Mutex<Vec<String>>>,
+}
+
+impl Steering {
+    pub fn push(&self, guidance: impl Into<String>) {
+        self.pending.lock().unwrap_or_else(|e| e.into_inner()).push(guidance.into());
+    }
+
+    fn take(&self) -> Vec<String> {
+        std::mem::take(&mut *self.pending.lock().unwrap_or_else(|e| e.into_inner()))
+    }
+}
+
+/// Times one step and collects the usage reported for its request.
+struct StepClock {
+    started_at: u64,
+    start: Instant,
+    usage: Option<Usage>,
+}
+
+impl StepClock {
+    fn start() -> Self {
+        let now = SystemTime::now().duration_since(UNIX_EPOCH).unwrap_or_default();
+        Self {
+            started_at: now.as_millis() as u64,
+            start: Instant::now(),
+            usage: None,
+        }
+    }
+
+    fn stamp(&self, step: Step, status: StepStatus) -> Step {
+        Step {
+            started_at: self.started_at,
+            duration_ms: self.start.elapsed().as_millis() as u64,
+            prompt_tokens: self.usage.map(|usage| usage.input_tokens),
+            completion_tokens: self.usage.map(|usage| usage.output_tokens + usage.reasoning_tokens),
+            status,
+            ..step
+        }
+    }
+}
+
+/// The result of a tool call stopped by the user.
+fn interrupted_output(tool: &str) -> ToolOutput {
+    ToolOutput::failure(
+        "interrupted",
+        format!("Interrupted by the user: {} was stopped before it finished.", tool),
+        false,
+    )
+}
+
+/// What came back for one request to the model.
+#[derive(Debug, Default)]
+struct Turn {
+    text: String,
+    /// Whether any chunk arrived at all.
+    has_content: bool,
+    has_tool_call: bool,
+    usage: Option<Usage>,
+    /// The model that answered, which bills `usage`.
+    model: String,
+    /// The model didn't finish within the turn's time limit.
+    timed_out: bool,
+    /// The run was cancelled before the model finished.
+    interrupted: bool,
+}
+
+/// Why a run ended.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum StopReason {
+    /// The model gave its final answer.
+    Finished,
+    /// The run used all its steps before the model gave a final answer.
+    MaxSteps,
+    /// The run used its token budget before the model gave a final answer.
+    TokenBudget,
+    /// The run's [`CancellationToken`] was cancelled, e.g. by Ctrl+C.
+    Interrupted,
+}
+
+/// The steps of a run that ended without an error.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct AgentResult {
+    pub steps: Vec<Step>,
+    pub stop_reason: StopReason,
+    /// After [`StopReason::MaxSteps`], the model's account of what was done
+    /// and what remains, if it gave one. It is also the last step.
+    pub summary: Option<String>,
+    /// Tokens billed for the steps, as the provider reported them.
+    #[serde(default)]
+    pub usage: Usage,
+    /// What `usage` cost in dollars, each request at the price of the model
+    /// that answered it, given with [`ReactAgent::with_pricing`] or
+    /// [`ReactAgent::with_model_pricing`]. Unknown if one had no price.
+    #[serde(default)]
+    pub cost_usd: Option<f64>,
+}
+
+pub type StepCallback = Arc<dyn Fn(usize, Step) + Send + Sync>;
+
+/// How many identical tool results in a row prompt the model to change
+/// strategy, unless set with [`ReactAgent::with_max_repeated_observations`].
+pub const DEFAULT_MAX_REPEATED_OBSERVATIONS: usize = 3;
+
+/// How many empty responses in a row the model is asked again after,
+/// unless set with [`ReactAgent::with_max_empty_turns`].
+pub const DEFAULT_MAX_EMPTY_TURNS: usize = 2;
+
+/// How many tool calls in a row may have their arguments rejected before
+/// the run fails, unless set with [`ReactAgent::with_max_argument_repairs`].
+pub const DEFAULT_MAX_ARGUMENT_REPAIRS: usize = 2;
+
+/// How many steps a run may take, unless given to [`ReactAgent::new`] or
+/// set as `max_steps` in the config.
+pub const DEFAULT_MAX_STEPS: usize = 200;
+
+/// Receives the model's text while a step is still streaming.
+pub type DeltaCallback = Arc<dyn Fn(Delta) + Send + Sync>;
+
+/// What a run reports as it goes, from [`ReactAgent::run_stream`]. Step
+/// numbers are 1-based, as passed to the step callback.
+#[derive(Debug)]
+pub enum AgentEvent {
+    /// The model's text while its response is still streaming. Not sent
+    /// while a guardrail is set, as for the [`DeltaCallback`].
+    Delta(Delta),
+    /// Older messages were summarized before the model request for `step`
+    /// to fit the context budget, leaving about `tokens`.
+    Compressed {
+        step: usize,
+        messages_before: usize,
+        messages_after: usize,
+        tokens: usize,
+    },
+    /// The model called `tool`. It may still be skipped or refused, which
+    /// [`AgentEvent::ToolFinished`] tells.
+    ToolStarted {
+        step: usize,
+        tool: String,
+        arguments: serde_json::Value,
+    },
+    /// `tool` returned `observation`, the result the model is shown.
+    ToolFinished {
+        step: usize,
+        tool: String,
+        status: StepStatus,
+        observation: String,
+        duration_ms: u64,
+    },
+    /// A step is complete.
+    Step(usize, Step),
+    /// The run ended. Always the last event.
+    Finished(Result<AgentResult, AgentError>),
+}
+
+/// Why a run stopped early. Step numbers are 1-based, as passed to the
+/// step callback.
+#[derive(Debug, Error)]
+pub enum AgentError {
+    #[error("No tools provided")]
+    NoTools,
+    /// The model request for `step` failed.
+    #[error("LLM error at step {step}: {source}")]
+    LLMError {
+        step: usize,
+        #[source]
+        source: LLMError,
+    },
+    /// `tool` failed at `step`, or no tool of that name exists.
+    #[error("Tool error at step {step} ({tool}): {source}")]
+    ToolError {
+        step: usize,
+        tool: String,
+        #[source]
+        source: ToolError,
+    },
+    #[error("Channel closed")]
+    ChannelClosed,
+    #[error("Invalid response format: {0}")]
+    InvalidResponseFormat(String),
+    #[error("Blocked by guardrail: {0}")]
+    Blocked(String),
+    #[error("Stopped by hook: {0}")]
+    Hook(String),
+    #[error("Aborted before running {0}")]
+    Aborted(String),
+}
+
+impl AgentError {
+    /// Whether running the task again might get further: the model
+    /// request failed in a way that can pass, or the model's response
+    /// could not be understood.
+    pub fn is_retryable(&self) -> bool {
+        match self {
+            AgentError::LLMError { source, .. } => source.is_retryable(),
+            AgentError::InvalidResponseFormat(_) => true,
+            AgentError::NoTools
+            | AgentError::ToolError { .. }
+            | AgentError::ChannelClosed
+            | AgentError::Blocked(_)
+            | AgentError::Hook(_)
+            | AgentError::Aborted(_) => false,
+        }
+    }
+}
+
+pub struct ReactAgent {
+    client: Arc<dyn LLMClient>,
+    /// Writes summaries instead of `client`, usually a smaller model.
+    summary_client: Option<Arc<dyn LLMClient>>,
+    tools: ToolManager,
+    max_steps: usize,
+    step_callback: Option<StepCallback>,
+    delta_callback: Option<DeltaCallback>,
+    /// Set while [`ReactAgent::run_stream`] is being consumed.
+    events: Option<UnboundedSender<AgentEvent>>,
+    telemetry: Arc<dyn TelemetrySink>,
+    max_repeated_observations: Option<usize>,
+    max_empty_turns: usize,
+    max_argument_repairs: usize,
+    timeouts: Timeouts,
+    quotas: Quotas,
+    scopes: ToolScopes,
+    permissions: PermissionPolicy,
+    approver: Option<Arc<dyn Approver>>,
+    pricing: Option<Pricing>,
+    model_pricing: BTreeMap<String, Pricing>,
+    tool_selection: ToolSelection,
+    minify_schemas: Option<MinifySchemas>,
+    prefetch: Option<Prefetch>,
+    guardrail: Option<Arc<dyn Guardrail>>,
+    hooks: Hooks,
+    step_gate: Option<Arc<dyn StepGate>>,
+    steering: Steering,
+    cancel: CancellationToken,
+    repo_map_tokens: Option<usize>,
+    scratch_dir: Option<String>,
+    detect_project: bool,
+    checkpoints: bool,
+    read_only: bool,
+    enable_compression: bool,
+    compressor: ContextCompressor,
+    history: ConversationHistory,
+    step_count: Arc<AtomicUsize>,
+    working_dir: PathBuf,
+    redactor: Redactor,
+}
+
+pub fn final_answer(steps: &[Step]) -> Option<String> {
+    steps
+        .iter()
+        .rev()
+        .find_map(|step| protocol::parse_final(&step.thought))
+        .map(str::to_string)
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Confidence {
+    Low,
+    Medium,
+    High,
+}
+
+/// The agent's own account of how far its final answer can be trusted.
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct Assessment {
+    /// Whether the tests passed, or `None` if they were not run.
+    pub tests_passed: Option<bool>,
+    /// What the agent did not check.
+    pub unverified: Vec<String>,
+    pub risks: Vec<String>,
+    pub confidence: Option<Confidence>,
+}
+
+impl Assessment {
+    /// Whether a person should look at the result before it is used: the
+    /// tests did not pass, or the agent reports risks or low confidence.
+    pub fn needs_review(&self) -> bool {
+        self.tests_passed != Some(true) || !self.risks.is_empty() || self.confidence == Some(Confidence::Low)
+    }
+}
+
+/// The self-assessment given with the final answer, if the model gave one
+/// that parses.
+pub fn assessment(steps: &[Step]) -> Option<Assessment> {
+    let step = steps.iter().rev().find(|step| protocol::parse_final(&step.thought).is_some())?;
+    serde_json::from_str(protocol::parse_assessment(&step.thought)?).ok()
+}
+
+impl ReactAgent {
+    pub fn new(
+        client: Box<dyn LLMClient>,
+        tools: ToolManager,
+        working_dir: PathBuf,
+        max_steps: Option<usize>,
+        enable_compression: Option<bool>,
+        step_callback: Option<StepCallback>,
+    ) -> Self {
+        Self {
+            client: Arc::from(client),
+            summary_client: None,
+            tools,
+            max_steps: max_steps.unwrap_or(DEFAULT_MAX_STEPS),
+            step_callback,
+            delta_callback: None,
+            events: None,
+            telemetry: Arc::new(NoopSink),
+            max_repeated_observations: Some(DEFAULT_MAX_REPEATED_OBSERVATIONS),
+            max_empty_turns: DEFAULT_MAX_EMPTY_TURNS,
+            max_argument_repairs: DEFAULT_MAX_ARGUMENT_REPAIRS,
+            timeouts: Timeouts::default(),
+            quotas: Quotas::default(),
+            scopes: ToolScopes::default(),
+            permissions: PermissionPolicy::default(),
+            approver: None,
+            pricing: None,
+            model_pricing: BTreeMap::new(),
+            tool_selection: ToolSelection::default(),
+            minify_schemas: None,
+            prefetch: None,
+            guardrail: None,
+            hooks: Hooks::default(),
+            step_gate: None,
+            cancel: CancellationToken::new(),
+            steering: Steering::default(),
+            repo_map_tokens: None,
+            scratch_dir: None,
+            detect_project: false,
+            checkpoints: false,
+            read_only: false,
+            enable_compression: enable_compression.unwrap_or(true),
+            compressor: ContextCompressor::with_tokens(12000),
+            history: ConversationHistory::new(50),
+            step_count: Arc::new(AtomicUsize::new(0)),
+            working_dir,
+            redactor: Redactor::default(),
+        }
+    }
+
+    /// Talks to a different model from the next run on. The conversation
+    /// so far is kept.
+    pub fn set_client(&mut self, client: Box<dyn LLMClient>) {
+        self.client = Arc::from(client);
+    }
+
+    /// Writes summaries with `client` rather than the main model, or with
+    /// the main model again if `None`.
+    pub fn set_summary_client(&mut self, client: Option<Box<dyn LLMClient>>) {
+        self.summary_client = client.map(Arc::from);
+    }
+
+    pub fn with_summary_client(mut self, client: Option<Box<dyn LLMClient>>) -> Self {
+        self.set_summary_client(client);
+        self
+    }
+
+    /// Stops runs once `token` is cancelled: a model response or tool in
+    /// progress is abandoned, and the run ends with
+    /// [`StopReason::Interrupted`] and the steps taken so far. A cancelled
+    /// token stays cancelled, so give each run a new one.
+    pub fn set_cancellation(&mut self, token: CancellationToken) {
+        self.cancel = token;
+    }
+
+    pub fn with_cancellation(mut self, token: CancellationToken) -> Self {
+        self.set_cancellation(token);
+        self
+    }
+
+    pub fn set_step_callback(&mut self, step_callback: Option<StepCallback>) {
+        self.step_callback = step_callback;
+    }
+
+    pub fn set_delta_callback(&mut self, delta_callback: Option<DeltaCallback>) {
+        self.delta_callback = delta_callback;
+    }
+
+    pub fn with_redactor(mut self, redactor: Redactor) -> Self {
+        self.redactor = redactor;
+        self
+    }
+
+    /// Redacts `secret` from now on, e.g. a key resolved mid-session.
+    pub fn add_secret(&mut self, secret: impl Into<String>) {
+        self.redactor.add_literal(secret);
+    }
+
+    /// After this many identical tool results in a row, the agent tells the
+    /// model to try something else or ask the user. `None` never does.
+    pub fn with_max_repeated_observations(mut self, max: Option<usize>) -> Self {
+        self.max_repeated_observations = max;
+        self
+    }
+
+    /// After an empty or whitespace-only response, the model is asked again
+    /// up to `max` times in a row before the run fails.
+    pub fn with_max_empty_turns(mut self, max: usize) -> Self {
+        self.max_empty_turns = max;
+        self
+    }
+
+    /// When a tool call's arguments don't fit the tool's schema, or the
+    /// tool rejects them, the model is told what is wrong and may call again
+    /// up to `max` times in a row before the run fails.
+    pub fn with_max_argument_repairs(mut self, max: usize) -> Self {
+        self.max_argument_repairs = max;
+        self
+    }
+
+    pub fn with_timeouts(mut self, timeouts: Timeouts) -> Self {
+        self.timeouts = timeouts;
+        self
+    }
+
+    /// Answers calls to a tool past its quota with an observation saying
+    /// so, instead of running it.
+    pub fn with_quotas(mut self, quotas: Quotas) -> Self {
+        self.quotas = quotas;
+        self
+    }
+
+    /// Answers calls outside the directories `scopes` allows with an
+    /// observation naming the rule, instead of running them.
+    pub fn with_scopes(mut self, scopes: ToolScopes) -> Self {
+        self.scopes = scopes;
+        self
+    }
+
+    /// Allows, refuses or asks about each call by `permissions`. Calls it
+    /// asks about are refused without an approver.
+    pub fn with_permissions(mut self, permissions: PermissionPolicy) -> Self {
+        self.permissions = permissions;
+        self
+    }
+
+    pub fn with_approver(mut self, approver: Option<Arc<dyn Approver>>) -> Self {
+        self.approver = approver;
+        self
+    }
+
+    pub fn set_pricing(&mut self, pricing: Option<Pricing>) {
+        self.pricing = pricing;
+    }
+
+    /// Prices the model's tokens, so results carry what a run cost.
+    pub fn with_pricing(mut self, pricing: Option<Pricing>) -> Self {
+        self.pricing = pricing;
+        self
+    }
+
+    /// Prices other models by name, for the requests the summary model or
+    /// a fallback answers.
+    pub fn with_model_pricing(mut self, pricing: BTreeMap<String, Pricing>) -> Self {
+        self.model_pricing = pricing;
+        self
+    }
+
+    /// Adds `billed`, answered by `model`, to `usage` and `cost`. The cost
+    /// is unknown from then on if that model has no price.
+    fn bill(&self, usage: &mut Usage, cost: &mut Option<f64>, model: &str, billed: Usage) {
+        *usage += billed;
+        let pricing = if model == self.client.model_info().name {
+            self.pricing
+        } else {
+            self.model_pricing.get(model).copied()
+        };
+        *cost = cost.zip(pricing).map(|(cost, pricing)| cost + billed.cost(&pricing));
+    }
+
+    /// Sends only the tools most relevant to each task, with a
+    /// [`LIST_ALL_TOOLS`] tool to see and use the rest.
+    pub fn with_tool_selection(mut self, selection: ToolSelection) -> Self {
+        self.tool_selection = selection;
+        self
+    }
+
+    /// Shrinks tool definitions before they are sent, from the next run on.
+    pub fn set_minify_schemas(&mut self, minify: Option<MinifySchemas>) {
+        self.minify_schemas = minify;
+    }
+
+    pub fn with_minify_schemas(mut self, minify: Option<MinifySchemas>) -> Self {
+        self.set_minify_schemas(minify);
+        self
+    }
+
+    /// Looks up the files the task and tool results mention while the turn
+    /// goes on, and tells the model what was found before its next turn.
+    /// Off when `None`.
+    pub fn with_prefetch(mut self, prefetch: Option<Prefetch>) -> Self {
+        self.prefetch = prefetch;
+        self
+    }
+
+    /// Starts looking up the files `text` mentions that weren't already.
+    fn start_prefetch(
+        &self,
+        text: &str,
+        prefetched: &mut HashSet<String>,
+    ) -> Option<tokio::task::JoinHandle<Vec<FileFacts>>> {
+        let prefetch = self.prefetch.clone()?;
+        let paths: Vec<String> = mentioned_paths(text, &self.working_dir)
+            .into_iter()
+            .filter(|path| prefetched.insert(path.clone()))
+            .take(prefetch.max_files)
+            .collect();
+        if paths.is_empty() {
+            return None;
+        }
+        let working_dir = self.working_dir.clone();
+        Some(tokio::spawn(async move { prefetch.fetch(&working_dir, paths).await }))
+    }
+
+    /// Every tool's definition, minified if set.
+    fn definitions(&self) -> Vec<ToolDefinition> {
+        let definitions = self.tools.get_definitions();
+        match &self.minify_schemas {
+            Some(minify) => definitions.iter().map(|definition| minify.apply(definition)).collect(),
+            None => definitions,
+        }
+    }
+
+    /// Reports what each run does to `sink`.
+    pub fn with_telemetry(mut self, sink: Arc<dyn TelemetrySink>) -> Self {
+        self.telemetry = sink;
+        self
+    }
+
+    /// Checks every request and response against `guardrail`. Deltas are
+    /// not streamed while a guardrail is set, since they would reach the
+    /// caller before the response is checked.
+    pub fn with_guardrail(mut self, guardrail: Arc<dyn Guardrail>) -> Self {
+        self.guardrail = Some(guardrail);
+        self
+    }
+
+    /// Runs `hooks` at the start and end of every run and before every
+    /// file write.
+    pub fn with_hooks(mut self, hooks: Hooks) -> Self {
+        self.hooks = hooks;
+        self
+    }
+
+    /// Tells the model it must not change anything, and answers calls to
+    /// tools it was not given, or that are not annotated read-only, with a
+    /// dry-run observation instead of stopping.
+    pub fn with_read_only(mut self, read_only: bool) -> Self {
+        self.read_only = read_only;
+        self
+    }
+
+    /// Records on each step that runs a tool not annotated read-only the
+    /// diff of what it changed in the working tree. Needs a git
+    /// repository; elsewhere steps carry no diff.
+    pub fn with_checkpoints(mut self, checkpoints: bool) -> Self {
+        self.checkpoints = checkpoints;
+        self
+    }
+
+    /// Pauses before every tool call until `gate` decides on it. `None`
+    /// runs tools without asking.
+    pub fn with_step_gate(mut self, gate: Option<Arc<dyn StepGate>>) -> Self {
+        self.step_gate = gate;
+        self
+    }
+
+    /// Adds a map of the working directory, ranked for each task, to the
+    /// system prompt in about `max_tokens` tokens. `None` leaves it out.
+    pub fn with_repo_map(mut self, max_tokens: Option<usize>) -> Self {
+        self.repo_map_tokens = max_tokens;
+        self
+    }
+
+    /// Describes the projects in the working directory and how to build,
+    /// test and lint them in the system prompt.
+    pub fn with_project_detection(mut self, enabled: bool) -> Self {
+        self.detect_project = enabled;
+        self
+    }
+
+    /// Tells the model to keep temporary files in `dir`, relative to the
+    /// working directory.
+    pub fn with_scratch_dir(mut self, dir: Option<String>) -> Self {
+        self.scratch_dir = dir;
+        self
+    }
+
+    /// A handle for giving the agent guidance while it runs.
+    pub fn steering(&self) -> Steering {
+        self.steering.clone()
+    }
+
+    pub fn with_retention(mut self, retention: RetentionPolicy) -> Self {
+        self.history.set_retention(retention);
+        self
+    }
+
+    pub async fn run(
+        &mut self,
+        task: &str,
+    ) -> Result<AgentResult, AgentError> {
+        let all_definitions = self.definitions();
+        let tools_definitions = self.tool_selection.select(&all_definitions, task).unwrap_or(all_definitions);
+
+        let mut system_prompt = build_code_agent_prompt(&tools_definitions, None);
+        if let Some(max_tokens) = self.repo_map_tokens {
+            let map = repomap::repo_map(&self.working_dir, task, max_tokens).await;
+            system_prompt.push_str("\n\n");
+            system_prompt.push_str(&build_repo_map_section(&map));
+        }
+        if self.detect_project {
+            let projects = project::detect(&self.working_dir);
+            if !projects.is_empty() {
+                let tools = project::check_tools(&self.working_dir, &projects).await;
+                let description = format!("{}\n{}", project::describe(&projects), project::describe_tools(&tools));
+                system_prompt.push_str("\n\n");
+                system_prompt.push_str(&build_project_section(&description));
+            }
+        }
+        if let Some(dir) = &self.scratch_dir {
+            system_prompt.push_str("\n\n");
+            system_prompt.push_str(&build_scratch_section(dir));
+        }
+        if self.read_only {
+            system_prompt.push_str("\n\n");
+            system_prompt.push_str(&build_read_only_note());
+        }
+        self.history.ensure_system_prompt(system_prompt);
+
+        self.hooks
+            .run(
+                &HookEvent::RunStart {
+                    task,
+                    working_dir: &self.working_dir,
+                },
+                &self.working_dir,
+            )
+            .await
+            .map_err(|e| AgentError::Hook(e.to_string()))?;
+
+        let initial_message = Message {
+            role: MessageRole::User,
+            content: task.to_string(),
+            tool_calls: None,
+        };
+
+        // Earlier runs on this agent carry over, so follow-up tasks keep
+        // their context.
+        let mut messages = self.history.conversation();
+        messages.push(initial_message);
+
+        self.telemetry.record(&TelemetryEvent::RunStarted {
+            model: self.client.model_info().name,
+            max_steps: self.max_steps,
+            tools: tools_definitions.len(),
+        });
+        let start = Instant::now();
+        let outcome = self.run_turns(&mut messages, &tools_definitions).await;
+        self.telemetry.record(&TelemetryEvent::RunFinished {
+            steps: match &outcome {
+                Ok(result) => result.steps.len(),
+                Err(_) => self.step_count(),
+            },
+            stop_reason: outcome.as_ref().ok().map(|result| result.stop_reason),
+            error: outcome.as_ref().err().map(ToString::to_string),
+            duration_ms: start.elapsed().as_millis() as u64,
+        });
+
+        let end = HookEvent::RunEnd {
+            steps: match &outcome {
+                Ok(result) => result.steps.len(),
+                Err(_) => self.step_count(),
+            },
+            stop_reason: outcome.as_ref().ok().map(|result| result.stop_reason),
+            error: outcome.as_ref().err().map(ToString::to_string),
+        };
+        if let Err(e) = self.hooks.run(&end, &self.working_dir).await {
+            tracing::warn!("{}", e);
+        }
+
+        self.history.replace_messages(messages);
+        outcome
+    }
+
+    /// Runs `task` like [`Self::run`], reporting each delta, tool call and
+    /// step as it happens and the outcome last, in
+    /// [`AgentEvent::Finished`]. The run goes on only while the stream is
+    /// polled, and stops if it is dropped.
+    pub fn run_stream<'a>(&'a mut self, task: &'a str) -> impl Stream<Item = AgentEvent> + 'a {
+        use futures::{FutureExt, StreamExt};
+
+        let (sender, receiver) = mpsc::unbounded();
+        let run = async move {
+            self.events = Some(sender);
+            let outcome = self.run(task).await;
+            // Dropping the sender ends the stream after the last event.
+            if let Some(events) = self.events.take() {
+                let _ = events.unbounded_send(AgentEvent::Finished(outcome));
+            }
+        };
+        // The run sends its events down the channel rather than yielding
+        // them, so it is polled beside the receiver.
+        futures::stream::select(receiver, run.into_stream().filter_map(|()| std::future::ready(None)))
+    }
+
+    fn emit(&self, event: AgentEvent) {
+        if let Some(events) = &self.events {
+            // The receiver is only gone once the stream is dropped, which
+            // also drops the run.
+            let _ = events.unbounded_send(event);
+        }
+    }
+
+    fn emit_delta(&self, delta: Delta) {
+        if let Some(callback) = &self.delta_callback {
+            callback(delta.clone());
+        }
+        self.emit(AgentEvent::Delta(delta));
+    }
+
+    /// The failure to answer a call with instead of running it, if the
+    /// permission policy denies it or the user doesn't approve it.
+    async fn check_permission(&self, tool: &str, arguments: &serde_json::Value) -> Option<ToolOutput> {
+        let action = describe_call(tool, arguments);
+        match self.permissions.decide(tool, arguments, self.approver.is_some()) {
+            Permission::Allow => None,
+            Permission::Deny => Some(ToolOutput::failure(
+                "permission_denied",
+                format!("Denied by the permission policy: {} is not allowed.", action),
+                false,
+            )),
+            Permission::Ask => {
+                let Some(approver) = &self.approver else {
+                    return Some(ToolOutput::failure(
+                        "permission_denied",
+                        format!("Not run: {} needs the user's approval, and no one can be asked in this run.", action),
+                        false,
+                    ));
+                };
+                let request = ApprovalRequest {
+                    tool: tool.to_string(),
+                    arguments: arguments.clone(),
+                    action: action.clone(),
+                };
+                if approver.approve(&request).await {
+                    None
+                } else {
+                    Some(ToolOutput::failure(
+                        "declined",
+                        format!("The user declined {}; it was not run.", action),
+                        false,
+                    ))
+                }
+            }
+        }
+    }
+
+    /// Runs the `on_file_write` hooks for a write or edit that is about to
+    /// run, giving up at `deadline` or when the run is cancelled. The
+    /// failure to answer the call with instead, if it must not run.
+    async fn check_file_write(
+        &self,
+        tool: &str,
+        arguments: &serde_json::Value,
+        deadline: Option<tokio::time::Instant>,
+    ) -> Option<ToolOutput> {
+        if (tool != WRITE_FILE_TOOL && tool != EDIT_FILE_TOOL) || self.hooks.on_file_write.is_empty() {
+            return None;
+        }
+        let path = arguments.get("path").and_then(|path| path.as_str())?;
+        // An edit that can't apply fails in the tool instead.
+        let content = match tool {
+            EDIT_FILE_TOOL => edited_content(&self.working_dir, arguments).await,
+            _ => arguments.get("content").and_then(|content| content.as_str()).map(str::to_string),
+        }?;
+        let event = HookEvent::FileWrite { path, content: &content };
+        let hooks = within(deadline, self.hooks.run(&event, &self.working_dir));
+        // Dropping the hooks' future kills whatever they started.
+        let reason = match self.cancel.run_until_cancelled(hooks).await {
+            Some(Some(Ok(()))) => return None,
+            Some(Some(Err(e))) => e.to_string(),
+            Some(None) => "the on_file_write hooks did not finish in time".to_string(),
+            None => return Some(interrupted_output(tool)),
+        };
+        Some(ToolOutput::failure("vetoed", format!("{} was not written: {}", path, reason), false))
+    }
+
+    /// Adds a completed step to `steps` and reports it.
+    fn finish_step(&self, steps: &mut Vec<Step>, step: Step) {
+        self.telemetry.record(&TelemetryEvent::Step {
+            index: steps.len() + 1,
+            tool: step.action.clone(),
+            status: step.status,
+            duration_ms: step.duration_ms,
+        });
+        steps.push(step.clone());
+        if self.events.is_some() {
+            self.emit(AgentEvent::Step(steps.len(), step.clone()));
+        }
+        if let Some(callback) = &self.step_callback {
+            callback(steps.len(), step);
+        }
+    }
+
+    async fn run_turns(
+        &self,
+        messages: &mut Vec<Message>,
+        tools_definitions: &[ToolDefinition],
+    ) -> Result<AgentResult, AgentError> {
+        let client = Arc::clone(&self.client);
+        let mut current_step = 0;
+        let mut steps = Vec::new();
+        let mut last_observation: Option<String> = None;
+        let mut repeats = 0;
+        let mut empty_turns = 0;
+        let mut repairs = 0;
+        let mut stop_reason = StopReason::Finished;
+        let started = Instant::now();
+        let mut usage = Usage::default();
+        let mut cost = (self.pricing.is_some() || !self.model_pricing.is_empty()).then_some(0.0);
+        // Every tool once the model has asked for the full list.
+        let mut tools_definitions = Cow::Borrowed(tools_definitions);
+        let mut calls: HashMap<String, usize> = HashMap::new();
+        let mut checkpoints = None;
+        if self.checkpoints {
+            match Checkpoints::start(&self.working_dir).await {
+                Ok(started) => checkpoints = Some(started),
+                Err(e) => tracing::debug!("No checkpoints for this run: {}", e),
+            }
+        }
+        // Unchecked text isn't streamed past a guardrail.
+        let stream_deltas = self.guardrail.is_none() && (self.delta_callback.is_some() || self.events.is_some());
+        let mut prefetched = HashSet::new();
+        let mut prefetching = messages
+            .iter()
+            .rev()
+            .find(|message| message.role == MessageRole::User)
+            .and_then(|task| self.start_prefetch(&task.content, &mut prefetched));
+
+        loop {
+            if self.cancel.is_cancelled() {
+                stop_reason = StopReason::Interrupted;
+                break;
+            }
+            if self.quotas.tokens.is_some_and(|budget| usage.total() >= budget) {
+                stop_reason = StopReason::TokenBudget;
+                break;
+            }
+            current_step += 1;
+            self.step_count.store(current_step, Ordering::Relaxed);
+
+            for guidance in self.steering.take() {
+                messages.push(Message {
+                    role: MessageRole::User,
+                    content: build_steering_prompt(&guidance),
+                    tool_calls: None,
+                });
+            }
+
+            if let Some(prefetch) = prefetching.take()
+                && let Ok(facts) = prefetch.await
+                && !facts.is_empty()
+            {
+                messages.push(Message {
+                    role: MessageRole::User,
+                    content: render_facts(&facts),
+                    tool_calls: None,
+                });
+            }
+
+            let step_prompt = Message {
+                role: MessageRole::User,
+                content: build_step_prompt(
+                    current_step,
+                    self.max_steps,
+                    self.quotas.tokens.map(|budget| (usage.total(), budget)),
+                    started.elapsed(),
+                ),
+                tool_calls: None,
+            };
+            let mut clock = StepClock::start();
+            let mut splitter = DeltaSplitter::default();
+            let turn = self
+                .request(
+                    client.as_ref(),
+                    messages,
+                    Some(step_prompt),
+                    &tools_definitions,
+                    steps.len() + 1,
+                    stream_deltas.then_some(&mut splitter),
+                )
+                .await?;
+            clock.usage = turn.usage;
+            let Turn {
+                text: mut raw_response,
+                has_content,
+                has_tool_call,
+                model,
+                timed_out,
+                interrupted,
+                ..
+            } = turn;
+            if let Some(step_usage) = clock.usage {
+                self.bill(&mut usage, &mut cost, &model, step_usage);
+            }
+            if interrupted {
+                // The partial response is dropped with the request.
+                stop_reason = StopReason::Interrupted;
+                break;
+            }
+
+            if timed_out {
+                // The partial response is dropped; the model is asked again.
+                let observation = format!(
+                    "Timeout: the model did not finish its response within {}s. Answer again, more briefly.",
+                    self.timeouts.llm_turn().unwrap_or_default().as_secs()
+                );
+                tracing::warn!("{}", observation);
+                messages.push(Message {
+                    role: MessageRole::User,
+                    content: observation.clone(),
+                    tool_calls: None,
+                });
+
+                let step = clock.stamp(
+                    Step::new(String::new(), String::new(), serde_json::json!({}), observation, raw_response),
+                    StepStatus::Timeout,
+                );
+                self.finish_step(&mut steps, step);
+
+                if current_step >= self.max_steps {
+                    stop_reason = StopReason::MaxSteps;
+                    break;
+                }
+                continue;
+            }
+
+            if !has_tool_call && raw_response.trim().is_empty() {
+                empty_turns += 1;
+                let received = if has_content { "an empty response" } else { "no content" };
+                if empty_turns > self.max_empty_turns {
+                    return Err(AgentError::LLMError {
+                        step: steps.len() + 1,
+                        source: LLMError::ApiError(format!(
+                            "Received {} {} time(s) in a row",
+                            received, empty_turns
+                        )),
+                    });
+                }
+                tracing::warn!("Received {} from the model, asking again", received);
+                messages.push(Message {
+                    role: MessageRole::User,
+                    content: build_empty_turn_prompt(),
+                    tool_calls: None,
+                });
+
+                let observation = format!("The model sent {}; it was asked again.", received);
+                let step = clock.stamp(
+                    Step::new(String::new(), String::new(), serde_json::json!({}), observation, raw_response),
+                    StepStatus::Empty,
+                );
+                self.finish_step(&mut steps, step);
+
+                if current_step >= self.max_steps {
+                    stop_reason = StopReason::MaxSteps;
+                    break;
+                }
+                continue;
+            }
+            empty_turns = 0;
+
+            if stream_deltas {
+                for delta in splitter.finish() {
+                    self.emit_delta(delta);
+                }
+            }
+
+            let mut note = None;
+            if let Some(guardrail) = &self.guardrail {
+                match guardrail.check_inbound(&raw_response) {
+                    Verdict::Allow => {}
+                    Verdict::Redact(content) => raw_response = content,
+                    Verdict::Annotate(text) => note = Some(text),
+                    Verdict::Block(reason) => return Err(AgentError::Blocked(reason)),
+                }
+            }
+
+            let mut finished = false;
+
+            match protocol::parse_response(&raw_response) {
+                Response::ToolCall { thought, mut call } => {
+                    let mut skipped = false;
+                    if let Some(gate) = &self.step_gate {
+                        match gate.before_tool(current_step, &thought, &call.name, &call.arguments).await {
+                            GateDecision::Continue => {}
+                            GateDecision::Skip => skipped = true,
+                            GateDecision::Edit(arguments) => {
+                                call.raw_arguments = arguments.to_string();
+                                call.arguments = arguments;
+                            }
+                            GateDecision::Abort => return Err(AgentError::Aborted(call.name)),
+                        }
+                    }
+
+                    let assistant_message = Message {
+                        role: MessageRole::Assistant,
+                        content: protocol::format_tool_call(&call.name, &call.raw_arguments),
+                        tool_calls: Some(vec![crate::clients::ToolCall {
+                            id: format!("call_{}", current_step),
+                            function: crate::clients::ToolFunction {
+                                name: call.name.clone(),
+                                arguments: call.raw_arguments.clone(),
+                            },
+                        }]),
+                    };
+                    messages.push(assistant_message);
+
+                    let limit = self.timeouts.tool(&call.name);
+                    let quota = self.quotas.tool(&call.name);
+                    let used = calls.entry(call.name.clone()).or_default();
+                    let over_quota = quota.is_some_and(|quota| *used >= quota);
+                    let invalid = if skipped { Vec::new() } else { self.tools.validate(&call.name, &call.arguments) };
+                    let out_of_scope = if skipped || !invalid.is_empty() {
+                        None
+                    } else {
+                        self.scopes.check(&call.name, &call.arguments, &self.working_dir)
+                    };
+                    let mut refused = if skipped || over_quota || !invalid.is_empty() || out_of_scope.is_some() {
+                        None
+                    } else {
+                        self.check_permission(&call.name, &call.arguments).await
+                    };
+                    if !skipped && !over_quota && invalid.is_empty() && out_of_scope.is_none() && refused.is_none() {
+                        *used += 1;
+                    }
+                    let deadline = limit.map(|limit| tokio::time::Instant::now() + limit);
+                    self.emit(AgentEvent::ToolStarted {
+                        step: steps.len() + 1,
+                        tool: call.name.clone(),
+                        arguments: call.arguments.clone(),
+                    });
+                    let tool_start = Instant::now();
+                    let result = match self.tools.get(&call.name) {
+                        _ if skipped => Some(Ok(ToolOutput::failure(
+                            "skipped",
+                            format!("The user skipped this call: {} was not run.", call.name),
+                            false,
+                        ))),
+                        _ if !invalid.is_empty() => Some(Err(ToolError::InvalidArguments(invalid.join("; ")))),
+                        _ if out_of_scope.is_some() => Some(Ok(ToolOutput::failure(
+                            "out_of_scope",
+                            format!("Out of scope: {}", out_of_scope.clone().unwrap_or_default()),
+                            false,
+                        ))),
+                        _ if refused.is_some() => refused.take().map(Ok),
+                        _ if over_quota => Some(Ok(ToolOutput::failure(
+                            "quota_exceeded",
+                            format!(
+                                "Quota exceeded: {} may be called at most {} times per run and was not run. \
+                                 Work with the results you have.",
+                                call.name,
+                                quota.unwrap_or_default()
+                            ),
+                            false,
+                        ))),
+                        None if call.name == LIST_ALL_TOOLS => {
+                            let all = self.definitions();
+                            let output = list_all_tools_result(&all);
+                            tools_definitions = Cow::Owned(all);
+                            Some(Ok(output))
+                        }
+                        Some(tool) if !self.read_only || tool.info().annotations.read_only => {
+                            match self.check_file_write(&call.name, &call.arguments, deadline).await {
+                                Some(vetoed) => Some(Ok(vetoed)),
+                                None => {
+                                    let execution = within(deadline, self.tools.execute(&call.name, call.arguments.clone()));
+                                    match self.cancel.run_until_cancelled(execution).await {
+                                        Some(result) => result.map(|result| {
+                                            result.map_err(|e| e.map_message(|message| self.redactor.redact(message).into_owned()))
+                                        }),
+                                        // Dropping the tool's future stops it, and any command it started.
+                                        None => Some(Ok(interrupted_output(&call.name))),
+                                    }
+                                }
+                            }
+                        }
+                        _ if self.read_only => {
+                            let mut available = self.tools.read_only();
+                            available.sort();
+                            Some(Ok(ToolOutput::failure(
+                                "read_only",
+                                format!(
+                                    "Read-only mode: {} was not run. Only these tools are available: {}.",
+                                    call.name,
+                                    available.join(", ")
+                                ),
+                                false,
+                            )))
+                        }
+                        _ => Some(Err(ToolError::UnknownTool(call.name.clone()))),
+                    };
+
+                    let mut rendered = None;
+                    let mut error = None;
+                    let (output, status) = match result {
+                        Some(Err(ToolError::InvalidArguments(message))) if repairs < self.max_argument_repairs => {
+                            repairs += 1;
+                            let message = self.redactor.redact(&message).into_owned();
+                            let errors: Vec<String> = if invalid.is_empty() {
+                                vec![message.clone()]
+                            } else {
+                                invalid.iter().map(|error| self.redactor.redact(error).into_owned()).collect()
+                            };
+                            let mut output = ToolOutput::failure(
+                                "invalid_arguments",
+                                format!(
+                                    "The arguments for {} were rejected: {}. Fix them and call it again.",
+                                    call.name, message
+                                ),
+                                true,
+                            );
+                            output.data = serde_json::json!({ "errors": errors });
+                            (output, StepStatus::InvalidArguments)
+                        }
+                        Some(Ok(mut output)) => {
+                            repairs = 0;
+                            output.data = self.redactor.redact_value(&output.data);
+                            if let Some(failure) = output.error.as_mut() {
+                                failure.message = self.redactor.redact(&failure.message).into_owned();
+                            }
+                            rendered = self.tools.get(&call.name).and_then(|tool| tool.render(&output));
+                            (output, StepStatus::Success)
+                        }
+                        Some(Err(e)) => {
+                            let output = ToolOutput::from_error(&e, tool_start.elapsed());
+                            error = Some(e);
+                            (output, StepStatus::ToolError)
+                        }
+                        None => {
+                            let message = format!(
+                                "Timeout: tool '{}' did not finish within {}s and was cancelled.",
+                                call.name,
+                                limit.unwrap_or_default().as_secs()
+                            );
+                            tracing::warn!("{}", message);
+                            let mut output = ToolOutput::failure("timeout", message, true);
+                            output.meta.duration_ms = tool_start.elapsed().as_millis() as u64;
+                            (output, StepStatus::Timeout)
+                        }
+                    };
+                    let observation = serde_json::to_string(&output).unwrap_or_default();
+
+                    self.telemetry.record(&TelemetryEvent::ToolCall {
+                        name: call.name.clone(),
+                        status,
+                        duration_ms: tool_start.elapsed().as_millis() as u64,
+                    });
+                    self.emit(AgentEvent::ToolFinished {
+                        step: steps.len() + 1,
+                        tool: call.name.clone(),
+                        status,
+                        observation: observation.clone(),
+                        duration_ms: tool_start.elapsed().as_millis() as u64,
+                    });
+
+                    if matches!(status, StepStatus::Timeout | StepStatus::InvalidArguments) {
+                        messages.push(Message {
+                            role: MessageRole::Tool,
+                            content: observation.clone(),
+                            tool_calls: None,
+                        });
+                    }
+
+                    if status == StepStatus::Success {
+                        let content = rendered.unwrap_or_else(|| observation.clone());
+                        prefetching = self.start_prefetch(&content, &mut prefetched);
+                        messages.push(Message {
+                            role: MessageRole::Tool,
+                            content,
+                            tool_calls: None,
+                        });
+
+                        // Timings differ between otherwise identical results.
+                        let fingerprint = output.fingerprint();
+                        repeats = if last_observation.as_ref() == Some(&fingerprint) { repeats + 1 } else { 1 };
+                        last_observation = Some(fingerprint);
+                        if self.max_repeated_observations.is_some_and(|max| repeats >= max) {
+                            tracing::warn!("The last {} tool results were identical, asking for a new strategy", repeats);
+                            messages.push(Message {
+                                role: MessageRole::User,
+                                content: build_repeated_observation_prompt(repeats),
+                                tool_calls: None,
+                            });
+                            repeats = 0;
+                        }
+                    }
+
+                    let mutating = self.tools.get(&call.name).is_some_and(|tool| !tool.info().annotations.read_only);
+                    let mut diff = None;
+                    if let Some(checkpoints) = checkpoints.as_mut()
+                        && mutating
+                        && status != StepStatus::ToolError
+                    {
+                        match checkpoints.diff().await {
+                            Ok(changes) if changes.is_empty() => {}
+                            Ok(changes) => diff = Some(changes),
+                            Err(e) => tracing::debug!("Could not take a checkpoint: {}", e),
+                        }
+                    }
+
+                    let mut step = clock.stamp(
+                        Step::new(thought, call.name, call.arguments, observation, raw_response),
+                        status,
+                    );
+                    step.diff = diff;
+
+                    let tool = step.action.clone();
+                    self.finish_step(&mut steps, step);
+
+                    if let Some(source) = error {
+                        return Err(AgentError::ToolError {
+                            step: steps.len(),
+                            tool,
+                            source,
+                        });
+                    }
+                }
+                Response::Thought(thought) if thought.is_empty() => {}
+                Response::Final { thought, .. } | Response::Thought(thought) => {
+                    let is_final = protocol::parse_final(&thought).is_some();
+
+                    messages.push(Message {
+                        role: MessageRole::Assistant,
+                        content: thought.clone(),
+                        tool_calls: None,
+                    });
+
+                    let status = if is_final {
+                        StepStatus::Success
+                    } else {
+                        StepStatus::Parsing
+                    };
+                    let step = clock.stamp(
+                        Step::new(thought, String::new(), serde_json::json!({}), String::new(), raw_response),
+                        status,
+                    );
+
+                    self.finish_step(&mut steps, step);
+
+                    finished = is_final && !has_tool_call;
+                }
+            }
+
+            if let Some(note) = note {
+                messages.push(Message {
+                    role: MessageRole::User,
+                    content: format!("[Note: {}]", note),
+                    tool_calls: None,
+                });
+            }
+            if finished {
+                break;
+            }
+
+            if current_step >= self.max_steps {
+                stop_reason = StopReason::MaxSteps;
+                break;
+            }
+        }
+
+        let mut summary = None;
+        if stop_reason == StopReason::MaxSteps {
+            let mut clock = StepClock::start();
+            match self.max_steps_summary(messages, steps.len() + 1).await {
+                Ok(Turn { text, usage: billed, model, .. }) => {
+                    clock.usage = billed;
+                    if let Some(billed) = billed {
+                        self.bill(&mut usage, &mut cost, &model, billed);
+                    }
+                    let step = clock.stamp(
+                        Step::new(text.clone(), String::new(), serde_json::json!({}), String::new(), text.clone()),
+                        StepStatus::Success,
+                    );
+                    self.finish_step(&mut steps, step);
+                    summary = Some(text);
+                }
+                Err(e) => tracing::warn!("Could not get a summary after running out of steps: {}", e),
+            }
+        }
+
+        Ok(AgentResult {
+            steps,
+            stop_reason,
+            summary,
+            cost_usd: cost,
+            usage,
+        })
+    }
+
+    /// Sends `messages` to `client` with `step_prompt` after them, which is
+    /// sent with this request only, so the history doesn't fill up with
+    /// step counters.
+    async fn request(
+        &self,
+        client: &dyn LLMClient,
+        messages: &mut Vec<Message>,
+        step_prompt: Option<Message>,
+        tools: &[ToolDefinition],
+        step: usize,
+        splitter: Option<&mut DeltaSplitter>,
+    ) -> Result<Turn, AgentError> {
+        // Added and taken off again rather than copying the history to add it.
+        let added = step_prompt.is_some();
+        messages.extend(step_prompt);
+        let turn = self.send(client, messages, tools, step, splitter).await;
+        if added {
+            messages.pop();
+        }
+        turn
+    }
+
+    /// Sends `messages` to `client` and collects the response. The request
+    /// goes through the retention policy, compression and the outbound
+    /// guardrail, and gives up at the turn's time limit or when the run is
+    /// cancelled. Content is also fed to `splitter` as it arrives.
+    async fn send(
+        &self,
+        client: &dyn LLMClient,
+        messages: &[Message],
+        tools: &[ToolDefinition],
+        step: usize,
+        mut splitter: Option<&mut DeltaSplitter>,
+    ) -> Result<Turn, AgentError> {
+        let retained = self.history.retention().apply(messages);
+        let request_messages = if self.enable_compression {
+            let (compressed, _, metadata) = self.compressor.compress(&retained, &[]);
+            if metadata.compressed {
+                self.emit(AgentEvent::Compressed {
+                    step,
+                    messages_before: retained.len(),
+                    messages_after: compressed.len(),
+                    tokens: metadata.total_tokens,
+                });
+            }
+            compressed
+        } else {
+            Cow::Borrowed(&*retained)
+        };
+        let request_messages = match &self.guardrail {
+            Some(guardrail) => {
+                guardrail::check_outbound(guardrail.as_ref(), &request_messages).map_err(AgentError::Blocked)?
+            }
+            None => Cow::Borrowed(&*request_messages),
+        };
+
+        use futures::stream::StreamExt;
+
+        let llm_error = |source| AgentError::LLMError { step, source };
+        let deadline = self.timeouts.llm_turn().map(|limit| tokio::time::Instant::now() + limit);
+        let mut turn = Turn {
+            model: client.model_info().name,
+            ..Turn::default()
+        };
+        let stream = within(deadline, client.stream_complete(&request_messages, tools));
+        let mut stream = match self.cancel.run_until_cancelled(stream).await {
+            Some(Some(stream)) => stream.map_err(llm_error)?,
+            Some(None) => {
+                turn.timed_out = true;
+                return Ok(turn);
+            }
+            None => {
+                turn.interrupted = true;
+                return Ok(turn);
+            }
+        };
+        loop {
+            let Some(next) = self.cancel.run_until_cancelled(within(deadline, stream.next())).await else {
+                turn.interrupted = true;
+                break;
+            };
+            let Some(next) = next else {
+                turn.timed_out = true;
+                break;
+            };
+            let Some(chunk) = next else {
+                break;
+            };
+            let chunk = chunk.map_err(llm_error)?;
+            turn.has_content = true;
+            match chunk.chunk_type {
+                ChunkType::Content => {
+                    turn.text.push_str(&chunk.content);
+                    if let Some(splitter) = splitter.as_deref_mut() {
+                        for delta in splitter.push(&chunk.content) {
+                            self.emit_delta(delta);
+                        }
+                    }
+                }
+                ChunkType::Reasoning => {
+                    // Kept out of the history: providers reject reasoning
+                    // echoed back to them.
+                    tracing::debug!("Reasoning: {}", chunk.content);
+                }
+                ChunkType::ToolCall | ChunkType::ToolArgs => turn.has_tool_call = true,
+                ChunkType::Usage => {
+                    tracing::debug!("Usage: {}", chunk.content);
+                    turn.usage = serde_json::from_str(&chunk.content).ok();
+                    // Named when another model than the client's answered.
+                    if let Ok(serde_json::Value::Object(billed)) = serde_json::from_str(&chunk.content)
+                        && let Some(model) = billed.get("model").and_then(|model| model.as_str())
+                    {
+                        turn.model = model.to_string();
+                    }
+                    if let Some(usage) = turn.usage {
+                        self.telemetry.record(&TelemetryEvent::Usage { usage });
+                    }
+                }
+                ChunkType::Done => break,
+                ChunkType::Error => return Err(llm_error(LLMError::ApiError(chunk.content))),
+            }
+        }
+        Ok(turn)
+    }
+
+    /// Asks the summary model, without tools, what it did and what remains
+    /// after the run used all its steps. The exchange is kept in
+    /// `messages`. Returns the turn with its text checked and trimmed.
+    async fn max_steps_summary(&self, messages: &mut Vec<Message>, step: usize) -> Result<Turn, AgentError> {
+        messages.push(Message {
+            role: MessageRole::User,
+            content: build_max_steps_prompt(self.max_steps),
+            tool_calls: None,
+        });
+
+        let llm_error = |source| AgentError::LLMError { step, source };
+        let client = self.summary_client.as_ref().unwrap_or(&self.client);
+        let mut turn = self.send(client.as_ref(), messages, &[], step, None).await?;
+        if turn.timed_out || turn.interrupted {
+            let reason = if turn.timed_out { "Timed out" } else { "Interrupted" };
+            return Err(llm_error(LLMError::RequestFailed(reason.to_string())));
+        }
+        let mut text = std::mem::take(&mut turn.text);
+
+        if let Some(guardrail) = &self.guardrail {
+            match guardrail.check_inbound(&text) {
+                Verdict::Allow | Verdict::Annotate(_) => {}
+                Verdict::Redact(content) => text = content,
+                Verdict::Block(reason) => return Err(AgentError::Blocked(reason)),
+            }
+        }
+        let text = text.trim().to_string();
+        if text.is_empty() {
+            return Err(llm_error(LLMError::ApiError("No content received".to_string())));
+        }
+        messages.push(Message {
+            role: MessageRole::Assistant,
+            content: text.clone(),
+            tool_calls: None,
+        });
+        turn.text = text;
+        Ok(turn)
+    }
+
+    /// Proposes folding the conversation carried between runs into a
+    /// summary. Nothing changes until [`Self::apply_compaction`].
+    pub fn preview_compaction(&self) -> Option<Compaction> {
+        let messages: Vec<Message> = self.history.get_messages().iter().map(|m| Message::clone(m)).collect();
+        self.compressor.compact(&messages)
+    }
+
+    /// Replaces the carried conversation with `compaction`, whose summary
+    /// may have been edited since it was previewed.
+    pub fn apply_compaction(&mut self, compaction: &Compaction) {
+        self.history.replace_messages(compaction.messages());
+    }
+
+    pub fn working_dir(&self) -> &Path {
+        &self.working_dir
+    }
+
+    pub fn step_count(&self) -> usize {
+        self.step_count.load(Ordering::Relaxed)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::clients::{OpenAIClient, ScriptedClient, StreamChunk};
+    use crate::tools::default_tools;
+    use std::path::PathBuf;
+
+    /// The requests `client` got, without the step prompt that ends each.
+    fn conversations(client: &ScriptedClient) -> Vec<Vec<Message>> {
+        client
+            .requests()
+            .into_iter()
+            .map(|mut request| {
+                let step = request.pop().unwrap();
+                assert!(step.content.starts_with("Step "), "{}", step.content);
+                request
+            })
+            .collect()
+    }
+
+    #[test]
+    fn test_step_new() {
+        let step = Step::new(
+            "Thinking".to_string(),
+            "read_file".to_string(),
+            serde_json::json!({"path": "test.txt"}),
+            "File content".to_string(),
+            "raw response".to_string(),
+        );
+
+        assert_eq!(step.thought, "Thinking");
+        assert_eq!(step.action, "read_file");
+    }
+
+    #[test]
+    fn test_assessment() {
+        let step = |thought: &str| Step::new(thought.to_string(), String::new(), serde_json::json!({}), String::new(), String::new());
+        let steps = [
+            step("FINAL: Fixed the parser.\nASSESSMENT: {\"tests_passed\": true, \"unverified\": [\"Windows paths\"], \"confidence\": \"high\"}"),
+        ];
+
+        let reported = assessment(&steps).unwrap();
+        assert_eq!(final_answer(&steps).as_deref(), Some("Fixed the parser."));
+        assert_eq!(reported.unverified, ["Windows paths"]);
+        assert!(!reported.needs_review());
+        assert!(Assessment::default().needs_review());
+        assert_eq!(assessment(&[step("FINAL: Done.\nASSESSMENT: not json")]), None);
+    }
+
+    #[test]
+    fn test_react_agent_new() {
+        let client = Box::new(OpenAIClient::new("test_key".to_string(), "gpt-4".to_string(), None));
+        let tools = ToolManager::new();
+        let working_dir = PathBuf::from("/tmp");
+
+        let agent = ReactAgent::new(
+            client,
+            tools,
+            working_dir,
+            Some(50),
+            Some(true),
+            None,
+        );
+
+        assert_eq!(agent.max_steps, 50);
+    }
+
+    #[tokio::test]
+    async fn test_run_tool_call_then_final() {
+        let dir = tempfile::tempdir().unwrap();
+        std::fs::write(dir.path().join("notes.txt"), "hello").unwrap();
+
+        let client = Arc::new(ScriptedClient::from_responses([
+            format!(
+                "I should read the file.\n{}",
+                ScriptedClient::tool_call("read_file", serde_json::json!({"path": "notes.txt"}))
+            ),
+            "FINAL: The file says hello.".to_string(),
+        ]));
+
+        let mut agent = ReactAgent::new(
+            Box::new(Arc::clone(&client)),
+            default_tools(dir.path().to_path_buf()),
+            dir.path().to_path_buf(),
+            Some(5),
+            Some(false),
+            None,
+        );
+
+        let steps = agent.run("What does notes.txt say?").await.unwrap().steps;
+
+        assert_eq!(steps.len(), 2);
+        assert_eq!(steps[0].action, "read_file");
+        assert!(steps[0].observation.contains("hello"));
+        assert_eq!(final_answer(&steps).as_deref(), Some("The file says hello."));
+
+        let requests = conversations(&client);
+        assert_eq!(requests.len(), 2);
+        assert_eq!(requests[1].last().unwrap().role, MessageRole::Tool);
+        // Each request ends with the step counter, which isn't kept.
+        assert!(client.requests()[1].last().unwrap().content.starts_with("Step 2/5 (3 left after this one"));
+        assert!(!requests[1].iter().any(|m| m.content.starts_with("Step 1/5")));
+    }
+
+    #[tokio::test]
+    async fn test_reasoning_is_not_sent_back() {
+        let dir = tempfile::tempdir().unwrap();
+        std::fs::write(dir.path().join("notes.txt"), "hello").unwrap();
+
+        let client = Arc::new(ScriptedClient::new(vec![
+            vec![
+                StreamChunk {
+                    content: "I should look at the notes first.".to_string(),
+                    chunk_type: ChunkType::Reasoning,
+                    delta: true,
+                },
+                StreamChunk::content(ScriptedClient::tool_call(
+                    "read_file",
+                    serde_json::json!({"path": "notes.txt"}),
+                )),
+                StreamChunk::done(),
+            ],
+            vec![StreamChunk::content("FINAL: hello"), StreamChunk::done()],
+        ]));
+
+        let mut agent = ReactAgent::new(
+            Box::new(Arc::clone(&client)),
+            default_tools(dir.path().to_path_buf()),
+            dir.path().to_path_buf(),
+            Some(5),
+            Some(false),
+            None,
+        );
+        let steps = agent.run("What do the notes say?").await.unwrap().steps;
+
+        assert_eq!(steps.len(), 2);
+        assert!(!steps[0].raw.contains("look at the notes"));
+        assert!(conversations(&client)[1]
+            .iter()
+            .all(|message| !message.content.contains("look at the notes")));
+    }
+
+    #[tokio::test]
+    async fn test_repeated_observations_escalate() {
+        let dir = tempfile::tempdir().unwrap();
+        std::fs::write(dir.path().join("build.log"), "error[E0308]: mismatched types").unwrap();
+        let read = ScriptedClient::tool_call("read_file", serde_json::json!({"path": "build.log"}));
+        let client = Arc::new(ScriptedClient::from_responses([
+            read.clone(),
+            read.clone(),
+            read,
+            "FINAL: I am stuck on a type error.".to_string(),
+        ]));
+
+        let mut agent = ReactAgent::new(
+            Box::new(Arc::clone(&client)),
+            default_tools(dir.path().to_path_buf()),
+            dir.path().to_path_buf(),
+            Some(10),
+            Some(false),
+            None,
+        );
+        agent.run("Fix the build").await.unwrap();
+
+        let requests = conversations(&client);
+        assert_eq!(requests[2].last().unwrap().role, MessageRole::Tool);
+        let escalation = requests[3].last().unwrap();
+        assert_eq!(escalation.role, MessageRole::User);
+        assert!(escalation.content.contains("3 tool results were identical"));
+    }
+
+    #[tokio::test]
+    async fn test_tool_timeout_is_observed() {
+        let dir = tempfile::tempdir().unwrap();
+        let client = Arc::new(ScriptedClient::from_responses([
+            ScriptedClient::tool_call("run_command", serde_json::json!({"command": "sleep 30"})),
+            "FINAL: The command hangs.".to_string(),
+        ]));
+        let timeouts = Timeouts {
+            tool_seconds: Some(60),
+            tools: HashMap::from([("run_command".to_string(), 1)]),
+            llm_turn_seconds: None,
+            stall_seconds: None,
+        };
+        assert_eq!(timeouts.tool("read_file"), Some(Duration::from_secs(60)));
+
+        let mut agent = ReactAgent::new(
+            Box::new(Arc::clone(&client)),
+            default_tools(dir.path().to_path_buf()),
+            dir.path().to_path_buf(),
+            Some(5),
+            Some(false),
+            None,
+        )
+        .with_timeouts(timeouts);
+        let steps = agent.run("Run the slow command").await.unwrap().steps;
+
+        assert_eq!(steps[0].status, StepStatus::Timeout);
+        let output: ToolOutput = serde_json::from_str(&steps[0].observation).unwrap();
+        let failure = output.error.unwrap();
+        assert_eq!(failure.code, "timeout");
+        assert_eq!(failure.message, "Timeout: tool 'run_command' did not finish within 1s and was cancelled.");
+        assert!(failure.retryable);
+        let observed = conversations(&client)[1].last().unwrap().clone();
+        assert_eq!(observed.role, MessageRole::Tool);
+        assert!(observed.content.contains("\"code\":\"timeout\""));
+    }
+
+    #[tokio::test]
+    async fn test_checkpoint_diffs_on_steps() {
+        let dir = tempfile::tempdir().unwrap();
+        std::process::Command::new("git").args(["init", "-q"]).current_dir(dir.path()).output().unwrap();
+        std::fs::write(dir.path().join("a.txt"), "old\n").unwrap();
+        let client = Arc::new(ScriptedClient::from_responses([
+            ScriptedClient::tool_call("write_file", serde_json::json!({"path": "a.txt", "content": "new\n"})),
+            ScriptedClient::tool_call("read_file", serde_json::json!({"path": "a.txt"})),
+            "FINAL: Done.".to_string(),
+        ]));
+        let mut agent = ReactAgent::new(
+            Box::new(Arc::clone(&client)),
+            default_tools(dir.path().to_path_buf()),
+            dir.path().to_path_buf(),
+            Some(5),
+            Some(false),
+            None,
+        )
+        .with_checkpoints(true);
+
+        let steps = agent.run("Update a.txt").await.unwrap().steps;
+
+        assert!(steps[0].diff.as_deref().unwrap().contains("-old\n+new\n"));
+        assert_eq!(steps[1].diff, None);
+    }
+
+    #[tokio::test]
+    async fn test_empty_turns_are_retried() {
+        let dir = tempfile::tempdir().unwrap();
+        let client = Arc::new(ScriptedClient::new(vec![
+            vec![StreamChunk::done()],
+            vec![StreamChunk::content("  \n"), StreamChunk::done()],
+            vec![StreamChunk::content("FINAL: Done."), StreamChunk::done()],
+        ]));
+        let mut agent = ReactAgent::new(
+            Box::new(Arc::clone(&client)),
+            default_tools(dir.path().to_path_buf()),
+            dir.path().to_path_buf(),
+            Some(5),
+            Some(false),
+            None,
+        );
+
+        let steps = agent.run("Finish").await.unwrap().steps;
+
+        assert_eq!(steps.len(), 3);
+        assert_eq!(steps[0].status, StepStatus::Empty);
+        assert_eq!(steps[1].observation, "The model sent an empty response; it was asked again.");
+        assert_eq!(conversations(&client)[2].last().unwrap().content, build_empty_turn_prompt());
+
+        let client = ScriptedClient::from_responses(["", " "]);
+        let mut agent = ReactAgent::new(
+            Box::new(client),
+            default_tools(dir.path().to_path_buf()),
+            dir.path().to_path_buf(),
+            Some(5),
+            Some(false),
+            None,
+        )
+        .with_max_empty_turns(1);
+        let error = agent.run("Finish").await.unwrap_err();
+        assert!(matches!(error, AgentError::LLMError { step: 2, .. }));
+    }
+
+    #[tokio::test]
+    async fn test_tool_quota() {
+        let dir = tempfile::tempdir().unwrap();
+        std::fs::write(dir.path().join("a.txt"), "a").unwrap();
+        let read = ScriptedClient::tool_call("read_file", serde_json::json!({"path": "a.txt"}));
+        let client = Arc::new(ScriptedClient::from_responses([
+            read.clone(),
+            read.clone(),
+            read,
+            "FINAL: Done.".to_string(),
+        ]));
+        let quotas = Quotas {
+            tools: HashMap::from([("read_file".to_string(), 2)]),
+            tokens: None,
+        };
+
+        let mut agent = ReactAgent::new(
+            Box::new(Arc::clone(&client)),
+            default_tools(dir.path().to_path_buf()),
+            dir.path().to_path_buf(),
+            Some(5),
+            Some(false),
+            None,
+        )
+        .with_max_repeated_observations(None)
+        .with_quotas(quotas);
+        let steps = agent.run("Read a.txt three times").await.unwrap().steps;
+
+        assert!(steps[1].observation.contains("\"content\":\"a\""));
+        assert_eq!(steps[2].status, StepStatus::Success);
+        assert!(steps[2].observation.contains("Quota exceeded: read_file may be called at most 2 times per run"));
+        assert_eq!(steps.len(), 4);
+    }
+
+    #[tokio::test]
+    async fn test_run_stream() {
+        use futures::StreamExt;
+
+        let dir = tempfile::tempdir().unwrap();
+        std::fs::write(dir.path().join("notes.txt"), "hello").unwrap();
+        let client = Arc::new(ScriptedClient::from_responses([
+            format!("Reading.\n{}", ScriptedClient::tool_call("read_file", serde_json::json!({"path": "notes.txt"}))),
+            "FINAL: hello".to_string(),
+        ]));
+        let mut agent = ReactAgent::new(
+            Box::new(Arc::clone(&client)),
+            default_tools(dir.path().to_path_buf()),
+            dir.path().to_path_buf(),
+            Some(5),
+            Some(false),
+            None,
+        );
+
+        let events: Vec<AgentEvent> = agent.run_stream("What do the notes say?").collect().await;
+        let shown: Vec<String> = events
+            .iter()
+            .map(|event| match event {
+                AgentEvent::Delta(Delta::Thought(text)) => format!("thought {}", text.trim()),
+                AgentEvent::Delta(Delta::Answer(text)) => format!("answer {}", text.trim()),
+                AgentEvent::ToolStarted { step, tool, .. } => format!("start {} {}", step, tool),
+                AgentEvent::ToolFinished { step, tool, status, .. } => format!("end {} {} {:?}", step, tool, status),
+                AgentEvent::Step(index, step) => format!("step {} {}", index, step.action),
+                AgentEvent::Compressed { .. } => "compressed".to_string(),
+                AgentEvent::Finished(outcome) => format!("finished {:?}", outcome.as_ref().unwrap().stop_reason),
+            })
+            .collect();
+        assert_eq!(
+            shown,
+            [
+                "thought Reading.",
+                "start 1 read_file",
+                "end 1 read_file Success",
+                "step 1 read_file",
+                "answer hello",
+                "step 2 ",
+                "finished Finished",
+            ]
+        );
+        // The events are only lent for the run.
+        assert!(agent.events.is_none());
+    }
+
+    #[tokio::test]
+    async fn test_cancellation_stops_tool() {
+        let dir = tempfile::tempdir().unwrap();
+        let client = ScriptedClient::from_responses([
+            ScriptedClient::tool_call("run_command", serde_json::json!({"command": "sleep 30"})),
+            "FINAL: Done.".to_string(),
+        ]);
+        let cancel = CancellationToken::new();
+        let mut agent = ReactAgent::new(
+            Box::new(client),
+            default_tools(dir.path().to_path_buf()),
+            dir.path().to_path_buf(),
+            Some(5),
+            Some(false),
+            None,
+        )
+        .with_checkpoints(false)
+        .with_cancellation(cancel.clone());
+
+        let interrupt = tokio::spawn(async move {
+            tokio::time::sleep(Duration::from_millis(200)).await;
+            cancel.cancel();
+        });
+        let started = Instant::now();
+        let result = agent.run("Wait a while").await.unwrap();
+        interrupt.await.unwrap();
+
+        assert!(started.elapsed() < Duration::from_secs(10));
+        assert_eq!(result.stop_reason, StopReason::Interrupted);
+        assert_eq!(result.steps.len(), 1);
+        assert!(result.steps[0].observation.contains("Interrupted by the user: run_command was stopped"));
+    }
+
+    #[tokio::test]
+    async fn test_argument_repair() {
+        let dir = tempfile::tempdir().unwrap();
+        std::fs::write(dir.path().join("a.txt"), "a").unwrap();
+        let wrong = ScriptedClient::tool_call("read_file", serde_json::json!({"path": {"name": "a.txt"}}));
+        let right = ScriptedClient::tool_call("read_file", serde_json::json!({"path": "a.txt"}));
+        let agent = |responses: Vec<String>, repairs| {
+            ReactAgent::new(
+                Box::new(ScriptedClient::from_responses(responses)),
+                default_tools(dir.path().to_path_buf()),
+                dir.path().to_path_buf(),
+                Some(5),
+                Some(false),
+                None,
+            )
+            .with_max_argument_repairs(repairs)
+        };
+
+        let responses = vec![wrong.clone(), right, "FINAL: a".to_string()];
+        let steps = agent(responses, 1).run("Read a.txt").await.unwrap().steps;
+        assert_eq!(steps[0].status, StepStatus::InvalidArguments);
+        let output: ToolOutput = serde_json::from_str(&steps[0].observation).unwrap();
+        assert_eq!(output.error.unwrap().code, "invalid_arguments");
+        assert_eq!(output.data["errors"][0], "argument `path` must be a string; you sent an object");
+        assert!(steps[1].observation.contains("\"content\":\"a\""));
+
+        let responses = vec![wrong.clone(), wrong, "FINAL: a".to_string()];
+        let error = agent(responses, 1).run("Read a.txt").await.unwrap_err();
+        assert!(matches!(error, AgentError::ToolError { step: 2, source: ToolError::InvalidArguments(_), .. }));
+    }
+
+    #[tokio::test]
+    async fn test_prefetch() {
+        let dir = tempfile::tempdir().unwrap();
+        std::fs::create_dir(dir.path().join("src")).unwrap();
+        std::fs::write(dir.path().join("src/lib.rs"), "pub fn add() {}\n").unwrap();
+        std::fs::write(dir.path().join("src/math.rs"), "pub fn sub() {}\n").unwrap();
+        let client = Arc::new(ScriptedClient::from_responses([
+            ScriptedClient::tool_call("grep", serde_json::json!({"pattern": "sub", "path": "src"})),
+            "FINAL: Done.".to_string(),
+        ]));
+
+        let mut agent = ReactAgent::new(
+            Box::new(Arc::clone(&client)),
+            default_tools(dir.path().to_path_buf()),
+            dir.path().to_path_buf(),
+            Some(5),
+            Some(false),
+            None,
+        )
+        .with_prefetch(Some(Prefetch::default()));
+        agent.run("Explain src/lib.rs").await.unwrap();
+
+        let conversations = conversations(&client);
+        let first = &conversations[0].last().unwrap().content;
+        assert!(first.contains("- src/lib.rs (16 bytes)\n    pub fn add()"), "{}", first);
+        let second = &conversations[1].last().unwrap().content;
+        assert!(second.contains("- src/math.rs (16 bytes)"), "{}", second);
+        assert!(!second.contains("src/lib.rs"), "{}", second);
+    }
+
+    #[tokio::test]
+    async fn test_token_budget() {
+        let dir = tempfile::tempdir().unwrap();
+        let turn = |response: &str| {
+            vec![
+                StreamChunk::content(response),
+                StreamChunk::usage(&Usage {
+                    input_tokens: 700,
+                    output_tokens: 100,
+                    reasoning_tokens: 0,
+                }),
+                StreamChunk::done(),
+            ]
+        };
+        let list = ScriptedClient::tool_call("list_dir", serde_json::json!({"path": "."}));
+        let client = Arc::new(ScriptedClient::new(vec![turn(&list), turn(&list), turn("FINAL: Done.")]));
+        let mut agent = ReactAgent::new(
+            Box::new(Arc::clone(&client)),
+            default_tools(dir.path().to_path_buf()),
+            dir.path().to_path_buf(),
+            Some(10),
+            Some(false),
+            None,
+        )
+        .with_max_repeated_observations(None)
+        .with_quotas(Quotas {
+            tokens: Some(1000),
+            ..Quotas::default()
+        })
+        .with_pricing(Some(Pricing {
+            input_per_mtok: 2.5,
+            output_per_mtok: 10.0,
+        }));
+
+        let result = agent.run("Look around").await.unwrap();
+
+        assert_eq!(result.stop_reason, StopReason::TokenBudget);
+        assert_eq!(result.steps.len(), 2);
+        assert_eq!((result.usage.input_tokens, result.usage.output_tokens), (1400, 200));
+        assert!((result.cost_usd.unwrap() - 0.0055).abs() < 1e-9);
+        let prompt = client.requests()[1].last().unwrap().content.clone();
+        assert!(prompt.starts_with("Step 2/10 (8 left after this one, 200 of 1000 tokens left, "));
+        assert!(prompt.contains("Resources are running low."));
+    }
+
+    #[tokio::test]
+    async fn test_usage_is_priced_by_the_model_that_answered() {
+        let dir = tempfile::tempdir().unwrap();
+        let turn = |response: &str, input_tokens: u64| {
+            vec![
+                StreamChunk::content(response),
+                StreamChunk::usage(&Usage {
+                    input_tokens,
+                    output_tokens: 100,
+                    reasoning_tokens: 0,
+                }),
+                StreamChunk::done(),
+            ]
+        };
+        let list = ScriptedClient::tool_call("list_dir", serde_json::json!({"path": "."}));
+        let agent = |small_pricing: BTreeMap<String, Pricing>| {
+            let big = ScriptedClient::new(vec![turn(&list, 1000), turn(&list, 2000)]).with_model("big");
+            let small = ScriptedClient::new(vec![turn("## Done\nListed twice.", 3000)]).with_model("small");
+            ReactAgent::new(
+                Box::new(big),
+                default_tools(dir.path().to_path_buf()),
+                dir.path().to_path_buf(),
+                Some(2),
+                Some(false),
+                None,
+            )
+            .with_summary_client(Some(Box::new(small)))
+            .with_pricing(Some(Pricing {
+                input_per_mtok: 10.0,
+                output_per_mtok: 30.0,
+            }))
+            .with_model_pricing(small_pricing)
+        };
+
+        let small = Pricing {
+            input_per_mtok: 1.0,
+            output_per_mtok: 2.0,
+        };
+        let result = agent(BTreeMap::from([("small".to_string(), small)])).run("Look around").await.unwrap();
+
+        assert_eq!(result.stop_reason, StopReason::MaxSteps);
+        // The summary's request counts with the steps'.
+        assert_eq!((result.usage.input_tokens, result.usage.output_tokens), (6000, 300));
+        let prompts: Vec<Option<u64>> = result.steps.iter().map(|step| step.prompt_tokens).collect();
+        assert_eq!(prompts, [Some(1000), Some(2000), Some(3000)]);
+        // 3000 in and 200 out at the main model's price, 3000 in and 100
+        // out at the summary model's.
+        assert!((result.cost_usd.unwrap() - (0.036 + 0.0032)).abs() < 1e-9);
+
+        // A model without a price makes the cost unknown, not wrong.
+        let result = agent(BTreeMap::new()).run("Look around").await.unwrap();
+        assert_eq!(result.usage.input_tokens, 6000);
+        assert_eq!(result.cost_usd, None);
+    }
+
+    #[tokio::test]
+    async fn test_guardrail() {
+        struct Policy;
+
+        impl Guardrail for Policy {
+            fn check_outbound(&self, message: &Message) -> Verdict {
+                if message.content.contains("hunter2") {
+                    Verdict::Redact(message.content.replace("hunter2", "[password]"))
+                } else {
+                    Verdict::Allow
+                }
+            }
+
+            fn check_inbound(&self, output: &str) -> Verdict {
+                if output.contains("rm -rf") {
+                    Verdict::Block("destructive command".to_string())
+                } else {
+                    Verdict::Allow
+                }
+            }
+        }
+
+        let dir = tempfile::tempdir().unwrap();
+        let client = Arc::new(ScriptedClient::from_responses([
+            "I'll clean up first.".to_string(),
+            ScriptedClient::tool_call("run_command", serde_json::json!({"command": "rm -rf /"})),
+        ]));
+        let mut agent = ReactAgent::new(
+            Box::new(Arc::clone(&client)),
+            default_tools(dir.path().to_path_buf()),
+            dir.path().to_path_buf(),
+            Some(5),
+            Some(false),
+            None,
+        )
+        .with_guardrail(Arc::new(Policy));
+
+        let error = agent.run("Log in with password hunter2").await.unwrap_err();
+
+        assert!(matches!(error, AgentError::Blocked(ref reason) if reason == "destructive command"));
+        let sent = &conversations(&client)[0];
+        assert_eq!(sent.last().unwrap().content, "Log in with password [password]");
+    }
+
+    #[tokio::test]
+    async fn test_hooks() {
+        let dir = tempfile::tempdir().unwrap();
+        let hooks = Hooks {
+            on_run_start: vec![],
+            on_file_write: vec!["! grep -q '\"path\":\"secrets' || { echo 'secrets are off limits' >&2; exit 1; }".to_string()],
+            on_run_end: vec!["cat > end.json".to_string()],
+        };
+        let client = Arc::new(ScriptedClient::from_responses([
+            ScriptedClient::tool_call("write_file", serde_json::json!({"path": "secrets.env", "content": "x"})),
+            ScriptedClient::tool_call("write_file", serde_json::json!({"path": "notes.txt", "content": "x"})),
+            "FINAL: Wrote the notes.".to_string(),
+        ]));
+        let mut agent = ReactAgent::new(
+            Box::new(Arc::clone(&client)),
+            default_tools(dir.path().to_path_buf()),
+            dir.path().to_path_buf(),
+            Some(5),
+            Some(false),
+            None,
+        )
+        .with_hooks(hooks.clone());
+
+        let steps = agent.run("Write the files").await.unwrap().steps;
+
+        assert!(steps[0].observation.contains("\"code\":\"vetoed\""));
+        assert!(steps[0].observation.contains("secrets are off limits"));
+        assert!(!dir.path().join("secrets.env").exists());
+        assert!(dir.path().join("notes.txt").exists());
+        let end: serde_json::Value =
+            serde_json::from_str(&std::fs::read_to_string(dir.path().join("end.json")).unwrap()).unwrap();
+        assert_eq!(end["event"], "run_end");
+        assert_eq!(end["steps"], 3);
+        assert_eq!(end["stop_reason"], "finished");
+
+        let mut agent = ReactAgent::new(
+            Box::new(ScriptedClient::from_responses(["FINAL: Done."])),
+            default_tools(dir.path().to_path_buf()),
+            dir.path().to_path_buf(),
+            Some(5),
+            Some(false),
+            None,
+        )
+        .with_hooks(Hooks {
+            on_run_start: vec!["exit 3".to_string()],
+            ..hooks
+        });
+        let error = agent.run("Anything").await.unwrap_err();
+        assert!(matches!(error, AgentError::Hook(ref reason) if reason.contains("exit status: 3")));
+
+        // A hook that hangs is stopped at the tool's time limit.
+        let mut agent = ReactAgent::new(
+            Box::new(ScriptedClient::from_responses([
+                ScriptedClient::tool_call("write_file", serde_json::json!({"path": "slow.txt", "content": "x"})),
+                "FINAL: Done.".to_string(),
+            ])),
+            default_tools(dir.path().to_path_buf()),
+            dir.path().to_path_buf(),
+            Some(5),
+            Some(false),
+            None,
+        )
+        .with_timeouts(Timeouts {
+            tools: HashMap::from([("write_file".to_string(), 1)]),
+            ..Timeouts::default()
+        })
+        .with_hooks(Hooks {
+            on_file_write: vec!["sleep 30".to_string()],
+            ..Hooks::default()
+        });
+        let steps = agent.run("Write slowly").await.unwrap().steps;
+        assert!(steps[0].observation.contains("did not finish in time"), "{}", steps[0].observation);
+        assert!(!dir.path().join("slow.txt").exists());
+    }
+
+    #[tokio::test]
+    async fn test_read_only_dry_runs_other_tools() {
+        let dir = tempfile::tempdir().unwrap();
+        let client = Arc::new(ScriptedClient::from_responses([
+            ScriptedClient::tool_call("write_file", serde_json::json!({"path": "a.txt", "content": "x"})),
+            "FINAL: I can't change files here.".to_string(),
+        ]));
+        let mut agent = ReactAgent::new(
+            Box::new(Arc::clone(&client)),
+            crate::tools::read_only_tools(dir.path().to_path_buf()),
+            dir.path().to_path_buf(),
+            Some(5),
+            Some(false),
+            None,
+        )
+        .with_read_only(true);
+
+        let steps = agent.run("Create a.txt").await.unwrap().steps;
+
+        assert_eq!(steps[0].status, StepStatus::Success);
+        assert!(steps[0].observation.contains("Read-only mode: write_file was not run"));
+        assert!(!dir.path().join("a.txt").exists());
+        assert!(client.requests()[0][0].content.contains("read-only mode"));
+
+        // Hooks only see writes that will happen.
+        let client = ScriptedClient::from_responses([
+            ScriptedClient::tool_call("write_file", serde_json::json!({"path": "a.txt", "content": "x"})),
+            "FINAL: Done.".to_string(),
+        ]);
+        let mut agent = ReactAgent::new(
+            Box::new(client),
+            default_tools(dir.path().to_path_buf()),
+            dir.path().to_path_buf(),
+            Some(5),
+            Some(false),
+            None,
+        )
+        .with_read_only(true)
+        .with_hooks(Hooks {
+            on_file_write: vec!["touch hook-ran".to_string()],
+            ..Hooks::default()
+        });
+        let steps = agent.run("Create a.txt").await.unwrap().steps;
+        assert!(steps[0].observation.contains("Read-only mode: write_file was not run"));
+        assert!(!dir.path().join("hook-ran").exists());
+    }
+
+    #[tokio::test]
+    async fn test_read_only_follows_annotations() {
+        let dir = tempfile::tempdir().unwrap();
+        std::fs::write(dir.path().join("b.txt"), "keep").unwrap();
+        let client = Arc::new(ScriptedClient::from_responses([
+            ScriptedClient::tool_call("run_command", serde_json::json!({"command": "rm b.txt"})),
+            ScriptedClient::tool_call("read_file", serde_json::json!({"path": "b.txt"})),
+            "FINAL: Read it.".to_string(),
+        ]));
+        let mut agent = ReactAgent::new(
+            Box::new(Arc::clone(&client)),
+            default_tools(dir.path().to_path_buf()),
+            dir.path().to_path_buf(),
+            Some(5),
+            Some(false),
+            None,
+        )
+        .with_read_only(true);
+
+        let steps = agent.run("Look at b.txt").await.unwrap().steps;
+
+        assert!(steps[0].observation.contains("Read-only mode: run_command was not run"));
+        assert!(!steps[0].observation.contains("write_file"));
+        assert!(dir.path().join("b.txt").exists());
+        assert!(steps[1].observation.contains("keep"));
+    }
+
+    struct ScriptedGate(std::sync::Mutex<Vec<GateDecision>>);
+
+    #[async_trait]
+    impl StepGate for ScriptedGate {
+        async fn before_tool(&self, _: usize, _: &str, _: &str, _: &serde_json::Value) -> GateDecision {
+            self.0.lock().unwrap().remove(0)
+        }
+    }
+
+    #[tokio::test]
+    async fn test_step_gate() {
+        let dir = tempfile::tempdir().unwrap();
+        std::fs::write(dir.path().join("b.txt"), "from b").unwrap();
+        let client = Arc::new(ScriptedClient::from_responses([
+            ScriptedClient::tool_call("read_file", serde_json::json!({"path": "a.txt"})),
+            ScriptedClient::tool_call("write_file", serde_json::json!({"path": "c.txt", "content": "x"})),
+            ScriptedClient::tool_call("run_command", serde_json::json!({"command": "rm b.txt"})),
+        ]));
+        let gate = ScriptedGate(std::sync::Mutex::new(vec![
+            GateDecision::Edit(serde_json::json!({"path": "b.txt"})),
+            GateDecision::Skip,
+            GateDecision::Abort,
+        ]));
+        let mut agent = ReactAgent::new(
+            Box::new(Arc::clone(&client)),
+            default_tools(dir.path().to_path_buf()),
+            dir.path().to_path_buf(),
+            Some(5),
+            Some(false),
+            None,
+        )
+        .with_step_gate(Some(Arc::new(gate)));
+
+        let result = agent.run("Look around").await;
+
+        assert!(matches!(result, Err(AgentError::Aborted(ref tool)) if tool == "run_command"));
+        let requests = conversations(&client);
+        let second = &requests[1];
+        assert!(second[second.len() - 2].content.contains("b.txt"));
+        assert!(second[second.len() - 1].content.contains("from b"));
+        assert!(requests[2].last().unwrap().content.contains("skipped"));
+        assert!(!dir.path().join("c.txt").exists());
+        assert!(dir.path().join("b.txt").exists());
+    }
+
+    #[tokio::test]
+    async fn test_permissions() {
+        struct Declines(std::sync::Mutex<Vec<String>>);
+
+        #[async_trait]
+        impl Approver for Declines {
+            async fn approve(&self, request: &ApprovalRequest) -> bool {
+                self.0.lock().unwrap().push(request.action.clone());
+                false
+            }
+        }
+
+        let dir = tempfile::tempdir().unwrap();
+        std::fs::write(dir.path().join("b.txt"), "from b").unwrap();
+        let client = Arc::new(ScriptedClient::from_responses([
+            ScriptedClient::tool_call("run_command", serde_json::json!({"command": "rm b.txt"})),
+            ScriptedClient::tool_call("run_command", serde_json::json!({"command": "git push --force"})),
+            ScriptedClient::tool_call("write_file", serde_json::json!({"path": "c.txt", "content": "x"})),
+            "FINAL: Left alone.".to_string(),
+        ]));
+        let permissions: PermissionPolicy = serde_json::from_value(serde_json::json!({
+            "default": "allow",
+            "rules": [
+                {"tools": ["run_command"], "pattern": "rm *", "permission": "ask"},
+                {"tools": ["run_command"], "pattern": "git push*", "permission": "deny"}
+            ]
+        }))
+        .unwrap();
+        let approver = Arc::new(Declines(std::sync::Mutex::new(Vec::new())));
+        let mut agent = ReactAgent::new(
+            Box::new(Arc::clone(&client)),
+            default_tools(dir.path().to_path_buf()),
+            dir.path().to_path_buf(),
+            Some(5),
+            Some(false),
+            None,
+        )
+        .with_permissions(permissions)
+        .with_approver(Some(Arc::clone(&approver) as Arc<dyn Approver>));
+
+        let steps = agent.run("Clean up").await.unwrap().steps;
+
+        assert!(steps[0].observation.contains("The user declined running `rm b.txt`; it was not run."));
+        assert!(steps[1].observation.contains("Denied by the permission policy: running `git push --force`"));
+        assert_eq!(*approver.0.lock().unwrap(), ["running `rm b.txt`"]);
+        assert!(dir.path().join("b.txt").exists());
+        assert!(dir.path().join("c.txt").exists());
+    }
+
+    #[tokio::test]
+    async fn test_steering_reaches_next_turn() {
+        let dir = tempfile::tempdir().unwrap();
+        let client = Arc::new(ScriptedClient::from_responses([
+            ScriptedClient::tool_call("list_dir", serde_json::json!({"path": "."})),
+            "FINAL: Done.".to_string(),
+        ]));
+        let mut agent = ReactAgent::new(
+            Box::new(Arc::clone(&client)),
+            default_tools(dir.path().to_path_buf()),
+            dir.path().to_path_buf(),
+            Some(5),
+            Some(false),
+            None,
+        );
+        let steering = agent.steering();
+        agent.set_step_callback(Some(Arc::new(move |_, _| steering.push("Use the async API"))));
+
+        agent.run("Refactor the client").await.unwrap();
+
+        let requests = conversations(&client);
+        assert!(!requests[0].iter().any(|m| m.content.contains("async API")));
+        let last = requests[1].last().unwrap();
+        assert_eq!(last.role, MessageRole::User);
+        assert!(last.content.contains("Use the async API"));
+    }
+
+    #[tokio::test]
+    async fn test_delta_callback() {
+        let dir = tempfile::tempdir().unwrap();
+        let client = ScriptedClient::new(vec![vec![
+            StreamChunk::content("I know this.\nFIN"),
+            StreamChunk::content("AL: 42"),
+            StreamChunk::done(),
+        ]]);
+        let deltas = Arc::new(std::sync::Mutex::new(Vec::new()));
+        let callback_deltas = Arc::clone(&deltas);
+
+        let mut agent = ReactAgent::new(
+            Box::new(client),
+            default_tools(dir.path().to_path_buf()),
+            dir.path().to_path_buf(),
+            Some(5),
+            Some(false),
+            None,
+        );
+        agent.set_delta_callback(Some(Arc::new(move |delta| callback_deltas.lock().unwrap().push(delta))));
+        agent.run("What is six times seven?").await.unwrap();
+
+        assert_eq!(
+            *deltas.lock().unwrap(),
+            vec![Delta::Thought("I know this.\n".to_string()), Delta::Answer("42".to_string())]
+        );
+    }
+
+    #[tokio::test]
+    async fn test_step_metadata() {
+        let dir = tempfile::tempdir().unwrap();
+        let usage = Usage {
+            input_tokens: 120,
+            output_tokens: 8,
+            reasoning_tokens: 4,
+        };
+        let client = ScriptedClient::new(vec![
+            vec![StreamChunk::content("Let me think."), StreamChunk::usage(&usage), StreamChunk::done()],
+            vec![
+                StreamChunk::content(ScriptedClient::tool_call("delete_everything", serde_json::json!({}))),
+                StreamChunk::done(),
+            ],
+        ]);
+        let seen = Arc::new(std::sync::Mutex::new(Vec::new()));
+        let callback_seen = Arc::clone(&seen);
+        let callback: StepCallback = Arc::new(move |_, step| callback_seen.lock().unwrap().push(step));
+
+        let mut agent = ReactAgent::new(
+            Box::new(client),
+            default_tools(dir.path().to_path_buf()),
+            dir.path().to_path_buf(),
+            Some(5),
+            Some(false),
+            Some(callback),
+        );
+        let error = agent.run("Clean up").await.unwrap_err();
+
+        assert!(matches!(
+            error,
+            AgentError::ToolError { step: 2, ref tool, source: ToolError::UnknownTool(_) } if tool == "delete_everything"
+        ));
+        assert_eq!(error.to_string(), "Tool error at step 2 (delete_everything): Unknown tool: delete_everything");
+        assert!(!error.is_retryable());
+        let steps = seen.lock().unwrap();
+        assert_eq!(steps.len(), 2);
+        assert_eq!(steps[0].status, StepStatus::Parsing);
+        assert_eq!(steps[0].prompt_tokens, Some(120));
+        assert_eq!(steps[0].completion_tokens, Some(12));
+        assert!(steps[0].started_at > 0);
+        assert_eq!(steps[1].status, StepStatus::ToolError);
+        assert_eq!(steps[1].prompt_tokens, None);
+        let output: ToolOutput = serde_json::from_str(&steps[1].observation).unwrap();
+        assert!(!output.success);
+        assert_eq!(output.error.unwrap().message, "Unknown tool: delete_everything");
+
+        // Steps saved before these fields existed still load.
+        let old: Step = serde_json::from_str(
+            r#"{"thought": "t", "action": "", "action_input": {}, "observation": "", "raw": "t"}"#,
+        )
+        .unwrap();
+        assert_eq!(old.status, StepStatus::Success);
+        assert_eq!(old.duration_ms, 0);
+    }
+
+    #[tokio::test]
+    async fn test_run_max_steps_exceeded() {
+        let client = Arc::new(ScriptedClient::from_responses([
+            "Thinking...",
+            "Still thinking...",
+            "## Done\nThought about it.\n## Remaining\n- everything",
+        ]));
+        let mut agent = ReactAgent::new(
+            Box::new(Arc::clone(&client)),
+            ToolManager::new(),
+            PathBuf::from("/tmp"),
+            Some(2),
+            Some(false),
+            None,
+        );
+
+        let result = agent.run("Do something").await.unwrap();
+
+        assert_eq!(result.stop_reason, StopReason::MaxSteps);
+        assert_eq!(result.summary.as_deref(), Some("## Done\nThought about it.\n## Remaining\n- everything"));
+        assert_eq!(result.steps.len(), 3);
+        assert_eq!(result.steps[2].thought, result.summary.unwrap());
+        assert_eq!(client.requests()[2].last().unwrap().content, build_max_steps_prompt(2));
+
+        // Without a summary the steps are still returned.
+        let client = ScriptedClient::from_responses(["Thinking..."]);
+        let mut agent =
+            ReactAgent::new(Box::new(client), ToolManager::new(), PathBuf::from("/tmp"), Some(1), Some(false), None);
+        let result = agent.run("Do something").await.unwrap();
+        assert_eq!(result.stop_reason, StopReason::MaxSteps);
+        assert_eq!(result.summary, None);
+        assert_eq!(result.steps.len(), 1);
+
+        // The summary model writes the summary when there is one.
+        let client = Arc::new(ScriptedClient::from_responses(["Thinking..."]));
+        let small = Arc::new(ScriptedClient::from_responses(["## Done\nNothing."]));
+        let mut agent = ReactAgent::new(
+            Box::new(Arc::clone(&client)),
+            ToolManager::new(),
+            PathBuf::from("/tmp"),
+            Some(1),
+            Some(false),
+            None,
+        )
+        .with_summary_client(Some(Box::new(Arc::clone(&small))));
+        let result = agent.run("Do something").await.unwrap();
+        assert_eq!(result.summary.as_deref(), Some("## Done\nNothing."));
+        assert_eq!(client.requests().len(), 1);
+        assert_eq!(small.requests()[0].last().unwrap().content, build_max_steps_prompt(1));
+    }
+
+    #[tokio::test]
+    async fn test_follow_up_runs_keep_context_and_compact() {
+        let dir = tempfile::tempdir().unwrap();
+        std::fs::write(dir.path().join("notes.txt"), "hello").unwrap();
+
+        let client = Arc::new(ScriptedClient::from_responses([
+            ScriptedClient::tool_call("read_file", serde_json::json!({"path": "notes.txt"})),
+            "FINAL: It says hello.".to_string(),
+            "FINAL: Yes, still hello.".to_string(),
+            "FINAL: Compacted.".to_string(),
+        ]));
+        let mut agent = ReactAgent::new(
+            Box::new(Arc::clone(&client)),
+            default_tools(dir.path().to_path_buf()),
+            dir.path().to_path_buf(),
+            Some(5),
+            Some(false),
+            None,
+        );
+
+        agent.run("What does notes.txt say?").await.unwrap();
+        agent.run("Are you sure?").await.unwrap();
+
+        let requests = conversations(&client);
+        let follow_up = &requests[2];
+        assert_eq!(follow_up[0].role, MessageRole::System);
+        assert_eq!(follow_up.iter().filter(|m| m.role == MessageRole::System).count(), 1);
+        assert_eq!(follow_up[1].content, "What does notes.txt say?");
+        assert_eq!(follow_up.last().unwrap().content, "Are you sure?");
+        // Tools are still available to the second run.
+        assert!(follow_up[0].content.contains("read_file"));
+
+        let mut compaction = agent.preview_compaction().unwrap();
+        compaction.summary = "User asked about notes.txt; it says hello.".to_string();
+        agent.apply_compaction(&compaction);
+        agent.run("Thanks").await.unwrap();
+
+        let last = conversations(&client).pop().unwrap();
+        assert!(last[1].content.contains("it says hello."));
+        assert_eq!(last.len(), compaction.recent.len() + 3);
+    }
+
+    #[tokio::test]
+    async fn test_telemetry() {
+        struct Collect(Mutex<Vec<TelemetryEvent>>);
+
+        impl TelemetrySink for Collect {
+            fn record(&self, event: &TelemetryEvent) {
+                self.0.lock().unwrap().push(event.clone());
+            }
+        }
+
+        let dir = tempfile::tempdir().unwrap();
+        std::fs::write(dir.path().join("notes.txt"), "hello").unwrap();
+        let sink = Arc::new(Collect(Mutex::new(Vec::new())));
+        let mut agent = ReactAgent::new(
+            Box::new(ScriptedClient::from_responses([
+                ScriptedClient::tool_call("read_file", serde_json::json!({"path": "notes.txt"})),
+                "FINAL: hello".to_string(),
+            ])),
+            default_tools(dir.path().to_path_buf()),
+            dir.path().to_path_buf(),
+            Some(5),
+            Some(false),
+            None,
+        )
+        .with_telemetry(Arc::clone(&sink) as Arc<dyn TelemetrySink>);
+
+        agent.run("What do the notes say?").await.unwrap();
+
+        let events = sink.0.lock().unwrap();
+        let names: Vec<String> = events
+            .iter()
+            .map(|event| serde_json::to_value(event).unwrap()["event"].as_str().unwrap().to_string())
+            .collect();
+        assert_eq!(names, ["run_started", "tool_call", "step", "step", "run_finished"]);
+        assert!(matches!(&events[1], TelemetryEvent::ToolCall { name, status: StepStatus::Success, .. } if name == "read_file"));
+        assert!(matches!(
+            &events[4],
+            TelemetryEvent::RunFinished { steps: 2, stop_reason: Some(StopReason::Finished), error: None, .. }
+        ));
+    }
+
+    #[tokio::test]
+    async fn test_tool_selection() {
+        let dir = tempfile::tempdir().unwrap();
+        std::fs::write(dir.path().join("notes.txt"), "hello").unwrap();
+        let client = Arc::new(ScriptedClient::from_responses([
+            ScriptedClient::tool_call(LIST_ALL_TOOLS, serde_json::json!({})),
+            ScriptedClient::tool_call("run_command", serde_json::json!({"command": "cat notes.txt"})),
+            "FINAL: hello".to_string(),
+        ]));
+        let mut agent = ReactAgent::new(
+            Box::new(Arc::clone(&client)),
+            default_tools(dir.path().to_path_buf()),
+            dir.path().to_path_buf(),
+            Some(5),
+            Some(false),
+            None,
+        )
+        .with_tool_selection(ToolSelection {
+            max_tools: Some(2),
+            always: vec!["read_file".to_string()],
+        })
+        .with_minify_schemas(Some(MinifySchemas {
+            max_description_chars: Some(10),
+            drop_parameter_descriptions: false,
+        }));
+
+        let steps = agent.run("What do the notes say? Search them").await.unwrap().steps;
+
+        let system = &client.requests()[0][0].content;
+        assert!(system.contains("- read_file: Read the...\n") && system.contains("- search_history:"));
+        assert!(system.contains(&format!("- {}:", LIST_ALL_TOOLS)));
+        assert!(!system.contains("- run_command:"));
+        assert_eq!(steps[0].status, StepStatus::Success);
+        assert!(steps[0].observation.contains("\"run_command\""));
+        assert!(steps[0].observation.contains("\"Run a...\""));
+        assert!(!steps[0].observation.contains("Search the transcripts"));
+        assert_eq!(steps[1].status, StepStatus::Success);
+        assert!(steps[1].observation.contains("hello"));
+    }
+
+    #[tokio::test]
+    async fn test_set_client_keeps_history() {
+        let dir = tempfile::tempdir().unwrap();
+        let cheap = Arc::new(ScriptedClient::from_responses(["FINAL: It is a Rust crate."]));
+        let strong = Arc::new(ScriptedClient::from_responses(["FINAL: Done."]));
+        let mut agent = ReactAgent::new(
+            Box::new(Arc::clone(&cheap)),
+            default_tools(dir.path().to_path_buf()),
+            dir.path().to_path_buf(),
+            Some(5),
+            Some(false),
+            None,
+        );
+
+        agent.run("What is this?").await.unwrap();
+        agent.set_client(Box::new(Arc::clone(&strong)));
+        agent.run("Now fix it").await.unwrap();
+
+        assert_eq!(cheap.requests().len(), 1);
+        let sent = &conversations(&strong)[0];
+        assert_eq!(sent[1].content, "What is this?");
+        assert_eq!(sent[2].content, "FINAL: It is a Rust crate.");
+        assert_eq!(sent.last().unwrap().content, "Now fix it");
+    }
+}
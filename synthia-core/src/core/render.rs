@@ -0,0 +1,159 @@
+use super::{AgentResult, Step, StopReason};
+use crate::clients::Usage;
+use std::fmt;
+
+/// How much of each step to show.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum Detail {
+    /// One line per step: the tool and what it returned.
+    #[default]
+    Compact,
+    /// The thought, tool call, result and changes of each step.
+    Verbose,
+}
+
+/// Step `index`, counted from 1. Verbose output starts with a header line.
+pub fn render_step(index: usize, step: &Step, detail: Detail) -> String {
+    match detail {
+        Detail::Compact => format!("{}. {}: {}", index, step.action, step.observation),
+        Detail::Verbose => format!("--- Step {} ---\n{}", index, step),
+    }
+}
+
+/// The tokens used and, if known, what they cost, on one line.
+pub fn render_usage(usage: &Usage, cost_usd: Option<f64>) -> String {
+    let mut out = format!("Tokens: {} in, {} out", usage.input_tokens, usage.output_tokens);
+    if usage.reasoning_tokens > 0 {
+        out.push_str(&format!(", {} reasoning", usage.reasoning_tokens));
+    }
+    if let Some(cost) = cost_usd {
+        out.push_str(&format!(" (${:.4})", cost));
+    }
+    out
+}
+
+/// The step count, the tokens used if the provider reported any, and each
+/// step, then why the run stopped early and its summary, if it did.
+pub fn render_result(result: &AgentResult, detail: Detail) -> String {
+    let mut out = format!("Total steps: {}\n", result.steps.len());
+    if result.usage != Usage::default() {
+        out.push_str(&render_usage(&result.usage, result.cost_usd));
+        out.push('\n');
+    }
+    for (i, step) in result.steps.iter().enumerate() {
+        if detail == Detail::Verbose {
+            out.push('\n');
+        }
+        out.push_str(&render_step(i + 1, step, detail));
+        out.push('\n');
+    }
+    if result.stop_reason != StopReason::Finished {
+        out.push_str(&format!("\nThe agent {} before finishing.\n", result.stop_reason));
+        if let Some(summary) = &result.summary {
+            out.push_str(summary);
+            out.push('\n');
+        }
+    }
+    out
+}
+
+/// The thought, then the tool call, its result and the changes it made if
+/// the step has them, each on labelled lines.
+impl fmt::Display for Step {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "Thought: {}", self.thought)?;
+        if !self.action.is_empty() {
+            write!(f, "\nAction: {}\nAction Input: {}", self.action, self.action_input)?;
+        }
+        if !self.observation.is_empty() {
+            write!(f, "\nObservation: {}", self.observation)?;
+        }
+        if let Some(diff) = &self.diff {
+            write!(f, "\nChanges:\n{}", diff.trim_end())?;
+        }
+        Ok(())
+    }
+}
+
+impl fmt::Display for StopReason {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(match self {
+            StopReason::Finished => "finished",
+            StopReason::MaxSteps => "ran out of steps",
+            StopReason::TokenBudget => "ran out of tokens",
+            StopReason::Interrupted => "was interrupted",
+        })
+    }
+}
+
+impl fmt::Display for AgentResult {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(&render_result(self, Detail::Compact))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    #[test]
+    fn test_render() {
+        let mut call = Step::new(
+            "Check the notes.".to_string(),
+            "read_file".to_string(),
+            json!({"path": "notes.txt"}),
+            "hello".to_string(),
+            String::new(),
+        );
+        call.diff = Some("+hello\n".to_string());
+        let answer = Step::new("FINAL: hello".to_string(), String::new(), json!({}), String::new(), String::new());
+
+        assert_eq!(
+            render_step(1, &call, Detail::Verbose),
+            "--- Step 1 ---\nThought: Check the notes.\nAction: read_file\nAction Input: {\"path\":\"notes.txt\"}\nObservation: hello\nChanges:\n+hello"
+        );
+        assert_eq!(answer.to_string(), "Thought: FINAL: hello");
+
+        let mut result = AgentResult {
+            steps: vec![call, answer],
+            stop_reason: StopReason::MaxSteps,
+            summary: Some("Read the notes.".to_string()),
+            usage: Usage::default(),
+            cost_usd: None,
+        };
+        assert_eq!(
+            result.to_string(),
+            "Total steps: 2\n1. read_file: hello\n2. : \n\nThe agent ran out of steps before finishing.\nRead the notes.\n"
+        );
+        assert!(render_result(&result, Detail::Verbose).contains("\n\n--- Step 2 ---\nThought: FINAL: hello\n"));
+
+        let json = serde_json::to_string(&result).unwrap();
+        assert!(json.contains("\"stop_reason\":\"max_steps\""));
+        assert_eq!(serde_json::from_str::<AgentResult>(&json).unwrap(), result);
+
+        result.usage = Usage {
+            input_tokens: 1200,
+            output_tokens: 300,
+            reasoning_tokens: 0,
+        };
+        result.cost_usd = Some(0.006);
+        assert!(result.to_string().starts_with("Total steps: 2\nTokens: 1200 in, 300 out ($0.0060)\n1. read_file"));
+    }
+
+    #[test]
+    fn test_render_usage() {
+        let usage = Usage {
+            input_tokens: 1200,
+            output_tokens: 300,
+            reasoning_tokens: 0,
+        };
+        assert_eq!(render_usage(&usage, None), "Tokens: 1200 in, 300 out");
+        assert_eq!(render_usage(&usage, Some(0.00125)), "Tokens: 1200 in, 300 out ($0.0013)");
+        let reasoning = Usage {
+            reasoning_tokens: 800,
+            ..usage
+        };
+        assert_eq!(render_usage(&reasoning, Some(0.1)), "Tokens: 1200 in, 300 out, 800 reasoning ($0.1000)");
+    }
+}
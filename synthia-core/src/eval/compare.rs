@@ -0,0 +1,278 @@
+use super::SuiteReport;
+use serde::Serialize;
+use std::collections::BTreeMap;
+
+/// How a task's pass rate moved between two runs of a suite.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum Change {
+    Regressed,
+    Improved,
+    Unchanged,
+    /// Only in the new run.
+    Added,
+    /// Only in the baseline.
+    Removed,
+}
+
+/// One task's runs within a report.
+#[derive(Debug, Clone, Default, PartialEq, Serialize)]
+pub struct TaskRuns {
+    pub passed: usize,
+    pub runs: usize,
+    pub mean_steps: f64,
+    /// Mean cost per run, or `None` if any run is unpriced.
+    pub mean_cost: Option<f64>,
+}
+
+impl TaskRuns {
+    fn pass_rate(&self) -> f64 {
+        self.passed as f64 / self.runs.max(1) as f64
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct TaskComparison {
+    pub name: String,
+    pub change: Change,
+    pub baseline: Option<TaskRuns>,
+    pub current: Option<TaskRuns>,
+}
+
+/// The difference between a baseline report and a new one, such as two
+/// models or two prompts run over the same suite. Deltas are new minus
+/// baseline, over the tasks in both.
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct Comparison {
+    pub tasks: Vec<TaskComparison>,
+    pub baseline_pass_rate: f64,
+    pub current_pass_rate: f64,
+    /// The fewest runs of any task on either side, which pass@k is given
+    /// for.
+    pub k: usize,
+    pub baseline_pass_at_k: Option<f64>,
+    pub current_pass_at_k: Option<f64>,
+    /// Change in the summed mean cost per task, or `None` if either side is
+    /// unpriced.
+    pub cost_delta: Option<f64>,
+    /// Change in the summed mean step count per task.
+    pub steps_delta: f64,
+}
+
+impl Comparison {
+    pub fn new(baseline: &SuiteReport, current: &SuiteReport) -> Self {
+        let baseline_runs = task_runs(baseline);
+        let mut current_runs = task_runs(current);
+
+        let mut tasks = Vec::new();
+        let mut cost_delta = Some(0.0);
+        let mut steps_delta = 0.0;
+        for (name, before) in baseline_runs {
+            let Some(after) = current_runs.remove(name) else {
+                tasks.push(TaskComparison {
+                    name: name.to_string(),
+                    change: Change::Removed,
+                    baseline: Some(before),
+                    current: None,
+                });
+                continue;
+            };
+            let change = match after.pass_rate().partial_cmp(&before.pass_rate()) {
+                Some(std::cmp::Ordering::Less) => Change::Regressed,
+                Some(std::cmp::Ordering::Greater) => Change::Improved,
+                _ => Change::Unchanged,
+            };
+            steps_delta += after.mean_steps - before.mean_steps;
+            cost_delta = match (cost_delta, before.mean_cost, after.mean_cost) {
+                (Some(total), Some(before), Some(after)) => Some(total + after - before),
+                _ => None,
+            };
+            tasks.push(TaskComparison {
+                name: name.to_string(),
+                change,
+                baseline: Some(before),
+                current: Some(after),
+            });
+        }
+        for (name, after) in current_runs {
+            tasks.push(TaskComparison {
+                name: name.to_string(),
+                change: Change::Added,
+                baseline: None,
+                current: Some(after),
+            });
+        }
+        tasks.sort_by(|a, b| a.name.cmp(&b.name));
+
+        let k = baseline.min_runs().min(current.min_runs());
+        Self {
+            tasks,
+            baseline_pass_rate: baseline.pass_rate(),
+            current_pass_rate: current.pass_rate(),
+            k,
+            baseline_pass_at_k: baseline.pass_at_k(k),
+            current_pass_at_k: current.pass_at_k(k),
+            cost_delta,
+            steps_delta,
+        }
+    }
+
+    /// Tasks that pass less often than in the baseline.
+    pub fn regressions(&self) -> impl Iterator<Item = &TaskComparison> {
+        self.tasks.iter().filter(|task| task.change == Change::Regressed)
+    }
+
+    /// A summary and a table of the tasks that changed, for posting on a
+    /// pull request.
+    pub fn render_markdown(&self) -> String {
+        let mut out = format!(
+            "**Pass rate:** {:.1}% → {:.1}%\n",
+            self.baseline_pass_rate * 100.0,
+            self.current_pass_rate * 100.0
+        );
+        if self.k > 1
+            && let (Some(before), Some(after)) = (self.baseline_pass_at_k, self.current_pass_at_k)
+        {
+            out.push_str(&format!(
+                "\n**pass@{}:** {:.1}% → {:.1}%\n",
+                self.k,
+                before * 100.0,
+                after * 100.0
+            ));
+        }
+        out.push_str(&format!(
+            "\n**Cost:** {}\n\n**Steps:** {:+.1}\n",
+            self.cost_delta
+                .map(|delta| format!("{}${:.4}", if delta < 0.0 { "-" } else { "+" }, delta.abs()))
+                .unwrap_or_else(|| "-".to_string()),
+            self.steps_delta,
+        ));
+
+        let changed: Vec<_> = self.tasks.iter().filter(|task| task.change != Change::Unchanged).collect();
+        if changed.is_empty() {
+            out.push_str("\nNo task changed.\n");
+            return out;
+        }
+        out.push_str("\n| Task | Change | Passed | Steps |\n|---|---|---|---|\n");
+        for task in changed {
+            out.push_str(&format!(
+                "| {} | {} | {} → {} | {} → {} |\n",
+                task.name,
+                match task.change {
+                    Change::Regressed => "regressed",
+                    Change::Improved => "improved",
+                    Change::Unchanged => "unchanged",
+                    Change::Added => "added",
+                    Change::Removed => "removed",
+                },
+                format_passed(task.baseline.as_ref()),
+                format_passed(task.current.as_ref()),
+                format_steps(task.baseline.as_ref()),
+                format_steps(task.current.as_ref()),
+            ));
+        }
+        out
+    }
+}
+
+fn format_passed(runs: Option<&TaskRuns>) -> String {
+    runs.map(|runs| format!("{}/{}", runs.passed, runs.runs))
+        .unwrap_or_else(|| "-".to_string())
+}
+
+fn format_steps(runs: Option<&TaskRuns>) -> String {
+    runs.map(|runs| format!("{:.1}", runs.mean_steps))
+        .unwrap_or_else(|| "-".to_string())
+}
+
+fn task_runs(report: &SuiteReport) -> BTreeMap<&str, TaskRuns> {
+    let mut totals: BTreeMap<&str, (TaskRuns, usize, Option<f64>)> = BTreeMap::new();
+    for task in &report.tasks {
+        let (runs, steps, cost) = totals
+            .entry(task.name.as_str())
+            .or_insert_with(|| (TaskRuns::default(), 0, Some(0.0)));
+        runs.passed += usize::from(task.passed);
+        runs.runs += 1;
+        *steps += task.steps;
+        *cost = cost.zip(task.cost).map(|(total, cost)| total + cost);
+    }
+    totals
+        .into_iter()
+        .map(|(name, (runs, steps, cost))| {
+            let count = runs.runs as f64;
+            let runs = TaskRuns {
+                mean_steps: steps as f64 / count,
+                mean_cost: cost.map(|cost| cost / count),
+                ..runs
+            };
+            (name, runs)
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::eval::TaskReport;
+
+    fn run(name: &str, passed: bool, steps: usize, cost: f64) -> TaskReport {
+        TaskReport {
+            name: name.to_string(),
+            attempt: 1,
+            passed,
+            steps,
+            input_tokens: 0,
+            output_tokens: 0,
+            reasoning_tokens: 0,
+            cost: Some(cost),
+            wall_time_secs: 0.0,
+            error: None,
+        }
+    }
+
+    #[test]
+    fn test_compare() {
+        let baseline = SuiteReport {
+            tasks: vec![
+                run("fix", true, 4, 0.10),
+                run("fix", true, 6, 0.10),
+                run("lint", false, 8, 0.20),
+                run("old", true, 1, 0.01),
+            ],
+        };
+        let current = SuiteReport {
+            tasks: vec![
+                run("fix", true, 3, 0.05),
+                run("fix", false, 3, 0.05),
+                run("lint", false, 8, 0.20),
+                run("new", true, 2, 0.02),
+            ],
+        };
+
+        let comparison = Comparison::new(&baseline, &current);
+
+        let changes: Vec<_> = comparison.tasks.iter().map(|task| (task.name.as_str(), task.change)).collect();
+        assert_eq!(
+            changes,
+            [
+                ("fix", Change::Regressed),
+                ("lint", Change::Unchanged),
+                ("new", Change::Added),
+                ("old", Change::Removed),
+            ]
+        );
+        assert_eq!(comparison.regressions().count(), 1);
+        assert_eq!(comparison.steps_delta, -2.0);
+        assert!((comparison.cost_delta.unwrap() + 0.05).abs() < 1e-9);
+
+        let markdown = comparison.render_markdown();
+        assert!(markdown.contains("**Cost:** -$0.0500"));
+        assert!(!markdown.contains("pass@"));
+        assert!(markdown.contains("| fix | regressed | 2/2 → 1/2 | 5.0 → 3.0 |\n"));
+        assert!(markdown.contains("| old | removed | 1/1 → - | 1.0 → - |\n"));
+        assert!(!markdown.contains("| lint |"));
+
+        let json = serde_json::to_value(&comparison).unwrap();
+        assert_eq!(json["tasks"][0]["change"], "regressed");
+    }
+}
@@ -0,0 +1,688 @@
+use crate::clients::{LLMClient, LLMError, Message, ModelInfo, StreamChunk, ToolDefinition};
+pub use crate::clients::Pricing;
+use crate::core::{ReactAgent, StopReason};
+use crate::tools::{NetworkPolicy, ResourceLimits, ToolManager, default_tools_with_policy};
+use async_trait::async_trait;
+use futures::{Stream, StreamExt};
+use serde::{Deserialize, Serialize};
+use std::path::{Path, PathBuf};
+use std::pin::Pin;
+use std::collections::BTreeMap;
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant};
+use thiserror::Error;
+
+mod compare;
+
+pub use compare::{Change, Comparison, TaskComparison, TaskRuns};
+
+#[derive(Debug, Error)]
+pub enum EvalError {
+    #[error("Failed to read suite {0}: {1}")]
+    Io(PathBuf, String),
+    #[error("Invalid suite {0}: {1}")]
+    InvalidSuite(PathBuf, String),
+    #[error("Invalid report {0}: {1}")]
+    InvalidReport(PathBuf, String),
+}
+
+/// How long a task's `setup` and `verify` commands may each run, unless
+/// set with [`EvalRunner::with_command_timeout`].
+pub const DEFAULT_COMMAND_TIMEOUT: Duration = Duration::from_secs(600);
+
+/// One benchmark task. `repo` is copied into a fresh temporary working
+/// directory, `setup` commands run there, then the agent gets `prompt` and
+/// the task passes if `verify` exits successfully afterwards. `limits`
+/// replaces the runner's limits on the agent's commands for this task.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct EvalTask {
+    pub name: String,
+    #[serde(default)]
+    pub repo: Option<PathBuf>,
+    #[serde(default)]
+    pub setup: Vec<String>,
+    pub prompt: String,
+    pub verify: String,
+    #[serde(default)]
+    pub max_steps: Option<usize>,
+    #[serde(default)]
+    pub limits: Option<ResourceLimits>,
+}
+
+#[derive(Debug, Clone, PartialEq, Deserialize)]
+pub struct Suite {
+    pub tasks: Vec<EvalTask>,
+    /// Directory that relative `repo` paths are resolved against.
+    #[serde(skip)]
+    pub base_dir: PathBuf,
+}
+
+impl Suite {
+    /// Loads a YAML file with a `tasks:` list, or a directory in which every
+    /// `.yaml`/`.yml` file defines a single task.
+    pub fn load(path: &Path) -> Result<Self, EvalError> {
+        if path.is_dir() {
+            let mut files: Vec<PathBuf> = std::fs::read_dir(path)
+                .map_err(|e| EvalError::Io(path.to_path_buf(), e.to_string()))?
+                .flatten()
+                .map(|entry| entry.path())
+                .filter(|file| {
+                    file.extension()
+                        .is_some_and(|ext| ext == "yaml" || ext == "yml")
+                })
+                .collect();
+            files.sort();
+
+            let tasks = files
+                .iter()
+                .map(|file| parse_yaml(file))
+                .collect::<Result<Vec<EvalTask>, _>>()?;
+
+            return Ok(Self {
+                tasks,
+                base_dir: path.to_path_buf(),
+            });
+        }
+
+        let mut suite: Suite = parse_yaml(path)?;
+        suite.base_dir = path.parent().map(Path::to_path_buf).unwrap_or_default();
+        Ok(suite)
+    }
+}
+
+fn parse_yaml<T: serde::de::DeserializeOwned>(path: &Path) -> Result<T, EvalError> {
+    let content = std::fs::read_to_string(path)
+        .map_err(|e| EvalError::Io(path.to_path_buf(), e.to_string()))?;
+    serde_yaml::from_str(&content)
+        .map_err(|e| EvalError::InvalidSuite(path.to_path_buf(), e.to_string()))
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct TaskReport {
+    pub name: String,
+    /// Which run of the task this is, counted from 1.
+    #[serde(default = "first_attempt")]
+    pub attempt: usize,
+    pub passed: bool,
+    pub steps: usize,
+    /// Tokens as the provider reported them; none for a run that failed.
+    pub input_tokens: usize,
+    pub output_tokens: usize,
+    /// Billed at the output rate, but reported apart from it.
+    pub reasoning_tokens: usize,
+    /// In dollars, each request at its model's price. Unknown if one had
+    /// no price.
+    pub cost: Option<f64>,
+    pub wall_time_secs: f64,
+    pub error: Option<String>,
+}
+
+fn first_attempt() -> usize {
+    1
+}
+
+/// Saved with `--format json`, so that runs can be compared later.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct SuiteReport {
+    pub tasks: Vec<TaskReport>,
+}
+
+impl SuiteReport {
+    /// Reads a report written as JSON.
+    pub fn load(path: &Path) -> Result<Self, EvalError> {
+        let content = std::fs::read_to_string(path)
+            .map_err(|e| EvalError::Io(path.to_path_buf(), e.to_string()))?;
+        serde_json::from_str(&content).map_err(|e| EvalError::InvalidReport(path.to_path_buf(), e.to_string()))
+    }
+
+    pub fn passed(&self) -> usize {
+        self.tasks.iter().filter(|task| task.passed).count()
+    }
+
+    pub fn pass_rate(&self) -> f64 {
+        if self.tasks.is_empty() {
+            return 0.0;
+        }
+        self.passed() as f64 / self.tasks.len() as f64
+    }
+
+    /// Passed and total runs of each task, by name, for suites run more
+    /// than once.
+    pub fn runs_by_task(&self) -> BTreeMap<&str, (usize, usize)> {
+        let mut runs: BTreeMap<&str, (usize, usize)> = BTreeMap::new();
+        for task in &self.tasks {
+            let (passed, total) = runs.entry(task.name.as_str()).or_default();
+            *passed += usize::from(task.passed);
+            *total += 1;
+        }
+        runs
+    }
+
+    /// The chance that at least one of `k` runs of a task passes, averaged
+    /// over tasks and estimated without bias from all of each task's runs.
+    /// `None` if a task has fewer than `k` runs.
+    pub fn pass_at_k(&self, k: usize) -> Option<f64> {
+        let runs = self.runs_by_task();
+        if k == 0 || runs.is_empty() || runs.values().any(|(_, total)| *total < k) {
+            return None;
+        }
+        // 1 - C(n - c, k) / C(n, k), as a product to stay in range.
+        let sum: f64 = runs
+            .values()
+            .map(|&(passed, total)| {
+                let failing_only: f64 = (total - passed + 1..=total)
+                    .map(|n| 1.0 - k as f64 / n as f64)
+                    .product();
+                1.0 - failing_only.max(0.0)
+            })
+            .sum();
+        Some(sum / runs.len() as f64)
+    }
+
+    /// The fewest runs of any task.
+    pub fn min_runs(&self) -> usize {
+        self.runs_by_task().values().map(|(_, total)| *total).min().unwrap_or(0)
+    }
+
+    pub fn render_table(&self) -> String {
+        let mut out = format!(
+            "{:<32} {:<6} {:>6} {:>10} {:>10} {:>10} {:>9} {:>9}\n",
+            "task", "result", "steps", "in tok", "out tok", "think tok", "cost", "time"
+        );
+
+        for task in &self.tasks {
+            let cost = task
+                .cost
+                .map(|cost| format!("${:.4}", cost))
+                .unwrap_or_else(|| "-".to_string());
+            out.push_str(&format!(
+                "{:<32} {:<6} {:>6} {:>10} {:>10} {:>10} {:>9} {:>8.1}s\n",
+                task.name,
+                if task.passed { "pass" } else { "FAIL" },
+                task.steps,
+                task.input_tokens,
+                task.output_tokens,
+                task.reasoning_tokens,
+                cost,
+                task.wall_time_secs,
+            ));
+            if let Some(error) = &task.error {
+                out.push_str(&format!("    {}\n", error));
+            }
+        }
+
+        let runs = self.runs_by_task();
+        if runs.len() < self.tasks.len() {
+            out.push('\n');
+            for (name, (passed, total)) in &runs {
+                out.push_str(&format!("{:<32} {}/{} passed\n", name, passed, total));
+            }
+            let k = self.min_runs();
+            if let Some(pass_at_k) = self.pass_at_k(k) {
+                out.push_str(&format!("\npass@{}: {:.1}%\n", k, pass_at_k * 100.0));
+            }
+        }
+
+        out.push_str(&format!(
+            "\n{}/{} passed ({:.1}%)\n",
+            self.passed(),
+            self.tasks.len(),
+            self.pass_rate() * 100.0
+        ));
+        out
+    }
+}
+
+/// Spaces requests to the wrapped client evenly, so concurrent tasks share
+/// one request rate between them.
+struct RateLimitedClient {
+    inner: Arc<dyn LLMClient>,
+    interval: Duration,
+    /// When the next request may be sent.
+    next: Mutex<Instant>,
+}
+
+impl RateLimitedClient {
+    fn new(inner: Arc<dyn LLMClient>, requests_per_minute: u32) -> Self {
+        Self {
+            inner,
+            interval: Duration::from_secs(60) / requests_per_minute.max(1),
+            next: Mutex::new(Instant::now()),
+        }
+    }
+}
+
+#[async_trait]
+impl LLMClient for RateLimitedClient {
+    async fn stream_complete(
+        &self,
+        messages: &[Message],
+        tools: &[ToolDefinition],
+    ) -> Result<Pin<Box<dyn Stream<Item = Result<StreamChunk, LLMError>> + Send>>, LLMError> {
+        let slot = {
+            let mut next = self.next.lock().unwrap_or_else(|e| e.into_inner());
+            let slot = (*next).max(Instant::now());
+            *next = slot + self.interval;
+            slot
+        };
+        tokio::time::sleep_until(slot.into()).await;
+        self.inner.stream_complete(messages, tools).await
+    }
+
+    fn model_info(&self) -> ModelInfo {
+        self.inner.model_info()
+    }
+}
+
+/// Builds the agent for one run of a task from the client, tools,
+/// working directory and step limit the runner gives it.
+pub type AgentFactory =
+    Box<dyn Fn(Box<dyn LLMClient>, ToolManager, PathBuf, Option<usize>) -> ReactAgent + Send + Sync>;
+
+pub struct EvalRunner {
+    client: Arc<dyn LLMClient>,
+    factory: AgentFactory,
+    pricing: Option<Pricing>,
+    max_steps: Option<usize>,
+    network: NetworkPolicy,
+    limits: ResourceLimits,
+    command_timeout: Duration,
+    concurrency: usize,
+    repeat: usize,
+}
+
+impl EvalRunner {
+    pub fn new(client: Arc<dyn LLMClient>) -> Self {
+        Self {
+            client,
+            factory: Box::new(|client, tools, workdir, max_steps| {
+                ReactAgent::new(client, tools, workdir, max_steps, Some(true), None)
+            }),
+            pricing: None,
+            max_steps: None,
+            network: NetworkPolicy::default(),
+            limits: ResourceLimits::default(),
+            command_timeout: DEFAULT_COMMAND_TIMEOUT,
+            concurrency: 1,
+            repeat: 1,
+        }
+    }
+
+    /// Builds each run's agent with `factory`, e.g. to set it up as the
+    /// config says, rather than with the defaults.
+    pub fn with_agent_factory(mut self, factory: AgentFactory) -> Self {
+        self.factory = factory;
+        self
+    }
+
+    pub fn with_pricing(mut self, pricing: Pricing) -> Self {
+        self.pricing = Some(pricing);
+        self
+    }
+
+    /// Step limit for tasks that don't set their own.
+    pub fn with_max_steps(mut self, max_steps: Option<usize>) -> Self {
+        self.max_steps = max_steps;
+        self
+    }
+
+    /// Network access for the agent's commands.
+    pub fn with_network(mut self, network: NetworkPolicy) -> Self {
+        self.network = network;
+        self
+    }
+
+    /// Limits on the agent's commands for tasks that don't set their own.
+    pub fn with_limits(mut self, limits: ResourceLimits) -> Self {
+        self.limits = limits;
+        self
+    }
+
+    /// How long each `setup` and `verify` command may run before it is
+    /// killed and the run fails.
+    pub fn with_command_timeout(mut self, timeout: Duration) -> Self {
+        self.command_timeout = timeout;
+        self
+    }
+
+    /// How many tasks run at once, each in its own working directory.
+    pub fn with_concurrency(mut self, concurrency: usize) -> Self {
+        self.concurrency = concurrency.max(1);
+        self
+    }
+
+    /// Runs every task `repeat` times, to see how much the results vary.
+    pub fn with_repeat(mut self, repeat: usize) -> Self {
+        self.repeat = repeat.max(1);
+        self
+    }
+
+    /// Sends at most `requests_per_minute` requests across all tasks.
+    pub fn with_rate_limit(mut self, requests_per_minute: u32) -> Self {
+        self.client = Arc::new(RateLimitedClient::new(self.client, requests_per_minute));
+        self
+    }
+
+    /// Reports are in suite order, with the runs of each task together.
+    pub async fn run(&self, suite: &Suite) -> SuiteReport {
+        let runs = suite
+            .tasks
+            .iter()
+            .flat_map(|task| (1..=self.repeat).map(move |attempt| (task, attempt)));
+        let tasks = futures::stream::iter(runs)
+            .map(|(task, attempt)| async move {
+                tracing::info!("Running eval task {} (run {})", task.name, attempt);
+                let mut report = self.run_task(task, &suite.base_dir).await;
+                report.attempt = attempt;
+                report
+            })
+            .buffered(self.concurrency)
+            .collect()
+            .await;
+        SuiteReport { tasks }
+    }
+
+    pub async fn run_task(&self, task: &EvalTask, base_dir: &Path) -> TaskReport {
+        let mut report = TaskReport {
+            name: task.name.clone(),
+            attempt: 1,
+            passed: false,
+            steps: 0,
+            input_tokens: 0,
+            output_tokens: 0,
+            reasoning_tokens: 0,
+            cost: None,
+            wall_time_secs: 0.0,
+            error: None,
+        };
+
+        let workdir = match prepare_workdir(task, base_dir, self.command_timeout).await {
+            Ok(workdir) => workdir,
+            Err(error) => {
+                report.error = Some(error);
+                return report;
+            }
+        };
+        let path = workdir.path().to_path_buf();
+
+        let tools = default_tools_with_policy(
+            path.clone(),
+            self.network.clone(),
+            task.limits.clone().unwrap_or_else(|| self.limits.clone()),
+        );
+        let client = Box::new(Arc::clone(&self.client));
+        let mut agent = (self.factory)(client, tools, path.clone(), task.max_steps.or(self.max_steps));
+        if self.pricing.is_some() {
+            agent.set_pricing(self.pricing);
+        }
+
+        let started = Instant::now();
+        let outcome = agent.run(&task.prompt).await;
+        report.wall_time_secs = started.elapsed().as_secs_f64();
+
+        report.steps = agent.step_count();
+        match outcome {
+            Ok(result) => {
+                // As the provider reported it, and priced by the model that
+                // answered each request.
+                report.input_tokens = result.usage.input_tokens as usize;
+                report.output_tokens = result.usage.output_tokens as usize;
+                report.reasoning_tokens = result.usage.reasoning_tokens as usize;
+                report.cost = result.cost_usd;
+                if result.stop_reason != StopReason::Finished {
+                    report.error = Some(format!("agent: {}", result.stop_reason));
+                }
+            }
+            Err(e) => report.error = Some(format!("agent: {}", e)),
+        }
+
+        match shell(&task.verify, &path, self.command_timeout).await {
+            Ok(()) => report.passed = true,
+            Err(e) if report.error.is_none() => report.error = Some(format!("verify: {}", e)),
+            Err(_) => {}
+        }
+
+        report
+    }
+}
+
+async fn prepare_workdir(task: &EvalTask, base_dir: &Path, timeout: Duration) -> Result<tempfile::TempDir, String> {
+    let workdir = tempfile::tempdir().map_err(|e| format!("tempdir: {}", e))?;
+
+    if let Some(repo) = &task.repo {
+        let source = base_dir.join(repo);
+        copy_dir(&source, workdir.path()).map_err(|e| format!("copy {}: {}", source.display(), e))?;
+    }
+
+    for command in &task.setup {
+        shell(command, workdir.path(), timeout)
+            .await
+            .map_err(|e| format!("setup: {}", e))?;
+    }
+
+    Ok(workdir)
+}
+
+fn copy_dir(source: &Path, target: &Path) -> std::io::Result<()> {
+    std::fs::create_dir_all(target)?;
+    for entry in std::fs::read_dir(source)? {
+        let entry = entry?;
+        let destination = target.join(entry.file_name());
+        if entry.file_type()?.is_dir() {
+            copy_dir(&entry.path(), &destination)?;
+        } else {
+            std::fs::copy(entry.path(), destination)?;
+        }
+    }
+    Ok(())
+}
+
+/// Runs `command` in `workdir`, killing it after `timeout`.
+async fn shell(command: &str, workdir: &Path, timeout: Duration) -> Result<(), String> {
+    let output = tokio::process::Command::new("sh")
+        .arg("-c")
+        .arg(command)
+        .current_dir(workdir)
+        .kill_on_drop(true)
+        .output();
+    let output = tokio::time::timeout(timeout, output)
+        .await
+        .map_err(|_| format!("`{}` timed out after {}s", command, timeout.as_secs_f64()))?
+        .map_err(|e| e.to_string())?;
+
+    if output.status.success() {
+        return Ok(());
+    }
+
+    Err(format!(
+        "`{}` exited with {}: {}",
+        command,
+        output.status,
+        String::from_utf8_lossy(&output.stderr).trim()
+    ))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::clients::{ChunkType, ScriptedClient, Usage};
+
+    #[test]
+    fn test_load_suite() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("tasks.yaml");
+        std::fs::write(
+            &path,
+            "tasks:\n  - name: greet\n    setup: [\"echo hi > a.txt\"]\n    prompt: Say hi\n    verify: test -f a.txt\n",
+        )
+        .unwrap();
+
+        let suite = Suite::load(&path).unwrap();
+
+        assert_eq!(suite.base_dir, dir.path());
+        assert_eq!(suite.tasks[0].name, "greet");
+        assert_eq!(suite.tasks[0].setup, vec!["echo hi > a.txt"]);
+        assert_eq!(suite.tasks[0].max_steps, None);
+    }
+
+    #[tokio::test]
+    async fn test_run_suite() {
+        let client = ScriptedClient::from_responses([
+            ScriptedClient::tool_call(
+                "write_file",
+                serde_json::json!({"path": "greeting.txt", "content": "hello world"}),
+            ),
+            "FINAL: Done.".to_string(),
+            "FINAL: Nothing to do.".to_string(),
+        ]);
+        let suite = Suite {
+            tasks: vec![
+                EvalTask {
+                    name: "write".to_string(),
+                    repo: None,
+                    setup: vec!["echo hello > greeting.txt".to_string()],
+                    prompt: "Greet the world".to_string(),
+                    verify: "grep -q world greeting.txt".to_string(),
+                    max_steps: Some(5),
+                    limits: None,
+                },
+                EvalTask {
+                    name: "lazy".to_string(),
+                    repo: None,
+                    setup: vec![],
+                    prompt: "Create missing.txt".to_string(),
+                    verify: "test -f missing.txt".to_string(),
+                    max_steps: Some(5),
+                    limits: None,
+                },
+            ],
+            base_dir: PathBuf::from("."),
+        };
+
+        let report = EvalRunner::new(Arc::new(client))
+            .with_pricing(Pricing {
+                input_per_mtok: 1_000_000.0,
+                output_per_mtok: 0.0,
+            })
+            .run(&suite)
+            .await;
+
+        assert!(report.tasks[0].passed);
+        assert_eq!(report.tasks[0].steps, 2);
+        // The script reports no usage, so there is nothing to bill.
+        assert_eq!(report.tasks[0].input_tokens, 0);
+        assert_eq!(report.tasks[0].cost, Some(0.0));
+        assert!(!report.tasks[1].passed);
+        assert!(report.tasks[1].error.as_deref().unwrap().starts_with("verify:"));
+        assert_eq!(report.pass_rate(), 0.5);
+    }
+
+    #[tokio::test]
+    async fn test_reported_usage() {
+        let client = ScriptedClient::new(vec![vec![
+            StreamChunk {
+                content: "weighing options".to_string(),
+                chunk_type: ChunkType::Reasoning,
+                delta: true,
+            },
+            StreamChunk::content("FINAL: Done."),
+            StreamChunk::usage(&Usage {
+                input_tokens: 1200,
+                output_tokens: 7,
+                reasoning_tokens: 300,
+            }),
+            StreamChunk::done(),
+        ]]);
+        let task = EvalTask {
+            name: "usage".to_string(),
+            repo: None,
+            setup: vec![],
+            prompt: "Say done".to_string(),
+            verify: "true".to_string(),
+            max_steps: Some(1),
+            limits: None,
+        };
+
+        let report = EvalRunner::new(Arc::new(client))
+            .with_pricing(Pricing {
+                input_per_mtok: 0.0,
+                output_per_mtok: 1_000_000.0,
+            })
+            .run_task(&task, Path::new("."))
+            .await;
+
+        assert_eq!(report.input_tokens, 1200);
+        assert_eq!(report.output_tokens, 7);
+        assert_eq!(report.reasoning_tokens, 300);
+        assert_eq!(report.cost, Some(307.0));
+    }
+
+    #[tokio::test]
+    async fn test_repeat_concurrently() {
+        let client = ScriptedClient::from_responses(["FINAL: Done."; 6]);
+        let task = |name: &str, verify: &str| EvalTask {
+            name: name.to_string(),
+            repo: None,
+            // Fails unless every run has a directory of its own.
+            setup: vec!["test ! -f marker && touch marker".to_string()],
+            prompt: "Finish".to_string(),
+            verify: verify.to_string(),
+            max_steps: Some(1),
+            limits: None,
+        };
+        let suite = Suite {
+            tasks: vec![task("pass", "true"), task("fail", "false")],
+            base_dir: PathBuf::from("."),
+        };
+
+        let report = EvalRunner::new(Arc::new(client))
+            .with_concurrency(4)
+            .with_repeat(3)
+            .run(&suite)
+            .await;
+
+        let runs: Vec<_> = report.tasks.iter().map(|task| (task.name.as_str(), task.attempt)).collect();
+        assert_eq!(runs, [("pass", 1), ("pass", 2), ("pass", 3), ("fail", 1), ("fail", 2), ("fail", 3)]);
+        assert!(report.tasks.iter().all(|task| task.steps == 1));
+        assert_eq!(report.passed(), 3);
+        assert_eq!(report.runs_by_task()["fail"], (0, 3));
+        assert!(report.render_table().contains("pass                             3/3 passed\n"));
+        assert_eq!(report.pass_at_k(3), Some(0.5));
+        assert_eq!(report.pass_at_k(4), None);
+        assert!(report.render_table().contains("pass@3: 50.0%"));
+    }
+
+    #[tokio::test]
+    async fn test_command_timeout() {
+        let client = ScriptedClient::from_responses(["FINAL: Done."]);
+        let task = EvalTask {
+            name: "slow".to_string(),
+            repo: None,
+            setup: vec![],
+            prompt: "Finish".to_string(),
+            verify: "sleep 5".to_string(),
+            max_steps: Some(1),
+            limits: None,
+        };
+
+        let started = Instant::now();
+        let report = EvalRunner::new(Arc::new(client))
+            .with_command_timeout(Duration::from_millis(200))
+            .run_task(&task, Path::new("."))
+            .await;
+
+        assert!(started.elapsed() < Duration::from_secs(5));
+        assert!(!report.passed);
+        assert!(report.error.as_deref().unwrap().contains("timed out"));
+    }
+
+    #[tokio::test]
+    async fn test_rate_limit() {
+        let client = RateLimitedClient::new(Arc::new(ScriptedClient::from_responses(["a", "b", "c"])), 600);
+        let started = Instant::now();
+        for _ in 0..3 {
+            let _ = client.stream_complete(&[], &[]).await.unwrap();
+        }
+        assert!(started.elapsed() >= Duration::from_millis(200));
+    }
+}
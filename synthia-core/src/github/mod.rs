@@ -0,0 +1,352 @@
+use crate::core::{Step, final_answer};
+use crate::redact::Redactor;
+use serde::{Deserialize, Serialize};
+use std::path::Path;
+use thiserror::Error;
+
+const DEFAULT_API_BASE: &str = "https://api.github.com";
+const JSON_MEDIA_TYPE: &str = "application/vnd.github+json";
+const DIFF_MEDIA_TYPE: &str = "application/vnd.github.diff";
+/// Actions listed in a pull request body; the rest are only counted.
+const MAX_LISTED_ACTIONS: usize = 50;
+/// The most of an action's path or command a pull request body shows.
+const MAX_ACTION_DETAIL_CHARS: usize = 120;
+/// The most of the agent's summary a pull request body shows.
+const MAX_SUMMARY_CHARS: usize = 4000;
+
+#[derive(Debug, Error)]
+pub enum GitHubError {
+    #[error("Request failed: {0}")]
+    RequestFailed(String),
+    #[error("API error ({0}): {1}")]
+    ApiError(u16, String),
+    #[error("Invalid repository '{0}', expected owner/name")]
+    InvalidRepo(String),
+    #[error("Git command failed: {0}")]
+    GitFailed(String),
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct RepoRef {
+    pub owner: String,
+    pub name: String,
+}
+
+impl RepoRef {
+    pub fn parse(spec: &str) -> Result<Self, GitHubError> {
+        let spec = spec
+            .trim()
+            .trim_start_matches("https://github.com/")
+            .trim_end_matches(".git")
+            .trim_end_matches('/');
+        match spec.split_once('/') {
+            Some((owner, name)) if !owner.is_empty() && !name.is_empty() && !name.contains('/') => {
+                Ok(Self {
+                    owner: owner.to_string(),
+                    name: name.to_string(),
+                })
+            }
+            _ => Err(GitHubError::InvalidRepo(spec.to_string())),
+        }
+    }
+}
+
+pub fn parse_pull_request_url(url: &str) -> Result<(RepoRef, u64), GitHubError> {
+    let invalid = || GitHubError::InvalidRepo(url.to_string());
+    let path = url
+        .trim()
+        .trim_start_matches("https://github.com/")
+        .trim_end_matches('/');
+    let mut parts = path.split('/');
+    match (parts.next(), parts.next(), parts.next(), parts.next()) {
+        (Some(owner), Some(name), Some("pull"), Some(number)) => {
+            let repo = RepoRef::parse(&format!("{}/{}", owner, name))?;
+            let number = number.parse().map_err(|_| invalid())?;
+            Ok((repo, number))
+        }
+        _ => Err(invalid()),
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct Issue {
+    pub number: u64,
+    pub title: String,
+    #[serde(default)]
+    pub body: Option<String>,
+    pub html_url: String,
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct NewPullRequest {
+    pub title: String,
+    pub head: String,
+    pub base: String,
+    pub body: String,
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct PullRequest {
+    pub number: u64,
+    pub html_url: String,
+}
+
+pub struct GitHubClient {
+    token: String,
+    api_base: String,
+    client: reqwest::Client,
+}
+
+impl GitHubClient {
+    pub fn new(token: String, api_base: Option<String>) -> Self {
+        Self {
+            token,
+            api_base: api_base.unwrap_or_else(|| DEFAULT_API_BASE.to_string()),
+            client: crate::http::shared_client(),
+        }
+    }
+
+    /// Uses `client` instead of the process-wide shared one.
+    pub fn with_http_client(mut self, client: reqwest::Client) -> Self {
+        self.client = client;
+        self
+    }
+
+    fn request(&self, method: reqwest::Method, path: &str, accept: &str) -> reqwest::RequestBuilder {
+        self.client
+            .request(method, format!("{}{}", self.api_base, path))
+            .header("Authorization", format!("Bearer {}", self.token))
+            .header("Accept", accept)
+            .header("X-GitHub-Api-Version", "2022-11-28")
+            .header("User-Agent", "synthia-agent")
+    }
+
+    async fn send<T: serde::de::DeserializeOwned>(
+        request: reqwest::RequestBuilder,
+    ) -> Result<T, GitHubError> {
+        let response = request
+            .send()
+            .await
+            .map_err(|e| GitHubError::RequestFailed(e.to_string()))?;
+
+        let status = response.status();
+        if !status.is_success() {
+            let body = response.text().await.unwrap_or_default();
+            return Err(GitHubError::ApiError(status.as_u16(), body));
+        }
+
+        response
+            .json()
+            .await
+            .map_err(|e| GitHubError::RequestFailed(e.to_string()))
+    }
+
+    pub async fn fetch_issue(&self, repo: &RepoRef, number: u64) -> Result<Issue, GitHubError> {
+        let path = format!("/repos/{}/{}/issues/{}", repo.owner, repo.name, number);
+        Self::send(self.request(reqwest::Method::GET, &path, JSON_MEDIA_TYPE)).await
+    }
+
+    pub async fn fetch_pull_request_diff(
+        &self,
+        repo: &RepoRef,
+        number: u64,
+    ) -> Result<String, GitHubError> {
+        let path = format!("/repos/{}/{}/pulls/{}", repo.owner, repo.name, number);
+        let response = self
+            .request(reqwest::Method::GET, &path, DIFF_MEDIA_TYPE)
+            .send()
+            .await
+            .map_err(|e| GitHubError::RequestFailed(e.to_string()))?;
+
+        let status = response.status();
+        let body = response
+            .text()
+            .await
+            .map_err(|e| GitHubError::RequestFailed(e.to_string()))?;
+        if !status.is_success() {
+            return Err(GitHubError::ApiError(status.as_u16(), body));
+        }
+        Ok(body)
+    }
+
+    pub async fn create_pull_request(
+        &self,
+        repo: &RepoRef,
+        pull_request: &NewPullRequest,
+    ) -> Result<PullRequest, GitHubError> {
+        let path = format!("/repos/{}/{}/pulls", repo.owner, repo.name);
+        Self::send(self.request(reqwest::Method::POST, &path, JSON_MEDIA_TYPE).json(pull_request)).await
+    }
+}
+
+pub fn branch_name(issue: &Issue) -> String {
+    format!("synthia/issue-{}", issue.number)
+}
+
+pub fn build_issue_task(issue: &Issue) -> String {
+    format!(
+        "Fix GitHub issue #{}: {}\n\n{}\n\nMake the necessary code changes in the working directory and verify them. Do not commit or push; that is handled for you.",
+        issue.number,
+        issue.title,
+        issue.body.as_deref().unwrap_or("(no description)").trim()
+    )
+}
+
+/// The pull request body for `issue`: the agent's summary and the tools
+/// it called, each with only its path or command. Everything in it goes
+/// through `redactor`, since the body is public.
+pub fn build_pull_request_body(issue: &Issue, steps: &[Step], redactor: &Redactor) -> String {
+    let summary = final_answer(steps).unwrap_or_else(|| format!("Automated fix for {}.", issue.title));
+
+    let actions: Vec<String> = steps
+        .iter()
+        .filter(|step| !step.action.is_empty())
+        .map(|step| {
+            let detail = ["path", "command"]
+                .iter()
+                .find_map(|key| step.action_input.get(*key).and_then(|value| value.as_str()));
+            match detail {
+                Some(detail) => {
+                    let detail = redactor.redact(detail).replace('\n', " ").replace('`', "'");
+                    let detail = clip(&detail, MAX_ACTION_DETAIL_CHARS);
+                    format!("- `{}` `{}`", step.action, detail)
+                }
+                None => format!("- `{}`", step.action),
+            }
+        })
+        .collect();
+
+    let summary = clip(&redactor.redact(&summary), MAX_SUMMARY_CHARS);
+    let mut body = format!("Fixes #{}\n\n{}\n", issue.number, summary);
+    if !actions.is_empty() {
+        body.push_str("\n<details><summary>Agent actions</summary>\n\n");
+        body.push_str(&actions[..actions.len().min(MAX_LISTED_ACTIONS)].join("\n"));
+        if actions.len() > MAX_LISTED_ACTIONS {
+            body.push_str(&format!("\n- …and {} more", actions.len() - MAX_LISTED_ACTIONS));
+        }
+        body.push_str("\n\n</details>\n");
+    }
+    body
+}
+
+/// `text` cut to `max` characters, with an ellipsis if anything was.
+fn clip(text: &str, max: usize) -> String {
+    match text.char_indices().nth(max) {
+        Some((end, _)) => format!("{}…", &text[..end]),
+        None => text.to_string(),
+    }
+}
+
+pub async fn git(workdir: &Path, args: &[&str]) -> Result<String, GitHubError> {
+    let output = tokio::process::Command::new("git")
+        .args(args)
+        .current_dir(workdir)
+        .output()
+        .await
+        .map_err(|e| GitHubError::GitFailed(e.to_string()))?;
+
+    if !output.status.success() {
+        return Err(GitHubError::GitFailed(format!(
+            "git {}: {}",
+            args.join(" "),
+            String::from_utf8_lossy(&output.stderr).trim()
+        )));
+    }
+
+    Ok(String::from_utf8_lossy(&output.stdout).trim().to_string())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn issue() -> Issue {
+        Issue {
+            number: 42,
+            title: "Crash on empty input".to_string(),
+            body: Some("Steps to reproduce...".to_string()),
+            html_url: "https://github.com/acme/widgets/issues/42".to_string(),
+        }
+    }
+
+    #[test]
+    fn test_repo_ref_parse() {
+        let repo = RepoRef::parse("acme/widgets").unwrap();
+        assert_eq!(repo.owner, "acme");
+        assert_eq!(repo.name, "widgets");
+
+        let repo = RepoRef::parse("https://github.com/acme/widgets.git").unwrap();
+        assert_eq!(repo.name, "widgets");
+
+        assert!(RepoRef::parse("widgets").is_err());
+        assert!(RepoRef::parse("a/b/c").is_err());
+    }
+
+    #[test]
+    fn test_parse_pull_request_url() {
+        let (repo, number) = parse_pull_request_url("https://github.com/acme/widgets/pull/7").unwrap();
+        assert_eq!(repo.owner, "acme");
+        assert_eq!(number, 7);
+
+        assert!(parse_pull_request_url("https://github.com/acme/widgets/issues/7").is_err());
+    }
+
+    #[test]
+    fn test_build_issue_task() {
+        let task = build_issue_task(&issue());
+        assert!(task.contains("#42"));
+        assert!(task.contains("Crash on empty input"));
+        assert!(task.contains("Steps to reproduce"));
+        assert_eq!(branch_name(&issue()), "synthia/issue-42");
+    }
+
+    #[test]
+    fn test_build_pull_request_body() {
+        let mut steps = vec![
+            Step::new(
+                "Look at the parser".to_string(),
+                "read_file".to_string(),
+                serde_json::json!({"path": "src/parse.rs"}),
+                "...".to_string(),
+                String::new(),
+            ),
+            Step::new(
+                "Write the fix".to_string(),
+                "write_file".to_string(),
+                serde_json::json!({"path": "src/parse.rs", "content": "fn parse() {}"}),
+                "...".to_string(),
+                String::new(),
+            ),
+            Step::new(
+                "Run the tests".to_string(),
+                "run_command".to_string(),
+                serde_json::json!({"command": format!("API_KEY=sk-abcdefghijklmnopqrstuvwx cargo test {}", "x".repeat(200))}),
+                "...".to_string(),
+                String::new(),
+            ),
+            Step::new(
+                "FINAL: Guarded the empty-input case.".to_string(),
+                String::new(),
+                serde_json::json!({}),
+                String::new(),
+                String::new(),
+            ),
+        ];
+
+        let body = build_pull_request_body(&issue(), &steps, &Redactor::default());
+        assert!(body.starts_with("Fixes #42"));
+        assert!(body.contains("Guarded the empty-input case."));
+        assert!(body.contains("- `read_file` `src/parse.rs`"));
+        assert!(body.contains("- `write_file` `src/parse.rs`"));
+        assert!(!body.contains("fn parse"));
+        assert!(!body.contains("sk-abcdefghijklmnopqrstuvwx"));
+        assert!(body.contains("cargo test xxx"));
+        assert!(!body.contains(&"x".repeat(200)));
+
+        let step = steps.remove(0);
+        let steps = vec![step; MAX_LISTED_ACTIONS + 3];
+        let body = build_pull_request_body(&issue(), &steps, &Redactor::default());
+        assert_eq!(body.matches("- `read_file`").count(), MAX_LISTED_ACTIONS);
+        assert!(body.contains("…and 3 more"));
+    }
+}
@@ -0,0 +1,94 @@
+use crate::clients::Message;
+use std::borrow::Cow;
+
+/// What a [`Guardrail`] decided about a piece of content.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Verdict {
+    Allow,
+    /// Use this text instead, e.g. with personal data masked.
+    Redact(String),
+    /// Let the content through with a note for the model.
+    Annotate(String),
+    /// Stop the run, for this reason.
+    Block(String),
+}
+
+/// A content policy hook. The agent checks every request before it leaves
+/// and every model response before acting on it.
+///
+/// Outbound verdicts change only what is sent; the conversation kept by the
+/// agent stays as it was. An outbound annotation is appended to the
+/// message. An inbound redaction replaces the response before it is parsed,
+/// and an inbound annotation is added to the conversation after it.
+pub trait Guardrail: Send + Sync {
+    /// Checks one message of an outgoing request.
+    fn check_outbound(&self, _message: &Message) -> Verdict {
+        Verdict::Allow
+    }
+
+    /// Checks one complete model response.
+    fn check_inbound(&self, _output: &str) -> Verdict {
+        Verdict::Allow
+    }
+}
+
+/// Runs `guardrail` over a request. Nothing is copied when every message is
+/// allowed. Returns the reason if any message is blocked.
+pub fn check_outbound<'a>(guardrail: &dyn Guardrail, messages: &'a [Message]) -> Result<Cow<'a, [Message]>, String> {
+    let mut checked: Option<Vec<Message>> = None;
+    for (i, message) in messages.iter().enumerate() {
+        let content = match guardrail.check_outbound(message) {
+            Verdict::Allow => continue,
+            Verdict::Redact(content) => content,
+            Verdict::Annotate(note) => format!("{}\n\n[Note: {}]", message.content, note),
+            Verdict::Block(reason) => return Err(reason),
+        };
+        checked.get_or_insert_with(|| messages.to_vec())[i].content = content;
+    }
+    Ok(match checked {
+        Some(messages) => Cow::Owned(messages),
+        None => Cow::Borrowed(messages),
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::clients::MessageRole;
+
+    struct NoEmails;
+
+    impl Guardrail for NoEmails {
+        fn check_outbound(&self, message: &Message) -> Verdict {
+            if message.content.contains("CONFIDENTIAL") {
+                Verdict::Block("confidential content".to_string())
+            } else if message.content.contains('@') {
+                Verdict::Redact(message.content.replace("alice@example.com", "[email]"))
+            } else {
+                Verdict::Allow
+            }
+        }
+    }
+
+    fn user(content: &str) -> Message {
+        Message {
+            role: MessageRole::User,
+            content: content.to_string(),
+            tool_calls: None,
+        }
+    }
+
+    #[test]
+    fn test_check_outbound() {
+        let clean = [user("hello")];
+        assert!(matches!(check_outbound(&NoEmails, &clean), Ok(Cow::Borrowed(_))));
+
+        let messages = [user("hello"), user("mail alice@example.com")];
+        let checked = check_outbound(&NoEmails, &messages).unwrap();
+        assert_eq!(checked[0].content, "hello");
+        assert_eq!(checked[1].content, "mail [email]");
+
+        let messages = [user("CONFIDENTIAL roadmap")];
+        assert_eq!(check_outbound(&NoEmails, &messages).unwrap_err(), "confidential content");
+    }
+}
@@ -0,0 +1,153 @@
+use crate::core::StopReason;
+use serde::{Deserialize, Serialize};
+use std::path::Path;
+use std::process::Stdio;
+use thiserror::Error;
+use tokio::io::AsyncWriteExt;
+
+/// The tools whose calls are reported to `on_file_write` hooks. Edits are
+/// reported with the whole file as it would be after them.
+pub const WRITE_FILE_TOOL: &str = "write_file";
+pub const EDIT_FILE_TOOL: &str = "edit_file";
+
+#[derive(Debug, Error)]
+pub enum HookError {
+    #[error("Could not run hook `{0}`: {1}")]
+    Spawn(String, String),
+    /// The hook exited unsuccessfully, with its stderr or exit status.
+    #[error("Hook `{0}` failed: {1}")]
+    Failed(String, String),
+}
+
+/// Shell commands run on lifecycle events, from the config's `hooks`
+/// section. Each gets the event as JSON on stdin and runs in the working
+/// directory, in order; the first to fail vetoes the action.
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct Hooks {
+    /// Before a run. A failure stops the run before anything is sent.
+    pub on_run_start: Vec<String>,
+    /// Before each `write_file` or `edit_file` call that is about to run,
+    /// within the tool's time limit. A failure or timeout skips the write
+    /// and tells the model why.
+    pub on_file_write: Vec<String>,
+    /// After a run. Failures are logged, since there is nothing left to
+    /// stop.
+    pub on_run_end: Vec<String>,
+}
+
+/// What a hook is told, tagged with `event`.
+#[derive(Debug, Clone, PartialEq, Serialize)]
+#[serde(tag = "event", rename_all = "snake_case")]
+pub enum HookEvent<'a> {
+    RunStart {
+        task: &'a str,
+        working_dir: &'a Path,
+    },
+    FileWrite {
+        path: &'a str,
+        content: &'a str,
+    },
+    /// `stop_reason` is `None` when the run ended with an error.
+    RunEnd {
+        steps: usize,
+        stop_reason: Option<StopReason>,
+        error: Option<String>,
+    },
+}
+
+impl Hooks {
+    pub fn is_empty(&self) -> bool {
+        self.on_run_start.is_empty() && self.on_file_write.is_empty() && self.on_run_end.is_empty()
+    }
+
+    /// Runs the hooks for `event` in `working_dir`, stopping at the first
+    /// that fails.
+    pub async fn run(&self, event: &HookEvent<'_>, working_dir: &Path) -> Result<(), HookError> {
+        let commands = match event {
+            HookEvent::RunStart { .. } => &self.on_run_start,
+            HookEvent::FileWrite { .. } => &self.on_file_write,
+            HookEvent::RunEnd { .. } => &self.on_run_end,
+        };
+        if commands.is_empty() {
+            return Ok(());
+        }
+        let input = serde_json::to_string(event).unwrap_or_default();
+        for command in commands {
+            run_hook(command, &input, working_dir).await?;
+        }
+        Ok(())
+    }
+}
+
+async fn run_hook(command: &str, input: &str, working_dir: &Path) -> Result<(), HookError> {
+    let mut child = tokio::process::Command::new("sh")
+        .arg("-c")
+        .arg(command)
+        .current_dir(working_dir)
+        .stdin(Stdio::piped())
+        .stdout(Stdio::null())
+        .stderr(Stdio::piped())
+        .kill_on_drop(true)
+        .spawn()
+        .map_err(|e| HookError::Spawn(command.to_string(), e.to_string()))?;
+
+    if let Some(mut stdin) = child.stdin.take() {
+        // A hook that doesn't read its input closes the pipe early.
+        if let Err(e) = stdin.write_all(input.as_bytes()).await {
+            tracing::debug!("Hook `{}` did not read its input: {}", command, e);
+        }
+    }
+
+    let output = child
+        .wait_with_output()
+        .await
+        .map_err(|e| HookError::Spawn(command.to_string(), e.to_string()))?;
+    if output.status.success() {
+        return Ok(());
+    }
+    let stderr = String::from_utf8_lossy(&output.stderr).trim().to_string();
+    Err(HookError::Failed(
+        command.to_string(),
+        if stderr.is_empty() { output.status.to_string() } else { stderr },
+    ))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[tokio::test]
+    async fn test_run_hooks() {
+        let dir = tempfile::tempdir().unwrap();
+        let hooks = Hooks {
+            on_run_start: vec!["cat > start.json".to_string()],
+            on_file_write: vec![
+                "grep -q '\"path\":\"ok.txt\"' || { echo 'only ok.txt' >&2; exit 1; }".to_string(),
+                "touch checked".to_string(),
+            ],
+            on_run_end: vec![],
+        };
+
+        hooks
+            .run(&HookEvent::RunStart { task: "Fix it", working_dir: dir.path() }, dir.path())
+            .await
+            .unwrap();
+        let start: serde_json::Value =
+            serde_json::from_str(&std::fs::read_to_string(dir.path().join("start.json")).unwrap()).unwrap();
+        assert_eq!(start["event"], "run_start");
+        assert_eq!(start["task"], "Fix it");
+
+        let write = |path| HookEvent::FileWrite { path, content: "" };
+        hooks.run(&write("ok.txt"), dir.path()).await.unwrap();
+        assert!(dir.path().join("checked").exists());
+        std::fs::remove_file(dir.path().join("checked")).unwrap();
+
+        let error = hooks.run(&write("secret.txt"), dir.path()).await.unwrap_err();
+        assert!(matches!(&error, HookError::Failed(_, reason) if reason == "only ok.txt"));
+        assert!(!dir.path().join("checked").exists());
+
+        let end = HookEvent::RunEnd { steps: 1, stop_reason: None, error: None };
+        hooks.run(&end, dir.path()).await.unwrap();
+    }
+}
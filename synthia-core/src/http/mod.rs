@@ -0,0 +1,35 @@
+use std::sync::OnceLock;
+use std::time::Duration;
+
+const POOL_IDLE_TIMEOUT: Duration = Duration::from_secs(90);
+const POOL_MAX_IDLE_PER_HOST: usize = 8;
+const TCP_KEEPALIVE: Duration = Duration::from_secs(60);
+const HTTP2_KEEPALIVE_INTERVAL: Duration = Duration::from_secs(30);
+const CONNECT_TIMEOUT: Duration = Duration::from_secs(30);
+
+static SHARED: OnceLock<reqwest::Client> = OnceLock::new();
+
+/// A new client tuned for long agent runs: idle connections are kept warm
+/// between steps so each LLM call doesn't pay for a fresh TLS handshake.
+pub fn build_client() -> reqwest::Client {
+    reqwest::Client::builder()
+        .user_agent(concat!("synthia-agent/", env!("CARGO_PKG_VERSION")))
+        .connect_timeout(CONNECT_TIMEOUT)
+        .pool_idle_timeout(POOL_IDLE_TIMEOUT)
+        .pool_max_idle_per_host(POOL_MAX_IDLE_PER_HOST)
+        .tcp_keepalive(TCP_KEEPALIVE)
+        .http2_adaptive_window(true)
+        .http2_keep_alive_interval(HTTP2_KEEPALIVE_INTERVAL)
+        .http2_keep_alive_while_idle(true)
+        .build()
+        .unwrap_or_else(|e| {
+            tracing::warn!("Falling back to a default HTTP client: {}", e);
+            reqwest::Client::new()
+        })
+}
+
+/// The process-wide client every HTTP user shares unless another one is
+/// injected. Clones are cheap handles onto the same connection pool.
+pub fn shared_client() -> reqwest::Client {
+    SHARED.get_or_init(build_client).clone()
+}
@@ -0,0 +1,137 @@
+use super::LedgerError;
+use crate::paths::PROJECT_DIR;
+use std::path::{Path, PathBuf};
+use std::sync::atomic::{AtomicUsize, Ordering};
+
+/// Diffs longer than this are cut.
+const MAX_DIFF_BYTES: usize = 64 * 1024;
+
+/// Snapshots of the working tree taken between steps, so each step that
+/// changes files can carry a diff of what it changed. Snapshots are git
+/// trees written through an index of their own; the repository's index,
+/// refs and stash are left alone.
+#[derive(Debug)]
+pub struct Checkpoints {
+    workdir: PathBuf,
+    index: PathBuf,
+    tree: String,
+}
+
+impl Checkpoints {
+    /// Takes the first snapshot, of the working tree before the run. Fails
+    /// outside a git repository.
+    pub async fn start(workdir: &Path) -> Result<Self, LedgerError> {
+        static NEXT: AtomicUsize = AtomicUsize::new(0);
+
+        let index = std::env::temp_dir().join(format!(
+            "synthia-checkpoint-{}-{}",
+            std::process::id(),
+            NEXT.fetch_add(1, Ordering::Relaxed)
+        ));
+        let mut checkpoints = Self {
+            workdir: workdir.to_path_buf(),
+            index,
+            tree: String::new(),
+        };
+        checkpoints.tree = checkpoints.snapshot().await?;
+        Ok(checkpoints)
+    }
+
+    /// Writes the working tree below `workdir` as a git tree, except the
+    /// agent's own state and git-ignored files. Returns the tree's id.
+    async fn snapshot(&self) -> Result<String, LedgerError> {
+        let exclude = format!(":(exclude){}", PROJECT_DIR);
+        self.git(&["add", "--all", "--", ".", &exclude]).await?;
+        Ok(self.git(&["write-tree"]).await?.trim().to_string())
+    }
+
+    /// The diff of what changed since the last checkpoint, with paths
+    /// relative to `workdir`, and takes a new one. Empty if nothing
+    /// changed.
+    pub async fn diff(&mut self) -> Result<String, LedgerError> {
+        let tree = self.snapshot().await?;
+        if tree == self.tree {
+            return Ok(String::new());
+        }
+        let mut diff = self
+            .git(&["diff", "--no-color", "--no-ext-diff", "--relative", &self.tree, &tree])
+            .await?;
+        self.tree = tree;
+
+        if diff.len() > MAX_DIFF_BYTES {
+            let mut end = MAX_DIFF_BYTES;
+            while !diff.is_char_boundary(end) {
+                end -= 1;
+            }
+            diff.truncate(end);
+            diff.push_str("\n[diff cut; see git diff for the rest]\n");
+        }
+        Ok(diff)
+    }
+
+    async fn git(&self, args: &[&str]) -> Result<String, LedgerError> {
+        let output = tokio::process::Command::new("git")
+            .args(args)
+            .env("GIT_INDEX_FILE", &self.index)
+            .current_dir(&self.workdir)
+            .output()
+            .await
+            .map_err(|e| LedgerError::Git(e.to_string()))?;
+
+        if !output.status.success() {
+            return Err(LedgerError::Git(format!(
+                "git {}: {}",
+                args.join(" "),
+                String::from_utf8_lossy(&output.stderr).trim()
+            )));
+        }
+        Ok(String::from_utf8_lossy(&output.stdout).to_string())
+    }
+}
+
+impl Drop for Checkpoints {
+    fn drop(&mut self) {
+        let _ = std::fs::remove_file(&self.index);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[tokio::test]
+    async fn test_checkpoint_diffs() {
+        let dir = tempfile::tempdir().unwrap();
+        let root = dir.path();
+        let git = |args: &[&str]| {
+            let status = std::process::Command::new("git").args(args).current_dir(root).output().unwrap().status;
+            assert!(status.success());
+        };
+        git(&["init", "-q"]);
+        std::fs::create_dir_all(root.join("app/.synthia")).unwrap();
+        std::fs::write(root.join("app/lib.rs"), "fn a() {}\n").unwrap();
+        std::fs::write(root.join("README.md"), "outside\n").unwrap();
+
+        let mut checkpoints = Checkpoints::start(&root.join("app")).await.unwrap();
+        assert_eq!(checkpoints.diff().await.unwrap(), "");
+
+        std::fs::write(root.join("app/lib.rs"), "fn b() {}\n").unwrap();
+        std::fs::write(root.join("app/.synthia/state.json"), "{}").unwrap();
+        std::fs::write(root.join("README.md"), "changed\n").unwrap();
+        let diff = checkpoints.diff().await.unwrap();
+        assert!(diff.starts_with("diff --git a/lib.rs b/lib.rs\n"));
+        assert!(diff.contains("-fn a() {}\n+fn b() {}\n"));
+        assert!(!diff.contains("state.json") && !diff.contains("README"));
+
+        std::fs::write(root.join("app/new.rs"), "fn c() {}\n").unwrap();
+        let diff = checkpoints.diff().await.unwrap();
+        assert!(diff.contains("new file mode") && diff.contains("+fn c() {}"));
+        assert!(!diff.contains("lib.rs"));
+
+        let index = checkpoints.index.clone();
+        drop(checkpoints);
+        assert!(!index.exists());
+        // The repository's own index was never written.
+        assert!(!root.join(".git/index").exists());
+    }
+}
@@ -0,0 +1,265 @@
+use crate::core::{Step, StepStatus};
+use crate::paths::PROJECT_DIR;
+use crate::tools::ToolOutput;
+use serde::{Deserialize, Serialize};
+use std::collections::BTreeMap;
+use std::path::Path;
+use thiserror::Error;
+
+mod checkpoint;
+
+pub use checkpoint::Checkpoints;
+
+#[derive(Debug, Error)]
+pub enum LedgerError {
+    #[error("git failed: {0}")]
+    Git(String),
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum ChangeKind {
+    Created,
+    Modified,
+    Deleted,
+}
+
+impl ChangeKind {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            ChangeKind::Created => "created",
+            ChangeKind::Modified => "modified",
+            ChangeKind::Deleted => "deleted",
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct FileChange {
+    /// Relative to the working directory.
+    pub path: String,
+    pub kind: ChangeKind,
+    /// The 1-based step that last wrote the file, or `None` if the change
+    /// was only seen in `git status`.
+    pub step: Option<usize>,
+}
+
+/// Porcelain `git status` codes by path, relative to the working directory.
+pub type GitStatus = BTreeMap<String, String>;
+
+/// Every file a run created, modified or deleted.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct ChangeLedger {
+    changes: BTreeMap<String, FileChange>,
+}
+
+impl ChangeLedger {
+    /// The files written by successful `write_file` and `edit_file` steps.
+    pub fn from_steps(steps: &[Step]) -> Self {
+        let mut ledger = Self::default();
+        for (i, step) in steps.iter().enumerate() {
+            if !matches!(step.action.as_str(), "write_file" | "edit_file") || step.status != StepStatus::Success {
+                continue;
+            }
+            let Some(path) = step.action_input.get("path").and_then(|path| path.as_str()) else {
+                continue;
+            };
+            let result = ToolOutput::from_recorded(serde_json::from_str(&step.observation).unwrap_or_default());
+            // A write refused because of a conflict changed nothing.
+            if !result.success {
+                continue;
+            }
+            let created = result.data.get("created").and_then(|created| created.as_bool()).unwrap_or(false);
+            let kind = if created { ChangeKind::Created } else { ChangeKind::Modified };
+            ledger.record(path, kind, Some(i + 1));
+        }
+        ledger
+    }
+
+    /// Adds a change, folding it into any earlier change to the same file.
+    pub fn record(&mut self, path: &str, kind: ChangeKind, step: Option<usize>) {
+        let path = path.trim_start_matches("./").to_string();
+        let kind = match (self.changes.get(&path).map(|change| change.kind), kind) {
+            (Some(ChangeKind::Created), ChangeKind::Deleted) => {
+                self.changes.remove(&path);
+                return;
+            }
+            (Some(ChangeKind::Created), _) => ChangeKind::Created,
+            (Some(ChangeKind::Deleted), ChangeKind::Created) => ChangeKind::Modified,
+            (_, kind) => kind,
+        };
+        self.changes.insert(path.clone(), FileChange { path, kind, step });
+    }
+
+    /// Adds what `git status` shows changed between `before` and `after`,
+    /// which catches files changed by commands rather than by `write_file`.
+    /// A file that was already dirty before the run and only changed by a
+    /// command is not noticed.
+    pub fn add_git_changes(&mut self, before: &GitStatus, after: &GitStatus) {
+        for (path, code) in after {
+            // The agent's own state changes on every run and is never reported.
+            if before.get(path) == Some(code) || Path::new(path).starts_with(PROJECT_DIR) {
+                continue;
+            }
+            let kind = if code == "??" || code.contains('A') {
+                ChangeKind::Created
+            } else if code.contains('D') {
+                ChangeKind::Deleted
+            } else {
+                ChangeKind::Modified
+            };
+            match self.changes.get(path) {
+                Some(_) if kind != ChangeKind::Deleted => {}
+                _ => self.record(path, kind, None),
+            }
+        }
+    }
+
+    /// Drops or marks files that no longer exist under `workdir`, e.g.
+    /// because a command removed them after they were written.
+    pub fn settle(&mut self, workdir: &Path) {
+        let gone: Vec<String> = self
+            .changes
+            .values()
+            .filter(|change| change.kind != ChangeKind::Deleted && !workdir.join(&change.path).exists())
+            .map(|change| change.path.clone())
+            .collect();
+        for path in gone {
+            self.record(&path, ChangeKind::Deleted, None);
+        }
+    }
+
+    pub fn changes(&self) -> impl Iterator<Item = &FileChange> {
+        self.changes.values()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.changes.is_empty()
+    }
+
+    /// `git diff --stat` for the changed files, with `full` the whole diff.
+    /// New files are diffed against an empty file, since git does not
+    /// track them yet.
+    pub async fn diff(&self, workdir: &Path, full: bool) -> Result<String, LedgerError> {
+        let mode = if full { "--patch" } else { "--stat" };
+        let mut tracked = vec!["diff", mode, "HEAD", "--"];
+        let mut out = String::new();
+        for change in self.changes() {
+            if change.kind != ChangeKind::Created {
+                tracked.push(&change.path);
+                continue;
+            }
+            let args = ["diff", mode, "--no-index", "--", "/dev/null", change.path.as_str()];
+            out.push_str(&git(workdir, &args).await?);
+        }
+        if tracked.len() > 4 {
+            out.insert_str(0, &git(workdir, &tracked).await?);
+        }
+        Ok(out)
+    }
+}
+
+/// The working tree's status, with paths relative to `workdir`. Paths
+/// outside it are left out.
+pub async fn git_status(workdir: &Path) -> Result<GitStatus, LedgerError> {
+    let prefix = git(workdir, &["rev-parse", "--show-prefix"]).await?;
+    let prefix = prefix.trim();
+    let output = git(workdir, &["status", "--porcelain=v1", "-z", "--untracked-files=all"]).await?;
+    Ok(parse_status(&output, prefix))
+}
+
+fn parse_status(output: &str, prefix: &str) -> GitStatus {
+    let mut status = GitStatus::new();
+    let mut entries = output.split('\0').filter(|entry| !entry.is_empty());
+    while let Some(entry) = entries.next() {
+        let (Some(code), Some(path)) = (entry.get(..2), entry.get(3..)) else {
+            continue;
+        };
+        if code.contains('R') || code.contains('C') {
+            // The original path follows as its own entry.
+            if let Some(from) = entries.next().and_then(|from| from.strip_prefix(prefix))
+                && code.contains('R')
+            {
+                status.insert(from.to_string(), " D".to_string());
+            }
+        }
+        if let Some(path) = path.strip_prefix(prefix) {
+            let code = if code.contains('R') || code.contains('C') { "A " } else { code };
+            status.insert(path.to_string(), code.to_string());
+        }
+    }
+    status
+}
+
+/// Runs git in `workdir`. Exit status 1 is accepted, since `git diff
+/// --no-index` uses it to say the files differ.
+async fn git(workdir: &Path, args: &[&str]) -> Result<String, LedgerError> {
+    let output = tokio::process::Command::new("git")
+        .args(args)
+        .current_dir(workdir)
+        .output()
+        .await
+        .map_err(|e| LedgerError::Git(e.to_string()))?;
+
+    if !matches!(output.status.code(), Some(0 | 1)) {
+        return Err(LedgerError::Git(format!(
+            "git {}: {}",
+            args.join(" "),
+            String::from_utf8_lossy(&output.stderr).trim()
+        )));
+    }
+    Ok(String::from_utf8_lossy(&output.stdout).to_string())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn write(path: &str, created: bool) -> Step {
+        Step::new(
+            String::new(),
+            "write_file".to_string(),
+            serde_json::json!({"path": path, "content": ""}),
+            serde_json::json!({"success": true, "path": path, "created": created}).to_string(),
+            String::new(),
+        )
+    }
+
+    #[test]
+    fn test_ledger_folds_changes() {
+        let dir = tempfile::tempdir().unwrap();
+        std::fs::write(dir.path().join("lib.rs"), "").unwrap();
+        std::fs::write(dir.path().join("new.rs"), "").unwrap();
+        std::fs::write(dir.path().join("b.rs"), "").unwrap();
+        let steps = [write("./new.rs", true), write("lib.rs", false), write("new.rs", false), write("tmp.rs", true)];
+
+        let mut ledger = ChangeLedger::from_steps(&steps);
+        let before = parse_status(" M README.md\0?? notes.txt\0", "");
+        let after = parse_status(
+            " M README.md\0?? notes.txt\0 D old.rs\0?? .synthia/sessions/1.json\0R  b.rs\0a.rs\0",
+            "",
+        );
+        ledger.add_git_changes(&before, &after);
+        ledger.settle(dir.path());
+
+        let changes: Vec<(&str, ChangeKind, Option<usize>)> =
+            ledger.changes().map(|c| (c.path.as_str(), c.kind, c.step)).collect();
+        assert_eq!(
+            changes,
+            vec![
+                ("a.rs", ChangeKind::Deleted, None),
+                ("b.rs", ChangeKind::Created, None),
+                ("lib.rs", ChangeKind::Modified, Some(2)),
+                ("new.rs", ChangeKind::Created, Some(3)),
+                ("old.rs", ChangeKind::Deleted, None),
+            ]
+        );
+    }
+
+    #[test]
+    fn test_parse_status_strips_prefix() {
+        let status = parse_status(" M app/src/main.rs\0 M other/x.rs\0", "app/");
+        assert_eq!(status.len(), 1);
+        assert_eq!(status["src/main.rs"], " M");
+    }
+}
@@ -0,0 +1,46 @@
+pub mod clarify;
+pub mod clients;
+pub mod config;
+pub mod context;
+pub mod core;
+#[cfg(feature = "github")]
+pub mod github;
+pub mod guardrail;
+pub mod hooks;
+pub mod tools;
+pub mod http;
+pub mod ledger;
+pub mod project;
+pub mod prompts;
+pub mod proto;
+pub mod protocol;
+pub mod memory;
+pub mod migrate;
+pub mod paths;
+pub mod lsp;
+pub mod mcp;
+pub mod redact;
+pub mod remote;
+pub mod repomap;
+pub mod secrets;
+#[cfg(feature = "semantic-search")]
+pub mod search;
+pub mod session;
+pub mod telemetry;
+#[cfg(feature = "review")]
+pub mod review;
+#[cfg(feature = "eval")]
+pub mod eval;
+#[cfg(feature = "voice")]
+pub mod voice;
+
+pub use clients::{
+    LLMClient, LLMError, Message, MessageRole, OpenAIClient, ReplayClient, ScriptedClient, StreamChunk,
+    ToolDefinition, create_llm_client,
+};
+pub use core::{AgentResult, GateDecision, Quotas, ReactAgent, Step, StepGate, StepStatus, Steering, StopReason, Timeouts};
+pub use tools::{default_tools, read_only_tools, ToolManager, ToolOutput, ToolTrait};
+pub use prompts::build_code_agent_prompt;
+pub use memory::{ContextCompressor, ConversationHistory, ToolResult};
+pub use mcp::{MCPConfig, MCPError, MCPManager};
+pub use redact::Redactor;
@@ -0,0 +1,473 @@
+//! Editor-facing server speaking the LSP base protocol (`Content-Length`
+//! framed JSON-RPC) with custom `synthia/*` requests, so editor plugins can
+//! reuse their language-client plumbing to talk to the agent.
+//!
+//! - `initialize` `{"rootUri"?: string}` → `{"capabilities": {...}}`
+//! - `synthia/fixDiagnostic` `{"textDocument": {"uri"}, "diagnostic":
+//!   {"range", "message"}}` runs the agent on the diagnostic. Every file the
+//!   agent writes is pushed to the editor as a `workspace/applyEdit` request
+//!   while the run is in progress; the response is `{"summary", "steps"}`.
+//! - `synthia/explainSelection` `{"textDocument": {"uri"}, "range"}` runs the
+//!   agent with read-only tools and responds with `{"explanation"}`.
+//! - `shutdown` / `exit` as in LSP.
+
+use crate::core::{AgentResult, ReactAgent, Step, StepCallback, final_answer};
+use crate::hooks::{EDIT_FILE_TOOL, WRITE_FILE_TOOL};
+use crate::proto::{
+    AGENT_ERROR, AgentFactory, INVALID_PARAMS, INVALID_REQUEST, METHOD_NOT_FOUND, PARSE_ERROR,
+    Request, RpcError, SessionOptions,
+};
+use serde::{Deserialize, Serialize};
+use serde_json::{Value, json};
+use std::path::{Path, PathBuf};
+use std::sync::Arc;
+use std::sync::atomic::{AtomicU64, Ordering};
+use tokio::io::{AsyncBufRead, AsyncBufReadExt, AsyncReadExt, AsyncWrite, AsyncWriteExt};
+use tokio::sync::mpsc;
+
+// Lines of surrounding code included with a diagnostic or selection.
+const CONTEXT_LINES: u32 = 20;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub struct Position {
+    pub line: u32,
+    pub character: u32,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub struct Range {
+    pub start: Position,
+    pub end: Position,
+}
+
+#[derive(Debug, Clone, PartialEq, Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct TextDocumentIdentifier {
+    uri: String,
+}
+
+#[derive(Debug, Clone, PartialEq, Deserialize)]
+struct Diagnostic {
+    range: Range,
+    message: String,
+    #[serde(default)]
+    source: Option<String>,
+}
+
+#[derive(Debug, Clone, PartialEq, Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct FixDiagnosticParams {
+    text_document: TextDocumentIdentifier,
+    diagnostic: Diagnostic,
+}
+
+#[derive(Debug, Clone, PartialEq, Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct ExplainSelectionParams {
+    text_document: TextDocumentIdentifier,
+    range: Range,
+}
+
+pub fn uri_to_path(uri: &str) -> PathBuf {
+    PathBuf::from(uri.strip_prefix("file://").unwrap_or(uri))
+}
+
+pub fn path_to_uri(path: &Path) -> String {
+    format!("file://{}", path.display())
+}
+
+fn excerpt(content: &str, range: &Range) -> String {
+    let first = range.start.line.saturating_sub(CONTEXT_LINES);
+    let last = range.end.line.saturating_add(CONTEXT_LINES);
+    content
+        .lines()
+        .enumerate()
+        .filter(|(i, _)| (first..=last).contains(&(*i as u32)))
+        .map(|(i, line)| format!("{:>5} | {}", i + 1, line))
+        .collect::<Vec<_>>()
+        .join("\n")
+}
+
+pub struct LspServer {
+    factory: AgentFactory,
+    root: PathBuf,
+    next_request: Arc<AtomicU64>,
+}
+
+impl LspServer {
+    pub fn new(factory: AgentFactory, root: PathBuf) -> Self {
+        Self {
+            factory,
+            root,
+            next_request: Arc::new(AtomicU64::new(0)),
+        }
+    }
+
+    pub async fn serve<R, W>(&mut self, mut reader: R, mut writer: W) -> std::io::Result<()>
+    where
+        R: AsyncBufRead + Unpin,
+        W: AsyncWrite + Unpin,
+    {
+        while let Some(body) = read_message(&mut reader).await? {
+            let request: Request = match serde_json::from_slice::<Value>(&body) {
+                Err(e) => {
+                    let error = RpcError::new(PARSE_ERROR, e.to_string());
+                    write_message(&mut writer, &error_response(Value::Null, error)).await?;
+                    continue;
+                }
+                // Responses to our workspace/applyEdit requests carry no method.
+                Ok(value) if value.get("method").is_none() => continue,
+                Ok(value) => match serde_json::from_value(value.clone()) {
+                    Ok(request) => request,
+                    Err(e) => {
+                        let id = value.get("id").cloned().unwrap_or(Value::Null);
+                        let error = RpcError::new(INVALID_REQUEST, e.to_string());
+                        write_message(&mut writer, &error_response(id, error)).await?;
+                        continue;
+                    }
+                },
+            };
+
+            if request.method == "exit" {
+                break;
+            }
+
+            let result = match request.method.as_str() {
+                "initialize" => Ok(self.initialize(&request.params)),
+                "initialized" => continue,
+                "shutdown" => Ok(Value::Null),
+                "synthia/fixDiagnostic" => self.fix_diagnostic(&request.params, &mut writer).await?,
+                "synthia/explainSelection" => {
+                    self.explain_selection(&request.params, &mut writer).await?
+                }
+                other => Err(RpcError::new(METHOD_NOT_FOUND, format!("Unknown method: {}", other))),
+            };
+
+            if let Some(id) = request.id {
+                let response = match result {
+                    Ok(result) => json!({"jsonrpc": "2.0", "id": id, "result": result}),
+                    Err(error) => error_response(id, error),
+                };
+                write_message(&mut writer, &response).await?;
+            }
+        }
+
+        Ok(())
+    }
+
+    fn initialize(&mut self, params: &Value) -> Value {
+        if let Some(root) = params.get("rootUri").and_then(|v| v.as_str()) {
+            self.root = uri_to_path(root);
+        }
+
+        json!({
+            "capabilities": {
+                "experimental": {
+                    "synthia": ["synthia/fixDiagnostic", "synthia/explainSelection"]
+                }
+            },
+            "serverInfo": {"name": "synthia-agent"}
+        })
+    }
+
+    async fn fix_diagnostic<W: AsyncWrite + Unpin>(
+        &self,
+        params: &Value,
+        writer: &mut W,
+    ) -> std::io::Result<Result<Value, RpcError>> {
+        let params: FixDiagnosticParams = match serde_json::from_value(params.clone()) {
+            Ok(params) => params,
+            Err(e) => return Ok(Err(RpcError::new(INVALID_PARAMS, e.to_string()))),
+        };
+
+        let path = uri_to_path(&params.text_document.uri);
+        let content = tokio::fs::read_to_string(&path).await.unwrap_or_default();
+        let diagnostic = &params.diagnostic;
+        let task = format!(
+            "Fix the following diagnostic in {} at line {}{}:\n{}\n\nSurrounding code:\n{}\n\nEdit the file with the available tools, keep the change minimal, and respond with FINAL: and a one-sentence summary.",
+            path.display(),
+            diagnostic.range.start.line + 1,
+            diagnostic
+                .source
+                .as_deref()
+                .map(|source| format!(" (reported by {})", source))
+                .unwrap_or_default(),
+            diagnostic.message,
+            excerpt(&content, &diagnostic.range),
+        );
+
+        let mut agent = (self.factory)(
+            &SessionOptions {
+                workdir: self.root.clone(),
+                max_steps: None,
+                read_only: false,
+            },
+            None,
+        );
+        let root = self.root.clone();
+        let next_request = Arc::clone(&self.next_request);
+        let outcome = run_with_notifications(&mut agent, &task, writer, move |step, tx| {
+            if let Some(edit) = workspace_edit_for_step(&root, step) {
+                let id = next_request.fetch_add(1, Ordering::Relaxed);
+                let _ = tx.send(json!({
+                    "jsonrpc": "2.0",
+                    "id": format!("synthia-edit-{}", id),
+                    "method": "workspace/applyEdit",
+                    "params": {"label": "synthia", "edit": edit},
+                }));
+            }
+        })
+        .await?;
+
+        Ok(outcome.map(|result| {
+            json!({
+                "summary": final_answer(&result.steps).or(result.summary),
+                "steps": result.steps.len(),
+            })
+        }))
+    }
+
+    async fn explain_selection<W: AsyncWrite + Unpin>(
+        &self,
+        params: &Value,
+        writer: &mut W,
+    ) -> std::io::Result<Result<Value, RpcError>> {
+        let params: ExplainSelectionParams = match serde_json::from_value(params.clone()) {
+            Ok(params) => params,
+            Err(e) => return Ok(Err(RpcError::new(INVALID_PARAMS, e.to_string()))),
+        };
+
+        let path = uri_to_path(&params.text_document.uri);
+        let content = tokio::fs::read_to_string(&path).await.unwrap_or_default();
+        let task = format!(
+            "Explain what the code in {} from line {} to line {} does, and why, for a developer reading it for the first time. Read related code if needed, then respond with FINAL: and the explanation in markdown.\n\n{}",
+            path.display(),
+            params.range.start.line + 1,
+            params.range.end.line + 1,
+            excerpt(&content, &params.range),
+        );
+
+        let mut agent = (self.factory)(
+            &SessionOptions {
+                workdir: self.root.clone(),
+                max_steps: None,
+                read_only: true,
+            },
+            None,
+        );
+        let outcome = run_with_notifications(&mut agent, &task, writer, |_, _| {}).await?;
+
+        Ok(outcome.map(|result| json!({ "explanation": final_answer(&result.steps) })))
+    }
+}
+
+async fn run_with_notifications<W, F>(
+    agent: &mut ReactAgent,
+    task: &str,
+    writer: &mut W,
+    on_step: F,
+) -> std::io::Result<Result<AgentResult, RpcError>>
+where
+    W: AsyncWrite + Unpin,
+    F: Fn(&Step, &mpsc::UnboundedSender<Value>) + Send + Sync + 'static,
+{
+    let (tx, mut rx) = mpsc::unbounded_channel::<Value>();
+    let callback: StepCallback = Arc::new(move |index: usize, step: Step| {
+        on_step(&step, &tx);
+        let _ = tx.send(json!({
+            "jsonrpc": "2.0",
+            "method": "synthia/progress",
+            "params": {"index": index, "step": step},
+        }));
+    });
+    agent.set_step_callback(Some(callback));
+
+    let outcome = {
+        let run = agent.run(task);
+        tokio::pin!(run);
+
+        loop {
+            tokio::select! {
+                outcome = &mut run => break outcome,
+                Some(message) = rx.recv() => write_message(writer, &message).await?,
+            }
+        }
+    };
+
+    agent.set_step_callback(None);
+    while let Ok(message) = rx.try_recv() {
+        write_message(writer, &message).await?;
+    }
+
+    Ok(outcome.map_err(|e| RpcError::new(AGENT_ERROR, e.to_string())))
+}
+
+pub fn workspace_edit_for_step(root: &Path, step: &Step) -> Option<Value> {
+    let path = step.action_input.get("path")?.as_str()?;
+    let content = match step.action.as_str() {
+        WRITE_FILE_TOOL => step.action_input.get("content")?.as_str()?.to_string(),
+        // Only the replacements are in the input, so the file is read as
+        // the step left it.
+        EDIT_FILE_TOOL => std::fs::read_to_string(root.join(path)).ok()?,
+        _ => return None,
+    };
+
+    // The agent has already written the file, so the whole editor buffer is
+    // replaced; clients clamp the end position to the document length.
+    let whole_document = Range {
+        start: Position { line: 0, character: 0 },
+        end: Position {
+            line: i32::MAX as u32,
+            character: 0,
+        },
+    };
+
+    Some(json!({
+        "changes": {
+            path_to_uri(&root.join(path)): [{"range": whole_document, "newText": content}]
+        }
+    }))
+}
+
+async fn read_message<R: AsyncBufRead + Unpin>(reader: &mut R) -> std::io::Result<Option<Vec<u8>>> {
+    let mut content_length = None;
+
+    loop {
+        let mut header = String::new();
+        if reader.read_line(&mut header).await? == 0 {
+            return Ok(None);
+        }
+        let header = header.trim_end();
+        if header.is_empty() {
+            break;
+        }
+        if let Some((name, value)) = header.split_once(':')
+            && name.eq_ignore_ascii_case("content-length")
+        {
+            content_length = value.trim().parse::<usize>().ok();
+        }
+    }
+
+    let length = content_length.ok_or_else(|| {
+        std::io::Error::new(std::io::ErrorKind::InvalidData, "missing Content-Length header")
+    })?;
+    let mut body = vec![0; length];
+    reader.read_exact(&mut body).await?;
+    Ok(Some(body))
+}
+
+async fn write_message<W: AsyncWrite + Unpin>(writer: &mut W, message: &Value) -> std::io::Result<()> {
+    let body = serde_json::to_string(message).map_err(std::io::Error::other)?;
+    writer
+        .write_all(format!("Content-Length: {}\r\n\r\n{}", body.len(), body).as_bytes())
+        .await?;
+    writer.flush().await
+}
+
+fn error_response(id: Value, error: RpcError) -> Value {
+    json!({"jsonrpc": "2.0", "id": id, "error": error})
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::clients::OpenAIClient;
+    use crate::tools::ToolManager;
+
+    fn frame(message: &str) -> String {
+        format!("Content-Length: {}\r\n\r\n{}", message.len(), message)
+    }
+
+    fn server() -> LspServer {
+        LspServer::new(
+            Box::new(|options, _| {
+                ReactAgent::new(
+                    Box::new(OpenAIClient::new("test_key".to_string(), "gpt-4".to_string(), None)),
+                    ToolManager::new(),
+                    options.workdir.clone(),
+                    options.max_steps,
+                    Some(true),
+                    None,
+                )
+            }),
+            PathBuf::from("."),
+        )
+    }
+
+    #[tokio::test]
+    async fn test_framing_and_lifecycle() {
+        let input = [
+            frame(r#"{"jsonrpc":"2.0","id":1,"method":"initialize","params":{"rootUri":"file:///tmp"}}"#),
+            frame(r#"{"jsonrpc":"2.0","method":"initialized","params":{}}"#),
+            frame(r#"{"jsonrpc":"2.0","id":2,"method":"textDocument/hover","params":{}}"#),
+            frame(r#"{"jsonrpc":"2.0","id":3,"method":"synthia/fixDiagnostic","params":{}}"#),
+            frame(r#"{"jsonrpc":"2.0","id":4,"method":"shutdown"}"#),
+            frame(r#"{"jsonrpc":"2.0","method":"exit"}"#),
+        ]
+        .concat();
+
+        let mut server = server();
+        let mut output = Vec::new();
+        server.serve(input.as_bytes(), &mut output).await.unwrap();
+
+        let mut reader = output.as_slice();
+        let mut responses = Vec::new();
+        while let Some(body) = read_message(&mut reader).await.unwrap() {
+            responses.push(serde_json::from_slice::<Value>(&body).unwrap());
+        }
+
+        assert_eq!(server.root, PathBuf::from("/tmp"));
+        assert_eq!(responses.len(), 4);
+        assert_eq!(responses[0]["result"]["serverInfo"]["name"], "synthia-agent");
+        assert_eq!(responses[1]["error"]["code"], METHOD_NOT_FOUND);
+        assert_eq!(responses[2]["error"]["code"], INVALID_PARAMS);
+        assert_eq!(responses[3]["result"], Value::Null);
+    }
+
+    #[test]
+    fn test_workspace_edit_for_step() {
+        let step = Step::new(
+            String::new(),
+            "write_file".to_string(),
+            json!({"path": "src/lib.rs", "content": "fn main() {}\n"}),
+            String::new(),
+            String::new(),
+        );
+
+        let edit = workspace_edit_for_step(Path::new("/repo"), &step).unwrap();
+        let changes = &edit["changes"]["file:///repo/src/lib.rs"][0];
+        assert_eq!(changes["newText"], "fn main() {}\n");
+        assert_eq!(changes["range"]["start"]["line"], 0);
+
+        let read = Step::new(String::new(), "read_file".to_string(), json!({}), String::new(), String::new());
+        assert!(workspace_edit_for_step(Path::new("/repo"), &read).is_none());
+
+        let dir = tempfile::tempdir().unwrap();
+        std::fs::write(dir.path().join("lib.rs"), "fn edited() {}\n").unwrap();
+        let edit = Step::new(
+            String::new(),
+            "edit_file".to_string(),
+            json!({"path": "lib.rs", "old_string": "fn main", "new_string": "fn edited"}),
+            String::new(),
+            String::new(),
+        );
+        let workspace_edit = workspace_edit_for_step(dir.path(), &edit).unwrap();
+        let changes = &workspace_edit["changes"][path_to_uri(&dir.path().join("lib.rs"))][0];
+        assert_eq!(changes["newText"], "fn edited() {}\n");
+        assert!(workspace_edit_for_step(Path::new("/repo"), &edit).is_none());
+    }
+
+    #[test]
+    fn test_excerpt() {
+        let content = (1..=100).map(|i| format!("line {}", i)).collect::<Vec<_>>().join("\n");
+        let range = Range {
+            start: Position { line: 49, character: 0 },
+            end: Position { line: 49, character: 4 },
+        };
+
+        let excerpt = excerpt(&content, &range);
+
+        assert!(excerpt.starts_with("   30 | line 30"));
+        assert!(excerpt.ends_with("   70 | line 70"));
+    }
+}
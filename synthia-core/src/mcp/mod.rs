@@ -0,0 +1,481 @@
+//! Tools from Model Context Protocol servers, configured in
+//! `.synthia/mcp.json`. Each server is started as a child process and spoken
+//! to over its stdin and stdout; its tools are then offered to the model
+//! beside the built-in ones.
+
+use crate::config::parse_checked;
+use crate::tools::{ToolAnnotations, ToolError, ToolInfo, ToolManager, ToolOutput, ToolTrait};
+use futures::Future;
+use serde::{Deserialize, Serialize};
+use serde_json::{Value, json};
+use std::collections::HashMap;
+use std::path::PathBuf;
+use std::pin::Pin;
+use std::sync::Arc;
+use std::time::Duration;
+use thiserror::Error;
+use transport::StdioConnection;
+
+mod transport;
+
+/// How long a request may take when the server sets no `timeout_seconds`.
+const DEFAULT_TIMEOUT: Duration = Duration::from_secs(60);
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct MCPServerConfig {
+    pub command: String,
+    #[serde(default)]
+    pub args: Vec<String>,
+    #[serde(default)]
+    pub env: HashMap<String, String>,
+    /// How long each request may take; a minute when 0 or unset.
+    #[serde(default)]
+    pub timeout_seconds: u64,
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct MCPConfig {
+    pub servers: HashMap<String, MCPServerConfig>,
+}
+
+#[derive(Debug, Error)]
+pub enum MCPError {
+    #[error("Connection failed: {0}")]
+    ConnectionFailed(String),
+    #[error("Server not found: {0}")]
+    ServerNotFound(String),
+    #[error("Tool call failed: {0}")]
+    ToolCallFailed(String),
+    #[error("Timeout: {0}")]
+    Timeout(String),
+    #[error("Protocol error: {0}")]
+    ProtocolError(String),
+}
+
+pub struct MCPClient {
+    name: String,
+    config: MCPServerConfig,
+    connection: tokio::sync::Mutex<Option<StdioConnection>>,
+}
+
+impl MCPClient {
+    pub fn new(name: String, config: MCPServerConfig) -> Self {
+        Self {
+            name,
+            config,
+            connection: tokio::sync::Mutex::new(None),
+        }
+    }
+
+    pub fn name(&self) -> &str {
+        &self.name
+    }
+
+    pub fn config(&self) -> &MCPServerConfig {
+        &self.config
+    }
+
+    fn timeout(&self) -> Duration {
+        match self.config.timeout_seconds {
+            0 => DEFAULT_TIMEOUT,
+            seconds => Duration::from_secs(seconds),
+        }
+    }
+
+    /// Starts the server and completes the `initialize` handshake. Does
+    /// nothing if it is already running.
+    pub async fn connect(&self) -> Result<(), MCPError> {
+        let mut connection = self.connection.lock().await;
+        if connection.is_none() {
+            *connection = Some(StdioConnection::open(&self.config, self.timeout()).await?);
+        }
+        Ok(())
+    }
+
+    /// Stops the server, if it is running.
+    pub async fn disconnect(&self) {
+        if let Some(connection) = self.connection.lock().await.take() {
+            connection.close().await;
+        }
+    }
+
+    async fn request(&self, method: &str, params: Value) -> Result<Value, MCPError> {
+        let mut connection = self.connection.lock().await;
+        let connection = connection
+            .as_mut()
+            .ok_or_else(|| MCPError::ConnectionFailed(format!("{} is not connected", self.name)))?;
+        connection.request(method, params).await
+    }
+
+    /// Every tool the server offers, following `nextCursor` through the
+    /// pages.
+    pub async fn list_tools(&self) -> Result<Vec<McpTool>, MCPError> {
+        let mut tools = Vec::new();
+        let mut cursor: Option<String> = None;
+        loop {
+            let params = match &cursor {
+                Some(cursor) => json!({"cursor": cursor}),
+                None => json!({}),
+            };
+            let page = self.request("tools/list", params).await?;
+            let listed = page.get("tools").cloned().unwrap_or_else(|| json!([]));
+            tools.extend(
+                serde_json::from_value::<Vec<McpTool>>(listed).map_err(|e| MCPError::ProtocolError(e.to_string()))?,
+            );
+            cursor = page.get("nextCursor").and_then(|c| c.as_str()).map(str::to_string);
+            if cursor.is_none() {
+                return Ok(tools);
+            }
+        }
+    }
+
+    /// The `tools/call` result: `content` blocks, `isError`, and maybe
+    /// `structuredContent`.
+    pub async fn call_tool(
+        &self,
+        name: &str,
+        arguments: Value,
+    ) -> Result<Value, MCPError> {
+        self.request("tools/call", json!({"name": name, "arguments": arguments}))
+            .await
+            .map_err(|e| match e {
+                MCPError::ProtocolError(message) => MCPError::ToolCallFailed(message),
+                e => e,
+            })
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct McpTool {
+    pub name: String,
+    #[serde(default)]
+    pub description: String,
+    /// The input schema; MCP calls it `inputSchema`.
+    #[serde(alias = "inputSchema", default)]
+    pub parameters: Value,
+    /// The server's hints about what the tool does.
+    #[serde(default)]
+    pub annotations: ToolAnnotations,
+}
+
+/// A server's tool as a [`ToolTrait`], run through its [`MCPClient`] and
+/// described to the model with the server's own schema.
+pub struct McpToolProxy {
+    client: Arc<MCPClient>,
+    tool: McpTool,
+    /// The name the model calls it by, which can differ from the server's.
+    name: String,
+}
+
+impl McpToolProxy {
+    pub fn new(client: Arc<MCPClient>, tool: McpTool) -> Self {
+        let name = tool.name.clone();
+        Self { client, tool, name }
+    }
+
+    /// Offers the tool to the model as `name`, e.g. to keep it apart from a
+    /// built-in tool; the server is still called with its own name.
+    pub fn with_name(mut self, name: impl Into<String>) -> Self {
+        self.name = name.into();
+        self
+    }
+}
+
+/// A `tools/call` result as a tool result: the text of its content blocks,
+/// and its structured content if it sent any, kept as data whatever its
+/// shape. `isError` makes it a failure.
+fn tool_result(result: &Value) -> ToolOutput {
+    let text: Vec<&str> = result
+        .get("content")
+        .and_then(|c| c.as_array())
+        .into_iter()
+        .flatten()
+        .filter_map(|block| match block.get("type").and_then(|t| t.as_str()) {
+            Some("text") => block.get("text").and_then(|t| t.as_str()),
+            _ => None,
+        })
+        .collect();
+    let text = text.join("\n");
+
+    if result.get("isError").and_then(|e| e.as_bool()) == Some(true) {
+        return ToolOutput::failure("tool_error", text, false);
+    }
+    let mut output = json!({"content": text});
+    if let Some(structured) = result.get("structuredContent") {
+        output["structured"] = structured.clone();
+    }
+    ToolOutput::success(output)
+}
+
+impl ToolTrait for McpToolProxy {
+    fn info(&self) -> ToolInfo {
+        // Providers reject a tool without an object schema.
+        let parameters = match &self.tool.parameters {
+            Value::Null => json!({"type": "object", "properties": {}}),
+            parameters => parameters.clone(),
+        };
+        ToolInfo {
+            name: self.name.clone(),
+            description: self.tool.description.clone(),
+            parameters,
+            annotations: self.tool.annotations,
+        }
+    }
+
+    fn execute(&self, arguments: Value) -> Pin<Box<dyn Future<Output = Result<ToolOutput, ToolError>> + Send + Sync>> {
+        let client = Arc::clone(&self.client);
+        let name = self.tool.name.clone();
+        Box::pin(async move {
+            let result = client
+                .call_tool(&name, arguments)
+                .await
+                .map_err(|e| ToolError::ExecutionFailed(format!("{} ({}): {}", name, client.name(), e)))?;
+            Ok(tool_result(&result))
+        })
+    }
+}
+
+pub struct MCPManager {
+    clients: HashMap<String, Arc<MCPClient>>,
+    /// Each tool's server, by the name it is offered under: its own, or
+    /// `server.tool` when an earlier server took that.
+    tools: HashMap<String, String>,
+    definitions: HashMap<String, McpTool>,
+    config: MCPConfig,
+}
+
+impl MCPManager {
+    pub fn new(config: MCPConfig) -> Self {
+        Self {
+            clients: HashMap::new(),
+            tools: HashMap::new(),
+            definitions: HashMap::new(),
+            config,
+        }
+    }
+
+    /// Starts the server `name` and learns its tools. A tool named like
+    /// one from a server connected earlier is named `name.tool` instead.
+    pub async fn connect_server(&mut self, name: &str) -> Result<(), MCPError> {
+        let server_config = self.config.servers.get(name)
+            .ok_or_else(|| MCPError::ServerNotFound(name.to_string()))?;
+
+        let client = MCPClient::new(name.to_string(), server_config.clone());
+        client.connect().await?;
+        let tools = match client.list_tools().await {
+            Ok(tools) => tools,
+            Err(e) => {
+                client.disconnect().await;
+                return Err(e);
+            }
+        };
+        for tool in tools {
+            let mut key = tool.name.clone();
+            if self.tools.contains_key(&key) {
+                key = format!("{}.{}", name, tool.name);
+                if self.tools.contains_key(&key) {
+                    tracing::warn!("MCP server {} repeats the tool {}; it is left out", name, tool.name);
+                    continue;
+                }
+                tracing::warn!("MCP server {} repeats the tool {}; it is offered as {}", name, tool.name, key);
+            }
+            self.tools.insert(key.clone(), name.to_string());
+            self.definitions.insert(key, tool);
+        }
+
+        self.clients.insert(name.to_string(), Arc::new(client));
+
+        Ok(())
+    }
+
+    /// Connects every configured server, in name order. Servers that fail
+    /// are skipped and returned with the reason.
+    pub async fn connect_all(&mut self) -> Vec<(String, MCPError)> {
+        let mut names: Vec<String> = self.config.servers.keys().cloned().collect();
+        names.sort();
+        let mut failures = Vec::new();
+        for name in names {
+            if let Err(e) = self.connect_server(&name).await {
+                failures.push((name, e));
+            }
+        }
+        failures
+    }
+
+    /// Adds the connected servers' tools to `manager`, only the ones
+    /// annotated read-only with `read_only`. A tool named like one already
+    /// in `manager`, such as a built-in, is offered as `server.tool`.
+    pub fn register_tools(&self, manager: &mut ToolManager, read_only: bool) {
+        let mut names: Vec<&String> = self.tools.keys().collect();
+        names.sort();
+        for name in names {
+            let server = &self.tools[name];
+            let (Some(client), Some(tool)) = (self.clients.get(server), self.definitions.get(name)) else {
+                continue;
+            };
+            if read_only && !tool.annotations.read_only {
+                continue;
+            }
+            let mut offered = name.clone();
+            if manager.get(&offered).is_some() {
+                offered = format!("{}.{}", server, tool.name);
+                if manager.get(&offered).is_some() {
+                    tracing::warn!("MCP tool {} of {} is named like another tool; it is left out", tool.name, server);
+                    continue;
+                }
+                tracing::warn!("MCP tool {} of {} is named like another tool; it is offered as {}", tool.name, server, offered);
+            }
+            let proxy = McpToolProxy::new(Arc::clone(client), tool.clone()).with_name(offered);
+            manager.register(Box::new(proxy));
+        }
+    }
+
+    /// The tools of the server `name`, once connected.
+    pub fn server_tools(&self, name: &str) -> Vec<&McpTool> {
+        let mut tools: Vec<&McpTool> = self
+            .tools
+            .iter()
+            .filter(|(_, server)| server.as_str() == name)
+            .filter_map(|(tool, _)| self.definitions.get(tool))
+            .collect();
+        tools.sort_by(|a, b| a.name.cmp(&b.name));
+        tools
+    }
+
+    /// Stops every server.
+    pub async fn disconnect_all(&mut self) {
+        let names: Vec<String> = self.clients.keys().cloned().collect();
+        for name in names {
+            let _ = self.disconnect_server(&name).await;
+        }
+    }
+
+    pub async fn disconnect_server(&mut self, name: &str) -> Result<(), MCPError> {
+        if let Some(client) = self.clients.remove(name) {
+            client.disconnect().await;
+            for tool_name in self.tools.keys().cloned().collect::<Vec<_>>() {
+                if self.tools.get(&tool_name) == Some(&name.to_string()) {
+                    self.tools.remove(&tool_name);
+                    self.definitions.remove(&tool_name);
+                }
+            }
+            Ok(())
+        } else {
+            Err(MCPError::ServerNotFound(name.to_string()))
+        }
+    }
+
+    pub async fn call_tool(
+        &self,
+        tool_name: &str,
+        arguments: Value,
+    ) -> Result<Value, MCPError> {
+        let (Some(server_name), Some(tool)) = (self.tools.get(tool_name), self.definitions.get(tool_name)) else {
+            return Err(MCPError::ToolCallFailed(format!("Unknown tool: {}", tool_name)));
+        };
+
+        let client = self.clients.get(server_name)
+            .ok_or_else(|| MCPError::ServerNotFound(server_name.clone()))?;
+
+        // A namespaced tool is still called by the server's own name.
+        client.call_tool(&tool.name, arguments).await
+    }
+
+    pub fn list_tools(&self) -> Vec<String> {
+        self.tools.keys().cloned().collect()
+    }
+
+    pub fn has_tool(&self, name: &str) -> bool {
+        self.tools.contains_key(name)
+    }
+}
+
+pub async fn load_mcp_config(config_path: &PathBuf) -> Result<MCPConfig, MCPError> {
+    if !config_path.exists() {
+        return Ok(MCPConfig { servers: HashMap::new() });
+    }
+
+    let content = tokio::fs::read_to_string(config_path)
+        .await
+        .map_err(|e| MCPError::ProtocolError(e.to_string()))?;
+
+    let (config, warnings) = parse_checked(&content, &[]).map_err(|e| MCPError::ProtocolError(e.to_string()))?;
+    for warning in warnings {
+        tracing::warn!("{}: {}", config_path.display(), warning);
+    }
+    Ok(config)
+}
+
+pub fn default_mcp_config() -> MCPConfig {
+    MCPConfig {
+        servers: HashMap::new(),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// A server speaking just enough MCP: `echo` returns its arguments,
+    /// and `fail` reports an error.
+    const FAKE_SERVER: &str = r#"while IFS= read -r line; do
+  id=$(printf '%s' "$line" | sed -n 's/^{"id":\([0-9]*\),.*/\1/p')
+  case "$line" in
+    *'"method":"initialize"'*)
+      echo "{\"jsonrpc\":\"2.0\",\"id\":$id,\"result\":{\"protocolVersion\":\"2025-06-18\",\"capabilities\":{\"tools\":{}},\"serverInfo\":{\"name\":\"fake\"}}}" ;;
+    *'"method":"tools/list"'*)
+      echo "{\"jsonrpc\":\"2.0\",\"id\":$id,\"result\":{\"tools\":[{\"name\":\"echo\",\"description\":\"Echo\",\"inputSchema\":{\"type\":\"object\"},\"annotations\":{\"readOnlyHint\":true}},{\"name\":\"fail\"}]}}" ;;
+    *'"name":"echo"'*)
+      echo "{\"jsonrpc\":\"2.0\",\"id\":$id,\"result\":{\"content\":[{\"type\":\"text\",\"text\":\"hello\"}]}}" ;;
+    *'"name":"fail"'*)
+      echo "{\"jsonrpc\":\"2.0\",\"id\":$id,\"result\":{\"content\":[{\"type\":\"text\",\"text\":\"no such row\"}],\"isError\":true}}" ;;
+  esac
+done"#;
+
+    #[tokio::test]
+    async fn test_mcp_stdio_tools() {
+        let server = MCPServerConfig {
+            command: "sh".to_string(),
+            args: vec!["-c".to_string(), FAKE_SERVER.to_string()],
+            env: HashMap::new(),
+            timeout_seconds: 5,
+        };
+        let mut manager = MCPManager::new(MCPConfig {
+            servers: HashMap::from([("fake".to_string(), server.clone()), ("other".to_string(), server)]),
+        });
+        assert!(manager.connect_all().await.is_empty());
+        let names: Vec<&str> = manager.server_tools("fake").iter().map(|t| t.name.as_str()).collect();
+        assert_eq!(names, ["echo", "fail"]);
+        // The second server's tools of the same names are namespaced.
+        assert!(manager.has_tool("other.echo") && manager.has_tool("other.fail"));
+        assert_eq!(manager.call_tool("other.echo", json!({})).await.unwrap()["content"][0]["text"], "hello");
+
+        let mut tools = ToolManager::new();
+        manager.register_tools(&mut tools, true);
+        assert!(tools.get("echo").is_some() && tools.get("fail").is_none());
+        let mut tools = ToolManager::new();
+        manager.register_tools(&mut tools, false);
+        let echoed = tools.get("echo").unwrap().execute(json!({"text": "hello"})).await.unwrap();
+        assert_eq!(echoed, ToolOutput::success(json!({"content": "hello"})));
+        let fail = tools.get("fail").unwrap();
+        assert_eq!(fail.info().parameters, json!({"type": "object", "properties": {}}));
+        let failed = fail.execute(json!({})).await.unwrap();
+        assert_eq!(failed, ToolOutput::failure("tool_error", "no such row", false));
+        // Structured content is data, even when shaped like a result.
+        let claimed = tool_result(&json!({"content": [], "structuredContent": {"version": 1, "success": false, "meta": {}}}));
+        assert!(claimed.success);
+        assert_eq!(claimed.data["structured"]["success"], false);
+
+        // A tool named like one already there is offered as `server.tool`.
+        let mut tools = ToolManager::new();
+        let builtin = McpToolProxy::new(Arc::clone(&manager.clients["fake"]), manager.definitions["fail"].clone());
+        tools.register(Box::new(builtin.with_name("echo")));
+        manager.register_tools(&mut tools, false);
+        assert_eq!(tools.get("echo").unwrap().info().description, "");
+        let echoed = tools.get("fake.echo").unwrap().execute(json!({})).await.unwrap();
+        assert_eq!(echoed, ToolOutput::success(json!({"content": "hello"})));
+
+        manager.disconnect_all().await;
+        assert!(manager.call_tool("echo", json!({})).await.is_err());
+    }
+}
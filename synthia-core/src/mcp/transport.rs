@@ -0,0 +1,152 @@
+//! MCP's stdio transport: the server is a child process, and JSON-RPC 2.0
+//! messages go over its stdin and stdout, one per line.
+
+use super::{MCPError, MCPServerConfig};
+use serde_json::{Value, json};
+use std::process::Stdio;
+use std::time::Duration;
+use tokio::io::{AsyncBufReadExt, AsyncWriteExt, BufReader, Lines};
+use tokio::process::{Child, ChildStdin, ChildStdout};
+
+/// The protocol revision asked for in `initialize`. Servers answer with
+/// the one they speak, which is accepted as long as the tools methods are
+/// there.
+pub(crate) const PROTOCOL_VERSION: &str = "2025-06-18";
+
+/// How long a server gets to exit once its stdin is closed.
+const SHUTDOWN_GRACE: Duration = Duration::from_secs(2);
+
+/// A running server. Requests are answered in turn, so one is sent only
+/// after the previous response has been read.
+pub(crate) struct StdioConnection {
+    child: Child,
+    stdin: Option<ChildStdin>,
+    stdout: Lines<BufReader<ChildStdout>>,
+    next_id: u64,
+    timeout: Duration,
+}
+
+impl StdioConnection {
+    /// Starts the server and completes the `initialize` handshake.
+    pub(crate) async fn open(config: &MCPServerConfig, timeout: Duration) -> Result<Self, MCPError> {
+        let mut child = tokio::process::Command::new(&config.command)
+            .args(&config.args)
+            .envs(&config.env)
+            .stdin(Stdio::piped())
+            .stdout(Stdio::piped())
+            .stderr(Stdio::null())
+            .kill_on_drop(true)
+            .spawn()
+            .map_err(|e| MCPError::ConnectionFailed(format!("{}: {}", config.command, e)))?;
+        let stdin = child.stdin.take();
+        let stdout = child
+            .stdout
+            .take()
+            .ok_or_else(|| MCPError::ConnectionFailed("no stdout".to_string()))?;
+
+        let mut connection = Self {
+            child,
+            stdin,
+            stdout: BufReader::new(stdout).lines(),
+            next_id: 1,
+            timeout,
+        };
+        let result = connection
+            .request(
+                "initialize",
+                json!({
+                    "protocolVersion": PROTOCOL_VERSION,
+                    "capabilities": {},
+                    "clientInfo": {"name": "synthia", "version": env!("CARGO_PKG_VERSION")}
+                }),
+            )
+            .await?;
+        if result.pointer("/capabilities/tools").is_none() {
+            tracing::debug!("MCP server {} does not announce tools", config.command);
+        }
+        connection.notify("notifications/initialized", json!({})).await?;
+        Ok(connection)
+    }
+
+    async fn send(&mut self, message: &Value) -> Result<(), MCPError> {
+        let stdin = self
+            .stdin
+            .as_mut()
+            .ok_or_else(|| MCPError::ConnectionFailed("the connection is closed".to_string()))?;
+        let mut line = message.to_string();
+        line.push('\n');
+        stdin
+            .write_all(line.as_bytes())
+            .await
+            .map_err(|e| MCPError::ConnectionFailed(e.to_string()))?;
+        stdin.flush().await.map_err(|e| MCPError::ConnectionFailed(e.to_string()))
+    }
+
+    async fn notify(&mut self, method: &str, params: Value) -> Result<(), MCPError> {
+        self.send(&json!({"jsonrpc": "2.0", "method": method, "params": params})).await
+    }
+
+    /// Sends a request and waits for its response, answering anything the
+    /// server asks in the meantime.
+    pub(crate) async fn request(&mut self, method: &str, params: Value) -> Result<Value, MCPError> {
+        let id = self.next_id;
+        self.next_id += 1;
+        self.send(&json!({"jsonrpc": "2.0", "id": id, "method": method, "params": params}))
+            .await?;
+
+        let timeout = self.timeout;
+        tokio::time::timeout(timeout, self.response(id))
+            .await
+            .map_err(|_| MCPError::Timeout(format!("{} got no answer in {}s", method, timeout.as_secs())))?
+    }
+
+    async fn response(&mut self, id: u64) -> Result<Value, MCPError> {
+        loop {
+            let line = self
+                .stdout
+                .next_line()
+                .await
+                .map_err(|e| MCPError::ConnectionFailed(e.to_string()))?
+                .ok_or_else(|| MCPError::ConnectionFailed("the server exited".to_string()))?;
+            if line.trim().is_empty() {
+                continue;
+            }
+            let message: Value = serde_json::from_str(&line)
+                .map_err(|e| MCPError::ProtocolError(format!("{}: {}", e, line)))?;
+
+            match (message.get("id"), message.get("method")) {
+                // A request from the server. Only pings are understood.
+                (Some(request_id), Some(method)) => {
+                    let reply = if method == "ping" {
+                        json!({"jsonrpc": "2.0", "id": request_id, "result": {}})
+                    } else {
+                        json!({
+                            "jsonrpc": "2.0",
+                            "id": request_id,
+                            "error": {"code": -32601, "message": format!("Method not found: {}", method)}
+                        })
+                    };
+                    self.send(&reply).await?;
+                }
+                (None, Some(method)) => tracing::debug!("MCP notification: {}", method),
+                (Some(response_id), None) if response_id.as_u64() == Some(id) => {
+                    if let Some(error) = message.get("error") {
+                        let text = error.get("message").and_then(|m| m.as_str()).unwrap_or("unknown error");
+                        return Err(MCPError::ProtocolError(text.to_string()));
+                    }
+                    return Ok(message.get("result").cloned().unwrap_or(Value::Null));
+                }
+                _ => tracing::debug!("Unexpected MCP message: {}", line),
+            }
+        }
+    }
+
+    /// Closes the server's stdin, which asks it to exit, and kills it if
+    /// it hasn't within a moment.
+    pub(crate) async fn close(mut self) {
+        drop(self.stdin.take());
+        if tokio::time::timeout(SHUTDOWN_GRACE, self.child.wait()).await.is_err() {
+            let _ = self.child.kill().await;
+        }
+    }
+}
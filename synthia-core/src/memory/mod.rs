@@ -0,0 +1,449 @@
+use crate::clients::{Message, MessageRole};
+use serde::{Deserialize, Serialize};
+use std::borrow::Cow;
+use std::collections::VecDeque;
+use std::num::NonZeroUsize;
+use std::sync::Arc;
+
+mod retention;
+
+pub use retention::{Expiry, Retention, RetentionPolicy};
+
+const DEFAULT_MAX_TOKENS: usize = 8000;
+const DEFAULT_COMPRESSION_RATIO: f64 = 0.7;
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct ConversationContext {
+    pub messages: Vec<Message>,
+    pub tool_results: Vec<ToolResult>,
+    pub metadata: ContextMetadata,
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct ToolResult {
+    pub tool_name: String,
+    pub arguments: serde_json::Value,
+    pub result: serde_json::Value,
+    pub timestamp: u64,
+}
+
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+pub struct ContextMetadata {
+    pub total_tokens: usize,
+    pub compressed: bool,
+    pub compression_count: usize,
+}
+
+pub struct ContextCompressor {
+    max_tokens: NonZeroUsize,
+    compression_ratio: f64,
+    preserve_recent: usize,
+}
+
+impl ContextCompressor {
+    pub fn new(max_tokens: usize, compression_ratio: f64, preserve_recent: usize) -> Self {
+        Self {
+            max_tokens: NonZeroUsize::new(max_tokens).unwrap_or(NonZeroUsize::new(DEFAULT_MAX_TOKENS).unwrap()),
+            compression_ratio: if compression_ratio > 0.0 && compression_ratio < 1.0 {
+                compression_ratio
+            } else {
+                DEFAULT_COMPRESSION_RATIO
+            },
+            preserve_recent,
+        }
+    }
+
+    pub fn with_tokens(max_tokens: usize) -> Self {
+        Self::new(max_tokens, DEFAULT_COMPRESSION_RATIO, 3)
+    }
+
+    pub fn max_tokens(&self) -> usize {
+        self.max_tokens.get()
+    }
+
+    pub fn compression_ratio(&self) -> f64 {
+        self.compression_ratio
+    }
+
+    /// Returns the context to send. When it already fits the budget the
+    /// input is borrowed as-is, so the common case copies nothing.
+    pub fn compress<'a>(
+        &self,
+        messages: &'a [Message],
+        tool_results: &'a [ToolResult],
+    ) -> (Cow<'a, [Message]>, Cow<'a, [ToolResult]>, ContextMetadata) {
+        let current_tokens = self.count_tokens(messages, tool_results);
+
+        if current_tokens <= self.max_tokens.get() {
+            return (
+                Cow::Borrowed(messages),
+                Cow::Borrowed(tool_results),
+                ContextMetadata {
+                    total_tokens: current_tokens,
+                    compressed: false,
+                    compression_count: 0,
+                },
+            );
+        }
+
+        let mut compressed_tool_results = tool_results.to_vec();
+
+        let system_messages: Vec<Message> = messages
+            .iter()
+            .filter(|m| m.role == MessageRole::System)
+            .cloned()
+            .collect();
+
+        let other_messages: Vec<&Message> = messages
+            .iter()
+            .filter(|m| m.role != MessageRole::System)
+            .collect();
+
+        let split_at = self.recent_split(&other_messages);
+        let recent_messages = &other_messages[split_at..];
+        let old_messages = &other_messages[..split_at];
+
+        let summary = self.summarize_messages(old_messages);
+
+        let mut final_messages = system_messages;
+        final_messages.push(summary_message(&summary));
+        final_messages.extend(recent_messages.iter().map(|m| (*m).clone()));
+
+        compressed_tool_results.retain(|tr| {
+            recent_messages.iter().any(|m| {
+                m.tool_calls.as_ref().is_some_and(|tc| {
+                    tc.iter().any(|call| call.function.name == tr.tool_name)
+                })
+            })
+        });
+
+        let final_tokens = self.count_tokens(&final_messages, &compressed_tool_results);
+
+        (
+            Cow::Owned(final_messages),
+            Cow::Owned(compressed_tool_results),
+            ContextMetadata {
+                total_tokens: final_tokens,
+                compressed: true,
+                compression_count: 1,
+            },
+        )
+    }
+
+    /// Builds a compaction of a whole conversation even when it fits the
+    /// budget, for a user who asks for one. Returns `None` when there is
+    /// nothing older than the messages that are always kept.
+    pub fn compact(&self, messages: &[Message]) -> Option<Compaction> {
+        let all: Vec<&Message> = messages.iter().collect();
+        let split_at = self.recent_split(&all);
+        if split_at == 0 {
+            return None;
+        }
+
+        Some(Compaction {
+            summary: self.summarize_messages(&all[..split_at]),
+            recent: messages[split_at..].to_vec(),
+            tokens_before: self.count_tokens(messages, &[]),
+        })
+    }
+
+    /// Where the verbatim tail of `messages` starts. The tail is widened so
+    /// it never opens on a tool result cut off from its call.
+    fn recent_split(&self, messages: &[&Message]) -> usize {
+        let mut split_at = messages.len() - std::cmp::min(self.preserve_recent, messages.len());
+        while split_at > 0 && split_at < messages.len() && messages[split_at].role == MessageRole::Tool {
+            split_at -= 1;
+        }
+        split_at
+    }
+
+    fn summarize_messages(&self, messages: &[&Message]) -> String {
+        if messages.is_empty() {
+            return "No previous conversation".to_string();
+        }
+
+        let tool_calls: usize = messages
+            .iter()
+            .filter(|m| m.tool_calls.is_some())
+            .flat_map(|m| m.tool_calls.as_ref().unwrap())
+            .count();
+
+        let user_messages: usize = messages
+            .iter()
+            .filter(|m| m.role == MessageRole::User)
+            .count();
+
+        let assistant_messages: usize = messages
+            .iter()
+            .filter(|m| m.role == MessageRole::Assistant)
+            .count();
+
+        format!(
+            "{} user messages, {} assistant responses, {} tool calls",
+            user_messages,
+            assistant_messages,
+            tool_calls
+        )
+    }
+
+    fn count_tokens(&self, messages: &[Message], tool_results: &[ToolResult]) -> usize {
+        let message_tokens: usize = messages.iter().map(message_tokens).sum();
+
+        let tool_result_tokens: usize = tool_results
+            .iter()
+            .map(|tr| {
+                tr.tool_name.len() / 4
+                    + tr.arguments.to_string().len() / 4
+                    + tr.result.to_string().len() / 4
+            })
+            .sum();
+
+        message_tokens + tool_result_tokens
+    }
+}
+
+fn message_tokens(message: &Message) -> usize {
+    message.content.len() / 4 + message.tool_calls.as_ref().map(|tc| tc.len() * 20).unwrap_or(0)
+}
+
+fn summary_message(summary: &str) -> Message {
+    Message {
+        role: MessageRole::User,
+        content: format!("[Previous conversation summarized: {}]", summary),
+        tool_calls: None,
+    }
+}
+
+/// A proposed compaction: everything but the most recent messages folded
+/// into `summary`, which the user may edit before it is applied.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct Compaction {
+    pub summary: String,
+    pub recent: Vec<Message>,
+    pub tokens_before: usize,
+}
+
+impl Compaction {
+    /// The conversation that replaces the compacted one.
+    pub fn messages(&self) -> Vec<Message> {
+        let mut messages = Vec::with_capacity(self.recent.len() + 1);
+        messages.push(summary_message(&self.summary));
+        messages.extend(self.recent.iter().cloned());
+        messages
+    }
+
+    pub fn tokens_after(&self) -> usize {
+        self.messages().iter().map(message_tokens).sum()
+    }
+
+    pub fn tokens_reclaimed(&self) -> usize {
+        self.tokens_before.saturating_sub(self.tokens_after())
+    }
+}
+
+/// The conversation an agent carries between runs. The system prompt is
+/// held apart from the other messages, so there is at most one however
+/// many runs the conversation spans, and it is never evicted.
+pub struct ConversationHistory {
+    system_prompt: Option<Arc<Message>>,
+    messages: VecDeque<Arc<Message>>,
+    tool_results: VecDeque<ToolResult>,
+    max_messages: usize,
+    retention: RetentionPolicy,
+}
+
+impl ConversationHistory {
+    pub fn new(max_messages: usize) -> Self {
+        Self {
+            system_prompt: None,
+            messages: VecDeque::with_capacity(max_messages),
+            tool_results: VecDeque::new(),
+            max_messages,
+            retention: RetentionPolicy::default(),
+        }
+    }
+
+    pub fn set_retention(&mut self, retention: RetentionPolicy) {
+        self.retention = retention;
+    }
+
+    /// How long each kind of message is sent to the model verbatim.
+    pub fn retention(&self) -> &RetentionPolicy {
+        &self.retention
+    }
+
+    /// Makes `prompt` the system prompt, replacing any earlier one. Each
+    /// run rebuilds the prompt, since the tools or the repository may have
+    /// changed since the last.
+    pub fn ensure_system_prompt(&mut self, prompt: impl Into<String>) {
+        let prompt = prompt.into();
+        if self.system_prompt().is_some_and(|current| current == prompt) {
+            return;
+        }
+        self.system_prompt = Some(Arc::new(Message {
+            role: MessageRole::System,
+            content: prompt,
+            tool_calls: None,
+        }));
+    }
+
+    pub fn system_prompt(&self) -> Option<&str> {
+        self.system_prompt.as_ref().map(|message| message.content.as_str())
+    }
+
+    /// Appends `message`, evicting the oldest ones once full. A tool result
+    /// left at the front without its call is evicted too. A system message
+    /// replaces the system prompt instead.
+    pub fn add_message(&mut self, message: impl Into<Arc<Message>>) {
+        let message = message.into();
+        if message.role == MessageRole::System {
+            self.system_prompt = Some(message);
+            return;
+        }
+        while self.messages.len() >= self.max_messages {
+            self.messages.pop_front();
+        }
+        self.messages.push_back(message);
+        while self.messages.len() > 1 && self.messages.front().is_some_and(|m| m.role == MessageRole::Tool) {
+            self.messages.pop_front();
+        }
+    }
+
+    /// Replaces the stored messages, keeping tool results.
+    pub fn replace_messages(&mut self, messages: impl IntoIterator<Item = Message>) {
+        self.messages.clear();
+        for message in messages {
+            self.add_message(message);
+        }
+    }
+
+    pub fn add_tool_result(&mut self, result: ToolResult) {
+        self.tool_results.push_back(result);
+    }
+
+    /// Shares the stored messages, without the system prompt, instead of
+    /// copying their contents.
+    pub fn get_messages(&self) -> Vec<Arc<Message>> {
+        self.messages.iter().map(Arc::clone).collect()
+    }
+
+    /// The whole conversation to send: the system prompt, if any, then the
+    /// stored messages.
+    pub fn conversation(&self) -> Vec<Message> {
+        self.system_prompt
+            .iter()
+            .chain(&self.messages)
+            .map(|message| Message::clone(message))
+            .collect()
+    }
+
+    pub fn get_tool_results(&self) -> Vec<ToolResult> {
+        self.tool_results.iter().cloned().collect()
+    }
+
+    pub fn clear(&mut self) {
+        self.system_prompt = None;
+        self.messages.clear();
+        self.tool_results.clear();
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_context_compressor_new() {
+        let compressor = ContextCompressor::new(10000, 0.8, 5);
+        assert_eq!(compressor.max_tokens.get(), 10000);
+    }
+
+    #[test]
+    fn test_compress_no_compression_needed() {
+        let compressor = ContextCompressor::with_tokens(10000);
+        let messages = vec![Message {
+            role: MessageRole::User,
+            content: "Hello".to_string(),
+            tool_calls: None,
+        }];
+
+        let (compressed, _, metadata) = compressor.compress(&messages, &[]);
+
+        assert_eq!(compressed.len(), 1);
+        assert!(!metadata.compressed);
+    }
+
+    #[test]
+    fn test_conversation_history() {
+        let mut history = ConversationHistory::new(5);
+
+        history.add_message(Message {
+            role: MessageRole::User,
+            content: "Test".to_string(),
+            tool_calls: None,
+        });
+
+        assert_eq!(history.get_messages().len(), 1);
+    }
+
+    fn message(role: MessageRole, content: &str) -> Message {
+        Message {
+            role,
+            content: content.to_string(),
+            tool_calls: None,
+        }
+    }
+
+    #[test]
+    fn test_compact() {
+        let compressor = ContextCompressor::new(10000, 0.7, 2);
+        let messages = vec![
+            message(MessageRole::User, &"a".repeat(4000)),
+            message(MessageRole::Assistant, "TOOL_CALL: read_file: {}"),
+            message(MessageRole::Tool, "contents"),
+            message(MessageRole::Assistant, "FINAL: done"),
+        ];
+
+        let compaction = compressor.compact(&messages).unwrap();
+
+        // The tool result pulls its call into the verbatim tail.
+        assert_eq!(compaction.recent, messages[1..].to_vec());
+        assert_eq!(compaction.summary, "1 user messages, 0 assistant responses, 0 tool calls");
+        assert!(compaction.tokens_reclaimed() > 900);
+        assert_eq!(compaction.messages().len(), 4);
+        assert!(compressor.compact(&messages[1..]).is_none());
+    }
+
+    #[test]
+    fn test_history_never_starts_with_tool_result() {
+        let mut history = ConversationHistory::new(2);
+        history.add_message(message(MessageRole::Assistant, "call"));
+        history.add_message(message(MessageRole::Tool, "result"));
+        history.add_message(message(MessageRole::Assistant, "FINAL: done"));
+
+        assert_eq!(history.get_messages().len(), 1);
+        assert_eq!(history.get_messages()[0].content, "FINAL: done");
+    }
+
+    #[test]
+    fn test_ensure_system_prompt() {
+        let mut history = ConversationHistory::new(2);
+        history.ensure_system_prompt("You are an agent.");
+        history.ensure_system_prompt("You are an agent.");
+        history.add_message(message(MessageRole::User, "first"));
+        assert_eq!(history.conversation().len(), 2);
+
+        // A run hands back the whole conversation, system prompt included.
+        let mut conversation = history.conversation();
+        conversation.push(message(MessageRole::Assistant, "FINAL: done"));
+        conversation.push(message(MessageRole::User, "second"));
+        history.replace_messages(conversation);
+        history.ensure_system_prompt("You are a careful agent.");
+
+        let conversation = history.conversation();
+        let roles: Vec<MessageRole> = conversation.iter().map(|m| m.role.clone()).collect();
+        assert_eq!(roles, [MessageRole::System, MessageRole::Assistant, MessageRole::User]);
+        assert_eq!(conversation[0].content, "You are a careful agent.");
+        assert_eq!(history.get_messages().len(), 2);
+    }
+}
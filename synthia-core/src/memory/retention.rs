@@ -0,0 +1,172 @@
+use crate::clients::{Message, MessageRole};
+use serde::{Deserialize, Serialize};
+use std::borrow::Cow;
+
+/// How many characters of an expired message [`Expiry::Truncate`] keeps.
+const TRUNCATED_CHARS: usize = 200;
+
+/// What happens to a message once it is older than its kind's window.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Expiry {
+    /// Keep only the start of the message.
+    #[default]
+    Truncate,
+    /// Remove the message. Tool observations are replaced by a placeholder
+    /// instead, since the call they answer still needs a result.
+    Drop,
+}
+
+/// How long one kind of message is kept verbatim, counted in agent steps.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct Retention {
+    /// `None` keeps the message verbatim forever.
+    pub verbatim_steps: Option<usize>,
+    pub then: Expiry,
+}
+
+impl Retention {
+    pub fn forever() -> Self {
+        Self::default()
+    }
+
+    pub fn steps(verbatim_steps: usize, then: Expiry) -> Self {
+        Self {
+            verbatim_steps: Some(verbatim_steps),
+            then,
+        }
+    }
+
+    fn expired(&self, age: usize) -> bool {
+        self.verbatim_steps.is_some_and(|steps| age >= steps)
+    }
+}
+
+/// Per-kind retention for the messages sent to the model. System prompts
+/// and assistant turns that carry tool calls are always kept. The default
+/// keeps everything.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct RetentionPolicy {
+    pub tool_observations: Retention,
+    pub assistant_thoughts: Retention,
+    pub user_messages: Retention,
+}
+
+impl RetentionPolicy {
+    fn retention(&self, message: &Message) -> Option<&Retention> {
+        match message.role {
+            MessageRole::System => None,
+            MessageRole::User => Some(&self.user_messages),
+            MessageRole::Assistant if message.tool_calls.is_some() => None,
+            MessageRole::Assistant => Some(&self.assistant_thoughts),
+            MessageRole::Tool => Some(&self.tool_observations),
+        }
+    }
+
+    /// Applies the policy to `messages`. A message's age is the number of
+    /// assistant turns after it. Nothing is copied when nothing has expired.
+    pub fn apply<'a>(&self, messages: &'a [Message]) -> Cow<'a, [Message]> {
+        let mut ages = vec![0; messages.len()];
+        let mut age = 0;
+        for (i, message) in messages.iter().enumerate().rev() {
+            ages[i] = age;
+            if message.role == MessageRole::Assistant {
+                age += 1;
+            }
+        }
+
+        let expired = |i: usize, message: &Message| {
+            self.retention(message)
+                .filter(|retention| retention.expired(ages[i]))
+                .copied()
+        };
+        if !messages.iter().enumerate().any(|(i, m)| expired(i, m).is_some()) {
+            return Cow::Borrowed(messages);
+        }
+
+        let mut retained = Vec::with_capacity(messages.len());
+        for (i, message) in messages.iter().enumerate() {
+            match expired(i, message).map(|retention| retention.then) {
+                None => retained.push(message.clone()),
+                Some(Expiry::Drop) if message.role == MessageRole::Tool => retained.push(Message {
+                    content: format!("[observation dropped after {} steps]", ages[i]),
+                    ..message.clone()
+                }),
+                Some(Expiry::Drop) => {}
+                Some(Expiry::Truncate) => {
+                    let mut content: String = message.content.chars().take(TRUNCATED_CHARS).collect();
+                    if content.len() < message.content.len() {
+                        content.push_str(&format!(" [... truncated after {} steps]", ages[i]));
+                    }
+                    retained.push(Message {
+                        content,
+                        ..message.clone()
+                    });
+                }
+            }
+        }
+        Cow::Owned(retained)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::clients::{ToolCall, ToolFunction};
+
+    fn message(role: MessageRole, content: &str) -> Message {
+        Message {
+            role,
+            content: content.to_string(),
+            tool_calls: None,
+        }
+    }
+
+    fn step(messages: &mut Vec<Message>, thought: &str, observation: &str) {
+        messages.push(Message {
+            tool_calls: Some(vec![ToolCall {
+                id: "call_1".to_string(),
+                function: ToolFunction {
+                    name: "read_file".to_string(),
+                    arguments: "{}".to_string(),
+                },
+            }]),
+            ..message(MessageRole::Assistant, "TOOL_CALL: read_file: {}")
+        });
+        messages.push(message(MessageRole::Tool, observation));
+        messages.push(message(MessageRole::Assistant, thought));
+    }
+
+    #[test]
+    fn test_default_keeps_everything() {
+        let mut messages = vec![message(MessageRole::User, "task")];
+        step(&mut messages, "hmm", &"x".repeat(1000));
+
+        assert!(matches!(RetentionPolicy::default().apply(&messages), Cow::Borrowed(_)));
+    }
+
+    #[test]
+    fn test_expiry_per_kind() {
+        let mut messages = vec![message(MessageRole::System, "prompt"), message(MessageRole::User, "task")];
+        step(&mut messages, "old thought", &"a".repeat(1000));
+        step(&mut messages, "new thought", "recent");
+        let policy = RetentionPolicy {
+            tool_observations: Retention::steps(2, Expiry::Truncate),
+            assistant_thoughts: Retention::steps(1, Expiry::Drop),
+            user_messages: Retention::forever(),
+        };
+
+        let retained = policy.apply(&messages);
+
+        let contents: Vec<&str> = retained.iter().map(|m| m.content.as_str()).collect();
+        assert_eq!(contents.len(), messages.len() - 1);
+        assert_eq!(contents[1], "task");
+        assert!(contents[3].starts_with(&"a".repeat(200)));
+        assert!(contents[3].ends_with("[... truncated after 3 steps]"));
+        assert!(!contents.contains(&"old thought"));
+        assert_eq!(contents[5], "recent");
+        assert_eq!(contents[6], "new thought");
+    }
+}
@@ -0,0 +1,132 @@
+//! Bringing files written by older versions up to date.
+//!
+//! Each file synthia keeps carries a `version`; files from before they did
+//! have none and count as version 0. Reading a file migrates it in memory,
+//! so upgrading never loses one, and `migrate` rewrites them in the current
+//! format. A file from a newer version is refused rather than misread.
+//!
+//! Indexes under [`INDEX_DIR`](crate::paths::INDEX_DIR) are versioned as
+//! well but are rebuilt instead of migrated, since everything in them can
+//! be derived again.
+
+use crate::paths::{LEGACY_MCP_CONFIG_FILE, MCP_CONFIG_FILE};
+use serde::Serialize;
+use serde::de::DeserializeOwned;
+use serde_json::{Map, Value};
+use std::path::{Path, PathBuf};
+use thiserror::Error;
+
+/// Rewrites a file saved at one version into the next.
+pub type Migration = fn(&mut Map<String, Value>);
+
+#[derive(Debug, Error)]
+pub enum FormatError {
+    #[error("format version {found} is newer than this build reads ({supported})")]
+    Newer { found: u32, supported: u32 },
+    #[error("expected a JSON object")]
+    NotAnObject,
+    #[error(transparent)]
+    Json(#[from] serde_json::Error),
+}
+
+/// Reads `json` saved at any version up to `migrations.len()`, running the
+/// migration from each older version in turn: `migrations[0]` takes a file
+/// from version 0 to 1, and so on. Returns the value and the version the
+/// file was at.
+pub fn upgrade<T: DeserializeOwned>(json: &str, migrations: &[Migration]) -> Result<(T, u32), FormatError> {
+    upgrade_value(serde_json::from_str(json)?, migrations)
+}
+
+/// [`upgrade`] for JSON that is already parsed, e.g. part of a larger
+/// file.
+pub fn upgrade_value<T: DeserializeOwned>(value: Value, migrations: &[Migration]) -> Result<(T, u32), FormatError> {
+    let current = migrations.len() as u32;
+    let Value::Object(mut object) = value else {
+        return Err(FormatError::NotAnObject);
+    };
+    let version = object.get("version").and_then(|version| version.as_u64()).unwrap_or(0) as u32;
+    if version > current {
+        return Err(FormatError::Newer {
+            found: version,
+            supported: current,
+        });
+    }
+    for migration in &migrations[version as usize..] {
+        migration(&mut object);
+    }
+    object.insert("version".to_string(), Value::from(current));
+    Ok((serde_json::from_value(Value::Object(object))?, version))
+}
+
+/// `value` as pretty JSON with `version` beside its own fields.
+pub fn versioned<T: Serialize>(value: &T, version: u32) -> Result<String, FormatError> {
+    Ok(serde_json::to_string_pretty(&versioned_value(value, version)?)?)
+}
+
+/// `value` as a JSON object with `version` beside its own fields.
+pub fn versioned_value<T: Serialize>(value: &T, version: u32) -> Result<Value, FormatError> {
+    let Value::Object(mut object) = serde_json::to_value(value)? else {
+        return Err(FormatError::NotAnObject);
+    };
+    object.insert("version".to_string(), Value::from(version));
+    Ok(Value::Object(object))
+}
+
+/// Files in `workdir` still where an older version kept them, each with
+/// where it belongs now. Both are read from the old place until moved.
+pub fn moved_files(workdir: &Path) -> Vec<(PathBuf, PathBuf)> {
+    let legacy = workdir.join(LEGACY_MCP_CONFIG_FILE);
+    let current = workdir.join(MCP_CONFIG_FILE);
+    if legacy.exists() && !current.exists() {
+        vec![(legacy, current)]
+    } else {
+        Vec::new()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde::Deserialize;
+
+    #[test]
+    fn test_upgrade() {
+        #[derive(Debug, PartialEq, Serialize, Deserialize)]
+        struct Note {
+            text: String,
+            tags: Vec<String>,
+        }
+
+        // Version 1 renamed `body` to `text`; version 2 added `tags`.
+        let migrations: &[Migration] = &[
+            |note| {
+                if let Some(body) = note.remove("body") {
+                    note.insert("text".to_string(), body);
+                }
+            },
+            |note| {
+                note.insert("tags".to_string(), Value::Array(Vec::new()));
+            },
+        ];
+        let mut note = Note {
+            text: "hi".to_string(),
+            tags: Vec::new(),
+        };
+        let (upgraded, version) = upgrade::<Note>(r#"{"body": "hi"}"#, migrations).unwrap();
+        assert_eq!((&upgraded, version), (&note, 0));
+        note.tags.push("kept".to_string());
+        let saved = versioned(&note, 2).unwrap();
+        assert_eq!(upgrade::<Note>(&saved, migrations).unwrap(), (note, 2));
+        assert!(matches!(
+            upgrade::<Note>(r#"{"version": 3, "text": "hi"}"#, migrations),
+            Err(FormatError::Newer { found: 3, supported: 2 })
+        ));
+
+        let dir = tempfile::tempdir().unwrap();
+        std::fs::write(dir.path().join(LEGACY_MCP_CONFIG_FILE), "{}").unwrap();
+        assert_eq!(
+            moved_files(dir.path()),
+            [(dir.path().join(LEGACY_MCP_CONFIG_FILE), dir.path().join(MCP_CONFIG_FILE))]
+        );
+    }
+}
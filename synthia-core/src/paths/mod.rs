@@ -0,0 +1,148 @@
+//! Where synthia keeps its files. What belongs to a project lives under
+//! [`PROJECT_DIR`] in its working directory; what belongs to the user
+//! lives in the platform's directories for config, cache, state and logs.
+
+use std::path::{Path, PathBuf};
+
+/// The project directory, relative to the working directory. Nothing the
+/// agent writes there counts as a change to the project.
+pub const PROJECT_DIR: &str = ".synthia";
+/// The project's config file.
+pub const CONFIG_FILE: &str = ".synthia/config.json";
+/// The project's MCP servers.
+pub const MCP_CONFIG_FILE: &str = ".synthia/mcp.json";
+/// Where MCP servers were configured before [`MCP_CONFIG_FILE`]; still read
+/// when that doesn't exist.
+pub const LEGACY_MCP_CONFIG_FILE: &str = "mcp_config.json";
+/// Saved sessions.
+pub const SESSION_DIR: &str = ".synthia/sessions";
+/// Indexes rebuilt from the source, such as outlines and embeddings.
+pub const INDEX_DIR: &str = ".synthia/index";
+/// Each run's scratch directory.
+pub const SCRATCH_DIR: &str = ".synthia/tmp";
+
+/// The name of synthia's directory in each of the user's directories.
+const APP_NAME: &str = "synthia";
+
+/// The user's directories for synthia. On Linux and other Unixes these
+/// follow the XDG base directory spec, with `~/.config`, `~/.cache` and
+/// `~/.local/state` where its variables are unset. macOS uses
+/// `~/Library`, and Windows `%APPDATA%` and `%LOCALAPPDATA%`.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct UserDirs {
+    /// Settings shared by every project.
+    pub config: PathBuf,
+    /// Anything that can be rebuilt.
+    pub cache: PathBuf,
+    /// Data that should outlive a restart but isn't config.
+    pub state: PathBuf,
+    pub logs: PathBuf,
+}
+
+impl UserDirs {
+    /// The directories for the current user, or `None` without a home
+    /// directory to put them in.
+    pub fn current() -> Option<Self> {
+        Self::resolve(|name| std::env::var_os(name).map(PathBuf::from), std::env::home_dir())
+    }
+
+    /// The directories given a way to read environment variables and the
+    /// home directory.
+    fn resolve(var: impl Fn(&str) -> Option<PathBuf>, home: Option<PathBuf>) -> Option<Self> {
+        if cfg!(windows) {
+            let roaming = var("APPDATA")?.join(APP_NAME);
+            let local = var("LOCALAPPDATA")?.join(APP_NAME);
+            return Some(Self {
+                config: roaming,
+                cache: local.join("cache"),
+                logs: local.join("logs"),
+                state: local,
+            });
+        }
+        let home = home.filter(|home| !home.as_os_str().is_empty())?;
+        if cfg!(target_os = "macos") {
+            let library = home.join("Library");
+            let support = library.join("Application Support").join(APP_NAME);
+            return Some(Self {
+                config: support.clone(),
+                cache: library.join("Caches").join(APP_NAME),
+                state: support,
+                logs: library.join("Logs").join(APP_NAME),
+            });
+        }
+        // The spec says to ignore relative paths.
+        let xdg = |name: &str, default: &str| {
+            var(name)
+                .filter(|path| path.is_absolute())
+                .unwrap_or_else(|| home.join(default))
+                .join(APP_NAME)
+        };
+        let state = xdg("XDG_STATE_HOME", ".local/state");
+        Some(Self {
+            config: xdg("XDG_CONFIG_HOME", ".config"),
+            cache: xdg("XDG_CACHE_HOME", ".cache"),
+            logs: state.join("logs"),
+            state,
+        })
+    }
+
+    /// The config file used by projects without their own.
+    pub fn config_file(&self) -> PathBuf {
+        self.config.join("config.json")
+    }
+
+    /// The MCP servers used by projects without their own.
+    pub fn mcp_config_file(&self) -> PathBuf {
+        self.config.join("mcp.json")
+    }
+}
+
+/// The first of `candidates` that exists.
+fn first_existing(candidates: impl IntoIterator<Item = PathBuf>) -> Option<PathBuf> {
+    candidates.into_iter().find(|path| path.exists())
+}
+
+/// The config file for `workdir`: its own [`CONFIG_FILE`], or else the
+/// user's. `None` if neither exists.
+pub fn config_file(workdir: &Path) -> Option<PathBuf> {
+    first_existing([Some(workdir.join(CONFIG_FILE)), UserDirs::current().map(|dirs| dirs.config_file())].into_iter().flatten())
+}
+
+/// The MCP config for `workdir`: its [`MCP_CONFIG_FILE`], its
+/// [`LEGACY_MCP_CONFIG_FILE`], or else the user's. `None` if none exists.
+pub fn mcp_config_file(workdir: &Path) -> Option<PathBuf> {
+    first_existing(
+        [
+            Some(workdir.join(MCP_CONFIG_FILE)),
+            Some(workdir.join(LEGACY_MCP_CONFIG_FILE)),
+            UserDirs::current().map(|dirs| dirs.mcp_config_file()),
+        ]
+        .into_iter()
+        .flatten(),
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::collections::HashMap;
+
+    #[test]
+    #[cfg(all(unix, not(target_os = "macos")))]
+    fn test_user_dirs() {
+        let env = HashMap::from([("XDG_CONFIG_HOME", "/etc/xdg-home"), ("XDG_CACHE_HOME", "relative/cache")]);
+        let dirs = UserDirs::resolve(|name| env.get(name).map(PathBuf::from), Some(PathBuf::from("/home/me"))).unwrap();
+        assert_eq!(dirs.config_file(), Path::new("/etc/xdg-home/synthia/config.json"));
+        assert_eq!(dirs.cache, Path::new("/home/me/.cache/synthia"));
+        assert_eq!(dirs.state, Path::new("/home/me/.local/state/synthia"));
+        assert_eq!(dirs.logs, Path::new("/home/me/.local/state/synthia/logs"));
+        assert_eq!(UserDirs::resolve(|_| None, None), None);
+
+        let project = tempfile::tempdir().unwrap();
+        std::fs::write(project.path().join(LEGACY_MCP_CONFIG_FILE), "{}").unwrap();
+        assert_eq!(mcp_config_file(project.path()), Some(project.path().join(LEGACY_MCP_CONFIG_FILE)));
+        std::fs::create_dir(project.path().join(PROJECT_DIR)).unwrap();
+        std::fs::write(project.path().join(MCP_CONFIG_FILE), "{}").unwrap();
+        assert_eq!(mcp_config_file(project.path()), Some(project.path().join(MCP_CONFIG_FILE)));
+    }
+}
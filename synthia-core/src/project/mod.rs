@@ -0,0 +1,301 @@
+//! Detects what kind of project the working directory holds and how to
+//! build, test and lint it, so the model doesn't spend its first steps
+//! finding out.
+
+use serde::{Deserialize, Serialize};
+use std::path::Path;
+
+mod toolchain;
+
+pub use toolchain::{ToolStatus, check_tools, describe_tools, install_hint, required_programs};
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Stack {
+    Rust,
+    Node,
+    Python,
+    Go,
+}
+
+impl Stack {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            Stack::Rust => "Rust",
+            Stack::Node => "Node.js",
+            Stack::Python => "Python",
+            Stack::Go => "Go",
+        }
+    }
+}
+
+/// A project found from its manifest, with the commands that build, test
+/// and lint it, run from the working directory.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct Project {
+    pub stack: Stack,
+    /// The manifest it was found from, e.g. `Cargo.toml`.
+    pub manifest: String,
+    pub build: Option<String>,
+    pub test: Option<String>,
+    pub lint: Option<String>,
+    /// Files where the program starts, relative to the working directory.
+    pub entry_points: Vec<String>,
+}
+
+/// The projects whose manifests are in `root`, in a fixed order. A
+/// directory can hold more than one, e.g. a Rust crate with a Node.js
+/// frontend.
+pub fn detect(root: &Path) -> Vec<Project> {
+    [detect_rust, detect_node, detect_python, detect_go]
+        .iter()
+        .filter_map(|detect| detect(root))
+        .collect()
+}
+
+fn read_toml(path: &Path) -> Option<toml::Table> {
+    let content = std::fs::read_to_string(path).ok()?;
+    match content.parse::<toml::Table>() {
+        Ok(table) => Some(table),
+        Err(e) => {
+            tracing::debug!("Could not parse {}: {}", path.display(), e);
+            Some(toml::Table::new())
+        }
+    }
+}
+
+fn existing(root: &Path, candidates: impl IntoIterator<Item = String>) -> Vec<String> {
+    candidates.into_iter().filter(|path| root.join(path).is_file()).collect()
+}
+
+/// Files in `dir` with `extension`, sorted.
+fn files_in(root: &Path, dir: &str, extension: &str) -> Vec<String> {
+    let Ok(entries) = std::fs::read_dir(root.join(dir)) else {
+        return Vec::new();
+    };
+    let mut files: Vec<String> = entries
+        .flatten()
+        .filter(|entry| entry.path().extension().is_some_and(|ext| ext == extension))
+        .map(|entry| format!("{}/{}", dir, entry.file_name().to_string_lossy()))
+        .collect();
+    files.sort();
+    files
+}
+
+fn detect_rust(root: &Path) -> Option<Project> {
+    let manifest = read_toml(&root.join("Cargo.toml"))?;
+    let scope = if manifest.contains_key("workspace") { " --workspace" } else { "" };
+
+    let mut entry_points: Vec<String> = manifest
+        .get("bin")
+        .and_then(|bins| bins.as_array())
+        .into_iter()
+        .flatten()
+        .filter_map(|bin| bin.get("path").and_then(|path| path.as_str()).map(str::to_string))
+        .collect();
+    entry_points.extend(existing(root, ["src/main.rs".to_string()]));
+    entry_points.extend(files_in(root, "src/bin", "rs"));
+    entry_points.dedup();
+
+    Some(Project {
+        stack: Stack::Rust,
+        manifest: "Cargo.toml".to_string(),
+        build: Some(format!("cargo build{}", scope)),
+        test: Some(format!("cargo test{}", scope)),
+        lint: Some(format!("cargo clippy{} --all-targets", scope)),
+        entry_points,
+    })
+}
+
+fn detect_node(root: &Path) -> Option<Project> {
+    let content = std::fs::read_to_string(root.join("package.json")).ok()?;
+    let package: serde_json::Value = serde_json::from_str(&content).unwrap_or_default();
+    let manager = [
+        ("pnpm-lock.yaml", "pnpm"),
+        ("yarn.lock", "yarn"),
+        ("bun.lockb", "bun"),
+        ("bun.lock", "bun"),
+    ]
+    .iter()
+    .find(|(lockfile, _)| root.join(lockfile).exists())
+    .map(|(_, manager)| *manager)
+    .unwrap_or("npm");
+    let script = |name: &str| {
+        package
+            .get("scripts")
+            .and_then(|scripts| scripts.get(name))
+            .map(|_| format!("{} run {}", manager, name))
+    };
+
+    let mut entry_points: Vec<String> = Vec::new();
+    for field in ["main", "module"] {
+        if let Some(path) = package.get(field).and_then(|v| v.as_str()) {
+            entry_points.push(path.trim_start_matches("./").to_string());
+        }
+    }
+    match package.get("bin") {
+        Some(serde_json::Value::String(path)) => entry_points.push(path.trim_start_matches("./").to_string()),
+        Some(serde_json::Value::Object(bins)) => entry_points.extend(
+            bins.values()
+                .filter_map(|path| path.as_str())
+                .map(|path| path.trim_start_matches("./").to_string()),
+        ),
+        _ => {}
+    }
+    entry_points.dedup();
+
+    Some(Project {
+        stack: Stack::Node,
+        manifest: "package.json".to_string(),
+        build: script("build"),
+        test: script("test"),
+        lint: script("lint"),
+        entry_points,
+    })
+}
+
+fn detect_python(root: &Path) -> Option<Project> {
+    let manifest = read_toml(&root.join("pyproject.toml"))?;
+    let tool = manifest.get("tool");
+    let has_tool = |name: &str| tool.and_then(|tool| tool.get(name)).is_some();
+    let mentions = |name: &str| {
+        std::fs::read_to_string(root.join("pyproject.toml")).is_ok_and(|content| content.contains(name))
+    };
+
+    let test = if has_tool("pytest") || mentions("pytest") || root.join("pytest.ini").exists() {
+        "pytest"
+    } else {
+        "python -m unittest"
+    };
+    let lint = if has_tool("ruff") || root.join("ruff.toml").exists() {
+        Some("ruff check .")
+    } else if root.join(".flake8").exists() || mentions("flake8") {
+        Some("flake8")
+    } else {
+        None
+    };
+    let build = manifest.contains_key("build-system").then(|| "python -m build".to_string());
+
+    // Console scripts name a module and function, e.g. "pkg.cli:main".
+    let mut entry_points: Vec<String> = manifest
+        .get("project")
+        .and_then(|project| project.get("scripts"))
+        .and_then(|scripts| scripts.as_table())
+        .into_iter()
+        .flat_map(|scripts| scripts.values())
+        .filter_map(|target| target.as_str())
+        .map(|target| target.to_string())
+        .collect();
+    entry_points.extend(existing(
+        root,
+        ["main.py", "app.py", "manage.py", "__main__.py"].map(str::to_string),
+    ));
+
+    Some(Project {
+        stack: Stack::Python,
+        manifest: "pyproject.toml".to_string(),
+        build,
+        test: Some(test.to_string()),
+        lint: lint.map(str::to_string),
+        entry_points,
+    })
+}
+
+fn detect_go(root: &Path) -> Option<Project> {
+    if !root.join("go.mod").is_file() {
+        return None;
+    }
+    let lint = if root.join(".golangci.yml").exists() || root.join(".golangci.yaml").exists() {
+        "golangci-lint run"
+    } else {
+        "go vet ./..."
+    };
+
+    let mut entry_points = existing(root, ["main.go".to_string()]);
+    if let Ok(commands) = std::fs::read_dir(root.join("cmd")) {
+        let mut commands: Vec<String> = commands
+            .flatten()
+            .map(|entry| format!("cmd/{}/main.go", entry.file_name().to_string_lossy()))
+            .collect();
+        commands.sort();
+        entry_points.extend(existing(root, commands));
+    }
+
+    Some(Project {
+        stack: Stack::Go,
+        manifest: "go.mod".to_string(),
+        build: Some("go build ./...".to_string()),
+        test: Some("go test ./...".to_string()),
+        lint: Some(lint.to_string()),
+        entry_points,
+    })
+}
+
+/// The projects as Markdown, for the system prompt.
+pub fn describe(projects: &[Project]) -> String {
+    let mut text = String::new();
+    for project in projects {
+        text.push_str(&format!("- {} ({})\n", project.stack.as_str(), project.manifest));
+        for (label, command) in [("Build", &project.build), ("Test", &project.test), ("Lint", &project.lint)] {
+            if let Some(command) = command {
+                text.push_str(&format!("  - {}: `{}`\n", label, command));
+            }
+        }
+        if !project.entry_points.is_empty() {
+            text.push_str(&format!("  - Entry points: {}\n", project.entry_points.join(", ")));
+        }
+    }
+    text
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_detect() {
+        let dir = tempfile::tempdir().unwrap();
+        let root = dir.path();
+        std::fs::write(root.join("Cargo.toml"), "[workspace]\nmembers = [\"cli\"]\n\n[[bin]]\nname = \"x\"\npath = \"tools/x.rs\"\n").unwrap();
+        std::fs::write(
+            root.join("package.json"),
+            r#"{"main": "./index.js", "bin": {"web": "bin/web.js"}, "scripts": {"test": "jest", "build": "tsc"}}"#,
+        )
+        .unwrap();
+        std::fs::write(root.join("yarn.lock"), "").unwrap();
+        std::fs::write(root.join("go.mod"), "module example.com/x\n").unwrap();
+        std::fs::create_dir_all(root.join("cmd/server")).unwrap();
+        std::fs::write(root.join("cmd/server/main.go"), "package main\n").unwrap();
+
+        let projects = detect(root);
+
+        let stacks: Vec<Stack> = projects.iter().map(|project| project.stack).collect();
+        assert_eq!(stacks, [Stack::Rust, Stack::Node, Stack::Go]);
+        assert_eq!(projects[0].test.as_deref(), Some("cargo test --workspace"));
+        assert_eq!(projects[0].entry_points, ["tools/x.rs"]);
+        assert_eq!(projects[1].build.as_deref(), Some("yarn run build"));
+        assert_eq!(projects[1].lint, None);
+        assert_eq!(projects[1].entry_points, ["index.js", "bin/web.js"]);
+        assert_eq!(projects[2].entry_points, ["cmd/server/main.go"]);
+        assert!(describe(&projects).contains("- Go (go.mod)\n  - Build: `go build ./...`\n"));
+    }
+
+    #[test]
+    fn test_detect_python() {
+        let dir = tempfile::tempdir().unwrap();
+        std::fs::write(
+            dir.path().join("pyproject.toml"),
+            "[build-system]\nrequires = [\"hatchling\"]\n\n[project]\nname = \"app\"\n\n[project.scripts]\napp = \"app.cli:main\"\n\n[tool.ruff]\nline-length = 100\n",
+        )
+        .unwrap();
+        std::fs::write(dir.path().join("manage.py"), "").unwrap();
+
+        let projects = detect(dir.path());
+
+        assert_eq!(projects.len(), 1);
+        assert_eq!(projects[0].test.as_deref(), Some("python -m unittest"));
+        assert_eq!(projects[0].lint.as_deref(), Some("ruff check ."));
+        assert_eq!(projects[0].build.as_deref(), Some("python -m build"));
+        assert_eq!(projects[0].entry_points, ["app.cli:main", "manage.py"]);
+    }
+}
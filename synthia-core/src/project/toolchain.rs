@@ -0,0 +1,145 @@
+use super::Project;
+use serde::{Deserialize, Serialize};
+use std::path::Path;
+use std::time::Duration;
+
+/// How long a program gets to print its version.
+const VERSION_TIMEOUT: Duration = Duration::from_secs(5);
+
+/// Files that mean the project is built or run with Docker.
+const DOCKER_FILES: &[&str] = &["Dockerfile", "docker-compose.yml", "docker-compose.yaml", "compose.yml", "compose.yaml"];
+
+/// A program the project needs, and its version if it is on `PATH`.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct ToolStatus {
+    pub program: String,
+    /// The first line the program printed for its version, or `None` if it
+    /// could not be run.
+    pub version: Option<String>,
+}
+
+impl ToolStatus {
+    pub fn is_missing(&self) -> bool {
+        self.version.is_none()
+    }
+}
+
+/// Where to get `program` from, for the programs the detected commands use.
+pub fn install_hint(program: &str) -> Option<&'static str> {
+    Some(match program {
+        "cargo" => "install Rust with rustup: https://rustup.rs",
+        "node" | "npm" => "install Node.js: https://nodejs.org",
+        "pnpm" => "npm install -g pnpm",
+        "yarn" => "corepack enable, or npm install -g yarn",
+        "bun" => "https://bun.sh",
+        "python" => "install Python: https://www.python.org/downloads",
+        "pytest" => "pip install pytest",
+        "ruff" => "pip install ruff",
+        "flake8" => "pip install flake8",
+        "go" => "install Go: https://go.dev/dl",
+        "golangci-lint" => "https://golangci-lint.run/welcome/install",
+        "docker" => "install Docker: https://docs.docker.com/get-docker",
+        _ => return None,
+    })
+}
+
+/// The programs the projects' commands start, plus each stack's runtime
+/// and Docker if the project has a Dockerfile or compose file.
+pub fn required_programs(root: &Path, projects: &[Project]) -> Vec<String> {
+    let mut programs: Vec<String> = Vec::new();
+    for project in projects {
+        if project.stack == super::Stack::Node {
+            programs.push("node".to_string());
+        }
+        let commands = [&project.build, &project.test, &project.lint];
+        for command in commands.into_iter().flatten() {
+            if let Some(program) = command.split_whitespace().next() {
+                programs.push(program.to_string());
+            }
+        }
+    }
+    if DOCKER_FILES.iter().any(|file| root.join(file).is_file()) {
+        programs.push("docker".to_string());
+    }
+    let mut seen = std::collections::HashSet::new();
+    programs.retain(|program| seen.insert(program.clone()));
+    programs
+}
+
+/// The version `program` reports, or `None` if it can't be run.
+async fn version(program: &str) -> Option<String> {
+    let flag = if program == "go" { "version" } else { "--version" };
+    let output = tokio::process::Command::new(program)
+        .arg(flag)
+        .stdin(std::process::Stdio::null())
+        .kill_on_drop(true)
+        .output();
+    let output = tokio::time::timeout(VERSION_TIMEOUT, output).await.ok()?.ok()?;
+    let text = format!(
+        "{}\n{}",
+        String::from_utf8_lossy(&output.stdout),
+        String::from_utf8_lossy(&output.stderr)
+    );
+    let line = text.lines().map(str::trim).find(|line| !line.is_empty()).unwrap_or("unknown version");
+    Some(line.to_string())
+}
+
+/// Checks that the programs the projects in `root` need can be run.
+pub async fn check_tools(root: &Path, projects: &[Project]) -> Vec<ToolStatus> {
+    let programs = required_programs(root, projects);
+    let versions = futures::future::join_all(programs.iter().map(|program| version(program))).await;
+    programs
+        .into_iter()
+        .zip(versions)
+        .map(|(program, version)| ToolStatus { program, version })
+        .collect()
+}
+
+/// The tools as Markdown, for the system prompt: versions of the ones
+/// found, and how to install the missing ones.
+pub fn describe_tools(tools: &[ToolStatus]) -> String {
+    let found: Vec<String> = tools
+        .iter()
+        .filter_map(|tool| tool.version.as_ref().map(|version| format!("{} ({})", tool.program, version)))
+        .collect();
+    let mut text = String::new();
+    if !found.is_empty() {
+        text.push_str(&format!("Installed: {}\n", found.join(", ")));
+    }
+    for tool in tools.iter().filter(|tool| tool.is_missing()) {
+        text.push_str(&format!("Missing: `{}` is not on PATH", tool.program));
+        if let Some(hint) = install_hint(&tool.program) {
+            text.push_str(&format!(" ({})", hint));
+        }
+        text.push_str(". Commands that need it will fail; tell the user rather than working around it.\n");
+    }
+    text
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::project::detect;
+
+    #[tokio::test]
+    async fn test_check_tools() {
+        let dir = tempfile::tempdir().unwrap();
+        std::fs::write(dir.path().join("package.json"), r#"{"scripts": {"test": "jest"}}"#).unwrap();
+        std::fs::write(dir.path().join("pnpm-lock.yaml"), "").unwrap();
+        std::fs::write(dir.path().join("Dockerfile"), "FROM scratch\n").unwrap();
+        let projects = detect(dir.path());
+        assert_eq!(required_programs(dir.path(), &projects), ["node", "pnpm", "docker"]);
+
+        assert!(version("sh").await.is_some());
+        assert_eq!(version("definitely-not-installed-xyz").await, None);
+
+        let missing = ToolStatus {
+            program: "pnpm".to_string(),
+            version: None,
+        };
+        assert_eq!(
+            describe_tools(&[missing]),
+            "Missing: `pnpm` is not on PATH (npm install -g pnpm). Commands that need it will fail; tell the user rather than working around it.\n"
+        );
+    }
+}
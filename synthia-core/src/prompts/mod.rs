@@ -0,0 +1,328 @@
+use serde_json::Value;
+use std::time::Duration;
+
+pub fn build_code_agent_prompt(
+    tools: &[crate::clients::ToolDefinition],
+    system_prompt: Option<String>,
+) -> String {
+    let tools_section = if tools.is_empty() {
+        "You have no tools available.".to_string()
+    } else {
+        let tool_descriptions: Vec<String> = tools
+            .iter()
+            .map(|t| {
+                format!(
+                    "- {}: {}",
+                    t.name,
+                    t.description
+                )
+            })
+            .collect();
+        format!(
+            "You have access to the following tools:\n{}\n\nWhen you need to use a tool, respond with a JSON object in the following format:\n{{\"tool\": \"<tool_name>\", \"parameters\": <parameters_json>}}",
+            tool_descriptions.join("\n")
+        )
+    };
+
+    let default_prompt = format!(
+        r#"You are an expert AI programming assistant that helps with software development tasks.
+
+## Your Capabilities
+- Reading, writing, and analyzing code
+- Running shell commands
+- File system operations
+- Code search and analysis
+
+## Guidelines
+1. Think step by step before taking action
+2. Use tools efficiently - read files before writing, search before creating
+3. Maintain code quality and follow best practices
+4. Explain your reasoning and the actions you're taking
+
+## Workflow
+1. Understand the user's request
+2. Plan your approach
+3. Execute actions using tools
+4. Verify results
+5. Iterate as needed
+
+## Important Notes
+- Always use absolute paths for file operations
+- Check file existence before reading
+- Create necessary directories before writing files
+- Handle errors gracefully and provide informative messages
+
+{}
+
+## Response Format
+You should think about the problem step by step, then take action using tools when needed. After receiving tool results, analyze them and continue until the task is complete.
+
+When you need to use a tool, respond with:
+```
+TOOL_CALL: <tool_name>: <arguments_json>
+```
+
+When you have completed the task or need to respond to the user:
+```
+FINAL: <your response>
+ASSESSMENT: {{"tests_passed": true | false | null, "unverified": ["<what you did not check>"], "risks": ["<known risks>"], "confidence": "high" | "medium" | "low"}}
+```
+Use null for tests_passed if you did not run the tests. Be honest: the assessment decides whether a person reviews your work."#,
+        tools_section
+    );
+
+    match system_prompt {
+        Some(custom) if !custom.is_empty() => custom,
+        _ => default_prompt,
+    }
+}
+
+/// Appended to the system prompt with a map of the repository.
+pub fn build_repo_map_section(map: &str) -> String {
+    format!(
+        r#"## Repository Map
+An outline of the repository, with the files most likely to matter for this task first. Use it to decide where to look before listing directories or searching.
+
+{}"#,
+        map.trim_end()
+    )
+}
+
+/// Appended to the system prompt with the detected projects.
+pub fn build_project_section(projects: &str) -> String {
+    format!(
+        r#"## Project
+Detected from the manifests in the working directory. Use these commands to build, test and lint instead of working them out.
+
+{}"#,
+        projects.trim_end()
+    )
+}
+
+/// Appended to the system prompt with the session's scratch directory.
+pub fn build_scratch_section(dir: &str) -> String {
+    format!(
+        r#"## Scratch Directory
+Put temporary files such as downloads, generated scripts and intermediate output in `{}`, not in the repository. It is also `$TMPDIR` for commands, and is removed after the run."#,
+        dir
+    )
+}
+
+/// Appended to the system prompt in read-only mode.
+pub fn build_read_only_note() -> String {
+    r#"You are in read-only mode: you may read and search the code, but must not change it. Files cannot be written and commands cannot be run; calls to tools you do not have are not executed. Answer questions, audit and review by reading."#
+        .to_string()
+}
+
+/// Sent with every request, so the model knows how much of the run is
+/// left: steps, tokens as `(used, budget)` if the run has a budget, and the
+/// time since it started. Near the end it is told to wrap up.
+pub fn build_step_prompt(step_number: usize, total_steps: usize, tokens: Option<(u64, u64)>, elapsed: Duration) -> String {
+    let left = total_steps.saturating_sub(step_number);
+    let mut status = Vec::new();
+    if left > 0 {
+        status.push(format!("{} left after this one", left));
+    }
+    if let Some((used, budget)) = tokens {
+        status.push(format!("{} of {} tokens left", budget.saturating_sub(used), budget));
+    }
+    status.push(format!("{} elapsed", format_elapsed(elapsed)));
+    let header = format!("Step {}/{} ({})", step_number, total_steps, status.join(", "));
+
+    let running_low = left <= 2 || tokens.is_some_and(|(used, budget)| budget.saturating_sub(used) * 5 <= budget);
+    if left == 0 {
+        format!("{}: This is your last step. Finish with a final answer now if you can.", header)
+    } else if running_low {
+        format!(
+            "{}: Resources are running low. Do what matters most and give your final answer soon.",
+            header
+        )
+    } else {
+        format!("{}: What is your next thought and action?", header)
+    }
+}
+
+fn format_elapsed(elapsed: Duration) -> String {
+    let secs = elapsed.as_secs();
+    match secs {
+        0..60 => format!("{}s", secs),
+        60..3600 => format!("{}m {}s", secs / 60, secs % 60),
+        _ => format!("{}h {}m", secs / 3600, secs % 3600 / 60),
+    }
+}
+
+pub fn build_observation_prompt(observation: &str) -> String {
+    format!(
+        r#"Observation: {}
+
+Based on this observation, what is your next thought and action?"#,
+        observation
+    )
+}
+
+pub fn build_tool_result_prompt(tool_name: &str, result: &Value) -> String {
+    format!(
+        r#"Tool '{}' returned:
+{}
+
+What is your next thought and action?"#,
+        tool_name,
+        serde_json::to_string_pretty(result).unwrap_or_default()
+    )
+}
+
+pub fn build_final_response_prompt(completed: bool) -> String {
+    if completed {
+        r#"Task completed! Please provide a summary of what was accomplished."#
+    } else {
+        r#"Please provide your final response or next action."#
+    }
+    .to_string()
+}
+
+pub fn build_repeated_observation_prompt(repeats: usize) -> String {
+    format!(
+        r#"The last {} tool results were identical, so your current approach is not making progress. Do not repeat it. Try a different strategy, or if you are blocked, give a FINAL answer that explains what is in the way and asks the user how to proceed."#,
+        repeats
+    )
+}
+
+pub fn build_empty_turn_prompt() -> String {
+    r#"Your last response was empty. Continue with your next thought and either a tool call or a FINAL answer."#.to_string()
+}
+
+pub fn build_max_steps_prompt(max_steps: usize) -> String {
+    format!(
+        r#"You have used all {} steps for this task and cannot call any more tools. Sum up the run for the user in Markdown with exactly these sections:
+
+## Done
+## Remaining
+
+Name the files you changed and the commands you ran, and list the remaining work as a bulleted todo list."#,
+        max_steps
+    )
+}
+
+pub fn build_steering_prompt(guidance: &str) -> String {
+    format!(
+        r#"The user added guidance while you were working. Take it into account from now on, even if it changes your plan:
+
+{}"#,
+        guidance
+    )
+}
+
+pub fn build_clarify_prompt(task: &str, overview: &str, max_questions: usize) -> String {
+    format!(
+        r#"Before starting on the task below, decide whether it is clear enough to do without guessing at what the user wants. Do not call any tools.
+
+If it is clear, reply with just READY. Otherwise ask at most {} short questions, each on its own line starting with "Q:". Only ask what the repository overview cannot answer and what would change the result.
+
+Task:
+{}
+
+Repository overview:
+{}"#,
+        max_questions,
+        task.trim(),
+        overview
+    )
+}
+
+pub fn build_handoff_prompt(task: &str, transcript: &str) -> String {
+    format!(
+        r#"You are handing off an unfinished coding session to a fresh agent that will not see the transcript below. Write handoff notes in Markdown with exactly these sections:
+
+## Goal
+## Done so far
+## State of the code
+## Remaining work
+
+Be specific: name files, functions and commands. List remaining work as a bulleted todo list. Do not call any tools.
+
+Task:
+{}
+
+Transcript:
+{}"#,
+        task.trim(),
+        transcript
+    )
+}
+
+pub fn build_session_title_prompt(task: &str, transcript: &str) -> String {
+    format!(
+        r#"Describe this coding session for a list of past sessions. Reply with exactly these two lines and nothing else:
+
+Title: <what the session was about, in at most 8 words, no full stop>
+Summary: <one paragraph: what was asked, what was done, and how it ended>
+
+Task:
+{}
+
+Transcript:
+{}"#,
+        task.trim(),
+        transcript
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_build_code_agent_prompt_with_tools() {
+        let tools = vec![
+            crate::clients::ToolDefinition {
+                name: "read_file".to_string(),
+                description: "Read a file".to_string(),
+                parameters: serde_json::json!({}),
+            },
+            crate::clients::ToolDefinition {
+                name: "write_file".to_string(),
+                description: "Write a file".to_string(),
+                parameters: serde_json::json!({}),
+            },
+        ];
+
+        let prompt = build_code_agent_prompt(&tools, None);
+
+        assert!(prompt.contains("read_file"));
+        assert!(prompt.contains("write_file"));
+        assert!(prompt.contains("TOOL_CALL:"));
+        assert!(prompt.contains("FINAL:"));
+    }
+
+    #[test]
+    fn test_build_code_agent_prompt_empty_tools() {
+        let tools = vec![];
+        let prompt = build_code_agent_prompt(&tools, None);
+
+        assert!(prompt.contains("no tools available"));
+    }
+
+    #[test]
+    fn test_build_code_agent_prompt_custom_system() {
+        let tools = vec![];
+        let custom_prompt = "You are a custom assistant.".to_string();
+        let prompt = build_code_agent_prompt(&tools, Some(custom_prompt.clone()));
+
+        assert_eq!(prompt, custom_prompt);
+    }
+
+    #[test]
+    fn test_build_step_prompt() {
+        assert_eq!(
+            build_step_prompt(2, 10, None, Duration::from_secs(75)),
+            "Step 2/10 (8 left after this one, 1m 15s elapsed): What is your next thought and action?"
+        );
+        assert_eq!(
+            build_step_prompt(2, 10, Some((45_000, 50_000)), Duration::from_secs(5)),
+            "Step 2/10 (8 left after this one, 5000 of 50000 tokens left, 5s elapsed): Resources are running low. \
+             Do what matters most and give your final answer soon."
+        );
+        assert!(build_step_prompt(8, 10, None, Duration::ZERO).contains("running low"));
+        assert!(build_step_prompt(10, 10, None, Duration::from_secs(3700)).starts_with("Step 10/10 (1h 1m elapsed): This is your last step."));
+    }
+}
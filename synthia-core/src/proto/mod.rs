@@ -0,0 +1,622 @@
+//! JSON-RPC 2.0 embedding protocol spoken over a pair of byte streams,
+//! normally the agent's stdin and stdout.
+//!
+//! Every message is a single JSON object on its own line. Requests:
+//!
+//! - `initialize` → `{"protocol_version": 1, "server": "synthia-agent"}`
+//! - `session/start` `{"workdir"?: string, "max_steps"?: number,
+//!   "read_only"?: bool}` →
+//!   `{"session_id": string}`
+//! - `session/send` `{"session_id": string, "message": string,
+//!   "deltas"?: bool}` runs the message as a task, with the session's
+//!   earlier messages as context, and answers with
+//!   `{"session_id", "steps": number, "stop_reason": "finished" |
+//!   "max_steps" | "token_budget" | "interrupted", "summary": string |
+//!   null, "final_answer": string | null,
+//!   "assessment": object | null, "needs_review": bool}` once the run is
+//!   over. `summary` says what was done and what remains when the run ran
+//!   out of steps. `assessment` is the agent's own `{"tests_passed",
+//!   "unverified", "risks", "confidence"}`; without one, `needs_review` is
+//!   true
+//! - `session/compact` `{"session_id": string, "apply"?: bool,
+//!   "summary"?: string}` folds the conversation carried between messages
+//!   into a summary. Without `apply` it only previews
+//!   `{"summary", "tokens_before", "tokens_after", "applied": false}`;
+//!   with it, `summary` (if given) replaces the generated one and the
+//!   result is applied
+//! - `session/close` `{"session_id": string}` → `{}`
+//! - `shutdown` → `{}`, after which the server stops reading
+//!
+//! While `session/send` is running the server emits `session/event`
+//! notifications `{"session_id", "event": "step", "index", "step"}`, one per
+//! completed step. With `deltas` it also emits
+//! `{"session_id", "event": "thought_delta" | "answer_delta", "text"}` as the
+//! model's text streams in. Agent failures are reported as error code
+//! `-32000`.
+//!
+//! Calls the permission policy asks about, such as commands and writes by
+//! default, are sent to the client as a `session/approve` request
+//! `{"session_id", "tool", "arguments", "action"}`, where `action` says
+//! what the call does, e.g. "running `cargo test`". The client answers
+//! `{"approved": bool}`; an error answer, or stdin closing, refuses the
+//! call. Other requests sent while a message runs are handled once it is
+//! over.
+
+use crate::core::{DeltaCallback, ReactAgent, Step, StepCallback, assessment, final_answer};
+use crate::protocol::Delta;
+use crate::tools::{ApprovalRequest, Approver};
+use async_trait::async_trait;
+use serde::{Deserialize, Serialize};
+use serde_json::{Value, json};
+use std::collections::{HashMap, VecDeque};
+use std::path::PathBuf;
+use std::sync::Arc;
+use tokio::io::{AsyncBufRead, AsyncBufReadExt, AsyncWrite, AsyncWriteExt, Lines};
+use tokio::sync::{mpsc, oneshot};
+
+pub const PROTOCOL_VERSION: u32 = 1;
+
+pub const PARSE_ERROR: i64 = -32700;
+pub const INVALID_REQUEST: i64 = -32600;
+pub const METHOD_NOT_FOUND: i64 = -32601;
+pub const INVALID_PARAMS: i64 = -32602;
+pub const AGENT_ERROR: i64 = -32000;
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct Request {
+    pub jsonrpc: String,
+    #[serde(default)]
+    pub id: Option<Value>,
+    pub method: String,
+    #[serde(default)]
+    pub params: Value,
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct RpcError {
+    pub code: i64,
+    pub message: String,
+}
+
+impl RpcError {
+    pub fn new(code: i64, message: impl Into<String>) -> Self {
+        Self {
+            code,
+            message: message.into(),
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Deserialize)]
+pub struct SessionOptions {
+    #[serde(default = "default_workdir")]
+    pub workdir: PathBuf,
+    #[serde(default)]
+    pub max_steps: Option<usize>,
+    #[serde(default)]
+    pub read_only: bool,
+}
+
+fn default_workdir() -> PathBuf {
+    PathBuf::from(".")
+}
+
+#[derive(Deserialize)]
+struct SendParams {
+    session_id: String,
+    message: String,
+    #[serde(default)]
+    deltas: bool,
+}
+
+#[derive(Deserialize)]
+struct CompactParams {
+    session_id: String,
+    #[serde(default)]
+    apply: bool,
+    #[serde(default)]
+    summary: Option<String>,
+}
+
+#[derive(Deserialize)]
+struct CloseParams {
+    session_id: String,
+}
+
+/// Builds a session's agent. The approver, if given, is who the agent
+/// asks about the calls its permission policy holds.
+pub type AgentFactory = Box<dyn Fn(&SessionOptions, Option<Arc<dyn Approver>>) -> ReactAgent + Send + Sync>;
+
+/// A `session/approve` request's params, and where its answer goes.
+type ApprovalQuestion = (Value, oneshot::Sender<bool>);
+
+/// Asks the client about a session's held calls with `session/approve`.
+struct ClientApprover {
+    session_id: String,
+    questions: mpsc::UnboundedSender<ApprovalQuestion>,
+}
+
+#[async_trait]
+impl Approver for ClientApprover {
+    async fn approve(&self, request: &ApprovalRequest) -> bool {
+        let (tx, rx) = oneshot::channel();
+        let params = json!({
+            "session_id": self.session_id,
+            "tool": request.tool,
+            "arguments": request.arguments,
+            "action": request.action,
+        });
+        if self.questions.send((params, tx)).is_err() {
+            return false;
+        }
+        rx.await.unwrap_or(false)
+    }
+}
+
+/// The client's messages: lines read while a message ran, then the rest.
+struct Input<R> {
+    lines: Lines<R>,
+    deferred: VecDeque<String>,
+    closed: bool,
+}
+
+impl<R: AsyncBufRead + Unpin> Input<R> {
+    async fn next_line(&mut self) -> std::io::Result<Option<String>> {
+        if let Some(line) = self.deferred.pop_front() {
+            return Ok(Some(line));
+        }
+        if self.closed {
+            return Ok(None);
+        }
+        self.lines.next_line().await
+    }
+}
+
+pub struct ProtoServer {
+    factory: AgentFactory,
+    sessions: HashMap<String, ReactAgent>,
+    next_session: usize,
+    questions: mpsc::UnboundedSender<ApprovalQuestion>,
+    pending_questions: mpsc::UnboundedReceiver<ApprovalQuestion>,
+    next_question: u64,
+}
+
+impl ProtoServer {
+    pub fn new(factory: AgentFactory) -> Self {
+        let (questions, pending_questions) = mpsc::unbounded_channel();
+        Self {
+            factory,
+            sessions: HashMap::new(),
+            next_session: 0,
+            questions,
+            pending_questions,
+            next_question: 0,
+        }
+    }
+
+    pub async fn serve<R, W>(&mut self, reader: R, mut writer: W) -> std::io::Result<()>
+    where
+        R: AsyncBufRead + Unpin,
+        W: AsyncWrite + Unpin,
+    {
+        let mut input = Input {
+            lines: reader.lines(),
+            deferred: VecDeque::new(),
+            closed: false,
+        };
+
+        while let Some(line) = input.next_line().await? {
+            // Blank lines, and answers to approvals that are no longer
+            // waiting.
+            if line.trim().is_empty() || approval_answer(&line).is_some() {
+                continue;
+            }
+
+            let request = match serde_json::from_str::<Value>(&line) {
+                Ok(value) => value,
+                Err(e) => {
+                    let error = RpcError::new(PARSE_ERROR, e.to_string());
+                    write_message(&mut writer, &error_response(Value::Null, error)).await?;
+                    continue;
+                }
+            };
+
+            let request: Request = match serde_json::from_value(request.clone()) {
+                Ok(request) => request,
+                Err(e) => {
+                    let id = request.get("id").cloned().unwrap_or(Value::Null);
+                    let error = RpcError::new(INVALID_REQUEST, e.to_string());
+                    write_message(&mut writer, &error_response(id, error)).await?;
+                    continue;
+                }
+            };
+
+            let shutdown = request.method == "shutdown";
+            let result = self.dispatch(&request, &mut input, &mut writer).await?;
+
+            // Requests without an id are notifications and get no reply.
+            if let Some(id) = request.id {
+                let response = match result {
+                    Ok(result) => json!({"jsonrpc": "2.0", "id": id, "result": result}),
+                    Err(error) => error_response(id, error),
+                };
+                write_message(&mut writer, &response).await?;
+            }
+
+            if shutdown {
+                break;
+            }
+        }
+
+        Ok(())
+    }
+
+    async fn dispatch<R: AsyncBufRead + Unpin, W: AsyncWrite + Unpin>(
+        &mut self,
+        request: &Request,
+        input: &mut Input<R>,
+        writer: &mut W,
+    ) -> std::io::Result<Result<Value, RpcError>> {
+        let result = match request.method.as_str() {
+            "initialize" => Ok(json!({
+                "protocol_version": PROTOCOL_VERSION,
+                "server": "synthia-agent",
+            })),
+            "session/start" => self.start_session(&request.params),
+            "session/send" => return self.send_message(&request.params, input, writer).await,
+            "session/compact" => self.compact_session(&request.params),
+            "session/close" => self.close_session(&request.params),
+            "shutdown" => {
+                self.sessions.clear();
+                Ok(json!({}))
+            }
+            other => Err(RpcError::new(METHOD_NOT_FOUND, format!("Unknown method: {}", other))),
+        };
+        Ok(result)
+    }
+
+    fn start_session(&mut self, params: &Value) -> Result<Value, RpcError> {
+        let params = if params.is_null() { json!({}) } else { params.clone() };
+        let options: SessionOptions = serde_json::from_value(params)
+            .map_err(|e| RpcError::new(INVALID_PARAMS, e.to_string()))?;
+
+        self.next_session += 1;
+        let session_id = format!("session-{}", self.next_session);
+
+        let approver = ClientApprover {
+            session_id: session_id.clone(),
+            questions: self.questions.clone(),
+        };
+        let agent = (self.factory)(&options, Some(Arc::new(approver)));
+        self.sessions.insert(session_id.clone(), agent);
+
+        Ok(json!({ "session_id": session_id }))
+    }
+
+    async fn send_message<R: AsyncBufRead + Unpin, W: AsyncWrite + Unpin>(
+        &mut self,
+        params: &Value,
+        input: &mut Input<R>,
+        writer: &mut W,
+    ) -> std::io::Result<Result<Value, RpcError>> {
+        let params: SendParams = match serde_json::from_value(params.clone()) {
+            Ok(params) => params,
+            Err(e) => return Ok(Err(RpcError::new(INVALID_PARAMS, e.to_string()))),
+        };
+
+        let Some(agent) = self.sessions.get_mut(&params.session_id) else {
+            return Ok(Err(RpcError::new(
+                INVALID_PARAMS,
+                format!("Unknown session: {}", params.session_id),
+            )));
+        };
+
+        let (tx, mut rx) = mpsc::unbounded_channel::<Value>();
+        if params.deltas {
+            let tx = tx.clone();
+            let session_id = params.session_id.clone();
+            let callback: DeltaCallback = Arc::new(move |delta: Delta| {
+                let (event, text) = match delta {
+                    Delta::Thought(text) => ("thought_delta", text),
+                    Delta::Answer(text) => ("answer_delta", text),
+                };
+                let _ = tx.send(json!({
+                    "jsonrpc": "2.0",
+                    "method": "session/event",
+                    "params": {
+                        "session_id": session_id,
+                        "event": event,
+                        "text": text,
+                    },
+                }));
+            });
+            agent.set_delta_callback(Some(callback));
+        }
+        let session_id = params.session_id.clone();
+        let callback: StepCallback = Arc::new(move |index: usize, step: Step| {
+            let _ = tx.send(json!({
+                "jsonrpc": "2.0",
+                "method": "session/event",
+                "params": {
+                    "session_id": session_id,
+                    "event": "step",
+                    "index": index,
+                    "step": step,
+                },
+            }));
+        });
+        agent.set_step_callback(Some(callback));
+
+        let outcome = {
+            let run = agent.run(&params.message);
+            tokio::pin!(run);
+            let mut waiting: HashMap<u64, oneshot::Sender<bool>> = HashMap::new();
+
+            loop {
+                tokio::select! {
+                    outcome = &mut run => break outcome,
+                    Some(notification) = rx.recv() => write_message(writer, &notification).await?,
+                    Some((question, answer)) = self.pending_questions.recv() => {
+                        // No one is left to answer.
+                        if input.closed {
+                            let _ = answer.send(false);
+                            continue;
+                        }
+                        self.next_question += 1;
+                        waiting.insert(self.next_question, answer);
+                        let request = json!({
+                            "jsonrpc": "2.0",
+                            "id": self.next_question,
+                            "method": "session/approve",
+                            "params": question,
+                        });
+                        write_message(writer, &request).await?;
+                    }
+                    line = input.lines.next_line(), if !input.closed => match line? {
+                        Some(line) => {
+                            let answer = approval_answer(&line)
+                                .and_then(|(id, approved)| Some((waiting.remove(&id)?, approved)));
+                            match answer {
+                                Some((answer, approved)) => {
+                                    let _ = answer.send(approved);
+                                }
+                                None => input.deferred.push_back(line),
+                            }
+                        }
+                        None => {
+                            input.closed = true;
+                            waiting.clear();
+                        }
+                    },
+                }
+            }
+        };
+
+        agent.set_step_callback(None);
+        agent.set_delta_callback(None);
+        while let Ok(notification) = rx.try_recv() {
+            write_message(writer, &notification).await?;
+        }
+
+        Ok(match outcome {
+            Ok(result) => {
+                let assessment = assessment(&result.steps);
+                Ok(json!({
+                    "session_id": params.session_id,
+                    "steps": result.steps.len(),
+                    "stop_reason": result.stop_reason,
+                    "summary": result.summary,
+                    "final_answer": final_answer(&result.steps),
+                    "needs_review": assessment.as_ref().is_none_or(|assessment| assessment.needs_review()),
+                    "assessment": assessment,
+                }))
+            }
+            Err(e) => Err(RpcError::new(AGENT_ERROR, e.to_string())),
+        })
+    }
+
+    fn compact_session(&mut self, params: &Value) -> Result<Value, RpcError> {
+        let params: CompactParams = serde_json::from_value(params.clone())
+            .map_err(|e| RpcError::new(INVALID_PARAMS, e.to_string()))?;
+
+        let agent = self.sessions.get_mut(&params.session_id).ok_or_else(|| {
+            RpcError::new(INVALID_PARAMS, format!("Unknown session: {}", params.session_id))
+        })?;
+        let mut compaction = agent
+            .preview_compaction()
+            .ok_or_else(|| RpcError::new(INVALID_REQUEST, "Nothing to compact yet"))?;
+
+        if let Some(summary) = params.summary {
+            compaction.summary = summary;
+        }
+        if params.apply {
+            agent.apply_compaction(&compaction);
+        }
+
+        Ok(json!({
+            "summary": compaction.summary,
+            "tokens_before": compaction.tokens_before,
+            "tokens_after": compaction.tokens_after(),
+            "applied": params.apply,
+        }))
+    }
+
+    fn close_session(&mut self, params: &Value) -> Result<Value, RpcError> {
+        let params: CloseParams = serde_json::from_value(params.clone())
+            .map_err(|e| RpcError::new(INVALID_PARAMS, e.to_string()))?;
+
+        match self.sessions.remove(&params.session_id) {
+            Some(_) => Ok(json!({})),
+            None => Err(RpcError::new(
+                INVALID_PARAMS,
+                format!("Unknown session: {}", params.session_id),
+            )),
+        }
+    }
+}
+
+/// The id and answer of a response to `session/approve`; anything but
+/// `{"approved": true}` refuses.
+fn approval_answer(line: &str) -> Option<(u64, bool)> {
+    let message: Value = serde_json::from_str(line).ok()?;
+    if message.get("method").is_some() || (message.get("result").is_none() && message.get("error").is_none()) {
+        return None;
+    }
+    let id = message.get("id")?.as_u64()?;
+    let approved = message.pointer("/result/approved").and_then(Value::as_bool).unwrap_or(false);
+    Some((id, approved))
+}
+
+fn error_response(id: Value, error: RpcError) -> Value {
+    json!({"jsonrpc": "2.0", "id": id, "error": error})
+}
+
+async fn write_message<W: AsyncWrite + Unpin>(writer: &mut W, message: &Value) -> std::io::Result<()> {
+    let mut line = serde_json::to_string(message).map_err(std::io::Error::other)?;
+    line.push('\n');
+    writer.write_all(line.as_bytes()).await?;
+    writer.flush().await
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::clients::{OpenAIClient, ScriptedClient};
+    use crate::tools::{ToolManager, default_tools};
+    use tokio::io::{AsyncBufReadExt, BufReader};
+
+    fn server() -> ProtoServer {
+        ProtoServer::new(Box::new(|options, _| {
+            ReactAgent::new(
+                Box::new(OpenAIClient::new("test_key".to_string(), "gpt-4".to_string(), None)),
+                ToolManager::new(),
+                options.workdir.clone(),
+                options.max_steps,
+                Some(true),
+                None,
+            )
+        }))
+    }
+
+    async fn roundtrip(input: &str) -> Vec<Value> {
+        let mut output = Vec::new();
+        server().serve(input.as_bytes(), &mut output).await.unwrap();
+        String::from_utf8(output)
+            .unwrap()
+            .lines()
+            .map(|line| serde_json::from_str(line).unwrap())
+            .collect()
+    }
+
+    #[tokio::test]
+    async fn test_initialize_and_sessions() {
+        let responses = roundtrip(concat!(
+            r#"{"jsonrpc":"2.0","id":1,"method":"initialize"}"#,
+            "\n",
+            r#"{"jsonrpc":"2.0","id":2,"method":"session/start","params":{"workdir":"/tmp"}}"#,
+            "\n",
+            r#"{"jsonrpc":"2.0","id":3,"method":"session/close","params":{"session_id":"session-1"}}"#,
+            "\n",
+            r#"{"jsonrpc":"2.0","id":4,"method":"session/close","params":{"session_id":"session-1"}}"#,
+            "\n",
+        ))
+        .await;
+
+        assert_eq!(responses[0]["result"]["protocol_version"], PROTOCOL_VERSION);
+        assert_eq!(responses[1]["result"]["session_id"], "session-1");
+        assert_eq!(responses[2]["result"], json!({}));
+        assert_eq!(responses[3]["error"]["code"], INVALID_PARAMS);
+    }
+
+    #[tokio::test]
+    async fn test_compact_empty_session() {
+        let responses = roundtrip(concat!(
+            r#"{"jsonrpc":"2.0","id":1,"method":"session/start"}"#,
+            "\n",
+            r#"{"jsonrpc":"2.0","id":2,"method":"session/compact","params":{"session_id":"session-1"}}"#,
+            "\n",
+            r#"{"jsonrpc":"2.0","id":3,"method":"session/compact","params":{"session_id":"session-9"}}"#,
+            "\n",
+        ))
+        .await;
+
+        assert_eq!(responses[1]["error"]["code"], INVALID_REQUEST);
+        assert_eq!(responses[2]["error"]["code"], INVALID_PARAMS);
+    }
+
+    #[tokio::test]
+    async fn test_errors_and_shutdown() {
+        let responses = roundtrip(concat!(
+            "not json\n",
+            r#"{"jsonrpc":"2.0","id":1,"method":"nope"}"#,
+            "\n",
+            r#"{"jsonrpc":"2.0","method":"session/start"}"#,
+            "\n",
+            r#"{"jsonrpc":"2.0","id":2,"method":"shutdown"}"#,
+            "\n",
+            r#"{"jsonrpc":"2.0","id":3,"method":"initialize"}"#,
+            "\n",
+        ))
+        .await;
+
+        assert_eq!(responses.len(), 3);
+        assert_eq!(responses[0]["error"]["code"], PARSE_ERROR);
+        assert_eq!(responses[1]["error"]["code"], METHOD_NOT_FOUND);
+        assert_eq!(responses[2]["id"], 2);
+    }
+
+    #[tokio::test]
+    async fn test_session_approve() {
+        let dir = tempfile::tempdir().unwrap();
+        let mut server = ProtoServer::new(Box::new(|options, approver| {
+            let client = ScriptedClient::from_responses([
+                ScriptedClient::tool_call("write_file", json!({"path": "a.txt", "content": "a"})),
+                ScriptedClient::tool_call("write_file", json!({"path": "b.txt", "content": "b"})),
+                "FINAL: Wrote a.txt.".to_string(),
+            ]);
+            ReactAgent::new(
+                Box::new(client),
+                default_tools(options.workdir.clone()),
+                options.workdir.clone(),
+                Some(5),
+                Some(false),
+                None,
+            )
+            .with_approver(approver)
+        }));
+        let (client, server_end) = tokio::io::duplex(64 * 1024);
+        let (server_read, server_write) = tokio::io::split(server_end);
+        let serving = tokio::spawn(async move { server.serve(BufReader::new(server_read), server_write).await });
+        let (client_read, mut client_write) = tokio::io::split(client);
+        let mut lines = BufReader::new(client_read).lines();
+
+        let start = json!({"jsonrpc": "2.0", "id": 1, "method": "session/start", "params": {"workdir": dir.path()}});
+        write_message(&mut client_write, &start).await.unwrap();
+        let send = json!({"jsonrpc": "2.0", "id": 2, "method": "session/send",
+                          "params": {"session_id": "session-1", "message": "Write files"}});
+        write_message(&mut client_write, &send).await.unwrap();
+
+        let mut actions = Vec::new();
+        let result = loop {
+            let message: Value = serde_json::from_str(&lines.next_line().await.unwrap().unwrap()).unwrap();
+            if message["method"] == "session/approve" {
+                let action = message["params"]["action"].as_str().unwrap().to_string();
+                let answer = json!({"jsonrpc": "2.0", "id": message["id"],
+                                    "result": {"approved": action.contains("a.txt")}});
+                write_message(&mut client_write, &answer).await.unwrap();
+                actions.push(action);
+            } else if message["id"] == 2 {
+                break message["result"].clone();
+            }
+        };
+
+        assert_eq!(actions, ["writing `a.txt`", "writing `b.txt`"]);
+        assert_eq!(result["final_answer"], "Wrote a.txt.");
+        assert!(dir.path().join("a.txt").exists());
+        assert!(!dir.path().join("b.txt").exists());
+
+        let shutdown = json!({"jsonrpc": "2.0", "id": 3, "method": "shutdown"});
+        write_message(&mut client_write, &shutdown).await.unwrap();
+        serving.await.unwrap().unwrap();
+    }
+}
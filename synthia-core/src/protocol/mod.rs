@@ -0,0 +1,482 @@
+//! Parsing for the text protocol the system prompt asks the model to follow:
+//! `TOOL_CALL: <tool_name>: <arguments_json>` to use a tool, or
+//! `FINAL: <answer>` to finish, optionally followed by
+//! `ASSESSMENT: <json>` with the agent's self-assessment.
+//!
+//! Models follow it loosely, so the parser tolerates code fences around the
+//! call, a missing colon after the tool name, and prose after the arguments.
+//! Arguments are read with a JSON parser rather than split on `:`, so colons
+//! inside strings (Windows paths, URLs) survive.
+
+use serde_json::{Value, json};
+
+pub const TOOL_CALL_MARKER: &str = "TOOL_CALL:";
+pub const FINAL_MARKER: &str = "FINAL:";
+pub const ASSESSMENT_MARKER: &str = "ASSESSMENT:";
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct ToolCallText {
+    pub name: String,
+    pub arguments: Value,
+    /// The arguments as the model wrote them, without fences or trailing text.
+    pub raw_arguments: String,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum Response {
+    ToolCall { thought: String, call: ToolCallText },
+    Final { thought: String, answer: String },
+    Thought(String),
+}
+
+/// Classifies one complete model turn. A tool call wins over `FINAL:` when
+/// both appear, since the answer can't account for a result it hasn't seen.
+pub fn parse_response(text: &str) -> Response {
+    if let Some((thought, call)) = parse_tool_call(text) {
+        return Response::ToolCall { thought, call };
+    }
+
+    match parse_final(text) {
+        Some(answer) => Response::Final {
+            thought: text.to_string(),
+            answer: answer.to_string(),
+        },
+        None => Response::Thought(text.to_string()),
+    }
+}
+
+/// Finds the first tool call in `text` and returns it with the thought that
+/// preceded it.
+pub fn parse_tool_call(text: &str) -> Option<(String, ToolCallText)> {
+    let (before, after) = text.split_once(TOOL_CALL_MARKER)?;
+
+    let rest = after.trim_start_matches(|c: char| c == '`' || c.is_whitespace());
+    let name_len = rest
+        .find(|c: char| !(c.is_alphanumeric() || c == '_' || c == '-' || c == '.'))
+        .unwrap_or(rest.len());
+    if name_len == 0 {
+        return None;
+    }
+    let name = rest[..name_len].to_string();
+
+    let rest = &rest[name_len..];
+    let rest = rest.strip_prefix('`').unwrap_or(rest);
+    let rest = rest.trim_start_matches(|c: char| c == ':' || c.is_whitespace());
+    let rest = skip_fence_opener(rest);
+
+    let (arguments, raw_arguments) = if rest.starts_with('{') || rest.starts_with('[') {
+        let mut values = serde_json::Deserializer::from_str(rest).into_iter::<Value>();
+        match values.next() {
+            Some(Ok(value)) => (value, rest[..values.byte_offset()].to_string()),
+            _ => (json!({}), strip_fence_closer(rest).to_string()),
+        }
+    } else {
+        let line = rest.lines().next().unwrap_or_default();
+        let input = strip_fence_closer(line).trim_end_matches('`').trim().to_string();
+        (json!({ "input": input }), input)
+    };
+
+    Some((
+        strip_trailing_fence(before).to_string(),
+        ToolCallText {
+            name,
+            arguments,
+            raw_arguments,
+        },
+    ))
+}
+
+/// The non-empty text after `FINAL:`, up to any `ASSESSMENT:`.
+pub fn parse_final(text: &str) -> Option<&str> {
+    let (_, answer) = text.split_once(FINAL_MARKER)?;
+    let answer = answer.split_once(ASSESSMENT_MARKER).map(|(answer, _)| answer).unwrap_or(answer).trim();
+    (!answer.is_empty()).then_some(answer)
+}
+
+/// The JSON after `ASSESSMENT:` in a final answer, without code fences.
+pub fn parse_assessment(text: &str) -> Option<&str> {
+    let (_, answer) = text.split_once(FINAL_MARKER)?;
+    let (_, assessment) = answer.split_once(ASSESSMENT_MARKER)?;
+    let assessment = strip_fence_closer(skip_fence_opener(assessment.trim()));
+    (!assessment.is_empty()).then_some(assessment)
+}
+
+pub fn format_tool_call(name: &str, arguments: &str) -> String {
+    format!("{} {}: {}", TOOL_CALL_MARKER, name, arguments)
+}
+
+/// Skips a code fence opener such as "```json\n" at the start of `text`.
+fn skip_fence_opener(text: &str) -> &str {
+    if !text.starts_with("```") {
+        return text;
+    }
+    match text.split_once('\n') {
+        Some((_, rest)) => rest.trim_start(),
+        None => text.trim_start_matches(|c: char| c == '`' || c.is_alphanumeric()).trim_start(),
+    }
+}
+
+fn strip_fence_closer(text: &str) -> &str {
+    let text = text.trim();
+    text.strip_suffix("```").unwrap_or(text).trim_end()
+}
+
+/// Drops a dangling fence opener that belonged to the tool call.
+fn strip_trailing_fence(thought: &str) -> &str {
+    let trimmed = thought.trim_end();
+    match trimmed.rsplit_once('\n') {
+        Some((head, last)) if last.trim_start().starts_with("```") => head.trim_end(),
+        None if trimmed.starts_with("```") => "",
+        _ => trimmed,
+    }
+}
+
+
+/// A piece of a model turn, emitted while the turn is still streaming.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Delta {
+    /// Text before the tool call or final answer.
+    Thought(String),
+    /// Text after [`FINAL_MARKER`].
+    Answer(String),
+}
+
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+enum Section {
+    #[default]
+    Thought,
+    Answer,
+    ToolCall,
+    Assessment,
+}
+
+/// Splits streamed text into [`Delta`]s as it arrives. Text that could be
+/// the start of a marker is held back until the next chunk settles it.
+/// Tool calls and assessments are not emitted; the finished step reports
+/// them.
+#[derive(Debug, Default)]
+pub struct DeltaSplitter {
+    pending: String,
+    section: Section,
+    answer_started: bool,
+}
+
+impl DeltaSplitter {
+    pub fn push(&mut self, text: &str) -> Vec<Delta> {
+        self.pending.push_str(text);
+        let mut deltas = Vec::new();
+        while self.section == Section::Thought {
+            let marker = [(FINAL_MARKER, Section::Answer), (TOOL_CALL_MARKER, Section::ToolCall)]
+                .into_iter()
+                .filter_map(|(marker, section)| self.pending.find(marker).map(|at| (at, marker, section)))
+                .min_by_key(|(at, ..)| *at);
+            let Some((at, marker, section)) = marker else {
+                let emit = self.pending.len() - marker_prefix_len(&self.pending, &[FINAL_MARKER, TOOL_CALL_MARKER]);
+                let thought: String = self.pending.drain(..emit).collect();
+                if !thought.is_empty() {
+                    deltas.push(Delta::Thought(thought));
+                }
+                return deltas;
+            };
+            let thought: String = self.pending.drain(..at + marker.len()).take(at).collect();
+            if !thought.is_empty() {
+                deltas.push(Delta::Thought(thought));
+            }
+            self.section = section;
+        }
+        if self.section == Section::Answer {
+            let answer = match self.pending.find(ASSESSMENT_MARKER) {
+                Some(at) => {
+                    self.section = Section::Assessment;
+                    self.pending[..at].trim_end().to_string()
+                }
+                None => {
+                    let emit = self.pending.len() - marker_prefix_len(&self.pending, &[ASSESSMENT_MARKER]);
+                    self.pending.drain(..emit).collect()
+                }
+            };
+            deltas.extend(self.answer(&answer));
+        }
+        if self.section != Section::Answer {
+            self.pending.clear();
+        }
+        deltas
+    }
+
+    /// Emits whatever was held back, once the turn is over.
+    pub fn finish(&mut self) -> Vec<Delta> {
+        let rest = std::mem::take(&mut self.pending);
+        match self.section {
+            Section::Thought if !rest.is_empty() => vec![Delta::Thought(rest)],
+            Section::Answer => self.answer(&rest).into_iter().collect(),
+            _ => Vec::new(),
+        }
+    }
+
+    fn answer(&mut self, text: &str) -> Option<Delta> {
+        let answer = if self.answer_started { text } else { text.trim_start() };
+        if answer.is_empty() {
+            return None;
+        }
+        self.answer_started = true;
+        Some(Delta::Answer(answer.to_string()))
+    }
+}
+
+/// Length of the longest suffix of `text` that could be the start of one of
+/// `markers`.
+fn marker_prefix_len(text: &str, markers: &[&str]) -> usize {
+    let longest = markers.iter().map(|marker| marker.len()).max().unwrap_or(0);
+    (1..longest)
+        .rev()
+        .filter(|&n| n <= text.len() && text.is_char_boundary(text.len() - n))
+        .find(|&n| {
+            let tail = &text[text.len() - n..];
+            markers.iter().any(|marker| marker.starts_with(tail))
+        })
+        .unwrap_or(0)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use proptest::prelude::*;
+
+    fn parsed_call(text: &str) -> ToolCallText {
+        parse_tool_call(text).unwrap().1
+    }
+
+    #[test]
+    fn test_plain_tool_call() {
+        let (thought, call) =
+            parse_tool_call("I need the file.\nTOOL_CALL: read_file: {\"path\": \"src/main.rs\"}").unwrap();
+
+        assert_eq!(thought, "I need the file.");
+        assert_eq!(call.name, "read_file");
+        assert_eq!(call.arguments, json!({"path": "src/main.rs"}));
+        assert_eq!(call.raw_arguments, r#"{"path": "src/main.rs"}"#);
+    }
+
+    #[test]
+    fn test_colons_inside_strings() {
+        let call = parsed_call(r#"TOOL_CALL: read_file: {"path": "C:\\Users\\me\\src\\main.rs"}"#);
+        assert_eq!(call.arguments["path"], r"C:\Users\me\src\main.rs");
+
+        let call = parsed_call(r#"TOOL_CALL: write_file {"path": "a.txt", "content": "key: value\nurl: http://x"}"#);
+        assert_eq!(call.name, "write_file");
+        assert_eq!(call.arguments["content"], "key: value\nurl: http://x");
+    }
+
+    #[test]
+    fn test_code_fences() {
+        let (thought, call) = parse_tool_call(
+            "Let me look.\n```\nTOOL_CALL: grep: {\"pattern\": \"fn main\"}\n```\n",
+        )
+        .unwrap();
+        assert_eq!(thought, "Let me look.");
+        assert_eq!(call.arguments, json!({"pattern": "fn main"}));
+
+        let call = parsed_call("TOOL_CALL: `list_dir`:\n```json\n{\"path\": \".\"}\n```");
+        assert_eq!(call.name, "list_dir");
+        assert_eq!(call.arguments, json!({"path": "."}));
+    }
+
+    #[test]
+    fn test_nested_json_and_trailing_junk() {
+        let call = parsed_call(
+            "TOOL_CALL: run: {\"opts\": {\"env\": {\"A\": \"}\"}}, \"args\": [\"x:y\"]} and then I'll check the output.",
+        );
+        assert_eq!(call.arguments, json!({"opts": {"env": {"A": "}"}}, "args": ["x:y"]}));
+        assert_eq!(call.raw_arguments, r#"{"opts": {"env": {"A": "}"}}, "args": ["x:y"]}"#);
+    }
+
+    #[test]
+    fn test_non_json_arguments() {
+        let call = parsed_call("TOOL_CALL: run_command: cargo test --workspace\nWaiting for results.");
+        assert_eq!(call.arguments, json!({"input": "cargo test --workspace"}));
+
+        let call = parsed_call("TOOL_CALL: read_file: {\"path\": ");
+        assert_eq!(call.arguments, json!({}));
+    }
+
+    #[test]
+    fn test_parse_response() {
+        assert_eq!(
+            parse_response("All done.\nFINAL: Fixed the bug."),
+            Response::Final {
+                thought: "All done.\nFINAL: Fixed the bug.".to_string(),
+                answer: "Fixed the bug.".to_string(),
+            }
+        );
+        assert_eq!(parse_response("FINAL:   "), Response::Thought("FINAL:   ".to_string()));
+        assert_eq!(parse_response("TOOL_CALL: "), Response::Thought("TOOL_CALL: ".to_string()));
+        assert!(matches!(
+            parse_response("TOOL_CALL: glob: {\"pattern\": \"*.rs\"}\nFINAL: done"),
+            Response::ToolCall { .. }
+        ));
+    }
+
+    fn arguments() -> impl Strategy<Value = Value> {
+        let leaf = prop_oneof![
+            any::<String>().prop_map(Value::from),
+            "[A-Za-z]:\\\\[a-z:\\\\ ]{0,20}".prop_map(Value::from),
+            any::<i64>().prop_map(Value::from),
+            any::<bool>().prop_map(Value::from),
+        ];
+        let value = leaf.prop_recursive(3, 16, 4, |inner| {
+            prop_oneof![
+                prop::collection::vec(inner.clone(), 0..4).prop_map(Value::from),
+                prop::collection::btree_map("[a-z:{}\"]{1,6}", inner, 0..4)
+                    .prop_map(|map| Value::Object(map.into_iter().collect())),
+            ]
+        });
+        prop::collection::btree_map("[a-z_]{1,8}", value, 0..4)
+            .prop_map(|map| Value::Object(map.into_iter().collect()))
+    }
+
+    proptest! {
+        #[test]
+        fn prop_arguments_roundtrip(
+            args in arguments(),
+            name in "[a-z_]{1,12}",
+            thought in "[A-Za-z .,]{0,40}",
+            fenced in any::<bool>(),
+            colon in any::<bool>(),
+            pretty in any::<bool>(),
+            junk in "( [A-Za-z.]{0,20})?",
+        ) {
+            let json = if pretty {
+                serde_json::to_string_pretty(&args).unwrap()
+            } else {
+                serde_json::to_string(&args).unwrap()
+            };
+            let separator = if colon { ": " } else { " " };
+            let call = format!("TOOL_CALL: {}{}{}{}", name, separator, json, junk);
+            let text = if fenced {
+                format!("{}\n```\n{}\n```\n", thought, call)
+            } else {
+                format!("{}\n{}", thought, call)
+            };
+
+            let (parsed_thought, parsed) = parse_tool_call(&text).unwrap();
+
+            prop_assert_eq!(parsed.name, name);
+            prop_assert_eq!(parsed.arguments, args);
+            prop_assert_eq!(parsed_thought, thought.trim_end());
+        }
+
+        #[test]
+        fn prop_split_chunks_reassemble(
+            args in arguments(),
+            thought in "[A-Za-z .,\n]{0,40}",
+            answer in "[A-Za-z][A-Za-z .,]{0,39}",
+            finish in any::<bool>(),
+            splits in prop::collection::vec(any::<prop::sample::Index>(), 0..8),
+        ) {
+            let ending = if finish {
+                format!("{} {}", FINAL_MARKER, answer)
+            } else {
+                format_tool_call("write_file", &serde_json::to_string(&args).unwrap())
+            };
+            let text = format!("{}\n{}", thought, ending);
+            let mut cuts: Vec<usize> = splits
+                .iter()
+                .map(|index| index.index(text.len() + 1))
+                .filter(|cut| text.is_char_boundary(*cut))
+                .collect();
+            cuts.sort();
+
+            // The chunks go through the splitter as a stream would deliver
+            // them, and into the buffer the finished turn is parsed from.
+            let mut splitter = DeltaSplitter::default();
+            let mut buffer = String::new();
+            let (mut thoughts, mut answers) = (String::new(), String::new());
+            let mut start = 0;
+            for cut in cuts.into_iter().chain([text.len()]) {
+                let chunk = &text[start..cut];
+                start = cut;
+                buffer.push_str(chunk);
+                for delta in splitter.push(chunk) {
+                    match delta {
+                        Delta::Thought(text) => thoughts.push_str(&text),
+                        Delta::Answer(text) => answers.push_str(&text),
+                    }
+                }
+            }
+            for delta in splitter.finish() {
+                match delta {
+                    Delta::Thought(text) => thoughts.push_str(&text),
+                    Delta::Answer(text) => answers.push_str(&text),
+                }
+            }
+
+            prop_assert_eq!(thoughts, format!("{}\n", thought));
+            match parse_response(&buffer) {
+                Response::Final { answer: parsed, .. } => {
+                    prop_assert!(finish);
+                    prop_assert_eq!(&answers, &answer);
+                    prop_assert_eq!(parsed, answer.trim_end());
+                }
+                Response::ToolCall { thought: parsed, call } => {
+                    prop_assert!(!finish);
+                    prop_assert_eq!(answers, "");
+                    prop_assert_eq!(parsed, thought.trim_end());
+                    prop_assert_eq!(call.arguments, args);
+                }
+                Response::Thought(_) => prop_assert!(false, "no tool call or answer in {:?}", buffer),
+            }
+        }
+
+        #[test]
+        fn prop_never_panics(text in any::<String>()) {
+            let _ = parse_response(&text);
+            let _ = parse_response(&format!("{}{}", TOOL_CALL_MARKER, text));
+        }
+    }
+
+    #[test]
+    fn test_delta_splitter() {
+        let mut splitter = DeltaSplitter::default();
+        let mut deltas = Vec::new();
+        for chunk in ["Checking the ", "file.\nFI", "NAL: It ", "says hi."] {
+            deltas.extend(splitter.push(chunk));
+        }
+        deltas.extend(splitter.finish());
+        assert_eq!(
+            deltas,
+            vec![
+                Delta::Thought("Checking the ".to_string()),
+                Delta::Thought("file.\n".to_string()),
+                Delta::Answer("It ".to_string()),
+                Delta::Answer("says hi.".to_string()),
+            ]
+        );
+
+        let mut splitter = DeltaSplitter::default();
+        let mut deltas = splitter.push("Read it.\nTOOL_");
+        deltas.extend(splitter.push("CALL: read_file: {}"));
+        deltas.extend(splitter.finish());
+        assert_eq!(deltas, vec![Delta::Thought("Read it.\n".to_string())]);
+
+        // A held-back tail that turns out not to be a marker is still shown.
+        let mut splitter = DeltaSplitter::default();
+        assert_eq!(splitter.push("Almost F"), vec![Delta::Thought("Almost ".to_string())]);
+        assert_eq!(splitter.finish(), vec![Delta::Thought("F".to_string())]);
+
+        // The assessment is not part of the answer.
+        let mut splitter = DeltaSplitter::default();
+        let mut deltas = splitter.push("FINAL: Fixed.\nASSESS");
+        deltas.extend(splitter.push("MENT: {\"risks\": []}"));
+        deltas.extend(splitter.finish());
+        assert_eq!(deltas, vec![Delta::Answer("Fixed.\n".to_string())]);
+    }
+
+    #[test]
+    fn test_final_with_assessment() {
+        let text = "Done.\nFINAL: Fixed the bug.\nASSESSMENT:\n```json\n{\"tests_passed\": true}\n```";
+        assert_eq!(parse_final(text), Some("Fixed the bug."));
+        assert_eq!(parse_assessment(text), Some("{\"tests_passed\": true}"));
+        assert_eq!(parse_assessment("FINAL: Fixed the bug."), None);
+        assert_eq!(parse_final("FINAL:\nASSESSMENT: {}"), None);
+    }
+}
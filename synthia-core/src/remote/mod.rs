@@ -0,0 +1,238 @@
+//! Working on another machine over SSH. The file tools and `run_command`
+//! run there through the local `ssh` client, using only a POSIX shell and
+//! standard utilities on the remote, so nothing has to be installed there.
+
+mod tools;
+
+use crate::tools::ToolError;
+use serde::{Deserialize, Serialize};
+use std::path::PathBuf;
+use std::process::Stdio;
+use tokio::io::AsyncWriteExt;
+
+pub use tools::{
+    RemoteFileReadTool, RemoteFileWriteTool, RemoteGlobTool, RemoteGrepTool, RemoteListDirTool, RemoteRunCommandTool,
+    remote_tools,
+};
+
+/// A host to work on, as a named profile under `remotes` in the config.
+///
+/// ```json
+/// {
+///   "remotes": {
+///     "devbox": { "host": "dev.example.com", "user": "me", "workdir": "src/app" }
+///   }
+/// }
+/// ```
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct RemoteConfig {
+    pub host: String,
+    pub user: Option<String>,
+    pub port: Option<u16>,
+    pub identity_file: Option<PathBuf>,
+    /// The directory paths are relative to and commands run in; the login
+    /// directory if not set.
+    pub workdir: Option<String>,
+    /// Extra arguments for `ssh`, e.g. `["-o", "ProxyJump=bastion"]`.
+    #[serde(default)]
+    pub ssh_args: Vec<String>,
+    /// Steps a run on this host may take, before the config's `max_steps`.
+    pub max_steps: Option<usize>,
+}
+
+/// `text` quoted for a POSIX shell.
+pub(crate) fn quote(text: &str) -> String {
+    format!("'{}'", text.replace('\'', r"'\''"))
+}
+
+/// `dir`, made if missing and readable only by the user. `None` if it
+/// can't be made so, e.g. because someone else owns it.
+fn private_dir(dir: PathBuf) -> Option<PathBuf> {
+    #[cfg(unix)]
+    {
+        use std::os::unix::fs::{DirBuilderExt, PermissionsExt};
+        match std::fs::DirBuilder::new().mode(0o700).create(&dir) {
+            Ok(()) => {}
+            Err(e) if e.kind() == std::io::ErrorKind::AlreadyExists => {}
+            Err(_) => return None,
+        }
+        // Only the owner can change the mode, so this fails for a
+        // directory someone else made first.
+        let metadata = std::fs::symlink_metadata(&dir).ok()?;
+        if !metadata.is_dir() {
+            return None;
+        }
+        if metadata.permissions().mode() & 0o077 != 0 {
+            std::fs::set_permissions(&dir, std::fs::Permissions::from_mode(0o700)).ok()?;
+        }
+        Some(dir)
+    }
+    #[cfg(not(unix))]
+    {
+        std::fs::create_dir_all(&dir).ok()?;
+        Some(dir)
+    }
+}
+
+/// Where connection sockets go: the user's runtime directory, or else
+/// `~/.ssh`, never a directory other users can write to.
+fn control_dir() -> Option<PathBuf> {
+    let base = std::env::var_os("XDG_RUNTIME_DIR")
+        .map(PathBuf::from)
+        .filter(|dir| dir.is_absolute() && dir.is_dir())
+        .or_else(|| std::env::home_dir().map(|home| home.join(".ssh")).filter(|dir| dir.is_dir()))?;
+    private_dir(base.join("synthia"))
+}
+
+/// Runs scripts on a [`RemoteConfig`]'s host. Connections are shared
+/// between calls with SSH's `ControlMaster`, so only the first call pays
+/// for the handshake.
+#[derive(Debug, Clone)]
+pub struct SshHost {
+    config: RemoteConfig,
+    program: String,
+    /// Where shared connections' sockets go; each call connects anew
+    /// without one.
+    control_dir: Option<PathBuf>,
+}
+
+impl SshHost {
+    pub fn new(config: RemoteConfig) -> Self {
+        Self {
+            config,
+            program: "ssh".to_string(),
+            control_dir: control_dir(),
+        }
+    }
+
+    /// Runs scripts with `program` instead of `ssh`, which gets the same
+    /// arguments with the script last.
+    #[cfg(test)]
+    fn with_program(mut self, program: impl Into<String>) -> Self {
+        self.program = program.into();
+        self
+    }
+
+    /// `user@host:workdir`, for messages.
+    pub fn name(&self) -> String {
+        let mut name = match &self.config.user {
+            Some(user) => format!("{}@{}", user, self.config.host),
+            None => self.config.host.clone(),
+        };
+        if let Some(workdir) = &self.config.workdir {
+            name.push(':');
+            name.push_str(workdir);
+        }
+        name
+    }
+
+    fn args(&self) -> Vec<String> {
+        let mut args = vec!["-o".to_string(), "BatchMode=yes".to_string()];
+        if let Some(dir) = &self.control_dir {
+            args.extend([
+                "-o".to_string(),
+                "ControlMaster=auto".to_string(),
+                "-o".to_string(),
+                format!("ControlPath={}", dir.join("%C").display()),
+                "-o".to_string(),
+                "ControlPersist=60".to_string(),
+            ]);
+        }
+        if let Some(port) = self.config.port {
+            args.extend(["-p".to_string(), port.to_string()]);
+        }
+        if let Some(identity) = &self.config.identity_file {
+            args.extend(["-i".to_string(), identity.to_string_lossy().to_string()]);
+        }
+        if let Some(user) = &self.config.user {
+            args.extend(["-l".to_string(), user.clone()]);
+        }
+        args.extend(self.config.ssh_args.iter().cloned());
+        args.push(self.config.host.clone());
+        args
+    }
+
+    /// A command that runs `script` in the remote working directory.
+    pub(crate) fn command(&self, script: &str) -> tokio::process::Command {
+        let script = match &self.config.workdir {
+            Some(workdir) => format!("cd {} || exit 1\n{}", quote(workdir), script),
+            None => script.to_string(),
+        };
+        let mut command = tokio::process::Command::new(&self.program);
+        command.args(self.args()).arg(script).kill_on_drop(true);
+        command
+    }
+
+    /// Runs `script` with `stdin` as its input and returns its output.
+    /// Fails if `ssh` can't be started or can't reach the host.
+    pub(crate) async fn output(&self, script: &str, stdin: &[u8]) -> Result<std::process::Output, ToolError> {
+        let mut child = self
+            .command(script)
+            .stdin(Stdio::piped())
+            .stdout(Stdio::piped())
+            .stderr(Stdio::piped())
+            .spawn()
+            .map_err(|e| ToolError::ExecutionFailed(format!("Failed to start ssh: {}", e)))?;
+        if let Some(mut input) = child.stdin.take() {
+            input.write_all(stdin).await?;
+        }
+        let output = child.wait_with_output().await?;
+        // ssh exits with 255 when it could not connect.
+        if output.status.code() == Some(255) {
+            return Err(ToolError::ExecutionFailed(format!(
+                "ssh {}: {}",
+                self.name(),
+                String::from_utf8_lossy(&output.stderr).trim()
+            )));
+        }
+        Ok(output)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_ssh_command() {
+        let config: RemoteConfig = serde_json::from_value(serde_json::json!({
+            "host": "dev.example.com",
+            "user": "me",
+            "port": 2222,
+            "workdir": "it's here",
+            "ssh_args": ["-o", "ProxyJump=bastion"]
+        }))
+        .unwrap();
+        let mut host = SshHost::new(config);
+        let dir = tempfile::tempdir().unwrap();
+        host.control_dir = Some(dir.path().join("sockets"));
+
+        assert_eq!(host.name(), "me@dev.example.com:it's here");
+        let command = host.command("ls");
+        let args: Vec<String> = command.as_std().get_args().map(|arg| arg.to_string_lossy().to_string()).collect();
+        assert_eq!(args[5], format!("ControlPath={}", dir.path().join("sockets/%C").display()));
+        assert_eq!(
+            &args[8..],
+            ["-p", "2222", "-l", "me", "-o", "ProxyJump=bastion", "dev.example.com", "cd 'it'\\''s here' || exit 1\nls"]
+        );
+    }
+
+    #[test]
+    #[cfg(unix)]
+    fn test_private_dir() {
+        use std::os::unix::fs::PermissionsExt;
+        let dir = tempfile::tempdir().unwrap();
+        let sockets = dir.path().join("sockets");
+        assert_eq!(private_dir(sockets.clone()), Some(sockets.clone()));
+        assert_eq!(std::fs::metadata(&sockets).unwrap().permissions().mode() & 0o777, 0o700);
+
+        // One left open to others is closed up before use.
+        std::fs::set_permissions(&sockets, std::fs::Permissions::from_mode(0o777)).unwrap();
+        assert_eq!(private_dir(sockets.clone()), Some(sockets.clone()));
+        assert_eq!(std::fs::metadata(&sockets).unwrap().permissions().mode() & 0o777, 0o700);
+
+        let file = dir.path().join("file");
+        std::fs::write(&file, "").unwrap();
+        assert_eq!(private_dir(file), None);
+    }
+}
@@ -0,0 +1,680 @@
+use super::{SshHost, quote};
+use crate::paths::PROJECT_DIR;
+use crate::tools::{
+    DEFAULT_READ_BUDGET, GrepMode, ResourceLimits, SearchHistoryTool, ToolAnnotations, ToolError, ToolInfo, ToolManager,
+    ToolOutput, ToolTrait, decode_region, exit_failure, limits, render,
+};
+use futures::Future;
+use serde_json::Value;
+use std::path::PathBuf;
+use std::pin::Pin;
+use std::process::Stdio;
+use std::sync::Arc;
+
+/// Matches `grep` and `glob` return before stopping.
+const MAX_MATCHES: usize = 1000;
+
+/// Directories `grep` and `glob` don't descend into.
+const SKIPPED_DIRS: &[&str] = &[".git", "node_modules", "target", PROJECT_DIR];
+
+fn path_argument<'a>(arguments: &'a Value, default: Option<&'a str>) -> Result<&'a str, ToolError> {
+    arguments
+        .get("path")
+        .and_then(|v| v.as_str())
+        .or(default)
+        .ok_or_else(|| ToolError::InvalidArguments("Missing 'path' argument".to_string()))
+}
+
+fn stderr_of(output: &std::process::Output) -> String {
+    String::from_utf8_lossy(&output.stderr).trim().to_string()
+}
+
+/// `read_file` on a remote host.
+pub struct RemoteFileReadTool {
+    host: Arc<SshHost>,
+}
+
+impl RemoteFileReadTool {
+    pub fn new(host: Arc<SshHost>) -> Self {
+        Self { host }
+    }
+}
+
+impl ToolTrait for RemoteFileReadTool {
+    fn info(&self) -> ToolInfo {
+        ToolInfo {
+            name: "read_file".to_string(),
+            description: format!(
+                "Read the contents of a file on {}. Large files are cut to max_bytes; use offset or tail to read other parts",
+                self.host.name()
+            ),
+            parameters: serde_json::json!({
+                "type": "object",
+                "properties": {
+                    "path": {
+                        "type": "string",
+                        "description": "Path to the file to read"
+                    },
+                    "offset": {
+                        "type": "integer",
+                        "description": "Byte offset to start reading at, e.g. from a grep match (default: 0)"
+                    },
+                    "tail": {
+                        "type": "boolean",
+                        "description": "Read the end of the file instead of the start"
+                    },
+                    "max_bytes": {
+                        "type": "integer",
+                        "description": "Maximum number of bytes to return (default: 262144)"
+                    }
+                },
+                "required": ["path"]
+            }),
+            annotations: ToolAnnotations::read_only(),
+        }
+    }
+
+    fn execute(&self, arguments: Value) -> Pin<Box<dyn Future<Output = Result<ToolOutput, ToolError>> + Send + Sync>> {
+        let host = Arc::clone(&self.host);
+        Box::pin(async move {
+            let path = path_argument(&arguments, None)?;
+            let tail = arguments.get("tail").and_then(|v| v.as_bool()).unwrap_or(false);
+            let budget = arguments
+                .get("max_bytes")
+                .and_then(|v| v.as_u64())
+                .unwrap_or(DEFAULT_READ_BUDGET);
+            let start = match (arguments.get("offset").and_then(|v| v.as_u64()), tail) {
+                (Some(offset), _) => format!("$(( {0} < s ? {0} : s ))", offset),
+                (None, true) => format!("$(( s > {0} ? s - {0} : 0 ))", budget),
+                (None, false) => "0".to_string(),
+            };
+
+            // The first line is the file's size and where the region starts.
+            let script = format!(
+                "f={}\ns=$(wc -c < \"$f\") || exit 1\nst={}\necho $((s)) $st\ntail -c +$((st + 1)) \"$f\" | head -c {}",
+                quote(path),
+                start,
+                budget
+            );
+            let output = host.output(&script, &[]).await?;
+            if !output.status.success() {
+                return Err(ToolError::IoError(stderr_of(&output)));
+            }
+            let newline = output.stdout.iter().position(|b| *b == b'\n').unwrap_or(output.stdout.len());
+            let header = String::from_utf8_lossy(&output.stdout[..newline]).to_string();
+            let bytes = output.stdout.get(newline + 1..).unwrap_or_default();
+            let mut numbers = header.split_whitespace().filter_map(|n| n.parse::<u64>().ok());
+            let (Some(size), Some(start)) = (numbers.next(), numbers.next()) else {
+                return Err(ToolError::ExecutionFailed(format!("Unexpected output from {}: {}", host.name(), header)));
+            };
+            let end = start + bytes.len() as u64;
+            let content = decode_region(bytes, start > 0, end < size);
+
+            if start == 0 && end == size {
+                return Ok(ToolOutput::success(serde_json::json!({
+                    "content": content,
+                    "path": path
+                })));
+            }
+            Ok(ToolOutput::success(serde_json::json!({
+                "content": content,
+                "path": path,
+                "size": size,
+                "start": start,
+                "end": end
+            }))
+            .with_truncated(true))
+        })
+    }
+
+    fn render(&self, output: &ToolOutput) -> Option<String> {
+        render::read_file(output)
+    }
+}
+
+/// `write_file` on a remote host.
+pub struct RemoteFileWriteTool {
+    host: Arc<SshHost>,
+}
+
+impl RemoteFileWriteTool {
+    pub fn new(host: Arc<SshHost>) -> Self {
+        Self { host }
+    }
+}
+
+impl ToolTrait for RemoteFileWriteTool {
+    fn info(&self) -> ToolInfo {
+        ToolInfo {
+            name: "write_file".to_string(),
+            description: format!("Write content to a file on {}", self.host.name()),
+            parameters: serde_json::json!({
+                "type": "object",
+                "properties": {
+                    "path": {
+                        "type": "string",
+                        "description": "Path to the file to write"
+                    },
+                    "content": {
+                        "type": "string",
+                        "description": "Content to write to the file"
+                    }
+                },
+                "required": ["path", "content"]
+            }),
+            annotations: ToolAnnotations {
+                read_only: false,
+                destructive: true,
+                idempotent: true,
+                open_world: false,
+            },
+        }
+    }
+
+    fn execute(&self, arguments: Value) -> Pin<Box<dyn Future<Output = Result<ToolOutput, ToolError>> + Send + Sync>> {
+        let host = Arc::clone(&self.host);
+        Box::pin(async move {
+            let path = path_argument(&arguments, None)?;
+            let content = arguments
+                .get("content")
+                .and_then(|v| v.as_str())
+                .ok_or_else(|| ToolError::InvalidArguments("Missing 'content' argument".to_string()))?;
+
+            let script = format!(
+                "f={}\nif [ -e \"$f\" ]; then echo exists; fi\nmkdir -p \"$(dirname \"$f\")\" && cat > \"$f\"",
+                quote(path)
+            );
+            let output = host.output(&script, content.as_bytes()).await?;
+            if !output.status.success() {
+                return Err(ToolError::IoError(stderr_of(&output)));
+            }
+
+            Ok(ToolOutput::success(serde_json::json!({
+                "path": path,
+                "created": !String::from_utf8_lossy(&output.stdout).contains("exists"),
+                "message": "File written successfully"
+            })))
+        })
+    }
+}
+
+/// `list_dir` on a remote host.
+pub struct RemoteListDirTool {
+    host: Arc<SshHost>,
+}
+
+impl RemoteListDirTool {
+    pub fn new(host: Arc<SshHost>) -> Self {
+        Self { host }
+    }
+}
+
+impl ToolTrait for RemoteListDirTool {
+    fn info(&self) -> ToolInfo {
+        ToolInfo {
+            name: "list_dir".to_string(),
+            description: format!("List directory contents on {}", self.host.name()),
+            parameters: serde_json::json!({
+                "type": "object",
+                "properties": {
+                    "path": {
+                        "type": "string",
+                        "description": "Path to the directory to list"
+                    }
+                },
+                "required": ["path"]
+            }),
+            annotations: ToolAnnotations::read_only(),
+        }
+    }
+
+    fn execute(&self, arguments: Value) -> Pin<Box<dyn Future<Output = Result<ToolOutput, ToolError>> + Send + Sync>> {
+        let host = Arc::clone(&self.host);
+        Box::pin(async move {
+            let path = path_argument(&arguments, None)?;
+
+            // One "<d|f|o>\t<size>\t<name>" line per entry, hidden ones too.
+            let script = format!(
+                "cd {} || exit 1\n\
+                 for e in * .[!.]* ..?*; do\n\
+                 [ -e \"$e\" ] || [ -L \"$e\" ] || continue\n\
+                 if [ -d \"$e\" ]; then printf 'd\\t0\\t%s\\n' \"$e\"\n\
+                 elif [ -f \"$e\" ]; then printf 'f\\t%s\\t%s\\n' \"$(wc -c < \"$e\")\" \"$e\"\n\
+                 else printf 'o\\t0\\t%s\\n' \"$e\"; fi\n\
+                 done",
+                quote(path)
+            );
+            let output = host.output(&script, &[]).await?;
+            if !output.status.success() {
+                return Err(ToolError::IoError(stderr_of(&output)));
+            }
+
+            let items: Vec<Value> = String::from_utf8_lossy(&output.stdout)
+                .lines()
+                .filter_map(|line| {
+                    let mut fields = line.splitn(3, '\t');
+                    let (kind, size, name) = (fields.next()?, fields.next()?, fields.next()?);
+                    Some(serde_json::json!({
+                        "name": name,
+                        "is_dir": kind == "d",
+                        "is_file": kind == "f",
+                        "size": size.trim().parse::<u64>().unwrap_or(0)
+                    }))
+                })
+                .collect();
+            Ok(ToolOutput::success(serde_json::json!({
+                "path": path,
+                "items": items
+            })))
+        })
+    }
+
+    fn render(&self, output: &ToolOutput) -> Option<String> {
+        render::list_dir(output)
+    }
+}
+
+/// `grep` on a remote host, with the host's `grep -r`.
+pub struct RemoteGrepTool {
+    host: Arc<SshHost>,
+}
+
+impl RemoteGrepTool {
+    pub fn new(host: Arc<SshHost>) -> Self {
+        Self { host }
+    }
+}
+
+impl ToolTrait for RemoteGrepTool {
+    fn info(&self) -> ToolInfo {
+        ToolInfo {
+            name: "grep".to_string(),
+            description: format!("Search for a pattern in files on {}", self.host.name()),
+            parameters: serde_json::json!({
+                "type": "object",
+                "properties": {
+                    "pattern": {
+                        "type": "string",
+                        "description": "Pattern to search for"
+                    },
+                    "path": {
+                        "type": "string",
+                        "description": "Path to search in (default: current directory)"
+                    },
+                    "file_pattern": {
+                        "type": "string",
+                        "description": "File pattern to match (e.g., *.rs)"
+                    },
+                    "ignore_case": {
+                        "type": "boolean",
+                        "description": "Match regardless of case (default: false)"
+                    },
+                    "files_with_matches": {
+                        "type": "boolean",
+                        "description": "Return only the files that contain a match, not the lines (default: false)"
+                    },
+                    "count_only": {
+                        "type": "boolean",
+                        "description": "Return how many lines match in each file, not the lines (default: false)"
+                    }
+                },
+                "required": ["pattern"]
+            }),
+            annotations: ToolAnnotations::read_only(),
+        }
+    }
+
+    fn execute(&self, arguments: Value) -> Pin<Box<dyn Future<Output = Result<ToolOutput, ToolError>> + Send + Sync>> {
+        let host = Arc::clone(&self.host);
+        Box::pin(async move {
+            let pattern = arguments
+                .get("pattern")
+                .and_then(|v| v.as_str())
+                .ok_or_else(|| ToolError::InvalidArguments("Missing 'pattern' argument".to_string()))?;
+            let path = path_argument(&arguments, Some("."))?;
+            let file_pattern = arguments
+                .get("file_pattern")
+                .and_then(|v| v.as_str())
+                .unwrap_or("*");
+
+            let ignore_case = arguments.get("ignore_case").and_then(|v| v.as_bool()).unwrap_or(false);
+            let mode = GrepMode::from_arguments(&arguments);
+
+            let mut script = match mode {
+                GrepMode::Lines => "grep -rnbIF",
+                GrepMode::Files => "grep -rlIF",
+                GrepMode::Counts => "grep -rcIF",
+            }
+            .to_string();
+            if ignore_case {
+                script.push_str(" -i");
+            }
+            for dir in SKIPPED_DIRS {
+                script.push_str(&format!(" --exclude-dir={}", quote(dir)));
+            }
+            if file_pattern != "*" {
+                script.push_str(&format!(" --include={}", quote(file_pattern)));
+            }
+            script.push_str(&format!(" -e {} -- {}", quote(pattern), quote(path)));
+            if mode == GrepMode::Counts {
+                // `grep -c` lists files without a match too.
+                script.push_str(" | grep -v ':0$'");
+            }
+            script.push_str(&format!(" | head -n {}", MAX_MATCHES + 1));
+            let output = host.output(&script, &[]).await?;
+
+            // Lines are "file:line:offset:content", "file" or "file:count".
+            let stdout = String::from_utf8_lossy(&output.stdout);
+            let mut results: Vec<Value> = stdout
+                .lines()
+                .filter_map(|line| {
+                    if mode == GrepMode::Files {
+                        return Some(serde_json::json!({"file": line}));
+                    }
+                    if mode == GrepMode::Counts {
+                        let (file, count) = line.rsplit_once(':')?;
+                        let count = count.parse::<u64>().ok().filter(|count| *count > 0)?;
+                        return Some(serde_json::json!({"file": file, "count": count}));
+                    }
+                    let mut fields = line.splitn(4, ':');
+                    let file = fields.next()?;
+                    let line_no = fields.next()?.parse::<u64>().ok()?;
+                    let offset = fields.next()?.parse::<u64>().ok()?;
+                    Some(serde_json::json!({
+                        "file": file,
+                        "line": line_no,
+                        "offset": offset,
+                        "content": fields.next().unwrap_or_default().trim()
+                    }))
+                })
+                .collect();
+            if results.is_empty() && !output.stderr.is_empty() {
+                return Err(ToolError::IoError(stderr_of(&output)));
+            }
+
+            let truncated = results.len() > MAX_MATCHES;
+            results.truncate(MAX_MATCHES);
+            let output = serde_json::json!({
+                "pattern": pattern,
+                "path": path,
+                "results": results
+            });
+            Ok(ToolOutput::success(output).with_truncated(truncated))
+        })
+    }
+
+    fn render(&self, output: &ToolOutput) -> Option<String> {
+        render::grep(output)
+    }
+}
+
+/// `glob` on a remote host, with the host's `find`.
+pub struct RemoteGlobTool {
+    host: Arc<SshHost>,
+}
+
+impl RemoteGlobTool {
+    pub fn new(host: Arc<SshHost>) -> Self {
+        Self { host }
+    }
+}
+
+impl ToolTrait for RemoteGlobTool {
+    fn info(&self) -> ToolInfo {
+        ToolInfo {
+            name: "glob".to_string(),
+            description: format!("Find files matching a pattern on {}", self.host.name()),
+            parameters: serde_json::json!({
+                "type": "object",
+                "properties": {
+                    "pattern": {
+                        "type": "string",
+                        "description": "Glob pattern (e.g., **/*.rs)"
+                    },
+                    "path": {
+                        "type": "string",
+                        "description": "Base path to search from"
+                    }
+                },
+                "required": ["pattern"]
+            }),
+            annotations: ToolAnnotations::read_only(),
+        }
+    }
+
+    fn execute(&self, arguments: Value) -> Pin<Box<dyn Future<Output = Result<ToolOutput, ToolError>> + Send + Sync>> {
+        let host = Arc::clone(&self.host);
+        Box::pin(async move {
+            let pattern = arguments
+                .get("pattern")
+                .and_then(|v| v.as_str())
+                .ok_or_else(|| ToolError::InvalidArguments("Missing 'pattern' argument".to_string()))?;
+            let path = path_argument(&arguments, Some("."))?;
+
+            // Like the local glob, only the last component is matched.
+            let name = pattern.rsplit('/').next().unwrap_or(pattern);
+            let name = if name == "**" { "*" } else { name };
+            let pruned: Vec<String> = SKIPPED_DIRS.iter().map(|dir| format!("-name {}", quote(dir))).collect();
+            let script = format!(
+                "find {} \\( {} \\) -prune -o -name {} -print | head -n {}",
+                quote(path),
+                pruned.join(" -o "),
+                quote(name),
+                MAX_MATCHES + 1
+            );
+            let output = host.output(&script, &[]).await?;
+
+            let stdout = String::from_utf8_lossy(&output.stdout);
+            let mut files: Vec<&str> = stdout.lines().collect();
+            if files.is_empty() && !output.stderr.is_empty() {
+                return Err(ToolError::IoError(stderr_of(&output)));
+            }
+            let truncated = files.len() > MAX_MATCHES;
+            files.truncate(MAX_MATCHES);
+
+            let output = serde_json::json!({
+                "pattern": pattern,
+                "path": path,
+                "files": files
+            });
+            Ok(ToolOutput::success(output).with_truncated(truncated))
+        })
+    }
+
+    fn render(&self, output: &ToolOutput) -> Option<String> {
+        render::glob(output)
+    }
+}
+
+/// `run_command` on a remote host. CPU and memory limits are applied there
+/// with `ulimit`; the network policy is not enforced remotely.
+pub struct RemoteRunCommandTool {
+    host: Arc<SshHost>,
+    limits: ResourceLimits,
+}
+
+impl RemoteRunCommandTool {
+    pub fn new(host: Arc<SshHost>) -> Self {
+        Self {
+            host,
+            limits: ResourceLimits::default(),
+        }
+    }
+
+    pub fn with_limits(mut self, limits: ResourceLimits) -> Self {
+        self.limits = limits;
+        self
+    }
+}
+
+impl ToolTrait for RemoteRunCommandTool {
+    fn info(&self) -> ToolInfo {
+        ToolInfo {
+            name: "run_command".to_string(),
+            description: format!("Run a shell command on {}", self.host.name()),
+            parameters: serde_json::json!({
+                "type": "object",
+                "properties": {
+                    "command": {
+                        "type": "string",
+                        "description": "Command to run"
+                    }
+                },
+                "required": ["command"]
+            }),
+            annotations: ToolAnnotations::default(),
+        }
+    }
+
+    fn execute(&self, arguments: Value) -> Pin<Box<dyn Future<Output = Result<ToolOutput, ToolError>> + Send + Sync>> {
+        let host = Arc::clone(&self.host);
+        let limits = self.limits.clone();
+        Box::pin(async move {
+            let command = arguments
+                .get("command")
+                .and_then(|v| v.as_str())
+                .ok_or_else(|| ToolError::InvalidArguments("Missing 'command' argument".to_string()))?;
+
+            let mut child = host
+                .command(&limits.wrap(command))
+                .stdin(Stdio::null())
+                .stdout(Stdio::piped())
+                .stderr(Stdio::piped())
+                .spawn()
+                .map_err(|e| ToolError::ExecutionFailed(format!("Failed to start ssh: {}", e)))?;
+
+            let ((stdout, stdout_dropped), (stderr, stderr_dropped)) =
+                match (child.stdout.take(), child.stderr.take()) {
+                    (Some(stdout), Some(stderr)) => tokio::try_join!(
+                        limits::read_capped(stdout, limits.max_output_bytes),
+                        limits::read_capped(stderr, limits.max_output_bytes),
+                    )?,
+                    _ => return Err(ToolError::ExecutionFailed("Command output was not captured".to_string())),
+                };
+            let status = child.wait().await?;
+
+            let mut result = serde_json::json!({
+                "command": command,
+                "stdout": stdout,
+                "stderr": stderr,
+                "exit_code": status.code()
+            });
+            let truncated = stdout_dropped > 0 || stderr_dropped > 0;
+            if truncated {
+                result["output_truncated"] = serde_json::json!({
+                    "stdout_bytes_dropped": stdout_dropped,
+                    "stderr_bytes_dropped": stderr_dropped
+                });
+            }
+            if status.code() == Some(255) {
+                result["note"] = Value::String(format!(
+                    "Exit code 255 usually means ssh could not reach {}.",
+                    host.name()
+                ));
+            }
+            let output = if status.success() {
+                ToolOutput::success(result)
+            } else {
+                exit_failure("nonzero_exit", status.code()).with_data(result)
+            };
+            Ok(output.with_truncated(truncated))
+        })
+    }
+
+    fn render(&self, output: &ToolOutput) -> Option<String> {
+        render::run_command(output)
+    }
+}
+
+/// The tools for an agent working on `host`: everything, or only the
+/// non-mutating ones if `read_only`. Session history stays local, under
+/// `workdir`.
+pub fn remote_tools(host: SshHost, workdir: PathBuf, read_only: bool, limits: ResourceLimits) -> ToolManager {
+    let host = Arc::new(host);
+    let mut manager = ToolManager::new();
+
+    manager.register(Box::new(RemoteFileReadTool::new(Arc::clone(&host))));
+    manager.register(Box::new(RemoteListDirTool::new(Arc::clone(&host))));
+    manager.register(Box::new(RemoteGrepTool::new(Arc::clone(&host))));
+    manager.register(Box::new(RemoteGlobTool::new(Arc::clone(&host))));
+    manager.register(Box::new(SearchHistoryTool::new(workdir)));
+    if !read_only {
+        manager.register(Box::new(RemoteFileWriteTool::new(Arc::clone(&host))));
+        manager.register(Box::new(RemoteRunCommandTool::new(host).with_limits(limits)));
+    }
+
+    manager
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::remote::RemoteConfig;
+
+    /// A stand-in for `ssh` that runs the script locally.
+    fn local_host(dir: &std::path::Path) -> SshHost {
+        let shim = dir.join("fake-ssh");
+        std::fs::write(&shim, "#!/bin/sh\nfor last; do :; done\nexec sh -c \"$last\"\n").unwrap();
+        let mut permissions = std::fs::metadata(&shim).unwrap().permissions();
+        std::os::unix::fs::PermissionsExt::set_mode(&mut permissions, 0o755);
+        std::fs::set_permissions(&shim, permissions).unwrap();
+        let workdir = dir.join("remote");
+        std::fs::create_dir(&workdir).unwrap();
+        SshHost::new(RemoteConfig {
+            host: "devbox".to_string(),
+            user: None,
+            port: None,
+            identity_file: None,
+            workdir: Some(workdir.to_string_lossy().to_string()),
+            ssh_args: Vec::new(),
+            max_steps: None,
+        })
+        .with_program(shim.to_string_lossy().to_string())
+    }
+
+    #[tokio::test]
+    async fn test_remote_file_tools() {
+        let dir = tempfile::tempdir().unwrap();
+        let tools = remote_tools(local_host(dir.path()), dir.path().to_path_buf(), false, ResourceLimits::default());
+        let run = |name: &str, arguments: Value| tools.get(name).unwrap().execute(arguments);
+
+        let written = run("write_file", serde_json::json!({"path": "src/it's.rs", "content": "fn main() {\n    todo!()\n}\n"}))
+            .await
+            .unwrap();
+        assert_eq!(written.data["created"], true);
+        assert_eq!(
+            std::fs::read_to_string(dir.path().join("remote/src/it's.rs")).unwrap(),
+            "fn main() {\n    todo!()\n}\n"
+        );
+
+        let read = run("read_file", serde_json::json!({"path": "src/it's.rs"})).await.unwrap();
+        assert_eq!(read.data["content"], "fn main() {\n    todo!()\n}\n");
+        let tail = run("read_file", serde_json::json!({"path": "src/it's.rs", "tail": true, "max_bytes": 2}))
+            .await
+            .unwrap();
+        assert_eq!(tail.data["content"], "}\n");
+        assert_eq!(tail.data["start"], 24);
+
+        let listed = run("list_dir", serde_json::json!({"path": "."})).await.unwrap();
+        assert_eq!(listed.data["items"][0]["name"], "src");
+        assert_eq!(listed.data["items"][0]["is_dir"], true);
+
+        let found = run("grep", serde_json::json!({"pattern": "todo!", "file_pattern": "*.rs"})).await.unwrap();
+        assert_eq!(found.data["results"][0]["line"], 2);
+        assert_eq!(found.data["results"][0]["offset"], 12);
+        let counted = run("grep", serde_json::json!({"pattern": "TODO", "ignore_case": true, "count_only": true}))
+            .await
+            .unwrap();
+        assert_eq!(counted.data["results"], serde_json::json!([{"file": "./src/it's.rs", "count": 1}]));
+
+        let globbed = run("glob", serde_json::json!({"pattern": "**/*.rs"})).await.unwrap();
+        assert_eq!(globbed.data["files"][0], "./src/it's.rs");
+
+        let ran = run("run_command", serde_json::json!({"command": "pwd; exit 3"})).await.unwrap();
+        assert_eq!(ran.data["exit_code"], 3);
+        assert!(ran.data["stdout"].as_str().unwrap().trim_end().ends_with("remote"));
+
+        let missing = run("read_file", serde_json::json!({"path": "missing.rs"})).await;
+        assert!(matches!(missing, Err(ToolError::IoError(_))));
+    }
+}
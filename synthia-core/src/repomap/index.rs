@@ -0,0 +1,113 @@
+use serde::{Deserialize, Serialize};
+use std::collections::BTreeMap;
+use std::fs::Metadata;
+use std::path::{Path, PathBuf};
+use std::time::UNIX_EPOCH;
+
+pub use crate::paths::INDEX_DIR;
+
+const INDEX_FILE: &str = "outlines.json";
+
+/// Bumped whenever outlines are extracted differently, so that outlines
+/// from older versions are not reused.
+const INDEX_VERSION: u32 = 1;
+
+/// Identifies a version of a file without reading it.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub(crate) struct Stamp {
+    modified_ns: u64,
+    len: u64,
+}
+
+impl Stamp {
+    pub(crate) fn of(metadata: &Metadata) -> Self {
+        let modified = metadata
+            .modified()
+            .ok()
+            .and_then(|time| time.duration_since(UNIX_EPOCH).ok())
+            .unwrap_or_default();
+        Self {
+            modified_ns: modified.as_nanos() as u64,
+            len: metadata.len(),
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+struct Entry {
+    stamp: Stamp,
+    symbols: Vec<String>,
+}
+
+#[derive(Debug, Default, Serialize, Deserialize)]
+struct Stored {
+    version: u32,
+    files: BTreeMap<String, Entry>,
+}
+
+/// File outlines kept under [`INDEX_DIR`] between runs, so only files
+/// changed since the last run are read again.
+pub(super) struct OutlineIndex {
+    path: PathBuf,
+    previous: BTreeMap<String, Entry>,
+    current: BTreeMap<String, Entry>,
+    changed: bool,
+}
+
+impl OutlineIndex {
+    /// The index of `root`, or an empty one if there is none or it can't be
+    /// read.
+    pub(super) fn load(root: &Path) -> Self {
+        let path = root.join(INDEX_DIR).join(INDEX_FILE);
+        let previous = std::fs::read_to_string(&path)
+            .ok()
+            .and_then(|json| serde_json::from_str::<Stored>(&json).ok())
+            .filter(|stored| stored.version == INDEX_VERSION)
+            .map(|stored| stored.files)
+            .unwrap_or_default();
+        Self {
+            path,
+            previous,
+            current: BTreeMap::new(),
+            changed: false,
+        }
+    }
+
+    /// The outline of the file at `path`, from the index if the file has
+    /// not changed since, or from `parse`.
+    pub(super) fn outline(&mut self, path: &str, stamp: Stamp, parse: impl FnOnce() -> Vec<String>) -> Vec<String> {
+        let entry = match self.previous.remove(path) {
+            Some(entry) if entry.stamp == stamp => entry,
+            _ => {
+                self.changed = true;
+                Entry { stamp, symbols: parse() }
+            }
+        };
+        let symbols = entry.symbols.clone();
+        self.current.insert(path.to_string(), entry);
+        symbols
+    }
+
+    /// Writes the index back if any file was added, changed or removed.
+    /// Failing to write it only costs time on the next run.
+    pub(super) fn save(self) {
+        if !self.changed && self.previous.is_empty() {
+            return;
+        }
+        let stored = Stored {
+            version: INDEX_VERSION,
+            files: self.current,
+        };
+        let write = || -> std::io::Result<()> {
+            if let Some(dir) = self.path.parent() {
+                std::fs::create_dir_all(dir)?;
+            }
+            let tmp = self.path.with_extension("json.tmp");
+            std::fs::write(&tmp, serde_json::to_string(&stored)?)?;
+            std::fs::rename(&tmp, &self.path)
+        };
+        if let Err(e) = write() {
+            tracing::debug!("Could not save the repository index {}: {}", self.path.display(), e);
+        }
+    }
+}
@@ -0,0 +1,326 @@
+//! A compact map of the repository for the system prompt: the top-level
+//! tree, the key files, and an outline of the files most likely to matter
+//! for the task. It saves the agent the `list_dir` and `grep` steps it
+//! would otherwise spend finding its way around.
+//!
+//! Outlines are kept in an index under [`INDEX_DIR`] and only files whose
+//! modification time or size changed are read again, so large repositories
+//! are not re-parsed on every run.
+
+pub(crate) mod index;
+
+use crate::tools::walk;
+use index::{OutlineIndex, Stamp};
+use regex::Regex;
+use serde::{Deserialize, Serialize};
+use std::path::Path;
+use std::sync::OnceLock;
+
+pub use index::INDEX_DIR;
+
+/// Files considered for the map.
+const MAX_FILES: usize = 5_000;
+
+/// Larger files are ranked by path alone and not outlined.
+const MAX_OUTLINE_BYTES: u64 = 256 * 1024;
+
+const MAX_SYMBOLS_PER_FILE: usize = 30;
+
+const MAX_SYMBOL_CHARS: usize = 120;
+
+/// Manifests and docs that say what a repository is and how it builds.
+const KEY_FILES: &[&str] = &[
+    "README.md",
+    "README",
+    "Cargo.toml",
+    "package.json",
+    "pyproject.toml",
+    "setup.py",
+    "go.mod",
+    "pom.xml",
+    "build.gradle",
+    "CMakeLists.txt",
+    "Makefile",
+];
+
+/// Words too common in tasks to say anything about where to look.
+const STOPWORDS: &[&str] = &[
+    "add", "and", "all", "bug", "can", "fix", "for", "from", "into", "make", "not", "should", "that", "the", "this",
+    "use", "when", "with",
+];
+
+/// File stems that usually hold a module's entry point.
+const ENTRY_POINTS: &[&str] = &["main", "lib", "mod", "index", "app", "__init__"];
+
+/// How much of the context the map may take, when enabled.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct RepoMapConfig {
+    pub enabled: bool,
+    pub max_tokens: usize,
+}
+
+impl Default for RepoMapConfig {
+    fn default() -> Self {
+        Self {
+            enabled: true,
+            max_tokens: 1024,
+        }
+    }
+}
+
+impl RepoMapConfig {
+    /// The token budget for [`crate::core::ReactAgent::with_repo_map`].
+    pub fn budget(&self) -> Option<usize> {
+        self.enabled.then_some(self.max_tokens)
+    }
+}
+
+struct FileOutline {
+    /// Relative to the repository root, with `/` separators.
+    path: String,
+    symbols: Vec<String>,
+    score: usize,
+}
+
+/// Builds the map of `root` for `task` in about `max_tokens` tokens. Files
+/// whose path or symbols mention words of the task come first, then entry
+/// points and files that define a lot.
+pub async fn repo_map(root: &Path, task: &str, max_tokens: usize) -> String {
+    let walk = walk::walk(root, MAX_FILES).await;
+    let root = root.to_path_buf();
+    let task = task.to_string();
+    tokio::task::spawn_blocking(move || {
+        let mut top_level = Vec::new();
+        let mut key_files = Vec::new();
+        let mut outlines = Vec::new();
+        let mut index = OutlineIndex::load(&root);
+        for entry in &walk.entries {
+            let Ok(relative) = entry.path.strip_prefix(&root) else {
+                continue;
+            };
+            let path = relative.to_string_lossy().replace('\\', "/");
+            if relative.components().count() == 1 {
+                top_level.push(if entry.is_dir { format!("{}/", path) } else { path.clone() });
+            }
+            if entry.is_dir {
+                continue;
+            }
+            if KEY_FILES.iter().any(|name| relative.file_name().is_some_and(|file| file == *name)) {
+                key_files.push(path.clone());
+            }
+            let Some(pattern) = entry.path.extension().and_then(|ext| definition_pattern(&ext.to_string_lossy()))
+            else {
+                continue;
+            };
+            let Ok(metadata) = std::fs::metadata(&entry.path) else {
+                continue;
+            };
+            let symbols = index.outline(&path, Stamp::of(&metadata), || {
+                if metadata.len() > MAX_OUTLINE_BYTES {
+                    Vec::new()
+                } else {
+                    outline(&entry.path, pattern)
+                }
+            });
+            if !symbols.is_empty() {
+                outlines.push(FileOutline { path, symbols, score: 0 });
+            }
+        }
+        index.save();
+        rank(&mut outlines, &task);
+        render(&top_level, &key_files, &outlines, max_tokens * 4)
+    })
+    .await
+    .unwrap_or_default()
+}
+
+/// The definitions in the file at `path` as the map would list them, or
+/// none for a language the map doesn't outline or a file too large to.
+pub(crate) fn outline_file(path: &Path) -> Vec<String> {
+    let Some(pattern) = path.extension().and_then(|ext| definition_pattern(&ext.to_string_lossy())) else {
+        return Vec::new();
+    };
+    match std::fs::metadata(path) {
+        Ok(metadata) if metadata.len() <= MAX_OUTLINE_BYTES => outline(path, pattern),
+        _ => Vec::new(),
+    }
+}
+
+/// The lines of `path` that match `pattern`, one per definition.
+fn outline(path: &Path, pattern: &Regex) -> Vec<String> {
+    let Ok(content) = std::fs::read_to_string(path) else {
+        return Vec::new();
+    };
+    content
+        .lines()
+        // Rust unit tests say nothing about the code's structure.
+        .take_while(|line| !line.starts_with("#[cfg(test)]"))
+        .filter(|line| pattern.is_match(line))
+        .map(|line| {
+            // Keep the signature, not the body.
+            let line = line.split('{').next().unwrap_or_default().trim().trim_end_matches(':');
+            let end = line.floor_char_boundary(MAX_SYMBOL_CHARS);
+            line[..end].to_string()
+        })
+        .take(MAX_SYMBOLS_PER_FILE)
+        .collect()
+}
+
+fn definition_pattern(extension: &str) -> Option<&'static Regex> {
+    static RUST: OnceLock<Regex> = OnceLock::new();
+    static PYTHON: OnceLock<Regex> = OnceLock::new();
+    static SCRIPT: OnceLock<Regex> = OnceLock::new();
+    static GO: OnceLock<Regex> = OnceLock::new();
+    static JVM: OnceLock<Regex> = OnceLock::new();
+
+    let (cell, pattern) = match extension {
+        "rs" => (
+            &RUST,
+            r"^\s*(pub(\([^)]*\))?\s+)?((async|const|unsafe)\s+)*(fn|struct|enum|trait|type|impl|mod|macro_rules!)\b",
+        ),
+        "py" => (&PYTHON, r"^\s*(async\s+)?(def|class)\s+\w+"),
+        "js" | "jsx" | "mjs" | "ts" | "tsx" => (
+            &SCRIPT,
+            r"^\s*(export\s+)?(default\s+)?(async\s+)?(function\*?|class|interface|type|enum)\s+\w+",
+        ),
+        "go" => (&GO, r"^(func|type)\s+"),
+        "java" | "kt" | "cs" => (
+            &JVM,
+            r"^\s*((public|private|protected|internal|static|final|abstract|sealed|data)\s+)*(class|interface|enum|record|object|fun)\s+\w+",
+        ),
+        _ => return None,
+    };
+    Some(cell.get_or_init(|| Regex::new(pattern).unwrap()))
+}
+
+/// The lowercase words of `task` worth matching against paths and symbols.
+fn keywords(task: &str) -> Vec<String> {
+    let mut words: Vec<String> = task
+        .split(|c: char| !(c.is_alphanumeric() || c == '_'))
+        .filter(|word| word.len() >= 3)
+        .map(str::to_lowercase)
+        .filter(|word| !STOPWORDS.contains(&word.as_str()))
+        .collect();
+    words.sort();
+    words.dedup();
+    words
+}
+
+/// Sorts `outlines` best first.
+fn rank(outlines: &mut [FileOutline], task: &str) {
+    let keywords = keywords(task);
+    for file in outlines.iter_mut() {
+        let path = file.path.to_lowercase();
+        let stem = Path::new(&file.path)
+            .file_stem()
+            .map(|stem| stem.to_string_lossy().to_string())
+            .unwrap_or_default();
+        let path_hits = keywords.iter().filter(|word| path.contains(word.as_str())).count();
+        let symbol_hits = file
+            .symbols
+            .iter()
+            .filter(|symbol| {
+                let symbol = symbol.to_lowercase();
+                keywords.iter().any(|word| symbol.contains(word.as_str()))
+            })
+            .count();
+        let entry_point = ENTRY_POINTS.contains(&stem.as_str());
+        file.score = 10 * path_hits + 3 * symbol_hits + 2 * usize::from(entry_point) + file.symbols.len().min(10) / 5;
+    }
+    outlines.sort_by(|a, b| {
+        b.score
+            .cmp(&a.score)
+            .then_with(|| a.path.matches('/').count().cmp(&b.path.matches('/').count()))
+            .then_with(|| a.path.cmp(&b.path))
+    });
+}
+
+/// The map as text of at most `max_chars`, outlining as many files as fit.
+fn render(top_level: &[String], key_files: &[String], outlines: &[FileOutline], max_chars: usize) -> String {
+    let mut map = format!("Top level: {}\n", top_level.join(" "));
+    if !key_files.is_empty() {
+        map.push_str(&format!("Key files: {}\n", key_files.join(", ")));
+    }
+    let mut left_out = 0;
+    for file in outlines {
+        let mut section = format!("\n{}:\n", file.path);
+        for symbol in &file.symbols {
+            section.push_str(&format!("  {}\n", symbol));
+        }
+        if map.len() + section.len() > max_chars {
+            left_out += 1;
+            continue;
+        }
+        map.push_str(&section);
+    }
+    if left_out > 0 {
+        map.push_str(&format!("\n({} more source files not outlined)\n", left_out));
+    }
+    map
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn repo() -> tempfile::TempDir {
+        let dir = tempfile::tempdir().unwrap();
+        std::fs::create_dir_all(dir.path().join("src/lexer")).unwrap();
+        std::fs::write(dir.path().join("Cargo.toml"), "[package]\nname = \"calc\"\n").unwrap();
+        std::fs::write(
+            dir.path().join("src/parser.rs"),
+            "use std::fmt;\n\npub struct Parser {\n    pos: usize,\n}\n\nimpl Parser {\n    pub fn parse_expr(&mut self) -> Expr {\n        todo!()\n    }\n}\n",
+        )
+        .unwrap();
+        std::fs::write(dir.path().join("src/lexer/mod.rs"), "pub enum Token {}\npub(crate) fn lex() {}\n").unwrap();
+        std::fs::write(dir.path().join("notes.txt"), "fn not_code() {}\n").unwrap();
+        dir
+    }
+
+    #[tokio::test]
+    async fn test_repo_map_ranks_by_task() {
+        let dir = repo();
+
+        let map = repo_map(dir.path(), "Fix the bug in parse_expr", 1024).await;
+
+        assert!(map.starts_with("Top level: Cargo.toml notes.txt src/\nKey files: Cargo.toml\n"));
+        assert!(map.contains("src/parser.rs:\n  pub struct Parser\n  impl Parser\n  pub fn parse_expr(&mut self) -> Expr\n"));
+        assert!(map.contains("src/lexer/mod.rs:\n  pub enum Token\n  pub(crate) fn lex()\n"));
+        assert!(map.find("src/parser.rs").unwrap() < map.find("src/lexer/mod.rs").unwrap());
+        assert!(!map.contains("not_code"));
+    }
+
+    #[tokio::test]
+    async fn test_repo_map_reuses_index() {
+        let dir = repo();
+        repo_map(dir.path(), "Fix the lexer", 1024).await;
+        let index_path = dir.path().join(INDEX_DIR).join("outlines.json");
+        let index = std::fs::read_to_string(&index_path).unwrap();
+        assert!(index.contains("src/lexer/mod.rs") && !index.contains("notes.txt"));
+
+        // An unchanged file's outline comes from the index.
+        std::fs::write(&index_path, index.replace("pub enum Token", "pub enum Cached")).unwrap();
+        let map = repo_map(dir.path(), "Fix the lexer", 1024).await;
+        assert!(map.contains("pub enum Cached"));
+
+        // A changed file is read again, and a deleted one dropped.
+        std::fs::write(dir.path().join("src/lexer/mod.rs"), "pub enum Token { Comma }
+").unwrap();
+        std::fs::remove_file(dir.path().join("src/parser.rs")).unwrap();
+        let map = repo_map(dir.path(), "Fix the lexer", 1024).await;
+        assert!(map.contains("pub enum Token") && !map.contains("Cached"));
+        assert!(!std::fs::read_to_string(&index_path).unwrap().contains("parser.rs"));
+    }
+
+    #[tokio::test]
+    async fn test_repo_map_stays_within_budget() {
+        let dir = repo();
+
+        let map = repo_map(dir.path(), "Add a token for commas to the lexer", 40).await;
+
+        assert!(map.len() <= 40 * 4);
+        assert!(map.contains("src/lexer/mod.rs:"));
+        assert!(map.contains("(1 more source files not outlined)"));
+    }
+}
@@ -0,0 +1,145 @@
+use serde::{Deserialize, Serialize};
+use thiserror::Error;
+
+#[derive(Debug, Error)]
+pub enum ReviewError {
+    #[error("No review comments found in agent response")]
+    MissingComments,
+    #[error("Invalid review comments: {0}")]
+    InvalidComments(String),
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Severity {
+    Nit,
+    Minor,
+    Major,
+    Critical,
+}
+
+impl std::fmt::Display for Severity {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        let label = match self {
+            Severity::Nit => "nit",
+            Severity::Minor => "minor",
+            Severity::Major => "major",
+            Severity::Critical => "critical",
+        };
+        f.write_str(label)
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct ReviewComment {
+    pub file: String,
+    pub line: u32,
+    pub severity: Severity,
+    pub comment: String,
+    #[serde(default)]
+    pub suggestion: Option<String>,
+}
+
+pub fn build_review_task(diff: &str) -> String {
+    format!(
+        r#"Review the following diff. Use the read-only tools to inspect the surrounding code in the working directory where the diff alone is not enough context.
+
+Report only real problems: bugs, missing error handling, security issues, unclear code and missing tests. Do not comment on unchanged code.
+
+When you are done, respond with FINAL: followed by a JSON array of comments, each of the form:
+{{"file": "<path>", "line": <line in the new file>, "severity": "nit" | "minor" | "major" | "critical", "comment": "<what is wrong>", "suggestion": "<optional replacement code or fix>"}}
+
+Respond with FINAL: [] if there is nothing to report.
+
+```diff
+{}
+```"#,
+        diff
+    )
+}
+
+pub fn parse_review_comments(answer: &str) -> Result<Vec<ReviewComment>, ReviewError> {
+    let start = answer.find('[').ok_or(ReviewError::MissingComments)?;
+    let end = answer.rfind(']').ok_or(ReviewError::MissingComments)?;
+    if end < start {
+        return Err(ReviewError::MissingComments);
+    }
+
+    let mut comments: Vec<ReviewComment> = serde_json::from_str(&answer[start..=end])
+        .map_err(|e| ReviewError::InvalidComments(e.to_string()))?;
+    comments.sort_by(|a, b| {
+        b.severity
+            .cmp(&a.severity)
+            .then_with(|| a.file.cmp(&b.file))
+            .then_with(|| a.line.cmp(&b.line))
+    });
+    Ok(comments)
+}
+
+pub fn render_markdown(comments: &[ReviewComment]) -> String {
+    if comments.is_empty() {
+        return "No issues found.\n".to_string();
+    }
+
+    let mut output = String::from("## Review\n");
+    for comment in comments {
+        output.push_str(&format!(
+            "\n### `{}:{}` ({})\n\n{}\n",
+            comment.file, comment.line, comment.severity, comment.comment
+        ));
+        if let Some(suggestion) = &comment.suggestion {
+            output.push_str(&format!("\n```suggestion\n{}\n```\n", suggestion.trim_end()));
+        }
+    }
+    output
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse_review_comments() {
+        let answer = r#"Here is my review:
+```json
+[
+  {"file": "src/a.rs", "line": 3, "severity": "nit", "comment": "Typo"},
+  {"file": "src/b.rs", "line": 10, "severity": "major", "comment": "Unwrap on user input", "suggestion": "let x = y?;"}
+]
+```"#;
+
+        let comments = parse_review_comments(answer).unwrap();
+
+        assert_eq!(comments.len(), 2);
+        assert_eq!(comments[0].severity, Severity::Major);
+        assert_eq!(comments[0].suggestion.as_deref(), Some("let x = y?;"));
+        assert_eq!(comments[1].file, "src/a.rs");
+    }
+
+    #[test]
+    fn test_parse_review_comments_errors() {
+        assert!(matches!(parse_review_comments("LGTM"), Err(ReviewError::MissingComments)));
+        assert!(matches!(
+            parse_review_comments(r#"[{"file": "a.rs"}]"#),
+            Err(ReviewError::InvalidComments(_))
+        ));
+        assert!(parse_review_comments("[]").unwrap().is_empty());
+    }
+
+    #[test]
+    fn test_render_markdown() {
+        let comments = vec![ReviewComment {
+            file: "src/b.rs".to_string(),
+            line: 10,
+            severity: Severity::Major,
+            comment: "Unwrap on user input".to_string(),
+            suggestion: Some("let x = y?;".to_string()),
+        }];
+
+        let markdown = render_markdown(&comments);
+
+        assert!(markdown.contains("`src/b.rs:10` (major)"));
+        assert!(markdown.contains("```suggestion\nlet x = y?;\n```"));
+        assert_eq!(render_markdown(&[]), "No issues found.\n");
+    }
+}
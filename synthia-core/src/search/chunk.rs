@@ -0,0 +1,112 @@
+use serde::{Deserialize, Serialize};
+use tree_sitter::{Language, Node, Parser};
+
+/// Definitions longer than this are split into the definitions inside them,
+/// or into windows of lines if there are none.
+const MAX_CHUNK_LINES: usize = 120;
+
+/// Lines per window where there is no grammar or no definition.
+const WINDOW_LINES: usize = 60;
+
+/// Lines of a file, 1-based and inclusive.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub struct Span {
+    pub start_line: usize,
+    pub end_line: usize,
+}
+
+impl Span {
+    pub fn text(&self, lines: &[&str]) -> String {
+        let end = self.end_line.min(lines.len());
+        let start = (self.start_line - 1).min(end);
+        lines[start..end].join("\n")
+    }
+}
+
+fn grammar(extension: &str) -> Option<(Language, &'static [&'static str])> {
+    const RUST: &[&str] = &[
+        "function_item",
+        "struct_item",
+        "enum_item",
+        "union_item",
+        "trait_item",
+        "impl_item",
+        "mod_item",
+        "macro_definition",
+    ];
+    const PYTHON: &[&str] = &["function_definition", "class_definition", "decorated_definition"];
+    const JAVASCRIPT: &[&str] = &[
+        "function_declaration",
+        "generator_function_declaration",
+        "class_declaration",
+        "method_definition",
+    ];
+    const GO: &[&str] = &["function_declaration", "method_declaration", "type_declaration"];
+
+    Some(match extension {
+        "rs" => (tree_sitter_rust::LANGUAGE.into(), RUST),
+        "py" => (tree_sitter_python::LANGUAGE.into(), PYTHON),
+        "js" | "jsx" | "mjs" | "cjs" => (tree_sitter_javascript::LANGUAGE.into(), JAVASCRIPT),
+        "go" => (tree_sitter_go::LANGUAGE.into(), GO),
+        _ => return None,
+    })
+}
+
+/// Splits a file into the functions, types and impls it defines, using
+/// tree-sitter where there is a grammar for `extension`. Code outside any
+/// definition is left out; files without definitions or a grammar are
+/// split into windows of lines.
+pub fn chunk(extension: &str, content: &str) -> Vec<Span> {
+    let line_count = content.lines().count();
+    let spans = grammar(extension)
+        .and_then(|(language, kinds)| {
+            let mut parser = Parser::new();
+            parser.set_language(&language).ok()?;
+            let tree = parser.parse(content, None)?;
+            let mut spans = Vec::new();
+            definitions(tree.root_node(), kinds, &mut spans);
+            Some(spans)
+        })
+        .unwrap_or_default();
+    if spans.is_empty() {
+        return windows(1, line_count);
+    }
+    spans
+}
+
+fn span(node: Node) -> Span {
+    Span {
+        start_line: node.start_position().row + 1,
+        end_line: node.end_position().row + 1,
+    }
+}
+
+fn definitions(node: Node, kinds: &[&str], spans: &mut Vec<Span>) {
+    let mut cursor = node.walk();
+    for child in node.named_children(&mut cursor) {
+        if !kinds.contains(&child.kind()) {
+            definitions(child, kinds, spans);
+            continue;
+        }
+        let whole = span(child);
+        if whole.end_line - whole.start_line < MAX_CHUNK_LINES {
+            spans.push(whole);
+            continue;
+        }
+        let before = spans.len();
+        definitions(child, kinds, spans);
+        if spans.len() == before {
+            spans.extend(windows(whole.start_line, whole.end_line));
+        }
+    }
+}
+
+fn windows(start_line: usize, end_line: usize) -> Vec<Span> {
+    (start_line..=end_line)
+        .step_by(WINDOW_LINES)
+        .map(|start| Span {
+            start_line: start,
+            end_line: (start + WINDOW_LINES - 1).min(end_line),
+        })
+        .collect()
+}
@@ -0,0 +1,409 @@
+//! Semantic code search: the repository split into definitions, embedded,
+//! and ranked by similarity to a question, for the `semantic_search` tool.
+//!
+//! Embeddings are kept under [`crate::repomap::INDEX_DIR`] and only files
+//! changed since the last search are embedded again.
+
+mod chunk;
+
+use crate::clients::{Embedder, LLMError, cosine_similarity};
+use crate::repomap::INDEX_DIR;
+use crate::repomap::index::Stamp;
+use crate::tools::{ToolAnnotations, ToolError, ToolInfo, ToolOutput, ToolTrait, walk};
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+use std::collections::BTreeMap;
+use std::future::Future;
+use std::path::PathBuf;
+use std::pin::Pin;
+use std::sync::Arc;
+
+pub use chunk::{Span, chunk};
+
+const INDEX_FILE: &str = "embeddings.json";
+
+/// Bumped whenever files are chunked differently.
+const INDEX_VERSION: u32 = 1;
+
+/// Files that are searched.
+const SOURCE_EXTENSIONS: &[&str] = &[
+    "rs", "py", "js", "jsx", "mjs", "cjs", "ts", "tsx", "go", "java", "kt", "cs", "c", "h", "cpp", "hpp", "rb",
+    "php", "swift", "scala", "sh",
+];
+
+const MAX_FILES: usize = 20_000;
+
+const MAX_FILE_BYTES: u64 = 512 * 1024;
+
+/// Texts sent per embeddings request.
+const EMBED_BATCH: usize = 64;
+
+/// Characters of a chunk that are embedded; the rest rarely changes what
+/// it is about.
+const MAX_EMBED_CHARS: usize = 6000;
+
+const DEFAULT_TOP_K: usize = 5;
+
+const MAX_TOP_K: usize = 20;
+
+/// Characters of each result's code returned to the model.
+const MAX_SNIPPET_CHARS: usize = 2000;
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+struct IndexedChunk {
+    span: Span,
+    vector: Vec<f32>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+struct IndexedFile {
+    stamp: Stamp,
+    chunks: Vec<IndexedChunk>,
+}
+
+#[derive(Debug, Default, Serialize, Deserialize)]
+struct EmbeddingIndex {
+    version: u32,
+    model: String,
+    files: BTreeMap<String, IndexedFile>,
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct SearchResult {
+    pub path: String,
+    pub start_line: usize,
+    pub end_line: usize,
+    pub score: f32,
+    pub snippet: String,
+}
+
+/// Embeddings of every source file under `root`, made with `model`.
+pub struct SemanticIndex {
+    root: PathBuf,
+    embedder: Arc<dyn Embedder>,
+    model: String,
+}
+
+impl SemanticIndex {
+    pub fn new(root: PathBuf, embedder: Arc<dyn Embedder>, model: impl Into<String>) -> Self {
+        Self {
+            root,
+            embedder,
+            model: model.into(),
+        }
+    }
+
+    fn index_path(&self) -> PathBuf {
+        self.root.join(INDEX_DIR).join(INDEX_FILE)
+    }
+
+    fn load(&self) -> EmbeddingIndex {
+        std::fs::read_to_string(self.index_path())
+            .ok()
+            .and_then(|json| serde_json::from_str::<EmbeddingIndex>(&json).ok())
+            .filter(|index| index.version == INDEX_VERSION && index.model == self.model)
+            .unwrap_or_else(|| EmbeddingIndex {
+                version: INDEX_VERSION,
+                model: self.model.clone(),
+                files: BTreeMap::new(),
+            })
+    }
+
+    fn save(&self, index: &EmbeddingIndex) -> std::io::Result<()> {
+        let path = self.index_path();
+        if let Some(dir) = path.parent() {
+            std::fs::create_dir_all(dir)?;
+        }
+        let tmp = path.with_extension("json.tmp");
+        std::fs::write(&tmp, serde_json::to_string(index)?)?;
+        std::fs::rename(&tmp, &path)
+    }
+
+    /// Brings the index up to date with the files on disk, embedding only
+    /// new and changed files.
+    async fn update(&self) -> Result<EmbeddingIndex, LLMError> {
+        let mut previous = self.load();
+        let mut index = EmbeddingIndex {
+            version: INDEX_VERSION,
+            model: self.model.clone(),
+            files: BTreeMap::new(),
+        };
+        let mut pending: Vec<(String, Stamp, Vec<Span>, Vec<String>)> = Vec::new();
+
+        for entry in walk::walk(&self.root, MAX_FILES).await.entries {
+            let extension = entry.path.extension().map(|ext| ext.to_string_lossy().to_string()).unwrap_or_default();
+            if entry.is_dir || !SOURCE_EXTENSIONS.contains(&extension.as_str()) {
+                continue;
+            }
+            let Ok(relative) = entry.path.strip_prefix(&self.root) else {
+                continue;
+            };
+            let path = relative.to_string_lossy().replace('\\', "/");
+            let Ok(metadata) = std::fs::metadata(&entry.path) else {
+                continue;
+            };
+            let stamp = Stamp::of(&metadata);
+            if let Some(file) = previous.files.remove(&path)
+                && file.stamp == stamp
+            {
+                index.files.insert(path, file);
+                continue;
+            }
+            if metadata.len() > MAX_FILE_BYTES {
+                continue;
+            }
+            let Ok(content) = std::fs::read_to_string(&entry.path) else {
+                continue;
+            };
+            let lines: Vec<&str> = content.lines().collect();
+            let spans = chunk(&extension, &content);
+            let texts = spans
+                .iter()
+                .map(|span| {
+                    let text = format!("{}\n{}", path, span.text(&lines));
+                    let end = text.floor_char_boundary(MAX_EMBED_CHARS);
+                    text[..end].to_string()
+                })
+                .collect();
+            pending.push((path, stamp, spans, texts));
+        }
+
+        let changed = !pending.is_empty() || !previous.files.is_empty();
+        let texts: Vec<String> = pending.iter().flat_map(|(.., texts)| texts.iter().cloned()).collect();
+        let mut vectors = Vec::with_capacity(texts.len());
+        for batch in texts.chunks(EMBED_BATCH) {
+            vectors.extend(self.embedder.embed(batch).await?);
+        }
+        let mut vectors = vectors.into_iter();
+        for (path, stamp, spans, _) in pending {
+            let chunks = spans
+                .into_iter()
+                .zip(vectors.by_ref())
+                .map(|(span, vector)| IndexedChunk { span, vector })
+                .collect();
+            index.files.insert(path, IndexedFile { stamp, chunks });
+        }
+
+        if changed && let Err(e) = self.save(&index) {
+            tracing::debug!("Could not save the embeddings index: {}", e);
+        }
+        Ok(index)
+    }
+
+    /// The `top_k` chunks most similar to `query`, optionally only under
+    /// the directory or file `under`.
+    pub async fn search(&self, query: &str, top_k: usize, under: Option<&str>) -> Result<Vec<SearchResult>, LLMError> {
+        let index = self.update().await?;
+        let query = self
+            .embedder
+            .embed(&[query.to_string()])
+            .await?
+            .pop()
+            .ok_or_else(|| LLMError::ParseError("No embedding for the query".to_string()))?;
+
+        let under = under.map(|path| path.trim_start_matches("./").trim_end_matches('/'));
+        let mut scored: Vec<(f32, &str, Span)> = index
+            .files
+            .iter()
+            .filter(|(path, _)| {
+                under.is_none_or(|under| under.is_empty() || *path == under || path.starts_with(&format!("{}/", under)))
+            })
+            .flat_map(|(path, file)| {
+                file.chunks
+                    .iter()
+                    .map(|chunk| (cosine_similarity(&query, &chunk.vector), path.as_str(), chunk.span))
+            })
+            .collect();
+        scored.sort_by(|a, b| b.0.total_cmp(&a.0));
+        scored.truncate(top_k);
+
+        Ok(scored
+            .into_iter()
+            .map(|(score, path, span)| {
+                let content = std::fs::read_to_string(self.root.join(path)).unwrap_or_default();
+                let lines: Vec<&str> = content.lines().collect();
+                let text = span.text(&lines);
+                let end = text.floor_char_boundary(MAX_SNIPPET_CHARS);
+                SearchResult {
+                    path: path.to_string(),
+                    start_line: span.start_line,
+                    end_line: span.end_line,
+                    score,
+                    snippet: text[..end].to_string(),
+                }
+            })
+            .collect())
+    }
+}
+
+pub struct SemanticSearchTool {
+    index: Arc<SemanticIndex>,
+}
+
+impl SemanticSearchTool {
+    pub fn new(base_path: PathBuf, embedder: Arc<dyn Embedder>, model: impl Into<String>) -> Self {
+        Self {
+            index: Arc::new(SemanticIndex::new(base_path, embedder, model)),
+        }
+    }
+}
+
+impl ToolTrait for SemanticSearchTool {
+    fn info(&self) -> ToolInfo {
+        ToolInfo {
+            name: "semantic_search".to_string(),
+            description: "Find code by meaning rather than exact text, e.g. \"where are auth tokens validated?\". Returns the most related functions and types with their file and lines".to_string(),
+            parameters: serde_json::json!({
+                "type": "object",
+                "properties": {
+                    "query": {
+                        "type": "string",
+                        "description": "A question or description of the code to find"
+                    },
+                    "top_k": {
+                        "type": "integer",
+                        "description": "Number of results (default: 5, at most 20)"
+                    },
+                    "path": {
+                        "type": "string",
+                        "description": "Only search this directory or file"
+                    }
+                },
+                "required": ["query"]
+            }),
+            annotations: ToolAnnotations::read_only(),
+        }
+    }
+
+    fn execute(&self, arguments: Value) -> Pin<Box<dyn Future<Output = Result<ToolOutput, ToolError>> + Send + Sync>> {
+        let index = Arc::clone(&self.index);
+        Box::pin(async move {
+            let query = arguments
+                .get("query")
+                .and_then(|v| v.as_str())
+                .ok_or_else(|| ToolError::InvalidArguments("Missing 'query' argument".to_string()))?
+                .to_string();
+            let top_k = arguments
+                .get("top_k")
+                .and_then(|v| v.as_u64())
+                .map(|n| (n as usize).clamp(1, MAX_TOP_K))
+                .unwrap_or(DEFAULT_TOP_K);
+            let under = arguments.get("path").and_then(|v| v.as_str()).map(str::to_string);
+
+            // Embedding requests are not Sync, so they run on their own task.
+            let search = tokio::spawn(async move {
+                let results = index.search(&query, top_k, under.as_deref()).await;
+                (query, results)
+            });
+            let (query, results) = search.await.map_err(|e| ToolError::ExecutionFailed(e.to_string()))?;
+            let results = results.map_err(|e| ToolError::ExecutionFailed(e.to_string()))?;
+
+            Ok(ToolOutput::success(serde_json::json!({
+                "query": query,
+                "results": results
+            })))
+        })
+    }
+
+    /// Each result as `path:start-end (score)` followed by its code.
+    fn render(&self, output: &ToolOutput) -> Option<String> {
+        let results = output.data.get("results")?.as_array()?;
+        if results.is_empty() {
+            return Some("No code indexed yet.\n".to_string());
+        }
+        let mut text = String::new();
+        for entry in results {
+            text.push_str(&format!(
+                "{}:{}-{} (score {:.2})\n{}\n\n",
+                entry.get("path")?.as_str()?,
+                entry.get("start_line")?,
+                entry.get("end_line")?,
+                entry.get("score")?.as_f64()?,
+                entry.get("snippet")?.as_str()?.trim_end()
+            ));
+        }
+        Some(text)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use async_trait::async_trait;
+    use std::sync::atomic::{AtomicUsize, Ordering};
+
+    const TOPICS: &[&str] = &["token", "parse", "render"];
+
+    /// Embeds text as counts of a few topic words.
+    #[derive(Default)]
+    struct Topics {
+        embedded: AtomicUsize,
+    }
+
+    #[async_trait]
+    impl Embedder for Topics {
+        async fn embed(&self, texts: &[String]) -> Result<Vec<Vec<f32>>, LLMError> {
+            self.embedded.fetch_add(texts.len(), Ordering::Relaxed);
+            Ok(texts
+                .iter()
+                .map(|text| {
+                    let text = text.to_lowercase();
+                    let mut vector: Vec<f32> = TOPICS.iter().map(|topic| text.matches(topic).count() as f32).collect();
+                    vector.push(0.1);
+                    vector
+                })
+                .collect())
+        }
+    }
+
+    #[test]
+    fn test_chunk_by_definition() {
+        let rust = "use std::fmt;\n\n/// Checks a token.\npub fn validate_token(t: &str) -> bool {\n    !t.is_empty()\n}\n\nstruct Parser;\n\nimpl Parser {\n    fn parse(&self) {}\n}\n";
+        let spans: Vec<(usize, usize)> = chunk("rs", rust).iter().map(|s| (s.start_line, s.end_line)).collect();
+        assert_eq!(spans, [(4, 6), (8, 8), (10, 12)]);
+
+        let python = "import os\n\nclass Renderer:\n    def render(self):\n        pass\n\ndef main():\n    pass\n";
+        let spans: Vec<(usize, usize)> = chunk("py", python).iter().map(|s| (s.start_line, s.end_line)).collect();
+        assert_eq!(spans, [(3, 5), (7, 8)]);
+
+        let spans = chunk("txt", &"line\n".repeat(130));
+        assert_eq!(spans.len(), 3);
+        assert_eq!(spans[2], Span { start_line: 121, end_line: 130 });
+    }
+
+    #[tokio::test]
+    async fn test_semantic_search() {
+        let dir = tempfile::tempdir().unwrap();
+        std::fs::create_dir(dir.path().join("src")).unwrap();
+        std::fs::write(
+            dir.path().join("src/auth.rs"),
+            "fn check_token(token: &str) -> bool {\n    token.len() > 8\n}\n\nfn render_login() {}\n",
+        )
+        .unwrap();
+        std::fs::write(dir.path().join("src/view.py"), "def render_page():\n    render()\n").unwrap();
+        let embedder = Arc::new(Topics::default());
+        let tool = SemanticSearchTool::new(dir.path().to_path_buf(), Arc::clone(&embedder) as Arc<dyn Embedder>, "topics");
+
+        let result = tool
+            .execute(serde_json::json!({"query": "where is the token checked?", "top_k": 2}))
+            .await
+            .unwrap();
+
+        let results = result.data["results"].as_array().unwrap();
+        assert_eq!(results.len(), 2);
+        assert_eq!(results[0]["path"], "src/auth.rs");
+        assert_eq!(results[0]["start_line"], 1);
+        assert_eq!(results[0]["end_line"], 3);
+        assert!(results[0]["snippet"].as_str().unwrap().starts_with("fn check_token"));
+
+        // Only the query is embedded when nothing changed.
+        let before = embedder.embedded.load(Ordering::Relaxed);
+        let result = tool
+            .execute(serde_json::json!({"query": "render", "path": "src/view.py"}))
+            .await
+            .unwrap();
+        assert_eq!(embedder.embedded.load(Ordering::Relaxed), before + 1);
+        assert_eq!(result.data["results"].as_array().unwrap().len(), 1);
+        assert_eq!(result.data["results"][0]["path"], "src/view.py");
+    }
+}
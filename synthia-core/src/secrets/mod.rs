@@ -0,0 +1,124 @@
+//! API keys kept out of the environment: printed by a command, such as a
+//! password manager's CLI, or stored in the OS keychain. A key is looked
+//! up once per process, and errors never include it.
+
+use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+use std::process::{Command, Stdio};
+use std::sync::{Mutex, OnceLock};
+use thiserror::Error;
+
+/// The keychain service keys are stored under, with the provider's name
+/// as the account.
+pub const KEYCHAIN_SERVICE: &str = "synthia";
+
+#[derive(Debug, Error)]
+pub enum SecretError {
+    #[error("The key command `{0}` failed: {1}")]
+    Command(String, String),
+    #[error("No {0} key in the keychain: {1}")]
+    Keychain(String, String),
+    #[error("{0} printed no key")]
+    Empty(String),
+}
+
+/// Where a provider's key comes from when its variable is unset.
+///
+/// ```json
+/// { "openai": { "command": "op read op://dev/openai/key" }, "anthropic": "keychain" }
+/// ```
+#[derive(Debug, Clone, PartialEq, Eq, Hash, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum KeySource {
+    /// A shell command that prints the key.
+    Command(String),
+    /// The OS keychain: `security` on macOS, `secret-tool` elsewhere. Add a
+    /// key with `security add-generic-password -s synthia -a openai -w` or
+    /// `secret-tool store --label=synthia service synthia account openai`.
+    Keychain,
+}
+
+fn cache() -> &'static Mutex<HashMap<(String, KeySource), String>> {
+    static CACHE: OnceLock<Mutex<HashMap<(String, KeySource), String>>> = OnceLock::new();
+    CACHE.get_or_init(Default::default)
+}
+
+impl KeySource {
+    /// Where the key comes from, for messages. Only a command's program is
+    /// named, since its arguments may say where the key is kept.
+    pub fn describe(&self) -> String {
+        match self {
+            KeySource::Command(command) => {
+                format!("the command `{}`", command.split_whitespace().next().unwrap_or_default())
+            }
+            KeySource::Keychain => "the keychain".to_string(),
+        }
+    }
+
+    /// The key for `provider`, trimmed.
+    pub fn resolve(&self, provider: &str) -> Result<String, SecretError> {
+        let cache_key = (provider.to_string(), self.clone());
+        if let Some(key) = cache().lock().ok().and_then(|cache| cache.get(&cache_key).cloned()) {
+            return Ok(key);
+        }
+        let key = match self {
+            KeySource::Command(command) => {
+                let program = command.split_whitespace().next().unwrap_or_default().to_string();
+                run("sh", &["-c", command]).map_err(|e| SecretError::Command(program, e))?
+            }
+            KeySource::Keychain if cfg!(target_os = "macos") => run(
+                "security",
+                &["find-generic-password", "-s", KEYCHAIN_SERVICE, "-a", provider, "-w"],
+            )
+            .map_err(|e| SecretError::Keychain(provider.to_string(), e))?,
+            KeySource::Keychain => run("secret-tool", &["lookup", "service", KEYCHAIN_SERVICE, "account", provider])
+                .map_err(|e| SecretError::Keychain(provider.to_string(), e))?,
+        };
+        if key.is_empty() {
+            return Err(SecretError::Empty(self.describe()));
+        }
+        if let Ok(mut cache) = cache().lock() {
+            cache.insert(cache_key, key.clone());
+        }
+        Ok(key)
+    }
+}
+
+/// What `program` prints, trimmed. Failures carry its stderr, never its
+/// stdout.
+fn run(program: &str, args: &[&str]) -> Result<String, String> {
+    let output = Command::new(program)
+        .args(args)
+        .stdin(Stdio::null())
+        .output()
+        .map_err(|e| format!("{}: {}", program, e))?;
+    if !output.status.success() {
+        return Err(format!("exited with {}: {}", output.status, String::from_utf8_lossy(&output.stderr).trim()));
+    }
+    Ok(String::from_utf8_lossy(&output.stdout).trim().to_string())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_key_command() {
+        let dir = tempfile::tempdir().unwrap();
+        let counter = dir.path().join("runs");
+        let command = format!("echo run >> {}; echo '  sk-test-123  '", counter.display());
+        let source = KeySource::Command(command);
+        assert_eq!(source.resolve("test-provider").unwrap(), "sk-test-123");
+        assert_eq!(source.resolve("test-provider").unwrap(), "sk-test-123");
+        assert_eq!(std::fs::read_to_string(&counter).unwrap(), "run\n");
+
+        let failing = KeySource::Command("echo sk-leaked; echo locked >&2; exit 1".to_string());
+        let error = failing.resolve("test-provider").unwrap_err().to_string();
+        assert_eq!(error, "The key command `echo` failed: exited with exit status: 1: locked");
+        let parsed: HashMap<String, KeySource> =
+            serde_json::from_str(r#"{"openai": {"command": "op read op://dev/openai/key"}, "anthropic": "keychain"}"#)
+                .unwrap();
+        assert_eq!(parsed["anthropic"], KeySource::Keychain);
+        assert_eq!(parsed["openai"].describe(), "the command `op`");
+    }
+}
@@ -0,0 +1,155 @@
+use super::{Session, SessionError, now};
+use crate::clients::{Message, MessageRole, ScriptedClient, Usage};
+use crate::migrate::{self, FormatError, Migration};
+use serde::{Deserialize, Serialize};
+use std::path::Path;
+
+/// How to bring an export at each older version up to the next. Add one
+/// whenever a change would make older builds misread an export.
+const EXPORT_MIGRATIONS: &[Migration] = &[
+    // Exports have always had a version.
+    |_| {},
+];
+
+/// The version of [`SessionExport`] written by this build. Exports from
+/// newer versions are refused.
+pub const EXPORT_VERSION: u32 = EXPORT_MIGRATIONS.len() as u32;
+
+/// A session in a self-contained form, to move it between machines, attach
+/// it to a bug report, or replay it.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct SessionExport {
+    pub version: u32,
+    /// Unix seconds.
+    pub exported_at: u64,
+    pub session: Session,
+    /// The conversation rebuilt from the steps: the task, each response and
+    /// each tool result. Prompts the agent added between turns are not
+    /// included.
+    pub messages: Vec<Message>,
+    /// Summed over the steps. Reasoning tokens are part of `output_tokens`,
+    /// since steps don't keep them apart.
+    pub usage: Usage,
+}
+
+impl SessionExport {
+    pub fn new(session: Session) -> Self {
+        let mut messages = vec![Message {
+            role: MessageRole::User,
+            content: session.task.clone(),
+            tool_calls: None,
+        }];
+        let mut usage = Usage::default();
+        for step in &session.steps {
+            messages.push(Message {
+                role: MessageRole::Assistant,
+                content: step.raw.clone(),
+                tool_calls: None,
+            });
+            if !step.action.is_empty() {
+                messages.push(Message {
+                    role: MessageRole::Tool,
+                    content: step.observation.clone(),
+                    tool_calls: None,
+                });
+            }
+            usage.input_tokens += step.prompt_tokens.unwrap_or(0);
+            usage.output_tokens += step.completion_tokens.unwrap_or(0);
+        }
+
+        Self {
+            version: EXPORT_VERSION,
+            exported_at: now().as_secs(),
+            session,
+            messages,
+            usage,
+        }
+    }
+
+    pub fn to_json(&self) -> String {
+        serde_json::to_string_pretty(self).unwrap_or_default()
+    }
+
+    /// Reads an export written by this or an earlier version.
+    pub fn load(path: &Path) -> Result<Self, SessionError> {
+        let json = std::fs::read_to_string(path).map_err(|e| SessionError::Io(path.to_path_buf(), e.to_string()))?;
+        match migrate::upgrade(&json, EXPORT_MIGRATIONS) {
+            Ok((export, _)) => Ok(export),
+            Err(FormatError::Newer { found, .. }) => Err(SessionError::UnsupportedVersion(found)),
+            Err(e) => Err(SessionError::Invalid(path.to_path_buf(), e.to_string())),
+        }
+    }
+
+    /// A client that gives the session's responses again in order, to
+    /// replay the run against the same files. Timeouts are not reproduced:
+    /// a response cut off by one is given as it was received.
+    pub fn replay_client(&self) -> ScriptedClient {
+        ScriptedClient::from_responses(self.session.steps.iter().map(|step| step.raw.clone()))
+            .with_model(self.session.model.clone())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::core::{ReactAgent, Step, final_answer};
+    use crate::tools::default_tools;
+    use std::path::PathBuf;
+
+    #[tokio::test]
+    async fn test_export_round_trip_and_replay() {
+        let dir = tempfile::tempdir().unwrap();
+        std::fs::write(dir.path().join("notes.txt"), "hello").unwrap();
+        let call = ScriptedClient::tool_call("read_file", serde_json::json!({"path": "notes.txt"}));
+
+        let mut session = Session::new("What do the notes say?", PathBuf::from("."), "gpt-4o");
+        let mut step = Step::new(
+            String::new(),
+            "read_file".to_string(),
+            serde_json::json!({"path": "notes.txt"}),
+            "hello".to_string(),
+            call.clone(),
+        );
+        step.prompt_tokens = Some(100);
+        step.completion_tokens = Some(10);
+        session.push_step(step);
+        session.push_step(Step::new(
+            "FINAL: hello".to_string(),
+            String::new(),
+            serde_json::json!({}),
+            String::new(),
+            "FINAL: hello".to_string(),
+        ));
+        session.finish(None);
+
+        let export = SessionExport::new(session.clone());
+        assert_eq!(export.messages.len(), 4);
+        assert_eq!(export.messages[2].role, MessageRole::Tool);
+        assert_eq!(export.usage.input_tokens, 100);
+        assert_eq!(export.usage.output_tokens, 10);
+
+        let path = dir.path().join("session.json");
+        std::fs::write(&path, export.to_json()).unwrap();
+        let imported = SessionExport::load(&path).unwrap();
+        assert_eq!(imported, export);
+
+        let mut agent = ReactAgent::new(
+            Box::new(imported.replay_client()),
+            default_tools(dir.path().to_path_buf()),
+            dir.path().to_path_buf(),
+            Some(5),
+            Some(false),
+            None,
+        );
+        let steps = agent.run(&imported.session.task).await.unwrap().steps;
+        assert_eq!(steps.len(), 2);
+        assert_eq!(steps[0].action, "read_file");
+        assert!(steps[0].observation.contains("hello"));
+        assert_eq!(final_answer(&steps).as_deref(), Some("hello"));
+
+        std::fs::write(&path, export.to_json().replacen("\"version\": 1", "\"version\": 2", 1)).unwrap();
+        assert!(matches!(SessionExport::load(&path), Err(SessionError::UnsupportedVersion(2))));
+        std::fs::write(&path, "{}").unwrap();
+        assert!(matches!(SessionExport::load(&path), Err(SessionError::Invalid(..))));
+    }
+}
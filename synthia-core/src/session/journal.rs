@@ -0,0 +1,291 @@
+//! A record of a run kept beside its session while it runs. Each step is
+//! appended and synced to disk before the run goes on, so after a crash or
+//! power loss the steps are there even if the last save of the session
+//! was lost. A run that ends removes its journal; one left behind by a
+//! process that is gone marks a run that was cut short.
+//!
+//! The start entry carries the session with its format version, and the
+//! steps after it are in the same format, so a journal left by an older
+//! build is upgraded like a saved session.
+
+use super::{SESSION_MIGRATIONS, SESSION_VERSION, Session, SessionError, SessionStatus, SessionStore};
+use crate::core::Step;
+use crate::migrate::{self, FormatError};
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+use std::fs::{File, OpenOptions};
+use std::io::Write;
+use std::path::{Path, PathBuf};
+#[cfg(not(unix))]
+use std::process::{Command, Stdio};
+
+/// Why a recovered session stopped.
+const INTERRUPTED_ERROR: &str = "Interrupted: the process stopped before the run finished";
+
+/// Added to a journal that can't be read, which is set aside rather than
+/// read again on every start.
+const UNREADABLE_EXTENSION: &str = "unreadable";
+
+#[derive(Serialize, Deserialize)]
+#[serde(tag = "kind", rename_all = "snake_case")]
+enum Entry {
+    /// `session` has a `version`, as saved sessions do.
+    Start { pid: u32, session: Value },
+    Step { step: Value },
+}
+
+/// The journal of one run.
+pub struct RunJournal {
+    path: PathBuf,
+    file: File,
+}
+
+impl RunJournal {
+    /// Starts the journal of `session`, which should already be saved in
+    /// `store`. The start entry is written aside and renamed into place, so
+    /// a journal is never seen without it.
+    pub fn start(store: &SessionStore, session: &Session) -> Result<Self, SessionError> {
+        let path = store.journal_path(&session.id);
+        let io = |e: std::io::Error| SessionError::Io(path.clone(), e.to_string());
+        let invalid = |e: FormatError| SessionError::Invalid(path.clone(), e.to_string());
+        std::fs::create_dir_all(store.dir()).map_err(io)?;
+        let tmp = path.with_extension("journal.tmp");
+        let file = OpenOptions::new().create(true).truncate(true).write(true).open(&tmp).map_err(io)?;
+        let mut journal = Self { path: tmp, file };
+        journal.append(&Entry::Start {
+            pid: std::process::id(),
+            session: migrate::versioned_value(session, SESSION_VERSION).map_err(invalid)?,
+        })?;
+        std::fs::rename(&journal.path, &path).map_err(io)?;
+        journal.path = path;
+        Ok(journal)
+    }
+
+    /// Appends `step`, returning once it is on disk.
+    pub fn record(&mut self, step: &Step) -> Result<(), SessionError> {
+        let step = serde_json::to_value(step).map_err(|e| SessionError::Invalid(self.path.clone(), e.to_string()))?;
+        self.append(&Entry::Step { step })
+    }
+
+    fn append(&mut self, entry: &Entry) -> Result<(), SessionError> {
+        let io = |e: std::io::Error| SessionError::Io(self.path.clone(), e.to_string());
+        let mut line = serde_json::to_string(entry).map_err(|e| SessionError::Invalid(self.path.clone(), e.to_string()))?;
+        line.push('\n');
+        self.file.write_all(line.as_bytes()).map_err(io)?;
+        self.file.sync_data().map_err(io)
+    }
+
+    /// Removes the journal once the run has ended and its session is saved.
+    pub fn finish(self) -> Result<(), SessionError> {
+        std::fs::remove_file(&self.path).map_err(|e| SessionError::Io(self.path.clone(), e.to_string()))
+    }
+}
+
+impl SessionStore {
+    fn journal_path(&self, id: &str) -> PathBuf {
+        self.dir.join(format!("{}.journal", id))
+    }
+
+    /// Sessions whose run was cut short: their journal is still here and
+    /// the process that wrote it is gone. Each is brought up to date from
+    /// its journal, marked as aborted and saved, so it can be continued,
+    /// and its journal removed. Oldest first. Journals that can't be read
+    /// are renamed to `*.journal.unreadable` and left for the user; ones
+    /// from a newer version are left for it.
+    pub fn recover_interrupted(&self) -> Result<Vec<Session>, SessionError> {
+        let entries = match std::fs::read_dir(&self.dir) {
+            Ok(entries) => entries,
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound => return Ok(Vec::new()),
+            Err(e) => return Err(SessionError::Io(self.dir.clone(), e.to_string())),
+        };
+
+        let mut recovered = Vec::new();
+        for entry in entries.flatten() {
+            let path = entry.path();
+            if path.extension().is_none_or(|extension| extension != "journal") {
+                continue;
+            }
+            let text = std::fs::read_to_string(&path).map_err(|e| SessionError::Io(path.clone(), e.to_string()))?;
+            // A crash while appending leaves the last line cut off.
+            let mut entries = text.lines().filter_map(|line| serde_json::from_str::<Entry>(line).ok());
+            let Some(Entry::Start { pid, session: started }) = entries.next() else {
+                set_aside(&path, "it has no start entry");
+                continue;
+            };
+            if is_running(pid) {
+                continue;
+            }
+            let steps: Vec<Value> = entries
+                .filter_map(|entry| match entry {
+                    Entry::Step { step } => Some(step),
+                    Entry::Start { .. } => None,
+                })
+                .collect();
+            // The steps are read with the session so they are upgraded
+            // along with it.
+            let mut journaled = started;
+            if let Value::Object(object) = &mut journaled {
+                object.insert("steps".to_string(), Value::Array(steps));
+            }
+            let journaled: Session = match migrate::upgrade_value(journaled, SESSION_MIGRATIONS) {
+                Ok((session, _)) => session,
+                Err(e @ FormatError::Newer { .. }) => {
+                    tracing::debug!("Leaving run journal {}: {}", path.display(), e);
+                    continue;
+                }
+                Err(e) => {
+                    set_aside(&path, &e.to_string());
+                    continue;
+                }
+            };
+
+            // The journal has every step; the saved session may be behind
+            // it, or lost if the crash came while it was being replaced.
+            let mut session = self.load(&journaled.id).unwrap_or_else(|_| journaled.clone());
+            if journaled.steps.len() > session.steps.len() {
+                session.steps = journaled.steps;
+            }
+            if session.status == SessionStatus::Running {
+                session.finish(Some(INTERRUPTED_ERROR.to_string()));
+            }
+            self.save(&session)?;
+            std::fs::remove_file(&path).map_err(|e| SessionError::Io(path.clone(), e.to_string()))?;
+            recovered.push(session);
+        }
+        recovered.sort_by(|a, b| (a.started_at, &a.id).cmp(&(b.started_at, &b.id)));
+        Ok(recovered)
+    }
+}
+
+/// Renames the journal at `path` so it isn't read again, warning once.
+fn set_aside(path: &Path, reason: &str) {
+    let mut aside = path.as_os_str().to_owned();
+    aside.push(".");
+    aside.push(UNREADABLE_EXTENSION);
+    let aside = PathBuf::from(aside);
+    match std::fs::rename(path, &aside) {
+        Ok(()) => tracing::warn!("Run journal {} can't be read ({}); moved it to {}", path.display(), reason, aside.display()),
+        Err(e) => tracing::warn!("Run journal {} can't be read ({}) or moved: {}", path.display(), reason, e),
+    }
+}
+
+/// Whether the process `pid` is still running. When that can't be told,
+/// it is taken to be, so a live run is never recovered from under itself.
+fn is_running(pid: u32) -> bool {
+    if pid == std::process::id() {
+        return true;
+    }
+    #[cfg(unix)]
+    {
+        let Ok(pid) = libc::pid_t::try_from(pid) else {
+            return false;
+        };
+        // SAFETY: signal 0 only checks that `pid` can be signalled.
+        if unsafe { libc::kill(pid, 0) } == 0 {
+            return true;
+        }
+        // EPERM is a live process owned by someone else; only ESRCH says
+        // it is gone.
+        std::io::Error::last_os_error().raw_os_error() != Some(libc::ESRCH)
+    }
+    #[cfg(not(unix))]
+    {
+        let pid = pid.to_string();
+        Command::new("tasklist")
+            .args(["/FI", &format!("PID eq {}", pid), "/NH"])
+            .stderr(Stdio::null())
+            .output()
+            .map(|output| String::from_utf8_lossy(&output.stdout).contains(&pid))
+            .unwrap_or(true)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_recover_interrupted() {
+        let dir = tempfile::tempdir().unwrap();
+        let store = SessionStore::new(dir.path().to_path_buf());
+        let step = |action: &str| Step::new(String::new(), action.to_string(), serde_json::json!({}), String::new(), String::new());
+
+        let mut session = Session::new("Fix the parser", PathBuf::from("."), "gpt-4o");
+        store.save(&session).unwrap();
+        let mut journal = RunJournal::start(&store, &session).unwrap();
+        for action in ["read_file", "edit_file"] {
+            journal.record(&step(action)).unwrap();
+        }
+        // The second save was lost, and the process died mid-append.
+        session.push_step(step("read_file"));
+        store.save(&session).unwrap();
+        drop(journal);
+        let path = store.journal_path(&session.id);
+        std::fs::OpenOptions::new().append(true).open(&path).unwrap().write_all(b"{\"kind\": \"st").unwrap();
+
+        // Still this process's own run.
+        assert!(store.recover_interrupted().unwrap().is_empty());
+        let text = std::fs::read_to_string(&path).unwrap();
+        let pid = std::process::id().to_string();
+        // Above the largest pid Linux hands out.
+        std::fs::write(&path, text.replacen(&pid, "999999999", 1)).unwrap();
+
+        let recovered = store.recover_interrupted().unwrap();
+        assert_eq!(recovered.len(), 1);
+        let loaded = store.load(&session.id).unwrap();
+        assert_eq!(loaded, recovered[0]);
+        assert_eq!(loaded.steps.len(), 2);
+        assert_eq!(loaded.steps[1].action, "edit_file");
+        assert_eq!(loaded.status, SessionStatus::Aborted);
+        assert_eq!(loaded.error.as_deref(), Some(INTERRUPTED_ERROR));
+        assert!(!path.exists());
+        assert!(store.recover_interrupted().unwrap().is_empty());
+
+        let mut finished = Session::new("Done", PathBuf::from("."), "gpt-4o");
+        finished.id = "f1".to_string();
+        RunJournal::start(&store, &finished).unwrap().finish().unwrap();
+        assert!(!store.journal_path("f1").exists());
+    }
+
+    #[test]
+    fn test_recover_old_and_unreadable_journals() {
+        let dir = tempfile::tempdir().unwrap();
+        let store = SessionStore::new(dir.path().to_path_buf());
+
+        // From before journals carried a version, and with no saved session.
+        let mut session = serde_json::to_value(Session::new("Old run", PathBuf::from("."), "gpt-4o")).unwrap();
+        session.as_object_mut().unwrap().remove("version");
+        session["id"] = "old".into();
+        let step = Step::new(String::new(), "read_file".to_string(), serde_json::json!({}), String::new(), String::new());
+        let lines = [
+            serde_json::json!({"kind": "start", "pid": 999999999, "session": session}),
+            serde_json::json!({"kind": "step", "step": step}),
+        ];
+        let text: String = lines.iter().map(|line| format!("{}\n", line)).collect();
+        std::fs::write(store.journal_path("old"), text).unwrap();
+        // Cut off before its start entry was whole.
+        std::fs::write(store.journal_path("cut"), "{\"kind\": \"sta").unwrap();
+        let newer = serde_json::json!({"kind": "start", "pid": 999999999, "session": {"version": SESSION_VERSION + 1}});
+        std::fs::write(store.journal_path("newer"), newer.to_string()).unwrap();
+
+        let recovered = store.recover_interrupted().unwrap();
+        assert_eq!(recovered.len(), 1);
+        assert_eq!(recovered[0].id, "old");
+        assert_eq!(recovered[0].steps[0].action, "read_file");
+        assert_eq!(store.load("old").unwrap().status, SessionStatus::Aborted);
+        assert!(!store.journal_path("cut").exists());
+        assert!(dir.path().join("cut.journal.unreadable").exists());
+        // A newer build may still recover it.
+        assert!(store.journal_path("newer").exists());
+        assert!(store.recover_interrupted().unwrap().is_empty());
+    }
+
+    #[test]
+    fn test_is_running() {
+        assert!(is_running(std::process::id()));
+        // Alive even when it can't be signalled because someone else owns it.
+        #[cfg(unix)]
+        assert!(is_running(1));
+        assert!(!is_running(999999999));
+    }
+}
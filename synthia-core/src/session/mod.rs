@@ -0,0 +1,571 @@
+use crate::clients::{ChunkType, LLMClient, LLMError, Message, MessageRole};
+use crate::core::Step;
+use crate::migrate::{self, FormatError, Migration};
+use crate::prompts::{build_handoff_prompt, build_session_title_prompt};
+use futures::StreamExt;
+use serde::{Deserialize, Serialize};
+use std::path::{Path, PathBuf};
+use std::time::{SystemTime, UNIX_EPOCH};
+use thiserror::Error;
+
+mod export;
+mod journal;
+mod report;
+mod scratch;
+
+pub use export::{EXPORT_VERSION, SessionExport};
+pub use journal::RunJournal;
+pub use report::{UsageReport, UsageRow, parse_age};
+pub use scratch::{KeepScratch, SCRATCH_DIR, ScratchDir, ScratchPolicy};
+
+pub use crate::paths::SESSION_DIR;
+
+/// Observations longer than this are cut when a transcript is rendered for
+/// the handoff summary.
+const TRANSCRIPT_OBSERVATION_CHARS: usize = 800;
+
+/// The end of the transcript the title and summary are written from, in
+/// characters.
+const TITLE_TRANSCRIPT_CHARS: usize = 8000;
+
+/// Tasks longer than this are cut in [`Session::label`].
+const LABEL_TASK_CHARS: usize = 60;
+
+/// Bytes of context kept on each side of a search match.
+const SNIPPET_CONTEXT: usize = 80;
+
+/// How to bring a session saved at each older version up to the next. Add
+/// one whenever a change would make older builds misread a session.
+const SESSION_MIGRATIONS: &[Migration] = &[
+    // Sessions from before versioning have the same fields.
+    |_| {},
+];
+
+/// The version of saved sessions written by this build.
+pub const SESSION_VERSION: u32 = SESSION_MIGRATIONS.len() as u32;
+
+#[derive(Debug, Error)]
+pub enum SessionError {
+    #[error("Failed to access session {0}: {1}")]
+    Io(PathBuf, String),
+    #[error("Invalid session {0}: {1}")]
+    Invalid(PathBuf, String),
+    #[error("No session matches '{0}'")]
+    NotFound(String),
+    #[error("'{0}' matches more than one session")]
+    Ambiguous(String),
+    #[error("Session {0} already exists")]
+    Exists(String),
+    #[error("Session export version {0} is newer than this build reads ({EXPORT_VERSION})")]
+    UnsupportedVersion(u32),
+    #[error("Session {0} was saved by a newer version (format {1}); this build reads up to {SESSION_VERSION}")]
+    Newer(PathBuf, u32),
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum SessionStatus {
+    Running,
+    Finished,
+    Aborted,
+}
+
+/// One agent run as recorded on disk.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct Session {
+    pub id: String,
+    pub task: String,
+    pub workdir: PathBuf,
+    pub model: String,
+    pub status: SessionStatus,
+    /// Unix seconds.
+    pub started_at: u64,
+    pub updated_at: u64,
+    /// The session this one continues, if any.
+    #[serde(default)]
+    pub parent: Option<String>,
+    /// A short title, written after the run.
+    #[serde(default)]
+    pub title: Option<String>,
+    /// One paragraph on what the run did, written with the title.
+    #[serde(default)]
+    pub summary: Option<String>,
+    #[serde(default)]
+    pub error: Option<String>,
+    #[serde(default)]
+    pub steps: Vec<Step>,
+}
+
+fn now() -> std::time::Duration {
+    SystemTime::now().duration_since(UNIX_EPOCH).unwrap_or_default()
+}
+
+impl Session {
+    pub fn new(task: impl Into<String>, workdir: PathBuf, model: impl Into<String>) -> Self {
+        let now = now();
+        Self {
+            id: format!("{:x}", now.as_millis()),
+            task: task.into(),
+            workdir,
+            model: model.into(),
+            status: SessionStatus::Running,
+            started_at: now.as_secs(),
+            updated_at: now.as_secs(),
+            parent: None,
+            title: None,
+            summary: None,
+            error: None,
+            steps: Vec::new(),
+        }
+    }
+
+    pub fn with_parent(mut self, parent: impl Into<String>) -> Self {
+        self.parent = Some(parent.into());
+        self
+    }
+
+    pub fn push_step(&mut self, step: Step) {
+        self.steps.push(step);
+        self.updated_at = now().as_secs();
+    }
+
+    /// Marks the run as over, aborted if `error` is set.
+    pub fn finish(&mut self, error: Option<String>) {
+        self.status = match error {
+            Some(_) => SessionStatus::Aborted,
+            None => SessionStatus::Finished,
+        };
+        self.error = error;
+        self.updated_at = now().as_secs();
+    }
+
+    /// The title, or the start of the task for sessions without one.
+    pub fn label(&self) -> String {
+        if let Some(title) = &self.title {
+            return title.clone();
+        }
+        let task = self.task.split_whitespace().collect::<Vec<_>>().join(" ");
+        match task.char_indices().nth(LABEL_TASK_CHARS) {
+            Some((end, _)) => format!("{}...", &task[..end]),
+            None => task,
+        }
+    }
+
+    /// The steps as plain text, with long observations shortened.
+    pub fn transcript(&self) -> String {
+        let mut out = String::new();
+        for (i, step) in self.steps.iter().enumerate() {
+            out.push_str(&format!("Step {}\nThought: {}\n", i + 1, step.thought.trim()));
+            if !step.action.is_empty() {
+                out.push_str(&format!("Action: {} {}\n", step.action, step.action_input));
+            }
+            if !step.observation.is_empty() {
+                let observation: String = step.observation.chars().take(TRANSCRIPT_OBSERVATION_CHARS).collect();
+                let cut = if observation.len() < step.observation.len() { " [...]" } else { "" };
+                out.push_str(&format!("Observation: {}{}\n", observation, cut));
+            }
+            if let Some(diff) = &step.diff {
+                let shown: String = diff.chars().take(TRANSCRIPT_OBSERVATION_CHARS).collect();
+                let cut = if shown.len() < diff.len() { "[...]\n" } else { "" };
+                out.push_str(&format!("Changes:\n{}{}", shown, cut));
+            }
+            out.push('\n');
+        }
+        if let Some(error) = &self.error {
+            out.push_str(&format!("The run stopped with an error: {}\n", error));
+        }
+        out
+    }
+}
+
+/// One place a search query matched.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct SearchHit {
+    pub session_id: String,
+    pub task: String,
+    pub started_at: u64,
+    /// The 1-based step that matched, or `None` for the task itself.
+    pub step: Option<usize>,
+    pub snippet: String,
+}
+
+/// The text around the first match of `term` in `text`, on one line.
+fn snippet(text: &str, lowered: &str, term: &str) -> String {
+    let at = lowered.find(term).unwrap_or(0);
+    let start = text.floor_char_boundary(at.saturating_sub(SNIPPET_CONTEXT));
+    let end = text.ceil_char_boundary((at + term.len() + SNIPPET_CONTEXT).min(text.len()));
+    let body = text[start..end].split_whitespace().collect::<Vec<_>>().join(" ");
+    format!(
+        "{}{}{}",
+        if start > 0 { "..." } else { "" },
+        body,
+        if end < text.len() { "..." } else { "" }
+    )
+}
+
+/// Sessions saved as one JSON file each under a directory.
+pub struct SessionStore {
+    dir: PathBuf,
+}
+
+impl SessionStore {
+    pub fn new(dir: PathBuf) -> Self {
+        Self { dir }
+    }
+
+    /// The store under `workdir`'s [`SESSION_DIR`].
+    pub fn for_workdir(workdir: &Path) -> Self {
+        Self::new(workdir.join(SESSION_DIR))
+    }
+
+    pub fn dir(&self) -> &Path {
+        &self.dir
+    }
+
+    fn path(&self, id: &str) -> PathBuf {
+        self.dir.join(format!("{}.json", id))
+    }
+
+    /// Writes `session`, replacing any earlier save. The file is written
+    /// aside and renamed into place so a crash never leaves half of one.
+    pub fn save(&self, session: &Session) -> Result<(), SessionError> {
+        let path = self.path(&session.id);
+        let io = |e: std::io::Error| SessionError::Io(path.clone(), e.to_string());
+        std::fs::create_dir_all(&self.dir).map_err(io)?;
+        let json =
+            migrate::versioned(session, SESSION_VERSION).map_err(|e| SessionError::Invalid(path.clone(), e.to_string()))?;
+        let tmp = path.with_extension("json.tmp");
+        std::fs::write(&tmp, json).map_err(io)?;
+        std::fs::rename(&tmp, &path).map_err(io)
+    }
+
+    /// Saves a session from elsewhere, such as an export, unless one with
+    /// its id is already here.
+    pub fn import(&self, session: &Session) -> Result<(), SessionError> {
+        if self.path(&session.id).exists() {
+            return Err(SessionError::Exists(session.id.clone()));
+        }
+        self.save(session)
+    }
+
+    /// Reads a session saved by this or an earlier version.
+    pub fn load(&self, id: &str) -> Result<Session, SessionError> {
+        self.load_versioned(id).map(|(session, _)| session)
+    }
+
+    /// The session and the version it was saved at.
+    fn load_versioned(&self, id: &str) -> Result<(Session, u32), SessionError> {
+        let path = self.path(id);
+        let json = match std::fs::read_to_string(&path) {
+            Ok(json) => json,
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound => {
+                return Err(SessionError::NotFound(id.to_string()));
+            }
+            Err(e) => return Err(SessionError::Io(path, e.to_string())),
+        };
+        migrate::upgrade(&json, SESSION_MIGRATIONS).map_err(|e| match e {
+            FormatError::Newer { found, .. } => SessionError::Newer(path, found),
+            e => SessionError::Invalid(path, e.to_string()),
+        })
+    }
+
+    /// The ids of the sessions saved here, in no order.
+    fn ids(&self) -> Result<Vec<String>, SessionError> {
+        let entries = match std::fs::read_dir(&self.dir) {
+            Ok(entries) => entries,
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound => return Ok(Vec::new()),
+            Err(e) => return Err(SessionError::Io(self.dir.clone(), e.to_string())),
+        };
+        Ok(entries
+            .flatten()
+            .filter_map(|entry| {
+                let name = entry.file_name().into_string().ok()?;
+                name.strip_suffix(".json").map(str::to_string)
+            })
+            .collect())
+    }
+
+    /// Every readable session, oldest first. Unreadable files are skipped.
+    pub fn list(&self) -> Result<Vec<Session>, SessionError> {
+        let mut sessions = Vec::new();
+        for id in self.ids()? {
+            match self.load(&id) {
+                Ok(session) => sessions.push(session),
+                Err(e) => tracing::warn!("Skipping session: {}", e),
+            }
+        }
+        sessions.sort_by(|a, b| (a.started_at, &a.id).cmp(&(b.started_at, &b.id)));
+        Ok(sessions)
+    }
+
+    /// Saves every session from an older version again in the current
+    /// format, or with `dry_run` only finds them. Returns their ids with
+    /// the version each was at. Sessions that can't be read are left as
+    /// they are.
+    pub fn migrate(&self, dry_run: bool) -> Result<Vec<(String, u32)>, SessionError> {
+        let mut migrated = Vec::new();
+        for id in self.ids()? {
+            let (session, version) = match self.load_versioned(&id) {
+                Ok(loaded) => loaded,
+                Err(e) => {
+                    tracing::warn!("Not migrating session: {}", e);
+                    continue;
+                }
+            };
+            if version < SESSION_VERSION {
+                if !dry_run {
+                    self.save(&session)?;
+                }
+                migrated.push((id, version));
+            }
+        }
+        migrated.sort();
+        Ok(migrated)
+    }
+
+    /// Searches the task and every step of every session for text containing
+    /// all words of `query`, ignoring ASCII case. Returns at most `limit`
+    /// hits, newest session first.
+    pub fn search(&self, query: &str, limit: usize) -> Result<Vec<SearchHit>, SessionError> {
+        let terms: Vec<String> = query.split_whitespace().map(|t| t.to_ascii_lowercase()).collect();
+        if terms.is_empty() {
+            return Ok(Vec::new());
+        }
+
+        let mut hits = Vec::new();
+        for session in self.list()?.into_iter().rev() {
+            let steps = session.steps.iter().enumerate().map(|(i, step)| {
+                let text = format!(
+                    "{}\n{} {}\n{}",
+                    step.thought, step.action, step.action_input, step.observation
+                );
+                (Some(i + 1), text)
+            });
+            for (step, text) in std::iter::once((None, session.task.clone())).chain(steps) {
+                let lowered = text.to_ascii_lowercase();
+                if !terms.iter().all(|term| lowered.contains(term.as_str())) {
+                    continue;
+                }
+                hits.push(SearchHit {
+                    session_id: session.id.clone(),
+                    task: session.task.clone(),
+                    started_at: session.started_at,
+                    step,
+                    snippet: snippet(&text, &lowered, &terms[0]),
+                });
+                if hits.len() >= limit {
+                    return Ok(hits);
+                }
+            }
+        }
+        Ok(hits)
+    }
+
+    /// Finds a session by id, unique id prefix, or `latest`.
+    pub fn resolve(&self, spec: &str) -> Result<Session, SessionError> {
+        let sessions = self.list()?;
+        if spec == "latest" {
+            return sessions.into_iter().last().ok_or_else(|| SessionError::NotFound(spec.to_string()));
+        }
+
+        let mut matches = sessions.into_iter().filter(|session| session.id.starts_with(spec));
+        match (matches.next(), matches.next()) {
+            (Some(session), None) => Ok(session),
+            (Some(_), Some(_)) => Err(SessionError::Ambiguous(spec.to_string())),
+            (None, _) => Err(SessionError::NotFound(spec.to_string())),
+        }
+    }
+}
+
+/// Sends `prompt` alone, without tools, and returns the trimmed answer.
+async fn ask(client: &dyn LLMClient, prompt: String) -> Result<String, LLMError> {
+    let messages = [Message {
+        role: MessageRole::User,
+        content: prompt,
+        tool_calls: None,
+    }];
+
+    let mut stream = client.stream_complete(&messages, &[]).await?;
+    let mut answer = String::new();
+    while let Some(chunk) = stream.next().await {
+        let chunk = chunk?;
+        match chunk.chunk_type {
+            ChunkType::Content => answer.push_str(&chunk.content),
+            ChunkType::Done => break,
+            ChunkType::Error => return Err(LLMError::ApiError(chunk.content)),
+            _ => {}
+        }
+    }
+    Ok(answer.trim().to_string())
+}
+
+/// Asks the model for a structured handoff of `session`: what was done, the
+/// state of the code, and what remains.
+pub async fn handoff_summary(client: &dyn LLMClient, session: &Session) -> Result<String, LLMError> {
+    ask(client, build_handoff_prompt(&session.task, &session.transcript())).await
+}
+
+/// Asks the model for a title and a one-paragraph summary of `session`,
+/// for [`Session::title`] and [`Session::summary`].
+pub async fn title_and_summary(client: &dyn LLMClient, session: &Session) -> Result<(String, String), LLMError> {
+    let transcript = session.transcript();
+    let start = transcript.floor_char_boundary(transcript.len().saturating_sub(TITLE_TRANSCRIPT_CHARS));
+    let answer = ask(client, build_session_title_prompt(&session.task, &transcript[start..])).await?;
+
+    let title = answer
+        .lines()
+        .find_map(|line| line.trim().strip_prefix("Title:"))
+        .map(|title| title.trim().trim_end_matches('.').to_string())
+        .filter(|title| !title.is_empty())
+        .ok_or_else(|| LLMError::ParseError(format!("No title in: {}", answer)))?;
+    let summary = answer
+        .split_once("Summary:")
+        .map(|(_, summary)| summary.split_whitespace().collect::<Vec<_>>().join(" "))
+        .unwrap_or_default();
+    Ok((title, summary))
+}
+
+/// The task for a run that picks up where `session` left off.
+pub fn build_continue_task(session: &Session, summary: &str) -> String {
+    format!(
+        "Continue a previous session on this task:\n{}\n\nHandoff notes from that session:\n{}\n\nPick up from the remaining work. Check the current state of the files before relying on these notes.",
+        session.task.trim(),
+        summary
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::clients::ScriptedClient;
+
+    fn session(id: &str, started_at: u64) -> Session {
+        let mut session = Session::new("Fix the parser", PathBuf::from("."), "gpt-4o");
+        session.id = id.to_string();
+        session.started_at = started_at;
+        session
+    }
+
+    #[test]
+    fn test_store_round_trip_and_resolve() {
+        let dir = tempfile::tempdir().unwrap();
+        let store = SessionStore::new(dir.path().join("sessions"));
+        assert!(store.list().unwrap().is_empty());
+
+        let mut first = session("abc1", 1);
+        first.push_step(Step::new(
+            "Look".to_string(),
+            "read_file".to_string(),
+            serde_json::json!({"path": "a.rs"}),
+            "fn main() {}".to_string(),
+            String::new(),
+        ));
+        first.finish(Some("Max steps exceeded".to_string()));
+        store.save(&first).unwrap();
+        store.save(&session("abd2", 2)).unwrap();
+
+        assert_eq!(store.load("abc1").unwrap(), first);
+        assert_eq!(store.resolve("latest").unwrap().id, "abd2");
+        assert_eq!(store.resolve("abc").unwrap().status, SessionStatus::Aborted);
+        assert!(matches!(store.resolve("ab"), Err(SessionError::Ambiguous(_))));
+        assert!(matches!(store.resolve("zz"), Err(SessionError::NotFound(_))));
+        assert!(matches!(store.import(&first), Err(SessionError::Exists(_))));
+        store.import(&session("abe3", 3)).unwrap();
+        assert_eq!(store.resolve("latest").unwrap().id, "abe3");
+    }
+
+    #[test]
+    fn test_migrate_sessions() {
+        let dir = tempfile::tempdir().unwrap();
+        let store = SessionStore::new(dir.path().to_path_buf());
+        let old = session("abc1", 1);
+        std::fs::write(dir.path().join("abc1.json"), serde_json::to_string(&old).unwrap()).unwrap();
+        store.save(&session("abd2", 2)).unwrap();
+        let newer = serde_json::json!({"version": SESSION_VERSION + 1, "id": "abe3"});
+        std::fs::write(dir.path().join("abe3.json"), newer.to_string()).unwrap();
+
+        assert_eq!(store.load("abc1").unwrap(), old);
+        assert!(matches!(store.load("abe3"), Err(SessionError::Newer(_, version)) if version == SESSION_VERSION + 1));
+        assert_eq!(store.migrate(true).unwrap(), [("abc1".to_string(), 0)]);
+        assert_eq!(store.migrate(false).unwrap(), [("abc1".to_string(), 0)]);
+        assert!(store.migrate(false).unwrap().is_empty());
+        let saved: serde_json::Value =
+            serde_json::from_str(&std::fs::read_to_string(dir.path().join("abc1.json")).unwrap()).unwrap();
+        assert_eq!(saved["version"], SESSION_VERSION);
+        assert_eq!(store.list().unwrap().len(), 2);
+    }
+
+    #[test]
+    fn test_search() {
+        let dir = tempfile::tempdir().unwrap();
+        let store = SessionStore::new(dir.path().to_path_buf());
+        let mut old = session("a1", 1);
+        old.push_step(Step::new(
+            "The panic comes from an unwrap".to_string(),
+            "write_file".to_string(),
+            serde_json::json!({"path": "src/lib.rs"}),
+            format!("{} Replaced unwrap with a PARSE error {}", "x".repeat(200), "y".repeat(200)),
+            String::new(),
+        ));
+        store.save(&old).unwrap();
+        store.save(&Session::new("Parse error on empty input", PathBuf::from("."), "gpt-4o")).unwrap();
+
+        let hits = store.search("parse ERROR", 10).unwrap();
+
+        assert_eq!(hits.len(), 2);
+        assert_eq!(hits[0].step, None);
+        assert_eq!(hits[1].session_id, "a1");
+        assert_eq!(hits[1].step, Some(1));
+        assert!(hits[1].snippet.starts_with("..."));
+        assert!(hits[1].snippet.contains("PARSE error"));
+        assert_eq!(store.search("parse error", 1).unwrap().len(), 1);
+        assert!(store.search("  ", 10).unwrap().is_empty());
+    }
+
+    #[tokio::test]
+    async fn test_handoff_summary() {
+        let client = ScriptedClient::from_responses(["## Remaining work\n- add tests\n"]);
+        let mut session = session("abc1", 1);
+        let mut step = Step::new(
+            "Edit".to_string(),
+            "write_file".to_string(),
+            serde_json::json!({"path": "a.rs"}),
+            "x".repeat(2000),
+            String::new(),
+        );
+        step.diff = Some("--- a/a.rs\n+++ b/a.rs\n@@ -1 +1 @@\n-a\n+b\n".to_string());
+        session.push_step(step);
+
+        let summary = handoff_summary(&client, &session).await.unwrap();
+
+        assert_eq!(summary, "## Remaining work\n- add tests");
+        let prompt = &client.requests()[0][0].content;
+        assert!(prompt.contains("Fix the parser"));
+        assert!(prompt.contains("write_file"));
+        assert!(prompt.contains("[...]"));
+        assert!(prompt.contains("Changes:\n--- a/a.rs\n"));
+        assert!(build_continue_task(&session, &summary).contains("- add tests"));
+    }
+
+    #[tokio::test]
+    async fn test_title_and_summary() {
+        let mut session = session("abc1", 1);
+        session.task = format!("Fix the parser so that {}", "it handles empty input ".repeat(5));
+        assert_eq!(session.label().chars().count(), LABEL_TASK_CHARS + 3);
+        assert!(session.label().ends_with("..."));
+
+        let client = ScriptedClient::from_responses([
+            "Title: Handle empty parser input.\nSummary: The parser panicked on empty input.\nIt now returns an error.",
+        ]);
+        let (title, summary) = title_and_summary(&client, &session).await.unwrap();
+        assert_eq!(title, "Handle empty parser input");
+        assert_eq!(summary, "The parser panicked on empty input. It now returns an error.");
+        assert!(client.requests()[0][0].content.contains("Fix the parser"));
+
+        session.title = Some(title);
+        assert_eq!(session.label(), "Handle empty parser input");
+
+        let client = ScriptedClient::from_responses(["I can't."]);
+        assert!(matches!(title_and_summary(&client, &session).await, Err(LLMError::ParseError(_))));
+    }
+}
@@ -0,0 +1,202 @@
+use super::Session;
+use crate::clients::Pricing;
+use serde::Serialize;
+use std::collections::BTreeMap;
+
+/// Usage summed over the sessions of one day, model and project.
+#[derive(Debug, Clone, Default, PartialEq, Serialize)]
+pub struct UsageRow {
+    /// The day the sessions started, `YYYY-MM-DD` in UTC.
+    pub day: String,
+    pub model: String,
+    pub project: String,
+    pub sessions: usize,
+    pub steps: usize,
+    pub input_tokens: u64,
+    /// Includes reasoning tokens.
+    pub output_tokens: u64,
+    /// `None` if the model has no price.
+    pub cost: Option<f64>,
+}
+
+/// Token and cost totals across sessions, for tracking spend.
+#[derive(Debug, Clone, Default, PartialEq, Serialize)]
+pub struct UsageReport {
+    /// By day, then model, then project.
+    pub rows: Vec<UsageRow>,
+    pub input_tokens: u64,
+    pub output_tokens: u64,
+    /// The cost of the rows with a price, or `None` if no row has one.
+    pub cost: Option<f64>,
+}
+
+impl UsageReport {
+    /// Sums the sessions started at or after `since`, in Unix seconds.
+    /// Each session is given with the name of its project.
+    pub fn new<'a>(
+        sessions: impl IntoIterator<Item = (&'a str, &'a Session)>,
+        since: u64,
+        pricing: &BTreeMap<String, Pricing>,
+    ) -> Self {
+        let mut rows: BTreeMap<(String, String, String), UsageRow> = BTreeMap::new();
+        for (project, session) in sessions {
+            if session.started_at < since {
+                continue;
+            }
+            let day = utc_day(session.started_at);
+            let key = (day.clone(), session.model.clone(), project.to_string());
+            let row = rows.entry(key).or_insert_with(|| UsageRow {
+                day,
+                model: session.model.clone(),
+                project: project.to_string(),
+                ..UsageRow::default()
+            });
+            row.sessions += 1;
+            row.steps += session.steps.len();
+            for step in &session.steps {
+                row.input_tokens += step.prompt_tokens.unwrap_or(0);
+                row.output_tokens += step.completion_tokens.unwrap_or(0);
+            }
+        }
+
+        let mut report = UsageReport::default();
+        for mut row in rows.into_values() {
+            row.cost = pricing
+                .get(&row.model)
+                .map(|price| price.cost(row.input_tokens as usize, row.output_tokens as usize));
+            report.input_tokens += row.input_tokens;
+            report.output_tokens += row.output_tokens;
+            if let Some(cost) = row.cost {
+                *report.cost.get_or_insert(0.0) += cost;
+            }
+            report.rows.push(row);
+        }
+        report
+    }
+
+    pub fn render_table(&self) -> String {
+        let mut out = format!(
+            "{:<10} {:<24} {:<20} {:>8} {:>6} {:>12} {:>12} {:>10}\n",
+            "day", "model", "project", "sessions", "steps", "in tok", "out tok", "cost"
+        );
+        for row in &self.rows {
+            out.push_str(&format!(
+                "{:<10} {:<24} {:<20} {:>8} {:>6} {:>12} {:>12} {:>10}\n",
+                row.day,
+                row.model,
+                row.project,
+                row.sessions,
+                row.steps,
+                row.input_tokens,
+                row.output_tokens,
+                format_cost(row.cost),
+            ));
+        }
+        out.push_str(&format!(
+            "\nTotal: {} input tokens, {} output tokens, {}\n",
+            self.input_tokens,
+            self.output_tokens,
+            match self.cost {
+                Some(_) if self.rows.iter().any(|row| row.cost.is_none()) => {
+                    format!("{} for the models with a price", format_cost(self.cost))
+                }
+                _ => format_cost(self.cost),
+            }
+        ));
+        out
+    }
+}
+
+fn format_cost(cost: Option<f64>) -> String {
+    cost.map(|cost| format!("${:.4}", cost)).unwrap_or_else(|| "-".to_string())
+}
+
+/// Parses an age such as `7d`, `12h`, `2w` or `30m` into seconds.
+pub fn parse_age(text: &str) -> Option<u64> {
+    let text = text.trim();
+    let unit = match text.chars().last()? {
+        'm' => 60,
+        'h' => 60 * 60,
+        'd' => 24 * 60 * 60,
+        'w' => 7 * 24 * 60 * 60,
+        _ => return None,
+    };
+    let count: u64 = text[..text.len() - 1].parse().ok()?;
+    count.checked_mul(unit)
+}
+
+/// The UTC date of a Unix time, as `YYYY-MM-DD`.
+fn utc_day(secs: u64) -> String {
+    // Days since the epoch to a civil date, after Howard Hinnant's
+    // `civil_from_days`, shifted so years start in March.
+    let days = (secs / 86_400) as i64 + 719_468;
+    let era = days.div_euclid(146_097);
+    let day_of_era = days.rem_euclid(146_097);
+    let year_of_era = (day_of_era - day_of_era / 1460 + day_of_era / 36_524 - day_of_era / 146_096) / 365;
+    let day_of_year = day_of_era - (365 * year_of_era + year_of_era / 4 - year_of_era / 100);
+    let month_index = (5 * day_of_year + 2) / 153;
+    let day = day_of_year - (153 * month_index + 2) / 5 + 1;
+    let month = if month_index < 10 { month_index + 3 } else { month_index - 9 };
+    let year = year_of_era + era * 400 + i64::from(month <= 2);
+    format!("{:04}-{:02}-{:02}", year, month, day)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::core::Step;
+    use std::path::PathBuf;
+
+    fn session(model: &str, started_at: u64, tokens: &[(u64, u64)]) -> Session {
+        let mut session = Session::new("task", PathBuf::from("."), model);
+        session.started_at = started_at;
+        for (input, output) in tokens {
+            let mut step = Step::new(String::new(), String::new(), serde_json::json!({}), String::new(), String::new());
+            step.prompt_tokens = Some(*input);
+            step.completion_tokens = Some(*output);
+            session.push_step(step);
+        }
+        session
+    }
+
+    #[test]
+    fn test_usage_report() {
+        // 2024-03-01 00:00 and 23:00 UTC, then 2024-03-02.
+        let day = 1_709_251_200;
+        let sessions = [
+            ("app", session("gpt-4o", day, &[(1_000_000, 100_000)])),
+            ("app", session("gpt-4o", day + 23 * 3600, &[(500_000, 0), (500_000, 100_000)])),
+            ("lib", session("local", day + 86_400, &[(10, 1)])),
+            ("app", session("gpt-4o", day - 1, &[(1, 1)])),
+        ];
+        let pricing = BTreeMap::from([(
+            "gpt-4o".to_string(),
+            Pricing {
+                input_per_mtok: 2.5,
+                output_per_mtok: 10.0,
+            },
+        )]);
+
+        let report = UsageReport::new(sessions.iter().map(|(project, session)| (*project, session)), day, &pricing);
+
+        assert_eq!(report.rows.len(), 2);
+        assert_eq!(report.rows[0].day, "2024-03-01");
+        assert_eq!(report.rows[0].sessions, 2);
+        assert_eq!(report.rows[0].steps, 3);
+        assert_eq!(report.rows[0].input_tokens, 2_000_000);
+        assert_eq!(report.rows[0].cost, Some(7.0));
+        assert_eq!(report.rows[1].day, "2024-03-02");
+        assert_eq!(report.rows[1].project, "lib");
+        assert_eq!(report.rows[1].cost, None);
+        assert_eq!(report.output_tokens, 200_001);
+        assert_eq!(report.cost, Some(7.0));
+        assert!(report.render_table().contains("$7.0000 for the models with a price"));
+
+        assert_eq!(utc_day(0), "1970-01-01");
+        assert_eq!(utc_day(951_782_400), "2000-02-29");
+        assert_eq!(parse_age("7d"), Some(7 * 86_400));
+        assert_eq!(parse_age("12h"), Some(12 * 3600));
+        assert_eq!(parse_age("7"), None);
+        assert_eq!(parse_age("d"), None);
+    }
+}
@@ -0,0 +1,142 @@
+use serde::{Deserialize, Serialize};
+use std::path::{Path, PathBuf};
+use std::time::{Duration, SystemTime};
+
+pub use crate::paths::SCRATCH_DIR;
+
+/// When a run's scratch directory is kept after it ends.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum KeepScratch {
+    Never,
+    /// Kept if the run failed, to see what it left behind.
+    OnFailure,
+    Always,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct ScratchPolicy {
+    pub keep: KeepScratch,
+    /// Kept scratch directories older than this are removed when the next
+    /// run starts.
+    pub max_age_days: u64,
+}
+
+impl Default for ScratchPolicy {
+    fn default() -> Self {
+        Self {
+            keep: KeepScratch::OnFailure,
+            max_age_days: 7,
+        }
+    }
+}
+
+/// A directory for one session's temporary files, such as downloads and
+/// generated scripts, so they don't end up in the repository.
+#[derive(Debug)]
+pub struct ScratchDir {
+    path: PathBuf,
+    relative: String,
+    policy: ScratchPolicy,
+}
+
+impl ScratchDir {
+    /// Creates the scratch directory of session `id` under `workdir`, and
+    /// removes ones older than the policy allows.
+    pub fn create(workdir: &Path, id: &str, policy: ScratchPolicy) -> std::io::Result<Self> {
+        let root = workdir.join(SCRATCH_DIR);
+        remove_stale(&root, Duration::from_secs(policy.max_age_days * 24 * 60 * 60));
+        let path = root.join(id);
+        std::fs::create_dir_all(&path)?;
+        Ok(Self {
+            path,
+            relative: format!("{}/{}", SCRATCH_DIR, id),
+            policy,
+        })
+    }
+
+    pub fn path(&self) -> &Path {
+        &self.path
+    }
+
+    /// The path relative to the working directory, as the model sees it.
+    pub fn relative(&self) -> &str {
+        &self.relative
+    }
+
+    /// Removes the directory unless the policy keeps it after a run that
+    /// `succeeded` or not. Returns whether it was kept.
+    pub fn finish(self, succeeded: bool) -> bool {
+        let keep = match self.policy.keep {
+            KeepScratch::Never => false,
+            KeepScratch::OnFailure => !succeeded,
+            KeepScratch::Always => true,
+        };
+        let empty = std::fs::read_dir(&self.path).map(|mut entries| entries.next().is_none()).unwrap_or(true);
+        if keep && !empty {
+            return true;
+        }
+        if let Err(e) = std::fs::remove_dir_all(&self.path)
+            && e.kind() != std::io::ErrorKind::NotFound
+        {
+            tracing::debug!("Could not remove scratch directory {}: {}", self.path.display(), e);
+        }
+        // Only removed if no other session is using it.
+        if let Some(root) = self.path.parent() {
+            let _ = std::fs::remove_dir(root);
+        }
+        false
+    }
+}
+
+fn remove_stale(root: &Path, max_age: Duration) {
+    let Ok(entries) = std::fs::read_dir(root) else {
+        return;
+    };
+    let now = SystemTime::now();
+    for entry in entries.flatten() {
+        let stale = entry
+            .metadata()
+            .and_then(|metadata| metadata.modified())
+            .ok()
+            .and_then(|modified| now.duration_since(modified).ok())
+            .is_some_and(|age| age > max_age);
+        if stale && let Err(e) = std::fs::remove_dir_all(entry.path()) {
+            tracing::debug!("Could not remove scratch directory {}: {}", entry.path().display(), e);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_scratch_policy() {
+        let dir = tempfile::tempdir().unwrap();
+        let on_failure = ScratchPolicy::default();
+
+        let scratch = ScratchDir::create(dir.path(), "a1", on_failure.clone()).unwrap();
+        assert_eq!(scratch.relative(), ".synthia/tmp/a1");
+        std::fs::write(scratch.path().join("script.py"), "print(1)").unwrap();
+        assert!(scratch.finish(false));
+        assert!(dir.path().join(".synthia/tmp/a1/script.py").exists());
+
+        let scratch = ScratchDir::create(dir.path(), "b2", on_failure).unwrap();
+        std::fs::write(scratch.path().join("out.json"), "{}").unwrap();
+        assert!(!scratch.finish(true));
+        assert!(!dir.path().join(".synthia/tmp/b2").exists());
+
+        // The failed run's directory is older than a max age of zero.
+        std::thread::sleep(Duration::from_millis(20));
+        let policy = ScratchPolicy {
+            keep: KeepScratch::Never,
+            max_age_days: 0,
+        };
+        let scratch = ScratchDir::create(dir.path(), "c3", policy).unwrap();
+        assert!(!dir.path().join(".synthia/tmp/a1").exists());
+        assert!(!scratch.finish(false));
+        assert!(!dir.path().join(SCRATCH_DIR).exists());
+    }
+}
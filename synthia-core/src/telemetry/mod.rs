@@ -0,0 +1,141 @@
+use crate::clients::Usage;
+use crate::core::{StepStatus, StopReason};
+use serde::{Deserialize, Serialize};
+use std::fs::{File, OpenOptions};
+use std::io::Write;
+use std::path::Path;
+use std::sync::Mutex;
+use std::time::{SystemTime, UNIX_EPOCH};
+
+/// Something that happened during a run. Events carry counts, names and
+/// timings but no prompt, response or tool output, so they can leave the
+/// machine without leaking the code being worked on.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(tag = "event", rename_all = "snake_case")]
+pub enum TelemetryEvent {
+    RunStarted {
+        model: String,
+        max_steps: usize,
+        tools: usize,
+    },
+    /// `stop_reason` is `None` when the run ended with an error.
+    RunFinished {
+        steps: usize,
+        stop_reason: Option<StopReason>,
+        error: Option<String>,
+        duration_ms: u64,
+    },
+    /// One completed step; `tool` is empty when no tool was called.
+    Step {
+        index: usize,
+        tool: String,
+        status: StepStatus,
+        duration_ms: u64,
+    },
+    /// One tool call, timed from the call to its result.
+    ToolCall {
+        name: String,
+        status: StepStatus,
+        duration_ms: u64,
+    },
+    /// What the provider reported for one request.
+    Usage {
+        #[serde(flatten)]
+        usage: Usage,
+    },
+}
+
+/// Receives the events of every run of an agent, for embedders that send
+/// run analytics to their own systems. Called on the agent's task, so slow
+/// work belongs on a channel.
+pub trait TelemetrySink: Send + Sync {
+    fn record(&self, event: &TelemetryEvent);
+}
+
+/// Drops every event.
+#[derive(Debug, Clone, Copy, Default)]
+pub struct NoopSink;
+
+impl TelemetrySink for NoopSink {
+    fn record(&self, _event: &TelemetryEvent) {}
+}
+
+/// Logs every event at debug level under the `telemetry` target.
+#[derive(Debug, Clone, Copy, Default)]
+pub struct TracingSink;
+
+impl TelemetrySink for TracingSink {
+    fn record(&self, event: &TelemetryEvent) {
+        tracing::debug!(target: "telemetry", "{}", serde_json::to_string(event).unwrap_or_default());
+    }
+}
+
+/// Appends every event to a file as one JSON object per line, with the
+/// Unix milliseconds it was recorded at in `at`.
+pub struct JsonlSink {
+    file: Mutex<File>,
+}
+
+#[derive(Serialize)]
+struct Line<'a> {
+    at: u64,
+    #[serde(flatten)]
+    event: &'a TelemetryEvent,
+}
+
+impl JsonlSink {
+    /// Appends to `path`, creating it if needed.
+    pub fn open(path: &Path) -> std::io::Result<Self> {
+        let file = OpenOptions::new().create(true).append(true).open(path)?;
+        Ok(Self { file: Mutex::new(file) })
+    }
+}
+
+impl TelemetrySink for JsonlSink {
+    fn record(&self, event: &TelemetryEvent) {
+        let at = SystemTime::now().duration_since(UNIX_EPOCH).unwrap_or_default().as_millis() as u64;
+        let Ok(line) = serde_json::to_string(&Line { at, event }) else {
+            return;
+        };
+        let mut file = self.file.lock().unwrap_or_else(|e| e.into_inner());
+        if let Err(e) = writeln!(file, "{}", line) {
+            tracing::debug!("Could not write telemetry: {}", e);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_jsonl_sink() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("telemetry.jsonl");
+        let sink = JsonlSink::open(&path).unwrap();
+        sink.record(&TelemetryEvent::ToolCall {
+            name: "read_file".to_string(),
+            status: StepStatus::Success,
+            duration_ms: 3,
+        });
+        sink.record(&TelemetryEvent::Usage {
+            usage: Usage {
+                input_tokens: 100,
+                output_tokens: 10,
+                reasoning_tokens: 0,
+            },
+        });
+
+        let lines: Vec<serde_json::Value> = std::fs::read_to_string(&path)
+            .unwrap()
+            .lines()
+            .map(|line| serde_json::from_str(line).unwrap())
+            .collect();
+        assert_eq!(lines.len(), 2);
+        assert_eq!(lines[0]["event"], "tool_call");
+        assert_eq!(lines[0]["status"], "success");
+        assert!(lines[0]["at"].as_u64().unwrap() > 0);
+        assert_eq!(lines[1]["event"], "usage");
+        assert_eq!(lines[1]["input_tokens"], 100);
+    }
+}
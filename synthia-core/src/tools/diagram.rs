@@ -0,0 +1,217 @@
+//! `render_diagram`: turns Mermaid or Graphviz source into an SVG or PNG
+//! in the workspace, so a drawing task leaves a picture behind rather
+//! than a fenced block. Rendering runs Graphviz's `dot` or mermaid-cli's
+//! `mmdc`, whichever the source needs.
+
+use super::{ToolAnnotations, ToolError, ToolInfo, ToolOutput, ToolTrait};
+use futures::Future;
+use serde_json::Value;
+use std::path::PathBuf;
+use std::pin::Pin;
+use std::process::Stdio;
+use tokio::io::AsyncWriteExt;
+
+/// The diagram languages `render_diagram` takes.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum DiagramLanguage {
+    Mermaid,
+    Graphviz,
+}
+
+impl DiagramLanguage {
+    fn parse(name: &str) -> Option<Self> {
+        match name.to_ascii_lowercase().as_str() {
+            "mermaid" => Some(DiagramLanguage::Mermaid),
+            "graphviz" | "dot" => Some(DiagramLanguage::Graphviz),
+            _ => None,
+        }
+    }
+
+    /// How to install the renderer, for when it's missing.
+    fn install_hint(self) -> &'static str {
+        match self {
+            DiagramLanguage::Mermaid => "install it with `npm install -g @mermaid-js/mermaid-cli`",
+            DiagramLanguage::Graphviz => "install the graphviz package",
+        }
+    }
+}
+
+pub struct RenderDiagramTool {
+    base_path: PathBuf,
+    mermaid: String,
+    graphviz: String,
+}
+
+impl RenderDiagramTool {
+    pub fn new(base_path: PathBuf) -> Self {
+        Self {
+            base_path,
+            mermaid: "mmdc".to_string(),
+            graphviz: "dot".to_string(),
+        }
+    }
+
+    /// Runs `program` to render `language` instead of `mmdc` or `dot`. It
+    /// is called the same way and must take the same arguments.
+    pub fn with_renderer(mut self, language: DiagramLanguage, program: impl Into<String>) -> Self {
+        match language {
+            DiagramLanguage::Mermaid => self.mermaid = program.into(),
+            DiagramLanguage::Graphviz => self.graphviz = program.into(),
+        }
+        self
+    }
+}
+
+impl ToolTrait for RenderDiagramTool {
+    fn info(&self) -> ToolInfo {
+        ToolInfo {
+            name: "render_diagram".to_string(),
+            description: "Render Mermaid or Graphviz source to an SVG or PNG file".to_string(),
+            parameters: serde_json::json!({
+                "type": "object",
+                "properties": {
+                    "source": {
+                        "type": "string",
+                        "description": "The diagram source"
+                    },
+                    "language": {
+                        "type": "string",
+                        "enum": ["mermaid", "graphviz"],
+                        "description": "The language the source is written in"
+                    },
+                    "path": {
+                        "type": "string",
+                        "description": "File to write, ending in .svg or .png"
+                    }
+                },
+                "required": ["source", "language", "path"]
+            }),
+            annotations: ToolAnnotations {
+                read_only: false,
+                destructive: true,
+                idempotent: true,
+                open_world: false,
+            },
+        }
+    }
+
+    fn execute(&self, arguments: Value) -> Pin<Box<dyn Future<Output = Result<ToolOutput, ToolError>> + Send + Sync>> {
+        let base_path = self.base_path.clone();
+        let programs = (self.mermaid.clone(), self.graphviz.clone());
+        Box::pin(async move {
+            let source = arguments
+                .get("source")
+                .and_then(|v| v.as_str())
+                .ok_or_else(|| ToolError::InvalidArguments("Missing 'source' argument".to_string()))?;
+            let language = arguments
+                .get("language")
+                .and_then(|v| v.as_str())
+                .ok_or_else(|| ToolError::InvalidArguments("Missing 'language' argument".to_string()))?;
+            let language = DiagramLanguage::parse(language).ok_or_else(|| {
+                ToolError::InvalidArguments(format!("Unknown language '{}', expected mermaid or graphviz", language))
+            })?;
+            let path = arguments
+                .get("path")
+                .and_then(|v| v.as_str())
+                .ok_or_else(|| ToolError::InvalidArguments("Missing 'path' argument".to_string()))?;
+            let format = match path.rsplit_once('.').map(|(_, extension)| extension.to_ascii_lowercase()) {
+                Some(extension) if extension == "svg" || extension == "png" => extension,
+                _ => return Err(ToolError::InvalidArguments(format!("'{}' must end in .svg or .png", path))),
+            };
+
+            let full_path = base_path.join(path);
+            let created = !full_path.exists();
+            if let Some(parent) = full_path.parent()
+                && !parent.exists()
+            {
+                tokio::fs::create_dir_all(parent)
+                    .await
+                    .map_err(|e| ToolError::IoError(e.to_string()))?;
+            }
+
+            // Both read the source from stdin and take the output file last.
+            let output = full_path.to_string_lossy().into_owned();
+            let (program, args) = match language {
+                DiagramLanguage::Mermaid => (programs.0, vec!["-q".to_string(), "-i".to_string(), "-".to_string()]),
+                DiagramLanguage::Graphviz => (programs.1, vec![format!("-T{}", format)]),
+            };
+            let child = tokio::process::Command::new(&program)
+                .args(&args)
+                .arg("-o")
+                .arg(&output)
+                .current_dir(&base_path)
+                .stdin(Stdio::piped())
+                .stdout(Stdio::null())
+                .stderr(Stdio::piped())
+                .kill_on_drop(true)
+                .spawn();
+            let mut child = match child {
+                Ok(child) => child,
+                Err(e) if e.kind() == std::io::ErrorKind::NotFound => {
+                    let message = format!(
+                        "`{}` is not installed, so the diagram was not rendered; {}, or keep the source as text.",
+                        program,
+                        language.install_hint()
+                    );
+                    return Ok(ToolOutput::failure("renderer_missing", message, false));
+                }
+                Err(e) => return Err(ToolError::IoError(format!("{}: {}", program, e))),
+            };
+            if let Some(mut stdin) = child.stdin.take() {
+                stdin.write_all(source.as_bytes()).await?;
+            }
+            let result = child.wait_with_output().await?;
+            if !result.status.success() {
+                let message = format!(
+                    "{} could not render the diagram: {}",
+                    program,
+                    String::from_utf8_lossy(&result.stderr).trim()
+                );
+                return Ok(ToolOutput::failure("render_failed", message, false).with_data(serde_json::json!({"path": path})));
+            }
+
+            let bytes = tokio::fs::metadata(&full_path).await.map(|meta| meta.len()).unwrap_or(0);
+            Ok(ToolOutput::success(serde_json::json!({
+                "path": path,
+                "created": created,
+                "bytes": bytes,
+            })))
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[tokio::test]
+    async fn test_render_diagram() {
+        let dir = tempfile::tempdir().unwrap();
+        // Stands in for `dot`: copies the source to the file named last.
+        let fake = dir.path().join("fake-dot");
+        std::fs::write(&fake, "#!/bin/sh\nfor out; do :; done\necho \"$1\" > \"$out\"\ncat >> \"$out\"\n").unwrap();
+        std::process::Command::new("chmod").arg("+x").arg(&fake).status().unwrap();
+
+        let tool = RenderDiagramTool::new(dir.path().to_path_buf())
+            .with_renderer(DiagramLanguage::Graphviz, fake.to_string_lossy());
+        let rendered = tool
+            .execute(serde_json::json!({"source": "digraph { a -> b }", "language": "graphviz", "path": "docs/arch.svg"}))
+            .await
+            .unwrap();
+        assert_eq!(rendered.data["created"], true);
+        let written = std::fs::read_to_string(dir.path().join("docs/arch.svg")).unwrap();
+        assert_eq!(written, "-Tsvg\ndigraph { a -> b }");
+
+        let missing = RenderDiagramTool::new(dir.path().to_path_buf())
+            .with_renderer(DiagramLanguage::Mermaid, "/nonexistent/mmdc")
+            .execute(serde_json::json!({"source": "graph TD; A-->B", "language": "mermaid", "path": "a.png"}))
+            .await
+            .unwrap();
+        assert_eq!(missing.error.as_ref().unwrap().code, "renderer_missing");
+
+        let wrong = tool
+            .execute(serde_json::json!({"source": "graph TD; A-->B", "language": "mermaid", "path": "a.pdf"}))
+            .await;
+        assert!(matches!(wrong, Err(ToolError::InvalidArguments(_))));
+    }
+}
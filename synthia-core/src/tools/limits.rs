@@ -0,0 +1,94 @@
+use serde::{Deserialize, Serialize};
+use tokio::io::{AsyncRead, AsyncReadExt};
+
+/// How much of each output stream a command may return unless configured.
+pub const DEFAULT_MAX_OUTPUT_BYTES: usize = 1024 * 1024;
+
+/// Limits on the commands the agent runs, so a runaway build can't take
+/// down the host. CPU and memory are set with `ulimit` on Unix and are not
+/// enforced elsewhere. Limits that are not set are not enforced.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct ResourceLimits {
+    /// CPU time per process.
+    pub cpu_seconds: Option<u64>,
+    /// Virtual memory per process.
+    pub memory_mb: Option<u64>,
+    /// Bytes kept of stdout and of stderr each; the rest is discarded.
+    pub max_output_bytes: Option<usize>,
+}
+
+impl Default for ResourceLimits {
+    fn default() -> Self {
+        Self {
+            cpu_seconds: None,
+            memory_mb: None,
+            max_output_bytes: Some(DEFAULT_MAX_OUTPUT_BYTES),
+        }
+    }
+}
+
+impl ResourceLimits {
+    /// `command` prefixed with the `ulimit` calls that apply the limits to
+    /// it and everything it starts.
+    pub(crate) fn wrap(&self, command: &str) -> String {
+        if !cfg!(unix) {
+            return command.to_string();
+        }
+        let mut script = String::new();
+        if let Some(seconds) = self.cpu_seconds {
+            script.push_str(&format!("ulimit -t {} || exit 125\n", seconds));
+        }
+        if let Some(mb) = self.memory_mb {
+            script.push_str(&format!("ulimit -v {} || exit 125\n", mb * 1024));
+        }
+        script.push_str(command);
+        script
+    }
+
+    /// A note for the model when `status` looks like a limit killed the
+    /// command.
+    #[cfg(unix)]
+    pub(crate) fn violation(&self, status: &std::process::ExitStatus) -> Option<String> {
+        use std::os::unix::process::ExitStatusExt;
+        const SIGKILL: i32 = 9;
+        const SIGXCPU: i32 = 24;
+        match (status.signal(), self.cpu_seconds) {
+            (Some(SIGXCPU), Some(seconds)) => Some(format!(
+                "The command was stopped after using its {}s of CPU time.",
+                seconds
+            )),
+            (Some(SIGKILL), _) if self.cpu_seconds.is_some() || self.memory_mb.is_some() => {
+                Some("The command was killed, possibly for exceeding its CPU or memory limit.".to_string())
+            }
+            _ => None,
+        }
+    }
+
+    #[cfg(not(unix))]
+    pub(crate) fn violation(&self, _status: &std::process::ExitStatus) -> Option<String> {
+        None
+    }
+}
+
+/// Reads all of `reader`, keeping at most `limit` bytes. Returns the kept
+/// text and the number of bytes dropped.
+pub(crate) async fn read_capped<R: AsyncRead + Unpin + Send>(
+    mut reader: R,
+    limit: Option<usize>,
+) -> std::io::Result<(String, u64)> {
+    let limit = limit.unwrap_or(usize::MAX);
+    let mut kept = Vec::new();
+    let mut dropped = 0u64;
+    let mut buf = [0u8; 8192];
+    loop {
+        let n = reader.read(&mut buf).await?;
+        if n == 0 {
+            break;
+        }
+        let keep = n.min(limit.saturating_sub(kept.len()));
+        kept.extend_from_slice(&buf[..keep]);
+        dropped += (n - keep) as u64;
+    }
+    Ok((String::from_utf8_lossy(&kept).into_owned(), dropped))
+}
@@ -0,0 +1,115 @@
+use crate::clients::ToolDefinition;
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+
+/// Shrinks tool definitions before they are sent, trading some of what
+/// the model is told about each tool for prompt tokens. Whitespace runs in
+/// descriptions are always collapsed to single spaces.
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct MinifySchemas {
+    /// Descriptions longer than this many characters are cut at a word.
+    pub max_description_chars: Option<usize>,
+    /// Leaves out the descriptions of parameters, keeping their names,
+    /// types and constraints.
+    pub drop_parameter_descriptions: bool,
+}
+
+impl MinifySchemas {
+    pub fn apply(&self, definition: &ToolDefinition) -> ToolDefinition {
+        let mut parameters = definition.parameters.clone();
+        self.minify_schema(&mut parameters);
+        ToolDefinition {
+            name: definition.name.clone(),
+            description: self.shorten(&definition.description),
+            parameters,
+        }
+    }
+
+    fn shorten(&self, description: &str) -> String {
+        let collapsed = description.split_whitespace().collect::<Vec<_>>().join(" ");
+        let Some(max) = self.max_description_chars else {
+            return collapsed;
+        };
+        let Some((end, _)) = collapsed.char_indices().nth(max) else {
+            return collapsed;
+        };
+        // Back to the start of the word the cap falls in, unless it is the
+        // only one.
+        let end = match collapsed[..end].rfind(' ') {
+            Some(space) if space > 0 && !collapsed[end..].starts_with(' ') => space,
+            _ => end,
+        };
+        let cut = &collapsed[..end];
+        format!("{}...", cut.trim_end_matches([',', ';', ':', '.']))
+    }
+
+    /// Walks a JSON schema. Keys under `properties` are parameter names,
+    /// so a parameter called `description` is kept.
+    fn minify_schema(&self, schema: &mut Value) {
+        let Value::Object(object) = schema else {
+            return;
+        };
+        if self.drop_parameter_descriptions {
+            object.remove("description");
+        } else if let Some(Value::String(description)) = object.get_mut("description") {
+            *description = self.shorten(description);
+        }
+
+        for (key, value) in object.iter_mut() {
+            match (key.as_str(), value) {
+                ("properties" | "patternProperties" | "$defs" | "definitions", Value::Object(children)) => {
+                    for child in children.values_mut() {
+                        self.minify_schema(child);
+                    }
+                }
+                ("items" | "additionalProperties" | "not", child) => self.minify_schema(child),
+                ("anyOf" | "oneOf" | "allOf" | "prefixItems", Value::Array(children)) => {
+                    for child in children {
+                        self.minify_schema(child);
+                    }
+                }
+                _ => {}
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    #[test]
+    fn test_minify() {
+        let definition = ToolDefinition {
+            name: "create_issue".to_string(),
+            description: "Create an issue.\n\n    The issue is filed in the project's tracker, with labels.".to_string(),
+            parameters: json!({
+                "type": "object",
+                "properties": {
+                    "description": {"type": "string", "description": "The   body of the issue"},
+                    "labels": {"type": "array", "items": {"type": "string", "description": "A label"}}
+                },
+                "required": ["description"]
+            }),
+        };
+
+        let collapsed = MinifySchemas::default().apply(&definition);
+        assert_eq!(
+            collapsed.description,
+            "Create an issue. The issue is filed in the project's tracker, with labels."
+        );
+        assert_eq!(collapsed.parameters["properties"]["description"]["description"], "The body of the issue");
+
+        let cut = MinifySchemas {
+            max_description_chars: Some(20),
+            drop_parameter_descriptions: true,
+        }
+        .apply(&definition);
+        assert_eq!(cut.description, "Create an issue. The...");
+        assert_eq!(cut.parameters["properties"]["description"], json!({"type": "string"}));
+        assert_eq!(cut.parameters["properties"]["labels"]["items"], json!({"type": "string"}));
+        assert_eq!(cut.parameters["required"], json!(["description"]));
+    }
+}
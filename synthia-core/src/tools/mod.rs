@@ -0,0 +1,1694 @@
+use futures::Future;
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+use std::path::{Path, PathBuf};
+use std::pin::Pin;
+use std::sync::Arc;
+use thiserror::Error;
+
+pub(crate) mod limits;
+mod diagram;
+mod network;
+mod minify;
+mod output;
+mod permission;
+pub(crate) mod render;
+mod scope;
+mod select;
+mod validate;
+mod versions;
+pub(crate) mod walk;
+
+pub use diagram::{DiagramLanguage, RenderDiagramTool};
+pub use limits::{DEFAULT_MAX_OUTPUT_BYTES, ResourceLimits};
+pub use minify::MinifySchemas;
+pub use network::{NetworkMode, NetworkPolicy};
+pub use output::{TOOL_OUTPUT_VERSION, ToolFailure, ToolMeta, ToolOutput};
+pub use permission::{ApprovalRequest, Approver, Permission, PermissionPolicy, PermissionRule, describe_call};
+pub use scope::{ScopeRule, ToolScopes};
+pub use select::{LIST_ALL_TOOLS, ToolSelection, list_all_tools_definition, list_all_tools_result};
+pub use validate::validate_arguments;
+pub use versions::FileVersions;
+pub use walk::DEFAULT_MAX_FILES;
+
+#[derive(Debug, Error)]
+pub enum ToolError {
+    #[error("Execution failed: {0}")]
+    ExecutionFailed(String),
+    #[error("Invalid arguments: {0}")]
+    InvalidArguments(String),
+    #[error("IO error: {0}")]
+    IoError(String),
+    #[error("Not found: {0}")]
+    NotFound(String),
+    /// No tool of this name is registered.
+    #[error("Unknown tool: {0}")]
+    UnknownTool(String),
+}
+
+impl ToolError {
+    /// The same error with its message passed through `f`, e.g. to redact
+    /// it.
+    pub fn map_message(self, f: impl FnOnce(&str) -> String) -> Self {
+        match self {
+            ToolError::ExecutionFailed(message) => ToolError::ExecutionFailed(f(&message)),
+            ToolError::InvalidArguments(message) => ToolError::InvalidArguments(f(&message)),
+            ToolError::IoError(message) => ToolError::IoError(f(&message)),
+            ToolError::NotFound(message) => ToolError::NotFound(f(&message)),
+            ToolError::UnknownTool(name) => ToolError::UnknownTool(f(&name)),
+        }
+    }
+
+    /// The [`ToolFailure`] code for this error.
+    pub fn code(&self) -> &'static str {
+        match self {
+            ToolError::ExecutionFailed(_) => "execution_failed",
+            ToolError::InvalidArguments(_) => "invalid_arguments",
+            ToolError::IoError(_) => "io_error",
+            ToolError::NotFound(_) => "not_found",
+            ToolError::UnknownTool(_) => "unknown_tool",
+        }
+    }
+
+    /// IO errors may be passing, such as a file locked by another process.
+    pub fn is_retryable(&self) -> bool {
+        matches!(self, ToolError::IoError(_))
+    }
+}
+
+impl From<std::io::Error> for ToolError {
+    fn from(error: std::io::Error) -> Self {
+        ToolError::IoError(error.to_string())
+    }
+}
+
+/// What a tool may do, with MCP's tool annotations as names. Unannotated
+/// tools get MCP's defaults, which assume the worst: a tool may change
+/// anything, destructively, and reach outside the workspace.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct ToolAnnotations {
+    /// Changes nothing.
+    #[serde(rename = "readOnlyHint")]
+    pub read_only: bool,
+    /// May delete or overwrite, rather than only add.
+    #[serde(rename = "destructiveHint")]
+    pub destructive: bool,
+    /// Calling it again with the same arguments changes nothing more.
+    #[serde(rename = "idempotentHint")]
+    pub idempotent: bool,
+    /// Reaches things outside the workspace, such as the network.
+    #[serde(rename = "openWorldHint")]
+    pub open_world: bool,
+}
+
+impl Default for ToolAnnotations {
+    fn default() -> Self {
+        Self {
+            read_only: false,
+            destructive: true,
+            idempotent: false,
+            open_world: true,
+        }
+    }
+}
+
+impl ToolAnnotations {
+    /// A tool that only reads the workspace.
+    pub fn read_only() -> Self {
+        Self {
+            read_only: true,
+            destructive: false,
+            idempotent: true,
+            open_world: false,
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct ToolInfo {
+    pub name: String,
+    pub description: String,
+    pub parameters: Value,
+    #[serde(default)]
+    pub annotations: ToolAnnotations,
+}
+
+pub trait ToolTrait: Send + Sync {
+    fn info(&self) -> ToolInfo;
+    fn execute(&self, arguments: Value) -> Pin<Box<dyn Future<Output = Result<ToolOutput, ToolError>> + Send + Sync>>;
+
+    /// `output` as compact text for the model, or `None` to send it as
+    /// JSON. The JSON is still what gets recorded.
+    fn render(&self, _output: &ToolOutput) -> Option<String> {
+        None
+    }
+}
+
+pub const DEFAULT_READ_BUDGET: u64 = 256 * 1024;
+
+/// How many entries `list_dir` returns in tree mode unless told otherwise.
+pub const DEFAULT_MAX_TREE_ENTRIES: usize = 500;
+
+struct FileRegion {
+    content: String,
+    start: u64,
+    end: u64,
+    size: u64,
+}
+
+/// Reads at most `budget` bytes of a file, from `offset` or from the end,
+/// without loading the rest of it.
+async fn read_region(path: &Path, offset: Option<u64>, tail: bool, budget: u64) -> Result<FileRegion, ToolError> {
+    use tokio::io::{AsyncReadExt, AsyncSeekExt};
+
+    let mut file = tokio::fs::File::open(path).await?;
+    let size = file.metadata().await?.len();
+
+    let start = match (offset, tail) {
+        (Some(offset), _) => offset.min(size),
+        (None, true) => size.saturating_sub(budget),
+        (None, false) => 0,
+    };
+    file.seek(std::io::SeekFrom::Start(start)).await?;
+
+    let mut bytes = Vec::new();
+    file.take(budget).read_to_end(&mut bytes).await?;
+    let end = start + bytes.len() as u64;
+
+    Ok(FileRegion {
+        content: decode_region(&bytes, start > 0, end < size),
+        start,
+        end,
+        size,
+    })
+}
+
+/// Decodes a slice of a file, dropping characters cut in half at either edge.
+pub(crate) fn decode_region(bytes: &[u8], cut_start: bool, cut_end: bool) -> String {
+    let mut bytes = bytes;
+    if cut_start {
+        let skip = bytes.iter().take(3).take_while(|b| (**b & 0xC0) == 0x80).count();
+        bytes = &bytes[skip..];
+    }
+    if cut_end
+        && let Err(e) = std::str::from_utf8(bytes)
+        && e.error_len().is_none()
+    {
+        bytes = &bytes[..e.valid_up_to()];
+    }
+    String::from_utf8_lossy(bytes).into_owned()
+}
+
+pub struct FileReadTool {
+    base_path: PathBuf,
+    versions: Option<Arc<FileVersions>>,
+}
+
+impl FileReadTool {
+    pub fn new(base_path: PathBuf) -> Self {
+        Self {
+            base_path,
+            versions: None,
+        }
+    }
+
+    /// Records what was read in `versions`, for `write_file` to check.
+    pub fn with_versions(mut self, versions: Arc<FileVersions>) -> Self {
+        self.versions = Some(versions);
+        self
+    }
+}
+
+impl ToolTrait for FileReadTool {
+    fn info(&self) -> ToolInfo {
+        ToolInfo {
+            name: "read_file".to_string(),
+            description: "Read the contents of a file. Large files are cut to max_bytes; use offset or tail to read other parts".to_string(),
+            parameters: serde_json::json!({
+                "type": "object",
+                "properties": {
+                    "path": {
+                        "type": "string",
+                        "description": "Path to the file to read"
+                    },
+                    "offset": {
+                        "type": "integer",
+                        "description": "Byte offset to start reading at, e.g. from a grep match (default: 0)"
+                    },
+                    "tail": {
+                        "type": "boolean",
+                        "description": "Read the end of the file instead of the start"
+                    },
+                    "max_bytes": {
+                        "type": "integer",
+                        "description": "Maximum number of bytes to return (default: 262144)"
+                    }
+                },
+                "required": ["path"]
+            }),
+            annotations: ToolAnnotations::read_only(),
+        }
+    }
+
+    fn execute(&self, arguments: Value) -> Pin<Box<dyn Future<Output = Result<ToolOutput, ToolError>> + Send + Sync>> {
+        let base_path = self.base_path.clone();
+        let versions = self.versions.clone();
+        Box::pin(async move {
+            let path = arguments
+                .get("path")
+                .and_then(|v| v.as_str())
+                .ok_or_else(|| ToolError::InvalidArguments("Missing 'path' argument".to_string()))?;
+
+            let offset = arguments.get("offset").and_then(|v| v.as_u64());
+            let tail = arguments.get("tail").and_then(|v| v.as_bool()).unwrap_or(false);
+            let max_bytes = arguments
+                .get("max_bytes")
+                .and_then(|v| v.as_u64())
+                .unwrap_or(DEFAULT_READ_BUDGET);
+
+            let full_path = base_path.join(path);
+            let region = read_region(&full_path, offset, tail, max_bytes).await?;
+            if let Some(versions) = versions {
+                versions.record(&full_path).await;
+            }
+
+            if region.start == 0 && region.end == region.size {
+                return Ok(ToolOutput::success(serde_json::json!({
+                    "content": region.content,
+                    "path": path
+                })));
+            }
+
+            Ok(ToolOutput::success(serde_json::json!({
+                "content": region.content,
+                "path": path,
+                "size": region.size,
+                "start": region.start,
+                "end": region.end
+            }))
+            .with_truncated(true))
+        })
+    }
+
+    fn render(&self, output: &ToolOutput) -> Option<String> {
+        render::read_file(output)
+    }
+}
+
+pub struct FileWriteTool {
+    base_path: PathBuf,
+    versions: Option<Arc<FileVersions>>,
+}
+
+impl FileWriteTool {
+    pub fn new(base_path: PathBuf) -> Self {
+        Self {
+            base_path,
+            versions: None,
+        }
+    }
+
+    /// Refuses to overwrite files that changed on disk since they were last
+    /// read or written through `versions`.
+    pub fn with_versions(mut self, versions: Arc<FileVersions>) -> Self {
+        self.versions = Some(versions);
+        self
+    }
+}
+
+impl ToolTrait for FileWriteTool {
+    fn info(&self) -> ToolInfo {
+        ToolInfo {
+            name: "write_file".to_string(),
+            description: "Write content to a file".to_string(),
+            parameters: serde_json::json!({
+                "type": "object",
+                "properties": {
+                    "path": {
+                        "type": "string",
+                        "description": "Path to the file to write"
+                    },
+                    "content": {
+                        "type": "string",
+                        "description": "Content to write to the file"
+                    },
+                    "on_conflict": {
+                        "type": "string",
+                        "enum": ["fail", "merge", "overwrite"],
+                        "description": "What to do if the file changed on disk since you last read it: fail (default), merge your content with the change on disk, or overwrite it"
+                    }
+                },
+                "required": ["path", "content"]
+            }),
+            annotations: ToolAnnotations {
+                read_only: false,
+                destructive: true,
+                idempotent: true,
+                open_world: false,
+            },
+        }
+    }
+
+    fn execute(&self, arguments: Value) -> Pin<Box<dyn Future<Output = Result<ToolOutput, ToolError>> + Send + Sync>> {
+        let base_path = self.base_path.clone();
+        let versions = self.versions.clone();
+        Box::pin(async move {
+            let path = arguments
+                .get("path")
+                .and_then(|v| v.as_str())
+                .ok_or_else(|| ToolError::InvalidArguments("Missing 'path' argument".to_string()))?;
+
+            let content = arguments
+                .get("content")
+                .and_then(|v| v.as_str())
+                .ok_or_else(|| ToolError::InvalidArguments("Missing 'content' argument".to_string()))?;
+
+            let on_conflict = arguments.get("on_conflict").and_then(|v| v.as_str()).unwrap_or("fail");
+
+            let full_path = base_path.join(path);
+            let created = !full_path.exists();
+
+            let base = match &versions {
+                Some(versions) => versions.changed_since_seen(&full_path).await,
+                None => None,
+            };
+            let mut content = std::borrow::Cow::Borrowed(content);
+            let mut conflicts = None;
+            match (base, on_conflict) {
+                (None, _) | (Some(_), "overwrite") => {}
+                (Some(base), "merge") => {
+                    let (merged, count) = versions::merge(&base, &content, &full_path).await?;
+                    content = std::borrow::Cow::Owned(merged);
+                    conflicts = Some(count);
+                }
+                (Some(_), _) => {
+                    let message = format!(
+                        "{} changed on disk since you last read it, so it was not written. \
+                         Read it again, or retry with on_conflict \"merge\" to combine both changes \
+                         or \"overwrite\" to replace it.",
+                        path
+                    );
+                    return Ok(ToolOutput::failure("conflict", message, false).with_data(serde_json::json!({"path": path})));
+                }
+            }
+
+            if let Some(parent) = full_path.parent()
+                && !parent.exists()
+            {
+                tokio::fs::create_dir_all(parent)
+                    .await
+                    .map_err(|e| ToolError::IoError(e.to_string()))?;
+            }
+
+            if let Err(e) = tokio::fs::write(&full_path, content.as_bytes()).await {
+                return Err(ToolError::IoError(e.to_string()));
+            }
+            if let Some(versions) = versions {
+                versions.record(&full_path).await;
+            }
+
+            let mut output = serde_json::json!({
+                "path": path,
+                "created": created,
+                "message": "File written successfully"
+            });
+            if let Some(conflicts) = conflicts {
+                output["merged"] = Value::Bool(true);
+                output["conflicts"] = conflicts.into();
+                if conflicts > 0 {
+                    output["message"] = format!(
+                        "Merged with the change on disk; {} conflict(s) are marked in the file and need resolving",
+                        conflicts
+                    )
+                    .into();
+                }
+            }
+            Ok(ToolOutput::success(output))
+        })
+    }
+}
+
+/// Why an `edit_file` replacement couldn't be made.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub(crate) enum EditMismatch {
+    NotFound,
+    /// Found more than once, starting on these 1-based lines.
+    Ambiguous(Vec<usize>),
+}
+
+/// `text` with `old` replaced by `new`, and how many times it was. `old`
+/// must occur exactly once unless `replace_all` is set.
+pub(crate) fn apply_edit(text: &str, old: &str, new: &str, replace_all: bool) -> Result<(String, usize), EditMismatch> {
+    let starts: Vec<usize> = text.match_indices(old).map(|(start, _)| start).collect();
+    match starts.len() {
+        0 => Err(EditMismatch::NotFound),
+        1 => Ok((text.replacen(old, new, 1), 1)),
+        n if replace_all => Ok((text.replace(old, new), n)),
+        _ => Err(EditMismatch::Ambiguous(
+            starts.iter().map(|start| text[..*start].matches('\n').count() + 1).collect(),
+        )),
+    }
+}
+
+/// What an `edit_file` call would leave in the file, or `None` if it
+/// would fail. Lets `on_file_write` hooks see edits as whole files.
+pub(crate) async fn edited_content(base_path: &Path, arguments: &Value) -> Option<String> {
+    let path = arguments.get("path")?.as_str()?;
+    let old = arguments.get("old_string")?.as_str()?;
+    let new = arguments.get("new_string")?.as_str()?;
+    let replace_all = arguments.get("replace_all").and_then(|v| v.as_bool()).unwrap_or(false);
+    let text = String::from_utf8(tokio::fs::read(base_path.join(path)).await.ok()?).ok()?;
+    apply_edit(&text, old, new, replace_all).ok().map(|(edited, _)| edited)
+}
+
+pub struct EditFileTool {
+    base_path: PathBuf,
+    versions: Option<Arc<FileVersions>>,
+}
+
+impl EditFileTool {
+    pub fn new(base_path: PathBuf) -> Self {
+        Self {
+            base_path,
+            versions: None,
+        }
+    }
+
+    /// Records each edit in `versions`, so a later `write_file` doesn't
+    /// mistake it for a change made on disk by someone else.
+    pub fn with_versions(mut self, versions: Arc<FileVersions>) -> Self {
+        self.versions = Some(versions);
+        self
+    }
+}
+
+impl ToolTrait for EditFileTool {
+    fn info(&self) -> ToolInfo {
+        ToolInfo {
+            name: "edit_file".to_string(),
+            description: "Replace exact text in a file, leaving the rest as it is".to_string(),
+            parameters: serde_json::json!({
+                "type": "object",
+                "properties": {
+                    "path": {
+                        "type": "string",
+                        "description": "Path to the file to edit"
+                    },
+                    "old_string": {
+                        "type": "string",
+                        "description": "Text to replace, copied exactly, including indentation; include enough lines around the change to make it unique"
+                    },
+                    "new_string": {
+                        "type": "string",
+                        "description": "Text to put in its place"
+                    },
+                    "replace_all": {
+                        "type": "boolean",
+                        "description": "Replace every occurrence instead of requiring exactly one (default: false)"
+                    }
+                },
+                "required": ["path", "old_string", "new_string"]
+            }),
+            annotations: ToolAnnotations {
+                read_only: false,
+                destructive: true,
+                idempotent: false,
+                open_world: false,
+            },
+        }
+    }
+
+    fn execute(&self, arguments: Value) -> Pin<Box<dyn Future<Output = Result<ToolOutput, ToolError>> + Send + Sync>> {
+        let base_path = self.base_path.clone();
+        let versions = self.versions.clone();
+        Box::pin(async move {
+            let path = arguments
+                .get("path")
+                .and_then(|v| v.as_str())
+                .ok_or_else(|| ToolError::InvalidArguments("Missing 'path' argument".to_string()))?;
+            let old = arguments
+                .get("old_string")
+                .and_then(|v| v.as_str())
+                .ok_or_else(|| ToolError::InvalidArguments("Missing 'old_string' argument".to_string()))?;
+            let new = arguments
+                .get("new_string")
+                .and_then(|v| v.as_str())
+                .ok_or_else(|| ToolError::InvalidArguments("Missing 'new_string' argument".to_string()))?;
+            let replace_all = arguments.get("replace_all").and_then(|v| v.as_bool()).unwrap_or(false);
+            if old.is_empty() {
+                return Err(ToolError::InvalidArguments(
+                    "'old_string' is empty; use write_file to create a file".to_string(),
+                ));
+            }
+            if old == new {
+                return Err(ToolError::InvalidArguments("'old_string' and 'new_string' are the same".to_string()));
+            }
+
+            let full_path = base_path.join(path);
+            if !full_path.is_file() {
+                return Err(ToolError::NotFound(format!("{} does not exist; use write_file to create it", path)));
+            }
+            // Written back whole, so bytes that aren't UTF-8 would be lost.
+            let text = match String::from_utf8(tokio::fs::read(&full_path).await?) {
+                Ok(text) => text,
+                Err(e) => {
+                    let message = format!(
+                        "{} is not valid UTF-8 (at byte {}), so edit_file can't change it without corrupting \
+                         it. Leave it as it is, or change it with run_command.",
+                        path,
+                        e.utf8_error().valid_up_to()
+                    );
+                    return Ok(ToolOutput::failure("not_utf8", message, false).with_data(serde_json::json!({"path": path})));
+                }
+            };
+
+            let (edited, replacements) = match apply_edit(&text, old, new, replace_all) {
+                Ok(edit) => edit,
+                Err(EditMismatch::NotFound) => {
+                    let message = format!(
+                        "old_string was not found in {}. Read the file again and copy the text exactly, \
+                         including whitespace.",
+                        path
+                    );
+                    return Ok(ToolOutput::failure("no_match", message, false).with_data(serde_json::json!({"path": path})));
+                }
+                Err(EditMismatch::Ambiguous(lines)) => {
+                    let list: Vec<String> = lines.iter().map(|line| line.to_string()).collect();
+                    let message = format!(
+                        "old_string occurs {} times in {} (lines {}). Include more surrounding lines to make \
+                         it unique, or set replace_all to change every occurrence.",
+                        list.len(),
+                        path,
+                        list.join(", ")
+                    );
+                    return Ok(ToolOutput::failure("ambiguous", message, false).with_data(serde_json::json!({
+                        "path": path,
+                        "occurrences": lines.len(),
+                        "lines": lines
+                    })));
+                }
+            };
+
+            tokio::fs::write(&full_path, edited.as_bytes())
+                .await
+                .map_err(|e| ToolError::IoError(e.to_string()))?;
+            if let Some(versions) = versions {
+                versions.record(&full_path).await;
+            }
+
+            Ok(ToolOutput::success(serde_json::json!({
+                "path": path,
+                "created": false,
+                "replacements": replacements,
+                "message": format!("Replaced {} occurrence(s)", replacements)
+            })))
+        })
+    }
+}
+
+pub struct ListDirTool {
+    base_path: PathBuf,
+}
+
+impl ListDirTool {
+    pub fn new(base_path: PathBuf) -> Self {
+        Self { base_path }
+    }
+
+    /// Lists the tree below `root` with each entry's path relative to it.
+    async fn tree(
+        root: PathBuf,
+        path: String,
+        depth: usize,
+        pattern: Option<String>,
+        limit: usize,
+    ) -> Result<ToolOutput, ToolError> {
+        if !root.is_dir() {
+            return Err(ToolError::NotFound(format!("{} is not a directory", path)));
+        }
+        tokio::task::spawn_blocking(move || {
+            let walk = walk::tree(&root, depth, pattern.as_deref(), limit)
+                .map_err(|e| ToolError::InvalidArguments(format!("Invalid pattern: {}", e)))?;
+            let items: Vec<Value> = walk
+                .entries
+                .iter()
+                .map(|entry| {
+                    let relative = entry.path.strip_prefix(&root).unwrap_or(&entry.path);
+                    let mut item = serde_json::json!({
+                        "path": relative.to_string_lossy().replace("\\", "/"),
+                        "is_dir": entry.is_dir
+                    });
+                    if !entry.is_dir
+                        && let Ok(metadata) = std::fs::metadata(&entry.path)
+                    {
+                        item["size"] = Value::from(metadata.len());
+                    }
+                    item
+                })
+                .collect();
+            let output = serde_json::json!({
+                "path": path,
+                "tree": true,
+                "depth": depth,
+                "items": items
+            });
+            Ok(ToolOutput::success(output).with_truncated(walk.truncated))
+        })
+        .await
+        .map_err(|e| ToolError::ExecutionFailed(e.to_string()))?
+    }
+}
+
+impl ToolTrait for ListDirTool {
+    fn info(&self) -> ToolInfo {
+        ToolInfo {
+            name: "list_dir".to_string(),
+            description: "List directory contents, or with depth or pattern, the tree below it".to_string(),
+            parameters: serde_json::json!({
+                "type": "object",
+                "properties": {
+                    "path": {
+                        "type": "string",
+                        "description": "Path to the directory to list"
+                    },
+                    "depth": {
+                        "type": "integer",
+                        "description": "How many levels to list. Above 1, subdirectories are listed as a tree, skipping hidden and git-ignored entries (default: 1)"
+                    },
+                    "pattern": {
+                        "type": "string",
+                        "description": "List only files matching this glob (e.g., *.rs or src/**/*.ts) in the tree, with the directories that hold them"
+                    },
+                    "max_entries": {
+                        "type": "integer",
+                        "description": "Stop the tree after this many entries (default: 500)"
+                    }
+                },
+                "required": ["path"]
+            }),
+            annotations: ToolAnnotations::read_only(),
+        }
+    }
+
+    fn execute(&self, arguments: Value) -> Pin<Box<dyn Future<Output = Result<ToolOutput, ToolError>> + Send + Sync>> {
+        let base_path = self.base_path.clone();
+        Box::pin(async move {
+            let path = arguments
+                .get("path")
+                .and_then(|v| v.as_str())
+                .ok_or_else(|| ToolError::InvalidArguments("Missing 'path' argument".to_string()))?;
+
+            let full_path = base_path.join(path);
+
+            let depth = arguments.get("depth").and_then(|v| v.as_u64()).unwrap_or(1).max(1) as usize;
+            let pattern = arguments.get("pattern").and_then(|v| v.as_str()).map(str::to_string);
+            if depth > 1 || pattern.is_some() {
+                let limit = arguments
+                    .get("max_entries")
+                    .and_then(|v| v.as_u64())
+                    .map(|n| n as usize)
+                    .unwrap_or(DEFAULT_MAX_TREE_ENTRIES);
+                return Self::tree(full_path, path.to_string(), depth, pattern, limit).await;
+            }
+
+            match tokio::fs::read_dir(&full_path).await {
+                Ok(mut entries) => {
+                    let mut items = Vec::new();
+                    while let Some(entry) = entries.next_entry().await.map_err(|e| ToolError::IoError(e.to_string()))? {
+                        let metadata = entry.metadata().await.map_err(|e| ToolError::IoError(e.to_string()))?;
+                        items.push(serde_json::json!({
+                            "name": entry.file_name().to_string_lossy().to_string(),
+                            "is_dir": metadata.is_dir(),
+                            "is_file": metadata.is_file(),
+                            "size": metadata.len()
+                        }));
+                    }
+                    Ok(ToolOutput::success(serde_json::json!({
+                        "path": path,
+                        "items": items
+                    })))
+                }
+                Err(e) => Err(ToolError::IoError(e.to_string())),
+            }
+        })
+    }
+
+    fn render(&self, output: &ToolOutput) -> Option<String> {
+        render::list_dir(output)
+    }
+}
+
+/// What `grep` returns: every matching line, the files with a match, or
+/// how many lines match in each file. Counts win if both are asked for.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub(crate) enum GrepMode {
+    Lines,
+    Files,
+    Counts,
+}
+
+impl GrepMode {
+    pub(crate) fn from_arguments(arguments: &Value) -> Self {
+        let flag = |name: &str| arguments.get(name).and_then(|v| v.as_bool()).unwrap_or(false);
+        if flag("count_only") {
+            GrepMode::Counts
+        } else if flag("files_with_matches") {
+            GrepMode::Files
+        } else {
+            GrepMode::Lines
+        }
+    }
+}
+
+pub struct GrepTool {
+    base_path: PathBuf,
+}
+
+impl GrepTool {
+    pub fn new(base_path: PathBuf) -> Self {
+        Self { base_path }
+    }
+
+    /// Scans a file line by line, so only one line is held in memory at a
+    /// time. Each match carries its byte offset for a follow-up `read_file`.
+    /// With `ignore_case`, `pattern` must already be lowercase. Stops at the
+    /// first match if `first_only`.
+    async fn search_in_file(
+        file_path: &Path,
+        pattern: &str,
+        ignore_case: bool,
+        first_only: bool,
+    ) -> Result<Vec<serde_json::Value>, std::io::Error> {
+        use tokio::io::AsyncBufReadExt;
+
+        let file = tokio::fs::File::open(file_path).await?;
+        let mut reader = tokio::io::BufReader::new(file);
+        let mut line = Vec::new();
+        let mut line_no = 0;
+        let mut offset = 0;
+        let mut matches = Vec::new();
+
+        loop {
+            line.clear();
+            let read = reader.read_until(b'\n', &mut line).await?;
+            if read == 0 {
+                break;
+            }
+            line_no += 1;
+
+            let text = String::from_utf8_lossy(&line);
+            let found = if ignore_case {
+                text.to_lowercase().contains(pattern)
+            } else {
+                text.contains(pattern)
+            };
+            if found {
+                matches.push(serde_json::json!({
+                    "file": file_path.to_string_lossy(),
+                    "line": line_no,
+                    "offset": offset,
+                    "content": text.trim()
+                }));
+                if first_only {
+                    break;
+                }
+            }
+            offset += read;
+        }
+        Ok(matches)
+    }
+
+    fn matches_file_pattern(path: &Path, pattern: &str) -> bool {
+        if pattern == "*" {
+            return true;
+        }
+        path.extension()
+            .is_some_and(|ext| pattern == format!("*.{}", ext.to_string_lossy()))
+    }
+}
+
+impl ToolTrait for GrepTool {
+    fn info(&self) -> ToolInfo {
+        ToolInfo {
+            name: "grep".to_string(),
+            description: "Search for a pattern in files".to_string(),
+            parameters: serde_json::json!({
+                "type": "object",
+                "properties": {
+                    "pattern": {
+                        "type": "string",
+                        "description": "Pattern to search for"
+                    },
+                    "path": {
+                        "type": "string",
+                        "description": "Path to search in (default: current directory)"
+                    },
+                    "file_pattern": {
+                        "type": "string",
+                        "description": "File pattern to match (e.g., *.rs)"
+                    },
+                    "max_files": {
+                        "type": "integer",
+                        "description": "Stop after visiting this many files and directories (default: 10000)"
+                    },
+                    "ignore_case": {
+                        "type": "boolean",
+                        "description": "Match regardless of case (default: false)"
+                    },
+                    "files_with_matches": {
+                        "type": "boolean",
+                        "description": "Return only the files that contain a match, not the lines (default: false)"
+                    },
+                    "count_only": {
+                        "type": "boolean",
+                        "description": "Return how many lines match in each file, not the lines (default: false)"
+                    }
+                },
+                "required": ["pattern"]
+            }),
+            annotations: ToolAnnotations::read_only(),
+        }
+    }
+
+    fn execute(&self, arguments: Value) -> Pin<Box<dyn Future<Output = Result<ToolOutput, ToolError>> + Send + Sync>> {
+        let base_path = self.base_path.clone();
+        Box::pin(async move {
+            let pattern = arguments
+                .get("pattern")
+                .and_then(|v| v.as_str())
+                .ok_or_else(|| ToolError::InvalidArguments("Missing 'pattern' argument".to_string()))?;
+
+            let path = arguments
+                .get("path")
+                .and_then(|v| v.as_str())
+                .unwrap_or(".");
+
+            let file_pattern = arguments
+                .get("file_pattern")
+                .and_then(|v| v.as_str())
+                .unwrap_or("*");
+
+            let max_files = arguments
+                .get("max_files")
+                .and_then(|v| v.as_u64())
+                .map(|n| n as usize)
+                .unwrap_or(DEFAULT_MAX_FILES);
+
+            let ignore_case = arguments.get("ignore_case").and_then(|v| v.as_bool()).unwrap_or(false);
+            let needle = if ignore_case { pattern.to_lowercase() } else { pattern.to_string() };
+            let mode = GrepMode::from_arguments(&arguments);
+
+            let search_path = base_path.join(path);
+
+            let mut results = Vec::new();
+
+            let walk = walk::walk(&search_path, max_files).await;
+            let files = walk
+                .entries
+                .into_iter()
+                .filter(|entry| !entry.is_dir && GrepTool::matches_file_pattern(&entry.path, file_pattern))
+                .map(|entry| entry.path);
+
+            for file in files {
+                match GrepTool::search_in_file(&file, &needle, ignore_case, mode == GrepMode::Files).await {
+                    Ok(matches) if matches.is_empty() => {}
+                    Ok(matches) => match mode {
+                        GrepMode::Lines => results.extend(matches),
+                        GrepMode::Files => results.push(serde_json::json!({"file": file.to_string_lossy()})),
+                        GrepMode::Counts => results.push(serde_json::json!({
+                            "file": file.to_string_lossy(),
+                            "count": matches.len()
+                        })),
+                    },
+                    Err(e) => {
+                        results.push(serde_json::json!({
+                            "error": format!("Failed to read {}: {}", file.to_string_lossy(), e)
+                        }));
+                    }
+                }
+            }
+
+            let output = serde_json::json!({
+                "pattern": pattern,
+                "path": path,
+                "results": results
+            });
+            Ok(ToolOutput::success(output).with_truncated(walk.truncated))
+        })
+    }
+
+    fn render(&self, output: &ToolOutput) -> Option<String> {
+        render::grep(output)
+    }
+}
+
+pub struct RunCommandTool {
+    base_path: PathBuf,
+    network: NetworkPolicy,
+    limits: ResourceLimits,
+    scratch_dir: Option<PathBuf>,
+}
+
+impl RunCommandTool {
+    pub fn new(base_path: PathBuf) -> Self {
+        Self {
+            base_path,
+            network: NetworkPolicy::default(),
+            limits: ResourceLimits::default(),
+            scratch_dir: None,
+        }
+    }
+
+    pub fn with_network(mut self, network: NetworkPolicy) -> Self {
+        self.network = network;
+        self
+    }
+
+    pub fn with_limits(mut self, limits: ResourceLimits) -> Self {
+        self.limits = limits;
+        self
+    }
+
+    /// Runs commands with `TMPDIR` set to `dir`.
+    pub fn with_scratch_dir(mut self, dir: Option<PathBuf>) -> Self {
+        self.scratch_dir = dir;
+        self
+    }
+}
+
+impl ToolTrait for RunCommandTool {
+    fn info(&self) -> ToolInfo {
+        ToolInfo {
+            name: "run_command".to_string(),
+            description: "Run a shell command".to_string(),
+            parameters: serde_json::json!({
+                "type": "object",
+                "properties": {
+                    "command": {
+                        "type": "string",
+                        "description": "Command to run"
+                    }
+                },
+                "required": ["command"]
+            }),
+            annotations: ToolAnnotations::default(),
+        }
+    }
+
+    fn execute(&self, arguments: Value) -> Pin<Box<dyn Future<Output = Result<ToolOutput, ToolError>> + Send + Sync>> {
+        let base_path = self.base_path.clone();
+        let network = self.network.clone();
+        let limits = self.limits.clone();
+        let scratch_dir = self.scratch_dir.clone();
+        Box::pin(async move {
+            let command = arguments
+                .get("command")
+                .and_then(|v| v.as_str())
+                .ok_or_else(|| ToolError::InvalidArguments("Missing 'command' argument".to_string()))?;
+
+            let mut process = network.command(&limits.wrap(command));
+            if let Some(dir) = &scratch_dir {
+                process.env("TMPDIR", dir);
+            }
+            let mut child = process
+                .current_dir(&base_path)
+                .stdout(std::process::Stdio::piped())
+                .stderr(std::process::Stdio::piped())
+                .kill_on_drop(true)
+                .spawn()
+                .map_err(|e| ToolError::ExecutionFailed(e.to_string()))?;
+
+            let ((stdout, stdout_dropped), (stderr, stderr_dropped)) =
+                match (child.stdout.take(), child.stderr.take()) {
+                    (Some(stdout), Some(stderr)) => tokio::try_join!(
+                        limits::read_capped(stdout, limits.max_output_bytes),
+                        limits::read_capped(stderr, limits.max_output_bytes),
+                    )?,
+                    _ => return Err(ToolError::ExecutionFailed("Command output was not captured".to_string())),
+                };
+            let status = child.wait().await?;
+
+            let network_violation = if status.success() {
+                None
+            } else {
+                network.violation(&format!("{}\n{}", stdout, stderr))
+            };
+
+            let mut result = serde_json::json!({
+                "command": command,
+                "stdout": stdout,
+                "stderr": stderr,
+                "exit_code": status.code()
+            });
+            let truncated = stdout_dropped > 0 || stderr_dropped > 0;
+            if truncated {
+                result["output_truncated"] = serde_json::json!({
+                    "stdout_bytes_dropped": stdout_dropped,
+                    "stderr_bytes_dropped": stderr_dropped
+                });
+            }
+            let failure = if let Some(violation) = network_violation {
+                result["network_blocked"] = Value::Bool(true);
+                result["note"] = Value::String(violation);
+                Some("network_blocked")
+            } else if let Some(violation) = limits.violation(&status) {
+                result["limit_exceeded"] = Value::Bool(true);
+                result["note"] = Value::String(violation);
+                Some("limit_exceeded")
+            } else if !status.success() {
+                Some("nonzero_exit")
+            } else {
+                None
+            };
+            let output = match failure {
+                Some(code) => exit_failure(code, status.code()).with_data(result),
+                None => ToolOutput::success(result),
+            };
+            Ok(output.with_truncated(truncated))
+        })
+    }
+
+    fn render(&self, output: &ToolOutput) -> Option<String> {
+        render::run_command(output)
+    }
+}
+
+/// A failed `run_command` output with a message saying how the command
+/// ended, with `exit_code` or by a signal.
+pub(crate) fn exit_failure(code: &str, exit_code: Option<i32>) -> ToolOutput {
+    let message = match exit_code {
+        Some(exit_code) => format!("The command exited with code {}.", exit_code),
+        None => "The command was killed by a signal.".to_string(),
+    };
+    ToolOutput::failure(code, message, false)
+}
+
+pub struct GlobTool {
+    base_path: PathBuf,
+}
+
+impl GlobTool {
+    pub fn new(base_path: PathBuf) -> Self {
+        Self { base_path }
+    }
+}
+
+impl ToolTrait for GlobTool {
+    fn info(&self) -> ToolInfo {
+        ToolInfo {
+            name: "glob".to_string(),
+            description: "Find files matching a pattern".to_string(),
+            parameters: serde_json::json!({
+                "type": "object",
+                "properties": {
+                    "pattern": {
+                        "type": "string",
+                        "description": "Glob pattern (e.g., **/*.rs)"
+                    },
+                    "path": {
+                        "type": "string",
+                        "description": "Base path to search from"
+                    },
+                    "max_files": {
+                        "type": "integer",
+                        "description": "Stop after visiting this many files and directories (default: 10000)"
+                    }
+                },
+                "required": ["pattern"]
+            }),
+            annotations: ToolAnnotations::read_only(),
+        }
+    }
+
+    fn execute(&self, arguments: Value) -> Pin<Box<dyn Future<Output = Result<ToolOutput, ToolError>> + Send + Sync>> {
+        let base_path = self.base_path.clone();
+        Box::pin(async move {
+            let pattern = arguments
+                .get("pattern")
+                .and_then(|v| v.as_str())
+                .ok_or_else(|| ToolError::InvalidArguments("Missing 'pattern' argument".to_string()))?;
+
+            let path = arguments
+                .get("path")
+                .and_then(|v| v.as_str())
+                .unwrap_or(".");
+
+            let max_files = arguments
+                .get("max_files")
+                .and_then(|v| v.as_u64())
+                .map(|n| n as usize)
+                .unwrap_or(DEFAULT_MAX_FILES);
+
+            let search_path = base_path.join(path);
+
+            fn matches_wildcard(name: &str, pattern: &str) -> bool {
+                if pattern.contains("**/") || pattern.starts_with("**") {
+                    let suffix = pattern
+                        .trim_start_matches("**/")
+                        .trim_start_matches("**");
+                    if suffix.contains('/') {
+                        name == suffix.split('/').next().unwrap_or(suffix)
+                            || name.ends_with(suffix.trim_start_matches('*'))
+                    } else {
+                        wildcard_match(name, suffix)
+                    }
+                } else {
+                    wildcard_match(name, pattern)
+                }
+            }
+
+            fn wildcard_match(name: &str, pattern: &str) -> bool {
+                let name_bytes = name.as_bytes();
+                let pattern_bytes = pattern.as_bytes();
+                let n = name_bytes.len();
+                let m = pattern_bytes.len();
+                let mut dp = vec![vec![false; m + 1]; n + 1];
+                dp[0][0] = true;
+                for j in 1..=m {
+                    if pattern_bytes[j - 1] == b'*' {
+                        dp[0][j] = dp[0][j - 1];
+                    }
+                }
+                for i in 1..=n {
+                    for j in 1..=m {
+                        if pattern_bytes[j - 1] == b'*' {
+                            dp[i][j] = dp[i - 1][j] || dp[i][j - 1];
+                        } else if pattern_bytes[j - 1] == b'?' || pattern_bytes[j - 1] == name_bytes[i - 1] {
+                            dp[i][j] = dp[i - 1][j - 1];
+                        } else {
+                            dp[i][j] = false;
+                        }
+                    }
+                }
+                dp[n][m]
+            }
+
+            let walk = walk::walk(&search_path, max_files).await;
+            let results: Vec<String> = walk
+                .entries
+                .iter()
+                .filter(|entry| {
+                    let file_name = entry.path.file_name().and_then(|n| n.to_str()).unwrap_or("");
+                    matches_wildcard(file_name, pattern)
+                })
+                .map(|entry| entry.path.to_string_lossy().replace("\\", "/"))
+                .collect();
+
+            let output = serde_json::json!({
+                "pattern": pattern,
+                "path": path,
+                "files": results
+            });
+            Ok(ToolOutput::success(output).with_truncated(walk.truncated))
+        })
+    }
+
+    fn render(&self, output: &ToolOutput) -> Option<String> {
+        render::glob(output)
+    }
+}
+
+/// How many hits `search_history` returns unless asked for more.
+const DEFAULT_HISTORY_HITS: usize = 10;
+
+pub struct SearchHistoryTool {
+    base_path: PathBuf,
+}
+
+impl SearchHistoryTool {
+    pub fn new(base_path: PathBuf) -> Self {
+        Self { base_path }
+    }
+}
+
+impl ToolTrait for SearchHistoryTool {
+    fn info(&self) -> ToolInfo {
+        ToolInfo {
+            name: "search_history".to_string(),
+            description: "Search the transcripts of past sessions in this workspace".to_string(),
+            parameters: serde_json::json!({
+                "type": "object",
+                "properties": {
+                    "query": {
+                        "type": "string",
+                        "description": "Words that must all appear, case-insensitive"
+                    },
+                    "limit": {
+                        "type": "integer",
+                        "description": "Maximum number of matches to return (default: 10)"
+                    }
+                },
+                "required": ["query"]
+            }),
+            annotations: ToolAnnotations::read_only(),
+        }
+    }
+
+    fn execute(&self, arguments: Value) -> Pin<Box<dyn Future<Output = Result<ToolOutput, ToolError>> + Send + Sync>> {
+        let store = crate::session::SessionStore::for_workdir(&self.base_path);
+        Box::pin(async move {
+            let query = arguments
+                .get("query")
+                .and_then(|v| v.as_str())
+                .ok_or_else(|| ToolError::InvalidArguments("Missing 'query' argument".to_string()))?;
+
+            let limit = arguments
+                .get("limit")
+                .and_then(|v| v.as_u64())
+                .map(|n| n as usize)
+                .unwrap_or(DEFAULT_HISTORY_HITS);
+
+            let hits = store
+                .search(query, limit)
+                .map_err(|e| ToolError::IoError(e.to_string()))?;
+
+            Ok(ToolOutput::success(serde_json::json!({
+                "query": query,
+                "hits": hits
+            })))
+        })
+    }
+}
+
+#[derive(Default)]
+pub struct ToolManager {
+    tools: std::collections::HashMap<String, Box<dyn ToolTrait>>,
+}
+
+impl ToolManager {
+    pub fn new() -> Self {
+        Self {
+            tools: std::collections::HashMap::new(),
+        }
+    }
+
+    pub fn register(&mut self, tool: Box<dyn ToolTrait>) {
+        self.tools.insert(tool.info().name.clone(), tool);
+    }
+
+    pub fn get(&self, name: &str) -> Option<&dyn ToolTrait> {
+        self.tools.get(name).map(|t| t.as_ref())
+    }
+
+    /// How `arguments` break the parameter schema of the tool `name`. Empty
+    /// when they fit or no such tool is registered.
+    pub fn validate(&self, name: &str, arguments: &Value) -> Vec<String> {
+        match self.get(name) {
+            Some(tool) => validate_arguments(&tool.info().parameters, arguments),
+            None => Vec::new(),
+        }
+    }
+
+    /// Runs the tool `name`, noting in its output how long it took.
+    pub async fn execute(&self, name: &str, arguments: Value) -> Result<ToolOutput, ToolError> {
+        let tool = self.get(name).ok_or_else(|| ToolError::UnknownTool(name.to_string()))?;
+        let started = std::time::Instant::now();
+        let mut output = tool.execute(arguments).await?;
+        output.meta.duration_ms = started.elapsed().as_millis() as u64;
+        Ok(output)
+    }
+
+    pub fn list(&self) -> Vec<String> {
+        self.tools.keys().cloned().collect()
+    }
+
+    /// The tools annotated as read-only.
+    pub fn read_only(&self) -> Vec<String> {
+        self.tools
+            .values()
+            .map(|tool| tool.info())
+            .filter(|info| info.annotations.read_only)
+            .map(|info| info.name)
+            .collect()
+    }
+
+    pub fn get_definitions(&self) -> Vec<crate::clients::ToolDefinition> {
+        self.tools
+            .values()
+            .map(|tool| {
+                let info = tool.info();
+                crate::clients::ToolDefinition {
+                    name: info.name,
+                    description: info.description,
+                    parameters: info.parameters,
+                }
+            })
+            .collect()
+    }
+}
+
+pub fn default_tools(base_path: PathBuf) -> ToolManager {
+    default_tools_with_policy(base_path, NetworkPolicy::default(), ResourceLimits::default())
+}
+
+/// [`default_tools`] with commands run under `network` and `limits`.
+pub fn default_tools_with_policy(base_path: PathBuf, network: NetworkPolicy, limits: ResourceLimits) -> ToolManager {
+    let mut manager = ToolManager::new();
+    let versions = Arc::new(FileVersions::new());
+
+    manager.register(Box::new(FileReadTool::new(base_path.clone()).with_versions(Arc::clone(&versions))));
+    manager.register(Box::new(FileWriteTool::new(base_path.clone()).with_versions(Arc::clone(&versions))));
+    manager.register(Box::new(EditFileTool::new(base_path.clone()).with_versions(versions)));
+    manager.register(Box::new(ListDirTool::new(base_path.clone())));
+    manager.register(Box::new(GrepTool::new(base_path.clone())));
+    manager.register(Box::new(RunCommandTool::new(base_path.clone()).with_network(network).with_limits(limits)));
+    manager.register(Box::new(GlobTool::new(base_path.clone())));
+    manager.register(Box::new(SearchHistoryTool::new(base_path.clone())));
+    manager.register(Box::new(RenderDiagramTool::new(base_path.clone())));
+
+    manager
+}
+
+pub fn read_only_tools(base_path: PathBuf) -> ToolManager {
+    let mut manager = ToolManager::new();
+
+    manager.register(Box::new(FileReadTool::new(base_path.clone())));
+    manager.register(Box::new(ListDirTool::new(base_path.clone())));
+    manager.register(Box::new(GrepTool::new(base_path.clone())));
+    manager.register(Box::new(GlobTool::new(base_path.clone())));
+    manager.register(Box::new(SearchHistoryTool::new(base_path.clone())));
+
+    manager
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[tokio::test]
+    async fn test_read_file_budget() {
+        let dir = tempfile::tempdir().unwrap();
+        std::fs::write(dir.path().join("app.log"), "first line\nmiddle é line\nlast line\n").unwrap();
+        let tool = FileReadTool::new(dir.path().to_path_buf());
+
+        let whole = tool.execute(serde_json::json!({"path": "app.log"})).await.unwrap();
+        assert!(!whole.meta.truncated);
+
+        let head = tool
+            .execute(serde_json::json!({"path": "app.log", "max_bytes": 10}))
+            .await
+            .unwrap();
+        assert_eq!(head.data["content"], "first line");
+        assert!(head.meta.truncated);
+        assert_eq!(head.data["size"], 36);
+
+        let tail = tool
+            .execute(serde_json::json!({"path": "app.log", "tail": true, "max_bytes": 10}))
+            .await
+            .unwrap();
+        assert_eq!(tail.data["content"], "last line\n");
+        assert_eq!(tail.data["start"], 26);
+
+        // Starting inside the two-byte 'é' drops the orphaned continuation byte.
+        let region = tool
+            .execute(serde_json::json!({"path": "app.log", "offset": 19, "max_bytes": 6}))
+            .await
+            .unwrap();
+        assert_eq!(region.data["content"], " line");
+    }
+
+    #[tokio::test]
+    async fn test_grep_reports_offsets() {
+        let dir = tempfile::tempdir().unwrap();
+        std::fs::write(dir.path().join("app.log"), "ok\nERROR disk full\nok\nERROR again\n").unwrap();
+        let tool = GrepTool::new(dir.path().to_path_buf());
+
+        let result = tool.execute(serde_json::json!({"pattern": "ERROR"})).await.unwrap();
+        let results = result.data["results"].as_array().unwrap();
+
+        assert_eq!(results.len(), 2);
+        assert_eq!(results[0]["line"], 2);
+        assert_eq!(results[0]["offset"], 3);
+        assert_eq!(results[1]["line"], 4);
+        assert_eq!(results[1]["offset"], 22);
+    }
+
+    #[tokio::test]
+    async fn test_grep_modes() {
+        let dir = tempfile::tempdir().unwrap();
+        std::fs::write(dir.path().join("a.log"), "Error one\nok\nERROR two\n").unwrap();
+        std::fs::write(dir.path().join("b.log"), "ok\n").unwrap();
+        std::fs::write(dir.path().join("c.log"), "error\n").unwrap();
+        let tool = GrepTool::new(dir.path().to_path_buf());
+        let search = |options: Value| {
+            let mut arguments = serde_json::json!({"pattern": "error", "ignore_case": true});
+            arguments.as_object_mut().unwrap().extend(options.as_object().unwrap().clone());
+            tool.execute(arguments)
+        };
+
+        let lines = search(serde_json::json!({})).await.unwrap();
+        assert_eq!(lines.data["results"].as_array().unwrap().len(), 3);
+
+        let files = search(serde_json::json!({"files_with_matches": true})).await.unwrap();
+        let files: Vec<&str> = files.data["results"]
+            .as_array()
+            .unwrap()
+            .iter()
+            .map(|entry| entry["file"].as_str().unwrap())
+            .collect();
+        assert_eq!(files.len(), 2);
+        assert!(files[0].ends_with("a.log") && files[1].ends_with("c.log"));
+
+        let counts = search(serde_json::json!({"count_only": true, "files_with_matches": true})).await.unwrap();
+        assert_eq!(counts.data["results"][0]["count"], 2);
+        assert_eq!(counts.data["results"][1]["count"], 1);
+
+        let exact = tool.execute(serde_json::json!({"pattern": "error", "count_only": true})).await.unwrap();
+        assert_eq!(exact.data["results"].as_array().unwrap().len(), 1);
+    }
+
+    #[tokio::test]
+    async fn test_glob_skips_hidden_and_respects_limit() {
+        let dir = tempfile::tempdir().unwrap();
+        std::fs::create_dir_all(dir.path().join("src/nested")).unwrap();
+        std::fs::create_dir_all(dir.path().join(".cache")).unwrap();
+        std::fs::write(dir.path().join("src/a.rs"), "").unwrap();
+        std::fs::write(dir.path().join("src/nested/b.rs"), "").unwrap();
+        std::fs::write(dir.path().join(".cache/c.rs"), "").unwrap();
+        let tool = GlobTool::new(dir.path().to_path_buf());
+
+        let result = tool.execute(serde_json::json!({"pattern": "**/*.rs"})).await.unwrap();
+        let files: Vec<&str> = result.data["files"]
+            .as_array()
+            .unwrap()
+            .iter()
+            .map(|f| f.as_str().unwrap())
+            .collect();
+        assert_eq!(files.len(), 2);
+        assert!(files[0].ends_with("src/a.rs"));
+        assert!(files[1].ends_with("src/nested/b.rs"));
+        assert!(!result.meta.truncated);
+
+        let result = tool
+            .execute(serde_json::json!({"pattern": "*", "max_files": 2}))
+            .await
+            .unwrap();
+        assert_eq!(result.data["files"].as_array().unwrap().len(), 2);
+        assert!(result.meta.truncated);
+    }
+
+    #[tokio::test]
+    async fn test_search_history() {
+        let dir = tempfile::tempdir().unwrap();
+        let store = crate::session::SessionStore::for_workdir(dir.path());
+        let session = crate::session::Session::new("Fix the flaky login test", dir.path().to_path_buf(), "gpt-4o");
+        store.save(&session).unwrap();
+        let tool = SearchHistoryTool::new(dir.path().to_path_buf());
+
+        let result = tool.execute(serde_json::json!({"query": "LOGIN"})).await.unwrap();
+        let hits = result.data["hits"].as_array().unwrap();
+        assert_eq!(hits.len(), 1);
+        assert_eq!(hits[0]["session_id"], session.id.as_str());
+        assert_eq!(hits[0]["snippet"], "Fix the flaky login test");
+
+        let result = tool.execute(serde_json::json!({"query": "logout"})).await.unwrap();
+        assert!(result.data["hits"].as_array().unwrap().is_empty());
+    }
+
+    #[tokio::test]
+    async fn test_edit_file() {
+        let dir = tempfile::tempdir().unwrap();
+        let file = dir.path().join("lib.rs");
+        std::fs::write(&file, "fn a() {\n    todo!()\n}\n\nfn b() {\n    todo!()\n}\n").unwrap();
+        let versions = Arc::new(FileVersions::new());
+        let tool = EditFileTool::new(dir.path().to_path_buf()).with_versions(Arc::clone(&versions));
+
+        let ambiguous = tool
+            .execute(serde_json::json!({"path": "lib.rs", "old_string": "    todo!()", "new_string": "    1"}))
+            .await
+            .unwrap();
+        assert_eq!(ambiguous.error.as_ref().unwrap().code, "ambiguous");
+        assert_eq!(ambiguous.data["lines"], serde_json::json!([2, 6]));
+
+        let edited = tool
+            .execute(serde_json::json!({"path": "lib.rs", "old_string": "fn b() {\n    todo!()", "new_string": "fn b() {\n    2"}))
+            .await
+            .unwrap();
+        assert_eq!(edited.data["replacements"], 1);
+        assert_eq!(std::fs::read_to_string(&file).unwrap(), "fn a() {\n    todo!()\n}\n\nfn b() {\n    2\n}\n");
+        assert_eq!(versions.changed_since_seen(&file).await, None);
+
+        let missing = tool
+            .execute(serde_json::json!({"path": "lib.rs", "old_string": "fn c()", "new_string": "fn d()"}))
+            .await
+            .unwrap();
+        assert_eq!(missing.error.as_ref().unwrap().code, "no_match");
+
+        let all = tool
+            .execute(serde_json::json!({"path": "lib.rs", "old_string": "fn ", "new_string": "pub fn ", "replace_all": true}))
+            .await
+            .unwrap();
+        assert_eq!(all.data["replacements"], 2);
+
+        let absent = tool
+            .execute(serde_json::json!({"path": "nope.rs", "old_string": "a", "new_string": "b"}))
+            .await;
+        assert!(matches!(absent, Err(ToolError::NotFound(_))));
+
+        let latin1 = b"// caf\xe9\nfn a() {}\n";
+        std::fs::write(dir.path().join("latin1.rs"), latin1).unwrap();
+        let refused = tool
+            .execute(serde_json::json!({"path": "latin1.rs", "old_string": "fn a", "new_string": "fn b"}))
+            .await
+            .unwrap();
+        assert_eq!(refused.error.as_ref().unwrap().code, "not_utf8");
+        assert!(refused.error.as_ref().unwrap().message.contains("at byte 6"));
+        assert_eq!(std::fs::read(dir.path().join("latin1.rs")).unwrap(), latin1);
+    }
+
+    #[tokio::test]
+    async fn test_write_detects_concurrent_edit() {
+        let dir = tempfile::tempdir().unwrap();
+        std::fs::write(dir.path().join("lib.rs"), "a\nb\nc\n").unwrap();
+        let versions = Arc::new(FileVersions::new());
+        let read = FileReadTool::new(dir.path().to_path_buf()).with_versions(Arc::clone(&versions));
+        let write = FileWriteTool::new(dir.path().to_path_buf()).with_versions(versions);
+
+        read.execute(serde_json::json!({"path": "lib.rs"})).await.unwrap();
+        // The user edits the last line in their editor.
+        std::fs::write(dir.path().join("lib.rs"), "a\nb\nC\n").unwrap();
+
+        let refused = write
+            .execute(serde_json::json!({"path": "lib.rs", "content": "A\nb\nc\n"}))
+            .await
+            .unwrap();
+        assert_eq!(refused.error.as_ref().unwrap().code, "conflict");
+        assert_eq!(std::fs::read_to_string(dir.path().join("lib.rs")).unwrap(), "a\nb\nC\n");
+
+        let merged = write
+            .execute(serde_json::json!({"path": "lib.rs", "content": "A\nb\nc\n", "on_conflict": "merge"}))
+            .await
+            .unwrap();
+        assert_eq!(merged.data["conflicts"], 0);
+        assert_eq!(std::fs::read_to_string(dir.path().join("lib.rs")).unwrap(), "A\nb\nC\n");
+
+        // The agent's own write is the new baseline.
+        let again = write
+            .execute(serde_json::json!({"path": "lib.rs", "content": "done\n"}))
+            .await
+            .unwrap();
+        assert!(again.success);
+
+        // Touched but not changed.
+        let file = std::fs::File::options().write(true).open(dir.path().join("lib.rs")).unwrap();
+        file.set_modified(std::time::SystemTime::now() + std::time::Duration::from_secs(60)).unwrap();
+        let touched = write
+            .execute(serde_json::json!({"path": "lib.rs", "content": "done again\n"}))
+            .await
+            .unwrap();
+        assert!(touched.success);
+    }
+
+    #[tokio::test]
+    async fn test_network_off_is_reported() {
+        let dir = tempfile::tempdir().unwrap();
+        let tool = RunCommandTool::new(dir.path().to_path_buf()).with_network(NetworkPolicy::allowlist(["crates.io"]));
+
+        let result = tool
+            .execute(serde_json::json!({"command": "echo \"$https_proxy $no_proxy\"; echo 'curl: (7) Failed to connect to 127.0.0.1:9' >&2; exit 7"}))
+            .await
+            .unwrap();
+
+        assert_eq!(result.data["stdout"], "http://127.0.0.1:9 crates.io\n");
+        assert_eq!(result.data["network_blocked"], true);
+        assert!(result.data["note"].as_str().unwrap().contains("crates.io"));
+    }
+
+    #[tokio::test]
+    async fn test_run_command_limits() {
+        let dir = tempfile::tempdir().unwrap();
+        let limits = ResourceLimits {
+            cpu_seconds: Some(1),
+            memory_mb: None,
+            max_output_bytes: Some(10),
+        };
+        let tool = RunCommandTool::new(dir.path().to_path_buf()).with_limits(limits);
+
+        let chatty = tool
+            .execute(serde_json::json!({"command": "seq 1 1000"}))
+            .await
+            .unwrap();
+        assert_eq!(chatty.data["stdout"], "1\n2\n3\n4\n5\n");
+        assert_eq!(chatty.data["output_truncated"]["stdout_bytes_dropped"], 3883);
+
+        let spinning = tool
+            .execute(serde_json::json!({"command": "while :; do :; done"}))
+            .await
+            .unwrap();
+        assert_eq!(spinning.data["limit_exceeded"], true);
+    }
+
+    #[tokio::test]
+    async fn test_list_dir_tree() {
+        let dir = tempfile::tempdir().unwrap();
+        let root = dir.path();
+        std::fs::create_dir_all(root.join(".git")).unwrap();
+        std::fs::write(root.join(".gitignore"), "target/\n").unwrap();
+        std::fs::create_dir_all(root.join("src/core")).unwrap();
+        std::fs::create_dir_all(root.join("target/debug")).unwrap();
+        std::fs::create_dir_all(root.join("docs")).unwrap();
+        std::fs::write(root.join("src/core/mod.rs"), "mod a;").unwrap();
+        std::fs::write(root.join("src/lib.rs"), "").unwrap();
+        std::fs::write(root.join("target/debug/out.rs"), "").unwrap();
+        std::fs::write(root.join("docs/guide.md"), "").unwrap();
+        let tool = ListDirTool::new(root.to_path_buf());
+        let paths = |result: &Value| -> Vec<String> {
+            result["items"]
+                .as_array()
+                .unwrap()
+                .iter()
+                .map(|item| item["path"].as_str().unwrap().to_string())
+                .collect()
+        };
+
+        let tree = tool.execute(serde_json::json!({"path": ".", "depth": 3})).await.unwrap();
+        assert_eq!(paths(&tree.data), ["docs", "docs/guide.md", "src", "src/core", "src/core/mod.rs", "src/lib.rs"]);
+        assert_eq!(tree.data["items"][4]["size"], 6);
+
+        let shallow = tool.execute(serde_json::json!({"path": ".", "depth": 2})).await.unwrap();
+        assert_eq!(paths(&shallow.data), ["docs", "docs/guide.md", "src", "src/core", "src/lib.rs"]);
+
+        let rust = tool.execute(serde_json::json!({"path": ".", "depth": 5, "pattern": "*.rs"})).await.unwrap();
+        assert_eq!(paths(&rust.data), ["src", "src/core", "src/core/mod.rs", "src/lib.rs"]);
+
+        let capped = tool
+            .execute(serde_json::json!({"path": "src", "depth": 3, "max_entries": 2}))
+            .await
+            .unwrap();
+        assert_eq!(paths(&capped.data), ["core", "core/mod.rs"]);
+        assert!(capped.meta.truncated);
+    }
+
+    #[test]
+    fn test_annotations() {
+        let hints: ToolAnnotations = serde_json::from_value(serde_json::json!({"readOnlyHint": true})).unwrap();
+        assert!(hints.read_only);
+        assert!(hints.destructive);
+        assert!(hints.open_world);
+
+        let mut read_only = default_tools(PathBuf::from(".")).read_only();
+        read_only.sort();
+        assert_eq!(read_only, ["glob", "grep", "list_dir", "read_file", "search_history"]);
+    }
+}
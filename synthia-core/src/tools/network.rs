@@ -0,0 +1,120 @@
+use serde::{Deserialize, Serialize};
+use std::sync::OnceLock;
+
+/// A proxy address nothing listens on, so proxied requests fail at once.
+const DEAD_PROXY: &str = "http://127.0.0.1:9";
+
+const PROXY_VARS: &[&str] = &["http_proxy", "https_proxy", "HTTP_PROXY", "HTTPS_PROXY", "ALL_PROXY", "all_proxy"];
+
+/// Output that suggests a command tried to reach the network and was stopped.
+const BLOCKED_MARKERS: &[&str] = &[
+    "127.0.0.1:9",
+    "Network is unreachable",
+    "Could not resolve host",
+    "Temporary failure in name resolution",
+    "Connection refused",
+];
+
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum NetworkMode {
+    Off,
+    Allowlist,
+    #[default]
+    On,
+}
+
+impl std::str::FromStr for NetworkMode {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.to_ascii_lowercase().as_str() {
+            "off" => Ok(NetworkMode::Off),
+            "allowlist" => Ok(NetworkMode::Allowlist),
+            "on" => Ok(NetworkMode::On),
+            _ => Err(format!("Unknown network mode '{}', expected off, allowlist or on", s)),
+        }
+    }
+}
+
+/// Network access for commands the agent runs.
+///
+/// `off` runs commands in a fresh network namespace where Linux allows it,
+/// and otherwise points the proxy variables at an address nothing listens
+/// on. `allowlist` uses the same dead proxy with the allowed hosts in
+/// `no_proxy`. The proxy variables only stop programs that honor them.
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct NetworkPolicy {
+    pub mode: NetworkMode,
+    /// Hosts reachable in `allowlist` mode, e.g. `crates.io` or
+    /// `.github.com` for its subdomains.
+    pub allow: Vec<String>,
+}
+
+impl NetworkPolicy {
+    pub fn off() -> Self {
+        Self {
+            mode: NetworkMode::Off,
+            allow: Vec::new(),
+        }
+    }
+
+    pub fn allowlist(allow: impl IntoIterator<Item = impl Into<String>>) -> Self {
+        Self {
+            mode: NetworkMode::Allowlist,
+            allow: allow.into_iter().map(Into::into).collect(),
+        }
+    }
+
+    /// A `sh -c` invocation of `command` under this policy.
+    pub(crate) fn command(&self, command: &str) -> tokio::process::Command {
+        let mut process = if self.mode == NetworkMode::Off && network_namespaces_work() {
+            let mut process = tokio::process::Command::new("unshare");
+            process.args(["--map-root-user", "--net", "sh", "-c", command]);
+            process
+        } else {
+            let mut process = tokio::process::Command::new("sh");
+            process.arg("-c").arg(command);
+            process
+        };
+        if self.mode != NetworkMode::On {
+            for var in PROXY_VARS {
+                process.env(var, DEAD_PROXY);
+            }
+            let allow = self.allow.join(",");
+            process.env("no_proxy", &allow).env("NO_PROXY", &allow);
+        }
+        process
+    }
+
+    /// A note for the model when `output` of a failed command looks like a
+    /// blocked connection.
+    pub(crate) fn violation(&self, output: &str) -> Option<String> {
+        if self.mode == NetworkMode::On || !BLOCKED_MARKERS.iter().any(|marker| output.contains(marker)) {
+            return None;
+        }
+        Some(match self.mode {
+            NetworkMode::Allowlist => format!(
+                "Network access is limited to these hosts for this run: {}. Work offline or use an allowed host.",
+                self.allow.join(", ")
+            ),
+            _ => "Network access is off for this run. Work offline, e.g. with vendored or cached dependencies."
+                .to_string(),
+        })
+    }
+}
+
+/// Whether unprivileged network namespaces are available, checked once.
+fn network_namespaces_work() -> bool {
+    static WORKS: OnceLock<bool> = OnceLock::new();
+    *WORKS.get_or_init(|| {
+        cfg!(target_os = "linux")
+            && std::process::Command::new("unshare")
+                .args(["--map-root-user", "--net", "true"])
+                .stdout(std::process::Stdio::null())
+                .stderr(std::process::Stdio::null())
+                .status()
+                .is_ok_and(|status| status.success())
+    })
+}
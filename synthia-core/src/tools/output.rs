@@ -0,0 +1,168 @@
+//! The envelope every tool result is sent and recorded in. Tools build it
+//! themselves; [`ToolManager::execute`](super::ToolManager::execute) adds
+//! how long the call took.
+
+use super::ToolError;
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+use std::time::Duration;
+
+/// The version of [`ToolOutput`] written now. Bumped when a field changes
+/// meaning, so readers of old sessions can tell the shapes apart.
+pub const TOOL_OUTPUT_VERSION: u32 = 1;
+
+/// A tool result: `{version, success, data, error, meta}`.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct ToolOutput {
+    pub version: u32,
+    pub success: bool,
+    /// What the tool returned, less the fields lifted into the envelope.
+    #[serde(default)]
+    pub data: Value,
+    /// Why the call failed. Set exactly when `success` is false.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub error: Option<ToolFailure>,
+    #[serde(default)]
+    pub meta: ToolMeta,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct ToolFailure {
+    /// A stable snake_case name such as `conflict` or `timeout`.
+    pub code: String,
+    pub message: String,
+    /// Whether the same call may succeed if tried again.
+    #[serde(default)]
+    pub retryable: bool,
+}
+
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct ToolMeta {
+    pub duration_ms: u64,
+    /// The data is incomplete: a file, search or command output was cut.
+    pub truncated: bool,
+}
+
+impl ToolOutput {
+    /// A successful result holding `data`.
+    pub fn success(data: Value) -> Self {
+        Self {
+            version: TOOL_OUTPUT_VERSION,
+            success: true,
+            data,
+            error: None,
+            meta: ToolMeta::default(),
+        }
+    }
+
+    /// A failure with no data, for calls that were never run or never
+    /// finished.
+    pub fn failure(code: &str, message: impl Into<String>, retryable: bool) -> Self {
+        Self {
+            version: TOOL_OUTPUT_VERSION,
+            success: false,
+            data: Value::Null,
+            error: Some(ToolFailure {
+                code: code.to_string(),
+                message: message.into(),
+                retryable,
+            }),
+            meta: ToolMeta::default(),
+        }
+    }
+
+    /// The output with `data`, e.g. the path a failure was about.
+    pub fn with_data(mut self, data: Value) -> Self {
+        self.data = data;
+        self
+    }
+
+    /// Marks the data as incomplete.
+    pub fn with_truncated(mut self, truncated: bool) -> Self {
+        self.meta.truncated = truncated;
+        self
+    }
+
+    /// `error` as a failure.
+    pub fn from_error(error: &ToolError, duration: Duration) -> Self {
+        let mut output = Self::failure(error.code(), error.to_string(), error.is_retryable());
+        output.meta.duration_ms = duration.as_millis() as u64;
+        output
+    }
+
+    /// A result as recorded in a session. Sessions from before the
+    /// envelope hold what the tool returned, whose `success` flag, `true`
+    /// when absent, and `code` and `message` (or `error`) give the failure.
+    pub(crate) fn from_recorded(value: Value) -> Self {
+        if let Ok(output) = serde_json::from_value::<ToolOutput>(value.clone()) {
+            return output;
+        }
+        let mut data = value;
+        let truncated = data.get("truncated").and_then(|v| v.as_bool()) == Some(true)
+            || data.get("output_truncated").is_some();
+        let Some(fields) = data.as_object_mut() else {
+            return Self::success(data).with_truncated(truncated);
+        };
+        if fields.remove("success").and_then(|v| v.as_bool()).unwrap_or(true) {
+            return Self::success(data).with_truncated(truncated);
+        }
+        let code = fields.remove("code").and_then(|v| v.as_str().map(str::to_string));
+        let message = fields
+            .remove("message")
+            .or_else(|| fields.remove("error"))
+            .and_then(|v| v.as_str().map(str::to_string));
+        Self::failure(
+            code.as_deref().unwrap_or("failed"),
+            message.unwrap_or_else(|| "The tool reported a failure.".to_string()),
+            false,
+        )
+        .with_data(data)
+        .with_truncated(truncated)
+    }
+
+    /// The output with [`ToolMeta`] left out, for telling whether two
+    /// calls returned the same thing.
+    pub fn fingerprint(&self) -> String {
+        serde_json::to_string(&(self.success, &self.data, &self.error)).unwrap_or_default()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    #[test]
+    fn test_from_recorded() {
+        let read = ToolOutput::from_recorded(json!({"success": true, "content": "abc", "truncated": true}));
+        assert!(read.success);
+        assert_eq!(read.data, json!({"content": "abc", "truncated": true}));
+        assert_eq!(read.error, None);
+        assert_eq!(read.meta, ToolMeta { duration_ms: 0, truncated: true });
+
+        let conflict = ToolOutput::from_recorded(
+            json!({"success": false, "code": "conflict", "path": "a.rs", "message": "a.rs changed"}),
+        );
+        assert!(!conflict.success);
+        assert_eq!(conflict.data, json!({"path": "a.rs"}));
+        let error = conflict.error.as_ref().unwrap();
+        assert_eq!((error.code.as_str(), error.message.as_str()), ("conflict", "a.rs changed"));
+
+        let plain = ToolOutput::from_recorded(json!("done"));
+        assert!(plain.success);
+        assert_eq!(plain.data, json!("done"));
+
+        let serialized = serde_json::to_value(&conflict).unwrap();
+        assert_eq!(serialized["version"], TOOL_OUTPUT_VERSION);
+        assert_eq!(serialized["error"]["retryable"], false);
+        assert_eq!(ToolOutput::from_recorded(serialized), conflict);
+        assert_eq!(
+            conflict,
+            ToolOutput::failure("conflict", "a.rs changed", false).with_data(json!({"path": "a.rs"}))
+        );
+
+        let missing = ToolOutput::from_error(&ToolError::NotFound("a.rs".to_string()), Duration::ZERO);
+        assert_eq!(missing.error.unwrap().code, "not_found");
+    }
+}
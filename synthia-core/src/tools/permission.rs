@@ -0,0 +1,289 @@
+//! Which tool calls need the user's say-so. Each call is allowed, denied
+//! or held for approval by the first rule that matches it, e.g. ask before
+//! any `rm`, never `git push --force`, and let everything else through.
+//! Without a `default`, commands and file writes are asked about whenever
+//! someone can be asked.
+
+use crate::hooks::{EDIT_FILE_TOOL, WRITE_FILE_TOOL};
+use async_trait::async_trait;
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+
+/// The tool whose rules match its command rather than a `path` argument.
+const RUN_COMMAND_TOOL: &str = "run_command";
+
+/// The tools asked about by default, as they change things.
+const ASKED_BY_DEFAULT: &[&str] = &[RUN_COMMAND_TOOL, WRITE_FILE_TOOL, EDIT_FILE_TOOL];
+
+/// What separates the commands of a command line, including the ones run
+/// for their output, as in `echo $(rm -rf src)` or `` echo `rm -rf src` ``.
+const COMMAND_SEPARATORS: &[char] = &['&', '|', ';', '\n', '(', ')', '`'];
+
+/// Programs that run the command after them, perhaps after options of
+/// their own.
+const COMMAND_WRAPPERS: &[&str] = &[
+    "sudo", "doas", "env", "command", "builtin", "exec", "nohup", "nice", "time", "xargs", "sh", "bash", "zsh",
+];
+
+/// What happens to a call. Ordered from the most to the least permissive.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, PartialOrd, Ord, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum Permission {
+    #[default]
+    Allow,
+    /// Run only if the user approves. Refused when no one can be asked.
+    Ask,
+    Deny,
+}
+
+/// Tool calls matched by their tools and a pattern.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct PermissionRule {
+    /// The tools the rule covers; every tool when empty.
+    #[serde(default)]
+    pub tools: Vec<String>,
+    /// Matched against each command for `run_command`, both as written and
+    /// without `sudo`, variable assignments and the program's directory,
+    /// and against the `path` argument for other tools; `*` stands for any
+    /// text, `/` included. Every call of the tools matches when unset.
+    #[serde(default)]
+    pub pattern: Option<String>,
+    pub permission: Permission,
+}
+
+impl PermissionRule {
+    fn matches(&self, tool: &str, subject: Option<&str>) -> bool {
+        if !self.tools.is_empty() && !self.tools.iter().any(|name| name == tool) {
+            return false;
+        }
+        match (&self.pattern, subject) {
+            (None, _) => true,
+            (Some(pattern), Some(subject)) => wildcard_match(pattern.trim(), subject),
+            (Some(_), None) => false,
+        }
+    }
+}
+
+/// The config's `permissions`:
+///
+/// ```json
+/// {
+///   "default": "allow",
+///   "rules": [
+///     { "tools": ["run_command"], "pattern": "git push*--force*", "permission": "deny" },
+///     { "tools": ["run_command"], "pattern": "rm *", "permission": "ask" },
+///     { "tools": ["write_file", "edit_file"], "pattern": "*.lock", "permission": "ask" }
+///   ]
+/// }
+/// ```
+///
+/// A command joined with `&&`, `||`, `;` or `|`, or run inside `$(…)`,
+/// gets the strictest permission of its parts, so `cargo build && rm -rf
+/// target` is asked about too. So is `sudo /bin/rm -rf target`.
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct PermissionPolicy {
+    /// For calls no rule matches. When unset, `run_command`, `write_file`
+    /// and `edit_file` are asked about if someone can be asked, and
+    /// everything else is allowed.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub default: Option<Permission>,
+    pub rules: Vec<PermissionRule>,
+}
+
+impl PermissionPolicy {
+    /// What happens to calling `tool` with `arguments`; `can_ask` is
+    /// whether there is an [`Approver`].
+    pub fn decide(&self, tool: &str, arguments: &Value, can_ask: bool) -> Permission {
+        let default = self.default.unwrap_or(if can_ask && ASKED_BY_DEFAULT.contains(&tool) {
+            Permission::Ask
+        } else {
+            Permission::Allow
+        });
+        let decide = |subject: Option<&str>| {
+            self.rules
+                .iter()
+                .find(|rule| rule.matches(tool, subject))
+                .map(|rule| rule.permission)
+                .unwrap_or(default)
+        };
+        if tool == RUN_COMMAND_TOOL {
+            let command = arguments.get("command").and_then(|c| c.as_str()).unwrap_or_default();
+            return command
+                .split(COMMAND_SEPARATORS)
+                .map(str::trim)
+                .filter(|part| !part.is_empty())
+                .map(|part| decide(Some(part)).max(decide(Some(&bare_command(part)))))
+                .max()
+                .unwrap_or_else(|| decide(None));
+        }
+        let path = arguments.get("path").and_then(|p| p.as_str());
+        decide(path.map(|path| path.trim_start_matches("./")))
+    }
+}
+
+/// A call held for the user's approval.
+#[derive(Debug, Clone, PartialEq)]
+pub struct ApprovalRequest {
+    pub tool: String,
+    pub arguments: Value,
+    /// What the call does, e.g. "running `rm -rf target`".
+    pub action: String,
+}
+
+/// Asks the user about calls the [`PermissionPolicy`] holds. Time spent
+/// deciding does not count against tool timeouts.
+#[async_trait]
+pub trait Approver: Send + Sync {
+    /// Whether the call may run.
+    async fn approve(&self, request: &ApprovalRequest) -> bool;
+}
+
+/// What calling `tool` with `arguments` does, for a question or a refusal.
+pub fn describe_call(tool: &str, arguments: &Value) -> String {
+    let argument = |name: &str| arguments.get(name).and_then(|value| value.as_str());
+    match (tool, argument("command"), argument("path")) {
+        (RUN_COMMAND_TOOL, Some(command), _) => format!("running `{}`", command),
+        (WRITE_FILE_TOOL, _, Some(path)) => format!("writing `{}`", path),
+        (EDIT_FILE_TOOL, _, Some(path)) => format!("editing `{}`", path),
+        (_, _, Some(path)) => format!("calling {} on `{}`", tool, path),
+        _ => format!("calling {}", tool),
+    }
+}
+
+/// The command `part` runs, without what only wraps it: `sudo` and the
+/// like with their options, variable assignments and the program's
+/// directory. `sudo FOO=1 /bin/rm -rf x` is `rm -rf x`.
+fn bare_command(part: &str) -> String {
+    let mut rest = part.trim();
+    let mut wrapped = false;
+    while let Some(word) = rest.split_whitespace().next() {
+        let wrapper = COMMAND_WRAPPERS.contains(&word);
+        let option = wrapped && word.starts_with('-');
+        if !(wrapper || option || is_assignment(word)) {
+            break;
+        }
+        wrapped |= wrapper;
+        rest = rest[word.len()..].trim_start();
+    }
+    // A quoted program, as in `sh -c 'rm -rf x'`, is matched unquoted.
+    let rest = rest.trim_start_matches(['\'', '"']);
+    let end = rest.find(char::is_whitespace).unwrap_or(rest.len());
+    let program = rest[..end].rsplit('/').next().unwrap_or_default();
+    format!("{}{}", program, &rest[end..])
+}
+
+/// Whether `word` sets a variable for the command, like `FOO=1`.
+fn is_assignment(word: &str) -> bool {
+    word.split_once('=').is_some_and(|(name, _)| {
+        name.chars().next().is_some_and(|c| c.is_ascii_alphabetic() || c == '_')
+            && name.chars().all(|c| c.is_ascii_alphanumeric() || c == '_')
+    })
+}
+
+fn wildcard_match(pattern: &str, text: &str) -> bool {
+    let mut parts = pattern.split('*');
+    let first = parts.next().unwrap_or_default();
+    let Some(mut rest) = text.strip_prefix(first) else {
+        return false;
+    };
+    let parts: Vec<&str> = parts.collect();
+    let Some((last, middle)) = parts.split_last() else {
+        // No `*`: the whole text must match.
+        return rest.is_empty();
+    };
+    for part in middle {
+        match rest.find(part) {
+            Some(at) => rest = &rest[at + part.len()..],
+            None => return false,
+        }
+    }
+    rest.ends_with(last)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    #[test]
+    fn test_permission_policy() {
+        let policy: PermissionPolicy = serde_json::from_value(json!({
+            "rules": [
+                { "tools": ["run_command"], "pattern": "git push*--force*", "permission": "deny" },
+                { "tools": ["run_command"], "pattern": "rm *", "permission": "ask" },
+                { "tools": ["write_file"], "pattern": "*.lock", "permission": "ask" }
+            ]
+        }))
+        .unwrap();
+        let command = |command: &str| policy.decide("run_command", &json!({ "command": command }), false);
+
+        assert_eq!(command("cargo test"), Permission::Allow);
+        assert_eq!(command("rm -rf target"), Permission::Ask);
+        assert_eq!(command("cargo build && rm -rf target"), Permission::Ask);
+        assert_eq!(command("rm -rf target; git push origin main --force"), Permission::Deny);
+        assert_eq!(command("git push origin main"), Permission::Allow);
+        assert_eq!(policy.decide("write_file", &json!({ "path": "./Cargo.lock" }), false), Permission::Ask);
+        assert_eq!(policy.decide("read_file", &json!({ "path": "Cargo.lock" }), false), Permission::Allow);
+
+        let strict = PermissionPolicy {
+            default: Some(Permission::Ask),
+            rules: vec![PermissionRule {
+                tools: vec!["read_file".to_string()],
+                pattern: None,
+                permission: Permission::Allow,
+            }],
+        };
+        assert_eq!(strict.decide("read_file", &json!({}), false), Permission::Allow);
+        assert_eq!(strict.decide("run_command", &json!({ "command": "ls" }), false), Permission::Ask);
+        assert_eq!(describe_call("run_command", &json!({ "command": "rm -rf target" })), "running `rm -rf target`");
+    }
+
+    #[test]
+    fn test_permission_default() {
+        let policy = PermissionPolicy::default();
+        let decide = |tool: &str, can_ask: bool| policy.decide(tool, &json!({ "command": "ls", "path": "a" }), can_ask);
+
+        assert_eq!(decide("run_command", true), Permission::Ask);
+        assert_eq!(decide("write_file", true), Permission::Ask);
+        assert_eq!(decide("edit_file", true), Permission::Ask);
+        assert_eq!(decide("read_file", true), Permission::Allow);
+        // Asking no one would refuse everything, so unattended runs go on.
+        assert_eq!(decide("run_command", false), Permission::Allow);
+        assert_eq!(decide("write_file", false), Permission::Allow);
+
+        let allow = PermissionPolicy {
+            default: Some(Permission::Allow),
+            rules: Vec::new(),
+        };
+        assert_eq!(allow.decide("run_command", &json!({ "command": "ls" }), true), Permission::Allow);
+    }
+
+    #[test]
+    fn test_permission_bypasses() {
+        let policy: PermissionPolicy = serde_json::from_value(json!({
+            "default": "allow",
+            "rules": [
+                { "tools": ["run_command"], "pattern": "rm *", "permission": "deny" },
+                { "tools": ["run_command"], "pattern": "sudo *", "permission": "ask" }
+            ]
+        }))
+        .unwrap();
+        let command = |command: &str| policy.decide("run_command", &json!({ "command": command }), true);
+
+        assert_eq!(command("sudo rm -rf /"), Permission::Deny);
+        assert_eq!(command("sudo -E rm -rf /"), Permission::Deny);
+        assert_eq!(command("FOO=1 rm -rf target"), Permission::Deny);
+        assert_eq!(command("env FOO=1 BAR=2 rm -rf target"), Permission::Deny);
+        assert_eq!(command("/bin/rm -rf target"), Permission::Deny);
+        assert_eq!(command("echo $(rm -rf target)"), Permission::Deny);
+        assert_eq!(command("echo `rm -rf target`"), Permission::Deny);
+        assert_eq!(command("find . -name '*.o' | xargs rm -f"), Permission::Deny);
+        assert_eq!(command("sh -c 'rm -rf target'"), Permission::Deny);
+        // Rules about the wrapper itself still apply.
+        assert_eq!(command("sudo apt update"), Permission::Ask);
+        assert_eq!(command("echo $(date)"), Permission::Allow);
+        assert_eq!(command("cargo rm-unused"), Permission::Allow);
+        assert_eq!(command("FOO=1 cargo test"), Permission::Allow);
+    }
+}
@@ -0,0 +1,217 @@
+//! Compact text forms of tool results for the model. JSON spends tokens on
+//! keys, quotes and escaped newlines that carry nothing the model needs.
+//! Each renderer returns `None` for results it doesn't recognize, which
+//! are then sent as JSON.
+
+use super::ToolOutput;
+use serde_json::Value;
+
+fn str_field<'a>(value: &'a Value, key: &str) -> &'a str {
+    value.get(key).and_then(|v| v.as_str()).unwrap_or_default()
+}
+
+/// The file's text, after a line saying which part it is when it was cut.
+pub(crate) fn read_file(output: &ToolOutput) -> Option<String> {
+    if !output.success {
+        return None;
+    }
+    let result = &output.data;
+    let content = result.get("content")?.as_str()?;
+    if !output.meta.truncated {
+        return Some(content.to_string());
+    }
+    Some(format!(
+        "[bytes {}-{} of {}; use offset or tail to read the rest]\n{}",
+        result.get("start")?,
+        result.get("end")?,
+        result.get("size")?,
+        content
+    ))
+}
+
+/// One `file:line: text` line per match, or one `file` or `file: count`
+/// line per file when only files or counts were asked for.
+pub(crate) fn grep(output: &ToolOutput) -> Option<String> {
+    if !output.success {
+        return None;
+    }
+    let result = &output.data;
+    let matches = result.get("results")?.as_array()?;
+    let mut text = String::new();
+    for entry in matches {
+        let file = str_field(entry, "file");
+        match (entry.get("error").and_then(|v| v.as_str()), entry.get("count"), entry.get("line")) {
+            (Some(error), _, _) => text.push_str(&format!("error: {}\n", error)),
+            (None, Some(count), _) => text.push_str(&format!("{}: {}\n", file, count)),
+            (None, None, Some(line)) => {
+                text.push_str(&format!("{}:{}: {}\n", file, line, str_field(entry, "content")))
+            }
+            (None, None, None) => text.push_str(&format!("{}\n", file)),
+        }
+    }
+    if matches.is_empty() {
+        text.push_str("No matches.\n");
+    }
+    if output.meta.truncated {
+        text.push_str("[search stopped early; narrow the path or pattern to see everything]\n");
+    }
+    Some(text)
+}
+
+/// One path per line.
+pub(crate) fn glob(output: &ToolOutput) -> Option<String> {
+    if !output.success {
+        return None;
+    }
+    let result = &output.data;
+    let files = result.get("files")?.as_array()?;
+    let mut text: String = files
+        .iter()
+        .filter_map(|file| file.as_str())
+        .map(|file| format!("{}\n", file))
+        .collect();
+    if files.is_empty() {
+        text.push_str("No files match.\n");
+    }
+    if output.meta.truncated {
+        text.push_str("[search stopped early; narrow the path to see everything]\n");
+    }
+    Some(text)
+}
+
+/// One entry per line, directories with a trailing `/` and files with
+/// their size. Trees are indented by depth.
+pub(crate) fn list_dir(output: &ToolOutput) -> Option<String> {
+    if !output.success {
+        return None;
+    }
+    let result = &output.data;
+    let items = result.get("items")?.as_array()?;
+    if result.get("tree").and_then(|v| v.as_bool()) == Some(true) {
+        return Some(tree(items, output.meta.truncated));
+    }
+    let mut entries: Vec<String> = items
+        .iter()
+        .map(|item| {
+            let name = str_field(item, "name");
+            match (item.get("is_dir").and_then(|v| v.as_bool()), item.get("size").and_then(|v| v.as_u64())) {
+                (Some(true), _) => format!("{}/", name),
+                (_, Some(size)) => format!("{} ({} bytes)", name, size),
+                _ => name.to_string(),
+            }
+        })
+        .collect();
+    entries.sort();
+    if entries.is_empty() {
+        return Some("The directory is empty.\n".to_string());
+    }
+    Some(entries.iter().map(|entry| format!("{}\n", entry)).collect())
+}
+
+fn tree(items: &[Value], truncated: bool) -> String {
+    let mut text = String::new();
+    for item in items {
+        let path = str_field(item, "path");
+        let (parent, name) = path.rsplit_once('/').unwrap_or(("", path));
+        let indent = if parent.is_empty() { 0 } else { parent.split('/').count() };
+        text.push_str(&"  ".repeat(indent));
+        match (item.get("is_dir").and_then(|v| v.as_bool()), item.get("size").and_then(|v| v.as_u64())) {
+            (Some(true), _) => text.push_str(&format!("{}/\n", name)),
+            (_, Some(size)) => text.push_str(&format!("{} ({} bytes)\n", name, size)),
+            _ => text.push_str(&format!("{}\n", name)),
+        }
+    }
+    if items.is_empty() {
+        text.push_str("Nothing to list.\n");
+    }
+    if truncated {
+        text.push_str("[tree cut at max_entries; list a subdirectory or lower the depth to see the rest]\n");
+    }
+    text
+}
+
+/// The exit code, then stdout and stderr where not empty, then any notes.
+pub(crate) fn run_command(output: &ToolOutput) -> Option<String> {
+    let result = &output.data;
+    let stdout = result.get("stdout")?.as_str()?;
+    let stderr = result.get("stderr")?.as_str()?;
+    let mut text = match result.get("exit_code").and_then(|v| v.as_i64()) {
+        Some(code) => format!("exit code: {}\n", code),
+        None => "exit code: none (killed by a signal)\n".to_string(),
+    };
+    for (name, output) in [("stdout", stdout), ("stderr", stderr)] {
+        if !output.is_empty() {
+            text.push_str(&format!("{}:\n{}\n", name, output.trim_end()));
+        }
+    }
+    if let Some(dropped) = result.get("output_truncated") {
+        text.push_str(&format!(
+            "[output cut: {} bytes of stdout and {} of stderr dropped]\n",
+            dropped.get("stdout_bytes_dropped").unwrap_or(&Value::from(0)),
+            dropped.get("stderr_bytes_dropped").unwrap_or(&Value::from(0))
+        ));
+    }
+    if let Some(note) = result.get("note").and_then(|v| v.as_str()) {
+        text.push_str(&format!("note: {}\n", note));
+    }
+    Some(text)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::tools::exit_failure;
+    use serde_json::json;
+
+    fn output(result: &Value) -> ToolOutput {
+        ToolOutput::success(result.clone())
+    }
+
+    fn cut(result: &Value) -> ToolOutput {
+        output(result).with_truncated(true)
+    }
+
+    #[test]
+    fn test_renderers() {
+        let found = json!({
+            "results": [
+                {"file": "src/a.rs", "line": 3, "offset": 40, "content": "fn a() {}"},
+                {"error": "Failed to read src/b.rs: denied"}
+            ]
+        });
+        assert_eq!(
+            grep(&cut(&found)).unwrap(),
+            "src/a.rs:3: fn a() {}\nerror: Failed to read src/b.rs: denied\n[search stopped early; narrow the path or pattern to see everything]\n"
+        );
+
+        let listed = json!({"items": [
+            {"name": "src", "is_dir": true, "is_file": false, "size": 4096},
+            {"name": "Cargo.toml", "is_dir": false, "is_file": true, "size": 120}
+        ]});
+        assert_eq!(list_dir(&output(&listed)).unwrap(), "Cargo.toml (120 bytes)\nsrc/\n");
+
+        let counted = json!({"results": [{"file": "src/a.rs", "count": 2}, {"file": "src/b.rs"}]});
+        assert_eq!(grep(&output(&counted)).unwrap(), "src/a.rs: 2\nsrc/b.rs\n");
+
+        let tree = json!({"tree": true, "items": [
+            {"path": "src", "is_dir": true},
+            {"path": "src/core", "is_dir": true},
+            {"path": "src/core/mod.rs", "is_dir": false, "size": 9},
+            {"path": "src/lib.rs", "is_dir": false, "size": 3}
+        ]});
+        assert_eq!(
+            list_dir(&cut(&tree)).unwrap(),
+            "src/\n  core/\n    mod.rs (9 bytes)\n  lib.rs (3 bytes)\n[tree cut at max_entries; list a subdirectory or lower the depth to see the rest]\n"
+        );
+
+        let ran = json!({"stdout": "", "stderr": "error[E0425]\n", "exit_code": 101, "note": "n"});
+        let ran = exit_failure("nonzero_exit", Some(101)).with_data(ran);
+        assert_eq!(run_command(&ran).unwrap(), "exit code: 101\nstderr:\nerror[E0425]\nnote: n\n");
+
+        let read = json!({"content": "tail\n", "size": 100, "start": 95, "end": 100});
+        assert_eq!(read_file(&cut(&read)).unwrap(), "[bytes 95-100 of 100; use offset or tail to read the rest]\ntail\n");
+
+        let failed = ToolOutput::failure("io_error", "denied", true).with_data(json!({"files": []}));
+        assert_eq!(glob(&failed), None);
+    }
+}
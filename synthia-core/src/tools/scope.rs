@@ -0,0 +1,147 @@
+//! Per-directory limits on tools, e.g. writes only under `src/` and
+//! `tests/`, or no commands inside `infra/`. The agent checks each call
+//! before running it and tells the model which rule stopped it.
+
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+use std::path::{Component, Path, PathBuf};
+
+/// The tool whose command text, rather than a `path` argument, names the
+/// paths it touches.
+const RUN_COMMAND_TOOL: &str = "run_command";
+
+/// Directories some tools are kept within or out of. Directories are
+/// relative to the workspace and cover everything below them.
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct ScopeRule {
+    /// The tools the rule covers; every tool when empty.
+    pub tools: Vec<String>,
+    /// The tools may only be used within these; anywhere when empty.
+    pub allow: Vec<String>,
+    /// The tools may not be used within these.
+    pub deny: Vec<String>,
+}
+
+impl ScopeRule {
+    fn covers(&self, tool: &str) -> bool {
+        self.tools.is_empty() || self.tools.iter().any(|name| name == tool)
+    }
+}
+
+/// Rules from the config's `scopes` list. A call must pass all of the
+/// rules that cover its tool.
+///
+/// Tools are checked on their `path` argument. `run_command` is checked
+/// on the words of its command that name a path: ones with a `/` in them,
+/// or naming something at the top of the workspace. That catches
+/// `cd infra && terraform apply` but not a path a script builds itself.
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(transparent)]
+pub struct ToolScopes {
+    pub rules: Vec<ScopeRule>,
+}
+
+impl ToolScopes {
+    /// Why calling `tool` with `arguments` is out of scope, if it is.
+    pub fn check(&self, tool: &str, arguments: &Value, workdir: &Path) -> Option<String> {
+        let rules: Vec<&ScopeRule> = self.rules.iter().filter(|rule| rule.covers(tool)).collect();
+        if rules.is_empty() {
+            return None;
+        }
+        let paths = touched_paths(tool, arguments, workdir);
+        for rule in rules {
+            for path in &paths {
+                if let Some(dir) = rule.deny.iter().find(|dir| within(path, dir)) {
+                    return Some(format!(
+                        "{} may not be used under {}, so it was not run on {}.",
+                        tool,
+                        dir,
+                        path.display()
+                    ));
+                }
+                if !rule.allow.is_empty() && !rule.allow.iter().any(|dir| within(path, dir)) {
+                    return Some(format!(
+                        "{} may only be used under {}, so it was not run on {}.",
+                        tool,
+                        rule.allow.join(", "),
+                        path.display()
+                    ));
+                }
+            }
+        }
+        None
+    }
+}
+
+/// The workspace paths a call names, relative to `workdir`.
+fn touched_paths(tool: &str, arguments: &Value, workdir: &Path) -> Vec<PathBuf> {
+    if tool == RUN_COMMAND_TOOL {
+        let command = arguments.get("command").and_then(|c| c.as_str()).unwrap_or_default();
+        return command
+            .split_whitespace()
+            .map(|word| word.trim_matches(|c: char| "'\"`;&|()<>".contains(c)))
+            .filter(|word| !word.is_empty() && !word.starts_with('-') && !word.contains("://"))
+            .filter(|word| word.contains('/') || workdir.join(word).exists())
+            .map(|word| relative(word, workdir))
+            .collect();
+    }
+    match arguments.get("path").and_then(|p| p.as_str()) {
+        Some(path) => vec![relative(path, workdir)],
+        None => Vec::new(),
+    }
+}
+
+/// `path` relative to `workdir`, with `.` and `..` worked out. A path
+/// that leaves the workspace keeps its leading `..` or stays absolute.
+fn relative(path: &str, workdir: &Path) -> PathBuf {
+    let path = Path::new(path);
+    let path = path.strip_prefix(workdir).unwrap_or(path);
+    let mut resolved = PathBuf::new();
+    for component in path.components() {
+        match component {
+            Component::CurDir => {}
+            Component::ParentDir if matches!(resolved.components().next_back(), Some(Component::Normal(_))) => {
+                resolved.pop();
+            }
+            component => resolved.push(component),
+        }
+    }
+    resolved
+}
+
+fn within(path: &Path, dir: &str) -> bool {
+    let dir = relative(dir.trim_end_matches('/'), Path::new(""));
+    !dir.as_os_str().is_empty() && path.starts_with(&dir)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    #[test]
+    fn test_tool_scopes() {
+        let dir = tempfile::tempdir().unwrap();
+        std::fs::create_dir(dir.path().join("infra")).unwrap();
+        let scopes: ToolScopes = serde_json::from_value(json!([
+            {"tools": ["write_file", "edit_file"], "allow": ["src/", "tests/"]},
+            {"tools": ["run_command"], "deny": ["infra/"]}
+        ]))
+        .unwrap();
+        let check = |tool: &str, arguments: Value| scopes.check(tool, &arguments, dir.path());
+
+        assert_eq!(check("write_file", json!({"path": "./src/lib.rs", "content": ""})), None);
+        assert_eq!(
+            check("edit_file", json!({"path": "src/../build.rs"})).as_deref(),
+            Some("edit_file may only be used under src/, tests/, so it was not run on build.rs.")
+        );
+        assert_eq!(check("read_file", json!({"path": "build.rs"})), None);
+        assert_eq!(check("run_command", json!({"command": "cargo test -p core"})), None);
+        assert_eq!(
+            check("run_command", json!({"command": "cd infra && terraform apply"})).as_deref(),
+            Some("run_command may not be used under infra/, so it was not run on infra.")
+        );
+        assert!(check("run_command", json!({"command": "cat ./infra/main.tf"})).is_some());
+    }
+}
@@ -0,0 +1,122 @@
+use super::ToolOutput;
+use crate::clients::ToolDefinition;
+use serde::{Deserialize, Serialize};
+use serde_json::{Value, json};
+use std::collections::HashSet;
+
+/// The tool that lists every registered tool when only some are sent. It
+/// is answered by the agent rather than registered.
+pub const LIST_ALL_TOOLS: &str = "list_all_tools";
+
+/// Which tools each request carries. With many tools registered, such as
+/// several MCP servers' on top of the built-in ones, their definitions can
+/// cost thousands of tokens per request.
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct ToolSelection {
+    /// Send at most this many tools, the ones whose names and descriptions
+    /// share the most words with the task, plus [`LIST_ALL_TOOLS`]. Every
+    /// tool is sent if unset.
+    pub max_tools: Option<usize>,
+    /// Tools sent whatever the task; they count towards `max_tools`.
+    pub always: Vec<String>,
+}
+
+/// Lowercase words of at least three letters or digits, split at
+/// underscores, hyphens and punctuation.
+fn words(text: &str) -> HashSet<String> {
+    text.split(|c: char| !c.is_alphanumeric())
+        .filter(|word| word.len() >= 3)
+        .map(str::to_lowercase)
+        .collect()
+}
+
+impl ToolSelection {
+    /// The definitions to send for `task`, or `None` if all of them fit.
+    /// Words in a tool's name count three times those in its description;
+    /// ties go by name, so the choice is the same every run.
+    pub fn select(&self, definitions: &[ToolDefinition], task: &str) -> Option<Vec<ToolDefinition>> {
+        let max = self.max_tools?;
+        if definitions.len() <= max {
+            return None;
+        }
+
+        let task = words(task);
+        let score = |tool: &ToolDefinition| {
+            if self.always.contains(&tool.name) {
+                return usize::MAX;
+            }
+            3 * words(&tool.name).intersection(&task).count() + words(&tool.description).intersection(&task).count()
+        };
+        let mut ranked: Vec<(usize, &ToolDefinition)> = definitions.iter().map(|tool| (score(tool), tool)).collect();
+        ranked.sort_by(|a, b| b.0.cmp(&a.0).then_with(|| a.1.name.cmp(&b.1.name)));
+
+        let mut selected: Vec<ToolDefinition> = ranked.into_iter().take(max).map(|(_, tool)| tool.clone()).collect();
+        selected.push(list_all_tools_definition(definitions.len()));
+        Some(selected)
+    }
+}
+
+pub fn list_all_tools_definition(total: usize) -> ToolDefinition {
+    ToolDefinition {
+        name: LIST_ALL_TOOLS.to_string(),
+        description: format!(
+            "Only some of the {} tools are listed. Lists all of them with their parameters; any of them can be called after that.",
+            total
+        ),
+        parameters: json!({"type": "object", "properties": {}}),
+    }
+}
+
+/// What [`LIST_ALL_TOOLS`] returns.
+pub fn list_all_tools_result(definitions: &[ToolDefinition]) -> ToolOutput {
+    let mut tools: Vec<Value> = definitions
+        .iter()
+        .map(|tool| json!({"name": tool.name, "description": tool.description, "parameters": tool.parameters}))
+        .collect();
+    tools.sort_by(|a, b| a["name"].as_str().cmp(&b["name"].as_str()));
+    ToolOutput::success(json!({"tools": tools}))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn tool(name: &str, description: &str) -> ToolDefinition {
+        ToolDefinition {
+            name: name.to_string(),
+            description: description.to_string(),
+            parameters: json!({}),
+        }
+    }
+
+    #[test]
+    fn test_select() {
+        let tools = vec![
+            tool("read_file", "Read a file"),
+            tool("jira_create_issue", "Create an issue in Jira"),
+            tool("slack_post", "Post a message to a Slack channel"),
+            tool("query_database", "Run an SQL query against the database"),
+        ];
+        let selection = ToolSelection {
+            max_tools: Some(2),
+            always: vec!["read_file".to_string()],
+        };
+
+        let selected = selection.select(&tools, "Why does the database query time out?").unwrap();
+        let names: Vec<&str> = selected.iter().map(|tool| tool.name.as_str()).collect();
+        assert_eq!(names, ["read_file", "query_database", LIST_ALL_TOOLS]);
+        assert!(selected[2].description.contains("4 tools"));
+
+        assert_eq!(ToolSelection::default().select(&tools, "anything"), None);
+        let roomy = ToolSelection {
+            max_tools: Some(4),
+            always: Vec::new(),
+        };
+        assert_eq!(roomy.select(&tools, "anything"), None);
+
+        let listed = list_all_tools_result(&tools);
+        assert_eq!(listed.data["tools"].as_array().unwrap().len(), 4);
+        assert_eq!(listed.data["tools"][0]["name"], "jira_create_issue");
+    }
+}
@@ -0,0 +1,155 @@
+//! Checks tool call arguments against the tool's JSON Schema, so a model
+//! that sent the wrong shape can be told what to fix. Only the keywords
+//! tool schemas commonly use are checked: `type`, `required`, `enum`,
+//! `properties`, `items` and `additionalProperties: false`. Anything else
+//! is accepted.
+
+use serde_json::Value;
+
+/// The JSON type of `value` as a schema names it, with an article.
+fn describe(value: &Value) -> &'static str {
+    match value {
+        Value::Null => "null",
+        Value::Bool(_) => "a boolean",
+        Value::Number(n) if n.is_i64() || n.is_u64() => "an integer",
+        Value::Number(_) => "a number",
+        Value::String(_) => "a string",
+        Value::Array(_) => "an array",
+        Value::Object(_) => "an object",
+    }
+}
+
+fn has_type(value: &Value, name: &str) -> bool {
+    match name {
+        "null" => value.is_null(),
+        "boolean" => value.is_boolean(),
+        "integer" => value.is_i64() || value.is_u64(),
+        "number" => value.is_number(),
+        "string" => value.is_string(),
+        "array" => value.is_array(),
+        "object" => value.is_object(),
+        _ => true,
+    }
+}
+
+fn with_article(name: &str) -> String {
+    match name {
+        "null" => "null".to_string(),
+        "array" | "integer" | "object" => format!("an {}", name),
+        _ => format!("a {}", name),
+    }
+}
+
+/// How `arguments` break `schema`, one message per problem, such as
+/// ``argument `path` must be a string; you sent an object``. Empty when
+/// they fit.
+pub fn validate_arguments(schema: &Value, arguments: &Value) -> Vec<String> {
+    let mut errors = Vec::new();
+    check(schema, arguments, None, &mut errors);
+    errors
+}
+
+fn check(schema: &Value, value: &Value, path: Option<&str>, errors: &mut Vec<String>) {
+    let subject = match path {
+        Some(path) => format!("argument `{}`", path),
+        None => "the arguments".to_string(),
+    };
+
+    let types: Vec<&str> = match schema.get("type") {
+        Some(Value::String(name)) => vec![name.as_str()],
+        Some(Value::Array(names)) => names.iter().filter_map(|name| name.as_str()).collect(),
+        _ => Vec::new(),
+    };
+    if !types.is_empty() && !types.iter().any(|name| has_type(value, name)) {
+        let expected: Vec<String> = types.iter().map(|name| with_article(name)).collect();
+        errors.push(format!(
+            "{} must be {}; you sent {}",
+            subject,
+            expected.join(" or "),
+            describe(value)
+        ));
+        return;
+    }
+
+    if let Some(allowed) = schema.get("enum").and_then(|allowed| allowed.as_array())
+        && !allowed.contains(value)
+    {
+        let allowed: Vec<String> = allowed.iter().map(|allowed| allowed.to_string()).collect();
+        errors.push(format!("{} must be one of {}; you sent {}", subject, allowed.join(", "), value));
+    }
+
+    if let Some(fields) = value.as_object() {
+        let properties = schema.get("properties").and_then(|properties| properties.as_object());
+        for name in schema.get("required").and_then(|required| required.as_array()).into_iter().flatten() {
+            if let Some(name) = name.as_str()
+                && !fields.contains_key(name)
+            {
+                errors.push(format!("missing required argument `{}`", join(path, name)));
+            }
+        }
+        for (name, field) in fields {
+            match properties.and_then(|properties| properties.get(name)) {
+                Some(property) => check(property, field, Some(&join(path, name)), errors),
+                None if schema.get("additionalProperties") == Some(&Value::Bool(false)) => {
+                    errors.push(format!("unknown argument `{}`", join(path, name)))
+                }
+                None => {}
+            }
+        }
+    }
+
+    if let (Some(items), Some(values)) = (schema.get("items"), value.as_array()) {
+        for (i, item) in values.iter().enumerate() {
+            let path = format!("{}[{}]", path.unwrap_or_default(), i);
+            check(items, item, Some(&path), errors);
+        }
+    }
+}
+
+fn join(path: Option<&str>, name: &str) -> String {
+    match path {
+        Some(path) => format!("{}.{}", path, name),
+        None => name.to_string(),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    #[test]
+    fn test_validate_arguments() {
+        let schema = json!({
+            "type": "object",
+            "properties": {
+                "path": {"type": "string"},
+                "max_bytes": {"type": "integer"},
+                "on_conflict": {"type": "string", "enum": ["fail", "merge"]},
+                "paths": {"type": "array", "items": {"type": "string"}}
+            },
+            "required": ["path"]
+        });
+
+        assert!(validate_arguments(&schema, &json!({"path": "a.rs", "max_bytes": 10})).is_empty());
+        assert_eq!(
+            validate_arguments(&schema, &json!({"path": {"name": "a.rs"}, "max_bytes": "10"})),
+            vec![
+                "argument `max_bytes` must be an integer; you sent a string",
+                "argument `path` must be a string; you sent an object",
+            ]
+        );
+        assert_eq!(
+            validate_arguments(&schema, &json!({"on_conflict": "replace", "paths": ["a", 1]})),
+            vec![
+                "missing required argument `path`",
+                "argument `on_conflict` must be one of \"fail\", \"merge\"; you sent \"replace\"",
+                "argument `paths[1]` must be a string; you sent an integer",
+            ]
+        );
+        assert_eq!(
+            validate_arguments(&schema, &json!("a.rs")),
+            vec!["the arguments must be an object; you sent a string"]
+        );
+    }
+}
@@ -0,0 +1,185 @@
+use super::ToolError;
+use std::collections::HashMap;
+use std::collections::hash_map::DefaultHasher;
+use std::hash::Hasher;
+use std::path::{Path, PathBuf};
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::{Mutex, OnceLock};
+use std::time::{Duration, SystemTime};
+use tokio::io::AsyncReadExt;
+
+/// The coarsest modification time a filesystem keeps (FAT's). A file
+/// changed within this long of being recorded can keep its old mtime, so
+/// its size and mtime alone don't show it is unchanged.
+const MTIME_GRANULARITY: Duration = Duration::from_secs(2);
+
+/// A file's size and modification time, which change with its content.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+struct Stamp {
+    len: u64,
+    modified: Option<SystemTime>,
+}
+
+impl Stamp {
+    async fn of(path: &Path) -> Option<Self> {
+        let metadata = tokio::fs::metadata(path).await.ok()?;
+        Some(Self {
+            len: metadata.len(),
+            modified: metadata.modified().ok(),
+        })
+    }
+}
+
+/// A file as the agent last read or wrote it. Only a hash is kept in
+/// memory; the content is copied aside for merges.
+#[derive(Debug, Clone)]
+struct Version {
+    stamp: Option<Stamp>,
+    recorded_at: SystemTime,
+    hash: u64,
+    snapshot: PathBuf,
+}
+
+impl Version {
+    /// Whether `stamp` shows the file is still as recorded, without
+    /// reading it.
+    fn unchanged(&self, stamp: Option<Stamp>) -> bool {
+        let settled = stamp
+            .and_then(|stamp| stamp.modified)
+            .and_then(|modified| self.recorded_at.duration_since(modified).ok())
+            .is_some_and(|age| age >= MTIME_GRANULARITY);
+        settled && stamp == self.stamp
+    }
+}
+
+/// What the agent last saw of each file, shared by `read_file` and
+/// `write_file` so a write can tell whether the file changed on disk in
+/// between, e.g. because the user edited it mid-run.
+#[derive(Debug, Default)]
+pub struct FileVersions {
+    seen: Mutex<HashMap<PathBuf, Version>>,
+    /// Where each version's content is kept, made when the first is
+    /// recorded and removed with the versions.
+    snapshots: OnceLock<Option<tempfile::TempDir>>,
+    next: AtomicUsize,
+}
+
+fn key(path: &Path) -> PathBuf {
+    std::fs::canonicalize(path).unwrap_or_else(|_| path.to_path_buf())
+}
+
+/// Hashes the file a block at a time, so large files aren't held in memory.
+async fn hash_file(path: &Path) -> std::io::Result<u64> {
+    let mut file = tokio::fs::File::open(path).await?;
+    let mut hasher = DefaultHasher::new();
+    let mut buffer = vec![0; 64 * 1024];
+    loop {
+        let read = file.read(&mut buffer).await?;
+        if read == 0 {
+            return Ok(hasher.finish());
+        }
+        hasher.write(&buffer[..read]);
+    }
+}
+
+impl FileVersions {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    fn lock(&self) -> std::sync::MutexGuard<'_, HashMap<PathBuf, Version>> {
+        self.seen.lock().unwrap_or_else(|e| e.into_inner())
+    }
+
+    /// Records the file as it is now on disk. A file that can't be copied
+    /// aside is forgotten, so writes to it aren't checked.
+    pub(crate) async fn record(&self, path: &Path) {
+        let key = key(path);
+        let stamp = Stamp::of(path).await;
+        if self.lock().get(&key).is_some_and(|seen| seen.unchanged(stamp)) {
+            return;
+        }
+        let recorded_at = SystemTime::now();
+        let version = match self.snapshot(path).await {
+            Ok((snapshot, hash)) => Some(Version {
+                stamp,
+                recorded_at,
+                hash,
+                snapshot,
+            }),
+            Err(e) => {
+                tracing::debug!("Not tracking changes to {}: {}", path.display(), e);
+                None
+            }
+        };
+        let replaced = match version {
+            Some(version) => self.lock().insert(key, version),
+            None => self.lock().remove(&key),
+        };
+        if let Some(replaced) = replaced {
+            let _ = tokio::fs::remove_file(replaced.snapshot).await;
+        }
+    }
+
+    /// Copies `path` aside and hashes the copy, so the hash matches what
+    /// was kept even if the file changes meanwhile.
+    async fn snapshot(&self, path: &Path) -> std::io::Result<(PathBuf, u64)> {
+        let dir = self
+            .snapshots
+            .get_or_init(|| tempfile::Builder::new().prefix("synthia-versions-").tempdir().ok())
+            .as_ref()
+            .ok_or_else(|| std::io::Error::other("no directory for file versions"))?;
+        let snapshot = dir.path().join(self.next.fetch_add(1, Ordering::Relaxed).to_string());
+        tokio::fs::copy(path, &snapshot).await?;
+        Ok((snapshot.clone(), hash_file(&snapshot).await?))
+    }
+
+    /// Where the content last seen is kept, if the file has changed on
+    /// disk since. Files that were never seen have nothing to conflict
+    /// with.
+    pub(crate) async fn changed_since_seen(&self, path: &Path) -> Option<PathBuf> {
+        let seen = self.lock().get(&key(path)).cloned()?;
+        let stamp = Stamp::of(path).await;
+        if seen.unchanged(stamp) {
+            return None;
+        }
+        match hash_file(path).await {
+            Ok(hash) if hash == seen.hash => None,
+            _ => Some(seen.snapshot),
+        }
+    }
+}
+
+/// A three-way merge of `ours` and the file's current content against the
+/// content kept at `base`, using `git merge-file`. Returns the merged text
+/// and the number of conflicts left marked in it.
+pub(crate) async fn merge(base: &Path, ours: &str, path: &Path) -> Result<(String, usize), ToolError> {
+    // Removed when dropped, at the end of the merge.
+    let temp = tempfile::Builder::new().prefix("synthia-merge-").tempdir()?;
+    let dir = temp.path();
+    tokio::fs::write(dir.join("ours"), ours).await?;
+    tokio::fs::copy(base, dir.join("base")).await?;
+    // A file removed on disk merges as an empty one.
+    if tokio::fs::copy(path, dir.join("theirs")).await.is_err() {
+        tokio::fs::write(dir.join("theirs"), "").await?;
+    }
+
+    let output = tokio::process::Command::new("git")
+        .args(["merge-file", "-p", "-L", "yours", "-L", "last read", "-L", "on disk", "ours", "base", "theirs"])
+        .current_dir(dir)
+        .output()
+        .await;
+
+    let output = output.map_err(|e| ToolError::ExecutionFailed(format!("git merge-file: {}", e)))?;
+    // The exit code is the number of conflicts; negative codes are errors.
+    match output.status.code() {
+        Some(conflicts @ 0..=127) => Ok((
+            String::from_utf8_lossy(&output.stdout).into_owned(),
+            conflicts as usize,
+        )),
+        _ => Err(ToolError::ExecutionFailed(format!(
+            "git merge-file: {}",
+            String::from_utf8_lossy(&output.stderr).trim()
+        ))),
+    }
+}
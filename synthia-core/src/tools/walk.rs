@@ -0,0 +1,120 @@
+use ignore::overrides::OverrideBuilder;
+use ignore::{WalkBuilder, WalkState};
+use std::path::{Path, PathBuf};
+use tokio::sync::mpsc;
+
+pub const DEFAULT_MAX_FILES: usize = 10_000;
+
+const MAX_WALK_THREADS: usize = 8;
+
+pub(crate) struct WalkEntry {
+    pub(crate) path: PathBuf,
+    pub(crate) is_dir: bool,
+}
+
+pub(crate) struct Walk {
+    pub(crate) entries: Vec<WalkEntry>,
+    /// The limit was hit before the whole tree was visited.
+    pub(crate) truncated: bool,
+}
+
+/// Lists everything under `root` except hidden and git-ignored entries, on
+/// a bounded pool of blocking threads so large trees don't stall the
+/// runtime. Stops once `limit` entries have been collected. Entries come
+/// back sorted by path.
+pub(crate) async fn walk(root: &Path, limit: usize) -> Walk {
+    let (tx, mut rx) = mpsc::channel::<WalkEntry>(256);
+    let threads = std::thread::available_parallelism()
+        .map(|n| n.get())
+        .unwrap_or(1)
+        .min(MAX_WALK_THREADS);
+    let walker = WalkBuilder::new(root).threads(threads).build_parallel();
+
+    let handle = tokio::task::spawn_blocking(move || {
+        walker.run(|| {
+            let tx = tx.clone();
+            Box::new(move |entry| {
+                let Ok(entry) = entry else {
+                    return WalkState::Continue;
+                };
+                // The root itself is not a result.
+                if entry.depth() == 0 {
+                    return WalkState::Continue;
+                }
+                let is_dir = entry.file_type().is_some_and(|t| t.is_dir());
+                match tx.blocking_send(WalkEntry {
+                    path: entry.into_path(),
+                    is_dir,
+                }) {
+                    Ok(()) => WalkState::Continue,
+                    Err(_) => WalkState::Quit,
+                }
+            })
+        });
+    });
+
+    let mut entries = Vec::new();
+    let mut truncated = false;
+    while let Some(entry) = rx.recv().await {
+        if entries.len() >= limit {
+            truncated = true;
+            break;
+        }
+        entries.push(entry);
+    }
+    // Closing the channel makes every walker thread quit.
+    drop(rx);
+    let _ = handle.await;
+
+    entries.sort_by(|a, b| a.path.cmp(&b.path));
+    Walk { entries, truncated }
+}
+
+/// Lists `root` down to `depth` levels, depth first with each directory's
+/// entries sorted by name, except hidden and git-ignored entries. With a
+/// `pattern`, only files matching it are listed, along with the
+/// directories that hold them. Stops once `limit` entries have been
+/// collected. Blocks; run it with `spawn_blocking`.
+pub(crate) fn tree(root: &Path, depth: usize, pattern: Option<&str>, limit: usize) -> Result<Walk, ignore::Error> {
+    let mut builder = WalkBuilder::new(root);
+    builder.max_depth(Some(depth)).sort_by_file_name(|a, b| a.cmp(b));
+    if let Some(pattern) = pattern {
+        let mut overrides = OverrideBuilder::new(root);
+        overrides.add(pattern)?;
+        builder.overrides(overrides.build()?);
+    }
+
+    let mut entries = Vec::new();
+    // With a pattern, directories wait here until a file under them matches.
+    let mut pending: Vec<PathBuf> = Vec::new();
+    for entry in builder.build() {
+        let Ok(entry) = entry else {
+            continue;
+        };
+        if entry.depth() == 0 {
+            continue;
+        }
+        let is_dir = entry.file_type().is_some_and(|t| t.is_dir());
+        let path = entry.into_path();
+        let mut found = Vec::new();
+        if pattern.is_some() {
+            while pending.last().is_some_and(|dir| !path.starts_with(dir)) {
+                pending.pop();
+            }
+            if is_dir {
+                pending.push(path);
+                continue;
+            }
+            found.extend(pending.drain(..).map(|path| WalkEntry { path, is_dir: true }));
+        }
+        found.push(WalkEntry { path, is_dir });
+
+        for entry in found {
+            if entries.len() >= limit {
+                return Ok(Walk { entries, truncated: true });
+            }
+            entries.push(entry);
+        }
+    }
+    Ok(Walk { entries, truncated: false })
+}
@@ -0,0 +1,253 @@
+//! Spoken tasks: record from the microphone, transcribe the recording
+//! with a speech-to-text endpoint, and optionally read the answer back.
+//! Recording and playback run external programs (`rec` and `play` from
+//! sox by default), so no audio library is linked in.
+
+use crate::clients::api_error;
+use serde::{Deserialize, Serialize};
+use std::path::Path;
+use std::time::Duration;
+use thiserror::Error;
+
+#[derive(Debug, Error)]
+pub enum VoiceError {
+    #[error("Recording failed: {0}")]
+    Record(String),
+    #[error("Transcription failed: {0}")]
+    Transcribe(String),
+    #[error("Speech synthesis failed: {0}")]
+    Speak(String),
+    #[error("Playback failed: {0}")]
+    Play(String),
+}
+
+/// Where speech is turned into text and back.
+///
+/// `stt_url` takes OpenAI's `/audio/transcriptions` or a whisper.cpp
+/// server's `/inference`; both accept the same form upload.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct VoiceConfig {
+    pub stt_url: String,
+    pub stt_model: String,
+    /// The variable holding the key for both endpoints. Nothing is sent
+    /// when it is unset, as a local server needs no key.
+    pub api_key_env: String,
+    /// Records to `{file}` as WAV and exits when done. The default stops
+    /// after two seconds of silence.
+    pub record_command: Vec<String>,
+    /// Reads the final answer aloud; off unless set.
+    pub speak: Option<SpeechConfig>,
+}
+
+impl Default for VoiceConfig {
+    fn default() -> Self {
+        Self {
+            stt_url: "https://api.openai.com/v1/audio/transcriptions".to_string(),
+            stt_model: "whisper-1".to_string(),
+            api_key_env: "OPENAI_API_KEY".to_string(),
+            record_command: ["rec", "-q", "-c", "1", "-r", "16000", "{file}", "silence", "1", "0.1", "1%", "1", "2.0", "1%"]
+                .map(str::to_string)
+                .to_vec(),
+            speak: None,
+        }
+    }
+}
+
+/// An OpenAI-compatible `/audio/speech` endpoint and how to play what it
+/// returns.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct SpeechConfig {
+    pub url: String,
+    pub model: String,
+    pub voice: String,
+    /// Plays the WAV file at `{file}`.
+    pub play_command: Vec<String>,
+}
+
+impl Default for SpeechConfig {
+    fn default() -> Self {
+        Self {
+            url: "https://api.openai.com/v1/audio/speech".to_string(),
+            model: "gpt-4o-mini-tts".to_string(),
+            voice: "alloy".to_string(),
+            play_command: ["play", "-q", "{file}"].map(str::to_string).to_vec(),
+        }
+    }
+}
+
+#[derive(Deserialize)]
+struct Transcription {
+    text: String,
+}
+
+/// `command` with `{file}` replaced by `file`.
+fn with_file(command: &[String], file: &Path) -> Vec<String> {
+    let file = file.to_string_lossy();
+    command.iter().map(|arg| arg.replace("{file}", &file)).collect()
+}
+
+/// Runs `command` to completion, for recording and playback.
+async fn run(command: &[String], file: &Path) -> Result<(), String> {
+    let command = with_file(command, file);
+    let Some((program, args)) = command.split_first() else {
+        return Err("no command configured".to_string());
+    };
+    let output = tokio::process::Command::new(program)
+        .args(args)
+        .stdin(std::process::Stdio::null())
+        .kill_on_drop(true)
+        .output()
+        .await
+        .map_err(|e| format!("{}: {}", program, e))?;
+    if !output.status.success() {
+        return Err(format!("{} exited with {}: {}", program, output.status, String::from_utf8_lossy(&output.stderr).trim()));
+    }
+    Ok(())
+}
+
+/// Records from the microphone and transcribes what was said.
+pub struct Voice {
+    config: VoiceConfig,
+    api_key: Option<String>,
+    client: reqwest::Client,
+    timeout: Duration,
+}
+
+impl Voice {
+    pub fn new(config: VoiceConfig) -> Self {
+        let api_key = std::env::var(&config.api_key_env).ok().filter(|key| !key.is_empty());
+        Self {
+            config,
+            api_key,
+            client: crate::http::shared_client(),
+            timeout: Duration::from_secs(120),
+        }
+    }
+
+    fn post(&self, url: &str) -> reqwest::RequestBuilder {
+        let request = self.client.post(url).timeout(self.timeout);
+        match &self.api_key {
+            Some(key) => request.bearer_auth(key),
+            None => request,
+        }
+    }
+
+    /// Records to `file` with the record command; returns once it exits.
+    pub async fn record(&self, file: &Path) -> Result<(), VoiceError> {
+        run(&self.config.record_command, file).await.map_err(VoiceError::Record)
+    }
+
+    /// The text spoken in the WAV file at `file`, trimmed.
+    pub async fn transcribe(&self, file: &Path) -> Result<String, VoiceError> {
+        let audio = tokio::fs::read(file)
+            .await
+            .map_err(|e| VoiceError::Transcribe(format!("{}: {}", file.display(), e)))?;
+        let part = reqwest::multipart::Part::bytes(audio)
+            .file_name("task.wav")
+            .mime_str("audio/wav")
+            .map_err(|e| VoiceError::Transcribe(e.to_string()))?;
+        let form = reqwest::multipart::Form::new()
+            .part("file", part)
+            .text("model", self.config.stt_model.clone())
+            .text("response_format", "json");
+
+        let response = self
+            .post(&self.config.stt_url)
+            .multipart(form)
+            .send()
+            .await
+            .map_err(|e| VoiceError::Transcribe(e.to_string()))?;
+        let status = response.status();
+        let body = response.text().await.map_err(|e| VoiceError::Transcribe(e.to_string()))?;
+        if !status.is_success() {
+            return Err(VoiceError::Transcribe(api_error(status.as_u16(), &body).to_string()));
+        }
+        let transcription: Transcription =
+            serde_json::from_str(&body).map_err(|e| VoiceError::Transcribe(format!("{}: {}", e, body)))?;
+        Ok(transcription.text.trim().to_string())
+    }
+
+    /// Records a task and transcribes it, keeping the recording in `dir`
+    /// only while it is needed.
+    pub async fn listen(&self, dir: &Path) -> Result<String, VoiceError> {
+        let file = dir.join(format!("synthia-voice-{}.wav", std::process::id()));
+        let text = match self.record(&file).await {
+            Ok(()) => self.transcribe(&file).await,
+            Err(e) => Err(e),
+        };
+        let _ = std::fs::remove_file(&file);
+        text
+    }
+
+    /// Reads `text` aloud, if [`VoiceConfig::speak`] is set.
+    pub async fn speak(&self, text: &str, dir: &Path) -> Result<(), VoiceError> {
+        let Some(speech) = &self.config.speak else {
+            return Ok(());
+        };
+        let response = self
+            .post(&speech.url)
+            .json(&serde_json::json!({
+                "model": speech.model,
+                "voice": speech.voice,
+                "input": text,
+                "response_format": "wav",
+            }))
+            .send()
+            .await
+            .map_err(|e| VoiceError::Speak(e.to_string()))?;
+        let status = response.status();
+        if !status.is_success() {
+            let body = response.text().await.unwrap_or_default();
+            return Err(VoiceError::Speak(api_error(status.as_u16(), &body).to_string()));
+        }
+        let audio = response.bytes().await.map_err(|e| VoiceError::Speak(e.to_string()))?;
+
+        let file = dir.join(format!("synthia-speech-{}.wav", std::process::id()));
+        std::fs::write(&file, &audio).map_err(|e| VoiceError::Play(format!("{}: {}", file.display(), e)))?;
+        let played = run(&speech.play_command, &file).await.map_err(VoiceError::Play);
+        let _ = std::fs::remove_file(&file);
+        played
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[tokio::test]
+    async fn test_listen() {
+        let dir = tempfile::tempdir().unwrap();
+        let server = tokio::net::TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let url = format!("http://{}/inference", server.local_addr().unwrap());
+        tokio::spawn(async move {
+            use tokio::io::{AsyncReadExt, AsyncWriteExt};
+            let (mut socket, _) = server.accept().await.unwrap();
+            let mut request = Vec::new();
+            let mut buf = [0; 4096];
+            while !String::from_utf8_lossy(&request).contains("RIFF-fake--") {
+                let n = socket.read(&mut buf).await.unwrap();
+                request.extend_from_slice(&buf[..n]);
+            }
+            let body = r#"{"text":" Fix the failing test. "}"#;
+            let response = format!("HTTP/1.1 200 OK\r\ncontent-length: {}\r\n\r\n{}", body.len(), body);
+            socket.write_all(response.as_bytes()).await.unwrap();
+        });
+
+        let voice = Voice::new(VoiceConfig {
+            stt_url: url,
+            api_key_env: "SYNTHIA_TEST_NO_SUCH_KEY".to_string(),
+            record_command: ["sh", "-c", "printf RIFF-fake-- > \"$0\"", "{file}"].map(str::to_string).to_vec(),
+            ..VoiceConfig::default()
+        });
+        assert_eq!(voice.listen(dir.path()).await.unwrap(), "Fix the failing test.");
+        assert_eq!(std::fs::read_dir(dir.path()).unwrap().count(), 0);
+
+        let failing = Voice::new(VoiceConfig {
+            record_command: vec!["false".to_string()],
+            ..VoiceConfig::default()
+        });
+        assert!(matches!(failing.listen(dir.path()).await, Err(VoiceError::Record(_))));
+    }
+}
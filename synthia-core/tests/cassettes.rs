@@ -0,0 +1,149 @@
+use futures::StreamExt;
+use std::path::PathBuf;
+use synthia_core::clients::{Cassette, ChunkType, StreamChunk};
+
+fn fixture(name: &str) -> Cassette {
+    let path = PathBuf::from(env!("CARGO_MANIFEST_DIR"))
+        .join("tests/fixtures/cassettes")
+        .join(name);
+    Cassette::load(&path).unwrap()
+}
+
+async fn replay(name: &str) -> Vec<StreamChunk> {
+    fixture(name)
+        .replay()
+        .map(|chunk| chunk.unwrap())
+        .collect()
+        .await
+}
+
+fn text(chunks: &[StreamChunk]) -> String {
+    chunks
+        .iter()
+        .filter(|chunk| chunk.chunk_type == ChunkType::Content)
+        .map(|chunk| chunk.content.as_str())
+        .collect()
+}
+
+#[tokio::test]
+async fn test_openai_text_split_across_reads() {
+    let chunks = replay("openai_text.json").await;
+
+    assert_eq!(text(&chunks), "Hello, world");
+    assert_eq!(chunks.last().unwrap().chunk_type, ChunkType::Done);
+}
+
+#[tokio::test]
+async fn test_openai_tool_call_assembly() {
+    let chunks = replay("openai_tool_call.json").await;
+
+    let calls: Vec<serde_json::Value> = chunks
+        .iter()
+        .filter(|chunk| chunk.chunk_type == ChunkType::ToolCall)
+        .map(|chunk| serde_json::from_str(&chunk.content).unwrap())
+        .collect();
+
+    assert_eq!(calls.len(), 1);
+    assert_eq!(calls[0]["id"], "call_abc");
+    assert_eq!(calls[0]["name"], "read_file");
+    assert_eq!(calls[0]["arguments"], r#"{"path": "src/main.rs"}"#);
+    assert_eq!(text(&chunks), "");
+    assert_eq!(chunks.last().unwrap().chunk_type, ChunkType::Done);
+}
+
+#[tokio::test]
+async fn test_ollama_text() {
+    let chunks = replay("ollama_text.json").await;
+
+    assert_eq!(text(&chunks), "Hi there");
+    assert_eq!(chunks[2].chunk_type, ChunkType::Usage);
+    assert_eq!(chunks.len(), 4);
+}
+
+#[tokio::test]
+async fn test_ollama_tool_calls() {
+    let chunks = replay("ollama_tool_calls.json").await;
+
+    let calls: Vec<serde_json::Value> = chunks
+        .iter()
+        .filter(|chunk| chunk.chunk_type == ChunkType::ToolCall)
+        .map(|chunk| serde_json::from_str(&chunk.content).unwrap())
+        .collect();
+
+    assert_eq!(chunks[0].chunk_type, ChunkType::Reasoning);
+    assert_eq!(calls.len(), 2);
+    assert_eq!(calls[0]["id"], "call_0");
+    assert_eq!(calls[0]["arguments"], r#"{"path":"src/a.rs"}"#);
+    assert_eq!(calls[1]["id"], "call_1");
+    assert_eq!(calls[1]["arguments"], r#"{"path":"src/b.rs"}"#);
+}
+
+#[tokio::test]
+async fn test_deepseek_reasoning_content() {
+    let chunks = replay("deepseek_reasoner.json").await;
+
+    let reasoning: String = chunks
+        .iter()
+        .filter(|chunk| chunk.chunk_type == ChunkType::Reasoning)
+        .map(|chunk| chunk.content.as_str())
+        .collect();
+
+    assert_eq!(reasoning, "The user wants a greeting.");
+    assert_eq!(text(&chunks), "FINAL: Hello!");
+}
+
+#[tokio::test]
+async fn test_xai_text() {
+    let chunks = replay("xai_text.json").await;
+
+    assert_eq!(text(&chunks), "Hello from Grok");
+    assert_eq!(chunks.last().unwrap().chunk_type, ChunkType::Done);
+}
+
+#[tokio::test]
+async fn test_mistral_unindexed_tool_calls() {
+    let chunks = replay("mistral_tool_calls.json").await;
+
+    let calls: Vec<serde_json::Value> = chunks
+        .iter()
+        .filter(|chunk| chunk.chunk_type == ChunkType::ToolCall)
+        .map(|chunk| serde_json::from_str(&chunk.content).unwrap())
+        .collect();
+
+    assert_eq!(calls.len(), 2);
+    assert_eq!(calls[0]["id"], "D681PevKs");
+    assert_eq!(calls[0]["arguments"], r#"{"path": "src/a.rs"}"#);
+    assert_eq!(calls[1]["id"], "x9Yz12AbC");
+    assert_eq!(calls[1]["arguments"], r#"{"path": "src/b.rs"}"#);
+}
+
+#[tokio::test]
+async fn test_anthropic_text_and_usage() {
+    let chunks = replay("anthropic_text.json").await;
+
+    assert_eq!(text(&chunks), "Hello from Claude");
+    let usage: Vec<&StreamChunk> = chunks.iter().filter(|chunk| chunk.chunk_type == ChunkType::Usage).collect();
+    assert_eq!(usage.len(), 1);
+    let usage: serde_json::Value = serde_json::from_str(&usage[0].content).unwrap();
+    assert_eq!(usage["input_tokens"], 12);
+    assert_eq!(usage["output_tokens"], 5);
+    assert_eq!(chunks.last().unwrap().chunk_type, ChunkType::Done);
+}
+
+#[tokio::test]
+async fn test_anthropic_tool_call_assembly() {
+    let chunks = replay("anthropic_tool_call.json").await;
+
+    let calls: Vec<serde_json::Value> = chunks
+        .iter()
+        .filter(|chunk| chunk.chunk_type == ChunkType::ToolCall)
+        .map(|chunk| serde_json::from_str(&chunk.content).unwrap())
+        .collect();
+
+    assert_eq!(text(&chunks), "I'll read it.");
+    assert_eq!(calls.len(), 1);
+    assert_eq!(calls[0]["id"], "toolu_01A09q90qw90lq917835lq9");
+    assert_eq!(calls[0]["name"], "read_file");
+    assert_eq!(calls[0]["arguments"], r#"{"path": "src/main.rs"}"#);
+    assert_eq!(chunks.last().unwrap().chunk_type, ChunkType::Done);
+}
@@ -0,0 +1,155 @@
+//! Golden-transcript tests for the agent loop. Each scenario drives
+//! `ReactAgent` with a `ScriptedClient` and the real file tools in a temp
+//! directory, then compares the steps and the resulting files against
+//! `tests/fixtures/golden/<scenario>.json`.
+//!
+//! Run with `UPDATE_GOLDEN=1` to rewrite the golden files after an
+//! intentional change, and review the diff before committing it.
+
+use serde_json::{Value, json};
+use std::collections::BTreeMap;
+use std::path::{Path, PathBuf};
+use synthia_core::tools::ToolOutput;
+use synthia_core::{ReactAgent, ScriptedClient, default_tools};
+
+struct Scenario {
+    name: &'static str,
+    files: &'static [(&'static str, &'static str)],
+    task: &'static str,
+    responses: Vec<String>,
+}
+
+fn golden_path(name: &str) -> PathBuf {
+    PathBuf::from(env!("CARGO_MANIFEST_DIR"))
+        .join("tests/fixtures/golden")
+        .join(format!("{}.json", name))
+}
+
+fn snapshot_files(root: &Path, dir: &Path, files: &mut BTreeMap<String, String>) {
+    for entry in std::fs::read_dir(dir).unwrap() {
+        let path = entry.unwrap().path();
+        if path.is_dir() {
+            snapshot_files(root, &path, files);
+        } else {
+            let relative = path.strip_prefix(root).unwrap().to_string_lossy().replace('\\', "/");
+            files.insert(relative, std::fs::read_to_string(&path).unwrap());
+        }
+    }
+}
+
+async fn run_scenario(scenario: Scenario) {
+    let dir = tempfile::tempdir().unwrap();
+    for (path, content) in scenario.files {
+        let path = dir.path().join(path);
+        std::fs::create_dir_all(path.parent().unwrap()).unwrap();
+        std::fs::write(path, content).unwrap();
+    }
+
+    let mut agent = ReactAgent::new(
+        Box::new(ScriptedClient::from_responses(scenario.responses)),
+        default_tools(dir.path().to_path_buf()),
+        dir.path().to_path_buf(),
+        Some(10),
+        Some(false),
+        None,
+    );
+    let mut steps = agent.run(scenario.task).await.unwrap().steps;
+    // Timings change per run.
+    for step in &mut steps {
+        step.started_at = 0;
+        step.duration_ms = 0;
+        if let Ok(mut output) = serde_json::from_str::<ToolOutput>(&step.observation) {
+            output.meta.duration_ms = 0;
+            step.observation = serde_json::to_string(&output).unwrap();
+        }
+    }
+
+    let mut files = BTreeMap::new();
+    snapshot_files(dir.path(), dir.path(), &mut files);
+
+    let transcript = json!({
+        "task": scenario.task,
+        "steps": steps,
+        "files": files,
+    });
+    // Tool observations can contain the temp dir path, which changes per run.
+    let transcript = serde_json::to_string_pretty(&transcript)
+        .unwrap()
+        .replace(&dir.path().to_string_lossy().to_string(), "$WORKDIR");
+
+    let path = golden_path(scenario.name);
+    if std::env::var_os("UPDATE_GOLDEN").is_some() {
+        std::fs::create_dir_all(path.parent().unwrap()).unwrap();
+        std::fs::write(&path, format!("{}\n", transcript)).unwrap();
+        return;
+    }
+
+    let expected = std::fs::read_to_string(&path)
+        .unwrap_or_else(|e| panic!("{}: {} (run with UPDATE_GOLDEN=1 to create it)", path.display(), e));
+    let expected: Value = serde_json::from_str(&expected).unwrap();
+    let actual: Value = serde_json::from_str(&transcript).unwrap();
+
+    assert!(
+        expected == actual,
+        "transcript for {} differs from {}\n\nactual:\n{}",
+        scenario.name,
+        path.display(),
+        transcript
+    );
+}
+
+#[tokio::test]
+async fn golden_read_then_answer() {
+    run_scenario(Scenario {
+        name: "read_then_answer",
+        files: &[("notes.txt", "The deploy key rotates every Monday.\n")],
+        task: "When does the deploy key rotate?",
+        responses: vec![
+            format!(
+                "I'll read the notes.\n{}",
+                ScriptedClient::tool_call("read_file", json!({"path": "notes.txt"}))
+            ),
+            "FINAL: Every Monday.".to_string(),
+        ],
+    })
+    .await;
+}
+
+#[tokio::test]
+async fn golden_fix_bug() {
+    run_scenario(Scenario {
+        name: "fix_bug",
+        files: &[(
+            "src/lib.rs",
+            "pub fn add(a: i32, b: i32) -> i32 {\n    a - b\n}\n",
+        )],
+        task: "add() subtracts instead of adding, fix it",
+        responses: vec![
+            ScriptedClient::tool_call("read_file", json!({"path": "src/lib.rs"})),
+            format!(
+                "The operator is wrong.\n```\n{}\n```",
+                ScriptedClient::tool_call(
+                    "write_file",
+                    json!({"path": "src/lib.rs", "content": "pub fn add(a: i32, b: i32) -> i32 {\n    a + b\n}\n"}),
+                )
+            ),
+            ScriptedClient::tool_call("grep", json!({"pattern": "a + b", "path": "src"})),
+            "FINAL: add() now returns a + b.".to_string(),
+        ],
+    })
+    .await;
+}
+
+#[tokio::test]
+async fn golden_thinking_before_answer() {
+    run_scenario(Scenario {
+        name: "thinking_before_answer",
+        files: &[],
+        task: "What is 2 + 2?",
+        responses: vec![
+            "Let me think about this.".to_string(),
+            "FINAL: 4".to_string(),
+        ],
+    })
+    .await;
+}
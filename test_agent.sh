@@ -18,7 +18,7 @@ echo -e "\n\n3. Testing synthia-agent with simple task..."
 
 # 运行synthia-agent执行一个简单任务
 echo "Running synthia-agent with simple task..."
-cd /home/crochee/workspace/synthia/synthia-agent && cargo run -- run --task "What is 2 + 2? Respond with just the number." --model "llama2:7b-chat-q4_0" --base-url "http://localhost:11434/v1/chat/completions" --api-key "ollama"
+cd /home/crochee/workspace/synthia && cargo run -p synthia-cli -- run --task "What is 2 + 2? Respond with just the number." --model "llama2:7b-chat-q4_0" --base-url "http://localhost:11434/v1/chat/completions" --api-key "ollama"
 
 echo -e "\n\n=== Testing Completed ==="
 echo "✓ Local Ollama model service is running"